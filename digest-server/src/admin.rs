@@ -0,0 +1,209 @@
+//! Token-authenticated admin endpoints
+
+use crate::{AppState, email_html, format_date};
+use axum::{
+    Json,
+    extract::{Request, State},
+    http::{HeaderMap, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Reject the request unless it carries `Authorization: Bearer <ADMIN_TOKEN>`
+pub fn require_admin(state: &AppState, headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+    let token = state.admin_token.as_deref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Admin API not configured".into(),
+    ))?;
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match presented {
+        Some(p) if constant_time_eq(p.as_bytes(), token.as_bytes()) => Ok(()),
+        _ => Err((StatusCode::UNAUTHORIZED, "Unauthorized".into())),
+    }
+}
+
+/// Like `require_admin`, for pages opened in a browser: the token may also be
+/// the password of HTTP Basic auth, which the browser is asked for when
+/// missing. Returns the refusal, if the request isn't allowed.
+pub fn deny_admin_page(state: &AppState, headers: &HeaderMap) -> Option<Response> {
+    let Some(token) = state.admin_token.as_deref() else {
+        return Some((StatusCode::SERVICE_UNAVAILABLE, "Admin API not configured").into_response());
+    };
+    if presents_token(headers, token) {
+        return None;
+    }
+    Some(
+        (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, r#"Basic realm="admin""#)],
+            "Unauthorized",
+        )
+            .into_response(),
+    )
+}
+
+/// Middleware restricting the stats pages when STATS_TOKEN is set.
+///
+/// Accepts `Authorization: Bearer <token>` for scripts, or HTTP Basic auth with
+/// the token as password (any username) so browsers can prompt for it.
+pub async fn require_stats_token(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(token) = state.stats_token.as_deref() else {
+        return next.run(request).await;
+    };
+    if presents_token(request.headers(), token) {
+        return next.run(request).await;
+    }
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, r#"Basic realm="stats""#)],
+        "Unauthorized",
+    )
+        .into_response()
+}
+
+/// Whether the Authorization header carries `token` as a bearer token or Basic password
+fn presents_token(headers: &HeaderMap, token: &str) -> bool {
+    let Some(value) = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return false;
+    };
+    if let Some(bearer) = value.strip_prefix("Bearer ") {
+        return constant_time_eq(bearer.as_bytes(), token.as_bytes());
+    }
+    value
+        .strip_prefix("Basic ")
+        .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())
+        .and_then(|decoded| {
+            let (_, password) = decoded.split_at(decoded.iter().position(|&b| b == b':')? + 1);
+            Some(constant_time_eq(password, token.as_bytes()))
+        })
+        .unwrap_or(false)
+}
+
+/// Compare secrets without leaking the mismatch position through timing
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[derive(Deserialize)]
+pub struct TestEmailRequest {
+    to: String,
+}
+
+#[derive(Serialize)]
+pub struct TestEmailResponse {
+    provider: &'static str,
+    digest_date: String,
+    message_ids: Vec<String>,
+}
+
+/// Send the latest digest to a single address via the configured provider
+pub async fn test_email(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<TestEmailRequest>,
+) -> Result<Json<TestEmailResponse>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+
+    let (date, html): (String, String) = state
+        .blocking(|state| {
+            state
+                .storage
+                .digest(None)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+                .ok_or_else(|| (StatusCode::NOT_FOUND, "No digests yet".to_string()))
+        })
+        .await?;
+
+    // As send-digest would mail it
+    let tracking_url = state
+        .click_tracking
+        .then(|| state.public_url.as_deref().unwrap_or_default());
+    let html = email_html::digest(html, &date, tracking_url);
+    let subject = format!("[Test] {} – {}", state.digest_name, format_date(&date));
+    let (provider, id) = state.send_email(request.to.trim(), &subject, &html).await?;
+
+    tracing::info!("Sent test email for {} via {}", date, provider);
+    Ok(Json(TestEmailResponse {
+        provider,
+        digest_date: date,
+        message_ids: id.into_iter().collect(),
+    }))
+}
+
+#[derive(Serialize)]
+pub struct PurgeCacheResponse {
+    purged: usize,
+}
+
+/// Drop every cached digest page, for after digests are edited in place
+pub async fn purge_cache(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<PurgeCacheResponse>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    let purged = state.digest_cache.clear();
+    tracing::info!("Purged {} cached digest pages", purged);
+    Ok(Json(PurgeCacheResponse { purged }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod presents_token {
+        use super::*;
+
+        fn auth(value: &str) -> HeaderMap {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, value.parse().unwrap());
+            headers
+        }
+
+        #[test]
+        fn bearer_and_basic() {
+            assert!(presents_token(&auth("Bearer s3cret"), "s3cret"));
+            // base64("me:s3cret")
+            assert!(presents_token(&auth("Basic bWU6czNjcmV0"), "s3cret"));
+        }
+
+        #[test]
+        fn rejects_wrong_or_missing() {
+            assert!(!presents_token(&HeaderMap::new(), "s3cret"));
+            assert!(!presents_token(&auth("Bearer nope"), "s3cret"));
+            // base64("s3cret"), no colon
+            assert!(!presents_token(&auth("Basic czNjcmV0"), "s3cret"));
+        }
+    }
+
+    mod constant_time_eq {
+        use super::*;
+
+        #[test]
+        fn equal_inputs() {
+            assert!(constant_time_eq(b"secret", b"secret"));
+        }
+
+        #[test]
+        fn different_inputs() {
+            assert!(!constant_time_eq(b"secret", b"secreT"));
+            assert!(!constant_time_eq(b"secret", b"secret2"));
+            assert!(!constant_time_eq(b"", b"x"));
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! Background check that alerts when a feed source degrades.
+//!
+//! Fires once when a source starts failing (low success rate, or no success
+//! for too long) and again only after it has recovered. Alert state lives in
+//! memory, so a restart re-announces sources that are still failing.
+
+use crate::{AppState, db, escape_html};
+use rusqlite::OpenFlags;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Alert thresholds and targets (`ALERT_*` environment variables)
+#[derive(Clone, Debug)]
+pub struct AlertConfig {
+    pub webhook_url: Option<String>,
+    pub email: Option<String>,
+    /// Alert when the success rate over `window_hours` drops below this
+    pub min_success_pct: f64,
+    pub window_hours: u32,
+    /// Alert when a source hasn't succeeded for this long
+    pub stale_hours: u32,
+    pub interval: Duration,
+}
+
+impl AlertConfig {
+    /// Read configuration from the environment; `None` when no alert target is set
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let webhook_url = var("ALERT_WEBHOOK_URL");
+        let email = var("ALERT_EMAIL");
+        if webhook_url.is_none() && email.is_none() {
+            return Ok(None);
+        }
+        fn number<T: std::str::FromStr>(
+            value: Option<String>,
+            name: &str,
+            default: T,
+        ) -> Result<T, String> {
+            value.map_or(Ok(default), |v| {
+                v.parse()
+                    .map_err(|_| format!("{name} must be a number, got '{v}'"))
+            })
+        }
+        Ok(Some(Self {
+            webhook_url,
+            email,
+            min_success_pct: number(var("ALERT_MIN_SUCCESS_PCT"), "ALERT_MIN_SUCCESS_PCT", 80.0)?,
+            window_hours: number(var("ALERT_WINDOW_HOURS"), "ALERT_WINDOW_HOURS", 24)?.max(1),
+            stale_hours: number(var("ALERT_STALE_HOURS"), "ALERT_STALE_HOURS", 48)?.max(1),
+            interval: Duration::from_secs(
+                60 * number(var("ALERT_INTERVAL_MINS"), "ALERT_INTERVAL_MINS", 60u64)?.max(1),
+            ),
+        }))
+    }
+}
+
+/// Recent fetch results for one source
+#[derive(Debug, PartialEq)]
+struct SourceStatus {
+    source_id: String,
+    /// Fetches and successes within the alert window
+    fetches: i64,
+    successes: i64,
+    /// `None` if the source has never succeeded
+    hours_since_success: Option<f64>,
+}
+
+impl SourceStatus {
+    /// Why this source needs attention, if it does
+    fn problem(&self, config: &AlertConfig) -> Option<String> {
+        match self.hours_since_success {
+            None => return Some("has never fetched successfully".into()),
+            Some(hours) if hours >= f64::from(config.stale_hours) => {
+                return Some(format!("no successful fetch in {hours:.0} hours"));
+            }
+            _ => {}
+        }
+        let rate = self.successes as f64 / self.fetches as f64 * 100.0;
+        (rate < config.min_success_pct).then(|| {
+            format!(
+                "{rate:.0}% success over the last {} hours ({}/{})",
+                config.window_hours, self.successes, self.fetches
+            )
+        })
+    }
+}
+
+/// Sources fetched within the window, with their recent success counts
+fn source_statuses(db_path: &str, window_hours: u32) -> Result<Vec<SourceStatus>, String> {
+    let conn = db::open(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("DB error: {e}"))?;
+    if !db::table_exists(&conn, "source_health")? {
+        return Ok(Vec::new());
+    }
+    let since = format!("-{window_hours} hours");
+    let mut stmt = conn
+        .prepare(
+            "SELECT source_id,
+                    SUM(recorded_at >= datetime('now', ?1)),
+                    SUM(recorded_at >= datetime('now', ?1) AND success = 1),
+                    (julianday('now') - julianday(MAX(CASE WHEN success = 1 THEN recorded_at END))) * 24
+             FROM source_health
+             GROUP BY source_id
+             HAVING SUM(recorded_at >= datetime('now', ?1)) > 0
+             ORDER BY source_id",
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    let rows = stmt
+        .query_map([since], |row| {
+            Ok(SourceStatus {
+                source_id: row.get(0)?,
+                fetches: row.get(1)?,
+                successes: row.get(2)?,
+                hours_since_success: row.get(3)?,
+            })
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(rows)
+}
+
+/// Run the check every `config.interval`, forever
+pub async fn run(state: Arc<AppState>, config: AlertConfig) {
+    let mut firing: HashSet<String> = HashSet::new();
+    let mut ticker = tokio::time::interval(config.interval);
+    loop {
+        ticker.tick().await;
+        let (db_path, window_hours) = (state.db_path.clone(), config.window_hours);
+        let statuses = db::blocking(move || source_statuses(&db_path, window_hours))
+            .await
+            .flatten();
+        let statuses = match statuses {
+            Ok(statuses) => statuses,
+            Err(e) => {
+                tracing::warn!("Source alert check failed: {}", e);
+                continue;
+            }
+        };
+        let problems: Vec<(String, String)> = statuses
+            .iter()
+            .filter_map(|s| s.problem(&config).map(|p| (s.source_id.clone(), p)))
+            .collect();
+        let new: Vec<&(String, String)> = problems
+            .iter()
+            .filter(|(id, _)| !firing.contains(id))
+            .collect();
+        firing = problems.iter().map(|(id, _)| id.clone()).collect();
+        if new.is_empty() {
+            continue;
+        }
+        for (id, problem) in &new {
+            tracing::warn!(source = %id, "Source degraded: {}", problem);
+        }
+        notify(&state, &config, &new).await;
+    }
+}
+
+/// Send one alert listing newly degraded sources to every configured target
+async fn notify(state: &AppState, config: &AlertConfig, problems: &[&(String, String)]) {
+    let subject = format!(
+        "{}: {} source(s) degraded",
+        state.digest_name,
+        problems.len()
+    );
+    if let Some(url) = &config.webhook_url {
+        let lines: Vec<String> = problems
+            .iter()
+            .map(|(id, problem)| format!("• {id}: {problem}"))
+            .collect();
+        let body = serde_json::json!({
+            "text": format!("{subject}\n{}", lines.join("\n")),
+            "sources": problems
+                .iter()
+                .map(|(id, problem)| serde_json::json!({"source_id": id, "problem": problem}))
+                .collect::<Vec<_>>(),
+        });
+        let result = state
+            .http_client
+            .post(url)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+        if let Err(e) = result {
+            tracing::warn!("Alert webhook failed: {}", e);
+        }
+    }
+    if let Some(to) = &config.email {
+        let items: String = problems
+            .iter()
+            .map(|(id, problem)| {
+                format!(
+                    "<li><strong>{}</strong>: {}</li>",
+                    escape_html(id),
+                    escape_html(problem)
+                )
+            })
+            .collect();
+        let html = format!("<p>These sources need attention:</p><ul>{items}</ul>");
+        if let Err((_, e)) = state.send_email(to, &subject, &html).await {
+            tracing::warn!("Alert email failed: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod problem {
+        use super::*;
+
+        fn config() -> AlertConfig {
+            AlertConfig {
+                webhook_url: None,
+                email: None,
+                min_success_pct: 80.0,
+                window_hours: 24,
+                stale_hours: 48,
+                interval: Duration::from_secs(3600),
+            }
+        }
+
+        fn status(fetches: i64, successes: i64, hours: Option<f64>) -> SourceStatus {
+            SourceStatus {
+                source_id: "bbc".into(),
+                fetches,
+                successes,
+                hours_since_success: hours,
+            }
+        }
+
+        #[test]
+        fn healthy_source() {
+            assert_eq!(status(10, 9, Some(1.0)).problem(&config()), None);
+        }
+
+        #[test]
+        fn low_success_rate() {
+            let problem = status(10, 5, Some(1.0)).problem(&config()).unwrap();
+            assert!(problem.starts_with("50% success"), "{problem}");
+        }
+
+        #[test]
+        fn stale_or_never_succeeded() {
+            assert!(status(4, 0, Some(60.0)).problem(&config()).is_some());
+            assert!(status(4, 0, None).problem(&config()).is_some());
+        }
+    }
+}
@@ -0,0 +1,390 @@
+//! Online database backups with `VACUUM INTO`.
+//!
+//! Each backup is a compacted, consistent copy of the live database written
+//! to `BACKUP_DIR` as `digest-YYYYMMDD-HHMMSS.db`, taken without blocking
+//! readers or the pipeline. Only the newest `BACKUP_KEEP` are kept. With
+//! `S3_BUCKET` set, each backup is also uploaded off-box (see `s3`). A
+//! separate telemetry database is folded into the same file, which is split
+//! out again when a restored backup is served.
+
+use crate::{AppState, admin, db, s3};
+use axum::{Json, extract::State, http::HeaderMap, http::StatusCode};
+use rusqlite::OpenFlags;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+const PREFIX: &str = "digest-";
+const SUFFIX: &str = ".db";
+
+/// Where backups go and how many to keep (`BACKUP_*` environment variables)
+#[derive(Clone, Debug)]
+pub struct BackupConfig {
+    pub dir: PathBuf,
+    pub keep: usize,
+    /// Take a backup this often; `None` for on-demand only
+    pub interval: Option<Duration>,
+    /// Also upload each backup here
+    pub s3: Option<s3::S3Config>,
+}
+
+impl BackupConfig {
+    /// Read configuration from the environment; `None` when `BACKUP_DIR` is unset
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let Some(dir) = var("BACKUP_DIR") else {
+            if var("S3_BUCKET").is_some() {
+                return Err("S3_BUCKET requires BACKUP_DIR".into());
+            }
+            return Ok(None);
+        };
+        let number = |name: &str, default: u64| {
+            var(name).map_or(Ok(default), |v| {
+                v.parse()
+                    .map_err(|_| format!("{name} must be a number, got '{v}'"))
+            })
+        };
+        let hours = number("BACKUP_INTERVAL_HOURS", 0)?;
+        let keep = number("BACKUP_KEEP", 7)?.max(1) as usize;
+        Ok(Some(Self {
+            dir: PathBuf::from(dir),
+            keep,
+            interval: (hours > 0).then(|| Duration::from_secs(3600 * hours)),
+            s3: s3::S3Config::from_env(keep)?,
+        }))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Backup {
+    path: String,
+    bytes: u64,
+    /// Older backups deleted to stay within `BACKUP_KEEP`
+    removed: Vec<String>,
+    /// Object key of the off-box copy, when S3 is configured
+    uploaded: Option<String>,
+}
+
+/// Snapshot the database into the backup directory, then drop the oldest
+/// backups beyond `keep`
+fn create(db_path: &str, config: &BackupConfig) -> Result<Backup, String> {
+    std::fs::create_dir_all(&config.dir)
+        .map_err(|e| format!("Cannot create {}: {e}", config.dir.display()))?;
+    // VACUUM INTO works on a read-only connection, so backups don't need write access
+    let conn = db::open(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Cannot open database: {e}"))?;
+    let stamp: String = conn
+        .query_row("SELECT strftime('%Y%m%d-%H%M%S', 'now')", [], |row| {
+            row.get(0)
+        })
+        .map_err(|e| format!("Query error: {e}"))?;
+    let path = config.dir.join(format!("{PREFIX}{stamp}{SUFFIX}"));
+    // Write under a name pruning ignores, so a failed backup never replaces a good one
+    let partial = path.with_extension("db.partial");
+    let _ = std::fs::remove_file(&partial);
+    conn.execute("VACUUM INTO ?1", [partial.to_string_lossy()])
+        .map_err(|e| format!("Backup failed: {e}"))?;
+    if let Some(telemetry) = db::telemetry_path() {
+        fold_telemetry(&partial, telemetry)?;
+    }
+    std::fs::rename(&partial, &path).map_err(|e| format!("Backup failed: {e}"))?;
+    let bytes = std::fs::metadata(&path).map_or(0, |m| m.len());
+    let removed = prune(&config.dir, config.keep)?;
+    Ok(Backup {
+        path: path.to_string_lossy().into_owned(),
+        bytes,
+        removed,
+        uploaded: None,
+    })
+}
+
+/// Copy the telemetry database's tables into the backup at `path`
+fn fold_telemetry(path: &Path, telemetry: &str) -> Result<(), String> {
+    let mut conn = db::open_alone(&path.to_string_lossy(), OpenFlags::SQLITE_OPEN_READ_WRITE)
+        .map_err(|e| format!("Backup failed: {e}"))?;
+    db::attach_telemetry(&conn, telemetry).map_err(|e| format!("Backup failed: {e}"))?;
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Backup failed: {e}"))?;
+    // Tables before indexes, so each is filled before it's indexed
+    let objects: Vec<(String, String)> = {
+        let mut stmt = tx
+            .prepare(
+                "SELECT tbl_name, sql FROM telemetry.sqlite_master
+                 WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite%'
+                 ORDER BY type = 'index'",
+            )
+            .map_err(|e| format!("Query error: {e}"))?;
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| format!("Query error: {e}"))?
+            .filter_map(|r| r.ok())
+            .collect()
+    };
+    for (table, sql) in objects {
+        // Unqualified, so created in the backup itself
+        tx.execute_batch(&sql)
+            .map_err(|e| format!("Cannot back up {table}: {e}"))?;
+        if sql.starts_with("CREATE TABLE") {
+            tx.execute_batch(&format!(
+                "INSERT INTO main.{table} SELECT * FROM {}.{table}",
+                db::TELEMETRY
+            ))
+            .map_err(|e| format!("Cannot back up {table}: {e}"))?;
+        }
+    }
+    tx.commit().map_err(|e| format!("Backup failed: {e}"))
+}
+
+/// Names of the backups in `dir`, oldest first (timestamped names sort that way)
+fn list(dir: &Path) -> Result<Vec<String>, String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir)
+        .map_err(|e| format!("Cannot list {}: {e}", dir.display()))?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| name.starts_with(PREFIX) && name.ends_with(SUFFIX))
+        .collect();
+    names.sort();
+    Ok(names)
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackupFile {
+    pub path: String,
+    /// UTC, as "YYYY-MM-DD HH:MM:SS"
+    pub taken_at: String,
+    pub bytes: u64,
+}
+
+/// The newest backup in `dir`, if any (a missing directory has none).
+/// Files not named as `create` names them are skipped.
+pub fn latest(dir: &Path) -> Result<Option<BackupFile>, String> {
+    if !dir.exists() {
+        return Ok(None);
+    }
+    let newest = list(dir)?.into_iter().rev().find_map(|name| {
+        let taken_at = taken_at(&name[PREFIX.len()..name.len() - SUFFIX.len()])?;
+        Some((name, taken_at))
+    });
+    let Some((name, taken_at)) = newest else {
+        return Ok(None);
+    };
+    let path = dir.join(&name);
+    Ok(Some(BackupFile {
+        bytes: std::fs::metadata(&path).map_or(0, |m| m.len()),
+        path: path.to_string_lossy().into_owned(),
+        taken_at,
+    }))
+}
+
+/// "YYYY-MM-DD HH:MM:SS" from a backup's `YYYYMMDD-HHMMSS` stamp
+fn taken_at(stamp: &str) -> Option<String> {
+    let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    let (d, t) = stamp.split_once('-')?;
+    if d.len() != 8 || t.len() != 6 || !digits(d) || !digits(t) {
+        return None;
+    }
+    Some(format!(
+        "{}-{}-{} {}:{}:{}",
+        &d[..4],
+        &d[4..6],
+        &d[6..],
+        &t[..2],
+        &t[2..4],
+        &t[4..]
+    ))
+}
+
+/// Delete all but the newest `keep` backups, returning the deleted names
+fn prune(dir: &Path, keep: usize) -> Result<Vec<String>, String> {
+    let mut names = list(dir)?;
+    let excess = names.len().saturating_sub(keep);
+    names.truncate(excess);
+    for name in &names {
+        std::fs::remove_file(dir.join(name)).map_err(|e| format!("Cannot remove {name}: {e}"))?;
+    }
+    Ok(names)
+}
+
+/// Back up locally, then upload the copy if S3 is configured
+async fn create_and_upload(state: &Arc<AppState>, config: BackupConfig) -> Result<Backup, String> {
+    let db_path = state.db_path.clone();
+    let job = config.clone();
+    let mut backup = db::blocking(move || create(&db_path, &job))
+        .await
+        .flatten()?;
+    tracing::info!(
+        "Backed up database to {} ({} bytes)",
+        backup.path,
+        backup.bytes
+    );
+    if let Some(s3) = &config.s3 {
+        let key = s3::upload(&state.http_client, s3, Path::new(&backup.path))
+            .await
+            .map_err(|e| format!("Saved {}, but the upload failed: {e}", backup.path))?;
+        tracing::info!("Uploaded backup to s3://{}/{}", s3.bucket, key);
+        backup.uploaded = Some(key);
+    }
+    Ok(backup)
+}
+
+/// Take a backup now
+pub async fn backup(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Backup>, (StatusCode, String)> {
+    admin::require_admin(&state, &headers)?;
+    if state.db.is_none() {
+        return Err((
+            StatusCode::NOT_IMPLEMENTED,
+            "Backups need SQLite storage".into(),
+        ));
+    }
+    let config = state.backup.clone().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Backups not configured".to_string(),
+    ))?;
+    create_and_upload(&state, config)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+}
+
+/// Take a backup every `interval`, forever. The first waits one interval, so
+/// restarts don't pile up backups.
+pub async fn run(state: Arc<AppState>, config: BackupConfig, interval: Duration) {
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    loop {
+        ticker.tick().await;
+        if let Err(e) = create_and_upload(&state, config.clone()).await {
+            tracing::warn!("Scheduled backup failed: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusqlite::Connection;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    mod create {
+        use super::*;
+
+        #[test]
+        fn copies_the_database() {
+            let dir = temp_dir("backup-create");
+            let db_path = dir.join("digest.db");
+            rusqlite::Connection::open(&db_path)
+                .unwrap()
+                .execute_batch(
+                    "CREATE TABLE digests (date TEXT PRIMARY KEY, html TEXT);
+                     INSERT INTO digests VALUES ('2026-01-01', '<p>one</p>');",
+                )
+                .unwrap();
+            let config = BackupConfig {
+                dir: dir.join("backups"),
+                keep: 2,
+                interval: None,
+                s3: None,
+            };
+            let backup = create(db_path.to_str().unwrap(), &config).unwrap();
+            assert!(backup.bytes > 0);
+            assert!(backup.removed.is_empty());
+            let html: String = rusqlite::Connection::open(&backup.path)
+                .unwrap()
+                .query_row("SELECT html FROM digests", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(html, "<p>one</p>");
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    mod fold_telemetry {
+        use super::*;
+
+        #[test]
+        fn copies_tables_and_indexes() {
+            let dir = temp_dir("backup-fold");
+            let (backup, telemetry) = (dir.join("backup.db"), dir.join("telemetry.db"));
+            Connection::open(&backup)
+                .unwrap()
+                .execute_batch("CREATE TABLE digests (date TEXT PRIMARY KEY)")
+                .unwrap();
+            Connection::open(&telemetry)
+                .unwrap()
+                .execute_batch(
+                    "CREATE TABLE digest_views (digest_date TEXT, visitor TEXT);
+                     CREATE INDEX idx_digest_views_date ON digest_views(digest_date);
+                     INSERT INTO digest_views VALUES ('2026-01-01', 'a'), ('2026-01-01', 'b');",
+                )
+                .unwrap();
+            fold_telemetry(&backup, telemetry.to_str().unwrap()).unwrap();
+            let (views, indexes): (i64, i64) = Connection::open(&backup)
+                .unwrap()
+                .query_row(
+                    "SELECT (SELECT COUNT(*) FROM digest_views),
+                            (SELECT COUNT(*) FROM sqlite_master WHERE name = 'idx_digest_views_date')",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .unwrap();
+            assert_eq!((views, indexes), (2, 1));
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    mod prune {
+        use super::*;
+
+        #[test]
+        fn keeps_the_newest() {
+            let dir = temp_dir("backup-prune");
+            for name in [
+                "digest-20260103-000000.db",
+                "digest-20260101-000000.db",
+                "digest-20260102-000000.db",
+                "digest-20260104-000000.db.partial",
+                "notes.txt",
+            ] {
+                std::fs::write(dir.join(name), "").unwrap();
+            }
+            assert_eq!(
+                prune(&dir, 2).unwrap(),
+                ["digest-20260101-000000.db".to_string()]
+            );
+            assert!(dir.join("digest-20260102-000000.db").exists());
+            assert!(dir.join("digest-20260104-000000.db.partial").exists());
+            assert!(dir.join("notes.txt").exists());
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    mod latest {
+        use super::*;
+
+        #[test]
+        fn skips_misnamed_files() {
+            let dir = temp_dir("backup-latest");
+            assert!(latest(&dir).unwrap().is_none());
+            for name in [
+                "digest-20260101-000000.db",
+                "digest-20260102-063000.db",
+                "digest-2026010é-000000.db",
+                "digest-20260103.db",
+                "digest-x.db",
+            ] {
+                std::fs::write(dir.join(name), "").unwrap();
+            }
+            let newest = latest(&dir).unwrap().unwrap();
+            assert_eq!(newest.taken_at, "2026-01-02 06:30:00");
+            assert!(newest.path.ends_with("digest-20260102-063000.db"));
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! Hacker News and subreddits as sources. A source whose URL is Hacker
+//! News's front page, or a subreddit, is read from the site's JSON listing
+//! of its top items rather than a feed: each item becomes an article linking
+//! to what it's about, and carries its points and comment count, which
+//! scoring counts as its popularity. Discussions without a link (Ask HN, or
+//! a subreddit's self posts) aren't news and are left out, as are Reddit's
+//! pinned and NSFW posts.
+
+use crate::feeds::Article;
+use chrono::{DateTime, TimeZone, Utc};
+use reqwest::Url;
+use serde::Deserialize;
+
+/// Hacker News's front page, from its search API
+const HACKER_NEWS: &str = "https://hn.algolia.com/api/v1/search?tags=front_page&hitsPerPage=30";
+
+/// How many of a subreddit's top posts of the day are read
+const REDDIT_POSTS: usize = 25;
+
+#[derive(Deserialize)]
+struct HackerNews {
+    hits: Vec<Hit>,
+}
+
+#[derive(Deserialize)]
+struct Hit {
+    title: Option<String>,
+    url: Option<String>,
+    #[serde(default)]
+    points: u64,
+    #[serde(default)]
+    num_comments: u64,
+    created_at_i: Option<i64>,
+}
+
+#[derive(Deserialize)]
+struct Reddit {
+    data: Listing,
+}
+
+#[derive(Deserialize)]
+struct Listing {
+    children: Vec<Child>,
+}
+
+#[derive(Deserialize)]
+struct Child {
+    data: Post,
+}
+
+#[derive(Deserialize)]
+struct Post {
+    title: String,
+    url: Option<String>,
+    #[serde(default)]
+    score: i64,
+    #[serde(default)]
+    num_comments: u64,
+    created_utc: Option<f64>,
+    #[serde(default)]
+    is_self: bool,
+    #[serde(default)]
+    stickied: bool,
+    #[serde(default)]
+    over_18: bool,
+}
+
+/// The listing to read for the source at `url`, when it's Hacker News or a
+/// subreddit
+pub fn listing_url(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    match host {
+        "news.ycombinator.com" if matches!(segments[..], [] | ["news" | "front"]) => {
+            Some(HACKER_NEWS.into())
+        }
+        "reddit.com" | "www.reddit.com" | "old.reddit.com" => match segments[..] {
+            ["r", subreddit, ..]
+                if subreddit
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'_') =>
+            {
+                // raw_json, or titles come HTML-escaped
+                Some(format!(
+                    "https://www.reddit.com/r/{subreddit}/top.json?t=day&limit={REDDIT_POSTS}&raw_json=1"
+                ))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether `url` is a listing [`listing_url`] gives
+pub fn is_listing(url: &str) -> bool {
+    url == HACKER_NEWS || url.starts_with("https://www.reddit.com/r/") && url.contains("/top.json?")
+}
+
+/// The articles in a listing fetched from `url`, dated no later than `now`
+pub fn parse(body: &[u8], url: &str, now: DateTime<Utc>) -> Result<Vec<Article>, String> {
+    let article = |title: String, link: String, points, comments, published: Option<_>| Article {
+        title: title.split_whitespace().collect::<Vec<_>>().join(" "),
+        url: link,
+        published: published.map(|date: DateTime<Utc>| date.min(now)),
+        summary: String::new(),
+        content: None,
+        story: None,
+        image: None,
+        points: Some(points),
+        comments: Some(comments),
+        keywords: Vec::new(),
+    };
+    let is_web =
+        |link: &str| Url::parse(link).is_ok_and(|link| matches!(link.scheme(), "http" | "https"));
+    if url == HACKER_NEWS {
+        let listing: HackerNews =
+            serde_json::from_slice(body).map_err(|e| format!("Listing parse error: {e}"))?;
+        return Ok(listing
+            .hits
+            .into_iter()
+            .filter_map(|hit| {
+                let link = hit.url.filter(|link| is_web(link))?;
+                let published = hit
+                    .created_at_i
+                    .and_then(|secs| Utc.timestamp_opt(secs, 0).single());
+                Some(article(
+                    hit.title?,
+                    link,
+                    hit.points,
+                    hit.num_comments,
+                    published,
+                ))
+            })
+            .collect());
+    }
+    let listing: Reddit =
+        serde_json::from_slice(body).map_err(|e| format!("Listing parse error: {e}"))?;
+    Ok(listing
+        .data
+        .children
+        .into_iter()
+        .map(|child| child.data)
+        .filter(|post| !(post.is_self || post.stickied || post.over_18))
+        .filter_map(|post| {
+            let link = post.url.filter(|link| is_web(link))?;
+            let published = post
+                .created_utc
+                .and_then(|secs| Utc.timestamp_opt(secs as i64, 0).single());
+            Some(article(
+                post.title,
+                link,
+                post.score.max(0) as u64,
+                post.num_comments,
+                published,
+            ))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod listing_url {
+        use super::*;
+
+        #[test]
+        fn knows_hacker_news_and_subreddits() {
+            for url in [
+                "https://news.ycombinator.com/",
+                "https://news.ycombinator.com/news",
+            ] {
+                assert_eq!(listing_url(url).as_deref(), Some(HACKER_NEWS), "{url}");
+            }
+            assert_eq!(
+                listing_url("https://old.reddit.com/r/worldnews/hot/").as_deref(),
+                Some("https://www.reddit.com/r/worldnews/top.json?t=day&limit=25&raw_json=1")
+            );
+            assert_eq!(listing_url("https://news.ycombinator.com/item?id=1"), None);
+            assert_eq!(listing_url("https://www.reddit.com/user/someone"), None);
+            assert_eq!(listing_url("https://example.com/r/worldnews"), None);
+            assert!(is_listing(
+                &listing_url("https://www.reddit.com/r/worldnews").unwrap()
+            ));
+            assert!(!is_listing("https://www.reddit.com/r/worldnews/.rss"));
+        }
+    }
+
+    mod parse {
+        use super::*;
+
+        fn now() -> DateTime<Utc> {
+            "2025-01-16T00:00:00Z".parse().unwrap()
+        }
+
+        #[test]
+        fn reads_hacker_news_links_with_their_points() {
+            let body = br#"{"hits": [
+                {"title": "A new  kernel", "url": "https://example.com/kernel", "points": 412,
+                 "num_comments": 230, "created_at_i": 1736937000, "objectID": "1"},
+                {"title": "Ask HN: Anyone?", "url": null, "points": 90, "num_comments": 80,
+                 "created_at_i": 1736937000, "objectID": "2"}
+            ]}"#;
+            let articles = parse(body, HACKER_NEWS, now()).unwrap();
+            assert_eq!(articles.len(), 1);
+            assert_eq!(articles[0].title, "A new kernel");
+            assert_eq!(articles[0].url, "https://example.com/kernel");
+            assert_eq!(
+                (articles[0].points, articles[0].comments),
+                (Some(412), Some(230))
+            );
+            assert_eq!(
+                articles[0].published,
+                Some("2025-01-15T10:30:00Z".parse().unwrap())
+            );
+        }
+
+        #[test]
+        fn reads_reddit_link_posts() {
+            let url = listing_url("https://www.reddit.com/r/worldnews").unwrap();
+            let body = br#"{"kind": "Listing", "data": {"children": [
+                {"kind": "t3", "data": {"title": "Rules & news", "url": "https://example.com/a",
+                 "score": 5120, "num_comments": 801, "created_utc": 1736937000.0,
+                 "is_self": false, "stickied": false, "over_18": false}},
+                {"kind": "t3", "data": {"title": "Live thread", "url": "https://www.reddit.com/r/worldnews/comments/x/",
+                 "score": 300, "num_comments": 9000, "created_utc": 1736937000.0,
+                 "is_self": true, "stickied": true, "over_18": false}}
+            ]}}"#;
+            let articles = parse(body, &url, now()).unwrap();
+            assert_eq!(articles.len(), 1);
+            assert_eq!(articles[0].title, "Rules & news");
+            assert_eq!(
+                (articles[0].points, articles[0].comments),
+                (Some(5120), Some(801))
+            );
+            assert!(parse(b"<html>", &url, now()).is_err());
+        }
+    }
+}
@@ -0,0 +1,118 @@
+//! Digests for days a run missed, made afterwards from `article_archive`.
+//!
+//! `fetch` archives the articles it keeps, and the next fetch after an
+//! outage keeps everything published since the last digest, so a missed
+//! day's articles are there once the pipeline is back. The digest of a day
+//! is made from the articles published the day before, as a run that morning
+//! would have fetched them.
+
+use chrono::{Days, NaiveDate};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Days articles stay in `article_archive` (`ARTICLE_ARCHIVE_DAYS`, default
+/// 30; 0 archives none)
+pub fn archive_days() -> u32 {
+    std::env::var("ARTICLE_ARCHIVE_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(30)
+}
+
+/// The days to backfill, oldest first: `--date`, or `--from` through `--to`
+/// (yesterday by default). Only days before `today` can be backfilled.
+pub fn dates(
+    date: Option<&str>,
+    from: Option<&str>,
+    to: Option<&str>,
+    today: NaiveDate,
+) -> Result<Vec<NaiveDate>, String> {
+    let parse = |flag: &str, value: &str| {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map_err(|_| format!("{flag} must be a date as YYYY-MM-DD, not {value}"))
+    };
+    let yesterday = today - Days::new(1);
+    let (from, to) = match (date, from, to) {
+        (Some(date), None, None) => {
+            let date = parse("--date", date)?;
+            (date, date)
+        }
+        (None, Some(from), to) => (
+            parse("--from", from)?,
+            to.map_or(Ok(yesterday), |to| parse("--to", to))?,
+        ),
+        _ => return Err("Give either --date, or --from (and optionally --to)".into()),
+    };
+    if to >= today {
+        return Err(format!(
+            "Only past days can be backfilled, up to {yesterday}"
+        ));
+    }
+    if from > to {
+        return Err(format!("--from {from} is after --to {to}"));
+    }
+    Ok(from.iter_days().take_while(|d| *d <= to).collect())
+}
+
+/// Replace the files in `dir` with one `{source_id}.json` per source, as
+/// `fetch` writes `fetched/`, for `curate::clusters` to read
+pub fn write(
+    dir: &Path,
+    articles: &BTreeMap<String, Vec<serde_json::Value>>,
+) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {e}", dir.display()))?;
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("Cannot read {}: {e}", dir.display()))?;
+    for entry in entries.flatten() {
+        if entry.path().extension().is_some_and(|ext| ext == "json") {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+    for (source_id, articles) in articles {
+        let path = dir.join(format!("{source_id}.json"));
+        let json = serde_json::to_string_pretty(articles)
+            .map_err(|e| format!("Cannot encode articles: {e}"))?;
+        std::fs::write(&path, json).map_err(|e| format!("Cannot write {}: {e}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(date: &str) -> NaiveDate {
+        date.parse().unwrap()
+    }
+
+    mod dates {
+        use super::*;
+
+        #[test]
+        fn takes_a_day_or_a_range_up_to_yesterday() {
+            let today = day("2026-01-10");
+            assert_eq!(
+                dates(Some("2026-01-05"), None, None, today).unwrap(),
+                [day("2026-01-05")]
+            );
+            assert_eq!(
+                dates(None, Some("2026-01-07"), None, today).unwrap(),
+                [day("2026-01-07"), day("2026-01-08"), day("2026-01-09")]
+            );
+            assert_eq!(
+                dates(None, Some("2026-01-03"), Some("2026-01-04"), today).unwrap(),
+                [day("2026-01-03"), day("2026-01-04")]
+            );
+        }
+
+        #[test]
+        fn rejects_today_backwards_ranges_and_bad_dates() {
+            let today = day("2026-01-10");
+            assert!(dates(Some("2026-01-10"), None, None, today).is_err());
+            assert!(dates(None, Some("2026-01-08"), Some("2026-01-06"), today).is_err());
+            assert!(dates(Some("Jan 5"), None, None, today).is_err());
+            assert!(dates(None, None, None, today).is_err());
+            assert!(dates(Some("2026-01-05"), Some("2026-01-04"), None, today).is_err());
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! Circuit breakers, one per source for the length of a run: a source whose
+//! requests keep failing, or that has used up its share of the run's time,
+//! has the rest of its requests skipped rather than left to time out one
+//! after another. Why a breaker opened is noted in the source's
+//! `source_health` row.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// When a source's breaker opens
+#[derive(Clone, Copy)]
+pub struct Limits {
+    /// Failed requests in a row
+    pub failures: u32,
+    /// Time spent on the source's requests, retries and article pages
+    /// included, but not time queued behind other requests
+    pub budget: Duration,
+}
+
+impl Limits {
+    /// `SOURCE_MAX_FAILURES` (default 5) and `SOURCE_TIME_BUDGET` seconds
+    /// (default 120)
+    pub fn from_env() -> Self {
+        let failures = std::env::var("SOURCE_MAX_FAILURES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(5u32);
+        let budget = std::env::var("SOURCE_TIME_BUDGET")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|secs: &f64| secs.is_finite() && *secs > 0.0)
+            .unwrap_or(120.0);
+        Self {
+            failures: failures.max(1),
+            budget: Duration::from_secs_f64(budget),
+        }
+    }
+}
+
+/// Every source's breaker
+pub struct Breakers {
+    limits: Limits,
+    sources: Mutex<HashMap<String, Breaker>>,
+}
+
+#[derive(Default)]
+struct Breaker {
+    failures: u32,
+    spent: Duration,
+    /// Why it opened
+    open: Option<String>,
+    /// Requests not sent since
+    skipped: usize,
+}
+
+impl Breakers {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            sources: Mutex::default(),
+        }
+    }
+
+    /// Whether a request for `source` may be sent. If not, why, and the
+    /// request counts as skipped.
+    pub fn check(&self, source: &str) -> Result<(), String> {
+        let mut sources = self.sources.lock().expect("breakers lock");
+        let breaker = sources.entry(source.to_string()).or_default();
+        match &breaker.open {
+            Some(reason) => {
+                breaker.skipped += 1;
+                Err(reason.clone())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Count a request for `source` that took `elapsed`, failing with
+    /// `error` if it did
+    pub fn record(&self, source: &str, elapsed: Duration, error: Option<&str>) {
+        let mut sources = self.sources.lock().expect("breakers lock");
+        let breaker = sources.entry(source.to_string()).or_default();
+        breaker.spent += elapsed;
+        breaker.failures = match error {
+            Some(_) => breaker.failures + 1,
+            None => 0,
+        };
+        if breaker.open.is_some() {
+            return;
+        }
+        if let Some(error) = error.filter(|_| breaker.failures >= self.limits.failures) {
+            breaker.open = Some(format!(
+                "circuit open after {} failures in a row (last: {error})",
+                breaker.failures
+            ));
+        } else if breaker.spent >= self.limits.budget {
+            breaker.open = Some(self.over_budget());
+        }
+    }
+
+    /// Count time spent on a request for `source` that says nothing of its
+    /// health either way, such as one looking for its feed where it might be
+    pub fn spend(&self, source: &str, elapsed: Duration) {
+        let mut sources = self.sources.lock().expect("breakers lock");
+        let breaker = sources.entry(source.to_string()).or_default();
+        breaker.spent += elapsed;
+        if breaker.open.is_none() && breaker.spent >= self.limits.budget {
+            breaker.open = Some(self.over_budget());
+        }
+    }
+
+    fn over_budget(&self) -> String {
+        format!(
+            "circuit open after using its {}s time budget",
+            self.limits.budget.as_secs_f64()
+        )
+    }
+
+    /// Why `source`'s breaker opened and how many requests it skipped, if
+    /// it did
+    pub fn note(&self, source: &str) -> Option<String> {
+        let sources = self.sources.lock().expect("breakers lock");
+        let breaker = sources.get(source)?;
+        let reason = breaker.open.as_ref()?;
+        Some(match breaker.skipped {
+            0 => reason.clone(),
+            skipped => format!("{reason}; skipped {skipped} request(s)"),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: Limits = Limits {
+        failures: 3,
+        budget: Duration::from_secs(60),
+    };
+
+    mod record {
+        use super::*;
+
+        #[test]
+        fn opens_after_failures_in_a_row() {
+            let breakers = Breakers::new(LIMITS);
+            let second = Duration::from_secs(1);
+            breakers.record("a", second, Some("HTTP 503"));
+            breakers.record("a", second, Some("HTTP 503"));
+            breakers.record("a", second, None);
+            breakers.record("a", second, Some("HTTP 503"));
+            breakers.record("a", second, Some("HTTP 503"));
+            assert_eq!(breakers.check("a"), Ok(()));
+            breakers.record("a", second, Some("Timed out"));
+            assert_eq!(
+                breakers.check("a").unwrap_err(),
+                "circuit open after 3 failures in a row (last: Timed out)"
+            );
+            assert_eq!(breakers.check("b"), Ok(()));
+        }
+
+        #[test]
+        fn opens_once_the_time_budget_is_spent() {
+            let breakers = Breakers::new(LIMITS);
+            breakers.record("a", Duration::from_secs(40), None);
+            breakers.spend("a", Duration::from_secs(5));
+            assert_eq!(breakers.check("a"), Ok(()));
+            breakers.record("a", Duration::from_secs(15), None);
+            assert_eq!(
+                breakers.check("a").unwrap_err(),
+                "circuit open after using its 60s time budget"
+            );
+        }
+    }
+
+    mod note {
+        use super::*;
+
+        #[test]
+        fn counts_skipped_requests() {
+            let breakers = Breakers::new(LIMITS);
+            assert_eq!(breakers.note("a"), None);
+            breakers.record("a", Duration::from_secs(90), Some("Timed out"));
+            assert_eq!(
+                breakers.note("a").as_deref(),
+                Some("circuit open after using its 60s time budget")
+            );
+            let _ = breakers.check("a");
+            let _ = breakers.check("a");
+            assert_eq!(
+                breakers.note("a").as_deref(),
+                Some("circuit open after using its 60s time budget; skipped 2 request(s)")
+            );
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! A run's spending limit on model requests (`LLM_BUDGET_USD`,
+//! `LLM_BUDGET_TOKENS`). Each request reserves what it could cost, its reply
+//! capped to fit, before it's sent, and settles up with what it did cost
+//! once answered, so requests in flight together can't overspend between
+//! them. Curation plans around what's left, and leaves work out rather than
+//! go over.
+
+use crate::llm::Usage;
+use serde::Serialize;
+use std::sync::Mutex;
+
+/// The limits a run was given
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Limits {
+    pub budget_usd: Option<f64>,
+    /// Input and output tokens together
+    pub budget_tokens: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct Budget {
+    limits: Limits,
+    /// What requests answered cost, and what those in flight may
+    accounts: Mutex<(Usage, Usage)>,
+}
+
+impl Budget {
+    /// `LLM_BUDGET_USD` and `LLM_BUDGET_TOKENS`, each unlimited when unset or 0
+    pub fn from_env() -> Self {
+        let positive = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|n| n.is_finite() && *n > 0.0)
+        };
+        Self::new(Limits {
+            budget_usd: positive("LLM_BUDGET_USD"),
+            budget_tokens: positive("LLM_BUDGET_TOKENS").map(|n| n as u64),
+        })
+    }
+
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            accounts: Mutex::default(),
+        }
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.limits != Limits::default()
+    }
+
+    /// Whether `cost` fits in what's neither spent nor reserved
+    pub fn fits(&self, cost: &Usage) -> bool {
+        let (spent, reserved) = *self.lock();
+        self.within(spent, reserved, cost)
+    }
+
+    /// Set `cost` aside for a request, if it fits
+    pub fn reserve(&self, cost: &Usage) -> bool {
+        let mut accounts = self.lock();
+        let (spent, reserved) = *accounts;
+        if !self.within(spent, reserved, cost) {
+            return false;
+        }
+        accounts.1 += *cost;
+        true
+    }
+
+    /// Release a request's reservation and charge what it did cost
+    pub fn settle(&self, reserved: &Usage, cost: Usage) {
+        let mut accounts = self.lock();
+        accounts.0 += cost;
+        let held = &mut accounts.1;
+        held.input_tokens = held.input_tokens.saturating_sub(reserved.input_tokens);
+        held.output_tokens = held.output_tokens.saturating_sub(reserved.output_tokens);
+        held.cost_usd = (held.cost_usd - reserved.cost_usd).max(0.0);
+    }
+
+    fn within(&self, spent: Usage, reserved: Usage, cost: &Usage) -> bool {
+        let tokens = |u: &Usage| u.input_tokens + u.output_tokens;
+        self.limits
+            .budget_usd
+            .is_none_or(|usd| spent.cost_usd + reserved.cost_usd + cost.cost_usd <= usd)
+            && self
+                .limits
+                .budget_tokens
+                .is_none_or(|limit| tokens(&spent) + tokens(&reserved) + tokens(cost) <= limit)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, (Usage, Usage)> {
+        self.accounts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(tokens: u64, cost_usd: f64) -> Usage {
+        Usage {
+            input_tokens: tokens,
+            output_tokens: 0,
+            cost_usd,
+        }
+    }
+
+    mod budget {
+        use super::*;
+
+        #[test]
+        fn reserves_until_settled() {
+            let budget = Budget::new(Limits {
+                budget_usd: Some(1.0),
+                budget_tokens: None,
+            });
+            let request = usage(1000, 0.6);
+            assert!(budget.reserve(&request));
+            // The first request might still cost what it reserved
+            assert!(!budget.reserve(&request));
+            budget.settle(&request, usage(500, 0.3));
+            assert!(budget.reserve(&request));
+            assert!(!budget.fits(&usage(0, 0.2)));
+            budget.settle(&request, usage(500, 0.3));
+            assert!(budget.fits(&usage(0, 0.4)));
+            assert!(!budget.fits(&usage(0, 0.41)));
+        }
+
+        #[test]
+        fn limits_tokens_and_nothing_when_unset() {
+            let budget = Budget::new(Limits {
+                budget_usd: None,
+                budget_tokens: Some(100),
+            });
+            assert!(budget.fits(&usage(100, 50.0)));
+            assert!(!budget.fits(&usage(101, 0.0)));
+
+            let unlimited = Budget::default();
+            assert!(!unlimited.is_limited());
+            assert!(unlimited.reserve(&usage(u64::MAX / 4, 1e9)));
+        }
+    }
+}
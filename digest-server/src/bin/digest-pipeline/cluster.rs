@@ -0,0 +1,231 @@
+//! Stories: the articles, once duplicates are gone, that report the same
+//! events. Each article is a TF-IDF vector of its title (counted twice),
+//! summary, and keywords, over the words it shares with some other article,
+//! and joins the story whose centroid it's most like, if it's like any
+//! closely enough.
+//! Stories of two or more articles get an id, `s1` covering the most
+//! sources, which `run.py` hands the curator so a narrative can draw on
+//! every side's reporting.
+
+use crate::feeds::Article;
+use crate::pages::plain_text;
+use std::collections::{HashMap, HashSet};
+
+/// Cosine similarity to a story's centroid an article needs to join it
+const STORY_SIMILARITY: f64 = 0.3;
+
+/// Words an article needs in common with a story to join it, so one name
+/// in two headlines doesn't make them one story
+const MIN_SHARED_WORDS: usize = 2;
+
+/// Words too common to say what a story is about
+const STOPWORDS: &[&str] = &[
+    "about", "after", "again", "against", "also", "amid", "and", "are", "been", "before", "being",
+    "but", "can", "could", "did", "does", "for", "from", "had", "has", "have", "her", "his", "how",
+    "into", "its", "more", "new", "not", "now", "off", "one", "our", "out", "over", "said", "says",
+    "she", "than", "that", "the", "their", "them", "then", "there", "these", "they", "this",
+    "those", "through", "two", "under", "was", "were", "what", "when", "where", "which", "while",
+    "who", "why", "will", "with", "would", "year", "years", "you", "your",
+];
+
+type Vector = HashMap<String, f64>;
+
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .filter(|word| !STOPWORDS.contains(&word.as_str()))
+}
+
+/// Term counts of an article's title, twice over, and its summary and
+/// keywords
+fn terms(article: &Article) -> HashMap<String, f64> {
+    let mut counts = HashMap::new();
+    for word in words(&article.title) {
+        *counts.entry(word).or_default() += 2.0;
+    }
+    for word in
+        words(&plain_text(&article.summary)).chain(article.keywords.iter().flat_map(|k| words(k)))
+    {
+        *counts.entry(word).or_default() += 1.0;
+    }
+    counts
+}
+
+fn normalize(mut vector: Vector) -> Vector {
+    let norm = vector.values().map(|w| w * w).sum::<f64>().sqrt();
+    if norm > 0.0 {
+        vector.values_mut().for_each(|w| *w /= norm);
+    }
+    vector
+}
+
+/// Cosine similarity of two normalized vectors, and how many terms they share
+fn cosine(a: &Vector, b: &Vector) -> (f64, usize) {
+    let (small, large) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    small
+        .iter()
+        .filter_map(|(term, w)| large.get(term).map(|v| w * v))
+        .fold((0.0, 0), |(sum, shared), product| {
+            (sum + product, shared + 1)
+        })
+}
+
+/// Give each story of two or more articles an id, in every one of its
+/// articles. Returns how many stories span more than one source.
+pub fn assign_stories(fetched: &mut [(&str, Vec<Article>)]) -> usize {
+    let positions: Vec<(usize, usize)> = fetched
+        .iter()
+        .enumerate()
+        .flat_map(|(source_index, (_, articles))| {
+            (0..articles.len()).map(move |index| (source_index, index))
+        })
+        .collect();
+    let counts: Vec<HashMap<String, f64>> = positions
+        .iter()
+        .map(|&(source_index, index)| terms(&fetched[source_index].1[index]))
+        .collect();
+
+    // Words in every article say nothing, and rare ones the most, but a word
+    // in one article alone can't tie it to another
+    let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+    for terms in &counts {
+        for term in terms.keys() {
+            *document_frequency.entry(term).or_default() += 1;
+        }
+    }
+    let total = counts.len() as f64;
+    let vectors: Vec<Vector> = counts
+        .iter()
+        .map(|terms| {
+            normalize(
+                terms
+                    .iter()
+                    .filter(|(term, _)| document_frequency[term.as_str()] > 1)
+                    .map(|(term, count)| {
+                        let df = document_frequency[term.as_str()] as f64;
+                        (term.clone(), count * ((1.0 + total) / (1.0 + df)).ln())
+                    })
+                    .collect(),
+            )
+        })
+        .collect();
+
+    // (centroid, summed member vectors, members)
+    let mut stories: Vec<(Vector, Vector, Vec<usize>)> = Vec::new();
+    for (member, vector) in vectors.into_iter().enumerate() {
+        let best = stories
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (centroid, _, _))| {
+                let (similarity, shared) = cosine(centroid, &vector);
+                (similarity >= STORY_SIMILARITY && shared >= MIN_SHARED_WORDS)
+                    .then_some((i, similarity))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        match best {
+            Some((i, _)) => {
+                let (centroid, sum, members) = &mut stories[i];
+                for (term, weight) in vector {
+                    *sum.entry(term).or_default() += weight;
+                }
+                *centroid = normalize(sum.clone());
+                members.push(member);
+            }
+            None => stories.push((vector.clone(), vector, vec![member])),
+        }
+    }
+
+    // Ids by breadth of coverage, then size, then first appearance
+    let mut stories: Vec<(usize, Vec<usize>)> = stories
+        .into_iter()
+        .map(|(_, _, members)| members)
+        .filter(|members| members.len() > 1)
+        .map(|members| {
+            let sources: HashSet<usize> = members.iter().map(|&m| positions[m].0).collect();
+            (sources.len(), members)
+        })
+        .collect();
+    stories.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.len().cmp(&a.1.len())));
+    for (number, (_, members)) in stories.iter().enumerate() {
+        for &member in members {
+            let (source_index, index) = positions[member];
+            fetched[source_index].1[index].story = Some(format!("s{}", number + 1));
+        }
+    }
+    stories.iter().filter(|(sources, _)| *sources > 1).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article(title: &str, summary: &str) -> Article {
+        Article {
+            title: title.into(),
+            url: format!("https://example.com/{}", title.len()),
+            published: None,
+            summary: summary.into(),
+            content: None,
+            story: None,
+            image: None,
+            points: None,
+            comments: None,
+            keywords: Vec::new(),
+        }
+    }
+
+    mod assign_stories {
+        use super::*;
+
+        #[test]
+        fn groups_reports_of_the_same_events() {
+            let mut fetched = vec![
+                (
+                    "left",
+                    vec![
+                        article(
+                            "Hurricane Milton makes landfall near Tampa",
+                            "<p>The storm brought flooding and power cuts to Florida's Gulf coast.</p>",
+                        ),
+                        article("Senate passes farm bill", "Subsidies for growers rise."),
+                        article("Trump rallies in Ohio", "Crowds gather."),
+                    ],
+                ),
+                (
+                    "right",
+                    vec![article(
+                        "Milton slams Florida as hurricane hits Tampa Bay",
+                        "Millions without power after landfall.",
+                    )],
+                ),
+                (
+                    "wire",
+                    vec![
+                        article(
+                            "Florida braces for flooding after Hurricane Milton landfall",
+                            "Tampa residents told to stay home.",
+                        ),
+                        article("Chip exports curbed", "New rules on semiconductors."),
+                        article("Trump sues newspaper", "Libel claim filed."),
+                    ],
+                ),
+            ];
+            assert_eq!(assign_stories(&mut fetched), 1);
+            let story = |s: usize, i: usize| fetched[s].1[i].story.as_deref();
+            assert_eq!(story(0, 0), Some("s1"));
+            assert_eq!(story(1, 0), Some("s1"));
+            assert_eq!(story(2, 0), Some("s1"));
+            for (s, i) in [(0, 1), (0, 2), (2, 1), (2, 2)] {
+                assert_eq!(story(s, i), None);
+            }
+        }
+
+        #[test]
+        fn handles_no_articles() {
+            assert_eq!(assign_stories(&mut []), 0);
+            let mut fetched = vec![("empty", Vec::new())];
+            assert_eq!(assign_stories(&mut fetched), 0);
+        }
+    }
+}
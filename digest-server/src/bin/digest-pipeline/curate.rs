@@ -0,0 +1,1077 @@
+//! The digest's selection, made by a language model: each story in
+//! `fetched/` (or article of its own) is tiered and written up, a batch of
+//! stories per request, and a last request writes the regional summaries.
+//! With a cheap model configured, it does all that, and the main model is
+//! only asked about the stories it puts in must_know, which the main model
+//! tiers and writes up again. Under a budget (see `budget`), curation plans
+//! what it can afford first: briefer summaries, then fewer of the stories
+//! that would only be signals. With tiering rules (see `scoring`), the
+//! model's tiers give way to the rules' scores. The result is the `selections.json` that
+//! `run.py --skip-select` renders, in the shape the `write_selections` tool
+//! gives it.
+
+use crate::budget::Budget;
+use crate::llm::{self, Model, Usage};
+use crate::pages::plain_text;
+use crate::prompts::Prompts;
+use crate::scoring::{self, Rules};
+use crate::sources::Source;
+use crate::youtube;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Stories per request
+const STORIES_PER_REQUEST: usize = 30;
+
+/// Requests in flight at once
+const CONCURRENT_REQUESTS: usize = 4;
+
+/// Longest reply to a request
+const MAX_REPLY_TOKENS: u32 = 8192;
+
+/// Stories the main model is budgeted to review when a cheap model tiers them
+const REVIEW_STORIES: usize = 10;
+
+/// Characters of digest the summary prompt is budgeted per story
+const SUMMARY_CHARS_PER_STORY: usize = 250;
+
+/// Attempts at a batch whose reply can't be read
+const READ_ATTEMPTS: u32 = 2;
+
+/// Signal groups, in display order, as `run.py` renders them
+pub const REGIONS: &[&str] = &[
+    "americas",
+    "europe",
+    "asia_pacific",
+    "middle_east_africa",
+    "tech",
+];
+
+/// Coverage topics, as `mcp_server.py` has them
+pub const TOPICS: &[&str] = &["geopolitics", "tech_ai", "privacy", "economy", "other"];
+
+/// How much the model is asked to write, and read, per story
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Detail {
+    Full,
+    /// Shorter article text and summaries, for a run short of budget
+    Brief,
+}
+
+impl Detail {
+    /// Longest article text put in a prompt, in characters
+    fn article_chars(self) -> usize {
+        match self {
+            Detail::Full => 600,
+            Detail::Brief => 250,
+        }
+    }
+
+    /// Reply tokens allowed per story
+    fn story_tokens(self) -> u32 {
+        match self {
+            Detail::Full => 250,
+            Detail::Brief => 120,
+        }
+    }
+
+    fn story_summary(self) -> &'static str {
+        match self {
+            Detail::Full => "2-3 sentences",
+            Detail::Brief => "1 sentence",
+        }
+    }
+
+    /// Reply tokens allowed per region
+    fn region_tokens(self) -> u32 {
+        match self {
+            Detail::Full => 300,
+            Detail::Brief => 120,
+        }
+    }
+
+    fn region_summary(self) -> &'static str {
+        match self {
+            Detail::Full => "3-5 sentences",
+            Detail::Brief => "1-2 sentences",
+        }
+    }
+}
+
+/// What a run sets out to do: how much detail, for how many of the
+/// clusters (the most widely reported first)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plan {
+    pub detail: Detail,
+    pub stories: usize,
+}
+
+/// The models curation asks: `main`, and `cheap`, if set, for all but
+/// must_know stories
+pub struct Models {
+    pub main: Arc<Model>,
+    pub cheap: Option<Arc<Model>>,
+}
+
+impl Models {
+    /// The model that tiers every story and writes the summaries
+    pub fn writer(&self) -> &Arc<Model> {
+        self.cheap.as_ref().unwrap_or(&self.main)
+    }
+}
+
+/// An article as digest-pipeline wrote it to `fetched/`
+#[derive(Clone, Debug, Deserialize)]
+pub struct FetchedArticle {
+    pub title: String,
+    pub url: String,
+    /// RFC 3339, if the feed dated it
+    #[serde(default)]
+    pub published: Option<String>,
+    #[serde(default)]
+    pub summary: String,
+    #[serde(default)]
+    pub content: Option<String>,
+    #[serde(default)]
+    pub story: Option<String>,
+    /// ISO 639-1 code of the language it's written in, once told (see
+    /// `language`)
+    #[serde(skip)]
+    pub language: Option<&'static str>,
+    /// The picture its feed lists
+    #[serde(default)]
+    pub image: Option<String>,
+    /// Its points and comment count on the aggregator it came from
+    #[serde(default)]
+    pub points: Option<u64>,
+    #[serde(default)]
+    pub comments: Option<u64>,
+    /// Its publisher's keywords, from a news sitemap
+    #[serde(default)]
+    pub keywords: Vec<String>,
+}
+
+/// A story and the reports of it, or one article of its own
+#[derive(Debug)]
+pub struct Cluster<'a> {
+    pub id: String,
+    pub articles: Vec<(&'a Source, FetchedArticle)>,
+}
+
+/// The fetched articles of `sources`, grouped by story, the most widely
+/// reported first
+pub fn clusters<'a>(dir: &Path, sources: &'a [Source]) -> Result<Vec<Cluster<'a>>, String> {
+    let mut stories: BTreeMap<String, Vec<(&Source, FetchedArticle)>> = BTreeMap::new();
+    let mut singles = Vec::new();
+    for source in sources {
+        let path = dir.join(format!("{}.json", source.id));
+        let json = match std::fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("Cannot read {}: {e}", path.display())),
+        };
+        let articles: Vec<FetchedArticle> = serde_json::from_str(&json)
+            .map_err(|e| format!("Cannot read {}: {e}", path.display()))?;
+        for article in articles {
+            match article.story.clone() {
+                Some(story) => stories.entry(story).or_default().push((source, article)),
+                None => singles.push((source, article)),
+            }
+        }
+    }
+    let mut clusters: Vec<Cluster> = stories
+        .into_values()
+        .chain(singles.into_iter().map(|single| vec![single]))
+        .map(|articles| Cluster {
+            id: String::new(),
+            articles,
+        })
+        .collect();
+    // Stable, so stories keep their order (s1 first) and singles follow
+    clusters.sort_by_key(|c| std::cmp::Reverse(c.articles.len()));
+    for (number, cluster) in clusters.iter_mut().enumerate() {
+        cluster.id = format!("c{}", number + 1);
+    }
+    Ok(clusters)
+}
+
+/// What the editor made of one story
+#[derive(Debug, Deserialize)]
+struct Verdict {
+    story: String,
+    tier: Tier,
+    #[serde(default)]
+    region: Option<String>,
+    #[serde(default)]
+    topic: Option<String>,
+    headline: String,
+    /// How much the story matters, 1-10
+    #[serde(default)]
+    importance: Option<f64>,
+    #[serde(default)]
+    summary: String,
+    #[serde(default)]
+    why_it_matters: String,
+    /// URLs of the articles it draws on
+    #[serde(default)]
+    sources: Vec<String>,
+    #[serde(default)]
+    reporting_varies: Vec<Angle>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Tier {
+    MustKnow,
+    ShouldKnow,
+    Signal,
+    Skip,
+}
+
+#[derive(Debug, Deserialize)]
+struct Verdicts {
+    stories: Vec<Verdict>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Angle {
+    pub source: String,
+    pub bias: String,
+    pub angle: String,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct SourceRef {
+    pub name: String,
+    pub url: String,
+    pub bias: String,
+    /// Who owns the source, as configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// Where the source reports from, as configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    /// ISO 639-1 code of the language the article is in, when not English
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Narrative {
+    pub headline: String,
+    pub summary: String,
+    pub why_it_matters: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+    pub sources: Vec<SourceRef>,
+    pub reporting_varies: Vec<Angle>,
+    /// Hash of its picture in `images` (see `thumbnails`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Signal {
+    pub headline: String,
+    pub source: SourceRef,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub topic: Option<String>,
+}
+
+/// `selections.json`
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Selections {
+    pub must_know: Vec<Narrative>,
+    pub should_know: Vec<Narrative>,
+    pub signals: BTreeMap<String, Vec<Signal>>,
+    pub regional_summary: BTreeMap<String, String>,
+}
+
+/// The prompt asking for verdicts on `clusters`
+fn stories_prompt(
+    prompts: &Prompts,
+    clusters: &[&Cluster],
+    previous: &[String],
+    detail: Detail,
+) -> String {
+    let previous_headlines = if previous.is_empty() {
+        "- (none)".to_string()
+    } else {
+        previous
+            .iter()
+            .map(|headline| format!("- {headline}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let mut stories = String::new();
+    for cluster in clusters {
+        stories.push_str(&format!("\n### {}\n", cluster.id));
+        for (source, article) in &cluster.articles {
+            let text = plain_text(article.content.as_deref().unwrap_or(&article.summary));
+            let text: String = text.chars().take(detail.article_chars()).collect();
+            stories.push_str(&format!(
+                "- {} ({}, {}): {}\n  {}\n  {}\n",
+                source.name,
+                source.bias,
+                source.perspective,
+                plain_text(&article.title),
+                article.url,
+                text
+            ));
+            if !article.keywords.is_empty() {
+                stories.push_str(&format!("  Keywords: {}\n", article.keywords.join(", ")));
+            }
+        }
+    }
+    prompts.stories(
+        &previous_headlines,
+        stories.trim(),
+        &REGIONS.join(", "),
+        &TOPICS.join(", "),
+        detail.story_summary(),
+    )
+}
+
+/// The prompt asking for regional summaries of `selections`
+fn summary_prompt(prompts: &Prompts, selections: &Selections, detail: Detail) -> String {
+    let mut digest = Vec::new();
+    for (tier, narratives) in [
+        ("must_know", &selections.must_know),
+        ("should_know", &selections.should_know),
+    ] {
+        for narrative in narratives {
+            let url = narrative.sources.first().map_or("", |s| s.url.as_str());
+            digest.push(format!(
+                "- [{tier}] {}: {} ({url})",
+                narrative.headline, narrative.summary
+            ));
+        }
+    }
+    for (region, signals) in &selections.signals {
+        for signal in signals {
+            digest.push(format!(
+                "- [signal, {region}] {} ({})",
+                signal.headline, signal.source.url
+            ));
+        }
+    }
+    prompts.summary(
+        &digest.join("\n"),
+        &REGIONS.join(", "),
+        detail.region_summary(),
+    )
+}
+
+/// The reply tokens allowed for verdicts on `stories` stories
+fn reply_tokens(stories: usize, detail: Detail) -> u32 {
+    (stories as u32)
+        .saturating_mul(detail.story_tokens())
+        .min(MAX_REPLY_TOKENS)
+}
+
+/// The reply tokens allowed for the regional summaries
+fn summary_tokens(detail: Detail) -> u32 {
+    REGIONS.len() as u32 * detail.region_tokens()
+}
+
+/// Send a prompt until its reply reads as `T`, reserving each attempt's
+/// cost from `budget` first. `None` if the budget can't cover a first try.
+pub async fn ask<T: serde::de::DeserializeOwned>(
+    model: &Model,
+    budget: &Budget,
+    system: &str,
+    prompt: &str,
+    max_tokens: u32,
+) -> Result<Option<(T, Usage)>, String> {
+    let estimate = model.estimate(system, prompt, max_tokens);
+    let mut usage = Usage::default();
+    let mut error = String::new();
+    for attempt in 0..READ_ATTEMPTS {
+        if !budget.reserve(&estimate) {
+            if attempt == 0 {
+                return Ok(None);
+            }
+            return Err(format!("{error} (no budget left to ask again)"));
+        }
+        let reply = model.complete(system, prompt, max_tokens).await;
+        budget.settle(
+            &estimate,
+            reply.as_ref().map(|r| r.usage).unwrap_or_default(),
+        );
+        let reply = reply?;
+        usage += reply.usage;
+        if reply.cut_off {
+            tracing::warn!("{} reply cut off at {} tokens", model.name(), max_tokens);
+        }
+        match llm::json_in(&reply.text) {
+            Ok(value) => return Ok(Some((value, usage))),
+            Err(e) => {
+                tracing::warn!("{}: {}", model.name(), e);
+                error = e;
+            }
+        }
+    }
+    Err(error)
+}
+
+/// `model`'s verdicts on `clusters`, a batch per request, leaving out
+/// batches the budget can't cover
+async fn verdicts(
+    model: &Arc<Model>,
+    budget: &Arc<Budget>,
+    prompts: &Prompts,
+    clusters: &[&Cluster<'_>],
+    previous: &[String],
+    detail: Detail,
+) -> Result<(Vec<Verdict>, Usage), String> {
+    let slots = Arc::new(Semaphore::new(CONCURRENT_REQUESTS));
+    let mut tasks = tokio::task::JoinSet::new();
+    for (batch, chunk) in clusters.chunks(STORIES_PER_REQUEST).enumerate() {
+        let prompt = stories_prompt(prompts, chunk, previous, detail);
+        let max_tokens = reply_tokens(chunk.len(), detail);
+        let (model, budget, slots, system) = (
+            model.clone(),
+            budget.clone(),
+            slots.clone(),
+            prompts.system.clone(),
+        );
+        tasks.spawn(async move {
+            let _slot = slots
+                .acquire()
+                .await
+                .expect("the semaphore is never closed");
+            let result = ask::<Verdicts>(&model, &budget, &system, &prompt, max_tokens).await;
+            (batch, result)
+        });
+    }
+    let mut batches = tasks.join_all().await;
+    batches.sort_by_key(|(batch, _)| *batch);
+
+    let mut usage = Usage::default();
+    let mut verdicts = Vec::new();
+    for (batch, result) in batches {
+        match result? {
+            Some((replied, used)) => {
+                usage += used;
+                verdicts.extend(replied.stories);
+            }
+            None => {
+                let first = batch * STORIES_PER_REQUEST;
+                let last = clusters.len().min(first + STORIES_PER_REQUEST);
+                tracing::warn!(
+                    "Over budget: {} not asked about stories {} to {}",
+                    model.name(),
+                    first + 1,
+                    last
+                );
+            }
+        }
+    }
+    Ok((verdicts, usage))
+}
+
+/// What curating `clusters` at `detail` may cost, if every reply runs to
+/// its cap and the main model reviews as many stories as it's budgeted to
+fn estimate(
+    models: &Models,
+    prompts: &Prompts,
+    clusters: &[&Cluster],
+    previous: &[String],
+    detail: Detail,
+) -> Usage {
+    let writer = models.writer();
+    let mut cost = Usage::default();
+    for chunk in clusters.chunks(STORIES_PER_REQUEST) {
+        let prompt = stories_prompt(prompts, chunk, previous, detail);
+        cost += writer.estimate(&prompts.system, &prompt, reply_tokens(chunk.len(), detail));
+    }
+    if models.cheap.is_some() {
+        let reviewed = &clusters[..clusters.len().min(REVIEW_STORIES)];
+        let prompt = stories_prompt(prompts, reviewed, previous, detail);
+        cost += models.main.estimate(
+            &prompts.system,
+            &prompt,
+            reply_tokens(reviewed.len(), detail),
+        );
+    }
+    let digest = "x".repeat(clusters.len() * SUMMARY_CHARS_PER_STORY);
+    let prompt = prompts.summary(&digest, &REGIONS.join(", "), detail.region_summary());
+    cost += writer.estimate(&prompts.system, &prompt, summary_tokens(detail));
+    cost
+}
+
+/// The most of `clusters` `budget` can cover: all of them in full, or else
+/// briefly, or else briefly for as many of the most widely reported as fit,
+/// which leaves out single-source stories, most of them signals, first
+pub fn plan(
+    models: &Models,
+    budget: &Budget,
+    prompts: &Prompts,
+    clusters: &[Cluster],
+    previous: &[String],
+) -> Result<Plan, String> {
+    let all = Plan {
+        detail: Detail::Full,
+        stories: clusters.len(),
+    };
+    let clusters: Vec<&Cluster> = clusters.iter().collect();
+    let fits = |plan: Plan| {
+        budget.fits(&estimate(
+            models,
+            prompts,
+            &clusters[..plan.stories],
+            previous,
+            plan.detail,
+        ))
+    };
+    if !budget.is_limited() || fits(all) {
+        return Ok(all);
+    }
+    let brief = |stories| Plan {
+        detail: Detail::Brief,
+        stories,
+    };
+    // The most stories that fit: more never cost less
+    let (mut fitting, mut too_many) = (0, clusters.len() + 1);
+    while too_many - fitting > 1 {
+        let middle = (fitting + too_many) / 2;
+        if fits(brief(middle)) {
+            fitting = middle;
+        } else {
+            too_many = middle;
+        }
+    }
+    if fitting == 0 {
+        return Err("The LLM budget can't cover curating a single story".into());
+    }
+    Ok(brief(fitting))
+}
+
+/// Retier `verdicts` on `clusters` by `rules`. Only stories the model wrote
+/// up can rise above signal, and skipped stories stay skipped.
+fn rank(rules: &Rules, clusters: &[Cluster], verdicts: &mut [Verdict], now: DateTime<Utc>) {
+    let by_id: HashMap<&str, &Cluster> = clusters.iter().map(|c| (c.id.as_str(), c)).collect();
+    let mut ranked = Vec::new();
+    for (index, verdict) in verdicts.iter().enumerate() {
+        let Some(cluster) = by_id.get(verdict.story.as_str()) else {
+            continue;
+        };
+        if verdict.tier == Tier::Skip {
+            continue;
+        }
+        let story = scoring::Story {
+            importance: verdict.importance,
+            sources: cluster
+                .articles
+                .iter()
+                .map(|(source, _)| source.id.as_str())
+                .collect::<HashSet<_>>()
+                .len(),
+            heaviest_source: cluster
+                .articles
+                .iter()
+                .map(|(source, _)| source.weight)
+                .fold(0.0, f64::max),
+            newest: cluster
+                .articles
+                .iter()
+                .filter_map(|(_, article)| article.published.as_deref())
+                .filter_map(|date| DateTime::parse_from_rfc3339(date).ok())
+                .map(|date| date.with_timezone(&Utc))
+                .max(),
+            popularity: cluster
+                .articles
+                .iter()
+                .map(|(_, article)| article.points.unwrap_or(0) + article.comments.unwrap_or(0))
+                .max()
+                .unwrap_or(0),
+            topic: verdict.topic.as_deref(),
+        };
+        let score = rules.score(&story, now);
+        let ceiling = if verdict.summary.is_empty() {
+            Tier::Signal
+        } else {
+            Tier::MustKnow
+        };
+        tracing::debug!(
+            "{} scores {:.2}: {}",
+            verdict.story,
+            score,
+            verdict.headline
+        );
+        ranked.push((index, (score, ceiling)));
+    }
+    let scores: Vec<(f64, Tier)> = ranked.iter().map(|(_, scored)| *scored).collect();
+    for ((index, _), tier) in ranked.iter().zip(rules.tiers(&scores)) {
+        verdicts[*index].tier = tier;
+    }
+}
+
+/// Tier and write up `clusters` at `detail`, then summarize each region,
+/// all within `budget`, and retier the stories by `rules`, if given.
+/// Returns the selections and the tokens they took.
+pub async fn curate(
+    models: &Models,
+    budget: Arc<Budget>,
+    rules: Option<&Rules>,
+    prompts: &Prompts,
+    clusters: &[Cluster<'_>],
+    previous: &[String],
+    detail: Detail,
+) -> Result<(Selections, Usage), String> {
+    let all: Vec<&Cluster> = clusters.iter().collect();
+    let (main, writer) = (&models.main, models.writer());
+    let (mut verdicts, mut usage) =
+        verdicts(writer, &budget, prompts, &all, previous, detail).await?;
+
+    if models.cheap.is_some() {
+        let must_know: HashSet<String> = verdicts
+            .iter()
+            .filter(|v| v.tier == Tier::MustKnow)
+            .map(|v| v.story.clone())
+            .collect();
+        let picked: Vec<&Cluster> = all
+            .iter()
+            .copied()
+            .filter(|c| must_know.contains(&c.id))
+            .collect();
+        if !picked.is_empty() {
+            tracing::info!(
+                "{} put {} stories in must_know; asking {}",
+                writer.name(),
+                picked.len(),
+                main.name()
+            );
+            let (reviewed, used) =
+                self::verdicts(main, &budget, prompts, &picked, previous, detail).await?;
+            usage += used;
+            // The main model's take replaces the cheap one's, even if it
+            // tiers the story lower; stories it wasn't asked about keep theirs
+            let replaced: HashSet<String> = reviewed
+                .iter()
+                .map(|v| v.story.clone())
+                .filter(|story| must_know.contains(story))
+                .collect();
+            verdicts.retain(|v| !replaced.contains(&v.story));
+            verdicts.extend(reviewed.into_iter().filter(|v| replaced.contains(&v.story)));
+        }
+    }
+    if let Some(rules) = rules {
+        rank(rules, clusters, &mut verdicts, Utc::now());
+    }
+    let mut selections = select(clusters, verdicts);
+
+    let summaries = match ask::<BTreeMap<String, String>>(
+        writer,
+        &budget,
+        &prompts.system,
+        &summary_prompt(prompts, &selections, detail),
+        summary_tokens(detail),
+    )
+    .await?
+    {
+        Some((summaries, used)) => {
+            usage += used;
+            summaries
+        }
+        None => {
+            tracing::warn!("Over budget: no regional summaries");
+            BTreeMap::new()
+        }
+    };
+    selections.regional_summary = REGIONS
+        .iter()
+        .map(|region| {
+            let text = summaries.get(*region).cloned().unwrap_or_default();
+            (region.to_string(), text)
+        })
+        .collect();
+    Ok((selections, usage))
+}
+
+/// Selections from the editor's verdicts, with sources named as configured
+fn select(clusters: &[Cluster], verdicts: Vec<Verdict>) -> Selections {
+    let by_id: HashMap<&str, &Cluster> = clusters.iter().map(|c| (c.id.as_str(), c)).collect();
+    let mut selections = Selections {
+        signals: REGIONS
+            .iter()
+            .map(|r| (r.to_string(), Vec::new()))
+            .collect(),
+        ..Default::default()
+    };
+    for verdict in verdicts {
+        let Some(cluster) = by_id.get(verdict.story.as_str()) else {
+            tracing::warn!("Reply names an unknown story {}", verdict.story);
+            continue;
+        };
+        let reference = |(source, article): &(&Source, FetchedArticle)| SourceRef {
+            name: source.name.clone(),
+            url: article.url.clone(),
+            bias: source.bias.clone(),
+            owner: source.owner.clone(),
+            region: source.region.clone(),
+            language: article
+                .language
+                .filter(|code| *code != "en")
+                .map(str::to_string),
+        };
+        let mut sources: Vec<SourceRef> = cluster
+            .articles
+            .iter()
+            .filter(|(_, article)| verdict.sources.contains(&article.url))
+            .map(reference)
+            .collect();
+        // Sources the reply got wrong are the story's own
+        if sources.is_empty() {
+            sources = cluster.articles.iter().map(reference).collect();
+        }
+        let topic = verdict
+            .topic
+            .filter(|t| TOPICS.contains(&t.as_str()))
+            .or_else(|| Some("other".into()));
+        let narrative = |reporting_varies| Narrative {
+            headline: verdict.headline.clone(),
+            summary: verdict.summary.clone(),
+            why_it_matters: verdict.why_it_matters.clone(),
+            topic: topic.clone(),
+            sources: sources.clone(),
+            reporting_varies,
+            image: None,
+        };
+        // Explainers and commentary rarely break news on their own
+        let only_videos = sources.iter().all(|s| youtube::video_id(&s.url).is_some());
+        let tier = match verdict.tier {
+            Tier::MustKnow if only_videos => Tier::ShouldKnow,
+            tier => tier,
+        };
+        match tier {
+            Tier::MustKnow => selections
+                .must_know
+                .push(narrative(verdict.reporting_varies)),
+            Tier::ShouldKnow => selections.should_know.push(narrative(Vec::new())),
+            Tier::Signal => {
+                let region = verdict
+                    .region
+                    .filter(|r| REGIONS.contains(&r.as_str()))
+                    .or_else(|| (topic.as_deref() == Some("tech_ai")).then(|| "tech".into()));
+                let Some(signals) = region.and_then(|r| selections.signals.get_mut(&r)) else {
+                    tracing::warn!("Signal without a region: {}", verdict.headline);
+                    continue;
+                };
+                signals.push(Signal {
+                    headline: verdict.headline,
+                    source: sources.swap_remove(0),
+                    topic,
+                });
+            }
+            Tier::Skip => {}
+        }
+    }
+    selections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(id: &str, bias: &str) -> Source {
+        crate::sources::parse(&format!(
+            "[[source]]\nid = \"{id}\"\nname = \"{id} News\"\nurl = \"https://{id}.example/feed\"\n\
+             bias = \"{bias}\"\nperspective = \"x\"\n"
+        ))
+        .unwrap()
+        .remove(0)
+    }
+
+    fn article(url: &str, story: Option<&str>) -> FetchedArticle {
+        FetchedArticle {
+            title: format!("Title of {url}"),
+            url: url.into(),
+            published: None,
+            summary: String::new(),
+            content: None,
+            story: story.map(str::to_string),
+            language: None,
+            image: None,
+            points: None,
+            comments: None,
+            keywords: Vec::new(),
+        }
+    }
+
+    mod clusters {
+        use super::*;
+
+        #[test]
+        fn groups_stories_ahead_of_single_articles() {
+            let dir = std::env::temp_dir().join(format!("curate-clusters-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(
+                dir.join("left.json"),
+                r#"[{"title": "A", "url": "https://left.example/a", "published": null, "summary": ""},
+                    {"title": "B", "url": "https://left.example/b", "published": null, "summary": "", "story": "s1"}]"#,
+            )
+            .unwrap();
+            std::fs::write(
+                dir.join("right.json"),
+                r#"[{"title": "B", "url": "https://right.example/b", "published": null, "summary": "", "story": "s1"}]"#,
+            )
+            .unwrap();
+            let sources = [
+                source("left", "left"),
+                source("right", "right"),
+                source("gone", "center"),
+            ];
+            let clusters = clusters(&dir, &sources).unwrap();
+            std::fs::remove_dir_all(&dir).unwrap();
+
+            let urls: Vec<(&str, Vec<&str>)> = clusters
+                .iter()
+                .map(|c| {
+                    (
+                        c.id.as_str(),
+                        c.articles.iter().map(|(_, a)| a.url.as_str()).collect(),
+                    )
+                })
+                .collect();
+            assert_eq!(
+                urls,
+                [
+                    (
+                        "c1",
+                        vec!["https://left.example/b", "https://right.example/b"]
+                    ),
+                    ("c2", vec!["https://left.example/a"]),
+                ]
+            );
+        }
+    }
+
+    mod select {
+        use super::*;
+
+        #[test]
+        fn tiers_stories_with_their_configured_sources() {
+            let (left, right) = (source("left", "left"), source("right", "right"));
+            let mut clusters = [
+                Cluster {
+                    id: "c1".into(),
+                    articles: vec![
+                        (&left, article("https://left.example/b", Some("s1"))),
+                        (&right, article("https://right.example/b", Some("s1"))),
+                    ],
+                },
+                Cluster {
+                    id: "c2".into(),
+                    articles: vec![(&left, article("https://left.example/a", None))],
+                },
+                Cluster {
+                    id: "c3".into(),
+                    articles: vec![(&right, article("https://right.example/c", None))],
+                },
+            ];
+            clusters[0].articles[1].1.language = Some("fr");
+            let verdicts: Verdicts = serde_json::from_str(
+                r#"{"stories": [
+                    {"story": "c1", "tier": "must_know", "region": "europe", "topic": "geopolitics",
+                     "headline": "Truce holds", "summary": "S.", "why_it_matters": "W.",
+                     "sources": ["https://right.example/b"],
+                     "reporting_varies": [{"source": "right News", "bias": "right", "angle": "Doubtful"}]},
+                    {"story": "c2", "tier": "signal", "topic": "tech_ai", "headline": "Chip rules",
+                     "sources": ["https://wrong.example"]},
+                    {"story": "c3", "tier": "skip", "headline": "Celebrity news"},
+                    {"story": "c9", "tier": "must_know", "headline": "Made up"}
+                ]}"#,
+            )
+            .unwrap();
+            let selections = select(&clusters, verdicts.stories);
+
+            assert_eq!(selections.must_know.len(), 1);
+            let truce = &selections.must_know[0];
+            assert_eq!(
+                truce.sources,
+                [SourceRef {
+                    name: "right News".into(),
+                    url: "https://right.example/b".into(),
+                    bias: "right".into(),
+                    owner: None,
+                    region: None,
+                    language: Some("fr".into()),
+                }]
+            );
+            assert_eq!(truce.reporting_varies.len(), 1);
+            assert!(selections.should_know.is_empty());
+            assert_eq!(selections.signals["tech"].len(), 1);
+            assert_eq!(
+                selections.signals["tech"][0].source.url,
+                "https://left.example/a"
+            );
+            assert_eq!(
+                selections.signals["tech"][0].topic.as_deref(),
+                Some("tech_ai")
+            );
+            assert!(selections.signals["europe"].is_empty());
+        }
+
+        #[test]
+        fn keeps_stories_only_videos_carry_out_of_must_know() {
+            let (channel, paper) = (source("channel", "center"), source("paper", "center"));
+            let video = "https://www.youtube.com/watch?v=dQw4w9WgXcQ";
+            let clusters = [
+                Cluster {
+                    id: "c1".into(),
+                    articles: vec![(&channel, article(video, None))],
+                },
+                Cluster {
+                    id: "c2".into(),
+                    articles: vec![
+                        (&channel, article(video, None)),
+                        (&paper, article("https://paper.example/a", None)),
+                    ],
+                },
+            ];
+            let verdicts: Verdicts = serde_json::from_str(
+                r#"{"stories": [
+                    {"story": "c1", "tier": "must_know", "headline": "Explained"},
+                    {"story": "c2", "tier": "must_know", "headline": "Reported"}
+                ]}"#,
+            )
+            .unwrap();
+            let selections = select(&clusters, verdicts.stories);
+            assert_eq!(selections.must_know.len(), 1);
+            assert_eq!(selections.must_know[0].headline, "Reported");
+            assert_eq!(selections.should_know[0].headline, "Explained");
+        }
+    }
+
+    mod plan {
+        use super::*;
+        use crate::budget::Limits;
+
+        #[test]
+        fn degrades_to_fit_the_budget() {
+            let left = source("left", "left");
+            let clusters: Vec<Cluster> = (0..40)
+                .map(|i| {
+                    let mut single = article(&format!("https://left.example/{i}"), None);
+                    single.summary = "word ".repeat(200);
+                    Cluster {
+                        id: format!("c{}", i + 1),
+                        articles: vec![(&left, single)],
+                    }
+                })
+                .collect();
+            let models = Models {
+                main: Arc::new(Model::priced(3.0, 15.0)),
+                cheap: Some(Arc::new(Model::priced(0.5, 1.5))),
+            };
+            let prompts = Prompts::bundled().unwrap();
+            let all: Vec<&Cluster> = clusters.iter().collect();
+            let cost = |stories: usize, detail| {
+                estimate(&models, &prompts, &all[..stories], &[], detail).cost_usd
+            };
+            let plan = |usd: f64| {
+                let budget = Budget::new(Limits {
+                    budget_usd: Some(usd),
+                    budget_tokens: None,
+                });
+                super::plan(&models, &budget, &prompts, &clusters, &[])
+            };
+            let brief = |stories| Plan {
+                detail: Detail::Brief,
+                stories,
+            };
+
+            assert!(cost(40, Detail::Brief) < cost(40, Detail::Full));
+            assert_eq!(
+                plan(cost(40, Detail::Full)),
+                Ok(Plan {
+                    detail: Detail::Full,
+                    stories: 40
+                })
+            );
+            assert_eq!(plan(cost(40, Detail::Brief)), Ok(brief(40)));
+            assert_eq!(plan(cost(25, Detail::Brief)), Ok(brief(25)));
+            assert!(plan(cost(1, Detail::Brief) / 2.0).is_err());
+        }
+    }
+
+    mod rank {
+        use super::*;
+
+        #[test]
+        fn retiers_by_score_but_keeps_skips_and_unwritten_stories() {
+            let (left, right) = (source("left", "left"), source("right", "right"));
+            let mut fresh = article("https://left.example/b", Some("s1"));
+            fresh.published = Some("2026-01-05T10:00:00+00:00".into());
+            let clusters = [
+                Cluster {
+                    id: "c1".into(),
+                    articles: vec![
+                        (&left, fresh),
+                        (&right, article("https://right.example/b", Some("s1"))),
+                    ],
+                },
+                Cluster {
+                    id: "c2".into(),
+                    articles: vec![(&left, article("https://left.example/a", None))],
+                },
+                Cluster {
+                    id: "c3".into(),
+                    articles: vec![(&right, article("https://right.example/c", None))],
+                },
+            ];
+            let mut verdicts: Verdicts = serde_json::from_str(
+                r#"{"stories": [
+                    {"story": "c1", "tier": "signal", "importance": 9, "headline": "Truce holds",
+                     "summary": "S."},
+                    {"story": "c2", "tier": "must_know", "importance": 3, "headline": "Chip rules",
+                     "summary": "S."},
+                    {"story": "c3", "tier": "skip", "importance": 10, "headline": "Celebrity news"}
+                ]}"#,
+            )
+            .unwrap();
+            let rules = Rules::parse(
+                "[score]\nrecency_hours = 24\n[tiers]\nmust_know = 6\nshould_know = 2",
+            )
+            .unwrap();
+            let now = DateTime::parse_from_rfc3339("2026-01-05T16:00:00+00:00")
+                .unwrap()
+                .with_timezone(&Utc);
+            rank(&rules, &clusters, &mut verdicts.stories, now);
+            let tiers: Vec<Tier> = verdicts.stories.iter().map(|v| v.tier).collect();
+            assert_eq!(tiers, [Tier::MustKnow, Tier::ShouldKnow, Tier::Skip]);
+        }
+    }
+
+    mod stories_prompt {
+        use super::*;
+
+        #[test]
+        fn lists_previous_headlines_and_each_story() {
+            let left = source("left", "left");
+            let mut long = article("https://left.example/a", None);
+            long.summary = format!("<p>{}</p>", "word ".repeat(500));
+            let cluster = Cluster {
+                id: "c1".into(),
+                articles: vec![(&left, long)],
+            };
+            let prompts = Prompts::bundled().unwrap();
+            let prompt =
+                stories_prompt(&prompts, &[&cluster], &["Truce holds".into()], Detail::Full);
+            assert!(prompt.contains("- Truce holds\n"));
+            assert!(
+                prompt.contains("### c1\n- left News (left, x): Title of https://left.example/a\n")
+            );
+            assert!(!prompt.contains("<p>"));
+            assert!(prompt.contains("2-3 sentences"));
+            assert!(prompt.len() < 2000 + Detail::Full.article_chars());
+        }
+    }
+}
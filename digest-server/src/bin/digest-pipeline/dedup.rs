@@ -0,0 +1,433 @@
+//! One copy of each story. Wire stories reach several outlets' feeds, and
+//! outlets list a story under its AMP and tracked links as well as its own,
+//! so articles are matched on their canonical URL (tracking parameters,
+//! AMP variants, and link shorteners undone) or a near-identical title, and
+//! only the copy from the most trusted source is kept.
+
+use crate::feeds::Article;
+use crate::hosts::Scheduler;
+use crate::pages::text_length;
+use reqwest::{Client, Url};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+/// Titles sharing this much of their words (Jaccard) are the same story
+const TITLE_SIMILARITY: f64 = 0.8;
+
+/// Titles with fewer words than this only match on URL
+const MIN_TITLE_WORDS: usize = 4;
+
+/// Query parameters that say where a click came from, not what it's for
+const TRACKING_PARAMS: &[&str] = &[
+    "at_campaign",
+    "at_medium",
+    "cmpid",
+    "dclid",
+    "fbclid",
+    "gclid",
+    "igshid",
+    "mc_cid",
+    "mc_eid",
+    "mkt_tok",
+    "ocid",
+    "smid",
+    "taid",
+    "wt.mc_id",
+    "yclid",
+];
+
+/// Hosts whose links only redirect to the article
+const REDIRECTORS: &[&str] = &[
+    "bit.ly",
+    "buff.ly",
+    "dlvr.it",
+    "feedproxy.google.com",
+    "feeds.feedburner.com",
+    "ow.ly",
+    "t.co",
+    "trib.al",
+];
+
+/// Words titles are compared without
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "as", "at", "by", "for", "from", "in", "is", "of", "on", "the", "to", "with",
+];
+
+/// `url` without tracking parameters or a fragment
+pub fn clean_url(url: &str) -> String {
+    let Ok(mut parsed) = Url::parse(url) else {
+        return url.to_string();
+    };
+    let query: Vec<(String, String)> = parsed
+        .query_pairs()
+        .filter(|(key, _)| !is_tracking(key))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    if query.is_empty() {
+        parsed.set_query(None);
+    } else {
+        parsed.query_pairs_mut().clear().extend_pairs(query);
+    }
+    parsed.set_fragment(None);
+    parsed.into()
+}
+
+fn is_tracking(key: &str) -> bool {
+    let key = key.to_ascii_lowercase();
+    key.starts_with("utm_") || TRACKING_PARAMS.contains(&key.as_str())
+}
+
+/// What two links to one article have in common: the cleaned URL without
+/// its scheme, `www.`, AMP markers, or trailing slash
+pub fn canonical(url: &str) -> String {
+    let cleaned = clean_url(url);
+    let Ok(parsed) = Url::parse(&cleaned) else {
+        return cleaned;
+    };
+    let host = parsed.host_str().unwrap_or_default();
+    let host = host
+        .strip_prefix("www.")
+        .or_else(|| host.strip_prefix("amp."))
+        .unwrap_or(host);
+    let path = parsed.path();
+    let path = path
+        .strip_suffix("/amp")
+        .or_else(|| path.strip_suffix("/amp/"))
+        .or_else(|| path.strip_suffix(".amp"))
+        .or_else(|| path.strip_suffix(".amp.html"))
+        .unwrap_or(path)
+        .replace("/amp/", "/");
+    let query: Vec<String> = parsed
+        .query_pairs()
+        .filter(|(key, value)| !(key == "amp" || (key == "outputType" && value == "amp")))
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect();
+    let mut key = format!("{host}{}", path.trim_end_matches('/'));
+    if !query.is_empty() {
+        key = format!("{key}?{}", query.join("&"));
+    }
+    key
+}
+
+/// Follow links through shorteners and feed proxies to the article, and
+/// drop tracking parameters from every link
+pub async fn resolve_urls(
+    client: &Client,
+    scheduler: &Arc<Scheduler>,
+    fetched: &mut [(&str, Vec<Article>)],
+) {
+    let mut tasks = tokio::task::JoinSet::new();
+    for (source_index, (_, articles)) in fetched.iter_mut().enumerate() {
+        for (index, article) in articles.iter_mut().enumerate() {
+            article.url = clean_url(&article.url);
+            if !redirects(&article.url) {
+                continue;
+            }
+            let (client, scheduler, url) = (client.clone(), scheduler.clone(), article.url.clone());
+            tasks.spawn(async move {
+                let _turn = scheduler.wait(&url, None).await;
+                // Only where the redirects end is wanted, so a HEAD will do
+                let resolved = match client.head(&url).send().await {
+                    Ok(response) => Some(response.url().to_string()),
+                    Err(e) => {
+                        tracing::debug!("{}: {}", url, e);
+                        None
+                    }
+                };
+                (source_index, index, resolved)
+            });
+        }
+    }
+    for (source_index, index, resolved) in tasks.join_all().await {
+        if let Some(url) = resolved {
+            fetched[source_index].1[index].url = clean_url(&url);
+        }
+    }
+}
+
+fn redirects(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|url| {
+        url.host_str()
+            .is_some_and(|host| REDIRECTORS.contains(&host.to_ascii_lowercase().as_str()))
+    })
+}
+
+/// A title's words, lowercased, without punctuation, stopwords, or a
+/// trailing " - Outlet" or " | Outlet"
+fn title_words(title: &str) -> HashSet<String> {
+    let title = match title
+        .rsplit_once(" - ")
+        .or_else(|| title.rsplit_once(" | "))
+    {
+        Some((headline, outlet)) if outlet.split_whitespace().count() <= 3 => headline,
+        _ => title,
+    };
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .filter(|word| !STOPWORDS.contains(&word.as_str()))
+        .collect()
+}
+
+fn similarity(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+/// Drop every article that repeats one kept before it, trying articles from
+/// heavier sources first, then those with more to say. Returns how many were
+/// dropped.
+pub fn dedup(fetched: &mut [(&str, Vec<Article>)], weights: &HashMap<&str, f64>) -> usize {
+    let mut order: Vec<(usize, usize)> = fetched
+        .iter()
+        .enumerate()
+        .flat_map(|(source_index, (_, articles))| {
+            (0..articles.len()).map(move |index| (source_index, index))
+        })
+        .collect();
+    let weight = |source_index: usize| weights.get(fetched[source_index].0).copied().unwrap_or(1.0);
+    let length = |(source_index, index): (usize, usize)| {
+        text_length(&fetched[source_index].1[index].summary)
+    };
+    // Stable, so ties keep feed order
+    order.sort_by(|&a, &b| {
+        weight(b.0)
+            .total_cmp(&weight(a.0))
+            .then(length(b).cmp(&length(a)))
+    });
+
+    // The copy kept of each link and title
+    let mut urls: HashMap<String, (usize, usize)> = HashMap::new();
+    let mut titles: Vec<(HashSet<String>, (usize, usize))> = Vec::new();
+    let mut dropped: HashSet<(usize, usize)> = HashSet::new();
+    // Popularity on aggregators, moved to the copy kept
+    let mut carried: Vec<((usize, usize), (usize, usize))> = Vec::new();
+    for (source_index, index) in order {
+        let article = &fetched[source_index].1[index];
+        let words = title_words(&article.title);
+        let comparable = words.len() >= MIN_TITLE_WORDS;
+        let url = canonical(&article.url);
+        let kept = urls.get(&url).copied().or_else(|| {
+            titles
+                .iter()
+                .find(|(t, _)| comparable && similarity(t, &words) >= TITLE_SIMILARITY)
+                .map(|(_, kept)| *kept)
+        });
+        if let Some(kept) = kept {
+            tracing::debug!("[{}] duplicate: {}", fetched[source_index].0, article.title);
+            dropped.insert((source_index, index));
+            urls.entry(url).or_insert(kept);
+            if article.points.is_some() || article.comments.is_some() {
+                carried.push((kept, (source_index, index)));
+            }
+        } else {
+            urls.insert(url, (source_index, index));
+            if comparable {
+                titles.push((words, (source_index, index)));
+            }
+        }
+    }
+    for ((source_index, index), (from_source, from)) in carried {
+        let copy = &fetched[from_source].1[from];
+        let (points, comments) = (copy.points, copy.comments);
+        let kept = &mut fetched[source_index].1[index];
+        kept.points = kept.points.max(points);
+        kept.comments = kept.comments.max(comments);
+    }
+
+    for (source_index, (_, articles)) in fetched.iter_mut().enumerate() {
+        let mut index = 0;
+        articles.retain(|_| {
+            index += 1;
+            !dropped.contains(&(source_index, index - 1))
+        });
+    }
+    dropped.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article(title: &str, url: &str, summary: &str) -> Article {
+        Article {
+            title: title.into(),
+            url: url.into(),
+            published: None,
+            summary: summary.into(),
+            content: None,
+            story: None,
+            image: None,
+            points: None,
+            comments: None,
+            keywords: Vec::new(),
+        }
+    }
+
+    mod clean_url {
+        use super::*;
+
+        #[test]
+        fn drops_tracking_parameters_and_fragments() {
+            assert_eq!(
+                clean_url(
+                    "https://example.com/story?id=7&utm_source=rss&UTM_Medium=x&fbclid=abc#top"
+                ),
+                "https://example.com/story?id=7"
+            );
+            assert_eq!(
+                clean_url("https://example.com/story?utm_campaign=feed"),
+                "https://example.com/story"
+            );
+            assert_eq!(clean_url("not a url"), "not a url");
+        }
+    }
+
+    mod canonical {
+        use super::*;
+
+        #[test]
+        fn matches_amp_and_www_variants() {
+            let story = canonical("https://www.example.com/world/story-1/");
+            for url in [
+                "http://example.com/world/story-1",
+                "https://amp.example.com/world/story-1",
+                "https://www.example.com/world/story-1/amp",
+                "https://www.example.com/amp/world/story-1",
+                "https://example.com/world/story-1.amp",
+                "https://example.com/world/story-1?outputType=amp",
+                "https://example.com/world/story-1?utm_source=rss#comments",
+            ] {
+                assert_eq!(canonical(url), story, "{url}");
+            }
+            assert_ne!(canonical("https://example.com/world/story-2"), story);
+            assert_ne!(
+                canonical("https://example.com/story?id=1"),
+                canonical("https://example.com/story?id=2")
+            );
+        }
+    }
+
+    mod redirects {
+        use super::*;
+
+        #[test]
+        fn knows_shorteners_and_feed_proxies() {
+            assert!(redirects("https://feedproxy.google.com/~r/example/~3/abc/"));
+            assert!(redirects("https://T.co/xyz"));
+            assert!(!redirects("https://example.com/story"));
+        }
+    }
+
+    mod title_words {
+        use super::*;
+
+        #[test]
+        fn drops_the_outlet_and_stopwords() {
+            let words = title_words("Fed holds rates steady as inflation cools - Reuters");
+            let expected: HashSet<String> =
+                ["fed", "holds", "rates", "steady", "inflation", "cools"]
+                    .map(String::from)
+                    .into();
+            assert_eq!(words, expected);
+            assert!(
+                title_words("Markets - a look back - at a year of rate rises").contains("rises")
+            );
+        }
+    }
+
+    mod dedup {
+        use super::*;
+
+        #[test]
+        fn keeps_the_copy_from_the_heaviest_source() {
+            let mut fetched = vec![
+                (
+                    "local",
+                    vec![
+                        article(
+                            "Fed holds rates steady as inflation cools",
+                            "https://local.example/fed",
+                            "",
+                        ),
+                        article(
+                            "Council approves new tram line",
+                            "https://local.example/tram",
+                            "",
+                        ),
+                    ],
+                ),
+                (
+                    "wire",
+                    vec![
+                        article(
+                            "Fed holds rates steady as inflation cools - Wire",
+                            "https://wire.example/fed",
+                            "",
+                        ),
+                        article("Tram vote", "https://www.local.example/tram/amp", ""),
+                    ],
+                ),
+            ];
+            let weights = HashMap::from([("wire", 2.0)]);
+            assert_eq!(dedup(&mut fetched, &weights), 2);
+            assert!(fetched[0].1.is_empty());
+            assert_eq!(fetched[1].1.len(), 2);
+        }
+
+        #[test]
+        fn prefers_the_longer_summary_and_leaves_short_titles_alone() {
+            let mut fetched = vec![
+                (
+                    "a",
+                    vec![article(
+                        "Fed holds rates steady today",
+                        "https://a.example/1",
+                        "Short.",
+                    )],
+                ),
+                (
+                    "b",
+                    vec![
+                        article(
+                            "Fed holds rates steady today",
+                            "https://b.example/1",
+                            "<p>A longer summary.</p>",
+                        ),
+                        article("Live updates", "https://b.example/2", ""),
+                    ],
+                ),
+                (
+                    "c",
+                    vec![article("Live updates", "https://c.example/2", "")],
+                ),
+            ];
+            assert_eq!(dedup(&mut fetched, &HashMap::new()), 1);
+            assert!(fetched[0].1.is_empty());
+            assert_eq!(fetched[1].1.len(), 2);
+            assert_eq!(fetched[2].1.len(), 1);
+        }
+
+        #[test]
+        fn keeps_the_popularity_of_the_copies_dropped() {
+            let mut on_hacker_news = article("Kernel released", "https://example.com/k", "");
+            on_hacker_news.points = Some(412);
+            on_hacker_news.comments = Some(230);
+            let mut fetched = vec![
+                ("hacker_news", vec![on_hacker_news]),
+                (
+                    "example",
+                    vec![article("Kernel released", "https://example.com/k/", "")],
+                ),
+            ];
+            let weights = HashMap::from([("example", 2.0)]);
+            assert_eq!(dedup(&mut fetched, &weights), 1);
+            let kept = &fetched[1].1[0];
+            assert_eq!((kept.points, kept.comments), (Some(412), Some(230)));
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Feeds for sources whose URL is a website rather than a feed. The feed is
+//! looked for where the page's `<link rel="alternate">` tags point, or, when
+//! it has none, at the paths feeds usually live at. What's found is kept in
+//! `discovered_feeds` and fetched directly on later runs, until it stops
+//! working and is looked for again.
+
+use regex::Regex;
+use reqwest::Url;
+use scraper::{Html, Selector};
+use std::sync::LazyLock;
+
+/// Where sites without feed links usually keep their feed, from the root
+const COMMON_PATHS: &[&str] = &[
+    "/feed",
+    "/rss",
+    "/feed.xml",
+    "/rss.xml",
+    "/atom.xml",
+    "/index.xml",
+];
+
+/// Media types of feeds the fetcher reads
+const FEED_TYPES: &[&str] = &[
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/rdf+xml",
+    "application/feed+json",
+];
+
+static ALTERNATE: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse("link[rel~=alternate][href]").expect("valid selector"));
+
+static HTML_START: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?is)^\s*(<!--.*?-->\s*)*<(!doctype\s+html|html)\b").expect("valid regex")
+});
+
+/// Whether `body` is a web page rather than a feed
+pub fn is_html(body: &[u8]) -> bool {
+    let start = String::from_utf8_lossy(&body[..body.len().min(1024)]);
+    HTML_START.is_match(start.trim_start_matches('\u{feff}'))
+}
+
+/// URLs to try for the feed of the page at `url`: its feed links in the
+/// page's order, or the common paths when it has none
+pub fn candidates(html: &str, url: &str) -> Vec<String> {
+    let Ok(base) = Url::parse(url) else {
+        return Vec::new();
+    };
+    let page = Html::parse_document(html);
+    let mut found: Vec<String> = Vec::new();
+    for link in page.select(&ALTERNATE) {
+        let is_feed = link.value().attr("type").is_some_and(|media_type| {
+            let media_type = media_type.trim().to_ascii_lowercase();
+            FEED_TYPES.iter().any(|t| media_type.starts_with(t))
+        });
+        let href = link.value().attr("href").map(str::trim).unwrap_or_default();
+        if let (true, Ok(feed)) = (is_feed, base.join(href))
+            && matches!(feed.scheme(), "http" | "https")
+            && feed != base
+            && !found.contains(&feed.to_string())
+        {
+            found.push(feed.to_string());
+        }
+    }
+    if found.is_empty() {
+        found = COMMON_PATHS
+            .iter()
+            .filter_map(|path| base.join(path).ok())
+            .filter(|feed| *feed != base)
+            .map(|feed| feed.to_string())
+            .collect();
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod is_html {
+        use super::*;
+
+        #[test]
+        fn tells_pages_from_feeds() {
+            assert!(is_html(b"\xef\xbb\xbf<!DOCTYPE html>\n<html lang=\"en\">"));
+            assert!(is_html(b"<!-- generated -->\n<html><head>"));
+            assert!(!is_html(b"<?xml version=\"1.0\"?>\n<rss version=\"2.0\">"));
+            assert!(!is_html(b"<feed xmlns=\"http://www.w3.org/2005/Atom\">"));
+        }
+    }
+
+    mod candidates {
+        use super::*;
+
+        #[test]
+        fn follows_feed_links_in_order() {
+            let html = r#"<html><head>
+                <link rel="stylesheet" href="/style.css">
+                <link rel="alternate" type="text/html" hreflang="fr" href="/fr/">
+                <link rel="alternate" type="application/atom+xml" href="/atom.xml">
+                <link rel="alternate" type="application/rss+xml; charset=utf-8" href="feed/">
+                <link rel="alternate" type="application/rss+xml" href="https://example.com/blog/feed/">
+            </head></html>"#;
+            assert_eq!(
+                candidates(html, "https://example.com/blog/"),
+                [
+                    "https://example.com/atom.xml",
+                    "https://example.com/blog/feed/"
+                ]
+            );
+        }
+
+        #[test]
+        fn tries_common_paths_without_links() {
+            let found = candidates("<html><body>Hi</body></html>", "https://example.com/news");
+            assert_eq!(found.len(), COMMON_PATHS.len());
+            assert_eq!(found[0], "https://example.com/feed");
+            assert!(candidates("<html></html>", "not a url").is_empty());
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! The article in an article page, Readability-style: paragraphs are scored
+//! and credited to the blocks holding them, the best block (and siblings
+//! that look like more of it) is taken as the article, and its text is put
+//! back together a paragraph per line, without navigation, sharing widgets,
+//! related links, and the like.
+
+use ego_tree::{NodeId, NodeRef};
+use regex::Regex;
+use scraper::{ElementRef, Html, Node};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+/// Paragraphs shorter than this, in characters, don't count towards a block
+const MIN_PARAGRAPH_CHARS: usize = 25;
+
+/// Less article text than this, in characters, and the page has none
+const MIN_ARTICLE_CHARS: usize = 250;
+
+/// Elements that are never the article
+const SKIPPED: &[&str] = &[
+    "aside", "button", "dialog", "footer", "form", "iframe", "nav", "noscript", "object", "script",
+    "select", "style", "svg", "template", "textarea",
+];
+
+/// Elements that start a new paragraph
+const BLOCKS: &[&str] = &[
+    "address",
+    "article",
+    "blockquote",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+/// Class names and ids of page furniture
+static UNLIKELY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)\b(ad|ads|advert\w*|banner|breadcrumbs?|combx|comments?|community|cookie\w*|disqus|extra|footer|header|menu|modal|newsletter|outbrain|pager|pagination|popup|promo\w*|related|remark|replies|rss|share|sharing|shoutbox|sidebar|skyscraper|social|sponsor\w*|subscribe|taboola|tags|tools|widget)\b",
+    )
+    .expect("valid regex")
+});
+
+/// Class names and ids of the article itself
+static LIKELY: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?i)\b(article|body|content|entry|hentry|main|page|post|story|text)\b")
+        .expect("valid regex")
+});
+
+/// The article text of `html`, a paragraph per line, or `None` when no part
+/// of the page reads like one
+pub fn article_text(html: &str) -> Option<String> {
+    let document = Html::parse_document(html);
+    let root = document.root_element();
+
+    // Credit each paragraph to its parent in full and its grandparent in half
+    let mut scores: HashMap<NodeId, f64> = HashMap::new();
+    for element in root.descendent_elements() {
+        if !matches!(element.value().name(), "p" | "pre" | "td") || skipped(*element) {
+            continue;
+        }
+        let text = text(*element);
+        let length = text.chars().count();
+        if length < MIN_PARAGRAPH_CHARS {
+            continue;
+        }
+        let score = 1.0 + text.matches(',').count() as f64 + (length / 100).min(3) as f64;
+        let parent = element.parent().and_then(ElementRef::wrap);
+        let grandparent = parent.and_then(|p| p.parent()).and_then(ElementRef::wrap);
+        for (ancestor, share) in [(parent, 1.0), (grandparent, 0.5)] {
+            if let Some(ancestor) = ancestor {
+                *scores
+                    .entry(ancestor.id())
+                    .or_insert_with(|| class_weight(ancestor)) += score * share;
+            }
+        }
+    }
+
+    // Long runs of links are menus, not articles
+    let scored: HashMap<NodeId, f64> = scores
+        .into_iter()
+        .filter_map(|(id, score)| {
+            let node = document.tree.get(id)?;
+            Some((id, score * (1.0 - link_density(node))))
+        })
+        .collect();
+    let (&top_id, &top_score) = scored.iter().max_by(|a, b| a.1.total_cmp(b.1))?;
+    let top = document.tree.get(top_id)?;
+
+    // Siblings scoring near the top block, or reading like prose, carry on
+    // the article (a lead paragraph outside the body's wrapper, say)
+    let threshold = (top_score * 0.2).max(10.0);
+    let parts: Vec<NodeRef<Node>> = match top.parent() {
+        Some(parent) => parent
+            .children()
+            .filter(|sibling| {
+                if sibling.id() == top_id {
+                    return true;
+                }
+                if scored.get(&sibling.id()).is_some_and(|s| *s >= threshold) {
+                    return true;
+                }
+                let is_paragraph = sibling
+                    .value()
+                    .as_element()
+                    .is_some_and(|e| e.name() == "p");
+                if !is_paragraph || skipped(*sibling) {
+                    return false;
+                }
+                let text = text(*sibling);
+                let density = link_density(*sibling);
+                match text.chars().count() {
+                    0 => false,
+                    length if length > 80 => density < 0.25,
+                    _ => density == 0.0 && text.contains(". "),
+                }
+            })
+            .collect(),
+        None => vec![top],
+    };
+
+    let mut paragraphs = Vec::new();
+    let mut current = String::new();
+    for part in parts {
+        collect_paragraphs(part, &mut current, &mut paragraphs);
+    }
+    flush(&mut current, &mut paragraphs);
+    let article = paragraphs.join("\n");
+    (article.chars().count() >= MIN_ARTICLE_CHARS).then_some(article)
+}
+
+/// Whether `node` is furniture, by its tag or its class and id
+fn skipped(node: NodeRef<Node>) -> bool {
+    let Some(element) = node.value().as_element() else {
+        return false;
+    };
+    if SKIPPED.contains(&element.name()) {
+        return true;
+    }
+    if matches!(element.name(), "html" | "body" | "article" | "main") {
+        return false;
+    }
+    let names = format!(
+        "{} {}",
+        element.attr("class").unwrap_or_default(),
+        element.id().unwrap_or_default()
+    );
+    UNLIKELY.is_match(&names) && !LIKELY.is_match(&names)
+}
+
+/// A block's head start from its tag, class names, and id
+fn class_weight(element: ElementRef) -> f64 {
+    let names = format!(
+        "{} {}",
+        element.attr("class").unwrap_or_default(),
+        element.attr("id").unwrap_or_default()
+    );
+    let mut weight = match element.value().name() {
+        "article" | "main" => 10.0,
+        "div" | "section" => 5.0,
+        "blockquote" | "pre" | "td" => 3.0,
+        "form" | "ol" | "ul" | "dl" | "li" | "th" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => -5.0,
+        _ => 0.0,
+    };
+    if LIKELY.is_match(&names) {
+        weight += 25.0;
+    }
+    if UNLIKELY.is_match(&names) {
+        weight -= 25.0;
+    }
+    weight
+}
+
+/// The text of `node`, furniture left out and whitespace collapsed
+fn text(node: NodeRef<Node>) -> String {
+    let mut raw = String::new();
+    push_text(node, &mut raw);
+    raw.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn push_text(node: NodeRef<Node>, out: &mut String) {
+    match node.value() {
+        Node::Text(text) => out.push_str(text),
+        Node::Element(_) if skipped(node) => {}
+        Node::Element(element) => {
+            for child in node.children() {
+                push_text(child, out);
+            }
+            if BLOCKS.contains(&element.name()) {
+                out.push(' ');
+            }
+        }
+        _ => {}
+    }
+}
+
+/// How much of `node`'s text is in links, from 0 to 1
+fn link_density(node: NodeRef<Node>) -> f64 {
+    let length = text(node).chars().count();
+    if length == 0 {
+        return 0.0;
+    }
+    let linked: usize = node
+        .descendants()
+        .filter(|d| d.value().as_element().is_some_and(|e| e.name() == "a"))
+        .map(|a| text(a).chars().count())
+        .sum();
+    linked as f64 / length as f64
+}
+
+/// `node`'s text, broken into paragraphs at block elements and `<br>`s
+fn collect_paragraphs(node: NodeRef<Node>, current: &mut String, paragraphs: &mut Vec<String>) {
+    match node.value() {
+        Node::Text(text) => current.push_str(text),
+        Node::Element(_) if skipped(node) => {}
+        Node::Element(element) if element.name() == "br" => flush(current, paragraphs),
+        Node::Element(element) => {
+            let is_block = BLOCKS.contains(&element.name());
+            if is_block {
+                flush(current, paragraphs);
+                // Lists of links within the article are related stories
+                if matches!(element.name(), "ul" | "ol") && link_density(node) > 0.5 {
+                    return;
+                }
+            }
+            for child in node.children() {
+                collect_paragraphs(child, current, paragraphs);
+            }
+            if is_block {
+                flush(current, paragraphs);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// End the paragraph being collected, if it has any text
+fn flush(current: &mut String, paragraphs: &mut Vec<String>) {
+    let paragraph = current.split_whitespace().collect::<Vec<_>>().join(" ");
+    if !paragraph.is_empty() {
+        paragraphs.push(paragraph);
+    }
+    current.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STORY: &str = "The council voted on Tuesday to expand the city's tram network, \
+        adding three lines, twelve stops, and a depot by the river.";
+
+    fn page(body: &str) -> String {
+        format!(
+            "<html><head><title>T</title><style>p {{}}</style></head><body>{body}</body></html>"
+        )
+    }
+
+    mod article_text {
+        use super::*;
+
+        #[test]
+        fn keeps_the_article_and_drops_the_furniture() {
+            let html = page(&format!(
+                r#"<nav><ul><li><a href="/">Home</a></li><li><a href="/world">World, news, and more</a></li></ul></nav>
+                <div class="share-tools"><p>Share this story on social media, by email, or in print.</p></div>
+                <div id="story-body" class="article-body">
+                  <h1>Trams are coming</h1>
+                  <p>{STORY}</p>
+                  <p>Work starts in <a href="/spring">spring</a>, the mayor said, and should be finished, weather permitting, by 2029.</p>
+                  <script>var tracking = "a, b, c, d, e, f";</script>
+                  <ul class="related"><li><a href="/a">Buses, again</a></li><li><a href="/b">Bikes, more of them</a></li></ul>
+                  <p>Line one<br>runs north.<br><br>Line two runs south, past the stadium and the old market hall.</p>
+                </div>
+                <footer><p>Copyright, all rights reserved, no part may be reproduced.</p></footer>"#
+            ));
+            assert_eq!(
+                article_text(&html).unwrap(),
+                format!(
+                    "Trams are coming\n{STORY}\n\
+                     Work starts in spring, the mayor said, and should be finished, weather permitting, by 2029.\n\
+                     Line one\nruns north.\n\
+                     Line two runs south, past the stadium and the old market hall."
+                )
+            );
+        }
+
+        #[test]
+        fn takes_sibling_paragraphs_along() {
+            let html = page(&format!(
+                r#"<div class="wrapper">
+                  <p class="lead">Trams are coming back to the city. The vote was close.</p>
+                  <div class="content"><p>{STORY}</p><p>{STORY}</p></div>
+                  <div class="sidebar"><p>Most read, most shared, most commented.</p></div>
+                </div>"#
+            ));
+            let text = article_text(&html).unwrap();
+            assert!(text.starts_with("Trams are coming back"));
+            assert!(!text.contains("Most read"));
+        }
+
+        #[test]
+        fn is_none_without_an_article() {
+            let html =
+                page(r#"<ul><li><a href="/a">One</a></li><li><a href="/b">Two</a></li></ul>"#);
+            assert_eq!(article_text(&html), None);
+            assert_eq!(article_text(&page(&format!("<p>{STORY}</p>"))), None);
+        }
+    }
+}
@@ -0,0 +1,338 @@
+//! Feed parsing: RSS 2.0, RSS 1.0 (RDF), and Atom items normalized into one
+//! [`Article`] shape.
+//!
+//! feed-rs reads the XML and parses dates leniently (RFC 3339, RFC 2822 with
+//! the usual mistakes, `dc:date`). On top of that, an item's date falls back
+//! from published to updated, placeholder dates count as undated, and dates
+//! ahead of the fetch are pulled back to it, so a feed with a mislabelled
+//! time zone can't keep items "new" for hours.
+
+use crate::thumbnails;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use serde::{Serialize, Serializer};
+
+/// Longest a summary is kept, in characters
+pub const MAX_SUMMARY_CHARS: usize = 500;
+
+/// One feed item, as `run.py` reads it from the fetched files
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Article {
+    pub title: String,
+    pub url: String,
+    /// When the item was published, or last updated if that's all the feed says
+    #[serde(serialize_with = "rfc3339")]
+    pub published: Option<DateTime<Utc>>,
+    pub summary: String,
+    /// The article page's text, when the summary was only a teaser
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// The story it's one report of, shared by the other reports in `fetched/`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub story: Option<String>,
+    /// The picture the feed lists for it (see `thumbnails`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    /// Its points on the aggregator it was read from (see `aggregators`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub points: Option<u64>,
+    /// Comments on it there
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comments: Option<u64>,
+    /// The publisher's keywords for it (see `sitemaps`)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+}
+
+/// "2025-01-15T10:30:00+00:00", as `run.py` writes dates
+fn rfc3339<S: Serializer>(date: &Option<DateTime<Utc>>, s: S) -> Result<S::Ok, S::Error> {
+    match date {
+        Some(date) => s.serialize_str(&date.to_rfc3339()),
+        None => s.serialize_none(),
+    }
+}
+
+/// Parse a feed fetched from `url` (relative links resolve against it).
+/// Items without a title or link are skipped.
+pub fn parse(body: &[u8], url: &str, now: DateTime<Utc>) -> Result<Vec<Article>, String> {
+    let feed = feed_rs::parser::Builder::new()
+        .base_uri(Some(url))
+        .build()
+        .parse(body)
+        .map_err(|e| format!("Feed parse error: {e}"))?;
+    let articles = feed
+        .entries
+        .into_iter()
+        .filter_map(|entry| {
+            let title = entry
+                .title
+                .map(|t| t.content.split_whitespace().collect::<Vec<_>>().join(" "))
+                .filter(|t| !t.is_empty())?;
+            // Atom entries can link to themselves, comments, and enclosures
+            // too; the article is the alternate link
+            let url = entry
+                .links
+                .iter()
+                .find(|link| link.rel.as_deref().is_none_or(|rel| rel == "alternate"))
+                .or(entry.links.first())
+                .map(|link| link.href.trim().to_string())
+                .filter(|href| !href.is_empty())?;
+            // A video's description is all YouTube's feeds give
+            let description = entry
+                .media
+                .iter()
+                .find_map(|media| media.description.as_ref())
+                .map(|d| d.content.clone());
+            let summary = entry
+                .summary
+                .map(|s| s.content)
+                .or_else(|| entry.content.and_then(|c| c.body))
+                .filter(|s| !s.trim().is_empty())
+                .or(description)
+                .unwrap_or_default();
+            let listed: Vec<(String, Option<u32>)> = entry
+                .media
+                .iter()
+                .flat_map(|media| {
+                    let thumbnails = media
+                        .thumbnails
+                        .iter()
+                        .map(|t| (t.image.uri.clone(), t.image.width));
+                    let images = media
+                        .content
+                        .iter()
+                        .filter(|c| {
+                            c.content_type
+                                .as_ref()
+                                .is_some_and(|t| t.to_string().starts_with("image/"))
+                        })
+                        .filter_map(|c| Some((c.url.as_ref()?.to_string(), c.width)));
+                    thumbnails.chain(images).collect::<Vec<_>>()
+                })
+                .filter(|(url, _)| url.starts_with("http://") || url.starts_with("https://"))
+                .collect();
+            Some(Article {
+                title,
+                url,
+                published: plausible(entry.published.or(entry.updated), now),
+                summary: summary.trim().chars().take(MAX_SUMMARY_CHARS).collect(),
+                content: None,
+                story: None,
+                image: thumbnails::pick(&listed),
+                points: None,
+                comments: None,
+                keywords: Vec::new(),
+            })
+        })
+        .collect();
+    Ok(articles)
+}
+
+/// `date`, unless it's a placeholder from before web feeds existed; dates
+/// after `now` become `now`
+pub fn plausible(date: Option<DateTime<Utc>>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let earliest = Utc.with_ymd_and_hms(1995, 1, 1, 0, 0, 0).single()?;
+    date.filter(|date| *date >= earliest)
+        .map(|date| date.min(now))
+}
+
+/// A SQLite "YYYY-MM-DD HH:MM:SS" UTC timestamp
+pub fn parse_sqlite_time(timestamp: &str) -> Option<DateTime<Utc>> {
+    let timestamp = timestamp.get(..19)?;
+    NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .map(|date| date.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, 15, 12, 0, 0).unwrap()
+    }
+
+    fn date(s: &str) -> Option<DateTime<Utc>> {
+        Some(DateTime::parse_from_rfc3339(s).unwrap().to_utc())
+    }
+
+    mod parse {
+        use super::*;
+
+        #[test]
+        fn reads_rss_items() {
+            let feed = br#"<?xml version="1.0"?>
+                <rss version="2.0"><channel><title>Feed</title>
+                  <item><title> Story
+                      one </title><link>https://example.com/1</link>
+                    <description>First</description>
+                    <pubDate>Tue, 15 Jan 2025 10:30:00 GMT</pubDate></item>
+                  <item><title>Eastern</title><link>https://example.com/2</link>
+                    <pubDate>Tue, 15 Jan 2025 10:30:00 EST</pubDate></item>
+                  <item><title>Dublin Core</title><link>/3</link>
+                    <dc:date xmlns:dc="http://purl.org/dc/elements/1.1/">2025-01-15T10:30:00+01:00</dc:date></item>
+                  <item><title>No link</title></item>
+                </channel></rss>"#;
+            let articles = parse(feed, "https://example.com/rss", now()).unwrap();
+            assert_eq!(articles.len(), 3);
+            assert_eq!(articles[0].title, "Story one");
+            assert_eq!(articles[0].url, "https://example.com/1");
+            assert_eq!(articles[0].summary, "First");
+            assert_eq!(articles[0].published, date("2025-01-15T10:30:00Z"));
+            assert_eq!(articles[1].published, date("2025-01-15T15:30:00Z"));
+            assert_eq!(articles[2].url, "https://example.com/3");
+            assert_eq!(articles[2].published, date("2025-01-15T09:30:00Z"));
+        }
+
+        #[test]
+        fn reads_rdf_items() {
+            let feed = br#"<?xml version="1.0" encoding="UTF-8"?>
+                <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"
+                         xmlns="http://purl.org/rss/1.0/"
+                         xmlns:dc="http://purl.org/dc/elements/1.1/">
+                  <channel rdf:about="https://example.com/">
+                    <title>Feed</title><link>https://example.com/</link>
+                    <items><rdf:Seq><rdf:li rdf:resource="https://example.com/a"/></rdf:Seq></items>
+                  </channel>
+                  <item rdf:about="https://example.com/a">
+                    <title>RDF story</title>
+                    <link>https://example.com/a</link>
+                    <description>Summary</description>
+                    <dc:date>2025-01-15T10:30:00Z</dc:date>
+                  </item>
+                </rdf:RDF>"#;
+            let articles = parse(feed, "https://example.com/rdf", now()).unwrap();
+            assert_eq!(
+                articles,
+                [Article {
+                    title: "RDF story".into(),
+                    url: "https://example.com/a".into(),
+                    published: date("2025-01-15T10:30:00Z"),
+                    summary: "Summary".into(),
+                    content: None,
+                    story: None,
+                    image: None,
+                    points: None,
+                    comments: None,
+                    keywords: Vec::new(),
+                }]
+            );
+        }
+
+        #[test]
+        fn reads_atom_entries() {
+            let feed = br#"<?xml version="1.0"?>
+                <feed xmlns="http://www.w3.org/2005/Atom"><title>Feed</title><id>x</id>
+                  <updated>2025-01-16T00:00:00Z</updated>
+                  <entry><title type="html">Atom &amp;amp; story</title><id>a</id>
+                    <link rel="self" href="https://example.com/a.atom"/>
+                    <link rel="alternate" href="https://example.com/a"/>
+                    <updated>2025-01-15T10:30:00Z</updated>
+                    <content type="html">&lt;p&gt;Body&lt;/p&gt;</content></entry>
+                </feed>"#;
+            let articles = parse(feed, "https://example.com/atom", now()).unwrap();
+            assert_eq!(articles[0].title, "Atom &amp; story");
+            assert_eq!(articles[0].url, "https://example.com/a");
+            assert_eq!(articles[0].published, date("2025-01-15T10:30:00Z"));
+            assert_eq!(articles[0].summary, "<p>Body</p>");
+        }
+
+        #[test]
+        fn picks_the_picture_a_feed_lists() {
+            let feed = br#"<?xml version="1.0"?>
+                <rss version="2.0" xmlns:media="http://search.yahoo.com/mrss/"><channel><title>Feed</title>
+                  <item><title>Sized</title><link>https://example.com/1</link>
+                    <media:thumbnail url="https://example.com/1-small.jpg" width="240"/>
+                    <media:content url="https://example.com/1-large.jpg" medium="image" type="image/jpeg" width="2048"/>
+                    <media:content url="https://example.com/1-medium.jpg" type="image/jpeg" width="800"/></item>
+                  <item><title>Enclosed</title><link>https://example.com/2</link>
+                    <enclosure url="https://example.com/2.mp3" type="audio/mpeg" length="1"/></item>
+                  <item><title>Bare</title><link>https://example.com/3</link></item>
+                </channel></rss>"#;
+            let articles = parse(feed, "https://example.com/rss", now()).unwrap();
+            assert_eq!(
+                articles[0].image.as_deref(),
+                Some("https://example.com/1-medium.jpg")
+            );
+            assert_eq!(articles[1].image, None);
+            assert_eq!(articles[2].image, None);
+        }
+
+        #[test]
+        fn reads_youtube_videos() {
+            let feed = br#"<?xml version="1.0" encoding="UTF-8"?>
+                <feed xmlns:yt="http://www.youtube.com/xml/schemas/2015"
+                      xmlns:media="http://search.yahoo.com/mrss/" xmlns="http://www.w3.org/2005/Atom">
+                  <title>Channel</title><id>yt:channel:UC1</id>
+                  <entry><id>yt:video:dQw4w9WgXcQ</id><yt:videoId>dQw4w9WgXcQ</yt:videoId>
+                    <title>Chips, explained</title>
+                    <link rel="alternate" href="https://www.youtube.com/watch?v=dQw4w9WgXcQ"/>
+                    <published>2025-01-15T10:30:00+00:00</published>
+                    <media:group>
+                      <media:title>Chips, explained</media:title>
+                      <media:content url="https://www.youtube.com/v/dQw4w9WgXcQ?version=3"
+                          type="application/x-shockwave-flash" width="640" height="390"/>
+                      <media:thumbnail url="https://i1.ytimg.com/vi/dQw4w9WgXcQ/hqdefault.jpg" width="480" height="360"/>
+                      <media:description>What the new export rules mean.</media:description>
+                    </media:group></entry>
+                </feed>"#;
+            let articles = parse(feed, "https://www.youtube.com/feeds/videos.xml", now()).unwrap();
+            assert_eq!(
+                articles[0].url,
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ"
+            );
+            assert_eq!(articles[0].summary, "What the new export rules mean.");
+            assert_eq!(
+                articles[0].image.as_deref(),
+                Some("https://i1.ytimg.com/vi/dQw4w9WgXcQ/hqdefault.jpg")
+            );
+        }
+
+        #[test]
+        fn rejects_what_is_not_a_feed() {
+            assert!(
+                parse(
+                    b"<html><body>Moved</body></html>",
+                    "https://example.com",
+                    now()
+                )
+                .is_err()
+            );
+        }
+    }
+
+    mod plausible {
+        use super::*;
+
+        #[test]
+        fn drops_placeholders_and_pulls_back_future_dates() {
+            assert_eq!(plausible(date("1970-01-01T00:00:00Z"), now()), None);
+            assert_eq!(plausible(date("2026-10-15T17:00:00Z"), now()), Some(now()));
+            assert_eq!(
+                plausible(date("2026-10-15T09:00:00Z"), now()),
+                date("2026-10-15T09:00:00Z")
+            );
+        }
+    }
+
+    mod serialize {
+        use super::*;
+
+        #[test]
+        fn writes_dates_as_run_py_does() {
+            let article = Article {
+                title: "Title".into(),
+                url: "https://example.com".into(),
+                published: date("2025-01-15T10:30:00Z"),
+                summary: String::new(),
+                content: None,
+                story: None,
+                image: None,
+                points: None,
+                comments: None,
+                keywords: Vec::new(),
+            };
+            let json = serde_json::to_value(&article).unwrap();
+            assert_eq!(json["published"], "2025-01-15T10:30:00+00:00");
+        }
+    }
+}
@@ -0,0 +1,467 @@
+//! Fetching every feed at once, a few at a time and one per host, with
+//! retries for flaky ones
+
+use crate::aggregators;
+use crate::breaker::Breakers;
+use crate::discover;
+use crate::feeds::{self, Article};
+use crate::hosts::Scheduler;
+use crate::newsletters;
+use crate::sitemaps;
+use crate::social;
+use crate::sources::Source;
+use crate::youtube;
+use reqwest::{Client, StatusCode, header};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How a feed fetch went
+pub struct FetchResult {
+    pub source_id: String,
+    pub articles: Vec<Article>,
+    pub error: Option<String>,
+    /// Wall-clock time including retries, but not time queued behind other
+    /// fetches
+    pub fetch_ms: i64,
+    /// The feed answered 304: nothing new since the validators we sent
+    pub not_modified: bool,
+    /// Validators of the feed as fetched, for the next conditional request
+    pub validators: Option<Validators>,
+    /// The feed fetched in place of the source's URL, when that's a website
+    pub feed_url: Option<String>,
+}
+
+/// A feed's `ETag` and `Last-Modified`, sent back as `If-None-Match` and
+/// `If-Modified-Since`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    fn from_headers(headers: &header::HeaderMap) -> Option<Self> {
+        let value = |name| {
+            headers
+                .get(name)
+                .and_then(|v: &header::HeaderValue| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let validators = Self {
+            etag: value(header::ETAG),
+            last_modified: value(header::LAST_MODIFIED),
+        };
+        (validators != Self::default()).then_some(validators)
+    }
+}
+
+/// Attempts per feed and the backoff between them
+#[derive(Clone, Copy)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    /// Doubled after each failed attempt
+    pub delay: Duration,
+}
+
+impl RetryPolicy {
+    /// `RSS_MAX_RETRIES` (default 3) and `RSS_RETRY_DELAY` seconds (default 2)
+    pub fn from_env() -> Self {
+        let var = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        };
+        Self {
+            attempts: var("RSS_MAX_RETRIES", 3).max(1) as u32,
+            delay: Duration::from_secs(var("RSS_RETRY_DELAY", 2)),
+        }
+    }
+}
+
+/// Fetch every source, in the sources' order. Sources with `validators` are
+/// fetched conditionally, and those with a `discovered` feed from it.
+pub async fn fetch_all(
+    client: &Client,
+    sources: &[Source],
+    validators: &HashMap<String, Validators>,
+    discovered: &HashMap<String, String>,
+    scheduler: &Arc<Scheduler>,
+    breakers: &Arc<Breakers>,
+    retry: RetryPolicy,
+) -> Vec<FetchResult> {
+    let mut tasks = tokio::task::JoinSet::new();
+    for (index, source) in sources.iter().cloned().enumerate() {
+        let (client, scheduler, breakers) = (client.clone(), scheduler.clone(), breakers.clone());
+        let sent = validators.get(&source.id).cloned();
+        let discovered = discovered.get(&source.id).cloned();
+        tasks.spawn(async move {
+            let mut result = FetchResult {
+                source_id: source.id.clone(),
+                articles: Vec::new(),
+                error: None,
+                fetch_ms: 0,
+                not_modified: false,
+                validators: None,
+                feed_url: None,
+            };
+            let (fetched, feed_url, elapsed) = fetch_feed(
+                &client,
+                &scheduler,
+                &breakers,
+                &source,
+                discovered,
+                sent.as_ref(),
+                retry,
+            )
+            .await;
+            result.feed_url = feed_url;
+            match fetched {
+                Ok(Fetched::Articles(articles, validators)) => {
+                    result.articles = articles;
+                    result.validators = validators;
+                }
+                Ok(Fetched::NotModified) => {
+                    result.not_modified = true;
+                    result.validators = sent;
+                }
+                Ok(Fetched::Page(_)) => {
+                    let e = format!("No feed found at {}", source.url);
+                    tracing::warn!("[{}] {}", source.id, e);
+                    result.error = Some(e);
+                }
+                Err(e) => {
+                    tracing::warn!("[{}] {}", source.id, e);
+                    result.error = Some(e);
+                }
+            }
+            result.fetch_ms = elapsed.as_millis() as i64;
+            (index, result)
+        });
+    }
+    let mut results: Vec<(usize, FetchResult)> = tasks.join_all().await;
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// A fetched feed
+enum Fetched {
+    Articles(Vec<Article>, Option<Validators>),
+    NotModified,
+    /// A web page rather than a feed
+    Page(Vec<u8>),
+}
+
+/// Fetch a source's feed, and how long that took apart from waiting for the
+/// scheduler. When the source's URL is a website, its feed is looked for on
+/// the page, or fetched from `discovered` where it was found before, and
+/// comes back with its URL. A found feed that stops working is looked for
+/// again.
+async fn fetch_feed(
+    client: &Client,
+    scheduler: &Scheduler,
+    breakers: &Breakers,
+    source: &Source,
+    discovered: Option<String>,
+    validators: Option<&Validators>,
+    retry: RetryPolicy,
+) -> (Result<Fetched, String>, Option<String>, Duration) {
+    if newsletters::is_mailbox(&source.url) {
+        return (
+            fetch_mailbox(scheduler, breakers, source).await,
+            None,
+            Duration::ZERO,
+        );
+    }
+    let mut elapsed = Duration::ZERO;
+    let mut validators = validators;
+    if let Some(feed_url) = discovered {
+        let (fetched, took) = fetch_source(
+            client, scheduler, breakers, source, &feed_url, validators, retry,
+        )
+        .await;
+        elapsed += took;
+        if !matches!(fetched, Ok(Fetched::Page(_)) | Err(_)) {
+            return (fetched, Some(feed_url), elapsed);
+        }
+        tracing::info!(
+            "[{}] {} stopped working; looking for the feed again",
+            source.id,
+            feed_url
+        );
+        // They were the feed's
+        validators = None;
+    }
+    // A Mastodon account or hashtag is read from the instance's listing of
+    // its posts, once the instance answers for it
+    if let Some(lookup) = social::mastodon_lookup(&source.url) {
+        let (answer, took) = ask(client, scheduler, breakers, source, &lookup).await;
+        elapsed += took;
+        let listing = answer.and_then(|(body, _)| social::mastodon_listing(&lookup, &body));
+        if let Some(listing) = listing {
+            tracing::info!("[{}] Found its posts at {}", source.id, listing);
+            let (fetched, took) =
+                fetch_source(client, scheduler, breakers, source, &listing, None, retry).await;
+            elapsed += took;
+            let found = fetched.is_ok().then_some(listing);
+            return (fetched, found, elapsed);
+        }
+    }
+    // A YouTube channel or playlist is read from its feed, and Hacker News,
+    // a subreddit, or Bluesky from its listing
+    let url = youtube::feed_url(&source.url)
+        .or_else(|| aggregators::listing_url(&source.url))
+        .or_else(|| social::listing_url(&source.url))
+        .unwrap_or_else(|| source.url.clone());
+    let (fetched, took) =
+        fetch_source(client, scheduler, breakers, source, &url, validators, retry).await;
+    elapsed += took;
+    let Ok(Fetched::Page(body)) = fetched else {
+        return (fetched, None, elapsed);
+    };
+    let page = String::from_utf8_lossy(&body).into_owned();
+    for candidate in discover::candidates(&page, &source.url) {
+        let (found, took) = probe(client, scheduler, breakers, source, &candidate).await;
+        elapsed += took;
+        if let Some((articles, validators)) = found {
+            tracing::info!("[{}] Found its feed at {}", source.id, candidate);
+            return (
+                Ok(Fetched::Articles(articles, validators)),
+                Some(candidate),
+                elapsed,
+            );
+        }
+    }
+    (Ok(Fetched::Page(body)), None, elapsed)
+}
+
+/// The newsletters in a source's mailbox, one connection at a time per
+/// mail server
+async fn fetch_mailbox(
+    scheduler: &Scheduler,
+    breakers: &Breakers,
+    source: &Source,
+) -> Result<Fetched, String> {
+    let _turn = scheduler.wait(&source.url, source.delay_secs).await;
+    if let Err(reason) = breakers.check(&source.id) {
+        return Err(format!("Skipped: {reason}"));
+    }
+    let sent = Instant::now();
+    let fetched = newsletters::fetch(&source.url, chrono::Utc::now()).await;
+    let error = fetched.as_ref().err().map(String::as_str);
+    breakers.record(&source.id, sent.elapsed(), error);
+    fetched.map(|articles| Fetched::Articles(articles, None))
+}
+
+/// The articles and validators of the feed at `url`, if it is one, and how
+/// long asking took. Only tried once, and a miss isn't the source failing.
+async fn probe(
+    client: &Client,
+    scheduler: &Scheduler,
+    breakers: &Breakers,
+    source: &Source,
+    url: &str,
+) -> (Option<(Vec<Article>, Option<Validators>)>, Duration) {
+    let (answer, elapsed) = ask(client, scheduler, breakers, source, url).await;
+    let feed = answer.and_then(|(body, validators)| {
+        feeds::parse(&body, url, chrono::Utc::now())
+            .ok()
+            .map(|articles| (articles, validators))
+    });
+    (feed, elapsed)
+}
+
+/// The body and validators of `url`, if it answers, and how long asking
+/// took. Only tried once, and a miss isn't the source failing.
+async fn ask(
+    client: &Client,
+    scheduler: &Scheduler,
+    breakers: &Breakers,
+    source: &Source,
+    url: &str,
+) -> (Option<(Vec<u8>, Option<Validators>)>, Duration) {
+    if breakers.check(&source.id).is_err() {
+        return (None, Duration::ZERO);
+    }
+    let turn = scheduler.wait(url, source.delay_secs).await;
+    let sent = Instant::now();
+    let downloaded = download(client, url, None).await;
+    drop(turn);
+    let elapsed = sent.elapsed();
+    breakers.spend(&source.id, elapsed);
+    (downloaded.ok().flatten(), elapsed)
+}
+
+/// Fetch and parse the feed at `url` for `source`, and how long that took
+/// apart from waiting for the scheduler. Network errors and error statuses
+/// are retried with exponential backoff until the source's breaker opens; a
+/// feed that doesn't parse isn't.
+async fn fetch_source(
+    client: &Client,
+    scheduler: &Scheduler,
+    breakers: &Breakers,
+    source: &Source,
+    url: &str,
+    validators: Option<&Validators>,
+    retry: RetryPolicy,
+) -> (Result<Fetched, String>, Duration) {
+    let started = Instant::now();
+    let mut queued = Duration::ZERO;
+    let mut last_error = String::new();
+    for attempt in 0..retry.attempts {
+        if attempt > 0 {
+            tokio::time::sleep(retry.delay * 2u32.pow(attempt - 1)).await;
+        }
+        let waiting = Instant::now();
+        let turn = scheduler.wait(url, source.delay_secs).await;
+        queued += waiting.elapsed();
+        if let Err(reason) = breakers.check(&source.id) {
+            let error = match attempt {
+                0 => format!("Skipped: {reason}"),
+                _ => format!("Failed after {attempt} attempt(s): {last_error}; {reason}"),
+            };
+            return (Err(error), started.elapsed() - queued);
+        }
+        let sent = Instant::now();
+        let downloaded = download(client, url, validators).await;
+        drop(turn);
+        let error = downloaded.as_ref().err().map(String::as_str);
+        breakers.record(&source.id, sent.elapsed(), error);
+        let fetched = match downloaded {
+            Ok(Some((body, validators))) => match parse(&body, url) {
+                Ok(articles) => Ok(Fetched::Articles(articles, validators)),
+                Err(_) if discover::is_html(&body) => Ok(Fetched::Page(body)),
+                Err(e) => Err(e),
+            },
+            Ok(None) => Ok(Fetched::NotModified),
+            Err(e) => {
+                last_error = e;
+                continue;
+            }
+        };
+        return (fetched, started.elapsed() - queued);
+    }
+    let error = format!("Failed after {} retries: {last_error}", retry.attempts);
+    (Err(error), started.elapsed() - queued)
+}
+
+/// The articles in a feed, a news sitemap, or an aggregator's or social
+/// network's listing, fetched from `url`
+fn parse(body: &[u8], url: &str) -> Result<Vec<Article>, String> {
+    let now = chrono::Utc::now();
+    if aggregators::is_listing(url) {
+        aggregators::parse(body, url, now)
+    } else if social::is_listing(url) {
+        social::parse(body, url, now)
+    } else if sitemaps::is_sitemap(body) {
+        sitemaps::parse(body, url, now)
+    } else {
+        feeds::parse(body, url, now)
+    }
+}
+
+/// The body and validators of `url`, or `None` when it answers 304 Not
+/// Modified to the validators given
+async fn download(
+    client: &Client,
+    url: &str,
+    validators: Option<&Validators>,
+) -> Result<Option<(Vec<u8>, Option<Validators>)>, String> {
+    let mut request = client.get(url);
+    if let Some(validators) = validators {
+        if let Some(etag) = &validators.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    let response = request.send().await.map_err(describe)?;
+    let status = response.status();
+    if status == StatusCode::NOT_MODIFIED && validators.is_some() {
+        return Ok(None);
+    }
+    if !status.is_success() {
+        return Err(format!("HTTP {status}"));
+    }
+    let validators = Validators::from_headers(response.headers());
+    let body = response.bytes().await.map_err(describe)?;
+    Ok(Some((body.to_vec(), validators)))
+}
+
+/// A request error with its underlying cause (DNS, TLS, timeout), which
+/// reqwest's own message leaves out
+fn describe(e: reqwest::Error) -> String {
+    if e.is_timeout() {
+        return "Timed out".into();
+    }
+    let mut message = e.to_string();
+    let mut source = std::error::Error::source(&e);
+    while let Some(cause) = source {
+        message = cause.to_string();
+        source = cause.source();
+    }
+    message
+}
+
+/// What a feed currently holds, so a change means new items appeared: the
+/// newest publish date when items are dated, else a hash of their links
+pub fn fingerprint(articles: &[Article]) -> Option<String> {
+    if let Some(newest) = articles.iter().filter_map(|a| a.published).max() {
+        return Some(newest.to_rfc3339());
+    }
+    if articles.is_empty() {
+        return None;
+    }
+    let mut urls: Vec<&str> = articles.iter().map(|a| a.url.as_str()).collect();
+    urls.sort_unstable();
+    let digest = ring::digest::digest(&ring::digest::SHA256, urls.join("\n").as_bytes());
+    let hex: String = digest.as_ref().iter().map(|b| format!("{b:02x}")).collect();
+    Some(hex[..16].to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article(url: &str, published: Option<&str>) -> Article {
+        Article {
+            title: "Title".into(),
+            url: url.into(),
+            published: published.map(|date| date.parse().unwrap()),
+            summary: String::new(),
+            content: None,
+            story: None,
+            image: None,
+            points: None,
+            comments: None,
+            keywords: Vec::new(),
+        }
+    }
+
+    mod fingerprint {
+        use super::*;
+
+        #[test]
+        fn prefers_the_newest_date() {
+            let articles = [
+                article("https://example.com/1", Some("2025-01-15T10:30:00Z")),
+                article("https://example.com/2", Some("2025-01-16T08:00:00Z")),
+            ];
+            assert_eq!(
+                fingerprint(&articles).as_deref(),
+                Some("2025-01-16T08:00:00+00:00")
+            );
+        }
+
+        #[test]
+        fn hashes_undated_links_in_any_order() {
+            let a = [article("https://a", None), article("https://b", None)];
+            let b = [article("https://b", None), article("https://a", None)];
+            assert_eq!(fingerprint(&a).unwrap().len(), 16);
+            assert_eq!(fingerprint(&a), fingerprint(&b));
+            assert_eq!(fingerprint(&[]), None);
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! Keyword filters: the `[filters]` of `sources.toml`, and each source's own,
+//! drop articles before they're deduplicated and grouped into stories, so a
+//! general-news feed's sports or celebrity items never reach the curator.
+//!
+//! An article is matched on its title, summary, and keywords. It's dropped
+//! when it matches any `exclude` or `exclude_regex`, and, when there's any
+//! `include` or `include_regex` for it, when it matches none of them.
+//! Keywords match whole words in any case ("sport" doesn't match
+//! "transport"); regular expressions match as written.
+
+use crate::feeds::Article;
+use crate::pages::plain_text;
+use crate::sources::{Filters, Source};
+use regex::RegexSet;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// The sources file, as far as filters go
+#[derive(Deserialize)]
+struct SourcesFile {
+    #[serde(default)]
+    filters: Filters,
+}
+
+/// The `[filters]` table of the sources file at `path`, which `sources`
+/// checked as it loaded the feeds
+pub fn load(path: &str) -> Result<Filters, String> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| format!("Cannot read sources {path}: {e}"))?;
+    toml::from_str::<SourcesFile>(&contents)
+        .map(|file| file.filters)
+        .map_err(|e| format!("{path}: {e}"))
+}
+
+/// One feed's filters, compiled
+#[derive(Debug)]
+pub struct Filter {
+    include: Option<RegexSet>,
+    exclude: Option<RegexSet>,
+}
+
+impl Filter {
+    /// The filters `all` feeds have and the ones given, together
+    pub fn new(all: &Filters, own: &Filters) -> Result<Self, String> {
+        let patterns = |keywords: [&Vec<String>; 2], regexes: [&Vec<String>; 2]| {
+            let keywords = keywords.into_iter().flatten().map(|keyword| {
+                let words: Vec<String> = keyword.split_whitespace().map(regex::escape).collect();
+                // \b only borders word characters, and keywords can end in others
+                format!(r"(?i)(?:^|\W){}(?:\W|$)", words.join(r"\s+"))
+            });
+            let patterns: Vec<String> = keywords
+                .chain(regexes.into_iter().flatten().cloned())
+                .collect();
+            if patterns.is_empty() {
+                return Ok(None);
+            }
+            RegexSet::new(patterns)
+                .map(Some)
+                .map_err(|e| format!("Invalid filter: {e}"))
+        };
+        Ok(Self {
+            include: patterns(
+                [&all.include, &own.include],
+                [&all.include_regex, &own.include_regex],
+            )?,
+            exclude: patterns(
+                [&all.exclude, &own.exclude],
+                [&all.exclude_regex, &own.exclude_regex],
+            )?,
+        })
+    }
+
+    /// Whether `article` gets through
+    pub fn keeps(&self, article: &Article) -> bool {
+        let text = format!(
+            "{}\n{}\n{}",
+            article.title,
+            plain_text(&article.summary),
+            article.keywords.join("\n")
+        );
+        self.include.as_ref().is_none_or(|set| set.is_match(&text))
+            && !self.exclude.as_ref().is_some_and(|set| set.is_match(&text))
+    }
+}
+
+/// Drop the articles of each source its filters don't keep, and how many
+/// each source lost
+pub fn apply<'a>(
+    kept: &mut [(&'a str, Vec<Article>)],
+    sources: &[Source],
+    all: &Filters,
+) -> Result<HashMap<&'a str, usize>, String> {
+    let mut filtered = HashMap::new();
+    for (source_id, articles) in kept.iter_mut() {
+        let own = sources
+            .iter()
+            .find(|s| s.id == *source_id)
+            .map(|s| &s.filters);
+        let filter = Filter::new(all, own.unwrap_or(&Filters::default()))?;
+        let before = articles.len();
+        articles.retain(|article| filter.keeps(article));
+        if articles.len() < before {
+            filtered.insert(*source_id, before - articles.len());
+        }
+    }
+    Ok(filtered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article(title: &str, summary: &str) -> Article {
+        Article {
+            title: title.into(),
+            url: "https://example.com/a".into(),
+            published: None,
+            summary: summary.into(),
+            content: None,
+            story: None,
+            image: None,
+            points: None,
+            comments: None,
+            keywords: Vec::new(),
+        }
+    }
+
+    fn filters(toml: &str) -> Filters {
+        toml::from_str(toml).unwrap()
+    }
+
+    mod keeps {
+        use super::*;
+
+        #[test]
+        fn drops_whole_word_matches_in_any_case() {
+            let filter = Filter::new(
+                &filters(r#"exclude = ["sport", "Premier League"]"#),
+                &Filters::default(),
+            )
+            .unwrap();
+            assert!(!filter.keeps(&article("SPORT: late goal", "")));
+            assert!(!filter.keeps(&article("Talks", "<p>The premier\nleague resumes</p>")));
+            assert!(filter.keeps(&article("Transport strike", "Sportswear sales")));
+        }
+
+        #[test]
+        fn keeps_only_includes_when_there_are_any() {
+            let filter = Filter::new(
+                &filters(r#"exclude_regex = ['\bNFL\b']"#),
+                &filters(
+                    r#"include = ["climate"]
+                           include_regex = ['(?i)emissions?']"#,
+                ),
+            )
+            .unwrap();
+            assert!(filter.keeps(&article("Climate summit opens", "")));
+            assert!(filter.keeps(&article("Talks", "Emission cuts agreed")));
+            assert!(!filter.keeps(&article("Budget passes", "")));
+            assert!(!filter.keeps(&article("NFL team's climate pledge", "")));
+        }
+
+        #[test]
+        fn matches_keywords() {
+            let filter =
+                Filter::new(&filters(r#"exclude = ["celebrity"]"#), &Filters::default()).unwrap();
+            let mut gossip = article("A night out", "");
+            gossip.keywords = vec!["Celebrity".into(), "Music".into()];
+            assert!(!filter.keeps(&gossip));
+        }
+    }
+
+    mod apply {
+        use super::*;
+
+        #[test]
+        fn counts_what_each_source_lost() {
+            let sources = crate::sources::parse(
+                "[[source]]\nid = \"bbc\"\nname = \"BBC\"\nurl = \"https://example.com/a\"\n\
+                 bias = \"center\"\nperspective = \"british\"\n\
+                 [source.filters]\nexclude = [\"football\"]\n",
+            )
+            .unwrap();
+            let mut kept = vec![
+                (
+                    "bbc",
+                    vec![
+                        article("Football final", ""),
+                        article("Celebrity wedding", ""),
+                    ],
+                ),
+                (
+                    "npr",
+                    vec![article("Football final", ""), article("Budget", "")],
+                ),
+            ];
+            let filtered =
+                apply(&mut kept, &sources, &filters(r#"exclude = ["celebrity"]"#)).unwrap();
+            assert_eq!(filtered, HashMap::from([("bbc", 2)]));
+            assert!(kept[0].1.is_empty());
+            assert_eq!(kept[1].1.len(), 2);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Politeness towards the sites feeds and articles are fetched from: a cap
+//! on requests in flight, and one request at a time per host, spaced by a
+//! delay, so several feeds from one publisher don't arrive as a burst.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{OwnedMutexGuard, Semaphore, SemaphorePermit};
+use tokio::time::Instant;
+
+/// How hard the sites feeds come from are pressed
+#[derive(Clone, Copy)]
+pub struct Politeness {
+    /// Requests in flight at once, across all hosts
+    pub concurrency: usize,
+    /// Pause between one request to a host finishing and the next starting,
+    /// unless a source sets its own `delay_secs`
+    pub host_delay: Duration,
+}
+
+impl Politeness {
+    /// `FETCH_CONCURRENCY` (default 10) and `FETCH_HOST_DELAY` seconds (default 1)
+    pub fn from_env() -> Self {
+        let concurrency = std::env::var("FETCH_CONCURRENCY")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(10usize);
+        let host_delay = std::env::var("FETCH_HOST_DELAY")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|secs: &f64| secs.is_finite() && *secs >= 0.0)
+            .unwrap_or(1.0);
+        Self {
+            concurrency: concurrency.max(1),
+            host_delay: Duration::from_secs_f64(host_delay),
+        }
+    }
+}
+
+/// Who goes next: a request waits for its host's turn, then for a free slot
+pub struct Scheduler {
+    slots: Semaphore,
+    hosts: Hosts,
+    host_delay: Duration,
+}
+
+impl Scheduler {
+    pub fn new(politeness: Politeness) -> Self {
+        Self {
+            slots: Semaphore::new(politeness.concurrency),
+            hosts: Hosts::default(),
+            host_delay: politeness.host_delay,
+        }
+    }
+
+    /// Wait until a request to `url` may be sent, `delay_secs` (or the host
+    /// delay) after the last one to its host. Hold both while it's in flight.
+    pub async fn wait(&self, url: &str, delay_secs: Option<f64>) -> (Turn, SemaphorePermit<'_>) {
+        let delay = delay_secs.map_or(self.host_delay, Duration::from_secs_f64);
+        // The host first, so requests queued behind a slow host don't hold
+        // slots other hosts could use
+        let turn = self.hosts.turn(url, delay).await;
+        let permit = self
+            .slots
+            .acquire()
+            .await
+            .expect("the semaphore is never closed");
+        (turn, permit)
+    }
+}
+
+/// When each host's last request finished
+#[derive(Default)]
+struct Hosts {
+    last: Mutex<HashMap<String, Arc<tokio::sync::Mutex<Option<Instant>>>>>,
+}
+
+/// The right to send a request to a host, until dropped
+pub struct Turn(OwnedMutexGuard<Option<Instant>>);
+
+impl Drop for Turn {
+    fn drop(&mut self) {
+        *self.0 = Some(Instant::now());
+    }
+}
+
+impl Hosts {
+    /// Wait until no other request to `url`'s host is in flight and `gap` has
+    /// passed since the last one finished
+    async fn turn(&self, url: &str, gap: Duration) -> Turn {
+        let slot = {
+            let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+            last.entry(host(url)).or_default().clone()
+        };
+        let guard = slot.lock_owned().await;
+        if let Some(finished) = *guard {
+            tokio::time::sleep_until(finished + gap).await;
+        }
+        Turn(guard)
+    }
+}
+
+/// The host of `url`, or the whole URL when it has none
+fn host(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| url.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod turn {
+        use super::*;
+
+        #[tokio::test]
+        async fn spaces_requests_to_one_host() {
+            let hosts = Hosts::default();
+            let gap = Duration::from_millis(200);
+            let started = Instant::now();
+            drop(hosts.turn("https://example.com/a.xml", gap).await);
+            drop(hosts.turn("https://example.org/b.xml", gap).await);
+            assert!(started.elapsed() < gap);
+            drop(hosts.turn("https://EXAMPLE.com/c.xml", gap).await);
+            assert!(started.elapsed() >= gap);
+        }
+    }
+}
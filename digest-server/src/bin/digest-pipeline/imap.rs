@@ -0,0 +1,246 @@
+//! Just enough of IMAP (RFC 9051) to read a mailbox: log in over TLS,
+//! examine a mailbox (read-only, so nothing is marked read), search it, and
+//! fetch whole messages.
+
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::{self, pki_types::ServerName};
+
+/// Longest a response line may run, so a broken server can't exhaust memory
+const MAX_LINE: usize = 64 * 1024;
+
+/// Largest message fetched, in bytes
+const MAX_LITERAL: usize = 20 * 1024 * 1024;
+
+/// A response line the server sent before completing a command, with the
+/// literals (`{n}` and n bytes) it carried taken out
+#[derive(Debug, Default, PartialEq)]
+pub struct Response {
+    pub line: String,
+    pub literals: Vec<Vec<u8>>,
+}
+
+/// A logged-in connection
+pub struct Session<S> {
+    stream: BufReader<S>,
+    tag: u32,
+}
+
+/// Log in to the IMAP server at `host` over TLS
+pub async fn connect(
+    host: &str,
+    port: u16,
+    username: &str,
+    password: &str,
+) -> Result<Session<tokio_rustls::client::TlsStream<TcpStream>>, String> {
+    let roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let tls = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .map_err(|e| format!("TLS error: {e}"))?
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+    let name =
+        ServerName::try_from(host.to_string()).map_err(|_| format!("Invalid IMAP host {host}"))?;
+    let tcp = TcpStream::connect((host, port))
+        .await
+        .map_err(|e| format!("Cannot connect to {host}:{port}: {e}"))?;
+    let stream = TlsConnector::from(Arc::new(tls))
+        .connect(name, tcp)
+        .await
+        .map_err(|e| format!("TLS error with {host}: {e}"))?;
+    let mut session = Session::greeted(stream).await?;
+    session
+        .command(&format!("LOGIN {} {}", quote(username)?, quote(password)?))
+        .await
+        .map_err(|e| format!("Login failed: {e}"))?;
+    Ok(session)
+}
+
+/// `s` as an IMAP quoted string
+fn quote(s: &str) -> Result<String, String> {
+    if s.contains(['\r', '\n', '\0']) {
+        return Err("Line breaks can't be sent in an IMAP string".into());
+    }
+    Ok(format!(
+        "\"{}\"",
+        s.replace('\\', "\\\\").replace('"', "\\\"")
+    ))
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Session<S> {
+    /// A session on `stream`, once the server's greeting says it's ready
+    async fn greeted(stream: S) -> Result<Self, String> {
+        let mut session = Self {
+            stream: BufReader::new(stream),
+            tag: 0,
+        };
+        let greeting = session.response().await?;
+        if !greeting.line.starts_with("* OK") {
+            return Err(format!("Unexpected greeting: {}", greeting.line));
+        }
+        Ok(session)
+    }
+
+    /// Open `mailbox` read-only
+    pub async fn examine(&mut self, mailbox: &str) -> Result<(), String> {
+        self.command(&format!("EXAMINE {}", quote(mailbox)?))
+            .await
+            .map(|_| ())
+    }
+
+    /// UIDs of the messages matching `criteria` (as `SEARCH` takes them),
+    /// oldest first
+    pub async fn search(&mut self, criteria: &str) -> Result<Vec<u32>, String> {
+        let responses = self.command(&format!("UID SEARCH {criteria}")).await?;
+        let mut uids: Vec<u32> = responses
+            .iter()
+            .filter_map(|r| r.line.strip_prefix("* SEARCH"))
+            .flat_map(|uids| uids.split_whitespace().filter_map(|uid| uid.parse().ok()))
+            .collect();
+        uids.sort_unstable();
+        Ok(uids)
+    }
+
+    /// The messages with these UIDs, whole
+    pub async fn fetch(&mut self, uids: &[u32]) -> Result<Vec<Vec<u8>>, String> {
+        if uids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let set: Vec<String> = uids.iter().map(u32::to_string).collect();
+        let responses = self
+            .command(&format!("UID FETCH {} (BODY.PEEK[])", set.join(",")))
+            .await?;
+        Ok(responses
+            .into_iter()
+            .filter(|r| r.line.starts_with("* ") && r.line.contains(" FETCH "))
+            .filter_map(|r| r.literals.into_iter().next())
+            .collect())
+    }
+
+    pub async fn logout(mut self) -> Result<(), String> {
+        self.command("LOGOUT").await.map(|_| ())
+    }
+
+    /// Send `command`, and the responses it got once the server says it
+    /// completed
+    async fn command(&mut self, command: &str) -> Result<Vec<Response>, String> {
+        self.tag += 1;
+        let tag = format!("a{}", self.tag);
+        let stream = self.stream.get_mut();
+        stream
+            .write_all(format!("{tag} {command}\r\n").as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+        stream.flush().await.map_err(|e| e.to_string())?;
+        let mut responses = Vec::new();
+        loop {
+            let response = self.response().await?;
+            let Some(status) = response.line.strip_prefix(&format!("{tag} ")) else {
+                responses.push(response);
+                continue;
+            };
+            if status.starts_with("OK") {
+                return Ok(responses);
+            }
+            return Err(status.to_string());
+        }
+    }
+
+    /// The next response, with any literals it carries
+    async fn response(&mut self) -> Result<Response, String> {
+        let mut response = Response::default();
+        loop {
+            let mut line = Vec::new();
+            let read = (&mut self.stream)
+                .take(MAX_LINE as u64)
+                .read_until(b'\n', &mut line)
+                .await
+                .map_err(|e| e.to_string())?;
+            if read == 0 {
+                return Err("Connection closed".into());
+            }
+            if !line.ends_with(b"\n") {
+                return Err("Response line too long".into());
+            }
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+            response.line.push_str(line);
+            let Some(length) = literal_length(line) else {
+                return Ok(response);
+            };
+            if length > MAX_LITERAL {
+                return Err(format!("Message larger than {MAX_LITERAL} bytes"));
+            }
+            let mut literal = vec![0; length];
+            self.stream
+                .read_exact(&mut literal)
+                .await
+                .map_err(|e| e.to_string())?;
+            response.literals.push(literal);
+        }
+    }
+}
+
+/// The length of the literal announced at the end of `line`, if one is
+fn literal_length(line: &str) -> Option<usize> {
+    let (_, length) = line.strip_suffix('}')?.rsplit_once('{')?;
+    length.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod quote {
+        use super::*;
+
+        #[test]
+        fn escapes_quotes_and_backslashes() {
+            assert_eq!(quote(r#"pa"ss\word"#).unwrap(), r#""pa\"ss\\word""#);
+            assert!(quote("two\r\nlines").is_err());
+        }
+    }
+
+    mod session {
+        use super::*;
+
+        #[tokio::test]
+        async fn searches_and_fetches_messages() {
+            let (client, mut server) = tokio::io::duplex(4096);
+            let script = tokio::spawn(async move {
+                let mut sent = String::new();
+                server.write_all(b"* OK IMAP4rev2 ready\r\n").await.unwrap();
+                let mut lines = BufReader::new(&mut server);
+                for reply in [
+                    "* 2 EXISTS\r\na1 OK [READ-ONLY] Done\r\n",
+                    "* SEARCH 9 4\r\na2 OK Done\r\n",
+                    "* 1 FETCH (UID 4 BODY[] {13}\r\nSubject: Hi\r\n)\r\na3 OK Done\r\n",
+                    "a4 NO Not today\r\n",
+                ] {
+                    lines.read_line(&mut sent).await.unwrap();
+                    lines.get_mut().write_all(reply.as_bytes()).await.unwrap();
+                }
+                sent
+            });
+            let mut session = Session::greeted(client).await.unwrap();
+            session.examine("Newsletters").await.unwrap();
+            assert_eq!(session.search("SINCE 1-Jan-2025").await.unwrap(), [4, 9]);
+            assert_eq!(
+                session.fetch(&[4]).await.unwrap(),
+                [b"Subject: Hi\r\n".to_vec()]
+            );
+            assert_eq!(session.command("NOOP").await.unwrap_err(), "NO Not today");
+            assert_eq!(
+                script.await.unwrap(),
+                "a1 EXAMINE \"Newsletters\"\r\na2 UID SEARCH SINCE 1-Jan-2025\r\n\
+                 a3 UID FETCH 4 (BODY.PEEK[])\r\na4 NOOP\r\n"
+            );
+        }
+    }
+}
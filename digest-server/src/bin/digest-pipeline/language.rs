@@ -0,0 +1,365 @@
+//! The language each fetched article is written in, told from its script or,
+//! for Latin script, its most common words. With `TRANSLATE` set, articles
+//! not in English are translated by the model that writes up the lower tiers
+//! before curation, so the digest can draw on sources in other languages.
+//! Narratives' sources keep the language their article was written in.
+
+use crate::budget::Budget;
+use crate::curate::{self, Cluster};
+use crate::llm::{Model, Usage};
+use crate::pages::plain_text;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Articles per translation request
+const ARTICLES_PER_REQUEST: usize = 10;
+
+/// Longest text translated per article, in characters: as much as a stories
+/// prompt takes at full detail
+const TRANSLATED_CHARS: usize = 600;
+
+/// Reply tokens allowed per article
+const ARTICLE_TOKENS: u32 = 300;
+
+/// Words, of the commonest, found in one language's writing but few others'
+const COMMON_WORDS: &[(&str, &[&str])] = &[
+    (
+        "en",
+        &[
+            "the", "and", "of", "to", "that", "for", "with", "was", "are", "this", "from", "has",
+            "have", "said", "will", "were", "been", "which", "their", "after",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "les", "des", "et", "est", "du", "une", "dans", "pour", "qui", "sur", "pas",
+            "aux", "avec", "sont", "cette", "ont", "été", "mais", "selon",
+        ],
+    ),
+    (
+        "de",
+        &[
+            "der", "die", "das", "und", "ist", "nicht", "mit", "den", "dem", "von", "zu", "ein",
+            "eine", "auf", "für", "sich", "auch", "wird", "im", "bei", "nach",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "los", "las", "y", "por", "para", "su", "como", "más", "pero", "fue", "sus",
+            "está", "según", "entre", "este", "esta", "muy", "también",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "gli", "della", "di", "che", "è", "sono", "nel", "alla", "anche", "dei", "delle",
+            "più", "non", "stato", "questo", "dopo", "essere", "molto",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "os", "do", "dos", "das", "não", "em", "um", "uma", "com", "é", "mais", "foi", "ao",
+            "na", "no", "pelo", "pela", "são", "também", "até",
+        ],
+    ),
+    (
+        "nl",
+        &[
+            "het", "een", "van", "niet", "dat", "op", "te", "voor", "met", "zijn", "wordt", "ook",
+            "bij", "door", "naar", "heeft", "werd", "worden",
+        ],
+    ),
+];
+
+/// Names of the languages told apart, by ISO 639-1 code
+const NAMES: &[(&str, &str)] = &[
+    ("ar", "Arabic"),
+    ("de", "German"),
+    ("el", "Greek"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fa", "Persian"),
+    ("fr", "French"),
+    ("he", "Hebrew"),
+    ("hi", "Hindi"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("nl", "Dutch"),
+    ("pt", "Portuguese"),
+    ("ru", "Russian"),
+    ("th", "Thai"),
+    ("uk", "Ukrainian"),
+    ("zh", "Chinese"),
+];
+
+/// The name of the language with ISO 639-1 `code`, if it's one told apart
+pub fn name(code: &str) -> Option<&'static str> {
+    NAMES
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, name)| *name)
+}
+
+/// The ISO 639-1 code of the language `text` is in, if it can be told
+pub fn detect(text: &str) -> Option<&'static str> {
+    let letters: Vec<char> = text.chars().filter(|c| c.is_alphabetic()).collect();
+    let latin = letters
+        .iter()
+        .filter(|c| c.is_ascii() || is_latin(**c))
+        .count();
+    if letters.is_empty() {
+        return None;
+    }
+    if latin * 2 < letters.len() {
+        return by_script(&letters);
+    }
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    let mut counts: Vec<(&str, usize)> = COMMON_WORDS
+        .iter()
+        .map(|(code, common)| {
+            let found = words
+                .iter()
+                .filter(|w| common.contains(&w.as_str()))
+                .count();
+            (*code, found)
+        })
+        .collect();
+    counts.sort_by_key(|(_, found)| std::cmp::Reverse(*found));
+    match counts[..] {
+        [(code, best), (_, next), ..] if best >= 2 && best > next => Some(code),
+        _ => None,
+    }
+}
+
+/// Latin letters beyond ASCII: accented and extended
+fn is_latin(c: char) -> bool {
+    matches!(c, '\u{00C0}'..='\u{024F}' | '\u{1E00}'..='\u{1EFF}')
+}
+
+/// The language of mostly non-Latin `letters`, by their script
+fn by_script(letters: &[char]) -> Option<&'static str> {
+    let count = |range: &[std::ops::RangeInclusive<char>]| {
+        letters
+            .iter()
+            .filter(|c| range.iter().any(|r| r.contains(*c)))
+            .count()
+    };
+    let has = |chars: &str| letters.iter().any(|c| chars.contains(*c));
+    let kana = count(&['\u{3040}'..='\u{30FF}']);
+    let scripts = [
+        (
+            count(&['\u{0400}'..='\u{04FF}']),
+            if has("іїєґ") { "uk" } else { "ru" },
+        ),
+        (count(&['\u{0370}'..='\u{03FF}']), "el"),
+        (
+            count(&['\u{0600}'..='\u{06FF}']),
+            if has("پچژگی") { "fa" } else { "ar" },
+        ),
+        (count(&['\u{0590}'..='\u{05FF}']), "he"),
+        (count(&['\u{0900}'..='\u{097F}']), "hi"),
+        (count(&['\u{0E00}'..='\u{0E7F}']), "th"),
+        (
+            count(&['\u{AC00}'..='\u{D7AF}', '\u{1100}'..='\u{11FF}']),
+            "ko",
+        ),
+        (kana, "ja"),
+        (
+            count(&['\u{4E00}'..='\u{9FFF}']),
+            if kana > 0 { "ja" } else { "zh" },
+        ),
+    ];
+    scripts
+        .into_iter()
+        .filter(|(found, _)| *found > 0)
+        .max_by_key(|(found, _)| *found)
+        .map(|(_, code)| code)
+}
+
+/// Whether `TRANSLATE` asks for articles not in English to be translated
+pub fn translating() -> bool {
+    std::env::var("TRANSLATE").is_ok_and(|v| v == "1" || v == "true")
+}
+
+/// Tag every article in `clusters` with its language, and count those told
+/// to be in another than English
+pub fn detect_all(clusters: &mut [Cluster]) -> usize {
+    let mut foreign = 0;
+    for (_, article) in clusters.iter_mut().flat_map(|c| c.articles.iter_mut()) {
+        let text = format!(
+            "{} {}",
+            plain_text(&article.title),
+            plain_text(article.content.as_deref().unwrap_or(&article.summary))
+        );
+        article.language = detect(&text);
+        if article.language.is_some_and(|code| code != "en") {
+            foreign += 1;
+        }
+    }
+    foreign
+}
+
+#[derive(Debug, Deserialize)]
+struct Translation {
+    title: String,
+    #[serde(default)]
+    text: String,
+}
+
+/// The prompt asking for `articles`, as (id, language, title, text), in
+/// English
+fn prompt(articles: &[(String, &str, String, String)]) -> String {
+    let mut prompt = String::from(
+        "Translate each of these news articles into English, keeping names, numbers, and \
+         quotes faithful. Reply with only a JSON object mapping each article's id to \
+         {\"title\": \"...\", \"text\": \"...\"}.\n",
+    );
+    for (id, language, title, text) in articles {
+        let language = name(language).unwrap_or(*language);
+        prompt.push_str(&format!(
+            "\n### {id} ({language})\nTitle: {title}\nText: {text}\n"
+        ));
+    }
+    prompt
+}
+
+/// Translate the articles of `clusters` that aren't in English, a batch per
+/// request, within `budget`. Articles the budget can't cover, or whose
+/// translation can't be read, stay as they were. Returns how many were
+/// translated and the tokens that took.
+pub async fn translate(
+    model: &Model,
+    budget: &Budget,
+    system: &str,
+    clusters: &mut [Cluster<'_>],
+) -> (usize, Usage) {
+    let mut foreign = Vec::new();
+    for (cluster, c) in clusters.iter().enumerate() {
+        for (index, (_, article)) in c.articles.iter().enumerate() {
+            if let Some(language) = article.language.filter(|code| *code != "en") {
+                let text = plain_text(article.content.as_deref().unwrap_or(&article.summary));
+                foreign.push((
+                    (cluster, index),
+                    (
+                        format!("t{}", foreign.len() + 1),
+                        language,
+                        plain_text(&article.title),
+                        text.chars().take(TRANSLATED_CHARS).collect::<String>(),
+                    ),
+                ));
+            }
+        }
+    }
+    let (mut translated, mut usage) = (0, Usage::default());
+    for batch in foreign.chunks(ARTICLES_PER_REQUEST) {
+        let articles: Vec<_> = batch.iter().map(|(_, article)| article.clone()).collect();
+        let max_tokens = ARTICLE_TOKENS * batch.len() as u32;
+        let replied = curate::ask::<HashMap<String, Translation>>(
+            model,
+            budget,
+            system,
+            &prompt(&articles),
+            max_tokens,
+        )
+        .await;
+        let translations = match replied {
+            Ok(Some((translations, used))) => {
+                usage += used;
+                translations
+            }
+            Ok(None) => {
+                tracing::warn!(
+                    "Over budget: {} articles left untranslated",
+                    foreign.len() - translated
+                );
+                break;
+            }
+            Err(e) => {
+                tracing::warn!("Cannot translate {} articles: {}", batch.len(), e);
+                continue;
+            }
+        };
+        for ((cluster, index), (id, ..)) in batch {
+            let Some(translation) = translations.get(id).filter(|t| !t.title.trim().is_empty())
+            else {
+                continue;
+            };
+            let article = &mut clusters[*cluster].articles[*index].1;
+            article.title = translation.title.trim().to_string();
+            article.summary = translation.text.trim().to_string();
+            article.content = None;
+            translated += 1;
+        }
+    }
+    (translated, usage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod detect {
+        use super::*;
+
+        #[test]
+        fn tells_latin_languages_by_their_common_words() {
+            assert_eq!(
+                detect("The minister said that talks with the union were over"),
+                Some("en")
+            );
+            assert_eq!(
+                detect("Le gouvernement a annoncé des mesures pour les agriculteurs"),
+                Some("fr")
+            );
+            assert_eq!(
+                detect("Die Regierung hat die neuen Regeln für den Export nicht bestätigt"),
+                Some("de")
+            );
+            assert_eq!(
+                detect("El gobierno anunció que los precios de la energía bajarán según el plan"),
+                Some("es")
+            );
+            assert_eq!(
+                detect("O governo não confirmou os números da inflação"),
+                Some("pt")
+            );
+            assert_eq!(detect("Ceasefire"), None);
+            assert_eq!(detect("1234 — 5678"), None);
+        }
+
+        #[test]
+        fn tells_other_scripts_apart() {
+            assert_eq!(detect("Правительство объявило о новых мерах"), Some("ru"));
+            assert_eq!(detect("Уряд оголосив про нові заходи і їх"), Some("uk"));
+            assert_eq!(detect("政府宣布了新的措施"), Some("zh"));
+            assert_eq!(detect("政府は新しい措置を発表した"), Some("ja"));
+            assert_eq!(detect("정부가 새로운 조치를 발표했다"), Some("ko"));
+            assert_eq!(detect("أعلنت الحكومة عن إجراءات جديدة"), Some("ar"));
+        }
+    }
+
+    mod prompt {
+        use super::*;
+
+        #[test]
+        fn names_each_articles_language() {
+            let prompt = prompt(&[(
+                "t1".into(),
+                "fr",
+                "Grève nationale".into(),
+                "Les syndicats appellent à la grève.".into(),
+            )]);
+            assert!(prompt.ends_with(
+                "### t1 (French)\nTitle: Grève nationale\nText: Les syndicats appellent à la grève.\n"
+            ));
+        }
+    }
+}
@@ -0,0 +1,421 @@
+//! Language models, whichever API serves them: Anthropic's Messages API, an
+//! OpenAI-compatible chat completions API, or a local Ollama. A model is
+//! named `provider:model` (`anthropic:claude-sonnet-4-5`, `openai:gpt-4o-mini`,
+//! `ollama:llama3.1`). Replies are streamed, so long ones don't sit on an idle
+//! connection, and requests that fail for reasons worth waiting out (network
+//! errors, 429, 5xx, an overloaded stream) are retried with backoff. Each
+//! provider reports tokens its own way; they're counted alike, and priced per
+//! model, for the run's record.
+
+mod anthropic;
+mod ollama;
+mod openai;
+
+use reqwest::{Client, RequestBuilder};
+use serde::Serialize;
+use serde_json::Value;
+use std::time::Duration;
+
+/// How long one request, its reply streamed in full, may take
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// The model used when `LLM_MODEL` isn't set
+const DEFAULT_MODEL: &str = "anthropic:claude-sonnet-4-5";
+
+/// Characters per token, roughly, for estimates before a request is sent
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Tokens used, and what they cost
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Usage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+}
+
+impl std::ops::AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cost_usd += other.cost_usd;
+    }
+}
+
+/// A streamed reply, put back together
+#[derive(Debug, Default, PartialEq)]
+pub struct Reply {
+    pub text: String,
+    /// Whether the reply stopped at `max_tokens`
+    pub cut_off: bool,
+    pub usage: Usage,
+}
+
+/// Why a request failed, and whether it's worth trying again
+#[derive(Debug)]
+struct Failure {
+    message: String,
+    retry: bool,
+    /// How long the API asked us to wait
+    after: Option<Duration>,
+}
+
+impl Failure {
+    fn retry(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            retry: true,
+            after: None,
+        }
+    }
+
+    fn fatal(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            retry: false,
+            after: None,
+        }
+    }
+}
+
+/// An API serving models: how to ask it, and how to read its replies
+trait Provider: Send + Sync {
+    /// The request for one reply, streamed
+    fn request(
+        &self,
+        http: &Client,
+        model: &str,
+        system: &str,
+        prompt: &str,
+        max_tokens: u32,
+    ) -> RequestBuilder;
+
+    /// A reader for one streamed reply
+    fn decoder(&self) -> Box<dyn Decoder>;
+}
+
+/// A streamed reply, read as it arrives
+trait Decoder: Send {
+    fn push(&mut self, chunk: &[u8]) -> Result<(), Failure>;
+
+    /// The reply, once the stream has ended
+    fn finish(self: Box<Self>) -> Result<Reply, Failure>;
+}
+
+/// Complete frames (server-sent events, or JSON lines) out of the chunks
+/// holding them
+struct Frames {
+    buffer: Vec<u8>,
+    separator: &'static [u8],
+}
+
+impl Frames {
+    fn new(separator: &'static [u8]) -> Self {
+        Self {
+            buffer: Vec::new(),
+            separator,
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        // Lines may end in CRLF; JSON escapes any carriage return it carries
+        self.buffer.extend(chunk.iter().filter(|&&b| b != b'\r'));
+        let mut frames = Vec::new();
+        while let Some(end) = self
+            .buffer
+            .windows(self.separator.len())
+            .position(|w| w == self.separator)
+        {
+            let frame: Vec<u8> = self.buffer.drain(..end + self.separator.len()).collect();
+            frames.push(String::from_utf8_lossy(&frame).into_owned());
+        }
+        frames
+    }
+}
+
+/// The `data:` of a server-sent event, empty for events without any
+fn event_data(event: &str) -> String {
+    event
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(str::trim_start)
+        .collect()
+}
+
+/// Prices and retries of a model, from the environment
+#[derive(Clone, Debug)]
+struct Settings {
+    /// USD per million input tokens (cached prompt tokens included)
+    input_price: f64,
+    /// USD per million output tokens
+    output_price: f64,
+    attempts: u32,
+    /// Doubled after each failed attempt, unless the API says how long to wait
+    retry_delay: Duration,
+}
+
+fn var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+fn number(name: &str, default: f64) -> f64 {
+    var(name)
+        .and_then(|v| v.parse().ok())
+        .filter(|n: &f64| n.is_finite() && *n >= 0.0)
+        .unwrap_or(default)
+}
+
+pub struct Model {
+    http: Client,
+    provider: Box<dyn Provider>,
+    /// `provider:model`
+    name: String,
+    model: String,
+    settings: Settings,
+}
+
+impl Model {
+    /// `LLM_MODEL` (default `anthropic:claude-sonnet-4-5`), priced by
+    /// `LLM_INPUT_PRICE` and `LLM_OUTPUT_PRICE`
+    pub fn from_env() -> Result<Self, String> {
+        let name = var("LLM_MODEL").unwrap_or_else(|| DEFAULT_MODEL.into());
+        Self::configured(&name, "LLM")
+    }
+
+    /// `LLM_CHEAP_MODEL`, if set, priced by `LLM_CHEAP_INPUT_PRICE` and
+    /// `LLM_CHEAP_OUTPUT_PRICE`
+    pub fn cheap_from_env() -> Result<Option<Self>, String> {
+        var("LLM_CHEAP_MODEL")
+            .map(|name| Self::configured(&name, "LLM_CHEAP"))
+            .transpose()
+    }
+
+    /// The model `name` names, its prices from `{prefix}_INPUT_PRICE` and
+    /// `{prefix}_OUTPUT_PRICE`, or the provider's list prices. Retries are
+    /// set by `LLM_MAX_RETRIES` (default 3) and `LLM_RETRY_DELAY` seconds
+    /// (default 5).
+    fn configured(name: &str, prefix: &str) -> Result<Self, String> {
+        let (kind, model) = name
+            .split_once(':')
+            .filter(|(_, model)| !model.is_empty())
+            .ok_or_else(|| format!("{name}: name a model as provider:model"))?;
+        let (provider, (input_price, output_price)): (Box<dyn Provider>, _) = match kind {
+            "anthropic" => (
+                Box::new(anthropic::Anthropic::from_env()?),
+                anthropic::PRICES,
+            ),
+            "openai" => (Box::new(openai::OpenAi::from_env()), openai::PRICES),
+            "ollama" => (Box::new(ollama::Ollama::from_env()), ollama::PRICES),
+            _ => {
+                return Err(format!(
+                    "{name}: the provider must be anthropic, openai, or ollama"
+                ));
+            }
+        };
+        let settings = Settings {
+            input_price: number(&format!("{prefix}_INPUT_PRICE"), input_price),
+            output_price: number(&format!("{prefix}_OUTPUT_PRICE"), output_price),
+            attempts: number("LLM_MAX_RETRIES", 3.0).max(1.0) as u32,
+            retry_delay: Duration::from_secs_f64(number("LLM_RETRY_DELAY", 5.0)),
+        };
+        Self::new(provider, name, model, settings)
+    }
+
+    fn new(
+        provider: Box<dyn Provider>,
+        name: &str,
+        model: &str,
+        settings: Settings,
+    ) -> Result<Self, String> {
+        let http = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| format!("Cannot build HTTP client: {e}"))?;
+        Ok(Self {
+            http,
+            provider,
+            name: name.to_string(),
+            model: model.to_string(),
+            settings,
+        })
+    }
+
+    /// A local model at the given prices, for tests that only estimate
+    #[cfg(test)]
+    pub fn priced(input_price: f64, output_price: f64) -> Self {
+        let settings = Settings {
+            input_price,
+            output_price,
+            attempts: 1,
+            retry_delay: Duration::ZERO,
+        };
+        Self::new(
+            Box::new(ollama::Ollama::from_env()),
+            "ollama:test",
+            "test",
+            settings,
+        )
+        .expect("an HTTP client")
+    }
+
+    /// `provider:model`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Send `prompt` under `system` and wait for the whole reply, retrying
+    /// failures worth retrying
+    pub async fn complete(
+        &self,
+        system: &str,
+        prompt: &str,
+        max_tokens: u32,
+    ) -> Result<Reply, String> {
+        let mut delay = self.settings.retry_delay;
+        for attempt in 1..=self.settings.attempts {
+            match self.send(system, prompt, max_tokens).await {
+                Ok(mut reply) => {
+                    reply.usage.cost_usd = self.cost(&reply.usage);
+                    return Ok(reply);
+                }
+                Err(failure) if failure.retry && attempt < self.settings.attempts => {
+                    let wait = failure.after.unwrap_or(delay);
+                    tracing::warn!(
+                        "{} request failed ({}), retrying in {}s",
+                        self.name,
+                        failure.message,
+                        wait.as_secs_f64()
+                    );
+                    tokio::time::sleep(wait).await;
+                    delay *= 2;
+                }
+                Err(failure) => {
+                    return Err(format!("{} request failed: {}", self.name, failure.message));
+                }
+            }
+        }
+        unreachable!("the last attempt returns")
+    }
+
+    /// What a request with `system` and `prompt` may cost if its reply runs
+    /// to `max_tokens`
+    pub fn estimate(&self, system: &str, prompt: &str, max_tokens: u32) -> Usage {
+        let mut usage = Usage {
+            input_tokens: ((system.len() + prompt.len()) / CHARS_PER_TOKEN) as u64,
+            output_tokens: max_tokens.into(),
+            cost_usd: 0.0,
+        };
+        usage.cost_usd = self.cost(&usage);
+        usage
+    }
+
+    fn cost(&self, usage: &Usage) -> f64 {
+        (usage.input_tokens as f64 * self.settings.input_price
+            + usage.output_tokens as f64 * self.settings.output_price)
+            / 1_000_000.0
+    }
+
+    async fn send(&self, system: &str, prompt: &str, max_tokens: u32) -> Result<Reply, Failure> {
+        let mut response = self
+            .provider
+            .request(&self.http, &self.model, system, prompt, max_tokens)
+            .send()
+            .await
+            .map_err(|e| Failure::retry(e.to_string()))?;
+        let status = response.status();
+        if !status.is_success() {
+            let after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs);
+            let detail = response.text().await.unwrap_or_default();
+            return Err(Failure {
+                message: format!("HTTP {status}: {}", error_message(&detail)),
+                retry: status.as_u16() == 429 || status.is_server_error(),
+                after,
+            });
+        }
+
+        let mut decoder = self.provider.decoder();
+        loop {
+            match response.chunk().await {
+                Ok(Some(chunk)) => decoder.push(&chunk)?,
+                Ok(None) => break,
+                Err(e) => return Err(Failure::retry(e.to_string())),
+            }
+        }
+        decoder.finish()
+    }
+}
+
+/// The message in an error response's body, however the API words it
+fn error_message(body: &str) -> String {
+    let Ok(error) = serde_json::from_str::<Value>(body) else {
+        return body.to_string();
+    };
+    error["error"]["message"]
+        .as_str()
+        .or_else(|| error["error"].as_str())
+        .map_or_else(|| body.to_string(), str::to_string)
+}
+
+/// The JSON value in a reply, ignoring any prose or code fence around it
+pub fn json_in<T: serde::de::DeserializeOwned>(text: &str) -> Result<T, String> {
+    let start = text.find(['{', '[']).ok_or("The reply has no JSON")?;
+    let end = text.rfind(['}', ']']).ok_or("The reply has no JSON")?;
+    if end < start {
+        return Err("The reply has no JSON".into());
+    }
+    serde_json::from_str(&text[start..=end])
+        .map_err(|e| format!("The reply's JSON is invalid: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    mod frames {
+        use super::*;
+
+        #[test]
+        fn splits_events_across_chunks() {
+            let mut frames = Frames::new(b"\n\n");
+            assert!(frames.push(b"data: {\"a\":").is_empty());
+            let mut events = frames.push(b" 1}\r\n\r\nevent: x\r\ndata: 2\n\ndata:");
+            assert_eq!(events.len(), 2);
+            events.extend(frames.push(b"3\n\n"));
+            let data: Vec<String> = events.iter().map(|e| event_data(e)).collect();
+            assert_eq!(data, ["{\"a\": 1}", "2", "3"]);
+        }
+    }
+
+    mod error_message {
+        use super::*;
+
+        #[test]
+        fn reads_each_api_s_errors() {
+            assert_eq!(
+                error_message(r#"{"type":"error","error":{"type":"x","message":"Bad key"}}"#),
+                "Bad key"
+            );
+            assert_eq!(
+                error_message(r#"{"error":"model 'x' not found"}"#),
+                "model 'x' not found"
+            );
+            assert_eq!(error_message("Bad gateway"), "Bad gateway");
+        }
+    }
+
+    mod json_in {
+        use super::*;
+
+        #[test]
+        fn finds_json_inside_a_code_fence() {
+            let value: Value = json_in("Here you go:\n```json\n{\"items\": [1, 2]}\n```").unwrap();
+            assert_eq!(value, json!({"items": [1, 2]}));
+            assert!(json_in::<Value>("No JSON here").is_err());
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Anthropic's Messages API. The system prompt is marked for caching, since
+//! a run sends the same one with every request.
+
+use super::{Decoder, Failure, Frames, Provider, Reply, event_data, var};
+use reqwest::{Client, RequestBuilder};
+use serde_json::{Value, json};
+
+const API_VERSION: &str = "2023-06-01";
+
+/// USD per million input and output tokens (Claude Sonnet)
+pub const PRICES: (f64, f64) = (3.0, 15.0);
+
+pub struct Anthropic {
+    api_key: String,
+    base_url: String,
+}
+
+impl Anthropic {
+    /// `ANTHROPIC_API_KEY` (required) and `ANTHROPIC_BASE_URL`
+    pub fn from_env() -> Result<Self, String> {
+        Ok(Self {
+            api_key: var("ANTHROPIC_API_KEY").ok_or("ANTHROPIC_API_KEY is not set")?,
+            base_url: var("ANTHROPIC_BASE_URL")
+                .unwrap_or_else(|| "https://api.anthropic.com".into())
+                .trim_end_matches('/')
+                .to_string(),
+        })
+    }
+}
+
+impl Provider for Anthropic {
+    fn request(
+        &self,
+        http: &Client,
+        model: &str,
+        system: &str,
+        prompt: &str,
+        max_tokens: u32,
+    ) -> RequestBuilder {
+        http.post(format!("{}/v1/messages", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", API_VERSION)
+            .json(&json!({
+                "model": model,
+                "max_tokens": max_tokens,
+                "stream": true,
+                "system": [{"type": "text", "text": system, "cache_control": {"type": "ephemeral"}}],
+                "messages": [{"role": "user", "content": prompt}],
+            }))
+    }
+
+    fn decoder(&self) -> Box<dyn Decoder> {
+        Box::new(Stream::default())
+    }
+}
+
+/// Server-sent events as they arrive, folded into a [`Reply`]
+struct Stream {
+    frames: Frames,
+    reply: Reply,
+    stopped: bool,
+}
+
+impl Default for Stream {
+    fn default() -> Self {
+        Self {
+            frames: Frames::new(b"\n\n"),
+            reply: Reply::default(),
+            stopped: false,
+        }
+    }
+}
+
+impl Stream {
+    fn event(&mut self, data: &str) -> Result<(), Failure> {
+        let event: Value = serde_json::from_str(data)
+            .map_err(|e| Failure::retry(format!("Unreadable event: {e}")))?;
+        let tokens = |usage: &Value, key: &str| usage[key].as_u64().unwrap_or(0);
+        match event["type"].as_str().unwrap_or_default() {
+            "message_start" => {
+                // Cached prompt tokens are input too
+                let usage = &event["message"]["usage"];
+                self.reply.usage.input_tokens = [
+                    "input_tokens",
+                    "cache_creation_input_tokens",
+                    "cache_read_input_tokens",
+                ]
+                .iter()
+                .map(|key| tokens(usage, key))
+                .sum();
+                self.reply.usage.output_tokens = tokens(usage, "output_tokens");
+            }
+            "content_block_delta" => {
+                if let Some(text) = event["delta"]["text"].as_str() {
+                    self.reply.text.push_str(text);
+                }
+            }
+            "message_delta" => {
+                if let Some(reason) = event["delta"]["stop_reason"].as_str() {
+                    self.reply.cut_off = reason == "max_tokens";
+                }
+                if let Some(output) = event["usage"]["output_tokens"].as_u64() {
+                    self.reply.usage.output_tokens = output;
+                }
+            }
+            "message_stop" => self.stopped = true,
+            "error" => {
+                let kind = event["error"]["type"].as_str().unwrap_or_default();
+                let message = event["error"]["message"].as_str().unwrap_or(kind);
+                return Err(
+                    if matches!(kind, "overloaded_error" | "api_error" | "rate_limit_error") {
+                        Failure::retry(message)
+                    } else {
+                        Failure::fatal(message)
+                    },
+                );
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl Decoder for Stream {
+    fn push(&mut self, chunk: &[u8]) -> Result<(), Failure> {
+        for event in self.frames.push(chunk) {
+            let data = event_data(&event);
+            if !data.is_empty() {
+                self.event(&data)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<Reply, Failure> {
+        if !self.stopped {
+            return Err(Failure::retry("Reply ended early"));
+        }
+        Ok(self.reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::Usage;
+
+    mod stream {
+        use super::*;
+
+        const EVENTS: &str = "event: message_start\n\
+            data: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":10,\"cache_read_input_tokens\":90,\"output_tokens\":1}}}\n\n\
+            event: ping\ndata: {\"type\":\"ping\"}\n\n\
+            event: content_block_delta\n\
+            data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hello, \"}}\n\n\
+            event: content_block_delta\r\n\
+            data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"wörld\"}}\n\n\
+            event: message_delta\n\
+            data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"max_tokens\"},\"usage\":{\"output_tokens\":42}}\n\n\
+            event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n";
+
+        #[test]
+        fn puts_the_reply_together_across_chunks() {
+            let mut stream = Box::new(Stream::default());
+            // Split mid-event and mid-character
+            for chunk in EVENTS.as_bytes().chunks(7) {
+                assert!(stream.push(chunk).is_ok());
+            }
+            let reply = stream.finish().unwrap();
+            assert_eq!(reply.text, "Hello, wörld");
+            assert!(reply.cut_off);
+            assert_eq!(
+                reply.usage,
+                Usage {
+                    input_tokens: 100,
+                    output_tokens: 42,
+                    cost_usd: 0.0
+                }
+            );
+        }
+
+        #[test]
+        fn retries_overloaded_and_cut_off_streams() {
+            let mut stream = Box::new(Stream::default());
+            let failure = stream
+                .push(b"event: error\ndata: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n")
+                .unwrap_err();
+            assert!(failure.retry);
+            assert_eq!(failure.message, "Overloaded");
+
+            let mut stream = Box::new(Stream::default());
+            assert!(stream.push(&EVENTS.as_bytes()[..200]).is_ok());
+            assert!(stream.finish().unwrap_err().retry);
+        }
+    }
+}
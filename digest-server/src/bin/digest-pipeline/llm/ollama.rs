@@ -0,0 +1,164 @@
+//! A local Ollama's chat API, which streams a JSON object per line and
+//! counts tokens in the last one.
+
+use super::{Decoder, Failure, Frames, Provider, Reply, var};
+use reqwest::{Client, RequestBuilder};
+use serde_json::{Value, json};
+
+/// USD per million input and output tokens: local models cost nothing per
+/// token
+pub const PRICES: (f64, f64) = (0.0, 0.0);
+
+pub struct Ollama {
+    host: String,
+}
+
+impl Ollama {
+    /// `OLLAMA_HOST` (default `http://localhost:11434`)
+    pub fn from_env() -> Self {
+        let host = var("OLLAMA_HOST").unwrap_or_else(|| "http://localhost:11434".into());
+        let host = host.trim_end_matches('/');
+        Self {
+            host: if host.contains("://") {
+                host.to_string()
+            } else {
+                format!("http://{host}")
+            },
+        }
+    }
+}
+
+impl Provider for Ollama {
+    fn request(
+        &self,
+        http: &Client,
+        model: &str,
+        system: &str,
+        prompt: &str,
+        max_tokens: u32,
+    ) -> RequestBuilder {
+        http.post(format!("{}/api/chat", self.host)).json(&json!({
+            "model": model,
+            "stream": true,
+            "options": {"num_predict": max_tokens},
+            "messages": [
+                {"role": "system", "content": system},
+                {"role": "user", "content": prompt},
+            ],
+        }))
+    }
+
+    fn decoder(&self) -> Box<dyn Decoder> {
+        Box::new(Stream::default())
+    }
+}
+
+/// JSON lines as they arrive, folded into a [`Reply`]
+struct Stream {
+    frames: Frames,
+    reply: Reply,
+    done: bool,
+}
+
+impl Default for Stream {
+    fn default() -> Self {
+        Self {
+            frames: Frames::new(b"\n"),
+            reply: Reply::default(),
+            done: false,
+        }
+    }
+}
+
+impl Stream {
+    fn line(&mut self, line: &str) -> Result<(), Failure> {
+        let line: Value = serde_json::from_str(line)
+            .map_err(|e| Failure::retry(format!("Unreadable line: {e}")))?;
+        if let Some(error) = line["error"].as_str() {
+            return Err(Failure::fatal(error));
+        }
+        if let Some(text) = line["message"]["content"].as_str() {
+            self.reply.text.push_str(text);
+        }
+        if line["done"].as_bool() == Some(true) {
+            self.done = true;
+            self.reply.cut_off = line["done_reason"].as_str() == Some("length");
+            self.reply.usage.input_tokens = line["prompt_eval_count"].as_u64().unwrap_or(0);
+            self.reply.usage.output_tokens = line["eval_count"].as_u64().unwrap_or(0);
+        }
+        Ok(())
+    }
+}
+
+impl Decoder for Stream {
+    fn push(&mut self, chunk: &[u8]) -> Result<(), Failure> {
+        for line in self.frames.push(chunk) {
+            let line = line.trim();
+            if !line.is_empty() {
+                self.line(line)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<Reply, Failure> {
+        // The last line may come without a newline
+        let rest = String::from_utf8_lossy(&std::mem::take(&mut self.frames.buffer)).into_owned();
+        if !rest.trim().is_empty() {
+            self.line(rest.trim())?;
+        }
+        if !self.done {
+            return Err(Failure::retry("Reply ended early"));
+        }
+        Ok(self.reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::Usage;
+
+    mod stream {
+        use super::*;
+
+        #[test]
+        fn puts_the_reply_and_usage_together() {
+            let lines = "{\"message\":{\"role\":\"assistant\",\"content\":\"Bon\"},\"done\":false}\n\
+                {\"message\":{\"role\":\"assistant\",\"content\":\"jour\"},\"done\":false}\n\
+                {\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"done\":true,\"done_reason\":\"stop\",\"prompt_eval_count\":31,\"eval_count\":4}";
+            let mut stream = Box::new(Stream::default());
+            for chunk in lines.as_bytes().chunks(5) {
+                assert!(stream.push(chunk).is_ok());
+            }
+            let reply = stream.finish().unwrap();
+            assert_eq!(reply.text, "Bonjour");
+            assert!(!reply.cut_off);
+            assert_eq!(
+                reply.usage,
+                Usage {
+                    input_tokens: 31,
+                    output_tokens: 4,
+                    cost_usd: 0.0
+                }
+            );
+        }
+
+        #[test]
+        fn fails_on_errors_and_unfinished_replies() {
+            let mut stream = Box::new(Stream::default());
+            let failure = stream
+                .push(b"{\"error\":\"model \\\"llama9\\\" not found\"}\n")
+                .unwrap_err();
+            assert!(!failure.retry);
+
+            let mut stream = Box::new(Stream::default());
+            assert!(
+                stream
+                    .push(b"{\"message\":{\"content\":\"Bon\"},\"done\":false}\n")
+                    .is_ok()
+            );
+            assert!(stream.finish().unwrap_err().retry);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! OpenAI's chat completions API, or any server speaking it (OpenRouter,
+//! Groq, vLLM, llama.cpp, LM Studio). Usage comes in a last chunk, when the
+//! server supports `stream_options`; servers that don't are counted as free.
+
+use super::{Decoder, Failure, Frames, Provider, Reply, event_data, var};
+use reqwest::{Client, RequestBuilder};
+use serde_json::{Value, json};
+
+/// USD per million input and output tokens: unknown, so set them with the
+/// model's prices
+pub const PRICES: (f64, f64) = (0.0, 0.0);
+
+pub struct OpenAi {
+    api_key: Option<String>,
+    base_url: String,
+}
+
+impl OpenAi {
+    /// `OPENAI_BASE_URL` (default `https://api.openai.com/v1`), and
+    /// `OPENAI_API_KEY` for servers that need one
+    pub fn from_env() -> Self {
+        Self {
+            api_key: var("OPENAI_API_KEY"),
+            base_url: var("OPENAI_BASE_URL")
+                .unwrap_or_else(|| "https://api.openai.com/v1".into())
+                .trim_end_matches('/')
+                .to_string(),
+        }
+    }
+}
+
+impl Provider for OpenAi {
+    fn request(
+        &self,
+        http: &Client,
+        model: &str,
+        system: &str,
+        prompt: &str,
+        max_tokens: u32,
+    ) -> RequestBuilder {
+        let request = http
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&json!({
+                "model": model,
+                "max_tokens": max_tokens,
+                "stream": true,
+                "stream_options": {"include_usage": true},
+                "messages": [
+                    {"role": "system", "content": system},
+                    {"role": "user", "content": prompt},
+                ],
+            }));
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    fn decoder(&self) -> Box<dyn Decoder> {
+        Box::new(Stream::default())
+    }
+}
+
+/// Server-sent chunks as they arrive, folded into a [`Reply`]
+struct Stream {
+    frames: Frames,
+    reply: Reply,
+    finished: bool,
+}
+
+impl Default for Stream {
+    fn default() -> Self {
+        Self {
+            frames: Frames::new(b"\n\n"),
+            reply: Reply::default(),
+            finished: false,
+        }
+    }
+}
+
+impl Stream {
+    fn chunk(&mut self, data: &str) -> Result<(), Failure> {
+        if data == "[DONE]" {
+            self.finished = true;
+            return Ok(());
+        }
+        let chunk: Value = serde_json::from_str(data)
+            .map_err(|e| Failure::retry(format!("Unreadable chunk: {e}")))?;
+        if let Some(error) = chunk.get("error") {
+            let message = error["message"].as_str().unwrap_or("Stream error");
+            // Errors reported mid-stream are the server's, and passing
+            return Err(Failure::retry(message));
+        }
+        let choice = &chunk["choices"][0];
+        if let Some(text) = choice["delta"]["content"].as_str() {
+            self.reply.text.push_str(text);
+        }
+        if let Some(reason) = choice["finish_reason"].as_str() {
+            self.reply.cut_off = reason == "length";
+            self.finished = true;
+        }
+        // Cached prompt tokens are counted in prompt_tokens already
+        let usage = &chunk["usage"];
+        if let Some(input) = usage["prompt_tokens"].as_u64() {
+            self.reply.usage.input_tokens = input;
+        }
+        if let Some(output) = usage["completion_tokens"].as_u64() {
+            self.reply.usage.output_tokens = output;
+        }
+        Ok(())
+    }
+}
+
+impl Decoder for Stream {
+    fn push(&mut self, chunk: &[u8]) -> Result<(), Failure> {
+        for event in self.frames.push(chunk) {
+            let data = event_data(&event);
+            if !data.is_empty() {
+                self.chunk(&data)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<Reply, Failure> {
+        if !self.finished {
+            return Err(Failure::retry("Reply ended early"));
+        }
+        Ok(self.reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::Usage;
+
+    mod stream {
+        use super::*;
+
+        #[test]
+        fn puts_the_reply_and_usage_together() {
+            let chunks = "data: {\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\",\"content\":\"\"}}]}\n\n\
+                data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"{\\\"a\\\": \"}}]}\n\n\
+                data: {\"choices\":[{\"index\":0,\"delta\":{\"content\":\"1}\"},\"finish_reason\":null}]}\n\n\
+                data: {\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n\
+                data: {\"choices\":[],\"usage\":{\"prompt_tokens\":120,\"completion_tokens\":8,\"prompt_tokens_details\":{\"cached_tokens\":100}}}\n\n\
+                data: [DONE]\n\n";
+            let mut stream = Box::new(Stream::default());
+            for chunk in chunks.as_bytes().chunks(11) {
+                assert!(stream.push(chunk).is_ok());
+            }
+            let reply = stream.finish().unwrap();
+            assert_eq!(reply.text, "{\"a\": 1}");
+            assert!(!reply.cut_off);
+            assert_eq!(
+                reply.usage,
+                Usage {
+                    input_tokens: 120,
+                    output_tokens: 8,
+                    cost_usd: 0.0
+                }
+            );
+        }
+
+        #[test]
+        fn notices_cut_off_and_unfinished_replies() {
+            let mut stream = Box::new(Stream::default());
+            let cut_off =
+                b"data: {\"choices\":[{\"delta\":{\"content\":\"{\"},\"finish_reason\":\"length\"}]}\n\n";
+            assert!(stream.push(cut_off).is_ok());
+            assert!(stream.finish().unwrap().cut_off);
+
+            let mut stream = Box::new(Stream::default());
+            assert!(
+                stream
+                    .push(b"data: {\"choices\":[{\"delta\":{\"content\":\"{\"}}]}\n\n")
+                    .is_ok()
+            );
+            assert!(stream.finish().unwrap_err().retry);
+        }
+    }
+}
@@ -0,0 +1,700 @@
+//! The digest's batch side, moving out of `run.py` a stage at a time.
+//!
+//! `digest-pipeline fetch` fetches every enabled feed in `sources.toml` at
+//! once, records each fetch in `source_health` and `source_activity`, and
+//! leaves the articles published since the last digest in `fetched/` next to
+//! the database, where `run.py --skip-fetch` picks them up.
+//! `digest-pipeline curate` has a language model select and write up the
+//! stories in `fetched/`, and fetches the pictures of the narratives it
+//! writes into `images`, leaving `selections.json` for `run.py --skip-select`.
+//! `digest-pipeline render` renders a digest's stored narratives to its HTML,
+//! or with `--preview` to the preview slot instead of `digests`.
+//! `digest-pipeline backfill` makes the digests of days a run missed from the
+//! articles `fetch` archived.
+//! `digest-pipeline import-opml` adds a feed reader's subscriptions to
+//! `sources.toml`, and `digest-pipeline sources` checks and lists it.
+
+mod aggregators;
+mod backfill;
+mod breaker;
+mod budget;
+mod cluster;
+#[path = "../../css.rs"]
+mod css;
+mod curate;
+mod dedup;
+mod discover;
+mod extract;
+mod feeds;
+mod fetch;
+mod filters;
+mod hosts;
+mod imap;
+mod language;
+mod llm;
+mod newsletters;
+mod opml;
+mod pages;
+mod prompts;
+mod render;
+mod robots;
+mod scoring;
+mod sitemaps;
+mod social;
+#[path = "../../sources.rs"]
+mod sources;
+mod spam;
+mod store;
+mod thumbnails;
+mod youtube;
+
+use hosts::{Politeness, Scheduler};
+use robots::Robots;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long one feed request may take
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Days of digests whose headlines aren't selected again
+const PREVIOUS_HEADLINE_DAYS: u32 = 7;
+
+const USAGE: &str = "Usage: digest-pipeline [fetch]
+       digest-pipeline curate
+       digest-pipeline render [--date YYYY-MM-DD] [--email FILE] [--preview]
+       digest-pipeline backfill (--date YYYY-MM-DD | --from YYYY-MM-DD [--to YYYY-MM-DD]) [--replace]
+       digest-pipeline sources
+       digest-pipeline import-opml [--input FILE] [--bias BIAS] [--perspective NAME] [--dry-run]";
+
+#[tokio::main]
+async fn main() {
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+    if std::env::var("LOG_FORMAT").is_ok_and(|f| f.eq_ignore_ascii_case("json")) {
+        tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .json()
+            .flatten_event(true)
+            .init();
+    } else {
+        tracing_subscriber::fmt().with_env_filter(filter).init();
+    }
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        None | Some("fetch") => fetch_command().await,
+        Some("curate") => curate_command().await,
+        Some("render") => render_command(&args[1..]),
+        Some("backfill") => backfill_command(&args[1..]).await,
+        Some("import-opml") => import_opml_command(&args[1..]),
+        Some("sources") => sources_command(),
+        Some(_) => {
+            eprintln!("{USAGE}");
+            std::process::exit(2);
+        }
+    };
+    if let Err(e) = result {
+        tracing::error!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// The database at `DATABASE_PATH`, and the directory it's in
+fn open_database() -> Result<(rusqlite::Connection, PathBuf), String> {
+    let db_path = std::env::var("DATABASE_PATH").unwrap_or_else(|_| "data/digest.db".into());
+    let data_dir = Path::new(&db_path).parent().unwrap_or(Path::new("."));
+    let telemetry = match std::env::var("TELEMETRY_DB").ok().filter(|v| !v.is_empty()) {
+        Some(name) if name.contains('/') || name.starts_with('.') => {
+            return Err("TELEMETRY_DB must be a file name, like telemetry.db".into());
+        }
+        Some(name) => Some(data_dir.join(name).to_string_lossy().into_owned()),
+        None => None,
+    };
+    std::fs::create_dir_all(data_dir)
+        .map_err(|e| format!("Cannot create {}: {e}", data_dir.display()))?;
+    let key = database_key()?;
+    if key.is_some() && !cfg!(feature = "sqlcipher") {
+        return Err("DATABASE_KEY is set, but this build lacks the `sqlcipher` feature".into());
+    }
+    let conn = store::connect(&db_path, key.as_deref(), telemetry.as_deref())?;
+    Ok((conn, data_dir.to_path_buf()))
+}
+
+/// The client feeds, pages, and pictures are fetched with
+fn http_client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .user_agent(format!("Mozilla/5.0 (compatible; {})", robots::AGENT))
+        .build()
+        .map_err(|e| format!("Cannot build HTTP client: {e}"))
+}
+
+fn enabled_sources() -> Result<Vec<sources::Source>, String> {
+    Ok(sources::load(&sources_path())?
+        .into_iter()
+        .filter(|s| s.enabled)
+        .collect())
+}
+
+async fn fetch_command() -> Result<(), String> {
+    // Marks the stage for runs the server starts
+    tracing::info!("Stage: fetch");
+    let sources = enabled_sources()?;
+    let (mut conn, data_dir) = open_database()?;
+    let last_run = store::last_run(&conn)?;
+
+    tracing::info!("Fetching {} RSS feeds...", sources.len());
+    let client = http_client()?;
+    let validators = store::validators(&conn, last_run.as_deref())?;
+    let full_text = std::env::var("FULL_TEXT").is_ok_and(|v| v == "1" || v == "true");
+    let scheduler = Arc::new(Scheduler::new(Politeness::from_env()));
+    let breakers = Arc::new(breaker::Breakers::new(breaker::Limits::from_env()));
+    let retry = fetch::RetryPolicy::from_env();
+    let discovered = store::discovered_feeds(&conn, &sources)?;
+    let results = fetch::fetch_all(
+        &client,
+        &sources,
+        &validators,
+        &discovered,
+        &scheduler,
+        &breakers,
+        retry,
+    )
+    .await;
+
+    let fingerprints: Vec<(&str, String)> = results
+        .iter()
+        .filter_map(|r| Some((r.source_id.as_str(), fetch::fingerprint(&r.articles)?)))
+        .collect();
+    store::record_activity(&mut conn, &fingerprints)?;
+
+    let mut kept: Vec<(&str, Vec<feeds::Article>)> = results
+        .iter()
+        .map(|r| {
+            let articles = store::newer_than(&r.articles, last_run.as_deref());
+            (
+                r.source_id.as_str(),
+                articles.into_iter().cloned().collect(),
+            )
+        })
+        .collect();
+    let filtered = filters::apply(&mut kept, &sources, &filters::load(&sources_path())?)?;
+    dedup::resolve_urls(&client, &scheduler, &mut kept).await;
+    let spam = spam::Screen::from_env()?.apply(&mut kept);
+    let weights: HashMap<&str, f64> = sources.iter().map(|s| (s.id.as_str(), s.weight)).collect();
+    let duplicates = dedup::dedup(&mut kept, &weights);
+    let mut notes = if full_text {
+        let robots = Arc::new(Robots::new(store::robots_txt(&conn)?));
+        let cache_days = pages::cache_days();
+        let mut cache = pages::Cache::new(match cache_days {
+            0 => HashMap::new(),
+            days => store::cached_pages(&conn, days)?,
+        });
+        let notes = pages::fill_content(
+            &client, &scheduler, &breakers, &robots, &mut cache, &sources, &mut kept,
+        )
+        .await;
+        store::record_robots_txt(&mut conn, &robots.fetched())?;
+        if cache_days > 0 {
+            store::record_pages(&mut conn, cache.fetched(), cache_days)?;
+        }
+        notes
+    } else {
+        HashMap::new()
+    };
+    for source in &sources {
+        if let Some(note) = breakers.note(&source.id) {
+            tracing::warn!("[{}] {}", source.id, note);
+            let existing = notes.entry(source.id.clone()).or_default();
+            *existing = match existing.as_str() {
+                "" => note,
+                other => format!("{note}; {other}"),
+            };
+        }
+    }
+    for (source_id, count) in &spam {
+        let note = format!("dropped {count} likely spam article(s)");
+        let existing = notes.entry(source_id.clone()).or_default();
+        *existing = match existing.as_str() {
+            "" => note,
+            other => format!("{other}; {note}"),
+        };
+    }
+    let stories = cluster::assign_stories(&mut kept);
+    store::write_fetched(&data_dir.join("fetched"), &kept)?;
+    let archive_days = backfill::archive_days();
+    if archive_days > 0 {
+        store::archive_articles(&mut conn, &kept, archive_days)?;
+    }
+    let kept_count = |source_id: &str| {
+        kept.iter()
+            .find(|(id, _)| *id == source_id)
+            .map_or(0, |(_, articles)| articles.len())
+    };
+    store::record_health(&mut conn, &results, kept_count, &filtered, &notes)?;
+    store::record_validators(&mut conn, &results)?;
+    store::record_discovered(&mut conn, &sources, &results)?;
+
+    if let Some(since) = &last_run {
+        tracing::info!("Kept articles published after {} UTC", since);
+    }
+    let filtered_count: usize = filtered.values().sum();
+    if filtered_count > 0 {
+        tracing::info!("Filtered out {} articles", filtered_count);
+    }
+    let spam_count: usize = spam.values().sum();
+    if spam_count > 0 {
+        tracing::info!("Dropped {} articles that looked like spam", spam_count);
+    }
+    if duplicates > 0 {
+        tracing::info!("Dropped {} articles other sources also carried", duplicates);
+    }
+    tracing::info!("Found {} stories covered by more than one source", stories);
+    for (result, (_, articles)) in results.iter().zip(&kept) {
+        if result.not_modified {
+            tracing::info!("[{}] not modified", result.source_id);
+        } else if !result.articles.is_empty() {
+            tracing::info!(
+                "[{}] {}/{}",
+                result.source_id,
+                articles.len(),
+                result.articles.len()
+            );
+        }
+    }
+    let failed: Vec<&str> = results
+        .iter()
+        .filter(|r| r.error.is_some())
+        .map(|r| r.source_id.as_str())
+        .collect();
+    tracing::info!(
+        "Fetched {}/{} articles from {}/{} sources ({} not modified)",
+        kept.iter().map(|(_, a)| a.len()).sum::<usize>(),
+        results.iter().map(|r| r.articles.len()).sum::<usize>(),
+        sources.len() - failed.len(),
+        sources.len(),
+        results.iter().filter(|r| r.not_modified).count()
+    );
+    if !failed.is_empty() {
+        tracing::warn!("Failed sources this run: {}", failed.join(", "));
+    }
+    Ok(())
+}
+
+/// Have the configured models select and write up what was fetched, for
+/// `run.py --skip-select`
+async fn curate_command() -> Result<(), String> {
+    tracing::info!("Stage: curate");
+    let sources = enabled_sources()?;
+    let (mut conn, data_dir) = open_database()?;
+    let prompts = prompts::Prompts::from_env()?;
+    let models = curate::Models {
+        main: Arc::new(llm::Model::from_env()?),
+        cheap: llm::Model::cheap_from_env()?.map(Arc::new),
+    };
+    let budget = Arc::new(budget::Budget::from_env());
+    let rules = scoring::Rules::from_env()?;
+    let clusters = curate::clusters(&data_dir.join("fetched"), &sources)?;
+    if clusters.is_empty() {
+        return Err("Nothing fetched to curate (run digest-pipeline fetch first)".into());
+    }
+    let previous = store::previous_headlines(&conn, "now", PREVIOUS_HEADLINE_DAYS)?;
+    let (selections, usage) = select(
+        &mut conn,
+        &models,
+        rules.as_ref(),
+        &prompts,
+        budget.clone(),
+        clusters,
+        &previous,
+    )
+    .await?;
+    let limits = budget.limits();
+
+    let dir = data_dir.join("claude_input");
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Cannot create {}: {e}", dir.display()))?;
+    let write = |name: &str, json: serde_json::Result<String>| {
+        let path = dir.join(name);
+        let json = json.map_err(|e| format!("Cannot encode {name}: {e}"))?;
+        std::fs::write(&path, json).map_err(|e| format!("Cannot write {}: {e}", path.display()))
+    };
+    write("selections.json", serde_json::to_string_pretty(&selections))?;
+    // run.py records the usage, the prompts behind it, and the budget it
+    // had, with the run
+    let mut run = serde_json::to_value(usage).map_err(|e| format!("Cannot encode usage: {e}"))?;
+    run["prompt_version"] = prompts.version.clone().into();
+    run["budget_usd"] = limits.budget_usd.into();
+    run["budget_tokens"] = limits.budget_tokens.into();
+    write("usage.json", serde_json::to_string_pretty(&run))?;
+    tracing::info!(
+        "Selected {} must_know, {} should_know, {} signals",
+        selections.must_know.len(),
+        selections.should_know.len(),
+        selections.signals.values().map(Vec::len).sum::<usize>()
+    );
+    tracing::info!(
+        "LLM usage: {} in / {} out tokens, ~${:.4}",
+        usage.input_tokens,
+        usage.output_tokens,
+        usage.cost_usd
+    );
+    Ok(())
+}
+
+/// Screen, translate, and curate `clusters` within `budget`, and store the
+/// pictures of the narratives selected
+async fn select(
+    conn: &mut rusqlite::Connection,
+    models: &curate::Models,
+    rules: Option<&scoring::Rules>,
+    prompts: &prompts::Prompts,
+    budget: Arc<budget::Budget>,
+    mut clusters: Vec<curate::Cluster<'_>>,
+    previous: &[String],
+) -> Result<(curate::Selections, llm::Usage), String> {
+    let mut screening = llm::Usage::default();
+    if spam::checking() {
+        let dropped;
+        (dropped, screening) =
+            spam::check(models.writer(), &budget, &prompts.system, &mut clusters).await;
+        tracing::info!(
+            "Dropped {} articles {} judged spam",
+            dropped,
+            models.writer().name()
+        );
+        if clusters.is_empty() {
+            return Err("Nothing left to curate after the spam check".into());
+        }
+    }
+    let foreign = language::detect_all(&mut clusters);
+    let mut translation = llm::Usage::default();
+    if foreign > 0 && language::translating() {
+        let translated;
+        (translated, translation) =
+            language::translate(models.writer(), &budget, &prompts.system, &mut clusters).await;
+        tracing::info!(
+            "Translated {} of {} articles not in English with {}",
+            translated,
+            foreign,
+            models.writer().name()
+        );
+    } else if foreign > 0 {
+        tracing::info!(
+            "{} articles aren't in English (set TRANSLATE to translate them)",
+            foreign
+        );
+    }
+    tracing::info!(
+        "Curating {} stories from {} articles with {}{} (prompts version {}{})...",
+        clusters.len(),
+        clusters.iter().map(|c| c.articles.len()).sum::<usize>(),
+        models.main.name(),
+        models
+            .cheap
+            .as_ref()
+            .map(|cheap| format!(", and {} for lower tiers", cheap.name()))
+            .unwrap_or_default(),
+        prompts.version,
+        if rules.is_some() {
+            ", tiered by SCORING_FILE"
+        } else {
+            ""
+        }
+    );
+    let plan = curate::plan(models, &budget, prompts, &clusters, previous)?;
+    let limits = budget.limits();
+    if budget.is_limited() {
+        tracing::info!(
+            "Budget: {}, {} tokens",
+            limits
+                .budget_usd
+                .map_or("no cost limit".into(), |usd| format!("${usd:.2}")),
+            limits
+                .budget_tokens
+                .map_or("unlimited".into(), |tokens| tokens.to_string())
+        );
+    }
+    if plan.detail != curate::Detail::Full || plan.stories < clusters.len() {
+        tracing::warn!(
+            "Over budget in full: curating {} of {} stories briefly",
+            plan.stories,
+            clusters.len()
+        );
+    }
+    let (mut selections, mut usage) = curate::curate(
+        models,
+        budget,
+        rules,
+        prompts,
+        &clusters[..plan.stories],
+        previous,
+        plan.detail,
+    )
+    .await?;
+    usage += translation;
+    usage += screening;
+
+    let client = http_client()?;
+    let scheduler = Arc::new(Scheduler::new(Politeness::from_env()));
+    let robots = Arc::new(Robots::new(store::robots_txt(conn)?));
+    let pictures = thumbnails::fetch_all(
+        &client,
+        &scheduler,
+        &robots,
+        &clusters[..plan.stories],
+        &mut selections,
+    )
+    .await;
+    store::save_images(conn, &pictures)?;
+    store::record_robots_txt(conn, &robots.fetched())?;
+    tracing::info!(
+        "Stored pictures for {} of {} must_know and should_know narratives",
+        pictures.len(),
+        selections.must_know.len() + selections.should_know.len()
+    );
+    Ok((selections, usage))
+}
+
+/// Render a digest (the latest, by default) from the narratives `run.py`
+/// stored, into `digests`, and its email variant to a file if asked
+fn render_command(args: &[String]) -> Result<(), String> {
+    tracing::info!("Stage: render");
+    let flag = |name: &str| {
+        args.iter()
+            .position(|a| a == name)
+            .and_then(|i| args.get(i + 1))
+    };
+    let (conn, _) = open_database()?;
+    let date = match flag("--date") {
+        Some(date) => date.clone(),
+        None => store::latest_narratives_date(&conn)?
+            .ok_or("No narratives to render (run run.py first)")?,
+    };
+    let digest = store::digest(&conn, &date)?;
+    if digest.narratives.is_empty() {
+        return Err(format!("No narratives for {date}"));
+    }
+    // A re-rendered digest keeps the time it was first made
+    let made = store::digest_created_at(&conn, &date)?
+        .and_then(|at| chrono::NaiveDateTime::parse_from_str(&at, "%Y-%m-%d %H:%M:%S").ok())
+        .map_or_else(chrono::Utc::now, |at| at.and_utc());
+    let site = render::Site::from_env();
+
+    let html = render::render(&digest, &site, &date, made, render::Variant::Web)?;
+    // DRY_RUN is how digest-server asks for one (POST /admin/run?dry_run=true)
+    let preview = args.iter().any(|a| a == "--preview")
+        || std::env::var("DRY_RUN").is_ok_and(|v| v == "1" || v == "true");
+    if preview {
+        store::save_preview(&conn, &date, &html)?;
+    } else {
+        store::save_digest(&conn, &date, &html)?;
+    }
+    tracing::info!(
+        "Rendered the digest of {} ({} narratives) into {}",
+        date,
+        digest.narratives.len(),
+        if preview {
+            "the preview slot"
+        } else {
+            "digests"
+        }
+    );
+    if let Some(path) = flag("--email") {
+        let email = render::render(&digest, &site, &date, made, render::Variant::Email)?;
+        std::fs::write(path, email).map_err(|e| format!("Cannot write {path}: {e}"))?;
+        tracing::info!("Wrote its email variant to {}", path);
+    }
+    Ok(())
+}
+
+/// Make the digests of past days from the articles archived for them, for
+/// days a run missed (or, with `--replace`, any day)
+async fn backfill_command(args: &[String]) -> Result<(), String> {
+    tracing::info!("Stage: backfill");
+    let flag = |name: &str| {
+        args.iter()
+            .position(|a| a == name)
+            .and_then(|i| args.get(i + 1))
+            .map(String::as_str)
+    };
+    let dates = backfill::dates(
+        flag("--date"),
+        flag("--from"),
+        flag("--to"),
+        chrono::Utc::now().date_naive(),
+    )?;
+    let replace = args.iter().any(|a| a == "--replace");
+    let sources = enabled_sources()?;
+    let (mut conn, data_dir) = open_database()?;
+    let prompts = prompts::Prompts::from_env()?;
+    let models = curate::Models {
+        main: Arc::new(llm::Model::from_env()?),
+        cheap: llm::Model::cheap_from_env()?.map(Arc::new),
+    };
+    let rules = scoring::Rules::from_env()?;
+    let site = render::Site::from_env();
+    let dir = data_dir.join("backfill");
+    // Headlines of the days already backfilled, newest first, so a range
+    // doesn't repeat a story day after day
+    let mut selected: Vec<String> = Vec::new();
+    let mut made = 0;
+    for date in dates {
+        let day = date.format("%Y-%m-%d").to_string();
+        if !replace && store::digest_created_at(&conn, &day)?.is_some() {
+            tracing::info!("{} has a digest (--replace to make it again)", day);
+            continue;
+        }
+        let published = (date - chrono::Days::new(1)).format("%Y-%m-%d").to_string();
+        let articles = store::archived_articles(&conn, &published)?;
+        let count: usize = articles.values().map(Vec::len).sum();
+        if count == 0 {
+            tracing::warn!("No articles archived from {} for {}", published, day);
+            continue;
+        }
+        backfill::write(&dir, &articles)?;
+        let clusters = curate::clusters(&dir, &sources)?;
+        tracing::info!(
+            "Backfilling {} from {} articles in {} stories...",
+            day,
+            count,
+            clusters.len()
+        );
+        let mut previous = selected.clone();
+        previous.extend(store::previous_headlines(
+            &conn,
+            &day,
+            PREVIOUS_HEADLINE_DAYS,
+        )?);
+        let (selections, usage) = select(
+            &mut conn,
+            &models,
+            rules.as_ref(),
+            &prompts,
+            Arc::new(budget::Budget::from_env()),
+            clusters,
+            &previous,
+        )
+        .await?;
+        store::save_backfill(&mut conn, &day, &selections, count)?;
+        let digest = store::digest(&conn, &day)?;
+        let made_at = date.and_time(chrono::NaiveTime::MIN).and_utc();
+        let html = render::render(&digest, &site, &day, made_at, render::Variant::Web)?;
+        store::save_digest(&conn, &day, &html)?;
+        let headlines = selections
+            .must_know
+            .iter()
+            .chain(&selections.should_know)
+            .map(|n| n.headline.clone());
+        selected.splice(0..0, headlines);
+        made += 1;
+        tracing::info!(
+            "Backfilled the digest of {} ({} narratives, {} in / {} out tokens, ~${:.4})",
+            day,
+            digest.narratives.len(),
+            usage.input_tokens,
+            usage.output_tokens,
+            usage.cost_usd
+        );
+    }
+    tracing::info!("Backfilled {} digests", made);
+    Ok(())
+}
+
+/// Merge feeds from an OPML export (a file, or stdin) into the sources file
+fn import_opml_command(args: &[String]) -> Result<(), String> {
+    let flag = |name: &str| {
+        args.iter()
+            .position(|a| a == name)
+            .and_then(|i| args.get(i + 1))
+    };
+    let xml = match flag("--input") {
+        Some(file) => {
+            std::fs::read_to_string(file).map_err(|e| format!("Cannot read {file}: {e}"))?
+        }
+        None => std::io::read_to_string(std::io::stdin())
+            .map_err(|e| format!("Cannot read OPML from stdin: {e}"))?,
+    };
+    let defaults = opml::Defaults {
+        bias: flag("--bias").cloned().unwrap_or_else(|| "center".into()),
+        perspective: flag("--perspective").cloned(),
+    };
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+
+    let path = sources_path();
+    let mut sources = match std::fs::exists(&path) {
+        Ok(true) => sources::load(&path)?,
+        _ => Vec::new(),
+    };
+    let configured = sources.len();
+    let merged = opml::merge(&mut sources, opml::parse(&xml)?, &defaults);
+    for url in &merged.invalid {
+        eprintln!("Skipped {url}: not an http(s) URL");
+    }
+    println!(
+        "Added {}, already configured {}, skipped {}",
+        merged.added.len(),
+        merged.existing,
+        merged.invalid.len()
+    );
+    for id in &merged.added {
+        println!("  {id}");
+    }
+    if dry_run || merged.added.is_empty() {
+        return Ok(());
+    }
+    opml::append(&path, &sources[configured..])?;
+    println!("Wrote {path}");
+    Ok(())
+}
+
+/// Check the sources file and list what it configures
+fn sources_command() -> Result<(), String> {
+    let path = sources_path();
+    let sources = sources::load(&path)?;
+    let enabled = sources.iter().filter(|s| s.enabled).count();
+    println!("{path}: {} sources, {enabled} enabled", sources.len());
+    for s in &sources {
+        let mut notes = vec![format!("{}, {}", s.bias, s.perspective)];
+        if s.weight != 1.0 {
+            notes.push(format!("weight {}", s.weight));
+        }
+        if !s.topics.is_empty() {
+            notes.push(s.topics.join("/"));
+        }
+        if let Some(delay) = s.delay_secs {
+            notes.push(format!("{delay}s between requests"));
+        }
+        if !s.enabled {
+            notes.push("disabled".into());
+        }
+        println!("  {} ({}): {} [{}]", s.id, s.name, s.url, notes.join("; "));
+    }
+    Ok(())
+}
+
+/// `SOURCES_FILE`, or `sources.toml` in the working directory
+fn sources_path() -> String {
+    std::env::var("SOURCES_FILE").unwrap_or_else(|_| "sources.toml".into())
+}
+
+/// SQLCipher key from `DATABASE_KEY`, or the first line of `DATABASE_KEY_FILE`,
+/// as digest-server reads it
+fn database_key() -> Result<Option<String>, String> {
+    if let Some(key) = std::env::var("DATABASE_KEY").ok().filter(|k| !k.is_empty()) {
+        return Ok(Some(key));
+    }
+    let Some(file) = std::env::var("DATABASE_KEY_FILE")
+        .ok()
+        .filter(|f| !f.is_empty())
+    else {
+        return Ok(None);
+    };
+    let contents = std::fs::read_to_string(&file)
+        .map_err(|e| format!("Cannot read DATABASE_KEY_FILE {file}: {e}"))?;
+    match contents.lines().next().map(str::trim_end) {
+        Some(key) if !key.is_empty() => Ok(Some(key.to_string())),
+        _ => Err(format!("DATABASE_KEY_FILE {file} is empty")),
+    }
+}
@@ -0,0 +1,420 @@
+//! Email newsletters as sources. A source whose URL is
+//! `imaps://host[:port]/Mailbox?from=address` reads the newsletters in a
+//! mailbox set aside for them, as `IMAP_USERNAME` with `IMAP_PASSWORD`: the
+//! messages of the past week from `address` (or from anyone, without
+//! `from`), newest first. Each becomes an article titled by its subject and
+//! linked to its web version (the "view in browser" link newsletters carry),
+//! with its text in place of a page's. Messages without a web version are
+//! passed over, as a digest would have nowhere to link them.
+
+use crate::extract;
+use crate::feeds::{Article, MAX_SUMMARY_CHARS};
+use crate::imap;
+use crate::pages::{MAX_CONTENT_CHARS, plain_text};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use reqwest::Url;
+use scraper::{Html, Selector};
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// How far back messages are read
+const LOOKBACK_DAYS: i64 = 7;
+
+/// Most messages read from a mailbox per fetch
+const MAX_MESSAGES: usize = 30;
+
+/// Longest a mailbox is given to answer
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Nesting deeper than this isn't a newsletter
+const MAX_DEPTH: usize = 8;
+
+/// What a link to a newsletter's web version says
+static WEB_VERSION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?i)\b(view|read|open|see)\b.{0,30}\b(online|browser|on the web|web ?version|on (our |the )?(site|website))\b",
+    )
+    .expect("valid regex")
+});
+
+/// An RFC 2047 encoded word, as headers carry text that isn't ASCII
+static ENCODED_WORD: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"=\?([^?\s]+)\?([BbQq])\?([^?\s]*)\?=").expect("valid regex"));
+
+static LINK: LazyLock<Selector> =
+    LazyLock::new(|| Selector::parse("a[href]").expect("valid selector"));
+
+/// Base64 as mail writes it, padding or not
+const BASE64: base64::engine::GeneralPurpose = base64::engine::GeneralPurpose::new(
+    &base64::alphabet::STANDARD,
+    base64::engine::GeneralPurposeConfig::new()
+        .with_decode_padding_mode(base64::engine::DecodePaddingMode::Indifferent)
+        .with_decode_allow_trailing_bits(true),
+);
+
+/// Whether `url` names a mailbox rather than a feed
+pub fn is_mailbox(url: &str) -> bool {
+    url.starts_with("imaps://")
+}
+
+/// The newsletters in the mailbox at `url`, dated no later than `now`
+pub async fn fetch(url: &str, now: DateTime<Utc>) -> Result<Vec<Article>, String> {
+    let url = Url::parse(url).map_err(|e| format!("Invalid mailbox URL: {e}"))?;
+    let host = url.host_str().ok_or("Mailbox URL without a host")?;
+    let mailbox = percent_decoded(url.path().trim_start_matches('/'));
+    let mailbox = if mailbox.is_empty() {
+        "INBOX".into()
+    } else {
+        mailbox
+    };
+    let from = url
+        .query_pairs()
+        .find(|(key, _)| key == "from")
+        .map(|(_, from)| from.into_owned());
+    let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+    let (Some(username), Some(password)) = (var("IMAP_USERNAME"), var("IMAP_PASSWORD")) else {
+        return Err("IMAP_USERNAME and IMAP_PASSWORD are needed to read newsletters".into());
+    };
+    let since = (now - chrono::Duration::days(LOOKBACK_DAYS)).format("%-d-%b-%Y");
+    let criteria = match &from {
+        Some(from) => format!("SINCE {since} FROM \"{}\"", from.replace(['"', '\\'], "")),
+        None => format!("SINCE {since}"),
+    };
+    let read = async {
+        let mut session =
+            imap::connect(host, url.port().unwrap_or(993), &username, &password).await?;
+        session.examine(&mailbox).await?;
+        let uids = session.search(&criteria).await?;
+        let newest = &uids[uids.len().saturating_sub(MAX_MESSAGES)..];
+        let messages = session.fetch(newest).await?;
+        // The messages are in hand either way
+        if let Err(e) = session.logout().await {
+            tracing::debug!("Logging out of {}: {}", host, e);
+        }
+        Ok::<_, String>(messages)
+    };
+    let messages = tokio::time::timeout(TIMEOUT, read)
+        .await
+        .map_err(|_| "Timed out".to_string())??;
+    let mut articles: Vec<Article> = messages
+        .iter()
+        .filter_map(|message| {
+            let article = article(message, now);
+            if article.is_none() {
+                tracing::debug!("Passed over a newsletter without a web version");
+            }
+            article
+        })
+        .collect();
+    articles.reverse();
+    Ok(articles)
+}
+
+/// A newsletter as an article, if it links to its web version
+fn article(message: &[u8], now: DateTime<Utc>) -> Option<Article> {
+    let (headers, _) = split(message);
+    let title = decode_words(header(&headers, "subject")?);
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    if title.is_empty() {
+        return None;
+    }
+    let (mut html, mut plain) = (None, None);
+    texts(message, 0, &mut html, &mut plain);
+    let url = web_version(html.as_deref()?)?;
+    let text = html
+        .as_deref()
+        .and_then(extract::article_text)
+        .or(plain)
+        .or_else(|| html.as_deref().map(plain_text))
+        .unwrap_or_default();
+    let published = header(&headers, "date")
+        .and_then(|date| DateTime::parse_from_rfc2822(date.trim()).ok())
+        .map(|date| date.with_timezone(&Utc).min(now));
+    let summary: String = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(MAX_SUMMARY_CHARS)
+        .collect();
+    Some(Article {
+        title,
+        url,
+        published,
+        summary,
+        content: Some(text.chars().take(MAX_CONTENT_CHARS).collect()),
+        story: None,
+        image: None,
+        points: None,
+        comments: None,
+        keywords: Vec::new(),
+    })
+}
+
+/// Where the newsletter in `html` can be read on the web
+fn web_version(html: &str) -> Option<String> {
+    Html::parse_document(html)
+        .select(&LINK)
+        .filter(|a| WEB_VERSION.is_match(&a.text().collect::<String>()))
+        .filter_map(|a| Url::parse(a.value().attr("href")?.trim()).ok())
+        .find(|url| matches!(url.scheme(), "http" | "https"))
+        .map(String::from)
+}
+
+/// A message's or part's headers, unfolded and with lowercase names, and
+/// its body
+fn split(message: &[u8]) -> (Vec<(String, String)>, &[u8]) {
+    let (head, body) = match message.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(at) => (&message[..at], &message[at + 4..]),
+        None => match message.windows(2).position(|w| w == b"\n\n") {
+            Some(at) => (&message[..at], &message[at + 2..]),
+            None => (message, &[][..]),
+        },
+    };
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in String::from_utf8_lossy(head).lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+    (headers, body)
+}
+
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// The `name` parameter of a header like `Content-Type`
+fn parameter(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|parameter| {
+        let (key, value) = parameter.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| value.trim().trim_matches('"').to_string())
+    })
+}
+
+/// The first HTML and plain text in a message or part, decoded
+fn texts(part: &[u8], depth: usize, html: &mut Option<String>, plain: &mut Option<String>) {
+    if depth > MAX_DEPTH {
+        return;
+    }
+    let (headers, body) = split(part);
+    let content_type = header(&headers, "content-type").unwrap_or("text/plain");
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    if header(&headers, "content-disposition").is_some_and(|d| d.starts_with("attachment")) {
+        return;
+    }
+    if mime.starts_with("multipart/") {
+        let Some(boundary) = parameter(content_type, "boundary") else {
+            return;
+        };
+        for part in parts(body, &boundary) {
+            texts(part, depth + 1, html, plain);
+        }
+        return;
+    }
+    let slot = match mime.as_str() {
+        "text/html" => html,
+        "text/plain" => plain,
+        _ => return,
+    };
+    if slot.is_some() {
+        return;
+    }
+    let encoding = header(&headers, "content-transfer-encoding")
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let decoded = match encoding.trim() {
+        "base64" => {
+            let data: Vec<u8> = body
+                .iter()
+                .copied()
+                .filter(|b| !b.is_ascii_whitespace())
+                .collect();
+            BASE64.decode(data).unwrap_or_default()
+        }
+        "quoted-printable" => {
+            quoted_printable::decode(body, quoted_printable::ParseMode::Robust).unwrap_or_default()
+        }
+        _ => body.to_vec(),
+    };
+    *slot = Some(decode(
+        &decoded,
+        parameter(content_type, "charset").as_deref(),
+    ));
+}
+
+/// The parts of a multipart body
+fn parts<'a>(body: &'a [u8], boundary: &str) -> Vec<&'a [u8]> {
+    let delimiter = format!("--{boundary}");
+    let delimiter = delimiter.as_bytes();
+    // Where each delimiter line starts, and the line after it
+    let mut lines = Vec::new();
+    let mut at = 0;
+    for line in body.split_inclusive(|&b| b == b'\n') {
+        if line.starts_with(delimiter) {
+            lines.push((
+                at,
+                at + line.len(),
+                line[delimiter.len()..].starts_with(b"--"),
+            ));
+        }
+        at += line.len();
+    }
+    lines
+        .windows(2)
+        .take_while(|pair| !pair[0].2)
+        .map(|pair| {
+            let part = &body[pair[0].1..pair[1].0];
+            // The line break before a delimiter is the delimiter's
+            let part = part.strip_suffix(b"\n").unwrap_or(part);
+            part.strip_suffix(b"\r").unwrap_or(part)
+        })
+        .collect()
+}
+
+/// `bytes` in `charset`, UTF-8 when it's unknown
+fn decode(bytes: &[u8], charset: Option<&str>) -> String {
+    let encoding = charset
+        .and_then(|c| encoding_rs::Encoding::for_label(c.as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    encoding.decode(bytes).0.into_owned()
+}
+
+/// A header's text, its encoded words decoded
+fn decode_words(value: &str) -> String {
+    let mut text = String::new();
+    let mut last = 0;
+    for word in ENCODED_WORD.captures_iter(value) {
+        let whole = word.get(0).expect("a match");
+        let between = &value[last..whole.start()];
+        // Space between encoded words only separates them
+        if last == 0 || !between.trim().is_empty() {
+            text.push_str(between);
+        }
+        let data = &word[3];
+        let bytes = if word[2].eq_ignore_ascii_case("b") {
+            BASE64.decode(data).unwrap_or_default()
+        } else {
+            quoted_printable::decode(data.replace('_', " "), quoted_printable::ParseMode::Robust)
+                .unwrap_or_default()
+        };
+        text.push_str(&decode(&bytes, Some(&word[1])));
+        last = whole.end();
+    }
+    text.push_str(&value[last..]);
+    text
+}
+
+/// `s` with its %XX escapes decoded
+fn percent_decoded(s: &str) -> String {
+    let mut bytes = Vec::new();
+    let mut rest = s.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let escaped = (b == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok());
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &str = "From: Chips Weekly <news@chips.example>\r\n\
+        Subject: =?utf-8?Q?Export_rules=2C_explained?= =?utf-8?B?IOKAlCBpc3N1ZSAx?=\r\n\
+        Date: Wed, 15 Jan 2025 10:30:00 +0000\r\n\
+        MIME-Version: 1.0\r\n\
+        Content-Type: multipart/alternative;\r\n\tboundary=\"b1\"\r\n\
+        \r\n\
+        --b1\r\n\
+        Content-Type: text/plain; charset=utf-8\r\n\
+        \r\n\
+        The new rules café owners need.\r\n\
+        --b1\r\n\
+        Content-Type: text/html; charset=\"iso-8859-1\"\r\n\
+        Content-Transfer-Encoding: quoted-printable\r\n\
+        \r\n\
+        <html><body><a href=3D\"https://chips.example/p/1?utm_source=3Demail\">View =\r\n\
+        this email in your browser</a><p>The new rules caf=E9 owners need.</p></body></html>\r\n\
+        --b1--\r\n";
+
+    mod article {
+        use super::*;
+
+        #[test]
+        fn reads_a_newsletter() {
+            let now = "2025-01-16T00:00:00Z".parse().unwrap();
+            let article = article(MESSAGE.as_bytes(), now).unwrap();
+            assert_eq!(article.title, "Export rules, explained — issue 1");
+            assert_eq!(article.url, "https://chips.example/p/1?utm_source=email");
+            assert_eq!(
+                article.published,
+                Some("2025-01-15T10:30:00Z".parse().unwrap())
+            );
+            assert!(article.summary.contains("The new rules café owners need."));
+        }
+
+        #[test]
+        fn passes_over_newsletters_without_a_web_version() {
+            let now = Utc::now();
+            let message = MESSAGE.replace("View =\r\nthis email in your browser", "Unsubscribe");
+            assert_eq!(article(message.as_bytes(), now), None);
+        }
+    }
+
+    mod parts {
+        use super::*;
+
+        #[test]
+        fn splits_on_the_boundary() {
+            let body = b"preamble\r\n--x\r\nA\r\n--x\r\n\r\nB\r\n--x--\r\nepilogue";
+            assert_eq!(parts(body, "x"), [&b"A"[..], &b"\r\nB"[..]]);
+        }
+    }
+
+    mod decode_words {
+        use super::*;
+
+        #[test]
+        fn decodes_base64_and_q_words() {
+            assert_eq!(
+                decode_words("Re: =?ISO-8859-1?Q?Caf=E9?= news"),
+                "Re: Café news"
+            );
+            assert_eq!(
+                decode_words("=?utf-8?B?w7xiZXI=?= =?utf-8?Q?_alles?="),
+                "über alles"
+            );
+            assert_eq!(decode_words("Plain"), "Plain");
+        }
+    }
+}
@@ -0,0 +1,342 @@
+//! OPML import: feed subscriptions exported from a feed reader, merged into
+//! the sources file.
+//!
+//! Feeds already in the file (by URL) are left as they are. New ones get an id
+//! from their title, the default bias, and their OPML folder as perspective
+//! unless one is given, and are appended to the file as `[[source]]` tables.
+
+use crate::sources::{Filters, Source};
+use quick_xml::XmlVersion;
+use quick_xml::events::{BytesStart, Event};
+use std::collections::HashSet;
+
+/// A feed outline
+#[derive(Debug, PartialEq)]
+pub struct Outline {
+    pub name: String,
+    pub url: String,
+    /// The enclosing folder, if any
+    pub folder: Option<String>,
+}
+
+/// Values for what OPML doesn't say
+pub struct Defaults {
+    pub bias: String,
+    /// Overrides the folder; "general" without either
+    pub perspective: Option<String>,
+}
+
+/// What an import did
+#[derive(Debug, Default, PartialEq)]
+pub struct Merged {
+    /// Ids of the sources added
+    pub added: Vec<String>,
+    /// Feeds already configured
+    pub existing: usize,
+    /// URLs skipped for not being http(s)
+    pub invalid: Vec<String>,
+}
+
+/// Feed outlines (those with an `xmlUrl`), in document order
+pub fn parse(xml: &str) -> Result<Vec<Outline>, String> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut outlines = Vec::new();
+    // Folder names of the open outlines; `None` for feeds with children
+    let mut folders: Vec<Option<String>> = Vec::new();
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| format!("Invalid OPML at byte {}: {e}", reader.buffer_position()))?;
+        match event {
+            Event::Start(e) if e.name().0 == "outline" => {
+                let (name, url) = attributes(&e)?;
+                match url {
+                    Some(url) => {
+                        outlines.push(outline(name, url, &folders));
+                        folders.push(None);
+                    }
+                    None => folders.push(Some(name)),
+                }
+            }
+            Event::Empty(e) if e.name().0 == "outline" => {
+                if let (name, Some(url)) = attributes(&e)? {
+                    outlines.push(outline(name, url, &folders));
+                }
+            }
+            Event::End(e) if e.name().0 == "outline" => {
+                folders.pop();
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    if outlines.is_empty() {
+        return Err("No feeds found (expected <outline xmlUrl=...> elements)".into());
+    }
+    Ok(outlines)
+}
+
+/// An outline's title (or text) and feed URL
+fn attributes(e: &BytesStart) -> Result<(String, Option<String>), String> {
+    let (mut text, mut title, mut url) = (None, None, None);
+    for attr in e.attributes() {
+        let attr = attr.map_err(|e| format!("Invalid OPML attribute: {e}"))?;
+        let value = attr
+            .normalized_value(XmlVersion::Implicit1_0)
+            .map_err(|e| format!("Invalid OPML attribute: {e}"))?
+            .trim()
+            .to_string();
+        match attr.key.0 {
+            "text" => text = Some(value),
+            "title" => title = Some(value),
+            "xmlUrl" => url = Some(value),
+            _ => {}
+        }
+    }
+    let name = title.filter(|t| !t.is_empty()).or(text).unwrap_or_default();
+    Ok((name, url.filter(|u| !u.is_empty())))
+}
+
+fn outline(name: String, url: String, folders: &[Option<String>]) -> Outline {
+    let folder = folders.iter().rev().find_map(|f| f.clone());
+    let name = if name.is_empty() { url.clone() } else { name };
+    Outline { name, url, folder }
+}
+
+/// Add the outlines not already in `sources`
+pub fn merge(sources: &mut Vec<Source>, outlines: Vec<Outline>, defaults: &Defaults) -> Merged {
+    let mut urls: HashSet<String> = sources.iter().map(|s| s.url.clone()).collect();
+    let mut ids: HashSet<String> = sources.iter().map(|s| s.id.clone()).collect();
+    let mut merged = Merged::default();
+    for outline in outlines {
+        if !outline.url.starts_with("http://") && !outline.url.starts_with("https://") {
+            merged.invalid.push(outline.url);
+            continue;
+        }
+        if !urls.insert(outline.url.clone()) {
+            merged.existing += 1;
+            continue;
+        }
+        let id = unique_id(&slug(&outline.name), &mut ids);
+        let perspective = defaults
+            .perspective
+            .clone()
+            .or_else(|| outline.folder.as_deref().map(slug))
+            .filter(|p| !p.is_empty())
+            .unwrap_or_else(|| "general".into());
+        merged.added.push(id.clone());
+        sources.push(Source {
+            id,
+            name: outline.name,
+            url: outline.url,
+            bias: defaults.bias.clone(),
+            perspective,
+            owner: None,
+            region: None,
+            weight: 1.0,
+            topics: Vec::new(),
+            delay_secs: None,
+            enabled: true,
+            filters: Filters::default(),
+        });
+    }
+    merged
+}
+
+/// Append `added` to the sources file as `[[source]]` tables, leaving what's
+/// there, comments included, as it was. The file is replaced whole, so a
+/// failed write leaves the old one.
+pub fn append(path: &str, added: &[Source]) -> Result<(), String> {
+    let mut contents = match std::fs::exists(path) {
+        Ok(true) => {
+            std::fs::read_to_string(path).map_err(|e| format!("Cannot read sources {path}: {e}"))?
+        }
+        _ => String::new(),
+    };
+    for source in added {
+        let separator = match () {
+            _ if contents.is_empty() || contents.ends_with("\n\n") => "",
+            _ if contents.ends_with('\n') => "\n",
+            _ => "\n\n",
+        };
+        contents.push_str(separator);
+        contents.push_str("[[source]]\n");
+        for (key, value) in [
+            ("id", &source.id),
+            ("name", &source.name),
+            ("url", &source.url),
+            ("bias", &source.bias),
+            ("perspective", &source.perspective),
+        ] {
+            contents.push_str(&format!("{key} = {}\n", toml::Value::from(value.as_str())));
+        }
+    }
+    let partial = format!("{path}.partial");
+    std::fs::write(&partial, contents)
+        .and_then(|_| std::fs::rename(&partial, path))
+        .map_err(|e| format!("Cannot write sources {path}: {e}"))
+}
+
+/// "BBC News: World" -> "bbc_news_world"
+fn slug(name: &str) -> String {
+    let lower = name.to_lowercase();
+    let words: Vec<&str> = lower
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect();
+    let mut slug = words.join("_");
+    slug.truncate(40);
+    slug.trim_end_matches('_').to_string()
+}
+
+/// `base`, or `base_2`, `base_3`... when taken
+fn unique_id(base: &str, ids: &mut HashSet<String>) -> String {
+    let base = if base.is_empty() { "feed" } else { base };
+    let id = (1..)
+        .map(|n| match n {
+            1 => base.to_string(),
+            n => format!("{base}_{n}"),
+        })
+        .find(|id| !ids.contains(id))
+        .unwrap_or_default();
+    ids.insert(id.clone());
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <opml version="2.0">
+          <head><title>Subscriptions</title></head>
+          <body>
+            <outline text="World News">
+              <outline type="rss" text="BBC" title="BBC News: World" xmlUrl="https://feeds.bbci.co.uk/news/world/rss.xml"/>
+              <outline type="rss" text="Le Monde" xmlUrl="https://www.lemonde.fr/en/rss/une.xml" htmlUrl="https://www.lemonde.fr"/>
+            </outline>
+            <outline type="rss" text="Tech &amp; Science" xmlUrl="https://example.com/tech.xml"></outline>
+            <outline type="rss" text="Local" xmlUrl="file:///etc/feed.xml"/>
+          </body>
+        </opml>"#;
+
+    fn defaults() -> Defaults {
+        Defaults {
+            bias: "center".into(),
+            perspective: None,
+        }
+    }
+
+    mod parse {
+        use super::*;
+
+        #[test]
+        fn reads_feeds_and_their_folders() {
+            let outlines = parse(OPML).unwrap();
+            assert_eq!(outlines.len(), 4);
+            assert_eq!(
+                outlines[0],
+                Outline {
+                    name: "BBC News: World".into(),
+                    url: "https://feeds.bbci.co.uk/news/world/rss.xml".into(),
+                    folder: Some("World News".into()),
+                }
+            );
+            assert_eq!(outlines[1].name, "Le Monde");
+            assert_eq!(outlines[2].name, "Tech & Science");
+            assert_eq!(outlines[2].folder, None);
+        }
+
+        #[test]
+        fn rejects_files_without_feeds() {
+            assert!(parse("<opml><body><outline text=\"Empty\"/></body></opml>").is_err());
+            assert!(parse("<opml><body><outline text=\"Broken\"").is_err());
+        }
+    }
+
+    mod merge {
+        use super::*;
+
+        #[test]
+        fn adds_new_feeds_and_keeps_existing_ones() {
+            let mut sources = vec![Source {
+                id: "bbc_news_world".into(),
+                name: "BBC World".into(),
+                url: "https://feeds.bbci.co.uk/news/world/rss.xml".into(),
+                bias: "center".into(),
+                perspective: "british".into(),
+                owner: None,
+                region: None,
+                weight: 1.0,
+                topics: Vec::new(),
+                delay_secs: None,
+                enabled: true,
+                filters: Filters::default(),
+            }];
+            let mut outlines = parse(OPML).unwrap();
+            outlines.push(Outline {
+                name: "BBC News: World".into(),
+                url: "https://example.com/other-bbc.xml".into(),
+                folder: None,
+            });
+            let merged = merge(&mut sources, outlines, &defaults());
+
+            assert_eq!(
+                merged,
+                Merged {
+                    added: vec![
+                        "le_monde".into(),
+                        "tech_science".into(),
+                        "bbc_news_world_2".into()
+                    ],
+                    existing: 1,
+                    invalid: vec!["file:///etc/feed.xml".into()],
+                }
+            );
+            assert_eq!(sources[0].perspective, "british");
+            assert_eq!(sources[1].perspective, "world_news");
+            assert_eq!(sources[2].perspective, "general");
+        }
+
+        #[test]
+        fn applies_a_given_perspective() {
+            let mut sources = Vec::new();
+            let defaults = Defaults {
+                bias: "center-left".into(),
+                perspective: Some("european".into()),
+            };
+            merge(&mut sources, parse(OPML).unwrap(), &defaults);
+            assert!(
+                sources
+                    .iter()
+                    .all(|s| s.perspective == "european" && s.bias == "center-left")
+            );
+        }
+    }
+    mod append {
+        use super::*;
+
+        #[test]
+        fn adds_tables_and_keeps_the_rest_of_the_file() {
+            let path = std::env::temp_dir().join(format!("opml-{}.toml", std::process::id()));
+            let path = path.to_str().unwrap();
+            std::fs::write(
+                path,
+                "# World news\n[[source]]\nid = \"bbc\"\nname = \"BBC\"\nurl = \"https://example.com/bbc\"\nbias = \"center\"\nperspective = \"british\"\n",
+            )
+            .unwrap();
+            let mut sources = crate::sources::load(path).unwrap();
+            merge(&mut sources, parse(OPML).unwrap(), &defaults());
+            append(path, &sources[1..]).unwrap();
+
+            let contents = std::fs::read_to_string(path).unwrap();
+            assert!(contents.starts_with("# World news\n"));
+            assert!(
+                contents
+                    .contains("\n\n[[source]]\nid = \"tech_science\"\nname = \"Tech & Science\"\n")
+            );
+            assert_eq!(crate::sources::load(path).unwrap(), sources);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Article pages, fetched for their text when a feed gives only a teaser,
+//! and YouTube videos' transcripts (see [`youtube`](crate::youtube)).
+//! Pages robots.txt disallows are skipped, as are pages from a source whose
+//! breaker has opened, and each source's skips and failures are noted in its
+//! `source_health` row. The text is picked out of
+//! the page by [`extract`](crate::extract).
+//!
+//! What a page gave, its text or that it had none, is kept in
+//! `article_cache` by the article's canonical URL for `ARTICLE_CACHE_DAYS`,
+//! so fetching again within that time doesn't download or extract it again.
+
+use crate::breaker::Breakers;
+use crate::dedup::canonical;
+use crate::extract;
+use crate::feeds::Article;
+use crate::hosts::Scheduler;
+use crate::robots::Robots;
+use crate::sources::Source;
+use crate::youtube;
+use regex::Regex;
+use reqwest::Client;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
+
+/// Summaries shorter than this, in characters without markup, are teasers
+const TEASER_CHARS: usize = 200;
+
+/// Longest page text kept, in characters
+pub const MAX_CONTENT_CHARS: usize = 5000;
+
+static TAG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<[^>]*>").expect("valid regex"));
+
+/// Days a page's text is kept, unless `ARTICLE_CACHE_DAYS` says otherwise
+const CACHE_DAYS: u32 = 7;
+
+/// Days a page's text is kept in `article_cache`; 0 keeps none
+pub fn cache_days() -> u32 {
+    std::env::var("ARTICLE_CACHE_DAYS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(CACHE_DAYS)
+}
+
+/// Pages fetched before, and the ones fetched this run, by canonical URL,
+/// each with its text, or `None` when it had no article or captions
+#[derive(Debug, Default)]
+pub struct Cache {
+    kept: HashMap<String, Option<String>>,
+    fetched: Vec<(String, Option<String>)>,
+}
+
+impl Cache {
+    pub fn new(kept: HashMap<String, Option<String>>) -> Self {
+        Self {
+            kept,
+            fetched: Vec::new(),
+        }
+    }
+
+    /// The pages fetched this run, to keep for the next
+    pub fn fetched(&self) -> &[(String, Option<String>)] {
+        &self.fetched
+    }
+
+    /// How the page at `url` went when it was last fetched, if it was
+    fn get(&self, url: &str, is_video: bool) -> Option<Page> {
+        Some(match self.kept.get(&canonical(url))? {
+            Some(text) => Page::Text(text.clone()),
+            None if is_video => Page::NoTranscript,
+            None => Page::NoArticle,
+        })
+    }
+
+    /// Keep what the page at `url` gave, unless it couldn't be had
+    fn record(&mut self, url: &str, page: &Page) {
+        let text = match page {
+            Page::Text(text) => Some(text.clone()),
+            Page::NoArticle | Page::NoTranscript => None,
+            Page::Disallowed | Page::Skipped | Page::Failed(_) => return,
+        };
+        self.fetched.push((canonical(url), text));
+    }
+}
+
+/// How a page fetch went
+enum Page {
+    Text(String),
+    /// Loaded, but nothing on it reads like an article
+    NoArticle,
+    /// A video without captions
+    NoTranscript,
+    Disallowed,
+    /// The source's breaker is open
+    Skipped,
+    Failed(String),
+}
+
+/// Fill in `content` for teaser articles, from `cache` or their pages, and
+/// return a note per source on the pages it couldn't have
+pub async fn fill_content(
+    client: &Client,
+    scheduler: &Arc<Scheduler>,
+    breakers: &Arc<Breakers>,
+    robots: &Arc<Robots>,
+    cache: &mut Cache,
+    sources: &[Source],
+    fetched: &mut [(&str, Vec<Article>)],
+) -> HashMap<String, String> {
+    let mut pages = Vec::new();
+    let mut tasks = tokio::task::JoinSet::new();
+    for (source_index, (source_id, articles)) in fetched.iter().enumerate() {
+        let delay_secs = sources
+            .iter()
+            .find(|s| s.id == *source_id)
+            .and_then(|s| s.delay_secs);
+        for (index, article) in articles.iter().enumerate() {
+            // A video's description is no substitute for what's said in it
+            let is_video = youtube::video_id(&article.url).is_some();
+            if article.content.is_some()
+                || (text_length(&article.summary) >= TEASER_CHARS && !is_video)
+            {
+                continue;
+            }
+            if let Some(page) = cache.get(&article.url, is_video) {
+                pages.push((source_index, index, page));
+                continue;
+            }
+            let (client, scheduler, breakers, robots) = (
+                client.clone(),
+                scheduler.clone(),
+                breakers.clone(),
+                robots.clone(),
+            );
+            let (source_id, url) = (source_id.to_string(), article.url.clone());
+            tasks.spawn(async move {
+                let page = if robots.allows(&client, &scheduler, &url).await {
+                    let _turn = scheduler.wait(&url, delay_secs).await;
+                    // Checked once it's this page's turn, so pages queued
+                    // behind failing ones are skipped
+                    if breakers.check(&source_id).is_err() {
+                        Page::Skipped
+                    } else {
+                        let sent = std::time::Instant::now();
+                        let downloaded = if is_video {
+                            youtube::transcript(&client, &url).await
+                        } else {
+                            download(&client, &url)
+                                .await
+                                .map(|html| extract::article_text(&html))
+                        };
+                        let error = downloaded.as_ref().err().map(String::as_str);
+                        breakers.record(&source_id, sent.elapsed(), error);
+                        match downloaded {
+                            Ok(Some(text)) => {
+                                Page::Text(text.chars().take(MAX_CONTENT_CHARS).collect())
+                            }
+                            Ok(None) if is_video => Page::NoTranscript,
+                            Ok(None) => Page::NoArticle,
+                            Err(e) => Page::Failed(e),
+                        }
+                    }
+                } else {
+                    Page::Disallowed
+                };
+                (source_index, index, page)
+            });
+        }
+    }
+
+    // Disallowed, failed, and articleless pages, and videos without
+    // captions, per source
+    let mut skipped: HashMap<usize, (usize, usize, usize, usize)> = HashMap::new();
+    for (source_index, index, page) in tasks.join_all().await {
+        cache.record(&fetched[source_index].1[index].url, &page);
+        pages.push((source_index, index, page));
+    }
+    for (source_index, index, page) in pages {
+        let (source_id, articles) = &mut fetched[source_index];
+        match page {
+            Page::Text(text) => {
+                let article = &mut articles[index];
+                if text_length(&text) > text_length(&article.summary) {
+                    article.content = Some(text);
+                }
+            }
+            Page::Disallowed => skipped.entry(source_index).or_default().0 += 1,
+            // The breaker's note covers these
+            Page::Skipped => {}
+            Page::Failed(e) => {
+                tracing::debug!("[{}] {}: {}", source_id, articles[index].url, e);
+                skipped.entry(source_index).or_default().1 += 1;
+            }
+            Page::NoArticle => skipped.entry(source_index).or_default().2 += 1,
+            Page::NoTranscript => skipped.entry(source_index).or_default().3 += 1,
+        }
+    }
+    skipped
+        .into_iter()
+        .map(
+            |(source_index, (disallowed, failed, articleless, uncaptioned))| {
+                let mut notes = Vec::new();
+                if disallowed > 0 {
+                    notes.push(format!("robots.txt disallows {disallowed} article page(s)"));
+                }
+                if failed > 0 {
+                    notes.push(format!("{failed} article page(s) failed to load"));
+                }
+                if articleless > 0 {
+                    notes.push(format!("no article text found on {articleless} page(s)"));
+                }
+                if uncaptioned > 0 {
+                    notes.push(format!("no captions on {uncaptioned} video(s)"));
+                }
+                (fetched[source_index].0.to_string(), notes.join("; "))
+            },
+        )
+        .collect()
+}
+
+/// The HTML page at `url`
+pub async fn download(client: &Client, url: &str) -> Result<String, String> {
+    let response = client.get(url).send().await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("HTTP {status}"));
+    }
+    let is_html = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.contains("html"));
+    if !is_html {
+        return Err("Not an HTML page".into());
+    }
+    response.text().await.map_err(|e| e.to_string())
+}
+
+/// Markup removed, common entities decoded, and whitespace collapsed
+pub fn plain_text(html: &str) -> String {
+    let text = TAG.replace_all(html, " ");
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+pub fn text_length(html: &str) -> usize {
+    plain_text(html).chars().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod text_length {
+        use super::*;
+
+        #[test]
+        fn counts_characters_without_markup() {
+            assert_eq!(text_length("<p>Café  au <i>lait</i></p>"), 12);
+        }
+    }
+
+    mod cache {
+        use super::*;
+
+        #[test]
+        fn finds_pages_by_their_canonical_url() {
+            let mut cache = Cache::new(HashMap::from([(
+                "example.com/story".to_string(),
+                Some("Text.".to_string()),
+            )]));
+            let cached = cache.get("https://www.example.com/story/?utm_source=rss", false);
+            assert!(matches!(cached, Some(Page::Text(text)) if text == "Text."));
+            assert!(cache.get("https://example.com/other", false).is_none());
+
+            cache.record(
+                "https://example.com/failed",
+                &Page::Failed("HTTP 500".into()),
+            );
+            cache.record("https://youtu.be/abc", &Page::NoTranscript);
+            assert_eq!(cache.fetched(), [("youtu.be/abc".to_string(), None)]);
+        }
+    }
+}
@@ -0,0 +1,270 @@
+//! The editorial prompts `curate` sends, as templates: the ones bundled in
+//! `prompts/`, or a deployment's own from `PROMPTS_DIR`. Templates fill in
+//! `{{name}}` placeholders, and every run records which version of them it
+//! used, so a digest can be traced back to the prompts that wrote it.
+
+use regex::Regex;
+use std::path::Path;
+use std::sync::LazyLock;
+
+/// Version of the bundled templates; bump it with any change to them
+const BUNDLED_VERSION: &str = "3";
+
+/// A template's file name, bundled text, and the placeholders it may use
+struct Template {
+    file: &'static str,
+    bundled: &'static str,
+    variables: &'static [&'static str],
+}
+
+const SYSTEM: Template = Template {
+    file: "system.md",
+    bundled: include_str!("prompts/system.md"),
+    variables: &[],
+};
+
+const STORIES: Template = Template {
+    file: "stories.md",
+    bundled: include_str!("prompts/stories.md"),
+    variables: &[
+        "previous_headlines",
+        "stories",
+        "regions",
+        "topics",
+        "summary_length",
+    ],
+};
+
+const SUMMARY: Template = Template {
+    file: "summary.md",
+    bundled: include_str!("prompts/summary.md"),
+    variables: &["digest", "regions", "summary_length"],
+};
+
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*(\w+)\s*\}\}").expect("valid regex"));
+
+/// The templates a run uses
+#[derive(Debug)]
+pub struct Prompts {
+    /// Sent as the system prompt of every request
+    pub system: String,
+    stories: String,
+    summary: String,
+    /// Recorded with the run: the bundled version, or a deployment's
+    pub version: String,
+}
+
+impl Prompts {
+    /// The bundled templates, with any of `system.md`, `stories.md`, and
+    /// `summary.md` in `PROMPTS_DIR` in their place
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("PROMPTS_DIR").ok().filter(|d| !d.is_empty()) {
+            Some(dir) => Self::load(Path::new(&dir)),
+            None => Self::bundled(),
+        }
+    }
+
+    /// The templates as bundled
+    pub fn bundled() -> Result<Self, String> {
+        Ok(Self {
+            system: checked(&SYSTEM, SYSTEM.bundled)?,
+            stories: checked(&STORIES, STORIES.bundled)?,
+            summary: checked(&SUMMARY, SUMMARY.bundled)?,
+            version: BUNDLED_VERSION.into(),
+        })
+    }
+
+    /// Templates from `dir`, falling back to the bundled ones. The version is
+    /// the directory's `VERSION` file, or else a hash of the templates.
+    fn load(dir: &Path) -> Result<Self, String> {
+        let read = |name: &str| match std::fs::read_to_string(dir.join(name)) {
+            Ok(text) => Ok(Some(text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Cannot read {}: {e}", dir.join(name).display())),
+        };
+        let mut overridden = false;
+        let mut template = |template: &Template| -> Result<String, String> {
+            match read(template.file)? {
+                Some(text) => {
+                    overridden = true;
+                    checked(template, &text)
+                }
+                None => Ok(template.bundled.to_string()),
+            }
+        };
+        let (system, stories, summary) =
+            (template(&SYSTEM)?, template(&STORIES)?, template(&SUMMARY)?);
+        let version = match read("VERSION")? {
+            Some(version) if !version.trim().is_empty() => version
+                .trim()
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string(),
+            _ if !overridden => BUNDLED_VERSION.into(),
+            _ => format!("custom-{}", fingerprint(&[&system, &stories, &summary])),
+        };
+        Ok(Self {
+            system,
+            stories,
+            summary,
+            version,
+        })
+    }
+
+    /// The request for verdicts on a batch of stories
+    pub fn stories(
+        &self,
+        previous_headlines: &str,
+        stories: &str,
+        regions: &str,
+        topics: &str,
+        summary_length: &str,
+    ) -> String {
+        render(
+            &self.stories,
+            &[
+                ("previous_headlines", previous_headlines),
+                ("stories", stories),
+                ("regions", regions),
+                ("topics", topics),
+                ("summary_length", summary_length),
+            ],
+        )
+    }
+
+    /// The request for regional summaries of the day's `digest`
+    pub fn summary(&self, digest: &str, regions: &str, summary_length: &str) -> String {
+        render(
+            &self.summary,
+            &[
+                ("digest", digest),
+                ("regions", regions),
+                ("summary_length", summary_length),
+            ],
+        )
+    }
+}
+
+/// `text`, if every placeholder in it is one `template` fills in
+fn checked(template: &Template, text: &str) -> Result<String, String> {
+    let unknown: Vec<&str> = PLACEHOLDER
+        .captures_iter(text)
+        .map(|c| c.get(1).map_or("", |m| m.as_str()))
+        .filter(|name| !template.variables.contains(name))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(format!(
+            "{}: unknown placeholder {} (it can use {})",
+            template.file,
+            unknown.join(", "),
+            if template.variables.is_empty() {
+                "none".to_string()
+            } else {
+                template.variables.join(", ")
+            }
+        ));
+    }
+    Ok(text.to_string())
+}
+
+/// `template` with its placeholders filled in, in one pass, so values that
+/// happen to contain `{{...}}` are left as they are
+fn render(template: &str, values: &[(&str, &str)]) -> String {
+    PLACEHOLDER
+        .replace_all(template, |c: &regex::Captures| {
+            let name = &c[1];
+            values
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map_or_else(|| c[0].to_string(), |(_, value)| value.to_string())
+        })
+        .into_owned()
+}
+
+/// A short hash of `texts`, to tell one set of templates from another
+fn fingerprint(texts: &[&str]) -> String {
+    let mut context = ring::digest::Context::new(&ring::digest::SHA256);
+    for text in texts {
+        context.update(text.as_bytes());
+        context.update(&[0]);
+    }
+    context.finish().as_ref()[..6]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("prompts-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    mod render {
+        use super::*;
+
+        #[test]
+        fn fills_placeholders_once() {
+            assert_eq!(
+                render(
+                    "Stories:\n{{ stories }}\nIn {{regions}}, not {{other}}",
+                    &[("stories", "- {{regions}}"), ("regions", "europe")]
+                ),
+                "Stories:\n- {{regions}}\nIn europe, not {{other}}"
+            );
+        }
+    }
+
+    mod load {
+        use super::*;
+
+        #[test]
+        fn bundled_templates_are_valid() {
+            let prompts = Prompts::bundled().unwrap();
+            assert_eq!(prompts.version, BUNDLED_VERSION);
+            let prompt =
+                prompts.stories("- (none)", "### c1", "europe, tech", "other", "1 sentence");
+            assert!(prompt.contains("### c1"));
+            assert!(!prompt.contains("{{"));
+        }
+
+        #[test]
+        fn overrides_some_templates_and_versions_them() {
+            let dir = temp_dir("override");
+            std::fs::write(dir.join("summary.md"), "Sum up {{digest}} by {{regions}}.").unwrap();
+            let prompts = Prompts::load(&dir).unwrap();
+            assert_eq!(
+                prompts.summary("- a", "europe", "x"),
+                "Sum up - a by europe."
+            );
+            assert_eq!(prompts.system, SYSTEM.bundled);
+            assert!(prompts.version.starts_with("custom-"));
+
+            std::fs::write(dir.join("VERSION"), "house-style-2\n").unwrap();
+            assert_eq!(Prompts::load(&dir).unwrap().version, "house-style-2");
+
+            std::fs::write(dir.join("system.md"), "Be brief about {{stories}}.").unwrap();
+            let error = Prompts::load(&dir).unwrap_err();
+            std::fs::remove_dir_all(&dir).unwrap();
+            assert_eq!(
+                error,
+                "system.md: unknown placeholder stories (it can use none)"
+            );
+        }
+
+        #[test]
+        fn an_empty_directory_is_the_bundled_version() {
+            let dir = temp_dir("empty");
+            let prompts = Prompts::load(&dir).unwrap();
+            std::fs::remove_dir_all(&dir).unwrap();
+            assert_eq!(prompts.version, BUNDLED_VERSION);
+        }
+    }
+}
@@ -0,0 +1,435 @@
+//! The digest's HTML, rendered from its narratives as `run.py` stores them
+//! (`narratives` and `regional_summaries`) through the askama templates in
+//! `templates/`. The web variant keeps the stylesheet's variables, so
+//! browsers get dark mode; it's what `digests` holds, and what the server
+//! serves (`send-digest` prepares it for mail as `run.py` does). The email
+//! variant resolves them to their light-mode values for mail clients that
+//! don't support them.
+
+use crate::css;
+use crate::curate::{Angle, REGIONS, SourceRef};
+use crate::language;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+/// The stylesheet, as `run.py` has it in `digest.css`
+const STYLES: &str = include_str!("templates/digest.css");
+
+/// Filled in per recipient by Resend or `send-digest`
+const UNSUBSCRIBE_PLACEHOLDER: &str = "{{{RESEND_UNSUBSCRIBE_URL}}}";
+
+/// Biases with a color of their own in the stylesheet
+const BIASES: &[&str] = &["left", "center-left", "center", "center-right", "right"];
+
+static MARKDOWN_LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[([^\]]+)\]\(([^)]+)\)").expect("valid regex"));
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Variant {
+    Web,
+    Email,
+}
+
+/// A narrative as stored in `narratives`
+#[derive(Debug, Default)]
+pub struct Narrative {
+    /// must_know, should_know, or signal
+    pub tier: String,
+    /// A signal's region
+    pub cluster: Option<String>,
+    pub headline: String,
+    pub summary: String,
+    pub why_it_matters: String,
+    pub sources: Vec<SourceRef>,
+    pub reporting_varies: Vec<Angle>,
+    /// Hash of its picture in `images`
+    pub image: Option<String>,
+}
+
+/// A digest's narratives, in display order, and its regional summaries
+#[derive(Debug, Default)]
+pub struct Digest {
+    pub narratives: Vec<Narrative>,
+    pub regional_summary: BTreeMap<String, String>,
+}
+
+/// The site around a digest, from the same variables `run.py` reads
+#[derive(Debug, Default)]
+pub struct Site {
+    name: String,
+    /// For the "View in browser" link
+    domain: Option<String>,
+    model_name: String,
+    source_url: Option<String>,
+    archive_url: Option<String>,
+    /// Where feedback replies go
+    feedback_email: Option<String>,
+    author: Option<String>,
+    author_url: Option<String>,
+}
+
+impl Site {
+    /// `DIGEST_NAME`, `DIGEST_DOMAIN`, `MODEL_NAME`, `SOURCE_URL`,
+    /// `ARCHIVE_URL`, `RESEND_FROM`, `AUTHOR_NAME`, and `AUTHOR_URL`
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let author = var("AUTHOR_NAME");
+        Self {
+            name: var("DIGEST_NAME").unwrap_or_else(|| "News Digest".into()),
+            domain: var("DIGEST_DOMAIN"),
+            model_name: var("MODEL_NAME").unwrap_or_else(|| "Claude".into()),
+            source_url: var("SOURCE_URL"),
+            archive_url: var("ARCHIVE_URL").filter(|url| is_safe_url(url)),
+            feedback_email: var("RESEND_FROM"),
+            author_url: var("AUTHOR_URL").filter(|url| author.is_some() && is_safe_url(url)),
+            author,
+        }
+    }
+}
+
+/// How a region is shown
+struct Region {
+    name: &'static str,
+    emoji: &'static str,
+    /// Its id in the page, for links to its signals
+    anchor: String,
+}
+
+fn region(key: &str) -> Region {
+    let (name, emoji) = match key {
+        "americas" => ("Americas", "🌎"),
+        "europe" => ("Europe", "🌍"),
+        "asia_pacific" => ("Asia-Pacific", "🌏"),
+        "middle_east_africa" => ("Middle East & Africa", "🌍"),
+        "tech" => ("Tech", "🤖"),
+        _ => ("Other", "🌐"),
+    };
+    Region {
+        name,
+        emoji,
+        anchor: key.replace('_', "-"),
+    }
+}
+
+#[derive(Template)]
+#[template(path = "digest.html", config = "src/bin/digest-pipeline/askama.toml")]
+struct Page<'a> {
+    site: &'a Site,
+    styles: String,
+    date: String,
+    timestamp: String,
+    preheader: String,
+    homepage_url: Option<String>,
+    /// Where narratives' pictures are served, up to their hash; pictures
+    /// need the digest's domain to show in mail
+    image_base: Option<String>,
+    /// The first must_know or should_know picture, for link previews
+    og_image: Option<String>,
+    /// Regions with a summary, as HTML
+    summaries: Vec<(Region, String)>,
+    must_know: Vec<&'a Narrative>,
+    should_know: Vec<&'a Narrative>,
+    /// Regions with signals
+    signals: Vec<(Region, Vec<&'a Narrative>)>,
+    unsubscribe: &'static str,
+}
+
+/// A label after a source's link in a narrative
+struct Chip {
+    class: String,
+    text: String,
+}
+
+impl SourceRef {
+    /// What's known of the source, as chips: its bias, its owner and region
+    /// when configured, and its article's language when not English
+    fn chips(&self) -> Vec<Chip> {
+        let mut chips = Vec::new();
+        if !self.bias.is_empty() {
+            let class = if BIASES.contains(&self.bias.as_str()) {
+                format!("chip bias-{}", self.bias)
+            } else {
+                "chip".into()
+            };
+            chips.push(Chip {
+                class,
+                text: self.bias.clone(),
+            });
+        }
+        let language = self.language.as_deref().and_then(language::name);
+        for text in [self.owner.clone(), self.region.clone()]
+            .into_iter()
+            .chain([language.map(|name| format!("in {name}"))])
+            .flatten()
+        {
+            chips.push(Chip {
+                class: "chip".into(),
+                text,
+            });
+        }
+        chips
+    }
+}
+
+/// The digest of `date` (YYYY-MM-DD), made at `made`
+pub fn render(
+    digest: &Digest,
+    site: &Site,
+    date: &str,
+    made: DateTime<Utc>,
+    variant: Variant,
+) -> Result<String, String> {
+    let tier = |name: &str| -> Vec<&Narrative> {
+        digest
+            .narratives
+            .iter()
+            .filter(|n| n.tier == name)
+            .collect()
+    };
+    let image_base = site
+        .domain
+        .as_ref()
+        .map(|domain| format!("https://{domain}/img/"));
+    let og_image = image_base.as_ref().and_then(|base| {
+        let hash = tier("must_know")
+            .into_iter()
+            .chain(tier("should_know"))
+            .find_map(|n| n.image.as_ref())?;
+        Some(format!("{base}{hash}"))
+    });
+    let page = Page {
+        site,
+        styles: match variant {
+            Variant::Web => css::minify(STYLES),
+            Variant::Email => css::minify(&css::resolve_variables(STYLES)),
+        },
+        date: made.format("%B %-d, %Y").to_string(),
+        timestamp: made.format("%A, %B %-d, %Y · %H:%M UTC").to_string(),
+        preheader: preheader(&digest.regional_summary, 150),
+        homepage_url: site
+            .domain
+            .as_ref()
+            .map(|domain| format!("https://{domain}/{date}")),
+        image_base,
+        og_image,
+        summaries: REGIONS
+            .iter()
+            .filter_map(|key| {
+                let text = digest.regional_summary.get(*key)?;
+                (!text.is_empty()).then(|| (region(key), markdown_to_html(text)))
+            })
+            .collect(),
+        must_know: tier("must_know"),
+        should_know: tier("should_know"),
+        signals: REGIONS
+            .iter()
+            .map(|key| {
+                let signals: Vec<&Narrative> = digest
+                    .narratives
+                    .iter()
+                    .filter(|n| n.tier == "signal" && n.cluster.as_deref() == Some(*key))
+                    .collect();
+                (region(key), signals)
+            })
+            .filter(|(_, signals)| !signals.is_empty())
+            .collect(),
+        unsubscribe: UNSUBSCRIBE_PLACEHOLDER,
+    };
+    page.render()
+        .map_err(|e| format!("Cannot render the digest: {e}"))
+}
+
+pub fn is_safe_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// `text`, escaped, with its markdown links as links (or plain text, if
+/// their URLs aren't http(s))
+fn markdown_to_html(text: &str) -> String {
+    let mut html = String::new();
+    let mut rest = 0;
+    for link in MARKDOWN_LINK.captures_iter(text) {
+        let whole = link.get(0).expect("a match");
+        html.push_str(&escape(&text[rest..whole.start()]));
+        let (label, url) = (escape(&link[1]), &link[2]);
+        if is_safe_url(url) {
+            html.push_str(&format!(r#"<a href="{}">{label}</a>"#, escape(url)));
+        } else {
+            html.push_str(&label);
+        }
+        rest = whole.end();
+    }
+    html.push_str(&escape(&text[rest..]));
+    html
+}
+
+/// The inbox preview: the first region summary's first sentence, without
+/// links, or as much of it as fits `max_length`
+fn preheader(regional_summary: &BTreeMap<String, String>, max_length: usize) -> String {
+    let Some(summary) = REGIONS
+        .iter()
+        .filter_map(|key| regional_summary.get(*key))
+        .find(|text| !text.is_empty())
+    else {
+        return String::new();
+    };
+    let plain = MARKDOWN_LINK.replace_all(summary, "$1");
+    let sentence = format!("{}.", plain.split('.').next().unwrap_or_default());
+    if sentence.chars().count() <= max_length {
+        return sentence;
+    }
+    let cut: String = plain.chars().take(max_length).collect();
+    let cut = cut.rsplit_once(' ').map_or(cut.as_str(), |(head, _)| head);
+    format!("{cut}...")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn narrative(tier: &str, cluster: Option<&str>, headline: &str) -> Narrative {
+        Narrative {
+            tier: tier.into(),
+            cluster: cluster.map(str::to_string),
+            headline: headline.into(),
+            summary: "What happened.".into(),
+            why_it_matters: "Why.".into(),
+            sources: vec![SourceRef {
+                name: "Wire".into(),
+                url: "https://wire.example/1".into(),
+                bias: "center".into(),
+                owner: None,
+                region: None,
+                language: None,
+            }],
+            reporting_varies: Vec::new(),
+            image: None,
+        }
+    }
+
+    mod render {
+        use super::*;
+
+        #[test]
+        fn lays_out_tiers_and_regions() {
+            let mut truce = narrative("must_know", None, "Truce <holds>");
+            truce.reporting_varies = vec![Angle {
+                source: "Left".into(),
+                bias: "left".into(),
+                angle: "Hopeful".into(),
+            }];
+            truce.sources[0].owner = Some("Wire Co-op".into());
+            truce.sources[0].language = Some("fr".into());
+            let mut rates = narrative("should_know", None, "Rates steady");
+            rates.image = Some("ab12".into());
+            let digest = Digest {
+                narratives: vec![
+                    truce,
+                    rates,
+                    narrative("signal", Some("tech"), "Chip rules"),
+                ],
+                regional_summary: BTreeMap::from([(
+                    "europe".into(),
+                    "Talks [resumed](https://wire.example/1) & more. Then".into(),
+                )]),
+            };
+            let site = Site {
+                name: "Daily".into(),
+                model_name: "Claude".into(),
+                domain: Some("news.example".into()),
+                ..Default::default()
+            };
+            let made = DateTime::parse_from_rfc3339("2026-01-05T07:00:00+00:00")
+                .unwrap()
+                .with_timezone(&Utc);
+            let html = render(&digest, &site, "2026-01-05", made, Variant::Web).unwrap();
+
+            assert!(html.contains("<title>Daily – January 5, 2026</title>"));
+            assert!(html.contains("<time>Monday, January 5, 2026 · 07:00 UTC</time>"));
+            assert!(
+                html.contains(r#"<a href="https://news.example/2026-01-05">View in browser</a>"#)
+            );
+            assert!(
+                html.contains(
+                    r#"<meta property="og:image" content="https://news.example/img/ab12">"#
+                )
+            );
+            assert!(html.contains("<h3>Truce &#60;holds&#62;</h3>\n      <p>"));
+            assert!(html.contains(
+                r#"<h3>Rates steady</h3>
+      <img class="thumbnail" src="https://news.example/img/ab12" alt="">"#
+            ));
+            assert!(html.contains("<em>Left</em> (left): Hopeful"));
+            assert!(html.contains(
+                r#"<a href="https://wire.example/1">Wire</a> <span class="chip bias-center">center</span> <span class="chip">Wire Co-op</span> <span class="chip">in French</span>"#
+            ));
+            assert!(html.contains(
+                r#"🌍 Europe:</span> Talks <a href="https://wire.example/1">resumed</a> &amp; more. Then</p>"#
+            ));
+            assert!(html.contains(r#"<div id="tech" class="cluster">"#));
+            assert!(html.contains(
+                r#"<p class="signal">Chip rules — <a href="https://wire.example/1">Wire</a></p>"#
+            ));
+            assert!(html.contains(r#"<a href="{{{RESEND_UNSUBSCRIBE_URL}}}">Unsubscribe</a>"#));
+            assert!(!html.contains("Past digests"));
+            assert!(html.contains("var(--"));
+
+            let email = render(&digest, &site, "2026-01-05", made, Variant::Email).unwrap();
+            assert!(!email.contains("var(--"));
+            assert!(!email.contains("prefers-color-scheme"));
+        }
+    }
+
+    mod markdown_to_html {
+        use super::*;
+
+        #[test]
+        fn links_only_web_urls_and_escapes_the_rest() {
+            assert_eq!(
+                markdown_to_html(
+                    "A [deal](https://a.example/?x=1&y=2) <b>, [bad](javascript:alert(1))"
+                ),
+                r#"A <a href="https://a.example/?x=1&amp;y=2">deal</a> &lt;b&gt;, bad)"#
+            );
+        }
+    }
+
+    mod preheader {
+        use super::*;
+
+        #[test]
+        fn takes_the_first_summarys_first_sentence() {
+            let summaries = BTreeMap::from([
+                ("tech".into(), "Chips. More.".into()),
+                ("americas".into(), String::new()),
+                (
+                    "europe".into(),
+                    "Talks [resumed](https://a.example). Later.".into(),
+                ),
+            ]);
+            assert_eq!(preheader(&summaries, 150), "Talks resumed.");
+            assert_eq!(preheader(&summaries, 10), "Talks...");
+            assert_eq!(preheader(&BTreeMap::new(), 150), "");
+        }
+    }
+
+    mod styles {
+        use super::*;
+
+        #[test]
+        fn the_bundled_stylesheet_is_run_pys() {
+            assert_eq!(STYLES, include_str!("../../../../digest.css"));
+        }
+    }
+}
@@ -0,0 +1,267 @@
+//! robots.txt, as RFC 9309 has it, for the article pages fetched for full
+//! text. Feeds are published for programs to read and aren't checked.
+//!
+//! Each site's file is fetched at most once a day and kept in `robots_txt`.
+//! A missing file (4xx) allows everything; a site that can't be reached or
+//! answers 5xx or 429 is off limits until the next run.
+
+use crate::hosts::Scheduler;
+use reqwest::{Client, StatusCode};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::OnceCell;
+
+/// The product token groups are matched against, also in the User-Agent
+pub const AGENT: &str = "digest-pipeline";
+
+/// Longest file read; the RFC's minimum is 500 KiB
+const MAX_BYTES: usize = 500 * 1024;
+
+#[derive(Debug, PartialEq)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+/// The rules of the group that applies to [`AGENT`]
+#[derive(Debug, Default, PartialEq)]
+pub struct Rules(Vec<Rule>);
+
+impl Rules {
+    /// The rules for `agent`: its own groups if the file names it, else the
+    /// `*` groups
+    pub fn parse(body: &str, agent: &str) -> Self {
+        let mut groups: Vec<(Vec<String>, Vec<Rule>)> = Vec::new();
+        let mut in_agents = false;
+        for line in body.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "user-agent" => {
+                    if !in_agents {
+                        groups.push((Vec::new(), Vec::new()));
+                        in_agents = true;
+                    }
+                    if let Some((agents, _)) = groups.last_mut() {
+                        agents.push(value.to_ascii_lowercase());
+                    }
+                }
+                key @ ("allow" | "disallow") => {
+                    in_agents = false;
+                    if let Some((_, rules)) = groups.last_mut()
+                        && !value.is_empty()
+                    {
+                        rules.push(Rule {
+                            allow: key == "allow",
+                            pattern: value.to_string(),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        let agent = agent.to_ascii_lowercase();
+        let named = groups.iter().any(|(agents, _)| agents.contains(&agent));
+        let rules = groups
+            .into_iter()
+            .filter(|(agents, _)| {
+                agents
+                    .iter()
+                    .any(|a| if named { *a == agent } else { a == "*" })
+            })
+            .flat_map(|(_, rules)| rules)
+            .collect();
+        Self(rules)
+    }
+
+    /// Nothing allowed, for a site that can't be asked
+    fn disallow_all() -> Self {
+        Self(vec![Rule {
+            allow: false,
+            pattern: "/".into(),
+        }])
+    }
+
+    /// Whether `path` (with its query) may be fetched: the longest matching
+    /// rule decides, `Allow` winning a tie
+    pub fn allows(&self, path: &str) -> bool {
+        if path == "/robots.txt" {
+            return true;
+        }
+        self.0
+            .iter()
+            .filter(|rule| matches(&rule.pattern, path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+}
+
+/// `pattern` against the start of `path`, with `*` matching any run of
+/// characters and a trailing `$` anchoring the end
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = path.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+/// The sites' rules, fetched as pages on them are first asked about
+pub struct Robots {
+    /// Files fetched on earlier runs, by origin
+    cached: HashMap<String, String>,
+    rules: Mutex<HashMap<String, Arc<OnceCell<Rules>>>>,
+    /// Files fetched this run, by origin, for the cache
+    fetched: Mutex<Vec<(String, String)>>,
+}
+
+impl Robots {
+    pub fn new(cached: HashMap<String, String>) -> Self {
+        Self {
+            cached,
+            rules: Mutex::default(),
+            fetched: Mutex::default(),
+        }
+    }
+
+    /// Whether `url` may be fetched, asking its site first if need be
+    pub async fn allows(&self, client: &Client, scheduler: &Scheduler, url: &str) -> bool {
+        let Ok(url) = reqwest::Url::parse(url) else {
+            return false;
+        };
+        let origin = url.origin().ascii_serialization();
+        let cell = {
+            let mut rules = self.rules.lock().unwrap_or_else(|e| e.into_inner());
+            rules.entry(origin.clone()).or_default().clone()
+        };
+        let rules = cell
+            .get_or_init(|| async {
+                if let Some(body) = self.cached.get(&origin) {
+                    return Rules::parse(body, AGENT);
+                }
+                match fetch(client, scheduler, &origin).await {
+                    Some(body) => {
+                        let rules = Rules::parse(&body, AGENT);
+                        let mut fetched = self.fetched.lock().unwrap_or_else(|e| e.into_inner());
+                        fetched.push((origin.clone(), body));
+                        rules
+                    }
+                    None => Rules::disallow_all(),
+                }
+            })
+            .await;
+        let path = match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_string(),
+        };
+        rules.allows(&path)
+    }
+
+    /// Files fetched this run, to be cached
+    pub fn fetched(&self) -> Vec<(String, String)> {
+        std::mem::take(&mut *self.fetched.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// `origin`'s robots.txt: empty when it has none, `None` when it can't be
+/// reached
+async fn fetch(client: &Client, scheduler: &Scheduler, origin: &str) -> Option<String> {
+    let url = format!("{origin}/robots.txt");
+    let _turn = scheduler.wait(&url, None).await;
+    let response = match client.get(&url).send().await {
+        Ok(response) => response,
+        Err(e) => {
+            tracing::warn!("{}: {}", url, e);
+            return None;
+        }
+    };
+    let status = response.status();
+    if status.is_client_error() && status != StatusCode::TOO_MANY_REQUESTS {
+        return Some(String::new());
+    }
+    if !status.is_success() {
+        tracing::warn!("{}: HTTP {}", url, status);
+        return None;
+    }
+    let body = response.bytes().await.ok()?;
+    let body = &body[..body.len().min(MAX_BYTES)];
+    Some(String::from_utf8_lossy(body).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = "
+        # Comments are ignored
+        User-agent: *
+        Disallow: /private/
+        Disallow: /*.pdf$
+        Allow: /private/press/
+
+        User-agent: GPTBot
+        User-agent: digest-pipeline
+        Disallow: /archive
+        Sitemap: https://example.com/sitemap.xml
+    ";
+
+    mod parse {
+        use super::*;
+
+        #[test]
+        fn uses_the_named_group_over_the_wildcard() {
+            let rules = Rules::parse(ROBOTS, AGENT);
+            assert!(!rules.allows("/archive/2024/story"));
+            assert!(rules.allows("/private/story"));
+        }
+
+        #[test]
+        fn falls_back_to_the_wildcard_group() {
+            let rules = Rules::parse(ROBOTS, "other-bot");
+            assert!(rules.allows("/archive/2024/story"));
+            assert!(!rules.allows("/private/story"));
+            assert!(rules.allows("/private/press/release"));
+            assert!(!rules.allows("/reports/annual.pdf"));
+            assert!(rules.allows("/reports/annual.pdf?download=1"));
+        }
+
+        #[test]
+        fn allows_everything_without_rules() {
+            assert!(Rules::parse("", AGENT).allows("/anything"));
+            assert!(Rules::parse("User-agent: *\nDisallow:\n", AGENT).allows("/anything"));
+            assert!(Rules::disallow_all().allows("/robots.txt"));
+            assert!(!Rules::disallow_all().allows("/"));
+        }
+    }
+
+    mod matches {
+        use super::*;
+
+        #[test]
+        fn handles_wildcards_and_anchors() {
+            assert!(matches("/news", "/news/world"));
+            assert!(matches("/*/amp", "/world/amp/story"));
+            assert!(matches("/*.php$", "/index.php"));
+            assert!(!matches("/*.php$", "/index.php?x=1"));
+            assert!(matches("/a*b*c$", "/a-b-b-c"));
+            assert!(!matches("/news$", "/news/world"));
+        }
+    }
+}
@@ -0,0 +1,304 @@
+//! Tiering rules: with `SCORING_FILE` set, a story's tier comes from a score
+//! rather than from the model's say alone. The score adds up weighted
+//! signals (the model's importance rating, how many sources carried the
+//! story, their weight in `sources.toml`, how recent it is, how popular it
+//! is on Hacker News, Reddit, Mastodon, or Bluesky, and its topic),
+//! and thresholds in the same file turn it into must_know, should_know, or
+//! signal. The model still decides what to skip.
+
+use crate::curate::{TOPICS, Tier};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// The rules in `SCORING_FILE`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rules {
+    #[serde(default)]
+    score: Weights,
+    /// Added to the score of stories on a topic
+    #[serde(default)]
+    topics: HashMap<String, f64>,
+    tiers: Thresholds,
+}
+
+/// What each signal, scaled to 0-1, adds to a score
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Weights {
+    /// The model's importance rating, 1-10
+    importance: f64,
+    /// Sources beyond the first, up to `max_sources`
+    sources: f64,
+    max_sources: usize,
+    /// The heaviest source's weight, as configured (1 is the norm)
+    source_weight: f64,
+    /// 1 when just published, falling to 0 at `recency_hours`
+    recency: f64,
+    recency_hours: f64,
+    /// Points and comments on an aggregator, rising with their logarithm to
+    /// 1 at `popular_at`
+    popularity: f64,
+    popular_at: f64,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            importance: 5.0,
+            sources: 2.0,
+            max_sources: 5,
+            source_weight: 1.0,
+            recency: 1.0,
+            recency_hours: 48.0,
+            popularity: 1.0,
+            popular_at: 1000.0,
+        }
+    }
+}
+
+/// Lowest score for each tier, and how many stories each may hold
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Thresholds {
+    must_know: f64,
+    should_know: f64,
+    #[serde(default)]
+    max_must_know: Option<usize>,
+    #[serde(default)]
+    max_should_know: Option<usize>,
+}
+
+/// What's known of a story to score it
+#[derive(Debug)]
+pub struct Story<'a> {
+    /// The model's rating, 1-10, if it gave one
+    pub importance: Option<f64>,
+    pub sources: usize,
+    pub heaviest_source: f64,
+    pub newest: Option<DateTime<Utc>>,
+    /// Points and comments of its most popular report on an aggregator
+    pub popularity: u64,
+    pub topic: Option<&'a str>,
+}
+
+impl Rules {
+    /// The rules in `SCORING_FILE`, if it's set
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let Some(path) = std::env::var("SCORING_FILE").ok().filter(|p| !p.is_empty()) else {
+            return Ok(None);
+        };
+        let contents =
+            std::fs::read_to_string(&path).map_err(|e| format!("Cannot read {path}: {e}"))?;
+        Self::parse(&contents)
+            .map(Some)
+            .map_err(|e| format!("{path}: {e}"))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let rules: Self = toml::from_str(contents).map_err(|e| e.to_string())?;
+        let score = &rules.score;
+        let weights = [
+            score.importance,
+            score.sources,
+            score.source_weight,
+            score.recency,
+            score.popularity,
+            rules.tiers.must_know,
+            rules.tiers.should_know,
+        ];
+        if let Some(weight) = weights
+            .iter()
+            .chain(rules.topics.values())
+            .find(|w| !w.is_finite())
+        {
+            return Err(format!("invalid number {weight}"));
+        }
+        if !(score.recency_hours.is_finite() && score.recency_hours > 0.0) {
+            return Err("score.recency_hours must be above 0".into());
+        }
+        if !(score.popular_at.is_finite() && score.popular_at >= 1.0) {
+            return Err("score.popular_at must be at least 1".into());
+        }
+        if let Some(topic) = rules.topics.keys().find(|t| !TOPICS.contains(&t.as_str())) {
+            return Err(format!(
+                "unknown topic {topic:?} (topics are {})",
+                TOPICS.join(", ")
+            ));
+        }
+        if rules.tiers.must_know < rules.tiers.should_know {
+            return Err("tiers.must_know is below tiers.should_know".into());
+        }
+        Ok(rules)
+    }
+
+    /// `story`'s score at `now`
+    pub fn score(&self, story: &Story, now: DateTime<Utc>) -> f64 {
+        let weights = &self.score;
+        // Unrated stories are middling
+        let importance = story
+            .importance
+            .map_or(0.5, |i| (i.clamp(1.0, 10.0) - 1.0) / 9.0);
+        let sources = if weights.max_sources > 1 {
+            (story.sources.clamp(1, weights.max_sources) - 1) as f64
+                / (weights.max_sources - 1) as f64
+        } else {
+            0.0
+        };
+        let recency = story.newest.map_or(0.0, |published| {
+            let hours = (now - published).num_minutes().max(0) as f64 / 60.0;
+            (1.0 - hours / weights.recency_hours).max(0.0)
+        });
+        let popularity = ((story.popularity as f64).ln_1p() / weights.popular_at.ln_1p()).min(1.0);
+        let topic = story
+            .topic
+            .and_then(|t| self.topics.get(t))
+            .copied()
+            .unwrap_or(0.0);
+        weights.importance * importance
+            + weights.sources * sources
+            + weights.source_weight * story.heaviest_source
+            + weights.recency * recency
+            + weights.popularity * popularity
+            + topic
+    }
+
+    /// Tiers for stories with these scores, each no higher than the tier
+    /// it's given with its score, in the same order. The highest scores
+    /// fill a tier first; those that don't fit drop to the next.
+    pub fn tiers(&self, stories: &[(f64, Tier)]) -> Vec<Tier> {
+        let mut order: Vec<usize> = (0..stories.len()).collect();
+        order.sort_by(|&a, &b| stories[b].0.total_cmp(&stories[a].0));
+        let (mut must_know, mut should_know) = (0, 0);
+        let mut tiers = vec![Tier::Signal; stories.len()];
+        for index in order {
+            let (score, ceiling) = stories[index];
+            let room = |count: usize, max: Option<usize>| max.is_none_or(|max| count < max);
+            if ceiling == Tier::MustKnow
+                && score >= self.tiers.must_know
+                && room(must_know, self.tiers.max_must_know)
+            {
+                must_know += 1;
+                tiers[index] = Tier::MustKnow;
+            } else if matches!(ceiling, Tier::MustKnow | Tier::ShouldKnow)
+                && score >= self.tiers.should_know
+                && room(should_know, self.tiers.max_should_know)
+            {
+                should_know += 1;
+                tiers[index] = Tier::ShouldKnow;
+            }
+        }
+        tiers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: &str = r#"
+        [score]
+        importance = 4.0
+        recency = 2.0
+
+        [topics]
+        privacy = 1.5
+
+        [tiers]
+        must_know = 5.0
+        should_know = 3.0
+        max_must_know = 1
+    "#;
+
+    mod parse {
+        use super::*;
+
+        #[test]
+        fn fills_in_default_weights() {
+            let rules = Rules::parse(RULES).unwrap();
+            assert_eq!(rules.score.importance, 4.0);
+            assert_eq!(rules.score.sources, 2.0);
+            assert_eq!(rules.tiers.max_should_know, None);
+        }
+
+        #[test]
+        fn rejects_unknown_topics_and_crossed_thresholds() {
+            assert_eq!(
+                Rules::parse(&RULES.replace("privacy", "sport")).unwrap_err(),
+                format!("unknown topic \"sport\" (topics are {})", TOPICS.join(", "))
+            );
+            assert!(
+                Rules::parse(&RULES.replace("must_know = 5.0", "must_know = 2.0"))
+                    .unwrap_err()
+                    .contains("below")
+            );
+            assert!(Rules::parse("[tiers]\nmust_know = 1\nshould_know = 0\nmaybe = 2").is_err());
+        }
+    }
+
+    mod score {
+        use super::*;
+
+        #[test]
+        fn adds_up_weighted_signals() {
+            let rules = Rules::parse(RULES).unwrap();
+            let now = Utc::now();
+            let story = Story {
+                importance: Some(10.0),
+                sources: 3,
+                heaviest_source: 1.5,
+                newest: Some(now - chrono::Duration::hours(12)),
+                popularity: 0,
+                topic: Some("privacy"),
+            };
+            // 4 × 1 + 2 × 2/4 + 1 × 1.5 + 2 × 0.75 + 1.5
+            assert!((rules.score(&story, now) - 9.5).abs() < 1e-9);
+            let quiet = Story {
+                importance: Some(1.0),
+                sources: 1,
+                heaviest_source: 1.0,
+                newest: None,
+                popularity: 0,
+                topic: Some("other"),
+            };
+            assert_eq!(rules.score(&quiet, now), 1.0);
+            // ln(1 + 999) / ln(1 + 1000), just short of all of it
+            let discussed = Story {
+                popularity: 999,
+                ..quiet
+            };
+            assert!((rules.score(&discussed, now) - 2.0).abs() < 1e-3);
+            let viral = Story {
+                popularity: 50_000,
+                ..discussed
+            };
+            assert_eq!(rules.score(&viral, now), 2.0);
+        }
+    }
+
+    mod tiers {
+        use super::*;
+
+        #[test]
+        fn fills_tiers_by_score_within_caps_and_ceilings() {
+            let rules = Rules::parse(RULES).unwrap();
+            assert_eq!(
+                rules.tiers(&[
+                    (6.0, Tier::MustKnow),
+                    (8.0, Tier::MustKnow),
+                    (9.0, Tier::Signal),
+                    (3.5, Tier::ShouldKnow),
+                    (2.0, Tier::MustKnow),
+                ]),
+                [
+                    Tier::ShouldKnow,
+                    Tier::MustKnow,
+                    Tier::Signal,
+                    Tier::ShouldKnow,
+                    Tier::Signal,
+                ]
+            );
+        }
+    }
+}
@@ -0,0 +1,305 @@
+//! Google News sitemaps as sources. Some outlets truncate or delay their RSS
+//! but keep a news sitemap (`news-sitemap.xml` and the like) of what they
+//! published in the last two days, each URL with its `news:title`,
+//! `news:publication_date`, and often `news:keywords`. A source whose URL
+//! answers with one is read from it: the keywords go with the article, and
+//! since sitemaps carry no summary, the article's page is where its text
+//! comes from (see `pages`).
+//!
+//! Plain sitemaps list a whole site without dates and aren't news; nor is a
+//! sitemap index, which only names other sitemaps, so the error points at
+//! one of those for the source to use instead.
+
+use crate::feeds::{self, Article};
+use crate::thumbnails;
+use chrono::{DateTime, NaiveDate, Utc};
+use quick_xml::XmlVersion;
+use quick_xml::escape::resolve_xml_entity;
+use quick_xml::events::Event;
+
+/// A `<url>` entry, as read so far
+#[derive(Default)]
+struct Entry {
+    loc: String,
+    title: String,
+    published: String,
+    keywords: String,
+    images: Vec<String>,
+    /// Whether it had a `news:news` element
+    news: bool,
+}
+
+/// Whether `body` is a sitemap or sitemap index, by its root element
+pub fn is_sitemap(body: &[u8]) -> bool {
+    let xml = String::from_utf8_lossy(body);
+    let mut reader = quick_xml::Reader::from_str(&xml);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e) | Event::Empty(e)) => {
+                return matches!(e.local_name().into_inner(), "urlset" | "sitemapindex");
+            }
+            Ok(Event::Eof) | Err(_) => return false,
+            Ok(_) => {}
+        }
+    }
+}
+
+/// The articles in a news sitemap fetched from `url`, dated no later than
+/// `now`. Entries without a title or link are skipped.
+pub fn parse(body: &[u8], url: &str, now: DateTime<Utc>) -> Result<Vec<Article>, String> {
+    let xml = String::from_utf8_lossy(body);
+    let mut reader = quick_xml::Reader::from_str(&xml);
+    let mut path: Vec<String> = Vec::new();
+    let mut entry: Option<Entry> = None;
+    let mut articles = Vec::new();
+    // Entries with and without news:news
+    let (mut news, mut plain) = (0, 0);
+    let mut sitemaps = Vec::new();
+    loop {
+        let event = reader.read_event().map_err(|e| {
+            format!(
+                "Sitemap parse error at byte {}: {e}",
+                reader.buffer_position()
+            )
+        })?;
+        let text = match event {
+            Event::Start(e) => {
+                let name = e.local_name().into_inner().to_string();
+                match (path.last().map(String::as_str), name.as_str()) {
+                    (Some("urlset"), "url") => entry = Some(Entry::default()),
+                    (Some("url"), "news") => {
+                        if let Some(entry) = &mut entry {
+                            entry.news = true;
+                        }
+                    }
+                    _ => {}
+                }
+                path.push(name);
+                continue;
+            }
+            Event::End(_) => {
+                if path.pop().as_deref() == Some("url")
+                    && let Some(done) = entry.take()
+                {
+                    match done.news {
+                        true => news += 1,
+                        false => plain += 1,
+                    }
+                    articles.extend(article(done, url, now));
+                }
+                continue;
+            }
+            Event::Text(e) => e.xml_content(XmlVersion::Implicit1_0).into_owned(),
+            Event::CData(e) => e.xml_content(XmlVersion::Implicit1_0).into_owned(),
+            Event::GeneralRef(e) => match e.resolve_char_ref() {
+                Ok(Some(c)) => c.to_string(),
+                _ => resolve_xml_entity(&e.xml10_content())
+                    .unwrap_or_default()
+                    .to_string(),
+            },
+            Event::Eof => break,
+            _ => continue,
+        };
+        let names: Vec<&str> = path.iter().rev().take(2).map(String::as_str).collect();
+        if let ["loc", "sitemap"] = names[..] {
+            sitemaps.push(text);
+            continue;
+        }
+        let Some(entry) = &mut entry else {
+            continue;
+        };
+        let field = match names[..] {
+            ["loc", "url"] => &mut entry.loc,
+            ["title", "news"] => &mut entry.title,
+            ["publication_date", "news"] => &mut entry.published,
+            ["keywords", "news"] => &mut entry.keywords,
+            ["loc", "image"] => {
+                entry.images.push(text);
+                continue;
+            }
+            _ => continue,
+        };
+        field.push_str(&text);
+    }
+    if let Some(first) = sitemaps.first() {
+        return Err(format!(
+            "A sitemap index, not a news sitemap: use one of its {} sitemaps, such as {}",
+            sitemaps.len(),
+            first.trim()
+        ));
+    }
+    if news == 0 && plain > 0 {
+        return Err("Not a news sitemap: none of its URLs have news:news".into());
+    }
+    Ok(articles)
+}
+
+/// The article an entry is, if it's news with a title and link
+fn article(entry: Entry, base: &str, now: DateTime<Utc>) -> Option<Article> {
+    if !entry.news {
+        return None;
+    }
+    let title = entry.title.split_whitespace().collect::<Vec<_>>().join(" ");
+    let url = reqwest::Url::parse(base)
+        .and_then(|base| base.join(entry.loc.trim()))
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))?;
+    if title.is_empty() {
+        return None;
+    }
+    let keywords = entry
+        .keywords
+        .split(',')
+        .map(|keyword| keyword.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|keyword| !keyword.is_empty())
+        .collect();
+    let listed: Vec<(String, Option<u32>)> = entry
+        .images
+        .into_iter()
+        .map(|image| image.trim().to_string())
+        .filter(|image| image.starts_with("http://") || image.starts_with("https://"))
+        .map(|image| (image, None))
+        .collect();
+    Some(Article {
+        title,
+        url: url.to_string(),
+        published: feeds::plausible(date(entry.published.trim()), now),
+        summary: String::new(),
+        content: None,
+        story: None,
+        image: thumbnails::pick(&listed),
+        points: None,
+        comments: None,
+        keywords,
+    })
+}
+
+/// A W3C datetime, as sitemaps write them: a date, or a date and time to
+/// the minute or second with a time zone
+fn date(s: &str) -> Option<DateTime<Utc>> {
+    let zoned = s.strip_suffix('Z').map(|s| format!("{s}+00:00"));
+    let s = zoned.as_deref().unwrap_or(s);
+    DateTime::parse_from_rfc3339(s)
+        .or_else(|_| DateTime::parse_from_str(s, "%Y-%m-%dT%H:%M%:z"))
+        .map(|date| date.to_utc())
+        .ok()
+        .or_else(|| {
+            let day = NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?;
+            Some(day.and_hms_opt(0, 0, 0)?.and_utc())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        "2025-01-16T00:00:00Z".parse().unwrap()
+    }
+
+    mod is_sitemap {
+        use super::*;
+
+        #[test]
+        fn knows_sitemaps_by_their_root() {
+            assert!(is_sitemap(
+                br#"<?xml version="1.0"?><!-- news --><urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"/>"#
+            ));
+            assert!(is_sitemap(b"<sitemapindex><sitemap/></sitemapindex>"));
+            assert!(!is_sitemap(b"<rss version=\"2.0\"><channel/></rss>"));
+            assert!(!is_sitemap(b"<!DOCTYPE html><html><body>"));
+        }
+    }
+
+    mod parse {
+        use super::*;
+
+        #[test]
+        fn reads_news_entries_with_their_keywords() {
+            let body = br#"<?xml version="1.0" encoding="UTF-8"?>
+                <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9"
+                        xmlns:news="http://www.google.com/schemas/sitemap-news/0.9"
+                        xmlns:image="http://www.google.com/schemas/sitemap-image/1.1">
+                  <url>
+                    <loc>https://example.com/world/talks</loc>
+                    <news:news>
+                      <news:publication><news:name>Example</news:name><news:language>en</news:language></news:publication>
+                      <news:publication_date>2025-01-15T10:30:00+01:00</news:publication_date>
+                      <news:title>Talks resume &amp; stall</news:title>
+                      <news:keywords>Ceasefire, Middle East,  Diplomacy</news:keywords>
+                    </news:news>
+                    <image:image><image:loc>https://example.com/talks.jpg</image:loc></image:image>
+                  </url>
+                  <url>
+                    <loc>/markets</loc>
+                    <news:news>
+                      <news:publication_date>2025-01-15</news:publication_date>
+                      <news:title><![CDATA[Markets <rally>]]></news:title>
+                    </news:news>
+                  </url>
+                  <url>
+                    <loc>https://example.com/untitled</loc>
+                    <news:news><news:publication_date>2025-01-15</news:publication_date></news:news>
+                  </url>
+                </urlset>"#;
+            let articles = parse(body, "https://example.com/news-sitemap.xml", now()).unwrap();
+            assert_eq!(articles.len(), 2);
+            assert_eq!(articles[0].title, "Talks resume & stall");
+            assert_eq!(articles[0].url, "https://example.com/world/talks");
+            assert_eq!(
+                articles[0].published,
+                Some("2025-01-15T09:30:00Z".parse().unwrap())
+            );
+            assert_eq!(
+                articles[0].keywords,
+                ["Ceasefire", "Middle East", "Diplomacy"]
+            );
+            assert_eq!(
+                articles[0].image.as_deref(),
+                Some("https://example.com/talks.jpg")
+            );
+            assert_eq!(articles[1].title, "Markets <rally>");
+            assert_eq!(articles[1].url, "https://example.com/markets");
+            assert_eq!(
+                articles[1].published,
+                Some("2025-01-15T00:00:00Z".parse().unwrap())
+            );
+            assert!(articles[1].keywords.is_empty());
+        }
+
+        #[test]
+        fn turns_away_plain_sitemaps_and_indexes() {
+            let plain = br#"<urlset><url><loc>https://example.com/about</loc></url></urlset>"#;
+            assert!(parse(plain, "https://example.com/sitemap.xml", now()).is_err());
+            let index = br#"<sitemapindex>
+                <sitemap><loc>https://example.com/news-1.xml</loc></sitemap>
+                <sitemap><loc>https://example.com/news-2.xml</loc></sitemap>
+              </sitemapindex>"#;
+            let error = parse(index, "https://example.com/sitemap.xml", now()).unwrap_err();
+            assert!(error.contains("https://example.com/news-1.xml"), "{error}");
+            let empty = br#"<urlset xmlns:news="http://www.google.com/schemas/sitemap-news/0.9"/>"#;
+            assert_eq!(
+                parse(empty, "https://example.com/news.xml", now()),
+                Ok(vec![])
+            );
+        }
+    }
+
+    mod date {
+        use super::*;
+
+        #[test]
+        fn reads_w3c_datetimes() {
+            let expected = "2025-01-15T10:30:00Z".parse().ok();
+            for s in [
+                "2025-01-15T10:30:00Z",
+                "2025-01-15T10:30:00.000+00:00",
+                "2025-01-15T11:30+01:00",
+                "2025-01-15T10:30Z",
+            ] {
+                assert_eq!(date(s), expected, "{s}");
+            }
+            assert_eq!(date("yesterday"), None);
+        }
+    }
+}
@@ -0,0 +1,419 @@
+//! Mastodon and Bluesky as sources. A source whose URL is a Mastodon
+//! account (`https://instance/@name`) or hashtag (`https://instance/tags/name`)
+//! is read from the instance's API, once the instance confirms it's one
+//! (the posts' listing is then kept in `discovered_feeds`, like a site's
+//! feed); a Bluesky profile (`https://bsky.app/profile/handle`) or hashtag
+//! (`https://bsky.app/hashtag/name`) from Bluesky's public API. Only posts
+//! linking to an article count: each linked article becomes one, carrying
+//! the posts' boosts and likes as points and their replies as comments,
+//! summed over every post linking it, so scoring counts them as popularity.
+//! Articles with less engagement than `SOCIAL_MIN_ENGAGEMENT` are left out.
+
+use crate::feeds::{Article, MAX_SUMMARY_CHARS};
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Bluesky's public API
+const BLUESKY: &str = "https://public.api.bsky.app/xrpc/";
+
+/// Posts asked for per listing
+const POSTS: usize = 40;
+
+/// Engagement an article needs, unless `SOCIAL_MIN_ENGAGEMENT` says otherwise
+const DEFAULT_MIN_ENGAGEMENT: u64 = 10;
+
+/// A Mastodon post
+#[derive(Deserialize)]
+struct Status {
+    created_at: Option<String>,
+    #[serde(default)]
+    reblogs_count: u64,
+    #[serde(default)]
+    favourites_count: u64,
+    #[serde(default)]
+    replies_count: u64,
+    card: Option<Card>,
+    /// The post boosted, for a boost
+    reblog: Option<Box<Status>>,
+}
+
+/// A Mastodon post's preview of the page it links to
+#[derive(Deserialize)]
+struct Card {
+    url: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    description: String,
+    image: Option<String>,
+}
+
+/// A Bluesky author feed or search
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Posts {
+    feed: Vec<FeedItem>,
+    posts: Vec<Post>,
+}
+
+#[derive(Deserialize)]
+struct FeedItem {
+    post: Post,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Post {
+    #[serde(default)]
+    indexed_at: Option<String>,
+    embed: Option<Embed>,
+    #[serde(default)]
+    like_count: u64,
+    #[serde(default)]
+    repost_count: u64,
+    #[serde(default)]
+    quote_count: u64,
+    #[serde(default)]
+    reply_count: u64,
+}
+
+/// What a Bluesky post embeds: a link's preview, or one alongside a quote
+#[derive(Deserialize)]
+struct Embed {
+    external: Option<External>,
+    media: Option<Box<Embed>>,
+}
+
+#[derive(Deserialize)]
+struct External {
+    uri: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    description: String,
+    thumb: Option<String>,
+}
+
+/// What a Mastodon instance says of an account or hashtag
+#[derive(Deserialize)]
+struct Lookup {
+    id: Option<String>,
+    name: Option<String>,
+}
+
+/// The listing to read for the Bluesky profile or hashtag at `url`
+pub fn listing_url(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    if !matches!(url.host_str()?, "bsky.app" | "www.bsky.app") {
+        return None;
+    }
+    let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    let (method, params) = match segments[..] {
+        ["profile", actor, ..] => (
+            "app.bsky.feed.getAuthorFeed",
+            vec![
+                ("actor", actor.to_string()),
+                ("filter", "posts_no_replies".into()),
+            ],
+        ),
+        ["hashtag", tag] => (
+            "app.bsky.feed.searchPosts",
+            vec![("q", format!("#{tag}")), ("sort", "top".into())],
+        ),
+        _ => return None,
+    };
+    let mut listing = Url::parse(&format!("{BLUESKY}{method}")).ok()?;
+    listing
+        .query_pairs_mut()
+        .extend_pairs(params)
+        .append_pair("limit", &POSTS.to_string());
+    Some(listing.into())
+}
+
+/// Where to ask the instance about the account or hashtag at `url`, if it
+/// might be on Mastodon
+pub fn mastodon_lookup(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    // The sites using the same paths for something else
+    if matches!(
+        url.host_str()?,
+        "bsky.app" | "medium.com" | "youtube.com" | "www.youtube.com" | "m.youtube.com"
+    ) {
+        return None;
+    }
+    let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    let mut lookup = url.clone();
+    lookup.set_query(None);
+    lookup.set_fragment(None);
+    match segments[..] {
+        [account] if account.len() > 1 && account.starts_with('@') => {
+            lookup.set_path("/api/v1/accounts/lookup");
+            lookup.query_pairs_mut().append_pair("acct", &account[1..]);
+        }
+        ["tags", tag] => lookup.set_path(&format!("/api/v1/tags/{tag}")),
+        _ => return None,
+    }
+    Some(lookup.into())
+}
+
+/// The listing of posts that the instance's answer to `lookup` points to,
+/// if the answer is Mastodon's
+pub fn mastodon_listing(lookup: &str, body: &[u8]) -> Option<String> {
+    let answer: Lookup = serde_json::from_slice(body).ok()?;
+    let mut listing = Url::parse(lookup).ok()?;
+    listing.set_query(None);
+    let limit = format!("limit={POSTS}");
+    if listing.path() == "/api/v1/accounts/lookup" {
+        let id = answer
+            .id
+            .filter(|id| id.bytes().all(|b| b.is_ascii_digit()))?;
+        listing.set_path(&format!("/api/v1/accounts/{id}/statuses"));
+        listing.set_query(Some(&format!("exclude_replies=true&{limit}")));
+    } else {
+        let name = answer.name.filter(|n| !n.is_empty())?;
+        listing.set_path(&format!("/api/v1/timelines/tag/{name}"));
+        listing.set_query(Some(&limit));
+    }
+    Some(listing.into())
+}
+
+/// Whether `url` is a listing [`listing_url`] or [`mastodon_listing`] gives
+pub fn is_listing(url: &str) -> bool {
+    let Ok(url) = Url::parse(url) else {
+        return false;
+    };
+    let path = url.path();
+    if url.as_str().starts_with(BLUESKY) {
+        return path.ends_with("/app.bsky.feed.getAuthorFeed")
+            || path.ends_with("/app.bsky.feed.searchPosts");
+    }
+    path.starts_with("/api/v1/timelines/tag/")
+        || (path.starts_with("/api/v1/accounts/") && path.ends_with("/statuses"))
+}
+
+/// `SOCIAL_MIN_ENGAGEMENT`, or the default
+fn min_engagement() -> u64 {
+    std::env::var("SOCIAL_MIN_ENGAGEMENT")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MIN_ENGAGEMENT)
+}
+
+/// The articles the posts in a listing fetched from `url` link to, dated no
+/// later than `now`, the most engaged with first
+pub fn parse(body: &[u8], url: &str, now: DateTime<Utc>) -> Result<Vec<Article>, String> {
+    let error = |e: serde_json::Error| format!("Listing parse error: {e}");
+    // Each post's link, its preview, and its (points, comments)
+    let links: Vec<(External, Option<String>, (u64, u64))> = if url.starts_with(BLUESKY) {
+        let listing: Posts = serde_json::from_slice(body).map_err(error)?;
+        listing
+            .feed
+            .into_iter()
+            .map(|item| item.post)
+            .chain(listing.posts)
+            .filter_map(|post| {
+                let embed = post.embed?;
+                let external = embed
+                    .external
+                    .or_else(|| embed.media.and_then(|media| media.external))?;
+                let points = post.like_count + post.repost_count + post.quote_count;
+                Some((external, post.indexed_at, (points, post.reply_count)))
+            })
+            .collect()
+    } else {
+        let statuses: Vec<Status> = serde_json::from_slice(body).map_err(error)?;
+        statuses
+            .into_iter()
+            .map(|status| match status.reblog {
+                Some(boosted) => *boosted,
+                None => status,
+            })
+            .filter_map(|status| {
+                let card = status.card?;
+                let link = External {
+                    uri: card.url,
+                    title: card.title,
+                    description: card.description,
+                    thumb: card.image,
+                };
+                let points = status.reblogs_count + status.favourites_count;
+                Some((link, status.created_at, (points, status.replies_count)))
+            })
+            .collect()
+    };
+
+    let mut articles: Vec<Article> = Vec::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for (link, posted, (points, comments)) in links {
+        let is_web = Url::parse(&link.uri).is_ok_and(|u| matches!(u.scheme(), "http" | "https"));
+        let title = link.title.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !is_web || title.is_empty() {
+            continue;
+        }
+        let posted = posted
+            .and_then(|date| DateTime::parse_from_rfc3339(&date).ok())
+            .map(|date| date.with_timezone(&Utc).min(now));
+        if let Some(&index) = seen.get(&link.uri) {
+            let article: &mut Article = &mut articles[index];
+            article.points = Some(article.points.unwrap_or(0) + points);
+            article.comments = Some(article.comments.unwrap_or(0) + comments);
+            // When it was first posted
+            article.published = match (article.published, posted) {
+                (Some(first), Some(posted)) => Some(first.min(posted)),
+                (first, posted) => first.or(posted),
+            };
+            continue;
+        }
+        seen.insert(link.uri.clone(), articles.len());
+        articles.push(Article {
+            title,
+            url: link.uri,
+            published: posted,
+            summary: link
+                .description
+                .trim()
+                .chars()
+                .take(MAX_SUMMARY_CHARS)
+                .collect(),
+            content: None,
+            story: None,
+            image: link.thumb,
+            points: Some(points),
+            comments: Some(comments),
+            keywords: Vec::new(),
+        });
+    }
+    let engagement = |a: &Article| a.points.unwrap_or(0) + a.comments.unwrap_or(0);
+    let min_engagement = min_engagement();
+    articles.retain(|a| engagement(a) >= min_engagement);
+    articles.sort_by_key(|a| std::cmp::Reverse(engagement(a)));
+    Ok(articles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod listing_url {
+        use super::*;
+
+        #[test]
+        fn reads_bluesky_profiles_and_hashtags() {
+            assert_eq!(
+                listing_url("https://bsky.app/profile/news.example.com").as_deref(),
+                Some(
+                    "https://public.api.bsky.app/xrpc/app.bsky.feed.getAuthorFeed?actor=news.example.com&filter=posts_no_replies&limit=40"
+                )
+            );
+            assert_eq!(
+                listing_url("https://bsky.app/hashtag/climate").as_deref(),
+                Some(
+                    "https://public.api.bsky.app/xrpc/app.bsky.feed.searchPosts?q=%23climate&sort=top&limit=40"
+                )
+            );
+            assert_eq!(listing_url("https://bsky.app/"), None);
+            assert_eq!(listing_url("https://example.com/profile/x"), None);
+        }
+    }
+
+    mod mastodon {
+        use super::*;
+
+        #[test]
+        fn finds_the_listing_of_accounts_and_hashtags() {
+            let lookup = mastodon_lookup("https://mastodon.social/@journalist").unwrap();
+            assert_eq!(
+                lookup,
+                "https://mastodon.social/api/v1/accounts/lookup?acct=journalist"
+            );
+            assert_eq!(
+                mastodon_listing(&lookup, br#"{"id": "10923", "username": "journalist"}"#)
+                    .as_deref(),
+                Some(
+                    "https://mastodon.social/api/v1/accounts/10923/statuses?exclude_replies=true&limit=40"
+                )
+            );
+            let lookup = mastodon_lookup("https://mastodon.social/tags/Climate").unwrap();
+            assert_eq!(
+                mastodon_listing(&lookup, br#"{"name": "climate", "url": "x"}"#).as_deref(),
+                Some("https://mastodon.social/api/v1/timelines/tag/climate?limit=40")
+            );
+            // A blog's tag page answers with a page, not Mastodon's JSON
+            let lookup = mastodon_lookup("https://blog.example/tags/rust").unwrap();
+            assert_eq!(mastodon_listing(&lookup, b"<!doctype html>"), None);
+            assert_eq!(mastodon_lookup("https://medium.com/@writer"), None);
+            assert_eq!(mastodon_lookup("https://example.com/news/1"), None);
+            assert!(is_listing(
+                "https://mastodon.social/api/v1/accounts/10923/statuses?limit=40"
+            ));
+            assert!(!is_listing("https://mastodon.social/@journalist.rss"));
+        }
+    }
+
+    mod parse {
+        use super::*;
+
+        fn now() -> DateTime<Utc> {
+            "2025-01-16T00:00:00Z".parse().unwrap()
+        }
+
+        #[test]
+        fn sums_the_engagement_of_posts_linking_an_article() {
+            let body = br#"[
+                {"created_at": "2025-01-15T11:00:00.000Z", "reblogs_count": 3, "favourites_count": 4,
+                 "replies_count": 1, "card": {"url": "https://example.com/a", "title": "Truce holds",
+                 "description": "Both sides.", "type": "link", "image": null}, "reblog": null},
+                {"created_at": "2025-01-15T12:00:00.000Z", "reblogs_count": 0, "favourites_count": 0,
+                 "replies_count": 0, "card": null, "reblog":
+                    {"created_at": "2025-01-15T10:30:00.000Z", "reblogs_count": 5, "favourites_count": 2,
+                     "replies_count": 0, "card": {"url": "https://example.com/a", "title": "Truce holds",
+                     "type": "link"}, "reblog": null}},
+                {"created_at": "2025-01-15T12:00:00.000Z", "reblogs_count": 1, "favourites_count": 1,
+                 "replies_count": 0, "card": {"url": "https://example.com/b", "title": "Quiet news",
+                 "type": "link"}, "reblog": null},
+                {"created_at": "2025-01-15T12:00:00.000Z", "reblogs_count": 90, "favourites_count": 90,
+                 "replies_count": 9, "card": null, "reblog": null}
+            ]"#;
+            let url = "https://mastodon.social/api/v1/timelines/tag/news?limit=40";
+            let articles = parse(body, url, now()).unwrap();
+            assert_eq!(articles.len(), 1);
+            assert_eq!(articles[0].url, "https://example.com/a");
+            assert_eq!(articles[0].summary, "Both sides.");
+            assert_eq!(
+                (articles[0].points, articles[0].comments),
+                (Some(14), Some(1))
+            );
+            assert_eq!(
+                articles[0].published,
+                Some("2025-01-15T10:30:00Z".parse().unwrap())
+            );
+        }
+
+        #[test]
+        fn reads_links_in_bluesky_posts() {
+            let body = br#"{"feed": [
+                {"post": {"uri": "at://x", "indexedAt": "2025-01-15T10:30:00.000Z",
+                  "embed": {"$type": "app.bsky.embed.external#view", "external":
+                    {"uri": "https://example.com/c", "title": "Chip rules", "description": "New.",
+                     "thumb": "https://cdn.example/c.jpg"}},
+                  "likeCount": 40, "repostCount": 12, "quoteCount": 2, "replyCount": 6}},
+                {"post": {"uri": "at://y", "indexedAt": "2025-01-15T10:30:00.000Z",
+                  "likeCount": 400, "repostCount": 12, "replyCount": 6}}
+            ]}"#;
+            let url = listing_url("https://bsky.app/profile/news.example.com").unwrap();
+            let articles = parse(body, &url, now()).unwrap();
+            assert_eq!(articles.len(), 1);
+            assert_eq!(articles[0].title, "Chip rules");
+            assert_eq!(
+                articles[0].image.as_deref(),
+                Some("https://cdn.example/c.jpg")
+            );
+            assert_eq!(
+                (articles[0].points, articles[0].comments),
+                (Some(54), Some(6))
+            );
+        }
+    }
+}
@@ -0,0 +1,487 @@
+//! Spam and junk: affiliate listicles, deal roundups, link farms, and SEO
+//! filler that feeds mix in with the news. They'd only dilute the stories
+//! they were grouped into and cost tokens to curate, so the fetch drops them
+//! before deduplication and clustering, on a few conservative heuristics:
+//!
+//! - a link from a domain in the `SPAM_BLOCKLIST` file (one domain per line,
+//!   its subdomains included; hosts-file lines work too)
+//! - a summary that is mostly links
+//! - a summary that is mostly shopping boilerplate (promo codes, "we may earn
+//!   a commission", and the like)
+//! - a title shaped like a shopping listicle or a "how to watch free" page
+//! - text that keeps repeating the same few words
+//!
+//! `SPAM_FILTER=0` turns the heuristics off, leaving the blocklist. With
+//! `SPAM_LLM_CHECK` set, `curate` also has the model that writes up the lower
+//! tiers pick out what's left of the junk before curation, within the run's
+//! budget.
+
+use crate::budget::Budget;
+use crate::curate::{self, Cluster};
+use crate::feeds::Article;
+use crate::llm::{Model, Usage};
+use crate::pages::plain_text;
+use regex::Regex;
+use reqwest::Url;
+use scraper::{Html, Selector};
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+
+/// Fewest characters of text a summary needs for its makeup to be judged
+const MIN_JUDGED_CHARS: usize = 80;
+
+/// Share of a summary's text in links that makes it a link farm, given at
+/// least [`MIN_LINKS`] links
+const MAX_LINK_SHARE: f64 = 0.6;
+const MIN_LINKS: usize = 3;
+
+/// Share of a summary's text in shopping boilerplate that makes it an ad
+const MAX_BOILERPLATE_SHARE: f64 = 0.5;
+
+/// Fewest words a text needs to be judged repetitive, and the share of them
+/// that must be distinct for it not to be
+const MIN_REPETITION_WORDS: usize = 60;
+const MIN_DISTINCT_SHARE: f64 = 0.35;
+
+/// Articles per request of the model check
+const ARTICLES_PER_REQUEST: usize = 40;
+
+/// Text the model sees per article, in characters
+const CHECKED_CHARS: usize = 300;
+
+static LINK: LazyLock<Selector> = LazyLock::new(|| Selector::parse("a").expect("valid selector"));
+
+/// Titles of shopping listicles and SEO pages
+static JUNK_TITLE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?ix)
+        ^\s*(?:the\s+)?\d+\s+(?:best|cheapest|top-rated)\b
+        | \b(?:promo|coupon|discount|voucher)\s+codes?\b
+        | \b\d{1,2}%\s+off\b
+        | \bbest\s[\w\s'-]{0,40}\bdeals\b
+        | \bdeals?\s+of\s+the\s+(?:day|week)\b
+        | \bhow\s+to\s+watch\b.*\b(?:free|without\s+cable)\b
+        | \blive\s*stream(?:ing)?\b.*\bfree\b",
+    )
+    .expect("valid regex")
+});
+
+/// Sentences of shopping boilerplate
+static BOILERPLATE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"(?ix)
+        \b(?:earn|receive)\s+(?:a\s+)?(?:small\s+)?commission\b
+        | \baffiliate\s+(?:links?|commissions?|partners?)\b
+        | \b(?:promo|coupon|discount|voucher)\s+codes?\b
+        | \b(?:buy|shop|order)\s+now\b
+        | \b(?:sponsored\s+(?:post|content)|paid\s+partnership)\b
+        | \b(?:free\s+shipping|add\s+to\s+cart|limited[\s-]time\s+offer)\b
+        | \b(?:lowest|best)\s+price\b
+        | \b\d{1,2}%\s+off\b",
+    )
+    .expect("valid regex")
+});
+
+static SENTENCE_END: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"[.!?]+\s+").expect("valid regex"));
+
+/// The checks the fetch makes
+#[derive(Debug, Default)]
+pub struct Screen {
+    /// Blocklisted domains, lowercase
+    blocklist: HashSet<String>,
+    heuristics: bool,
+}
+
+impl Screen {
+    /// The heuristics, unless `SPAM_FILTER` is `0` or `false`, and the
+    /// domains of the `SPAM_BLOCKLIST` file, if set
+    pub fn from_env() -> Result<Self, String> {
+        let heuristics = std::env::var("SPAM_FILTER").map_or(true, |v| v != "0" && v != "false");
+        let blocklist = match std::env::var("SPAM_BLOCKLIST") {
+            Ok(path) => std::fs::read_to_string(&path)
+                .map(|list| blocklist(&list))
+                .map_err(|e| format!("Cannot read SPAM_BLOCKLIST {path}: {e}"))?,
+            Err(_) => HashSet::new(),
+        };
+        Ok(Self {
+            blocklist,
+            heuristics,
+        })
+    }
+
+    /// Why `article` looks like junk, if it does
+    pub fn reason(&self, article: &Article) -> Option<&'static str> {
+        if self.is_blocklisted(&article.url) {
+            return Some("blocklisted domain");
+        }
+        if !self.heuristics {
+            return None;
+        }
+        if JUNK_TITLE.is_match(&article.title) {
+            return Some("shopping or SEO title");
+        }
+        if is_link_farm(&article.summary) {
+            return Some("mostly links");
+        }
+        let text = plain_text(article.content.as_deref().unwrap_or(&article.summary));
+        if boilerplate_share(&text) >= MAX_BOILERPLATE_SHARE {
+            return Some("shopping boilerplate");
+        }
+        if is_repetitive(&text) {
+            return Some("repetitive text");
+        }
+        None
+    }
+
+    fn is_blocklisted(&self, url: &str) -> bool {
+        if self.blocklist.is_empty() {
+            return false;
+        }
+        let Some(host) = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_lowercase))
+        else {
+            return false;
+        };
+        // The host, and each domain it's under
+        let mut domain = host.as_str();
+        loop {
+            if self.blocklist.contains(domain) {
+                return true;
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) if parent.contains('.') => domain = parent,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Drop the articles that look like junk, and how many each source lost
+    pub fn apply(&self, kept: &mut [(&str, Vec<Article>)]) -> HashMap<String, usize> {
+        let mut dropped = HashMap::new();
+        for (source_id, articles) in kept.iter_mut() {
+            articles.retain(|article| {
+                let Some(reason) = self.reason(article) else {
+                    return true;
+                };
+                tracing::debug!("[{}] Dropped {} ({})", source_id, article.url, reason);
+                *dropped.entry(source_id.to_string()).or_default() += 1;
+                false
+            });
+        }
+        dropped
+    }
+}
+
+/// The domains of a blocklist file: one a line, or a hosts file's, with
+/// `#` comments
+fn blocklist(list: &str) -> HashSet<String> {
+    list.lines()
+        .filter_map(|line| line.split('#').next()?.split_whitespace().last())
+        .map(|domain| domain.trim_start_matches("*.").to_lowercase())
+        .filter(|domain| domain.contains('.'))
+        .collect()
+}
+
+/// Whether a summary's text is mostly the text of its links
+fn is_link_farm(html: &str) -> bool {
+    let document = Html::parse_fragment(html);
+    let chars = |text: &mut dyn Iterator<Item = &str>| -> usize {
+        text.map(|t| t.chars().filter(|c| !c.is_whitespace()).count())
+            .sum()
+    };
+    let total = chars(&mut document.root_element().text());
+    if total < MIN_JUDGED_CHARS {
+        return false;
+    }
+    let links: Vec<_> = document.select(&LINK).collect();
+    let linked: usize = links.iter().map(|link| chars(&mut link.text())).sum();
+    links.len() >= MIN_LINKS && linked as f64 / total as f64 >= MAX_LINK_SHARE
+}
+
+/// The share of `text`, by characters, in sentences of shopping boilerplate
+fn boilerplate_share(text: &str) -> f64 {
+    let total = text.trim().chars().count();
+    if total < MIN_JUDGED_CHARS {
+        return 0.0;
+    }
+    let boilerplate: usize = SENTENCE_END
+        .split(text)
+        .filter(|sentence| BOILERPLATE.is_match(sentence))
+        .map(|sentence| sentence.chars().count())
+        .sum();
+    boilerplate as f64 / total as f64
+}
+
+/// Whether `text` is long and made of few distinct words, as text written
+/// to rank for them is
+fn is_repetitive(text: &str) -> bool {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    if words.len() < MIN_REPETITION_WORDS {
+        return false;
+    }
+    let distinct: HashSet<&String> = words.iter().collect();
+    (distinct.len() as f64 / words.len() as f64) < MIN_DISTINCT_SHARE
+}
+
+/// Whether `SPAM_LLM_CHECK` asks for the model check
+pub fn checking() -> bool {
+    std::env::var("SPAM_LLM_CHECK").is_ok_and(|v| v == "1" || v == "true")
+}
+
+/// The prompt asking which of `articles`, as (id, title, text), are junk
+fn prompt(articles: &[(String, String, String)]) -> String {
+    let mut prompt = String::from(
+        "Which of these feed items are junk rather than news or analysis: advertising, \
+         shopping listicles and deal roundups, coupon pages, press-release spam, or filler \
+         written to rank in search rather than inform? Judge conservatively; when in doubt, \
+         it's news. Reply with only a JSON array of the junk items' ids, or [] if none are.\n",
+    );
+    for (id, title, text) in articles {
+        prompt.push_str(&format!("\n### {id}\nTitle: {title}\nText: {text}\n"));
+    }
+    prompt
+}
+
+/// Have `model` pick out the junk among the articles of `clusters`, a batch
+/// per request, within `budget`, and drop it, along with any cluster left
+/// empty. Batches the budget can't cover, or whose reply can't be read, are
+/// kept. Returns how many articles were dropped and the tokens that took.
+pub async fn check(
+    model: &Model,
+    budget: &Budget,
+    system: &str,
+    clusters: &mut Vec<Cluster<'_>>,
+) -> (usize, Usage) {
+    let mut articles = Vec::new();
+    for (cluster, c) in clusters.iter().enumerate() {
+        for (index, (_, article)) in c.articles.iter().enumerate() {
+            let text = plain_text(article.content.as_deref().unwrap_or(&article.summary));
+            articles.push((
+                (cluster, index),
+                (
+                    format!("a{}", articles.len() + 1),
+                    plain_text(&article.title),
+                    text.chars().take(CHECKED_CHARS).collect::<String>(),
+                ),
+            ));
+        }
+    }
+    let (mut junk, mut usage) = (HashSet::new(), Usage::default());
+    for batch in articles.chunks(ARTICLES_PER_REQUEST) {
+        let listed: Vec<_> = batch.iter().map(|(_, article)| article.clone()).collect();
+        // An id and its quotes each, and the brackets
+        let max_tokens = 8 * batch.len() as u32 + 16;
+        let replied =
+            curate::ask::<Vec<String>>(model, budget, system, &prompt(&listed), max_tokens).await;
+        let ids = match replied {
+            Ok(Some((ids, used))) => {
+                usage += used;
+                ids
+            }
+            Ok(None) => {
+                tracing::warn!("Over budget: the rest of the articles weren't checked for spam");
+                break;
+            }
+            Err(e) => {
+                tracing::warn!("Cannot check {} articles for spam: {}", batch.len(), e);
+                continue;
+            }
+        };
+        let ids: HashSet<String> = ids.into_iter().collect();
+        junk.extend(
+            batch
+                .iter()
+                .filter(|(_, (id, ..))| ids.contains(id))
+                .map(|(at, _)| *at),
+        );
+    }
+    (drop_articles(clusters, &junk), usage)
+}
+
+/// Drop the articles at `at` (cluster, index) from `clusters`, and the
+/// clusters left empty, returning how many articles went
+fn drop_articles(clusters: &mut Vec<Cluster<'_>>, at: &HashSet<(usize, usize)>) -> usize {
+    for (cluster, c) in clusters.iter_mut().enumerate() {
+        let mut index = 0;
+        c.articles.retain(|_| {
+            index += 1;
+            !at.contains(&(cluster, index - 1))
+        });
+    }
+    clusters.retain(|c| !c.articles.is_empty());
+    at.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article(title: &str, summary: &str) -> Article {
+        Article {
+            title: title.into(),
+            url: "https://news.example.com/a".into(),
+            published: None,
+            summary: summary.into(),
+            content: None,
+            story: None,
+            image: None,
+            points: None,
+            comments: None,
+            keywords: Vec::new(),
+        }
+    }
+
+    fn screen() -> Screen {
+        Screen {
+            blocklist: blocklist(
+                "0.0.0.0 spam.example\n# deals\n*.deals.example  # and subdomains\n",
+            ),
+            heuristics: true,
+        }
+    }
+
+    mod reason {
+        use super::*;
+
+        #[test]
+        fn passes_news() {
+            for (title, summary) in [
+                (
+                    "Ceasefire deal for Gaza agreed",
+                    "<p>Talks in Cairo ended.</p>",
+                ),
+                (
+                    "European Commission fines chipmaker",
+                    "<p>The commission said the firm abused its position. \
+                     <a href=\"/a\">Read more</a></p><p>The post <a href=\"/b\">Fines</a> \
+                     appeared first on <a href=\"/\">Example News</a>.</p>",
+                ),
+                (
+                    "Bill sponsored by senator passes",
+                    "An al-Qaeda affiliate claimed it.",
+                ),
+                ("How to watch the eclipse safely", ""),
+            ] {
+                assert_eq!(screen().reason(&article(title, summary)), None, "{title}");
+            }
+        }
+
+        #[test]
+        fn catches_listicles_and_seo_titles() {
+            for title in [
+                "The 12 best air fryers of 2026",
+                "Nike promo codes: 20% off in October",
+                "Best Prime Day laptop deals",
+                "How to watch the final live stream for free",
+            ] {
+                assert_eq!(
+                    screen().reason(&article(title, "")),
+                    Some("shopping or SEO title"),
+                    "{title}"
+                );
+            }
+        }
+
+        #[test]
+        fn catches_link_farms_and_boilerplate() {
+            let links = "<p><a href=\"/1\">Cheap flights to anywhere today</a> \
+                         <a href=\"/2\">Top loans for bad credit approved</a> \
+                         <a href=\"/3\">Win big at the online casino now</a> and more</p>";
+            assert_eq!(
+                screen().reason(&article("Offers", links)),
+                Some("mostly links")
+            );
+            let ad = "Shop now and save. Use our promo code for free shipping on every order \
+                      this week. We may earn a commission from links on this page.";
+            assert_eq!(
+                screen().reason(&article("Our picks", ad)),
+                Some("shopping boilerplate")
+            );
+            let chaff = "cheap shoes online best cheap shoes buy cheap shoes online ".repeat(8);
+            assert_eq!(
+                screen().reason(&article("Shoes", &chaff)),
+                Some("repetitive text")
+            );
+        }
+
+        #[test]
+        fn blocks_listed_domains_and_their_subdomains() {
+            let mut spam = article("Talks resume", "");
+            for url in ["https://spam.example/a", "https://www.deals.example/b"] {
+                spam.url = url.into();
+                assert_eq!(screen().reason(&spam), Some("blocklisted domain"), "{url}");
+            }
+            spam.url = "https://notspam.example/a".into();
+            assert_eq!(screen().reason(&spam), None);
+            let off = Screen {
+                heuristics: false,
+                ..screen()
+            };
+            assert_eq!(off.reason(&article("The 12 best air fryers", "")), None);
+        }
+    }
+
+    mod apply {
+        use super::*;
+
+        #[test]
+        fn counts_what_each_source_lost() {
+            let mut kept = vec![(
+                "gadgets",
+                vec![
+                    article("The 10 best phones", ""),
+                    article("Chipmaker fined", ""),
+                ],
+            )];
+            assert_eq!(
+                screen().apply(&mut kept),
+                HashMap::from([("gadgets".to_string(), 1)])
+            );
+            assert_eq!(kept[0].1.len(), 1);
+        }
+    }
+
+    mod drop_articles {
+        use super::*;
+        use crate::curate::FetchedArticle;
+        use crate::sources::Source;
+
+        fn fetched(url: &str) -> FetchedArticle {
+            serde_json::from_str(&format!(r#"{{"title": "T", "url": "{url}"}}"#)).unwrap()
+        }
+
+        #[test]
+        fn drops_clusters_left_empty() {
+            let source: Source = crate::sources::parse(
+                "[[source]]\nid = \"a\"\nname = \"A\"\nurl = \"https://a.example\"\n\
+                 bias = \"center\"\nperspective = \"western\"\n",
+            )
+            .unwrap()
+            .remove(0);
+            let mut clusters = vec![
+                Cluster {
+                    id: "s1".into(),
+                    articles: vec![
+                        (&source, fetched("https://a/1")),
+                        (&source, fetched("https://a/2")),
+                    ],
+                },
+                Cluster {
+                    id: "a3".into(),
+                    articles: vec![(&source, fetched("https://a/3"))],
+                },
+            ];
+            let at = HashSet::from([(0, 1), (1, 0)]);
+            assert_eq!(drop_articles(&mut clusters, &at), 2);
+            assert_eq!(clusters.len(), 1);
+            assert_eq!(clusters[0].articles.len(), 1);
+            assert_eq!(clusters[0].articles[0].1.url, "https://a/1");
+        }
+    }
+}
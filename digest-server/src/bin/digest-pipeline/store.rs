@@ -0,0 +1,1353 @@
+//! The pipeline's database writes and fetched-article files, in the same
+//! tables and formats as `run.py`
+
+use crate::curate::{REGIONS, Selections, SourceRef};
+use crate::feeds::{self, Article};
+use crate::fetch::{FetchResult, Validators};
+use crate::render::{self, Digest, Narrative};
+use crate::sources::Source;
+use crate::thumbnails::Picture;
+use rusqlite::{Connection, OptionalExtension};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// Tables the pipeline writes, created if this is the first run
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS source_activity (
+    source_id TEXT PRIMARY KEY,
+    fingerprint TEXT NOT NULL,
+    last_new_item_at DATETIME NOT NULL
+);
+CREATE TABLE IF NOT EXISTS robots_txt (
+    origin TEXT PRIMARY KEY,
+    body TEXT NOT NULL,
+    fetched_at DATETIME NOT NULL
+);
+CREATE TABLE IF NOT EXISTS article_cache (
+    url TEXT PRIMARY KEY,
+    content TEXT,
+    fetched_at DATETIME NOT NULL
+);
+CREATE TABLE IF NOT EXISTS article_archive (
+    url TEXT PRIMARY KEY,
+    source_id TEXT NOT NULL,
+    published_at DATETIME,
+    article TEXT NOT NULL,
+    archived_at DATETIME NOT NULL
+);
+CREATE INDEX IF NOT EXISTS idx_article_archive_published ON article_archive(published_at);
+CREATE TABLE IF NOT EXISTS backfilled_digests (
+    date TEXT PRIMARY KEY,
+    articles INTEGER NOT NULL,
+    backfilled_at DATETIME NOT NULL
+);
+CREATE TABLE IF NOT EXISTS feed_validators (
+    source_id TEXT PRIMARY KEY,
+    etag TEXT,
+    last_modified TEXT,
+    fetched_at DATETIME NOT NULL
+);
+CREATE TABLE IF NOT EXISTS discovered_feeds (
+    source_id TEXT PRIMARY KEY,
+    site_url TEXT NOT NULL,
+    feed_url TEXT NOT NULL,
+    discovered_at DATETIME NOT NULL
+);
+CREATE TABLE IF NOT EXISTS images (
+    hash TEXT PRIMARY KEY,
+    content_type TEXT NOT NULL,
+    data BLOB NOT NULL,
+    created_at DATETIME DEFAULT (datetime('now', 'utc'))
+);
+CREATE TABLE IF NOT EXISTS digest_preview (
+    slot INTEGER PRIMARY KEY CHECK (slot = 1),
+    date TEXT NOT NULL,
+    html TEXT NOT NULL,
+    created_at DATETIME DEFAULT (datetime('now', 'utc'))
+);
+";
+
+/// Telemetry tables, in the telemetry database when one is attached
+const TELEMETRY_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS {schema}.source_health (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    source_id TEXT NOT NULL,
+    success INTEGER NOT NULL,
+    error_message TEXT,
+    fetch_ms INTEGER,
+    new_articles INTEGER,
+    filtered_articles INTEGER,
+    recorded_at DATETIME DEFAULT (datetime('now', 'utc'))
+);
+CREATE INDEX IF NOT EXISTS {schema}.idx_source_health_source ON source_health(source_id, recorded_at);
+";
+
+/// Open the database like `run.py`'s `connect_db()`: keyed when a key is
+/// given, with `telemetry` attached when configured
+pub fn connect(
+    db_path: &str,
+    key: Option<&str>,
+    telemetry: Option<&str>,
+) -> Result<Connection, String> {
+    let conn = Connection::open(db_path).map_err(|e| format!("Cannot open {db_path}: {e}"))?;
+    let error = |e: rusqlite::Error| format!("Cannot open {db_path}: {e}");
+    if let Some(key) = key {
+        conn.pragma_update(None, "key", key).map_err(error)?;
+    }
+    if let Some(path) = telemetry {
+        match key {
+            Some(key) => conn.execute(
+                "ATTACH DATABASE ?1 AS telemetry KEY ?2",
+                rusqlite::params![path, key],
+            ),
+            None => conn.execute("ATTACH DATABASE ?1 AS telemetry", [path]),
+        }
+        .map_err(error)?;
+    }
+    let schema = if telemetry.is_some() {
+        "telemetry"
+    } else {
+        "main"
+    };
+    conn.execute_batch(SCHEMA)
+        .and_then(|_| conn.execute_batch(&TELEMETRY_SCHEMA.replace("{schema}", schema)))
+        .map_err(|e| format!("Cannot create tables: {e}"))?;
+    // Older source_health tables predate filters
+    if !column_exists(&conn, "source_health", "filtered_articles")? {
+        conn.execute_batch(&format!(
+            "ALTER TABLE {schema}.source_health ADD COLUMN filtered_articles INTEGER"
+        ))
+        .map_err(|e| format!("Cannot create tables: {e}"))?;
+    }
+    Ok(conn)
+}
+
+/// When the last digest was made, so only newer articles are kept
+pub fn last_run(conn: &Connection) -> Result<Option<String>, String> {
+    if !table_exists(conn, "digest_runs")? {
+        return Ok(None);
+    }
+    conn.query_row("SELECT MAX(run_at) FROM digest_runs", [], |row| row.get(0))
+        .map_err(|e| format!("Query error: {e}"))
+}
+
+/// Headlines shown in the `days` days' digests before `until` ("now", or a
+/// backfilled day's date), newest first, so they aren't selected again
+pub fn previous_headlines(
+    conn: &Connection,
+    until: &str,
+    days: u32,
+) -> Result<Vec<String>, String> {
+    if !table_exists(conn, "shown_narratives")? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn
+        .prepare(
+            "SELECT headline FROM shown_narratives
+             WHERE shown_at > datetime(?1, ?2) AND shown_at <= datetime(?1)
+             ORDER BY shown_at DESC",
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    let headlines = stmt
+        .query_map([until.to_string(), format!("-{days} days")], |row| {
+            row.get(0)
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(headlines)
+}
+
+fn table_exists(conn: &Connection, table: &str) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        [table],
+        |_| Ok(()),
+    )
+    .optional()
+    .map(|found| found.is_some())
+    .map_err(|e| format!("Query error: {e}"))
+}
+
+fn column_exists(conn: &Connection, table: &str, column: &str) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2",
+        [table, column],
+        |_| Ok(()),
+    )
+    .optional()
+    .map(|found| found.is_some())
+    .map_err(|e| format!("Query error: {e}"))
+}
+
+/// The latest digest `run.py` saved narratives for
+pub fn latest_narratives_date(conn: &Connection) -> Result<Option<String>, String> {
+    if !table_exists(conn, "narratives")? {
+        return Ok(None);
+    }
+    conn.query_row("SELECT MAX(digest_date) FROM narratives", [], |row| {
+        row.get(0)
+    })
+    .map_err(|e| format!("Query error: {e}"))
+}
+
+/// The narratives, in display order, and regional summaries of the digest
+/// of `date`, as `run.py`'s `save_narratives()` stores them. Sources
+/// without an http(s) URL are left out.
+pub fn digest(conn: &Connection, date: &str) -> Result<Digest, String> {
+    if !table_exists(conn, "narratives")? {
+        return Ok(Digest::default());
+    }
+    let error = |e: rusqlite::Error| format!("Query error: {e}");
+    // Narratives stored before pictures were have none
+    let image = if column_exists(conn, "narratives", "image")? {
+        "image"
+    } else {
+        "NULL"
+    };
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT tier, cluster, headline, summary, why_it_matters, sources, reporting_varies,
+                 {image}
+             FROM narratives WHERE digest_date = ?1 ORDER BY id"
+        ))
+        .map_err(error)?;
+    let rows = stmt
+        .query_map([date], |row| {
+            Ok((
+                Narrative {
+                    tier: row.get(0)?,
+                    cluster: row.get(1)?,
+                    headline: row.get(2)?,
+                    summary: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                    why_it_matters: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                    image: row.get(7)?,
+                    ..Default::default()
+                },
+                row.get::<_, String>(5)?,
+                row.get::<_, String>(6)?,
+            ))
+        })
+        .map_err(error)?;
+    let mut narratives = Vec::new();
+    for row in rows {
+        let (mut narrative, sources, reporting_varies) = row.map_err(error)?;
+        let json = |e: serde_json::Error| format!("{date}: {}: {e}", narrative.headline);
+        narrative.sources = serde_json::from_str::<Vec<SourceRef>>(&sources)
+            .map_err(json)?
+            .into_iter()
+            .filter(|s| render::is_safe_url(&s.url))
+            .collect();
+        narrative.reporting_varies = serde_json::from_str(&reporting_varies).map_err(json)?;
+        narratives.push(narrative);
+    }
+    let regional_summary = if table_exists(conn, "regional_summaries")? {
+        let mut stmt = conn
+            .prepare("SELECT region, summary FROM regional_summaries WHERE digest_date = ?1")
+            .map_err(error)?;
+        stmt.query_map([date], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(error)?
+            .collect::<Result<BTreeMap<String, String>, _>>()
+            .map_err(error)?
+    } else {
+        BTreeMap::new()
+    };
+    Ok(Digest {
+        narratives,
+        regional_summary,
+    })
+}
+
+/// When the digest of `date` was first saved, if it has been
+pub fn digest_created_at(conn: &Connection, date: &str) -> Result<Option<String>, String> {
+    if !table_exists(conn, "digests")? {
+        return Ok(None);
+    }
+    conn.query_row(
+        "SELECT created_at FROM digests WHERE date = ?1",
+        [date],
+        |row| row.get(0),
+    )
+    .optional()
+    .map(Option::flatten)
+    .map_err(|e| format!("Query error: {e}"))
+}
+
+/// Hex SHA-256 of digest HTML, as digest-server stores it in `html_sha256`
+fn sha256(html: &str) -> String {
+    ring::digest::digest(&ring::digest::SHA256, html.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Store the HTML of the digest of `date` in place of any earlier copy,
+/// compressed or offloaded ones included, unless that copy is the same HTML
+pub fn save_digest(conn: &Connection, date: &str, html: &str) -> Result<(), String> {
+    let error = |e: rusqlite::Error| format!("Cannot save the digest of {date}: {e}");
+    if !table_exists(conn, "digests")? {
+        return Err(format!(
+            "Cannot save the digest of {date}: no digests table (run run.py first)"
+        ));
+    }
+    // digest-server records the hash of each body it compresses; re-saving
+    // the same body would only record a revision of it
+    if column_exists(conn, "digests", "html_sha256")? {
+        let stored: Option<Option<String>> = conn
+            .query_row(
+                "SELECT html_sha256 FROM digests WHERE date = ?1 AND html = ''",
+                [date],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(error)?;
+        if stored.flatten().is_some_and(|hash| hash == sha256(html)) {
+            return Ok(());
+        }
+    }
+    let mut set = vec!["html = excluded.html".to_string()];
+    for column in ["html_zstd", "html_blob"] {
+        if column_exists(conn, "digests", column)? {
+            set.push(format!("{column} = NULL"));
+        }
+    }
+    conn.execute(
+        &format!(
+            "INSERT INTO digests (date, html) VALUES (?1, ?2)
+             ON CONFLICT(date) DO UPDATE SET {}",
+            set.join(", ")
+        ),
+        [date, html],
+    )
+    .map_err(error)?;
+    Ok(())
+}
+
+/// Put a dry run's digest of `date` in the preview slot, replacing the last one
+pub fn save_preview(conn: &Connection, date: &str, html: &str) -> Result<(), String> {
+    conn.execute(
+        "INSERT OR REPLACE INTO digest_preview (slot, date, html, created_at)
+         VALUES (1, ?1, ?2, datetime('now'))",
+        [date, html],
+    )
+    .map_err(|e| format!("Cannot save the preview of {date}: {e}"))?;
+    Ok(())
+}
+
+/// Validators to fetch with, by source. Only those from fetches a digest has
+/// run since are used: until then, the articles of the fetch that returned
+/// them are still waiting in `fetched/`, and a 304 would drop them.
+pub fn validators(
+    conn: &Connection,
+    last_run: Option<&str>,
+) -> Result<HashMap<String, Validators>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT source_id, etag, last_modified FROM feed_validators
+             WHERE fetched_at <= ?1",
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    let rows = stmt
+        .query_map([last_run], |row| {
+            Ok((
+                row.get(0)?,
+                Validators {
+                    etag: row.get(1)?,
+                    last_modified: row.get(2)?,
+                },
+            ))
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Query error: {e}"))?;
+    Ok(rows)
+}
+
+/// Feeds found on the pages of sources that are websites, by source, for
+/// sources still pointing at the site they were found on
+pub fn discovered_feeds(
+    conn: &Connection,
+    sources: &[Source],
+) -> Result<HashMap<String, String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT source_id, site_url, feed_url FROM discovered_feeds")
+        .map_err(|e| format!("Query error: {e}"))?;
+    let rows: Vec<(String, String, String)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .map_err(|e| format!("Query error: {e}"))?
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Query error: {e}"))?;
+    Ok(rows
+        .into_iter()
+        .filter(|(id, site_url, _)| sources.iter().any(|s| s.id == *id && s.url == *site_url))
+        .map(|(id, _, feed_url)| (id, feed_url))
+        .collect())
+}
+
+/// Keep the feeds found for sources that are websites, and forget them for
+/// sources fetched as feeds. Failed fetches keep theirs to try again.
+pub fn record_discovered(
+    conn: &mut Connection,
+    sources: &[Source],
+    results: &[FetchResult],
+) -> Result<(), String> {
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Cannot record discovered feeds: {e}"))?;
+    for result in results.iter().filter(|r| r.error.is_none()) {
+        let Some(source) = sources.iter().find(|s| s.id == result.source_id) else {
+            continue;
+        };
+        match &result.feed_url {
+            Some(feed_url) => tx.execute(
+                "INSERT INTO discovered_feeds (source_id, site_url, feed_url, discovered_at)
+                 VALUES (?1, ?2, ?3, datetime('now'))
+                 ON CONFLICT(source_id) DO UPDATE SET
+                     site_url = excluded.site_url,
+                     feed_url = excluded.feed_url,
+                     discovered_at = excluded.discovered_at
+                 WHERE site_url != excluded.site_url OR feed_url != excluded.feed_url",
+                rusqlite::params![result.source_id, source.url, feed_url],
+            ),
+            None => tx.execute(
+                "DELETE FROM discovered_feeds WHERE source_id = ?1",
+                [&result.source_id],
+            ),
+        }
+        .map_err(|e| format!("Cannot record discovered feeds: {e}"))?;
+    }
+    tx.commit()
+        .map_err(|e| format!("Cannot record discovered feeds: {e}"))
+}
+
+/// Keep the validators of feeds fetched in full, and forget them for feeds
+/// that stopped sending any. Feeds that answered 304 keep theirs as they were.
+pub fn record_validators(conn: &mut Connection, results: &[FetchResult]) -> Result<(), String> {
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Cannot record feed validators: {e}"))?;
+    for result in results {
+        if result.error.is_some() || result.not_modified {
+            continue;
+        }
+        match &result.validators {
+            Some(v) => tx.execute(
+                "INSERT INTO feed_validators (source_id, etag, last_modified, fetched_at)
+                 VALUES (?1, ?2, ?3, datetime('now'))
+                 ON CONFLICT(source_id) DO UPDATE SET
+                     etag = excluded.etag,
+                     last_modified = excluded.last_modified,
+                     fetched_at = excluded.fetched_at",
+                rusqlite::params![result.source_id, v.etag, v.last_modified],
+            ),
+            None => tx.execute(
+                "DELETE FROM feed_validators WHERE source_id = ?1",
+                [&result.source_id],
+            ),
+        }
+        .map_err(|e| format!("Cannot record feed validators: {e}"))?;
+    }
+    tx.commit()
+        .map_err(|e| format!("Cannot record feed validators: {e}"))
+}
+
+/// robots.txt files fetched in the last day, by origin
+pub fn robots_txt(conn: &Connection) -> Result<HashMap<String, String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT origin, body FROM robots_txt WHERE fetched_at > datetime('now', '-1 day')")
+        .map_err(|e| format!("Query error: {e}"))?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| format!("Query error: {e}"))?
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Query error: {e}"))?;
+    Ok(rows)
+}
+
+/// Cache robots.txt files fetched this run
+pub fn record_robots_txt(conn: &mut Connection, files: &[(String, String)]) -> Result<(), String> {
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Cannot record robots.txt: {e}"))?;
+    for (origin, body) in files {
+        tx.execute(
+            "INSERT OR REPLACE INTO robots_txt (origin, body, fetched_at)
+             VALUES (?1, ?2, datetime('now'))",
+            rusqlite::params![origin, body],
+        )
+        .map_err(|e| format!("Cannot record robots.txt: {e}"))?;
+    }
+    tx.commit()
+        .map_err(|e| format!("Cannot record robots.txt: {e}"))
+}
+
+/// Article pages fetched in the last `days` days, by canonical URL, with
+/// their text, if they had any
+pub fn cached_pages(
+    conn: &Connection,
+    days: u32,
+) -> Result<HashMap<String, Option<String>>, String> {
+    let mut stmt = conn
+        .prepare("SELECT url, content FROM article_cache WHERE fetched_at > datetime('now', ?1)")
+        .map_err(|e| format!("Query error: {e}"))?;
+    let rows = stmt
+        .query_map([format!("-{days} days")], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("Query error: {e}"))?;
+    Ok(rows)
+}
+
+/// Cache the article pages fetched this run, and forget those older than
+/// `days` days
+pub fn record_pages(
+    conn: &mut Connection,
+    pages: &[(String, Option<String>)],
+    days: u32,
+) -> Result<(), String> {
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Cannot cache article pages: {e}"))?;
+    for (url, content) in pages {
+        tx.execute(
+            "INSERT OR REPLACE INTO article_cache (url, content, fetched_at)
+             VALUES (?1, ?2, datetime('now'))",
+            rusqlite::params![url, content],
+        )
+        .map_err(|e| format!("Cannot cache article pages: {e}"))?;
+    }
+    tx.execute(
+        "DELETE FROM article_cache WHERE fetched_at <= datetime('now', ?1)",
+        [format!("-{days} days")],
+    )
+    .map_err(|e| format!("Cannot cache article pages: {e}"))?;
+    tx.commit()
+        .map_err(|e| format!("Cannot cache article pages: {e}"))
+}
+
+/// Keep the articles of this fetch in `article_archive`, for `backfill` to
+/// make a missed day's digest from, and forget those archived more than
+/// `days` days ago
+pub fn archive_articles(
+    conn: &mut Connection,
+    fetched: &[(&str, Vec<Article>)],
+    days: u32,
+) -> Result<(), String> {
+    let error = |e: rusqlite::Error| format!("Cannot archive articles: {e}");
+    let tx = conn.transaction().map_err(error)?;
+    for (source_id, articles) in fetched {
+        for article in articles {
+            let json = serde_json::to_string(article)
+                .map_err(|e| format!("Cannot encode articles: {e}"))?;
+            tx.execute(
+                "INSERT OR REPLACE INTO article_archive
+                     (url, source_id, published_at, article, archived_at)
+                 VALUES (?1, ?2, ?3, ?4, datetime('now'))",
+                rusqlite::params![
+                    article.url,
+                    source_id,
+                    article
+                        .published
+                        .map(|p| p.format("%Y-%m-%d %H:%M:%S").to_string()),
+                    json
+                ],
+            )
+            .map_err(error)?;
+        }
+    }
+    tx.execute(
+        "DELETE FROM article_archive WHERE archived_at <= datetime('now', ?1)",
+        [format!("-{days} days")],
+    )
+    .map_err(error)?;
+    tx.commit().map_err(error)
+}
+
+/// The archived articles published on `date` (undated ones by when they were
+/// fetched), as `fetched/` has them, by source. Stories are numbered per
+/// fetch, so each is qualified by the fetch it came from.
+pub fn archived_articles(
+    conn: &Connection,
+    date: &str,
+) -> Result<BTreeMap<String, Vec<serde_json::Value>>, String> {
+    let error = |e: rusqlite::Error| format!("Query error: {e}");
+    let mut stmt = conn
+        .prepare(
+            "SELECT source_id, article, archived_at FROM article_archive
+             WHERE date(COALESCE(published_at, archived_at)) = ?1
+             ORDER BY COALESCE(published_at, archived_at)",
+        )
+        .map_err(error)?;
+    let rows = stmt
+        .query_map([date], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })
+        .map_err(error)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(error)?;
+    let mut by_source: BTreeMap<String, Vec<serde_json::Value>> = BTreeMap::new();
+    for (source_id, json, archived_at) in rows {
+        let mut article: serde_json::Value = serde_json::from_str(&json)
+            .map_err(|e| format!("Cannot read an archived article of {source_id}: {e}"))?;
+        if let Some(story) = article.get("story").and_then(|s| s.as_str()) {
+            article["story"] = format!("{archived_at} {story}").into();
+        }
+        by_source.entry(source_id).or_default().push(article);
+    }
+    Ok(by_source)
+}
+
+/// Store a backfilled digest's selections as `run.py`'s `save_narratives()`
+/// does, in place of any the day had, and note it was backfilled
+pub fn save_backfill(
+    conn: &mut Connection,
+    date: &str,
+    selections: &Selections,
+    articles: usize,
+) -> Result<(), String> {
+    if !table_exists(conn, "narratives")? {
+        return Err(format!(
+            "Cannot save the digest of {date}: no narratives table (run run.py first)"
+        ));
+    }
+    let error = |e: rusqlite::Error| format!("Cannot save the digest of {date}: {e}");
+    let json = |v: serde_json::Result<String>| {
+        v.map_err(|e| format!("Cannot save the digest of {date}: {e}"))
+    };
+    let tx = conn.transaction().map_err(error)?;
+    tx.execute("DELETE FROM narratives WHERE digest_date = ?1", [date])
+        .map_err(error)?;
+    tx.execute(
+        "DELETE FROM regional_summaries WHERE digest_date = ?1",
+        [date],
+    )
+    .map_err(error)?;
+    let insert = "INSERT INTO narratives (digest_date, tier, cluster, position, headline,
+                      summary, why_it_matters, topic, sources, reporting_varies, image)
+                  VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)";
+    for (tier, narratives) in [
+        ("must_know", &selections.must_know),
+        ("should_know", &selections.should_know),
+    ] {
+        for (position, n) in narratives.iter().enumerate() {
+            tx.execute(
+                insert,
+                rusqlite::params![
+                    date,
+                    tier,
+                    None::<String>,
+                    position as i64,
+                    n.headline,
+                    n.summary,
+                    n.why_it_matters,
+                    n.topic,
+                    json(serde_json::to_string(&n.sources))?,
+                    json(serde_json::to_string(&n.reporting_varies))?,
+                    n.image
+                ],
+            )
+            .map_err(error)?;
+        }
+    }
+    for region in REGIONS {
+        for (position, s) in selections
+            .signals
+            .get(*region)
+            .into_iter()
+            .flatten()
+            .enumerate()
+        {
+            tx.execute(
+                insert,
+                rusqlite::params![
+                    date,
+                    "signal",
+                    region,
+                    position as i64,
+                    s.headline,
+                    "",
+                    "",
+                    s.topic,
+                    json(serde_json::to_string(&[&s.source]))?,
+                    "[]",
+                    None::<String>
+                ],
+            )
+            .map_err(error)?;
+        }
+    }
+    for (region, summary) in &selections.regional_summary {
+        if !summary.is_empty() {
+            tx.execute(
+                "INSERT INTO regional_summaries (digest_date, region, summary) VALUES (?1, ?2, ?3)",
+                [date, region, summary],
+            )
+            .map_err(error)?;
+        }
+    }
+    tx.execute(
+        "INSERT OR REPLACE INTO backfilled_digests (date, articles, backfilled_at)
+         VALUES (?1, ?2, datetime('now'))",
+        rusqlite::params![date, articles as i64],
+    )
+    .map_err(error)?;
+    tx.commit().map_err(error)
+}
+
+/// Keep `pictures` in `images`, where digest-server serves them from
+pub fn save_images(conn: &mut Connection, pictures: &[Picture]) -> Result<(), String> {
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Cannot save pictures: {e}"))?;
+    for picture in pictures {
+        tx.execute(
+            "INSERT OR IGNORE INTO images (hash, content_type, data) VALUES (?1, ?2, ?3)",
+            rusqlite::params![picture.hash, picture.content_type, picture.data],
+        )
+        .map_err(|e| format!("Cannot save pictures: {e}"))?;
+    }
+    tx.commit()
+        .map_err(|e| format!("Cannot save pictures: {e}"))
+}
+
+/// Articles published after `since`, plus undated ones
+pub fn newer_than<'a>(articles: &'a [Article], since: Option<&str>) -> Vec<&'a Article> {
+    let since = since.and_then(feeds::parse_sqlite_time);
+    articles
+        .iter()
+        .filter(|a| match (since, a.published) {
+            (Some(since), Some(published)) => published > since,
+            _ => true,
+        })
+        .collect()
+}
+
+/// Replace the fetched files with one `{source_id}.json` per source
+pub fn write_fetched(dir: &Path, fetched: &[(&str, Vec<Article>)]) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {e}", dir.display()))?;
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("Cannot read {}: {e}", dir.display()))?;
+    for entry in entries.flatten() {
+        if entry.path().extension().is_some_and(|ext| ext == "json") {
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+    for (source_id, articles) in fetched {
+        let path = dir.join(format!("{source_id}.json"));
+        let json = serde_json::to_string_pretty(articles)
+            .map_err(|e| format!("Cannot encode articles: {e}"))?;
+        std::fs::write(&path, json).map_err(|e| format!("Cannot write {}: {e}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Record each fetch in `source_health`, with how many articles it kept. A
+/// successful fetch's `error_message` is its note on what was left out.
+pub fn record_health(
+    conn: &mut Connection,
+    results: &[FetchResult],
+    kept: impl Fn(&str) -> usize,
+    filtered: &HashMap<&str, usize>,
+    notes: &HashMap<String, String>,
+) -> Result<(), String> {
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Cannot record source health: {e}"))?;
+    {
+        let mut stmt = tx
+            .prepare(
+                "INSERT INTO source_health
+                     (source_id, success, error_message, fetch_ms, new_articles, filtered_articles)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )
+            .map_err(|e| format!("Query error: {e}"))?;
+        for result in results {
+            let success = result.error.is_none();
+            let new_articles = success.then(|| kept(&result.source_id) as i64);
+            let filtered_articles = success.then(|| {
+                filtered
+                    .get(result.source_id.as_str())
+                    .map_or(0, |n| *n as i64)
+            });
+            let message = result
+                .error
+                .as_ref()
+                .or_else(|| notes.get(&result.source_id));
+            stmt.execute(rusqlite::params![
+                result.source_id,
+                success,
+                message,
+                result.fetch_ms,
+                new_articles,
+                filtered_articles
+            ])
+            .map_err(|e| format!("Cannot record source health: {e}"))?;
+        }
+    }
+    tx.commit()
+        .map_err(|e| format!("Cannot record source health: {e}"))
+}
+
+/// Bump `last_new_item_at` for sources whose fingerprint changed
+pub fn record_activity(
+    conn: &mut Connection,
+    fingerprints: &[(&str, String)],
+) -> Result<(), String> {
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Cannot record source activity: {e}"))?;
+    for (source_id, fingerprint) in fingerprints {
+        tx.execute(
+            "INSERT INTO source_activity (source_id, fingerprint, last_new_item_at)
+             VALUES (?1, ?2, datetime('now'))
+             ON CONFLICT(source_id) DO UPDATE SET
+                 last_new_item_at = CASE WHEN fingerprint != excluded.fingerprint
+                     THEN excluded.last_new_item_at ELSE last_new_item_at END,
+                 fingerprint = excluded.fingerprint",
+            rusqlite::params![source_id, fingerprint],
+        )
+        .map_err(|e| format!("Cannot record source activity: {e}"))?;
+    }
+    tx.commit()
+        .map_err(|e| format!("Cannot record source activity: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn article(published: Option<&str>) -> Article {
+        Article {
+            title: "Title".into(),
+            url: "https://example.com".into(),
+            published: published.map(|date| date.parse().unwrap()),
+            summary: String::new(),
+            content: None,
+            story: None,
+            image: None,
+            points: None,
+            comments: None,
+            keywords: Vec::new(),
+        }
+    }
+
+    mod previous_headlines {
+        use super::*;
+
+        #[test]
+        fn keeps_the_last_days_headlines() {
+            let conn = Connection::open_in_memory().unwrap();
+            assert!(previous_headlines(&conn, "now", 7).unwrap().is_empty());
+            conn.execute_batch(
+                "CREATE TABLE shown_narratives (headline TEXT, shown_at DATETIME);
+                 INSERT INTO shown_narratives VALUES
+                     ('Old', datetime('now', '-8 days')),
+                     ('Yesterday', datetime('now', '-1 days')),
+                     ('Today', datetime('now'));",
+            )
+            .unwrap();
+            assert_eq!(
+                previous_headlines(&conn, "now", 7).unwrap(),
+                ["Today", "Yesterday"]
+            );
+        }
+
+        #[test]
+        fn keeps_the_headlines_before_a_backfilled_day() {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(
+                "CREATE TABLE shown_narratives (headline TEXT, shown_at DATETIME);
+                 INSERT INTO shown_narratives VALUES
+                     ('Too old', '2026-01-01 07:00:00'),
+                     ('Before', '2026-01-04 07:00:00'),
+                     ('After', '2026-01-06 07:00:00');",
+            )
+            .unwrap();
+            assert_eq!(
+                previous_headlines(&conn, "2026-01-05", 3).unwrap(),
+                ["Before"]
+            );
+        }
+    }
+
+    mod digest {
+        use super::*;
+
+        #[test]
+        fn loads_narratives_and_replaces_stored_html() {
+            let conn = Connection::open_in_memory().unwrap();
+            assert!(latest_narratives_date(&conn).unwrap().is_none());
+            conn.execute_batch(
+                r#"CREATE TABLE narratives (id INTEGER PRIMARY KEY, digest_date TEXT, tier TEXT,
+                       cluster TEXT, position INTEGER, headline TEXT, summary TEXT,
+                       why_it_matters TEXT, topic TEXT, sources TEXT, reporting_varies TEXT);
+                   CREATE TABLE regional_summaries (digest_date TEXT, region TEXT, summary TEXT);
+                   CREATE TABLE digests (date TEXT PRIMARY KEY, html TEXT NOT NULL,
+                       created_at DATETIME, html_zstd BLOB);
+                   INSERT INTO narratives VALUES
+                       (1, '2026-01-05', 'must_know', NULL, 0, 'Truce holds', 'S.', 'W.', NULL,
+                        '[{"name": "Wire", "url": "https://wire.example/1", "bias": "center"},
+                          {"name": "Bad", "url": "javascript:x"}]', '[]'),
+                       (2, '2026-01-05', 'signal', 'tech', 0, 'Chips', '', '', NULL, '[]', '[]'),
+                       (3, '2026-01-04', 'signal', 'tech', 0, 'Older', '', '', NULL, '[]', '[]');
+                   INSERT INTO regional_summaries VALUES ('2026-01-05', 'europe', 'Calm.');
+                   INSERT INTO digests VALUES ('2026-01-05', '', '2026-01-05 07:00:00', x'00');"#,
+            )
+            .unwrap();
+            assert_eq!(
+                latest_narratives_date(&conn).unwrap().as_deref(),
+                Some("2026-01-05")
+            );
+            let digest = digest(&conn, "2026-01-05").unwrap();
+            assert_eq!(digest.narratives.len(), 2);
+            assert_eq!(digest.narratives[0].sources.len(), 1);
+            assert_eq!(digest.narratives[1].cluster.as_deref(), Some("tech"));
+            assert_eq!(digest.regional_summary["europe"], "Calm.");
+
+            save_digest(&conn, "2026-01-05", "<html>").unwrap();
+            save_digest(&conn, "2026-01-04", "<old>").unwrap();
+            let (html, compressed): (String, Option<Vec<u8>>) = conn
+                .query_row(
+                    "SELECT html, html_zstd FROM digests WHERE date = '2026-01-05'",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .unwrap();
+            assert_eq!((html.as_str(), compressed), ("<html>", None));
+            assert_eq!(
+                digest_created_at(&conn, "2026-01-05").unwrap().as_deref(),
+                Some("2026-01-05 07:00:00")
+            );
+        }
+        #[test]
+        fn leaves_a_compressed_copy_of_the_same_html() {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(
+                "CREATE TABLE digests (date TEXT PRIMARY KEY, html TEXT NOT NULL,
+                     html_zstd BLOB, html_sha256 TEXT);
+                 CREATE TABLE digest_revisions (digest_date TEXT, html TEXT);
+                 CREATE TRIGGER digests_revision_on_update BEFORE UPDATE OF html ON digests
+                 WHEN NEW.html != '' AND NEW.html IS NOT OLD.html
+                 BEGIN
+                     INSERT INTO digest_revisions VALUES (OLD.date, OLD.html);
+                 END;",
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO digests VALUES ('2026-01-05', '', x'00', ?1)",
+                [sha256("<html>")],
+            )
+            .unwrap();
+            save_digest(&conn, "2026-01-05", "<html>").unwrap();
+            let revisions = |conn: &Connection| -> i64 {
+                conn.query_row("SELECT COUNT(*) FROM digest_revisions", [], |row| {
+                    row.get(0)
+                })
+                .unwrap()
+            };
+            assert_eq!(revisions(&conn), 0);
+            save_digest(&conn, "2026-01-05", "<html>fixed").unwrap();
+            assert_eq!(revisions(&conn), 1);
+        }
+    }
+
+    mod save_images {
+        use super::*;
+
+        #[test]
+        fn keeps_each_picture_once_for_narratives_to_show() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(SCHEMA).unwrap();
+            conn.execute_batch(
+                r#"CREATE TABLE narratives (id INTEGER PRIMARY KEY, digest_date TEXT, tier TEXT,
+                       cluster TEXT, position INTEGER, headline TEXT, summary TEXT,
+                       why_it_matters TEXT, topic TEXT, sources TEXT, reporting_varies TEXT,
+                       story TEXT, image TEXT);
+                   INSERT INTO narratives VALUES
+                       (1, '2026-01-05', 'must_know', NULL, 0, 'Truce holds', 'S.', 'W.', NULL,
+                        '[]', '[]', NULL, 'ab12');"#,
+            )
+            .unwrap();
+            let picture = || Picture {
+                hash: "ab12".into(),
+                content_type: "image/png",
+                data: b"\x89PNG\r\n\x1a\n".to_vec(),
+            };
+            save_images(&mut conn, &[picture()]).unwrap();
+            save_images(&mut conn, &[picture()]).unwrap();
+
+            let stored: (i64, String) = conn
+                .query_row("SELECT COUNT(*), content_type FROM images", [], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })
+                .unwrap();
+            assert_eq!(stored, (1, "image/png".to_string()));
+            let digest = digest(&conn, "2026-01-05").unwrap();
+            assert_eq!(digest.narratives[0].image.as_deref(), Some("ab12"));
+        }
+    }
+
+    mod save_preview {
+        use super::*;
+
+        #[test]
+        fn keeps_one_preview() {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(SCHEMA).unwrap();
+            save_preview(&conn, "2026-01-05", "<first>").unwrap();
+            save_preview(&conn, "2026-01-06", "<second>").unwrap();
+            let previews: Vec<(String, String)> = conn
+                .prepare("SELECT date, html FROM digest_preview")
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(previews, [("2026-01-06".into(), "<second>".into())]);
+        }
+    }
+
+    mod newer_than {
+        use super::*;
+
+        #[test]
+        fn keeps_articles_after_the_last_run_and_undated_ones() {
+            let articles = [
+                article(Some("2026-01-17T09:00:00Z")),
+                article(Some("2026-01-17T11:00:00Z")),
+                article(None),
+            ];
+            let kept = newer_than(&articles, Some("2026-01-17 10:00:00"));
+            assert_eq!(kept, [&articles[1], &articles[2]]);
+            assert_eq!(newer_than(&articles, None).len(), 3);
+        }
+    }
+
+    mod record_health {
+        use super::*;
+
+        #[test]
+        fn records_telemetry_in_its_own_file() {
+            let dir = std::env::temp_dir().join(format!("pipeline-store-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            let db = dir.join("digest.db");
+            let telemetry = dir.join("telemetry.db");
+            let mut conn = connect(
+                db.to_str().unwrap(),
+                None,
+                Some(telemetry.to_str().unwrap()),
+            )
+            .unwrap();
+
+            let results = [
+                FetchResult {
+                    source_id: "bbc".into(),
+                    articles: vec![article(None)],
+                    error: None,
+                    fetch_ms: 120,
+                    not_modified: false,
+                    validators: None,
+                    feed_url: None,
+                },
+                FetchResult {
+                    source_id: "npr".into(),
+                    articles: Vec::new(),
+                    error: Some("HTTP 503".into()),
+                    fetch_ms: 900,
+                    not_modified: false,
+                    validators: None,
+                    feed_url: None,
+                },
+            ];
+            let notes = HashMap::from([(
+                "bbc".to_string(),
+                "robots.txt disallows 1 article page(s)".to_string(),
+            )]);
+            let filtered = HashMap::from([("bbc", 3)]);
+            record_health(&mut conn, &results, |_| 1, &filtered, &notes).unwrap();
+            let rows: Vec<(String, bool, Option<i64>, Option<String>)> = conn
+                .prepare("SELECT source_id, success, new_articles, error_message FROM telemetry.source_health ORDER BY id")
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(
+                rows,
+                [
+                    (
+                        "bbc".into(),
+                        true,
+                        Some(1),
+                        Some("robots.txt disallows 1 article page(s)".into())
+                    ),
+                    ("npr".into(), false, None, Some("HTTP 503".into()))
+                ]
+            );
+            let filtered: Vec<Option<i64>> = conn
+                .prepare("SELECT filtered_articles FROM telemetry.source_health ORDER BY id")
+                .unwrap()
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(filtered, [Some(3), None]);
+        }
+    }
+
+    mod record_pages {
+        use super::*;
+
+        #[test]
+        fn keeps_pages_for_their_days() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(SCHEMA).unwrap();
+            conn.execute(
+                "INSERT INTO article_cache VALUES ('example.com/old', 'Old.', '2000-01-01')",
+                [],
+            )
+            .unwrap();
+            let pages = [
+                ("example.com/a".to_string(), Some("Text.".to_string())),
+                ("example.com/b".to_string(), None),
+            ];
+            record_pages(&mut conn, &pages, 7).unwrap();
+            assert_eq!(
+                cached_pages(&conn, 7).unwrap(),
+                HashMap::from(pages.clone())
+            );
+            let count: i64 = conn
+                .query_row("SELECT COUNT(*) FROM article_cache", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(count, 2);
+        }
+    }
+
+    mod archived_articles {
+        use super::*;
+
+        #[test]
+        fn finds_a_days_articles_with_their_stories_per_fetch() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(SCHEMA).unwrap();
+            let mut earlier = article(Some("2026-01-04T09:00:00Z"));
+            earlier.story = Some("s1".into());
+            let mut later = article(Some("2026-01-05T09:00:00Z"));
+            later.url = "https://example.com/later".into();
+            archive_articles(&mut conn, &[("wire", vec![earlier, later])], 30).unwrap();
+
+            let archived = archived_articles(&conn, "2026-01-04").unwrap();
+            assert_eq!(archived.len(), 1);
+            let story = archived["wire"][0]["story"].as_str().unwrap();
+            assert!(story.ends_with(" s1"), "{story}");
+            assert!(archived_articles(&conn, "2026-01-03").unwrap().is_empty());
+        }
+    }
+
+    mod save_backfill {
+        use super::*;
+        use crate::curate::{Narrative as Selected, Signal};
+
+        #[test]
+        fn stores_narratives_for_render_and_notes_the_backfill() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(SCHEMA).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE narratives (id INTEGER PRIMARY KEY, digest_date TEXT, tier TEXT,
+                     cluster TEXT, position INTEGER, headline TEXT, summary TEXT,
+                     why_it_matters TEXT, topic TEXT, sources TEXT, reporting_varies TEXT,
+                     image TEXT);
+                 CREATE TABLE regional_summaries (digest_date TEXT, region TEXT, summary TEXT);
+                 INSERT INTO narratives (digest_date, tier, position, headline, sources,
+                     reporting_varies) VALUES ('2026-01-05', 'must_know', 0, 'Stale', '[]', '[]');",
+            )
+            .unwrap();
+            let source = SourceRef {
+                name: "Wire".into(),
+                url: "https://wire.example/1".into(),
+                bias: "center".into(),
+                ..Default::default()
+            };
+            let selections = Selections {
+                must_know: vec![Selected {
+                    headline: "Truce holds".into(),
+                    summary: "S.".into(),
+                    why_it_matters: "W.".into(),
+                    topic: None,
+                    sources: vec![source.clone()],
+                    reporting_varies: Vec::new(),
+                    image: None,
+                }],
+                signals: BTreeMap::from([(
+                    "tech".to_string(),
+                    vec![Signal {
+                        headline: "Chips".into(),
+                        source,
+                        topic: None,
+                    }],
+                )]),
+                regional_summary: BTreeMap::from([
+                    ("europe".to_string(), "Calm.".to_string()),
+                    ("tech".to_string(), String::new()),
+                ]),
+                ..Default::default()
+            };
+            save_backfill(&mut conn, "2026-01-05", &selections, 40).unwrap();
+
+            let digest = digest(&conn, "2026-01-05").unwrap();
+            let headlines: Vec<&str> = digest
+                .narratives
+                .iter()
+                .map(|n| n.headline.as_str())
+                .collect();
+            assert_eq!(headlines, ["Truce holds", "Chips"]);
+            assert_eq!(digest.narratives[1].sources.len(), 1);
+            assert_eq!(digest.regional_summary.len(), 1);
+            let articles: i64 = conn
+                .query_row(
+                    "SELECT articles FROM backfilled_digests WHERE date = '2026-01-05'",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(articles, 40);
+        }
+    }
+
+    mod record_activity {
+        use super::*;
+
+        #[test]
+        fn moves_only_when_the_fingerprint_changes() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(SCHEMA).unwrap();
+            let stamp = |conn: &Connection| -> String {
+                conn.query_row("SELECT last_new_item_at FROM source_activity", [], |row| {
+                    row.get(0)
+                })
+                .unwrap()
+            };
+            record_activity(&mut conn, &[("bbc", "a".into())]).unwrap();
+            conn.execute(
+                "UPDATE source_activity SET last_new_item_at = '2000-01-01'",
+                [],
+            )
+            .unwrap();
+            record_activity(&mut conn, &[("bbc", "a".into())]).unwrap();
+            assert_eq!(stamp(&conn), "2000-01-01");
+            record_activity(&mut conn, &[("bbc", "b".into())]).unwrap();
+            assert_ne!(stamp(&conn), "2000-01-01");
+        }
+    }
+
+    mod validators {
+        use super::*;
+
+        fn result(source_id: &str, not_modified: bool, etag: Option<&str>) -> FetchResult {
+            FetchResult {
+                source_id: source_id.into(),
+                articles: Vec::new(),
+                error: None,
+                fetch_ms: 100,
+                not_modified,
+                validators: etag.map(|etag| Validators {
+                    etag: Some(etag.into()),
+                    last_modified: None,
+                }),
+                feed_url: None,
+            }
+        }
+
+        #[test]
+        fn are_used_once_a_digest_has_run_since() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(SCHEMA).unwrap();
+            record_validators(
+                &mut conn,
+                &[
+                    result("bbc", false, Some("\"v1\"")),
+                    result("npr", false, None),
+                ],
+            )
+            .unwrap();
+
+            assert!(validators(&conn, None).unwrap().is_empty());
+            assert!(
+                validators(&conn, Some("2000-01-01 00:00:00"))
+                    .unwrap()
+                    .is_empty()
+            );
+            let later = validators(&conn, Some("9999-01-01 00:00:00")).unwrap();
+            assert_eq!(later.len(), 1);
+            assert_eq!(later["bbc"].etag.as_deref(), Some("\"v1\""));
+        }
+
+        #[test]
+        fn are_kept_on_304_and_dropped_when_no_longer_sent() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(SCHEMA).unwrap();
+            let since = Some("9999-01-01 00:00:00");
+            record_validators(&mut conn, &[result("bbc", false, Some("\"v1\""))]).unwrap();
+            record_validators(&mut conn, &[result("bbc", true, Some("\"v1\""))]).unwrap();
+            assert_eq!(validators(&conn, since).unwrap().len(), 1);
+            record_validators(&mut conn, &[result("bbc", false, None)]).unwrap();
+            assert!(validators(&conn, since).unwrap().is_empty());
+        }
+    }
+
+    mod discovered_feeds {
+        use super::*;
+
+        fn source(id: &str, url: &str) -> Source {
+            crate::sources::parse(&format!(
+                "[[source]]\nid = \"{id}\"\nname = \"Site\"\nurl = \"{url}\"\nbias = \"center\"\nperspective = \"western\"\n"
+            ))
+            .unwrap()
+            .remove(0)
+        }
+
+        fn result(source_id: &str, feed_url: Option<&str>, error: Option<&str>) -> FetchResult {
+            FetchResult {
+                source_id: source_id.into(),
+                articles: Vec::new(),
+                error: error.map(str::to_string),
+                fetch_ms: 100,
+                not_modified: false,
+                validators: None,
+                feed_url: feed_url.map(str::to_string),
+            }
+        }
+
+        #[test]
+        fn are_kept_while_the_source_points_at_the_site() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(SCHEMA).unwrap();
+            let site = [source("blog", "https://example.com/")];
+            record_discovered(
+                &mut conn,
+                &site,
+                &[result("blog", Some("https://example.com/feed"), None)],
+            )
+            .unwrap();
+            assert_eq!(
+                discovered_feeds(&conn, &site).unwrap()["blog"],
+                "https://example.com/feed"
+            );
+            let moved = [source("blog", "https://example.org/")];
+            assert!(discovered_feeds(&conn, &moved).unwrap().is_empty());
+
+            record_discovered(&mut conn, &site, &[result("blog", None, Some("HTTP 503"))]).unwrap();
+            assert_eq!(discovered_feeds(&conn, &site).unwrap().len(), 1);
+            record_discovered(&mut conn, &site, &[result("blog", None, None)]).unwrap();
+            assert!(discovered_feeds(&conn, &site).unwrap().is_empty());
+        }
+    }
+}
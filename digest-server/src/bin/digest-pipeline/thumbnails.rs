@@ -0,0 +1,399 @@
+//! Narratives' pictures, fetched once by the pipeline and kept in `images`,
+//! so digests show them from digest-server's `/img/{hash}` rather than
+//! hotlinking publishers, whose links break and who'd see every reader's IP.
+//! A narrative's picture is the first of its articles' that loads: the one
+//! their feed lists or, failing that, their page's `og:image`. Of the sizes a
+//! feed lists, the smallest at least [`WIDTH`] wide is taken, and a picture
+//! wider than that is scaled down to it before it's kept: to a JPEG, or a
+//! PNG if it has transparency (AVIFs, which there's no decoder here for, are
+//! kept as they are). Pictures over `IMAGE_MAX_BYTES` (as `run.py` reads it)
+//! as downloaded, or narrower than an icon, are passed over.
+
+use crate::curate::{Cluster, FetchedArticle, Narrative, Selections};
+use crate::hosts::Scheduler;
+use crate::pages;
+use crate::robots::Robots;
+use crate::sources::Source;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat, ImageReader, Limits};
+use reqwest::{Client, Url};
+use scraper::{Html, Selector};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{Arc, LazyLock};
+
+/// Width a digest shows pictures at, in pixels
+pub const WIDTH: u32 = 600;
+
+/// Narrower pictures are icons or tracking pixels
+const MIN_WIDTH: u32 = 200;
+
+/// Quality of the JPEGs pictures are scaled down to
+const JPEG_QUALITY: u8 = 82;
+
+/// Most memory decoding a picture may take, in bytes, against ones whose
+/// header claims far more pixels than their download size would hold
+const MAX_DECODED_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Largest picture kept, in bytes, unless `IMAGE_MAX_BYTES` says otherwise
+const DEFAULT_MAX_BYTES: usize = 500_000;
+
+static PAGE_IMAGE: LazyLock<Selector> = LazyLock::new(|| {
+    Selector::parse(r#"meta[property="og:image"][content], meta[name="twitter:image"][content]"#)
+        .expect("valid selector")
+});
+
+/// A picture to keep in `images`
+#[derive(Debug)]
+pub struct Picture {
+    /// Hex SHA-256 of `data`, as digest-server serves it by
+    pub hash: String,
+    pub content_type: &'static str,
+    pub data: Vec<u8>,
+}
+
+/// Of the pictures a feed item lists, as (URL, width if given), the one to
+/// show: the smallest at least [`WIDTH`] wide, else one of unknown width,
+/// else the widest
+pub fn pick(listed: &[(String, Option<u32>)]) -> Option<String> {
+    listed
+        .iter()
+        .min_by_key(|(_, width)| match width {
+            Some(width) if *width >= WIDTH => (0, *width),
+            None => (1, 0),
+            Some(width) => (2, u32::MAX - width),
+        })
+        .map(|(url, _)| url.clone())
+}
+
+/// The picture the page at `url` names for sharing (`og:image`, or
+/// `twitter:image`)
+pub fn page_image(html: &str, url: &str) -> Option<String> {
+    let base = Url::parse(url).ok()?;
+    Html::parse_document(html)
+        .select(&PAGE_IMAGE)
+        .filter_map(|meta| base.join(meta.value().attr("content")?.trim()).ok())
+        .find(|image| matches!(image.scheme(), "http" | "https"))
+        .map(String::from)
+}
+
+/// Content type of a JPEG, PNG, GIF, WebP, or AVIF picture, from its
+/// leading bytes, as `run.py`'s `image_type` tells it
+pub fn content_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        Some("image/webp")
+    } else if matches!(data.get(4..12), Some(b"ftypavif" | b"ftypavis")) {
+        Some("image/avif")
+    } else {
+        None
+    }
+}
+
+/// Width of a JPEG, PNG, GIF, or WebP picture, in pixels, from its header
+pub fn width(data: &[u8]) -> Option<u32> {
+    let be16 = |at: usize| Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?) as u32);
+    let le16 = |at: usize| Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?) as u32);
+    match content_type(data)? {
+        "image/png" => Some(u32::from_be_bytes(data.get(16..20)?.try_into().ok()?)),
+        "image/gif" => le16(6),
+        "image/webp" => match data.get(12..16)? {
+            b"VP8 " => Some(le16(26)? & 0x3fff),
+            b"VP8L" => Some((le16(21)? & 0x3fff) + 1),
+            b"VP8X" => {
+                let bytes = data.get(24..27)?;
+                Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]) + 1)
+            }
+            _ => None,
+        },
+        "image/jpeg" => {
+            // The frame header follows whatever segments come first
+            let mut at = 2;
+            while *data.get(at)? == 0xff {
+                let marker = *data.get(at + 1)?;
+                if matches!(marker, 0xc0..=0xcf) && !matches!(marker, 0xc4 | 0xc8 | 0xcc) {
+                    return be16(at + 7);
+                }
+                at += 2 + be16(at + 2)? as usize;
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// `data`, scaled down to [`WIDTH`] wide if it's wider, as a JPEG or, if it
+/// has transparency, a PNG. AVIFs, and pictures no wider, come back as they
+/// are. An animated GIF that's too wide keeps only its first frame.
+pub fn downscale(data: Vec<u8>) -> Result<Vec<u8>, String> {
+    if content_type(&data) == Some("image/avif") || width(&data).is_some_and(|w| w <= WIDTH) {
+        return Ok(data);
+    }
+    let mut limits = Limits::default();
+    limits.max_alloc = Some(MAX_DECODED_BYTES);
+    let mut reader = ImageReader::new(Cursor::new(&data[..]))
+        .with_guessed_format()
+        .map_err(|e| e.to_string())?;
+    reader.limits(limits);
+    let decoded = reader
+        .decode()
+        .map_err(|e| format!("Couldn't decode it: {e}"))?;
+    if decoded.width() <= WIDTH {
+        return Ok(data);
+    }
+    let scaled = decoded.resize(WIDTH, u32::MAX, FilterType::Lanczos3);
+    let mut out = Cursor::new(Vec::new());
+    if scaled.color().has_alpha() {
+        scaled.write_to(&mut out, ImageFormat::Png)
+    } else {
+        let rgb = DynamicImage::ImageRgb8(scaled.into_rgb8());
+        rgb.write_with_encoder(image::codecs::jpeg::JpegEncoder::new_with_quality(
+            &mut out,
+            JPEG_QUALITY,
+        ))
+    }
+    .map_err(|e| format!("Couldn't encode it: {e}"))?;
+    Ok(out.into_inner())
+}
+
+/// `IMAGE_MAX_BYTES`, or the default
+fn max_bytes() -> usize {
+    std::env::var("IMAGE_MAX_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|&bytes| bytes > 0)
+        .unwrap_or(DEFAULT_MAX_BYTES)
+}
+
+/// Fetch a picture for each must_know and should_know narrative in
+/// `selections`, from the articles of `clusters` it cites, and set its
+/// `image` to the picture's hash. Returns the pictures, to be stored.
+pub async fn fetch_all(
+    client: &Client,
+    scheduler: &Arc<Scheduler>,
+    robots: &Arc<Robots>,
+    clusters: &[Cluster<'_>],
+    selections: &mut Selections,
+) -> Vec<Picture> {
+    let articles: HashMap<&str, &(&Source, FetchedArticle)> = clusters
+        .iter()
+        .flat_map(|c| c.articles.iter())
+        .map(|cited| (cited.1.url.as_str(), cited))
+        .collect();
+    let max_bytes = max_bytes();
+    let mut tasks = tokio::task::JoinSet::new();
+    let narratives = selections
+        .must_know
+        .iter()
+        .chain(&selections.should_know)
+        .enumerate();
+    for (index, narrative) in narratives {
+        // Each cited article's page, the picture its feed lists, and the
+        // delay its source asks for
+        let cited: Vec<(String, Option<String>, Option<f64>)> = narrative
+            .sources
+            .iter()
+            .filter_map(|s| articles.get(s.url.as_str()))
+            .map(|(source, article)| {
+                (
+                    article.url.clone(),
+                    article.image.clone(),
+                    source.delay_secs,
+                )
+            })
+            .collect();
+        let (client, scheduler, robots) = (client.clone(), scheduler.clone(), robots.clone());
+        tasks.spawn(async move {
+            for (page, listed, delay_secs) in cited {
+                // The feed's picture, then the page's if that one won't do
+                let mut image = listed;
+                let mut page = Some(page);
+                loop {
+                    if image.is_none()
+                        && let Some(page) = page.take()
+                        && robots.allows(&client, &scheduler, &page).await
+                    {
+                        let _turn = scheduler.wait(&page, delay_secs).await;
+                        image = pages::download(&client, &page)
+                            .await
+                            .ok()
+                            .and_then(|html| page_image(&html, &page));
+                    }
+                    let Some(url) = image.take() else {
+                        break;
+                    };
+                    match download(&client, &scheduler, &url, delay_secs, max_bytes).await {
+                        Ok(picture) => return (index, Some(picture)),
+                        Err(e) => tracing::debug!("Passed over the picture {}: {}", url, e),
+                    }
+                }
+            }
+            (index, None)
+        });
+    }
+
+    let mut pictures = Vec::new();
+    let mut found = tasks.join_all().await;
+    found.sort_by_key(|(index, _)| *index);
+    let narratives: Vec<&mut Narrative> = selections
+        .must_know
+        .iter_mut()
+        .chain(&mut selections.should_know)
+        .collect();
+    for ((_, picture), narrative) in found.into_iter().zip(narratives) {
+        if let Some(picture) = picture {
+            narrative.image = Some(picture.hash.clone());
+            pictures.push(picture);
+        }
+    }
+    pictures
+}
+
+/// The picture at `url`, if it's one worth keeping
+async fn download(
+    client: &Client,
+    scheduler: &Scheduler,
+    url: &str,
+    delay_secs: Option<f64>,
+    max_bytes: usize,
+) -> Result<Picture, String> {
+    let _turn = scheduler.wait(url, delay_secs).await;
+    let mut response = client.get(url).send().await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("HTTP {status}"));
+    }
+    let too_large = || format!("Larger than {max_bytes} bytes");
+    if response
+        .content_length()
+        .is_some_and(|length| length > max_bytes as u64)
+    {
+        return Err(too_large());
+    }
+    let mut data = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|e| e.to_string())? {
+        data.extend_from_slice(&chunk);
+        if data.len() > max_bytes {
+            return Err(too_large());
+        }
+    }
+    content_type(&data).ok_or("Not a JPEG, PNG, GIF, WebP, or AVIF image")?;
+    if let Some(width) = width(&data).filter(|width| *width < MIN_WIDTH) {
+        return Err(format!("Only {width} pixels wide"));
+    }
+    let data = tokio::task::spawn_blocking(move || downscale(data))
+        .await
+        .map_err(|e| e.to_string())??;
+    let content_type = content_type(&data).ok_or("Not a JPEG, PNG, GIF, WebP, or AVIF image")?;
+    let digest = ring::digest::digest(&ring::digest::SHA256, &data);
+    Ok(Picture {
+        hash: digest.as_ref().iter().map(|b| format!("{b:02x}")).collect(),
+        content_type,
+        data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod pick {
+        use super::*;
+
+        #[test]
+        fn takes_the_smallest_wide_enough() {
+            let listed = |widths: &[Option<u32>]| -> Vec<(String, Option<u32>)> {
+                widths.iter().map(|w| (format!("{w:?}"), *w)).collect()
+            };
+            let picked = |widths: &[Option<u32>]| pick(&listed(widths)).unwrap();
+            assert_eq!(
+                picked(&[Some(1920), Some(150), Some(800), None]),
+                "Some(800)"
+            );
+            assert_eq!(picked(&[Some(150), None, Some(400)]), "None");
+            assert_eq!(picked(&[Some(150), Some(400)]), "Some(400)");
+            assert_eq!(pick(&[]), None);
+        }
+    }
+
+    mod page_image {
+        use super::*;
+
+        #[test]
+        fn reads_the_sharing_picture() {
+            let html = r#"<html><head>
+                <meta property="og:image" content="/img/lead.jpg">
+                <meta name="twitter:image" content="https://cdn.example/lead.jpg">
+            </head></html>"#;
+            assert_eq!(
+                page_image(html, "https://example.com/news/1").as_deref(),
+                Some("https://example.com/img/lead.jpg")
+            );
+            let html = r#"<meta property="og:image" content="javascript:alert(1)">"#;
+            assert_eq!(page_image(html, "https://example.com/"), None);
+        }
+    }
+
+    mod downscale {
+        use super::*;
+
+        fn encoded(picture: DynamicImage, format: ImageFormat) -> Vec<u8> {
+            let mut data = Cursor::new(Vec::new());
+            picture.write_to(&mut data, format).unwrap();
+            data.into_inner()
+        }
+
+        #[test]
+        fn scales_wide_pictures_to_the_width() {
+            let wide = encoded(DynamicImage::new_rgb8(1200, 800), ImageFormat::Png);
+            let scaled = downscale(wide).unwrap();
+            assert_eq!(content_type(&scaled), Some("image/jpeg"));
+            let scaled = image::load_from_memory(&scaled).unwrap();
+            assert_eq!((scaled.width(), scaled.height()), (WIDTH, 400));
+
+            let transparent = encoded(DynamicImage::new_rgba8(1200, 600), ImageFormat::Png);
+            let scaled = downscale(transparent).unwrap();
+            assert_eq!(content_type(&scaled), Some("image/png"));
+            assert_eq!(width(&scaled), Some(WIDTH));
+        }
+
+        #[test]
+        fn keeps_narrow_pictures_as_they_are() {
+            let narrow = encoded(DynamicImage::new_rgb8(WIDTH, 300), ImageFormat::Png);
+            assert_eq!(downscale(narrow.clone()).unwrap(), narrow);
+            let avif = b"\0\0\0\x1cftypavif".to_vec();
+            assert_eq!(downscale(avif.clone()).unwrap(), avif);
+            assert!(downscale(b"\xff\xd8\xff\xe0 truncated".to_vec()).is_err());
+        }
+    }
+
+    mod width {
+        use super::*;
+
+        #[test]
+        fn reads_it_from_the_header() {
+            let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+            png.extend_from_slice(&[0, 0, 2, 0x58, 0, 0, 1, 0x90]);
+            assert_eq!(width(&png), Some(600));
+
+            assert_eq!(width(b"GIF89a\x10\x00\x10\x00"), Some(16));
+
+            // An APP0 segment, then the frame header: 8-bit, 400 high, 640 wide
+            let mut jpeg = b"\xff\xd8\xff\xe0\x00\x04JF".to_vec();
+            jpeg.extend_from_slice(b"\xff\xc0\x00\x11\x08\x01\x90\x02\x80");
+            assert_eq!(width(&jpeg), Some(640));
+
+            let mut webp = b"RIFF\0\0\0\0WEBPVP8X\x0a\0\0\0\0\0\0\0".to_vec();
+            webp.extend_from_slice(&[0x1f, 0x03, 0x00, 0x57, 0x02, 0x00]);
+            assert_eq!(width(&webp), Some(800));
+
+            assert_eq!(width(b"\xff\xd8\xff"), None);
+            assert_eq!(width(b"<svg/>"), None);
+        }
+    }
+}
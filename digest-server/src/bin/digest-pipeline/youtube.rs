@@ -0,0 +1,222 @@
+//! YouTube channels and playlists as sources. A channel's or playlist's URL
+//! is read from its Atom feed (a handle's page links to it, and is found
+//! like any site's feed), each video's description standing in for a
+//! summary. With `FULL_TEXT` set, videos get their transcript, when they
+//! have captions, in place of the article text pages get. Stories only
+//! videos carry are kept out of must_know (see `curate`): explainers rarely
+//! break news.
+
+use crate::pages::plain_text;
+use regex::Regex;
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use std::sync::LazyLock;
+
+/// Where a channel's or playlist's feed is, given its id
+const FEEDS: &str = "https://www.youtube.com/feeds/videos.xml";
+
+static CAPTION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<(text|p)\b[^>]*>(.*?)</(text|p)>").expect("valid regex"));
+
+/// A video's captions in one language, as its page lists them
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Track {
+    base_url: String,
+    #[serde(default)]
+    language_code: String,
+    /// "asr" for captions generated by speech recognition
+    #[serde(default)]
+    kind: Option<String>,
+}
+
+fn is_youtube(url: &Url) -> bool {
+    matches!(
+        url.host_str(),
+        Some("youtube.com" | "www.youtube.com" | "m.youtube.com" | "youtu.be")
+    )
+}
+
+/// The feed of the channel, user, or playlist at `url`, when its id is in
+/// the URL
+pub fn feed_url(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    if !is_youtube(&url) {
+        return None;
+    }
+    let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    let (key, id) = match segments[..] {
+        ["channel", id, ..] => ("channel_id", id.to_string()),
+        ["user", name, ..] => ("user", name.to_string()),
+        ["playlist"] => (
+            "playlist_id",
+            url.query_pairs()
+                .find(|(key, _)| key == "list")
+                .map(|(_, list)| list.into_owned())?,
+        ),
+        _ => return None,
+    };
+    Url::parse_with_params(FEEDS, [(key, id)])
+        .ok()
+        .map(String::from)
+}
+
+/// The id of the video at `url`
+pub fn video_id(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    if !is_youtube(&url) {
+        return None;
+    }
+    let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+    let id = match segments[..] {
+        [id] if url.host_str() == Some("youtu.be") => id.to_string(),
+        ["watch"] => url
+            .query_pairs()
+            .find(|(key, _)| key == "v")
+            .map(|(_, id)| id.into_owned())?,
+        ["shorts" | "live" | "embed", id] => id.to_string(),
+        _ => return None,
+    };
+    let valid = !id.is_empty()
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_'));
+    valid.then_some(id)
+}
+
+/// Where the best of a watch page's captions are: hand-written English,
+/// then generated English, then hand-written in any language, then any
+fn caption_url(page: &str) -> Option<String> {
+    let start = page.find("\"captionTracks\":")? + "\"captionTracks\":".len();
+    let tracks = serde_json::Deserializer::from_str(&page[start..])
+        .into_iter::<Vec<Track>>()
+        .next()?
+        .ok()?;
+    let english = |t: &&Track| t.language_code == "en" || t.language_code.starts_with("en-");
+    let written = |t: &&Track| t.kind.as_deref() != Some("asr");
+    let track = tracks
+        .iter()
+        .filter(english)
+        .find(written)
+        .or_else(|| tracks.iter().find(english))
+        .or_else(|| tracks.iter().find(written))
+        .or_else(|| tracks.first())?;
+    Url::parse(&track.base_url)
+        .ok()
+        .filter(is_youtube)
+        .map(String::from)
+}
+
+/// The text of captions in YouTube's timed-text XML
+fn caption_text(xml: &str) -> String {
+    let lines: Vec<String> = CAPTION
+        .captures_iter(xml)
+        // Caption text is escaped twice over
+        .map(|c| plain_text(&plain_text(&c[2])))
+        .filter(|line| !line.is_empty())
+        .collect();
+    lines.join(" ")
+}
+
+/// The transcript of the video at `url`, if it has captions
+pub async fn transcript(client: &Client, url: &str) -> Result<Option<String>, String> {
+    let id = video_id(url).ok_or("Not a YouTube video")?;
+    let page =
+        crate::pages::download(client, &format!("https://www.youtube.com/watch?v={id}")).await?;
+    let Some(captions) = caption_url(&page) else {
+        return Ok(None);
+    };
+    let response = client
+        .get(&captions)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("HTTP {status} for captions"));
+    }
+    let xml = response.text().await.map_err(|e| e.to_string())?;
+    let text = caption_text(&xml);
+    Ok((!text.is_empty()).then_some(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod feed_url {
+        use super::*;
+
+        #[test]
+        fn reads_ids_from_channel_user_and_playlist_urls() {
+            assert_eq!(
+                feed_url("https://www.youtube.com/channel/UCabc-123_x/videos").as_deref(),
+                Some("https://www.youtube.com/feeds/videos.xml?channel_id=UCabc-123_x")
+            );
+            assert_eq!(
+                feed_url("https://youtube.com/user/someone").as_deref(),
+                Some("https://www.youtube.com/feeds/videos.xml?user=someone")
+            );
+            assert_eq!(
+                feed_url("https://www.youtube.com/playlist?list=PLxyz&si=t").as_deref(),
+                Some("https://www.youtube.com/feeds/videos.xml?playlist_id=PLxyz")
+            );
+            assert_eq!(feed_url("https://www.youtube.com/@someone"), None);
+            assert_eq!(
+                feed_url("https://www.youtube.com/feeds/videos.xml?channel_id=UC1"),
+                None
+            );
+            assert_eq!(feed_url("https://example.com/channel/UC1"), None);
+        }
+    }
+
+    mod video_id {
+        use super::*;
+
+        #[test]
+        fn reads_it_from_each_kind_of_link() {
+            for url in [
+                "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42",
+                "https://youtu.be/dQw4w9WgXcQ",
+                "https://www.youtube.com/shorts/dQw4w9WgXcQ",
+                "https://m.youtube.com/embed/dQw4w9WgXcQ",
+            ] {
+                assert_eq!(video_id(url).as_deref(), Some("dQw4w9WgXcQ"), "{url}");
+            }
+            assert_eq!(video_id("https://www.youtube.com/watch?v=a/b"), None);
+            assert_eq!(video_id("https://www.youtube.com/@someone"), None);
+            assert_eq!(video_id("https://example.com/watch?v=dQw4w9WgXcQ"), None);
+        }
+    }
+
+    mod caption_url {
+        use super::*;
+
+        #[test]
+        fn prefers_written_english_captions() {
+            let page = r#"<script>var ytInitialPlayerResponse = {"captions":{"playerCaptionsTracklistRenderer":{"captionTracks":[
+                {"baseUrl":"https://www.youtube.com/api/timedtext?v=x&lang=en&kind=asr","name":{"runs":[{"text":"English (auto)"}]},"languageCode":"en","kind":"asr"},
+                {"baseUrl":"https://www.youtube.com/api/timedtext?v=x&lang=de","languageCode":"de"},
+                {"baseUrl":"https://www.youtube.com/api/timedtext?v=x&lang=en-GB","languageCode":"en-GB"}
+            ],"audioTracks":[]}}};</script>"#;
+            assert_eq!(
+                caption_url(page).as_deref(),
+                Some("https://www.youtube.com/api/timedtext?v=x&lang=en-GB")
+            );
+            assert_eq!(caption_url("<html>No captions</html>"), None);
+        }
+    }
+
+    mod caption_text {
+        use super::*;
+
+        #[test]
+        fn joins_captions_unescaped() {
+            let xml = r#"<?xml version="1.0" encoding="utf-8" ?><transcript>
+                <text start="0.5" dur="2">Today we&amp;#39;re looking</text>
+                <text start="2.5" dur="1">at chips &amp;amp; rules</text>
+                <text start="4" dur="1"></text></transcript>"#;
+            assert_eq!(caption_text(xml), "Today we're looking at chips & rules");
+        }
+    }
+}
@@ -0,0 +1,403 @@
+//! Digest bodies kept outside the database (`DIGEST_STORE`).
+//!
+//! When a blob store is configured, the compression job moves each digest's
+//! zstd-compressed HTML out of `digests.html_zstd` into the store and records
+//! its key in `html_blob`, so the database keeps only queryable metadata.
+//! Keys name their content (`digests/{date}/{sha256}.html.zst`): a key is
+//! never rewritten, so revisions can keep pointing at superseded bodies.
+//! Nothing deletes blobs.
+//!
+//! - `sqlite:NAME` - a `blobs` table in a file next to `DATABASE_PATH`
+//! - `file:/DIR` - one file per blob under a directory
+//! - `s3://BUCKET/PREFIX` - an S3-compatible bucket, signed with the `S3_*`
+//!   endpoint and credentials backups use
+
+use crate::{compression, db, s3};
+use ring::digest;
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Rows offloaded per pass, so a large backlog is picked up in steps
+const BATCH: i64 = 20;
+
+/// Where digest bodies are stored
+pub trait BlobStore: Send + Sync {
+    /// Backend name, for logs
+    fn name(&self) -> &'static str;
+
+    /// The bytes stored under `key`, if any
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String>;
+
+    /// Store `data` under `key`
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), String>;
+}
+
+/// The store `DIGEST_STORE` names, if any
+pub fn from_env(db_path: &str) -> Result<Option<Arc<dyn BlobStore>>, String> {
+    std::env::var("DIGEST_STORE")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .map(|spec| open(&spec, db_path))
+        .transpose()
+}
+
+/// Open the store a `DIGEST_STORE` value describes
+fn open(spec: &str, db_path: &str) -> Result<Arc<dyn BlobStore>, String> {
+    if let Some(name) = spec.strip_prefix("sqlite:") {
+        if name.is_empty() || name.contains('/') || name.starts_with('.') {
+            return Err("DIGEST_STORE=sqlite: needs a file name, like sqlite:blobs.db".into());
+        }
+        let path = Path::new(db_path).with_file_name(name);
+        return Ok(Arc::new(SqliteBlobs {
+            path: path.to_string_lossy().into_owned(),
+        }));
+    }
+    if let Some(dir) = spec.strip_prefix("file:") {
+        if !dir.starts_with('/') {
+            return Err(
+                "DIGEST_STORE=file: needs an absolute directory, like file:/data/blobs".into(),
+            );
+        }
+        return Ok(Arc::new(FileBlobs { root: dir.into() }));
+    }
+    if let Some(location) = spec.strip_prefix("s3://") {
+        let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+        if bucket.is_empty() {
+            return Err("DIGEST_STORE=s3:// needs a bucket, like s3://my-bucket/digests/".into());
+        }
+        let prefix = match prefix.trim_end_matches('/') {
+            "" => String::new(),
+            prefix => format!("{prefix}/"),
+        };
+        return Ok(Arc::new(S3Blobs {
+            client: reqwest::Client::new(),
+            config: s3::S3Config::for_bucket(bucket.into(), prefix, 1)?,
+            runtime: tokio::runtime::Handle::try_current()
+                .map_err(|e| format!("No runtime for S3 blobs: {e}"))?,
+        }));
+    }
+    Err(format!(
+        "DIGEST_STORE must start with sqlite:, file:, or s3://, got '{spec}'"
+    ))
+}
+
+/// Keys are generated here, but come back from the database: refuse anything
+/// that could leave the store's directory
+fn check_key(key: &str) -> Result<(), String> {
+    let safe = !key.is_empty()
+        && !key.starts_with('/')
+        && key.split('/').all(|part| !part.is_empty() && part != "..")
+        && key
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'/'));
+    if safe {
+        Ok(())
+    } else {
+        Err(format!("Bad blob key '{key}'"))
+    }
+}
+
+/// Blobs in their own SQLite file, keyed like the main database
+struct SqliteBlobs {
+    path: String,
+}
+
+impl BlobStore for SqliteBlobs {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        if !Path::new(&self.path).exists() {
+            return Ok(None);
+        }
+        let conn = db::open_alone(&self.path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| format!("Cannot open {}: {e}", self.path))?;
+        if !db::table_exists(&conn, "blobs")? {
+            return Ok(None);
+        }
+        conn.query_row("SELECT data FROM blobs WHERE key = ?1", [key], |row| {
+            row.get(0)
+        })
+        .optional()
+        .map_err(|e| format!("Query error: {e}"))
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), String> {
+        let conn = db::open_alone(
+            &self.path,
+            OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE,
+        )
+        .map_err(|e| format!("Cannot open {}: {e}", self.path))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS blobs (key TEXT PRIMARY KEY, data BLOB NOT NULL)",
+        )
+        .map_err(|e| format!("Cannot create blobs table: {e}"))?;
+        conn.execute(
+            "INSERT OR REPLACE INTO blobs (key, data) VALUES (?1, ?2)",
+            rusqlite::params![key, data],
+        )
+        .map_err(|e| format!("Cannot store blob {key}: {e}"))?;
+        Ok(())
+    }
+}
+
+/// One file per blob, written whole with a rename so readers never see half
+struct FileBlobs {
+    root: PathBuf,
+}
+
+impl BlobStore for FileBlobs {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        check_key(key)?;
+        match std::fs::read(self.root.join(key)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Cannot read blob {key}: {e}")),
+        }
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), String> {
+        check_key(key)?;
+        let path = self.root.join(key);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Cannot create {}: {e}", dir.display()))?;
+        }
+        let partial = path.with_extension("partial");
+        std::fs::write(&partial, data)
+            .and_then(|()| std::fs::rename(&partial, &path))
+            .map_err(|e| format!("Cannot store blob {key}: {e}"))
+    }
+}
+
+/// An S3-compatible bucket. Store calls come from blocking threads, so
+/// requests are driven on the server's runtime from there.
+struct S3Blobs {
+    client: reqwest::Client,
+    config: s3::S3Config,
+    runtime: tokio::runtime::Handle,
+}
+
+impl BlobStore for S3Blobs {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        check_key(key)?;
+        tokio::task::block_in_place(|| {
+            self.runtime
+                .block_on(s3::get(&self.client, &self.config, key))
+        })
+    }
+
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), String> {
+        check_key(key)?;
+        tokio::task::block_in_place(|| {
+            self.runtime
+                .block_on(s3::put(&self.client, &self.config, key, data.to_vec()))
+        })
+    }
+}
+
+/// Key for a digest body, named by its compressed bytes
+fn key(date: &str, compressed: &[u8]) -> String {
+    let hash: String = digest::digest(&digest::SHA256, compressed)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    format!("digests/{date}/{hash}.html.zst")
+}
+
+/// The digest HTML from a row's `html`, `html_zstd`, and `html_blob` columns,
+/// fetching from `store` when the body was offloaded
+pub fn html(
+    store: Option<&dyn BlobStore>,
+    plain: String,
+    compressed: Option<Vec<u8>>,
+    blob: Option<String>,
+) -> Result<String, String> {
+    match blob {
+        Some(key) if plain.is_empty() && compressed.is_none() => {
+            let store = store.ok_or_else(|| {
+                format!("Digest body {key} is in a blob store, but DIGEST_STORE is unset")
+            })?;
+            let data = store
+                .get(&key)?
+                .ok_or_else(|| format!("Blob {key} is missing from the {} store", store.name()))?;
+            compression::html(String::new(), Some(data))
+        }
+        _ => compression::html(plain, compressed),
+    }
+}
+
+/// Add `html_blob` to digests and their revisions, and have the revision
+/// triggers copy it
+pub fn migrate(conn: &Connection) -> Result<(), String> {
+    for table in ["digests", "digest_revisions"] {
+        if db::table_exists(conn, table)? && !db::column_exists(conn, table, "html_blob")? {
+            conn.execute_batch(&format!("ALTER TABLE {table} ADD COLUMN html_blob TEXT"))
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    if db::table_exists(conn, "digest_revisions")? {
+        conn.execute_batch(crate::revisions::BLOB_TRIGGERS)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Move compressed digest bodies into `store`, returning how many. Rows are
+/// only repointed if they still hold the bytes that were stored, so a
+/// rewrite during the upload is left for the next pass.
+pub fn offload_pending(conn: &Connection, store: &dyn BlobStore) -> Result<usize, String> {
+    if !db::column_exists(conn, "digests", "html_blob")? {
+        return Ok(0);
+    }
+    let mut total = 0;
+    loop {
+        let pending: Vec<(String, Vec<u8>)> = {
+            let mut stmt = conn
+                .prepare(
+                    "SELECT date, html_zstd FROM digests
+                     WHERE html = '' AND html_zstd IS NOT NULL LIMIT ?1",
+                )
+                .map_err(|e| format!("Query error: {e}"))?;
+            stmt.query_map([BATCH], |row| Ok((row.get(0)?, row.get(1)?)))
+                .map_err(|e| format!("Query error: {e}"))?
+                .filter_map(|r| r.ok())
+                .collect()
+        };
+        let mut moved = 0;
+        for (date, compressed) in &pending {
+            let key = key(date, compressed);
+            store.put(&key, compressed)?;
+            moved += conn
+                .execute(
+                    "UPDATE digests SET html_blob = ?1, html_zstd = NULL
+                     WHERE date = ?2 AND html = '' AND html_zstd = ?3",
+                    rusqlite::params![key, date, compressed],
+                )
+                .map_err(|e| format!("Cannot offload digest {date}: {e}"))?;
+        }
+        total += moved;
+        if moved == 0 {
+            return Ok(total);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digests() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch("CREATE TABLE digests (date TEXT PRIMARY KEY, html TEXT NOT NULL)")
+            .unwrap();
+        crate::migrations::run(&mut conn).unwrap();
+        for (date, html) in [("2026-01-01", "<p>one</p>"), ("2026-01-02", "<p>two</p>")] {
+            conn.execute(
+                "INSERT INTO digests (date, html, html_zstd) VALUES (?1, '', ?2)",
+                rusqlite::params![date, zstd::encode_all(html.as_bytes(), 3).unwrap()],
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    fn read(conn: &Connection, store: &dyn BlobStore, date: &str) -> String {
+        let (plain, compressed, blob) = conn
+            .query_row(
+                "SELECT html, html_zstd, html_blob FROM digests WHERE date = ?1",
+                [date],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        html(Some(store), plain, compressed, blob).unwrap()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    mod offload_pending {
+        use super::*;
+
+        #[test]
+        fn moves_bodies_to_each_store() {
+            let dir = temp_dir("blobs-stores");
+            let db_path = dir.join("digest.db");
+            let db_path = db_path.to_str().unwrap();
+            let file = format!("file:{}", dir.join("blobs").display());
+            for spec in ["sqlite:blobs.db", file.as_str()] {
+                let store = open(spec, db_path).unwrap();
+                let conn = digests();
+                assert_eq!(offload_pending(&conn, store.as_ref()).unwrap(), 2);
+                assert_eq!(offload_pending(&conn, store.as_ref()).unwrap(), 0);
+                assert_eq!(read(&conn, store.as_ref(), "2026-01-02"), "<p>two</p>");
+                let inline: i64 = conn
+                    .query_row(
+                        "SELECT COUNT(*) FROM digests WHERE html_zstd IS NOT NULL",
+                        [],
+                        |row| row.get(0),
+                    )
+                    .unwrap();
+                assert_eq!(inline, 0, "{spec}");
+            }
+        }
+
+        #[test]
+        fn rewrites_keep_the_moved_body_as_a_revision() {
+            let dir = temp_dir("blobs-rewrite");
+            let store = open("sqlite:blobs.db", dir.join("digest.db").to_str().unwrap()).unwrap();
+            let conn = digests();
+            offload_pending(&conn, store.as_ref()).unwrap();
+            conn.execute(
+                "INSERT OR REPLACE INTO digests (date, html) VALUES ('2026-01-01', '<p>redone</p>')",
+                [],
+            )
+            .unwrap();
+            assert_eq!(read(&conn, store.as_ref(), "2026-01-01"), "<p>redone</p>");
+            let (plain, compressed, blob) = conn
+                .query_row(
+                    "SELECT html, html_zstd, html_blob FROM digest_revisions",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+                )
+                .unwrap();
+            assert_eq!(
+                html(Some(store.as_ref()), plain, compressed, blob).unwrap(),
+                "<p>one</p>"
+            );
+        }
+
+        #[test]
+        fn needs_the_store_to_read() {
+            let err = html(None, String::new(), None, Some("digests/x.html.zst".into()));
+            assert!(err.unwrap_err().contains("DIGEST_STORE is unset"));
+        }
+    }
+
+    mod check_key {
+        use super::*;
+
+        #[test]
+        fn refuses_paths_out_of_the_store() {
+            assert!(check_key(&key("2026-01-01", b"x")).is_ok());
+            for bad in ["", "/etc/passwd", "digests/../../x", "a//b", "a b"] {
+                assert!(check_key(bad).is_err(), "{bad}");
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Small in-memory caches for expensive read-only queries and rendered pages
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Maps keys to shared values that expire `ttl` after being stored
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    entries: Mutex<HashMap<K, (Instant, Arc<V>)>>,
+}
+
+impl<K: Eq + Hash, V> TtlCache<K, V> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Return the cached value for `key`, or compute and store it.
+    ///
+    /// Errors are not cached. The lock isn't held while computing, so two
+    /// concurrent misses may both run `compute`; the last one wins.
+    pub fn get_or_try_insert<E>(
+        &self,
+        key: K,
+        compute: impl FnOnce() -> Result<V, E>,
+    ) -> Result<Arc<V>, E> {
+        if let Some((stored, value)) = self.entries.lock().unwrap().get(&key)
+            && stored.elapsed() < self.ttl
+        {
+            return Ok(Arc::clone(value));
+        }
+        let value = Arc::new(compute()?);
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (stored, _)| stored.elapsed() < self.ttl);
+        entries.insert(key, (Instant::now(), Arc::clone(&value)));
+        Ok(value)
+    }
+}
+
+/// Keeps the `capacity` most recently used values, evicting the least
+/// recently used one when full. A capacity of 0 stores nothing.
+pub struct LruCache<K, V> {
+    capacity: usize,
+    entries: Mutex<Lru<K, V>>,
+}
+
+struct Lru<K, V> {
+    tick: u64,
+    map: HashMap<K, (u64, Arc<V>)>,
+}
+
+impl<K: Eq + Hash + Clone, V> LruCache<K, V> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(Lru {
+                tick: 0,
+                map: HashMap::new(),
+            }),
+        }
+    }
+
+    /// The cached value for `key`, marking it as recently used
+    pub fn get(&self, key: &K) -> Option<Arc<V>> {
+        let mut lru = self.entries.lock().unwrap();
+        lru.tick += 1;
+        let tick = lru.tick;
+        let (used, value) = lru.map.get_mut(key)?;
+        *used = tick;
+        Some(Arc::clone(value))
+    }
+
+    /// Store `value` under `key`, replacing any previous value
+    pub fn insert(&self, key: K, value: V) -> Arc<V> {
+        let value = Arc::new(value);
+        if self.capacity == 0 {
+            return value;
+        }
+        let mut lru = self.entries.lock().unwrap();
+        if lru.map.len() >= self.capacity && !lru.map.contains_key(&key) {
+            // Capacities are small, so a scan beats maintaining a linked list
+            let oldest = lru
+                .map
+                .iter()
+                .min_by_key(|(_, (used, _))| *used)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                lru.map.remove(&oldest);
+            }
+        }
+        lru.tick += 1;
+        let tick = lru.tick;
+        lru.map.insert(key, (tick, Arc::clone(&value)));
+        value
+    }
+
+    /// Drop every entry, returning how many there were
+    pub fn clear(&self) -> usize {
+        let mut lru = self.entries.lock().unwrap();
+        let cleared = lru.map.len();
+        lru.map.clear();
+        cleared
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod lru_cache {
+        use super::*;
+
+        #[test]
+        fn evicts_the_least_recently_used() {
+            let cache = LruCache::new(2);
+            cache.insert("a", 1);
+            cache.insert("b", 2);
+            assert_eq!(cache.get(&"a").as_deref(), Some(&1));
+            cache.insert("c", 3);
+            assert!(cache.get(&"b").is_none());
+            assert_eq!(cache.get(&"a").as_deref(), Some(&1));
+            assert_eq!(cache.get(&"c").as_deref(), Some(&3));
+        }
+
+        #[test]
+        fn replaces_and_clears() {
+            let cache = LruCache::new(2);
+            cache.insert("a", 1);
+            cache.insert("a", 2);
+            assert_eq!(cache.get(&"a").as_deref(), Some(&2));
+            assert_eq!(cache.clear(), 1);
+            assert!(cache.get(&"a").is_none());
+
+            let disabled = LruCache::new(0);
+            disabled.insert("a", 1);
+            assert!(disabled.get(&"a").is_none());
+        }
+    }
+
+    mod get_or_try_insert {
+        use super::*;
+
+        #[test]
+        fn reuses_fresh_values() {
+            let cache = TtlCache::new(Duration::from_secs(60));
+            let first = cache.get_or_try_insert("k", || Ok::<_, ()>(1)).unwrap();
+            let second = cache.get_or_try_insert("k", || Ok::<_, ()>(2)).unwrap();
+            assert_eq!((*first, *second), (1, 1));
+        }
+
+        #[test]
+        fn recomputes_expired_values_and_skips_errors() {
+            let cache = TtlCache::new(Duration::ZERO);
+            cache.get_or_try_insert("k", || Ok::<_, ()>(1)).unwrap();
+            let again = cache.get_or_try_insert("k", || Ok::<_, ()>(2)).unwrap();
+            assert_eq!(*again, 2);
+
+            let cache = TtlCache::new(Duration::from_secs(60));
+            assert!(cache.get_or_try_insert("k", || Err("db locked")).is_err());
+            let value = cache.get_or_try_insert("k", || Ok::<_, &str>(3)).unwrap();
+            assert_eq!(*value, 3);
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! `Cache-Control` for the public pages, so a CDN or proxy in front can cache
+//! them. Each kind of page gets its own, replaced by setting the variable to
+//! another header value, or left without one by setting it empty:
+//!
+//! - `CACHE_CONTROL_PAST_DIGESTS`: digests dated two or more days ago
+//!   (default `public, max-age=86400`). Only corrections change them.
+//! - `CACHE_CONTROL_DIGESTS`: newer digests, which a pipeline re-run can
+//!   still replace (default `public, max-age=300`)
+//! - `CACHE_CONTROL_INDEX`: the index (default `public, max-age=300`)
+//! - `CACHE_CONTROL_STATS`: /stats, /sources, /health/history, and their
+//!   JSON and CSV (default `public, max-age=60`, or `private` in place of
+//!   `public` when `STATS_TOKEN` guards them)
+//!
+//! "Two or more days" keeps the digest a re-run could replace on the shorter
+//! lifetime, whatever time zone the pipeline dates digests in. Only successes
+//! and `304`s carry the header; errors don't.
+
+use crate::AppState;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{Days, NaiveDate, Utc};
+use std::sync::Arc;
+
+/// Routes whose pages count as stats
+const STATS_ROUTES: [&str; 6] = [
+    "/stats",
+    "/stats.json",
+    "/stats.csv",
+    "/sources",
+    "/sources.json",
+    "/health/history",
+];
+
+/// The `Cache-Control` for each kind of page; `None` sends none
+#[derive(Debug)]
+pub struct CacheControl {
+    past_digests: Option<HeaderValue>,
+    digests: Option<HeaderValue>,
+    index: Option<HeaderValue>,
+    stats: Option<HeaderValue>,
+}
+
+impl CacheControl {
+    pub fn from_env(stats_token: bool) -> Result<Self, String> {
+        let stats = if stats_token {
+            "private, max-age=60"
+        } else {
+            "public, max-age=60"
+        };
+        Ok(Self {
+            past_digests: value("CACHE_CONTROL_PAST_DIGESTS", "public, max-age=86400")?,
+            digests: value("CACHE_CONTROL_DIGESTS", "public, max-age=300")?,
+            index: value("CACHE_CONTROL_INDEX", "public, max-age=300")?,
+            stats: value("CACHE_CONTROL_STATS", stats)?,
+        })
+    }
+
+    /// The header for a page on `route` (`/{date}` for digests) at `path`
+    fn for_page(&self, route: &str, path: &str, today: NaiveDate) -> Option<&HeaderValue> {
+        match route {
+            "/" => self.index.as_ref(),
+            "/{date}" => {
+                let date = NaiveDate::parse_from_str(path.trim_start_matches('/'), "%Y-%m-%d");
+                match date {
+                    Ok(date) if today.checked_sub_days(Days::new(2)) >= Some(date) => {
+                        self.past_digests.as_ref()
+                    }
+                    _ => self.digests.as_ref(),
+                }
+            }
+            route if STATS_ROUTES.contains(&route) => self.stats.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// `name`'s header value, `default` when unset, or none when set empty
+fn value(name: &str, default: &str) -> Result<Option<HeaderValue>, String> {
+    let value = std::env::var(name).unwrap_or_else(|_| default.to_string());
+    if value.trim().is_empty() {
+        return Ok(None);
+    }
+    HeaderValue::from_str(value.trim())
+        .map(Some)
+        .map_err(|_| format!("{name} must be a Cache-Control header value, not {value}"))
+}
+
+/// Middleware adding the matched route's `Cache-Control`, unless the handler set one
+pub async fn set(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string());
+    let path = request.uri().path().to_string();
+    let mut response = next.run(request).await;
+    let cacheable =
+        response.status() == StatusCode::OK || response.status() == StatusCode::NOT_MODIFIED;
+    if cacheable
+        && !response.headers().contains_key(header::CACHE_CONTROL)
+        && let Some(route) = route
+        && let Some(value) = state
+            .cache_control
+            .for_page(&route, &path, Utc::now().date_naive())
+    {
+        response
+            .headers_mut()
+            .insert(header::CACHE_CONTROL, value.clone());
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod for_page {
+        use super::*;
+
+        fn cache_control() -> CacheControl {
+            let value = |v: &str| Some(HeaderValue::from_str(v).unwrap());
+            CacheControl {
+                past_digests: value("immutable"),
+                digests: value("short"),
+                index: None,
+                stats: value("stats"),
+            }
+        }
+
+        #[test]
+        fn digests_settle_after_two_days() {
+            let today = NaiveDate::from_ymd_opt(2026, 1, 17).unwrap();
+            let header = |path| cache_control().for_page("/{date}", path, today).cloned();
+            assert_eq!(header("/2026-01-15").unwrap(), "immutable");
+            assert_eq!(header("/2025-12-31").unwrap(), "immutable");
+            assert_eq!(header("/2026-01-16").unwrap(), "short");
+            assert_eq!(header("/2026-01-17").unwrap(), "short");
+            assert_eq!(header("/2026-01-18").unwrap(), "short");
+        }
+
+        #[test]
+        fn by_route() {
+            let today = NaiveDate::from_ymd_opt(2026, 1, 17).unwrap();
+            let cache_control = cache_control();
+            assert_eq!(
+                cache_control.for_page("/stats.json", "/stats.json", today),
+                Some(&HeaderValue::from_static("stats"))
+            );
+            assert_eq!(cache_control.for_page("/", "/", today), None);
+            assert_eq!(
+                cache_control.for_page("/admin/db", "/admin/db", today),
+                None
+            );
+        }
+    }
+}
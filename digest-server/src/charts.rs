@@ -0,0 +1,183 @@
+//! Server-rendered SVG charts for the stats dashboard (no client JS)
+
+/// Render a polyline sparkline scaled to fit `width` x `height`.
+///
+/// Returns an empty string when there are fewer than two points to draw.
+pub fn sparkline(values: &[f64], width: u32, height: u32) -> String {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    sparkline_between(values, min, max, width, height)
+}
+
+/// Like `sparkline`, but on a fixed `min..=max` scale (e.g. 0–100 for rates,
+/// so a steady 100% sits at the top instead of the baseline)
+pub fn sparkline_between(values: &[f64], min: f64, max: f64, width: u32, height: u32) -> String {
+    if values.len() < 2 {
+        return String::new();
+    }
+    let range = if max > min { max - min } else { 1.0 };
+    let step = f64::from(width) / (values.len() - 1) as f64;
+    let pad = 1.0;
+    let usable = f64::from(height) - 2.0 * pad;
+
+    let points: Vec<String> = values
+        .iter()
+        .enumerate()
+        .map(|(i, v)| {
+            let x = i as f64 * step;
+            let y = pad + usable - (v.clamp(min, min + range) - min) / range * usable;
+            format!("{x:.1},{y:.1}")
+        })
+        .collect();
+
+    format!(
+        r#"<svg class="sparkline" width="{width}" height="{height}" viewBox="0 0 {width} {height}" role="img" aria-hidden="true"><polyline points="{}" fill="none" stroke="currentColor" stroke-width="1.5" stroke-linejoin="round"/></svg>"#,
+        points.join(" ")
+    )
+}
+
+/// Render a grid of counts as shaded cells, one row per label and one column
+/// per hour (0–23), darker for higher counts. Each cell has a tooltip.
+pub fn heatmap(rows: &[(&str, [i64; 24])], cell: u32) -> String {
+    let label_width = 3 * cell;
+    let header = cell;
+    let width = label_width + 24 * cell;
+    let height = header + rows.len() as u32 * cell;
+    let max = rows
+        .iter()
+        .flat_map(|(_, counts)| counts.iter().copied())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    let mut svg = format!(
+        r#"<svg class="heatmap" width="{width}" height="{height}" viewBox="0 0 {width} {height}" role="img">"#
+    );
+    for hour in (0..24).step_by(6) {
+        svg += &format!(
+            r#"<text x="{}" y="{}" font-size="{}" fill="currentColor">{hour:02}</text>"#,
+            label_width + hour * cell,
+            header - 2,
+            cell * 3 / 4
+        );
+    }
+    for (r, (label, counts)) in rows.iter().enumerate() {
+        let y = header + r as u32 * cell;
+        svg += &format!(
+            r#"<text x="0" y="{}" font-size="{}" fill="currentColor">{label}</text>"#,
+            y + cell * 3 / 4,
+            cell * 3 / 4
+        );
+        for (hour, &count) in counts.iter().enumerate() {
+            let opacity = if count > 0 {
+                0.2 + 0.8 * count as f64 / max as f64
+            } else {
+                0.05
+            };
+            svg += &format!(
+                r#"<rect x="{}" y="{y}" width="{}" height="{}" rx="2" fill="currentColor" fill-opacity="{opacity:.2}"><title>{label} {hour:02}:00 – {count}</title></rect>"#,
+                label_width + hour as u32 * cell,
+                cell - 1,
+                cell - 1
+            );
+        }
+    }
+    svg + "</svg>"
+}
+
+/// Render a run's stages as one bar, each a segment as long as its share of
+/// the run's time (at least 2px, so quick stages stay visible), alternately
+/// shaded, with a failed one in red. Each segment's tooltip is its label.
+pub fn stages(segments: &[(String, i64, bool)], width: u32, height: u32) -> String {
+    if segments.is_empty() {
+        return String::new();
+    }
+    let min = 2.0;
+    let spare = (f64::from(width) - min * segments.len() as f64).max(0.0);
+    let total = segments.iter().map(|(_, ms, _)| *ms).sum::<i64>().max(1) as f64;
+    let mut svg = format!(
+        r#"<svg class="stages" width="{width}" height="{height}" viewBox="0 0 {width} {height}" role="img">"#
+    );
+    let mut x = 0.0;
+    for (i, (label, ms, failed)) in segments.iter().enumerate() {
+        let w = min + spare * (*ms).max(0) as f64 / total;
+        let (fill, opacity) = if *failed {
+            ("#dc2626", 1.0)
+        } else {
+            ("currentColor", if i % 2 == 0 { 0.9 } else { 0.5 })
+        };
+        svg += &format!(
+            r#"<rect x="{x:.1}" y="0" width="{w:.1}" height="{height}" fill="{fill}" fill-opacity="{opacity}"><title>{label}</title></rect>"#
+        );
+        x += w;
+    }
+    svg + "</svg>"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod sparkline {
+        use super::*;
+
+        #[test]
+        fn empty_for_short_series() {
+            assert_eq!(sparkline(&[], 100, 20), "");
+            assert_eq!(sparkline(&[5.0], 100, 20), "");
+        }
+
+        #[test]
+        fn spans_full_width() {
+            let svg = sparkline(&[0.0, 5.0, 10.0], 100, 20);
+            assert!(svg.contains(r#"points="0.0,19.0 50.0,10.0 100.0,1.0""#));
+        }
+
+        #[test]
+        fn flat_series_draws_baseline() {
+            let svg = sparkline(&[3.0, 3.0], 10, 10);
+            assert!(svg.contains(r#"points="0.0,9.0 10.0,9.0""#));
+        }
+
+        #[test]
+        fn fixed_scale() {
+            let svg = sparkline_between(&[100.0, 50.0, 100.0], 0.0, 100.0, 10, 10);
+            assert!(svg.contains(r#"points="0.0,1.0 5.0,5.0 10.0,1.0""#));
+        }
+    }
+
+    mod heatmap {
+        use super::*;
+
+        #[test]
+        fn shades_cells_by_count() {
+            let mut counts = [0; 24];
+            counts[7] = 4;
+            counts[9] = 1;
+            let svg = heatmap(&[("Mon", counts), ("Tue", [0; 24])], 10);
+            assert_eq!(svg.matches("<rect").count(), 48);
+            assert!(svg.contains(r#"fill-opacity="1.00"><title>Mon 07:00 – 4</title>"#));
+            assert!(svg.contains(r#"fill-opacity="0.40"><title>Mon 09:00 – 1</title>"#));
+            assert!(svg.contains(r#"fill-opacity="0.05"><title>Tue 07:00 – 0</title>"#));
+        }
+    }
+
+    mod stages {
+        use super::*;
+
+        #[test]
+        fn splits_the_width_by_time() {
+            assert_eq!(stages(&[], 100, 8), "");
+            let svg = stages(
+                &[("fetch".into(), 3000, false), ("select".into(), 1000, true)],
+                104,
+                8,
+            );
+            assert!(svg.contains(r#"<rect x="0.0" y="0" width="77.0""#), "{svg}");
+            assert!(
+                svg.contains(r##"<rect x="77.0" y="0" width="27.0" height="8" fill="#dc2626""##)
+            );
+            assert!(svg.contains("<title>select</title>"));
+        }
+    }
+}
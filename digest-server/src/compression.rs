@@ -0,0 +1,217 @@
+//! zstd compression of stored digest HTML.
+//!
+//! The pipeline writes plain `html`. A background job moves it into
+//! `html_zstd` and blanks `html`, so a row holds exactly one copy. Readers
+//! take `html` when it is non-empty and decompress `html_zstd` otherwise,
+//! which also covers rows the pipeline rewrites after they were compressed.
+//! With a blob store configured, compressed bodies then move out of the
+//! database (see `blobs`).
+//!
+//! Blanking `html` also records its hash in `html_sha256`, since the revision
+//! triggers can no longer compare against it: writers skip re-saving a body
+//! whose hash a compressed row already holds (see `unchanged`).
+
+use crate::blobs::{self, BlobStore};
+use crate::db;
+use rusqlite::{Connection, OptionalExtension, TransactionBehavior};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Digests are written once and read many times, so favour ratio over speed
+const LEVEL: i32 = 19;
+
+/// Rows compressed per transaction, so a large backlog doesn't hold the
+/// write lock for long
+const BATCH: i64 = 20;
+
+/// The digest HTML from a row's `html` and `html_zstd` columns
+pub fn html(plain: String, compressed: Option<Vec<u8>>) -> Result<String, String> {
+    match compressed {
+        Some(bytes) if plain.is_empty() => zstd::decode_all(bytes.as_slice())
+            .map_err(|e| format!("Cannot decompress digest: {e}"))
+            .and_then(|raw| String::from_utf8(raw).map_err(|e| format!("Bad digest HTML: {e}"))),
+        _ => Ok(plain),
+    }
+}
+
+/// Hex SHA-256 of digest HTML, as `html_sha256` holds it
+pub fn sha256(html: &str) -> String {
+    ring::digest::digest(&ring::digest::SHA256, html.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Whether the digest of `date` is stored compressed (or offloaded) with
+/// exactly `html` as its body, so writing `html` again would change nothing.
+/// Plain rows are left to the revision triggers, which compare `html` itself.
+pub fn unchanged(conn: &Connection, date: &str, html: &str) -> Result<bool, String> {
+    if !db::column_exists(conn, "digests", "html_sha256")? {
+        return Ok(false);
+    }
+    let stored: Option<Option<String>> = conn
+        .query_row(
+            "SELECT html_sha256 FROM digests WHERE date = ?1 AND html = ''",
+            [date],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| format!("Query error: {e}"))?;
+    Ok(stored.flatten().is_some_and(|hash| hash == sha256(html)))
+}
+
+/// Compress every digest still stored as plain HTML, returning how many
+fn compress_pending(conn: &mut Connection) -> Result<usize, String> {
+    if !db::column_exists(conn, "digests", "html_sha256")? {
+        return Ok(0);
+    }
+    let mut total = 0;
+    loop {
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(|e| format!("Cannot compress digests: {e}"))?;
+        let pending: Vec<(String, String)> = {
+            let mut stmt = tx
+                .prepare("SELECT date, html FROM digests WHERE html != '' LIMIT ?1")
+                .map_err(|e| format!("Query error: {e}"))?;
+            stmt.query_map([BATCH], |row| Ok((row.get(0)?, row.get(1)?)))
+                .map_err(|e| format!("Query error: {e}"))?
+                .filter_map(|r| r.ok())
+                .collect()
+        };
+        if pending.is_empty() {
+            return Ok(total);
+        }
+        for (date, html) in &pending {
+            let compressed = zstd::encode_all(html.as_bytes(), LEVEL)
+                .map_err(|e| format!("Cannot compress digest {date}: {e}"))?;
+            tx.execute(
+                "UPDATE digests SET html_zstd = ?1, html = '', html_sha256 = ?2 WHERE date = ?3",
+                rusqlite::params![compressed, sha256(html), date],
+            )
+            .map_err(|e| format!("Cannot compress digest {date}: {e}"))?;
+        }
+        tx.commit()
+            .map_err(|e| format!("Cannot compress digests: {e}"))?;
+        total += pending.len();
+    }
+}
+
+/// Compress new digests every `interval`, forever, moving them into `store`
+/// when there is one
+pub async fn run(db_path: String, interval: Duration, store: Option<Arc<dyn BlobStore>>) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let path = db_path.clone();
+        let store = store.clone();
+        let result = db::blocking(move || {
+            let mut conn = db::open_rw(&path)?;
+            let compressed = compress_pending(&mut conn)?;
+            let offloaded = match store {
+                Some(store) => blobs::offload_pending(&conn, store.as_ref())?,
+                None => 0,
+            };
+            Ok::<_, String>((compressed, offloaded))
+        })
+        .await
+        .flatten();
+        match result {
+            Ok((0, 0)) => {}
+            Ok((compressed, offloaded)) => tracing::info!(
+                "Compressed {} digests, moved {} to the blob store",
+                compressed,
+                offloaded
+            ),
+            Err(e) => tracing::warn!("Could not compress digests: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE digests (date TEXT PRIMARY KEY, html TEXT NOT NULL, html_zstd BLOB,
+                 html_sha256 TEXT)",
+        )
+        .unwrap();
+        for day in 1..=25 {
+            conn.execute(
+                "INSERT INTO digests (date, html) VALUES (?1, ?2)",
+                [
+                    format!("2026-01-{day:02}"),
+                    format!("<p>digest {day}</p>").repeat(100),
+                ],
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    fn read(conn: &Connection, date: &str) -> String {
+        let (plain, compressed) = conn
+            .query_row(
+                "SELECT html, html_zstd FROM digests WHERE date = ?1",
+                [date],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        html(plain, compressed).unwrap()
+    }
+
+    mod compress_pending {
+        use super::*;
+
+        #[test]
+        fn round_trips_every_row() {
+            let mut conn = conn();
+            assert_eq!(compress_pending(&mut conn).unwrap(), 25);
+            assert_eq!(compress_pending(&mut conn).unwrap(), 0);
+            assert_eq!(read(&conn, "2026-01-07"), "<p>digest 7</p>".repeat(100));
+            let plain: i64 = conn
+                .query_row("SELECT COUNT(*) FROM digests WHERE html != ''", [], |row| {
+                    row.get(0)
+                })
+                .unwrap();
+            assert_eq!(plain, 0);
+        }
+
+        #[test]
+        fn rewritten_rows_read_as_plain() {
+            let mut conn = conn();
+            compress_pending(&mut conn).unwrap();
+            // The pipeline's INSERT OR REPLACE drops the compressed copy
+            conn.execute(
+                "INSERT OR REPLACE INTO digests (date, html) VALUES ('2026-01-07', '<p>redone</p>')",
+                [],
+            )
+            .unwrap();
+            assert_eq!(read(&conn, "2026-01-07"), "<p>redone</p>");
+        }
+
+        #[test]
+        fn identical_rewrites_are_unchanged() {
+            let mut conn = conn();
+            let body = "<p>digest 7</p>".repeat(100);
+            // Plain rows are for the revision triggers to compare
+            assert!(!unchanged(&conn, "2026-01-07", &body).unwrap());
+            compress_pending(&mut conn).unwrap();
+            assert!(unchanged(&conn, "2026-01-07", &body).unwrap());
+            assert!(!unchanged(&conn, "2026-01-07", "<p>redone</p>").unwrap());
+            assert!(!unchanged(&conn, "2026-02-01", &body).unwrap());
+        }
+
+        #[test]
+        fn skips_tables_without_the_column() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch("CREATE TABLE digests (date TEXT PRIMARY KEY, html TEXT)")
+                .unwrap();
+            assert_eq!(compress_pending(&mut conn).unwrap(), 0);
+        }
+    }
+}
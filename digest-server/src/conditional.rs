@@ -0,0 +1,194 @@
+//! Conditional GETs: ETag and Last-Modified validators from a row's UTC
+//! timestamp, and 304s for clients that already hold the current page.
+//!
+//! Only IMF-fixdate (`Sat, 17 Jan 2026 14:02:03 GMT`) is understood in
+//! `If-Modified-Since`; the obsolete formats are ignored, which costs a full
+//! response rather than a wrong 304.
+
+use crate::day_of_week;
+use axum::http::{HeaderMap, HeaderValue, header};
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// A response's validators, from when its content last changed
+pub struct Validators {
+    etag: String,
+    /// "YYYY-MM-DD HH:MM:SS", the resolution Last-Modified can carry
+    modified: Option<String>,
+}
+
+impl Validators {
+    /// Validators for content last written at `timestamp` (SQLite or Postgres
+    /// UTC text, fractional seconds kept in the ETag). Rendering changes with
+    /// the server build, so its version is part of the tag.
+    pub fn new(timestamp: &str) -> Self {
+        let digits: String = timestamp.chars().filter(char::is_ascii_digit).collect();
+        Self {
+            etag: format!("\"{digits}-{}\"", env!("CARGO_PKG_VERSION")),
+            modified: timestamp.get(..19).map(str::to_string),
+        }
+    }
+
+    /// The ETag also covering `content`, for a page that can change without
+    /// its timestamp moving, like a list an older entry drops out of
+    pub fn including(mut self, content: &str) -> Self {
+        let hash = ring::digest::digest(&ring::digest::SHA256, content.as_bytes());
+        let hex: String = hash.as_ref()[..4]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        self.etag = format!("\"{hex}-{}", &self.etag[1..]);
+        self
+    }
+
+    /// Whether the request's If-None-Match or, without one, If-Modified-Since
+    /// shows the client's copy is current (RFC 9110 section 13.2.2)
+    pub fn is_fresh(&self, headers: &HeaderMap) -> bool {
+        if let Some(value) = headers.get(header::IF_NONE_MATCH) {
+            return value
+                .to_str()
+                .is_ok_and(|value| etag_matches(value, &self.etag));
+        }
+        let since = headers
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(parse_http_date);
+        matches!((since, &self.modified), (Some(since), Some(modified)) if since >= *modified)
+    }
+
+    /// ETag and, when the timestamp parses, Last-Modified headers
+    pub fn headers(&self) -> Vec<(header::HeaderName, HeaderValue)> {
+        let mut headers = Vec::new();
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            headers.push((header::ETAG, etag));
+        }
+        if let Some(date) = self.modified.as_deref().and_then(http_date)
+            && let Ok(date) = HeaderValue::from_str(&date)
+        {
+            headers.push((header::LAST_MODIFIED, date));
+        }
+        headers
+    }
+}
+
+/// Whether an If-None-Match list names `etag`, weakly compared as GET allows
+pub fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.trim() == "*"
+        || if_none_match
+            .split(',')
+            .any(|tag| tag.trim().trim_start_matches("W/") == etag.trim_start_matches("W/"))
+}
+
+/// "Sat, 17 Jan 2026 14:02:03 GMT" from "2026-01-17 14:02:03"
+fn http_date(timestamp: &str) -> Option<String> {
+    let number = |range: std::ops::Range<usize>| timestamp.get(range)?.parse::<u32>().ok();
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    if !(1..=12).contains(&month) {
+        return None;
+    }
+    Some(format!(
+        "{}, {day:02} {} {year} {} GMT",
+        DAYS[day_of_week(year as i32, month, day)],
+        MONTHS[month as usize - 1],
+        timestamp.get(11..19)?
+    ))
+}
+
+/// "2026-01-17 14:02:03" from an IMF-fixdate, so it compares as text with
+/// stored timestamps
+fn parse_http_date(value: &str) -> Option<String> {
+    let parts: Vec<&str> = value.split_whitespace().collect();
+    let [_, day, month, year, time, "GMT"] = parts[..] else {
+        return None;
+    };
+    let month = MONTHS.iter().position(|m| *m == month)? + 1;
+    let valid = day.len() == 2
+        && year.len() == 4
+        && time.len() == 8
+        && format!("{day}{year}{time}")
+            .bytes()
+            .all(|b| b.is_ascii_digit() || b == b':');
+    valid.then(|| format!("{year}-{month:02}-{day} {time}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(name: header::HeaderName, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    mod http_date {
+        use super::*;
+
+        #[test]
+        fn formats_and_parses_imf_fixdates() {
+            let date = http_date("2026-01-17 14:02:03.123").unwrap();
+            assert_eq!(date, "Sat, 17 Jan 2026 14:02:03 GMT");
+            assert_eq!(parse_http_date(&date).unwrap(), "2026-01-17 14:02:03");
+        }
+
+        #[test]
+        fn rejects_other_formats() {
+            assert_eq!(parse_http_date("Saturday, 17-Jan-26 14:02:03 GMT"), None);
+            assert_eq!(parse_http_date("Sat Jan 17 14:02:03 2026"), None);
+            assert_eq!(http_date("yesterday"), None);
+        }
+    }
+
+    mod is_fresh {
+        use super::*;
+
+        #[test]
+        fn matches_etags() {
+            let validators = Validators::new("2026-01-17 14:02:03.123");
+            let tag = validators.etag.clone();
+            assert!(validators.is_fresh(&request(header::IF_NONE_MATCH, &tag)));
+            assert!(
+                validators.is_fresh(&request(header::IF_NONE_MATCH, &format!("\"x\", W/{tag}")))
+            );
+            assert!(!validators.is_fresh(&request(header::IF_NONE_MATCH, "\"x\"")));
+            // A changed row within the same second still gets a new tag
+            assert_ne!(Validators::new("2026-01-17 14:02:03.456").etag, tag);
+        }
+
+        #[test]
+        fn compares_modification_times_without_an_etag() {
+            let validators = Validators::new("2026-01-17 14:02:03.123");
+            let since = |date| validators.is_fresh(&request(header::IF_MODIFIED_SINCE, date));
+            assert!(since("Sat, 17 Jan 2026 14:02:03 GMT"));
+            assert!(since("Sun, 18 Jan 2026 00:00:00 GMT"));
+            assert!(!since("Sat, 17 Jan 2026 14:02:02 GMT"));
+            assert!(!validators.is_fresh(&HeaderMap::new()));
+        }
+
+        #[test]
+        fn prefers_if_none_match() {
+            let validators = Validators::new("2026-01-17 14:02:03");
+            let mut headers = request(header::IF_NONE_MATCH, "\"stale\"");
+            headers.insert(
+                header::IF_MODIFIED_SINCE,
+                HeaderValue::from_static("Sun, 18 Jan 2026 00:00:00 GMT"),
+            );
+            assert!(!validators.is_fresh(&headers));
+        }
+
+        #[test]
+        fn tells_apart_content_at_the_same_time() {
+            let listed = |dates: &str| Validators::new("2026-01-17 14:02:03").including(dates);
+            let tag = listed("2026-01-17 2026-01-16").etag;
+            assert!(
+                listed("2026-01-17 2026-01-16").is_fresh(&request(header::IF_NONE_MATCH, &tag))
+            );
+            assert!(
+                !listed("2026-01-17 2026-01-15").is_fresh(&request(header::IF_NONE_MATCH, &tag))
+            );
+        }
+    }
+}
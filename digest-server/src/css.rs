@@ -0,0 +1,69 @@
+//! The digest stylesheet made ready for mail clients, shared by the server's
+//! `email_html` and `digest-pipeline`'s `render`. It uses CSS variables and a
+//! dark-mode media query, which mail clients support neither of.
+
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+static CSS_COMMENT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)/\*.*?\*/").expect("valid regex"));
+static CSS_PUNCTUATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\s*([{};:,>])\s*").expect("valid regex"));
+static WHITESPACE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+").expect("valid regex"));
+static CSS_ROOT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r":root\s*\{([^}]+)\}").expect("valid regex"));
+static CSS_VARIABLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"--([a-z-]+)\s*:\s*([^;]+);").expect("valid regex"));
+static CSS_VAR_USE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"var\(--([a-z-]+)\)").expect("valid regex"));
+static DARK_MODE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"@media\s*\([^)]*prefers-color-scheme[^)]*\)\s*\{[^}]*\{[^}]*\}[^}]*\}")
+        .expect("valid regex")
+});
+
+/// `css` without comments or needless whitespace
+pub fn minify(css: &str) -> String {
+    let css = CSS_COMMENT.replace_all(css, "");
+    let css = CSS_PUNCTUATION.replace_all(&css, "$1");
+    WHITESPACE.replace_all(&css, " ").trim().to_string()
+}
+
+/// `css` with its variables replaced by their light-mode values, and
+/// without the dark-mode rules mail clients can't apply
+pub fn resolve_variables(css: &str) -> String {
+    let Some(root) = CSS_ROOT.captures(css) else {
+        return css.to_string();
+    };
+    let variables: BTreeMap<&str, &str> = CSS_VARIABLE
+        .captures_iter(root.get(1).map_or("", |m| m.as_str()))
+        .filter_map(|c| Some((c.get(1)?.as_str(), c.get(2)?.as_str().trim())))
+        .collect();
+    let css = DARK_MODE.replace_all(css, "");
+    let css = CSS_VAR_USE.replace_all(&css, |c: &regex::Captures| {
+        variables
+            .get(&c[1])
+            .map_or_else(|| c[0].to_string(), |value| value.to_string())
+    });
+    CSS_ROOT.replace_all(&css, "").into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod resolve_variables {
+        use super::*;
+
+        #[test]
+        fn uses_light_mode_values() {
+            let css = ":root { --text: #111; --bg: #fff; }\n\
+                       @media (prefers-color-scheme: dark) { :root { --text: #eee; } }\n\
+                       body { color: var(--text); background: var(--bg); border: var(--none); }";
+            assert_eq!(
+                minify(&resolve_variables(css)),
+                "body{color:#111;background:#fff;border:var(--none);}"
+            );
+        }
+    }
+}
@@ -0,0 +1,609 @@
+//! Shared SQLite helpers
+
+use crate::is_valid_date;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use std::sync::{LazyLock, OnceLock};
+use std::time::Duration;
+
+/// Read-only connections shared by request handlers
+pub type Pool = r2d2::Pool<SqliteConnectionManager>;
+pub type PooledConnection = r2d2::PooledConnection<SqliteConnectionManager>;
+
+/// Longest window the stats views will aggregate over
+const MAX_RANGE_DAYS: i64 = 5 * 366;
+
+/// Inclusive range of UTC dates (YYYY-MM-DD) for stats queries.
+///
+/// Bind `from` and `to` and filter with `col >= ?from AND col < date(?to, '+1 day')`
+/// so timestamps anywhere on the last day are included.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DateRange {
+    pub from: String,
+    pub to: String,
+    /// Number of days covered, counting both ends
+    pub days: i64,
+}
+
+impl DateRange {
+    /// Resolve `from`/`to` query parameters, falling back to the last `days`
+    /// days ending at `to` (or today)
+    pub fn resolve(
+        conn: &Connection,
+        days: u32,
+        from: Option<&str>,
+        to: Option<&str>,
+    ) -> Result<Self, String> {
+        for date in [from, to].into_iter().flatten() {
+            if !is_valid_date(date) {
+                return Err(format!("Invalid date '{date}' (expected YYYY-MM-DD)"));
+            }
+        }
+        let (from, to, span): (String, String, i64) = conn
+            .query_row(
+                "WITH bounds AS (
+                     SELECT COALESCE(?2, date(COALESCE(?3, date('now')), '-' || (MAX(?1, 1) - 1) || ' days')) AS f,
+                            COALESCE(?3, date('now')) AS t
+                 )
+                 SELECT f, t, CAST(julianday(t) - julianday(f) AS INTEGER) + 1 FROM bounds",
+                rusqlite::params![days, from, to],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .map_err(|e| format!("Query error: {e}"))?;
+        if span < 1 {
+            return Err("'from' must not be after 'to'".into());
+        }
+        if span > MAX_RANGE_DAYS {
+            return Err(format!("Date range is limited to {MAX_RANGE_DAYS} days"));
+        }
+        Ok(Self {
+            from,
+            to,
+            days: span,
+        })
+    }
+
+    /// The equally long range ending the day before this one
+    pub fn previous(&self, conn: &Connection) -> Result<Self, String> {
+        let to: String = conn
+            .query_row("SELECT date(?1, '-1 day')", [&self.from], |row| row.get(0))
+            .map_err(|e| format!("Query error: {e}"))?;
+        let days = u32::try_from(self.days).map_err(|_| "Invalid range".to_string())?;
+        Self::resolve(conn, days, None, Some(&to))
+    }
+}
+
+/// Run SQLite work on Tokio's blocking thread pool, keeping slow queries off
+/// the runtime's worker threads
+pub async fn blocking<T: Send + 'static>(
+    f: impl FnOnce() -> T + Send + 'static,
+) -> Result<T, String> {
+    // Keep log lines from the blocking thread inside the request's span
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || span.in_scope(f))
+        .await
+        .map_err(|e| format!("Database task failed: {e}"))
+}
+
+/// How long a connection waits for a lock held by another writer (usually the
+/// pipeline) before giving up with SQLITE_BUSY
+static BUSY_TIMEOUT: LazyLock<Duration> = LazyLock::new(|| {
+    Duration::from_millis(
+        std::env::var("DB_BUSY_TIMEOUT_MS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(5000),
+    )
+});
+
+/// Schema name the telemetry database is attached under
+pub const TELEMETRY: &str = "telemetry";
+
+/// High-churn tables that live in the telemetry database when one is
+/// configured, so their writes never lock the file readers are served from
+pub const TELEMETRY_TABLES: &[&str] = &[
+    "source_health",
+    "source_health_weekly",
+    "health_checks",
+    "digest_views",
+    "link_clicks",
+    "email_events",
+];
+
+/// SQLCipher key (`DATABASE_KEY`), applied to every connection
+static KEY: OnceLock<String> = OnceLock::new();
+
+/// Open encrypted databases with `key`. Applies to connections opened
+/// afterwards, so call it before opening any.
+#[cfg(feature = "sqlcipher")]
+pub fn use_key(key: String) -> Result<(), String> {
+    KEY.set(key)
+        .map_err(|_| "Database key already set".to_string())
+}
+
+#[cfg(not(feature = "sqlcipher"))]
+pub fn use_key(_key: String) -> Result<(), String> {
+    Err("DATABASE_KEY is set, but this build lacks the `sqlcipher` feature".into())
+}
+
+/// Check the key opens the database at `path`, so a wrong or missing one
+/// fails at startup instead of on the first request
+pub fn check_key(path: &str) -> Result<(), String> {
+    unlock(path, KEY.get().map(String::as_str))
+}
+
+fn unlock(path: &str, key: Option<&str>) -> Result<(), String> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Cannot open database {path}: {e}"))?;
+    apply_key(&conn, key).map_err(|e| format!("Cannot key database {path}: {e}"))?;
+    if key.is_some()
+        && conn
+            .query_row("PRAGMA cipher_version", [], |row| row.get::<_, String>(0))
+            .optional()
+            .map_err(|e| format!("Query error: {e}"))?
+            .is_none()
+    {
+        return Err("DATABASE_KEY is set, but SQLite was built without SQLCipher".into());
+    }
+    conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| {
+        row.get::<_, i64>(0)
+    })
+    .map_err(|e| match key {
+        Some(_) => {
+            format!("Cannot decrypt {path}: wrong key, or the database isn't encrypted ({e})")
+        }
+        None => format!("Cannot read {path}: {e} (encrypted? set DATABASE_KEY)"),
+    })?;
+    Ok(())
+}
+
+/// Must be the first statement on a connection
+fn apply_key(conn: &Connection, key: Option<&str>) -> rusqlite::Result<()> {
+    if let Some(key) = key {
+        conn.pragma_update(None, "key", key)?;
+    }
+    Ok(())
+}
+
+/// Open a database file on its own, without the telemetry database or other
+/// server settings, but keyed like every other connection
+pub fn open_alone(path: &str, flags: OpenFlags) -> rusqlite::Result<Connection> {
+    let conn = Connection::open_with_flags(path, flags)?;
+    apply_key(&conn, KEY.get().map(String::as_str))?;
+    Ok(conn)
+}
+
+/// The telemetry database (`TELEMETRY_DB`), attached to every connection
+static TELEMETRY_PATH: OnceLock<String> = OnceLock::new();
+
+/// Keep telemetry tables in a separate file, creating it if needed. Applies to
+/// connections opened afterwards, so call it before opening any.
+pub fn use_telemetry_db(path: &str) -> Result<(), String> {
+    open_alone(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE,
+    )
+    .or_else(|_| open_alone(path, OpenFlags::SQLITE_OPEN_READ_ONLY))
+    .map_err(|e| format!("Cannot open telemetry database {path}: {e}"))?;
+    TELEMETRY_PATH
+        .set(path.to_string())
+        .map_err(|_| "Telemetry database already set".to_string())
+}
+
+/// The telemetry database, when one is configured
+pub fn telemetry_path() -> Option<&'static str> {
+    TELEMETRY_PATH.get().map(String::as_str)
+}
+
+/// Attach the telemetry database as `telemetry`. Unqualified table names
+/// resolve in `main` first, then here, so queries needn't know where a table lives.
+pub fn attach_telemetry(conn: &Connection, path: &str) -> rusqlite::Result<()> {
+    // SQLCipher only reuses the main key for files created on the same connection
+    match KEY.get() {
+        Some(key) => conn.execute(
+            "ATTACH DATABASE ?1 AS telemetry KEY ?2",
+            rusqlite::params![path, key],
+        )?,
+        None => conn.execute("ATTACH DATABASE ?1 AS telemetry", [path])?,
+    };
+    Ok(())
+}
+
+/// Databases on a connection: `main`, plus `telemetry` when attached
+pub fn schemas(conn: &Connection) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT name FROM pragma_database_list WHERE name != 'temp' ORDER BY seq")
+        .map_err(|e| format!("Query error: {e}"))?;
+    let names = stmt
+        .query_map([], |row| row.get(0))
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(names)
+}
+
+/// Per-connection settings: wait out other writers instead of failing, and
+/// skip the fsync on every commit that WAL mode makes unnecessary
+fn configure(conn: &Connection) -> rusqlite::Result<()> {
+    apply_key(conn, KEY.get().map(String::as_str))?;
+    conn.busy_timeout(*BUSY_TIMEOUT)?;
+    if let Some(path) = TELEMETRY_PATH.get() {
+        attach_telemetry(conn, path)?;
+    }
+    if !conn.is_readonly(rusqlite::MAIN_DB)? {
+        conn.execute_batch("PRAGMA main.synchronous = NORMAL")?;
+        if TELEMETRY_PATH.get().is_some() {
+            conn.execute_batch("PRAGMA telemetry.synchronous = NORMAL")?;
+        }
+    }
+    Ok(())
+}
+
+/// Open the database with the server's connection settings
+pub fn open(path: &str, flags: OpenFlags) -> rusqlite::Result<Connection> {
+    let conn = Connection::open_with_flags(path, flags)?;
+    configure(&conn)?;
+    Ok(conn)
+}
+
+/// Open the database read-write
+pub fn open_rw(path: &str) -> Result<Connection, String> {
+    open(path, OpenFlags::SQLITE_OPEN_READ_WRITE)
+        .map_err(|e| format!("Cannot open database read-write: {e}"))
+}
+
+/// Switch the database (and the telemetry one, if attached) to write-ahead
+/// logging so readers never block on, or get blocked by, the pipeline's
+/// writes. The mode is stored in the file.
+pub fn enable_wal(conn: &Connection) -> Result<(), String> {
+    for schema in schemas(conn)? {
+        let mode: String = conn
+            .query_row(&format!("PRAGMA {schema}.journal_mode = WAL"), [], |row| {
+                row.get(0)
+            })
+            .map_err(|e| format!("Cannot enable WAL: {e}"))?;
+        if !mode.eq_ignore_ascii_case("wal") {
+            return Err(format!(
+                "Cannot enable WAL on {schema}: journal mode is {mode}"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Open `size` read-only connections to the database up front
+pub fn read_only_pool(path: &str, size: u32) -> Result<Pool, String> {
+    let manager = SqliteConnectionManager::file(path)
+        .with_flags(OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .with_init(|conn| configure(conn));
+    r2d2::Pool::builder()
+        .max_size(size)
+        .connection_timeout(Duration::from_secs(5))
+        .build(manager)
+        .map_err(|e| format!("Cannot open database pool: {e}"))
+}
+
+/// The database holding a table, or `None` if no attached one has it
+pub fn table_schema(conn: &Connection, name: &str) -> Result<Option<String>, String> {
+    for schema in schemas(conn)? {
+        let found = conn
+            .query_row(
+                &format!("SELECT 1 FROM {schema}.sqlite_master WHERE type='table' AND name=?1"),
+                [name],
+                |_| Ok(()),
+            )
+            .optional()
+            .map_err(|e| format!("Query error: {e}"))?;
+        if found.is_some() {
+            return Ok(Some(schema));
+        }
+    }
+    Ok(None)
+}
+
+/// Check whether a table exists (tables owned by optional features may be absent)
+pub fn table_exists(conn: &Connection, name: &str) -> Result<bool, String> {
+    table_schema(conn, name).map(|schema| schema.is_some())
+}
+
+/// Move telemetry tables out of the main database into the attached telemetry
+/// one: those created before the split, and those migrations just created.
+/// Returns the tables moved.
+pub fn move_telemetry_tables(conn: &mut Connection) -> Result<Vec<&'static str>, String> {
+    if !schemas(conn)?.iter().any(|s| s == TELEMETRY) {
+        return Ok(Vec::new());
+    }
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Cannot move telemetry tables: {e}"))?;
+    let mut moved = Vec::new();
+    for &table in TELEMETRY_TABLES {
+        // The table first, then its indexes; automatic indexes have no SQL
+        let statements: Vec<String> = {
+            let mut stmt = tx
+                .prepare(
+                    "SELECT sql FROM main.sqlite_master
+                     WHERE tbl_name = ?1 AND sql IS NOT NULL
+                     ORDER BY type = 'index'",
+                )
+                .map_err(|e| format!("Query error: {e}"))?;
+            stmt.query_map([table], |row| row.get(0))
+                .map_err(|e| format!("Query error: {e}"))?
+                .filter_map(|r| r.ok())
+                .collect()
+        };
+        if statements.is_empty() {
+            continue;
+        }
+        // The pipeline may have created a copy already; if nothing's in it
+        // yet, rebuild it from the main schema, which may have more columns
+        let copied = tx
+            .query_row(
+                &format!("SELECT 1 FROM {TELEMETRY}.sqlite_master WHERE type='table' AND name=?1"),
+                [table],
+                |_| Ok(()),
+            )
+            .optional()
+            .map_err(|e| format!("Query error: {e}"))?
+            .is_some();
+        if copied {
+            let empty: bool = tx
+                .query_row(
+                    &format!("SELECT NOT EXISTS (SELECT 1 FROM {TELEMETRY}.{table})"),
+                    [],
+                    |row| row.get(0),
+                )
+                .map_err(|e| format!("Query error: {e}"))?;
+            if empty {
+                tx.execute_batch(&format!("DROP TABLE {TELEMETRY}.{table}"))
+                    .map_err(|e| format!("Cannot move {table}: {e}"))?;
+            }
+        }
+        for sql in &statements {
+            let Some(qualified) = in_telemetry(sql) else {
+                return Err(format!("Cannot move {table}: unexpected schema {sql}"));
+            };
+            tx.execute_batch(&qualified)
+                .map_err(|e| format!("Cannot create telemetry.{table}: {e}"))?;
+        }
+        // Copy the columns both have, in case an older copy has fewer
+        let columns = columns_in(&tx, TELEMETRY, table)?;
+        let shared = columns_in(&tx, "main", table)?
+            .into_iter()
+            .filter(|c| columns.contains(c))
+            .collect::<Vec<_>>()
+            .join(", ");
+        tx.execute_batch(&format!(
+            "INSERT INTO telemetry.{table} ({shared}) SELECT {shared} FROM main.{table};
+             DROP TABLE main.{table};"
+        ))
+        .map_err(|e| format!("Cannot move {table}: {e}"))?;
+        moved.push(table);
+    }
+    tx.commit()
+        .map_err(|e| format!("Cannot move telemetry tables: {e}"))?;
+    Ok(moved)
+}
+
+/// Rewrite a stored `CREATE TABLE`/`CREATE INDEX` statement to create the
+/// object in the telemetry database, unless it's already there
+fn in_telemetry(sql: &str) -> Option<String> {
+    ["CREATE TABLE ", "CREATE INDEX ", "CREATE UNIQUE INDEX "]
+        .iter()
+        .find_map(|prefix| {
+            sql.strip_prefix(prefix)
+                .map(|rest| format!("{prefix}IF NOT EXISTS {TELEMETRY}.{rest}"))
+        })
+}
+
+fn columns_in(conn: &Connection, schema: &str, table: &str) -> Result<Vec<String>, String> {
+    let mut stmt = conn
+        .prepare(&format!("PRAGMA {schema}.table_info({table})"))
+        .map_err(|e| format!("Query error: {e}"))?;
+    let columns = stmt
+        .query_map([], |row| row.get(1))
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(columns)
+}
+
+/// Check whether a table has a given column (for additive migrations)
+pub fn column_exists(conn: &Connection, table: &str, column: &str) -> Result<bool, String> {
+    let mut stmt = conn
+        .prepare(&format!("PRAGMA table_info({table})"))
+        .map_err(|e| format!("Query error: {e}"))?;
+    let found = stmt
+        .query_map([], |row| row.get::<_, String>(1))
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok())
+        .any(|name| name == column);
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod date_range {
+        use super::*;
+
+        fn conn() -> Connection {
+            Connection::open_in_memory().unwrap()
+        }
+
+        #[test]
+        fn explicit_range() {
+            let r =
+                DateRange::resolve(&conn(), 30, Some("2026-01-01"), Some("2026-01-31")).unwrap();
+            assert_eq!(
+                (r.from.as_str(), r.to.as_str(), r.days),
+                ("2026-01-01", "2026-01-31", 31)
+            );
+        }
+
+        #[test]
+        fn days_before_to() {
+            let r = DateRange::resolve(&conn(), 7, None, Some("2026-03-07")).unwrap();
+            assert_eq!((r.from.as_str(), r.days), ("2026-03-01", 7));
+        }
+
+        #[test]
+        fn defaults_to_today() {
+            let r = DateRange::resolve(&conn(), 30, None, None).unwrap();
+            assert_eq!(r.days, 30);
+        }
+
+        #[test]
+        fn previous_period() {
+            let r =
+                DateRange::resolve(&conn(), 30, Some("2026-03-01"), Some("2026-03-07")).unwrap();
+            let p = r.previous(&conn()).unwrap();
+            assert_eq!(
+                (p.from.as_str(), p.to.as_str(), p.days),
+                ("2026-02-22", "2026-02-28", 7)
+            );
+        }
+
+        #[test]
+        fn rejects_bad_input() {
+            assert!(
+                DateRange::resolve(&conn(), 30, Some("2026-02-01"), Some("2026-01-01")).is_err()
+            );
+            assert!(DateRange::resolve(&conn(), 30, Some("yesterday"), None).is_err());
+            assert!(
+                DateRange::resolve(&conn(), 30, Some("2000-01-01"), Some("2026-01-01")).is_err()
+            );
+        }
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    mod unlock {
+        use super::*;
+
+        #[test]
+        fn needs_the_right_key() {
+            let path = std::env::temp_dir().join(format!("cipher-{}.db", std::process::id()));
+            let path = path.to_str().unwrap();
+            let conn = Connection::open(path).unwrap();
+            apply_key(&conn, Some("s3cret")).unwrap();
+            conn.execute_batch("CREATE TABLE digests (date TEXT)")
+                .unwrap();
+            drop(conn);
+            assert!(unlock(path, Some("s3cret")).is_ok());
+            let err = unlock(path, Some("wrong")).unwrap_err();
+            assert!(err.contains("wrong key"), "{err}");
+            assert!(unlock(path, None).is_err());
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    mod read_only_pool {
+        use super::*;
+
+        #[test]
+        fn reads_but_rejects_writes() {
+            let path = std::env::temp_dir().join(format!("pool-{}.db", std::process::id()));
+            let path = path.to_str().unwrap();
+            Connection::open(path)
+                .unwrap()
+                .execute_batch("CREATE TABLE digests (date TEXT)")
+                .unwrap();
+            let pool = read_only_pool(path, 2).unwrap();
+            let conn = pool.get().unwrap();
+            assert!(table_exists(&conn, "digests").unwrap());
+            assert!(
+                conn.execute("INSERT INTO digests VALUES ('x')", [])
+                    .is_err()
+            );
+            drop(conn);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    mod open_rw {
+        use super::*;
+
+        #[test]
+        fn waits_for_another_writer() {
+            let path = std::env::temp_dir().join(format!("busy-{}.db", std::process::id()));
+            let path = path.to_str().unwrap().to_string();
+            Connection::open(&path).unwrap();
+            let writer = open_rw(&path).unwrap();
+            enable_wal(&writer).unwrap();
+            writer
+                .execute_batch("CREATE TABLE t (x); BEGIN IMMEDIATE; INSERT INTO t VALUES (1);")
+                .unwrap();
+            let commit = std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(200));
+                writer.execute_batch("COMMIT").unwrap();
+            });
+            // Blocks on the lock instead of failing with SQLITE_BUSY
+            open_rw(&path)
+                .unwrap()
+                .execute("INSERT INTO t VALUES (2)", [])
+                .unwrap();
+            commit.join().unwrap();
+            for suffix in ["", "-wal", "-shm"] {
+                let _ = std::fs::remove_file(format!("{path}{suffix}"));
+            }
+        }
+    }
+
+    mod move_telemetry_tables {
+        use super::*;
+
+        #[test]
+        fn moves_rows_and_indexes() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            attach_telemetry(&conn, ":memory:").unwrap();
+            conn.execute_batch(
+                "CREATE TABLE digests (date TEXT PRIMARY KEY, html TEXT);
+                 CREATE TABLE source_health (id INTEGER PRIMARY KEY AUTOINCREMENT,
+                     source_id TEXT, success INTEGER, fetch_ms INTEGER);
+                 CREATE INDEX idx_source_health_source ON source_health(source_id);
+                 INSERT INTO source_health (source_id, success, fetch_ms) VALUES ('bbc', 1, 120), ('npr', 0, NULL);
+                 -- An older, empty copy the pipeline created after the split
+                 CREATE TABLE telemetry.source_health (id INTEGER PRIMARY KEY AUTOINCREMENT,
+                     source_id TEXT, success INTEGER);",
+            )
+            .unwrap();
+            assert_eq!(move_telemetry_tables(&mut conn).unwrap(), ["source_health"]);
+            assert!(move_telemetry_tables(&mut conn).unwrap().is_empty());
+
+            assert_eq!(
+                table_schema(&conn, "source_health").unwrap().as_deref(),
+                Some(TELEMETRY)
+            );
+            assert_eq!(
+                table_schema(&conn, "digests").unwrap().as_deref(),
+                Some("main")
+            );
+            let (successes, fetch_ms): (i64, i64) = conn
+                .query_row(
+                    "SELECT SUM(success), SUM(fetch_ms) FROM source_health",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .unwrap();
+            assert_eq!((successes, fetch_ms), (1, 120));
+            let indexed: i64 = conn
+                .query_row(
+                    "SELECT COUNT(*) FROM telemetry.sqlite_master WHERE name = 'idx_source_health_source'",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(indexed, 1);
+        }
+
+        #[test]
+        fn nothing_to_do_without_telemetry() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch("CREATE TABLE source_health (id INTEGER PRIMARY KEY)")
+                .unwrap();
+            assert!(move_telemetry_tables(&mut conn).unwrap().is_empty());
+            assert_eq!(schemas(&conn).unwrap(), ["main"]);
+        }
+    }
+}
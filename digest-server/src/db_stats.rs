@@ -0,0 +1,308 @@
+//! Database size and contents for capacity planning: each file's size, WAL,
+//! and free space, rows and bytes per table, bytes per index, and the newest
+//! backup. Served to admins at `/admin/db` and `/admin/db.json`.
+
+use crate::{AppState, admin, backup, db, escape_html, render_page};
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+};
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, Serialize)]
+pub struct DbStats {
+    /// The main database, then the telemetry one when separate
+    files: Vec<FileStats>,
+    /// Newest backup in `BACKUP_DIR`, when backups are configured
+    last_backup: Option<backup::BackupFile>,
+}
+
+#[derive(Debug, Serialize)]
+struct FileStats {
+    schema: String,
+    path: String,
+    bytes: u64,
+    /// Write-ahead log not yet checkpointed into the file
+    wal_bytes: u64,
+    /// Pages freed by deletes, reclaimed by VACUUM
+    free_bytes: i64,
+    tables: Vec<TableStats>,
+    indexes: Vec<IndexStats>,
+}
+
+#[derive(Debug, Serialize)]
+struct TableStats {
+    name: String,
+    rows: i64,
+    /// Pages used, without the table's indexes
+    bytes: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct IndexStats {
+    name: String,
+    table: String,
+    bytes: Option<i64>,
+}
+
+/// Page bytes per table and index in `schema`, from the `dbstat` table when
+/// SQLite was built with it
+fn object_sizes(conn: &Connection, schema: &str) -> HashMap<String, i64> {
+    conn.prepare("SELECT name, SUM(pgsize) FROM dbstat(?1) GROUP BY name")
+        .and_then(|mut stmt| {
+            stmt.query_map([schema], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn file_stats(conn: &Connection, schema: &str, path: &str) -> Result<FileStats, String> {
+    let pragma = |name: &str| {
+        conn.query_row(&format!("PRAGMA {schema}.{name}"), [], |row| {
+            row.get::<_, i64>(0)
+        })
+        .map_err(|e| format!("Query error: {e}"))
+    };
+    let free_bytes = pragma("freelist_count")? * pragma("page_size")?;
+    let size = |path: &str| std::fs::metadata(path).map_or(0, |m| m.len());
+    let sizes = object_sizes(conn, schema);
+
+    let objects: Vec<(String, String, String)> = {
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT type, name, tbl_name FROM {schema}.sqlite_master
+                 WHERE type IN ('table', 'index') AND name NOT LIKE 'sqlite_%'
+                 ORDER BY name"
+            ))
+            .map_err(|e| format!("Query error: {e}"))?;
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .map_err(|e| format!("Query error: {e}"))?
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("Query error: {e}"))?
+    };
+    let (mut tables, mut indexes) = (Vec::new(), Vec::new());
+    for (kind, name, table) in objects {
+        let bytes = sizes.get(&name).copied();
+        if kind == "index" {
+            indexes.push(IndexStats { name, table, bytes });
+            continue;
+        }
+        let rows = conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM {schema}.\"{}\"",
+                    name.replace('"', "\"\"")
+                ),
+                [],
+                |row| row.get(0),
+            )
+            .map_err(|e| format!("Cannot count {name}: {e}"))?;
+        tables.push(TableStats { name, rows, bytes });
+    }
+    Ok(FileStats {
+        schema: schema.to_string(),
+        path: path.to_string(),
+        bytes: size(path),
+        wal_bytes: size(&format!("{path}-wal")),
+        free_bytes,
+        tables,
+        indexes,
+    })
+}
+
+/// Stats for the database at `db_path` and any attached telemetry database
+pub fn collect(db_path: &str, backup_dir: Option<&Path>) -> Result<DbStats, String> {
+    let conn = db::open(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("Cannot open database: {e}"))?;
+    let files: Vec<(String, String)> = {
+        let mut stmt = conn
+            .prepare(
+                "SELECT name, file FROM pragma_database_list WHERE name != 'temp' ORDER BY seq",
+            )
+            .map_err(|e| format!("Query error: {e}"))?;
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| format!("Query error: {e}"))?
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("Query error: {e}"))?
+    };
+    Ok(DbStats {
+        files: files
+            .iter()
+            .map(|(schema, path)| file_stats(&conn, schema, path))
+            .collect::<Result<_, _>>()?,
+        last_backup: backup_dir.map(backup::latest).transpose()?.flatten(),
+    })
+}
+
+/// "1.5 MB", to one decimal place above a kilobyte
+fn format_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+async fn stats(state: &Arc<AppState>) -> Result<DbStats, (StatusCode, String)> {
+    if state.db.is_none() {
+        return Err((
+            StatusCode::NOT_IMPLEMENTED,
+            "Database statistics need SQLite storage".into(),
+        ));
+    }
+    state
+        .blocking(|state| {
+            let backup_dir = state.backup.as_ref().map(|config| config.dir.as_path());
+            collect(&state.db_path, backup_dir).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+        })
+        .await
+}
+
+/// Database statistics as JSON
+pub async fn json(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<DbStats>, (StatusCode, String)> {
+    admin::require_admin(&state, &headers)?;
+    stats(&state).await.map(Json)
+}
+
+/// Database statistics page
+pub async fn page(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    if let Some(response) = admin::deny_admin_page(&state, &headers) {
+        return Ok(response);
+    }
+    let stats = stats(&state).await?;
+    let size = |bytes: Option<i64>| bytes.map(format_bytes).unwrap_or_else(|| "–".into());
+
+    let mut sections = Vec::new();
+    for file in &stats.files {
+        let tables: String = file
+            .tables
+            .iter()
+            .map(|t| {
+                format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    escape_html(&t.name),
+                    t.rows,
+                    size(t.bytes)
+                )
+            })
+            .collect();
+        let indexes: String = file
+            .indexes
+            .iter()
+            .map(|i| {
+                format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    escape_html(&i.name),
+                    escape_html(&i.table),
+                    size(i.bytes)
+                )
+            })
+            .collect();
+        sections.push(format!(
+            r#"<section>
+      <h2>{schema}</h2>
+      <p>{path}: {bytes}, WAL {wal}, {free} free</p>
+      <table>
+        <thead><tr><th>Table</th><th>Rows</th><th>Size</th></tr></thead>
+        <tbody>{tables}</tbody>
+      </table>
+      <table>
+        <thead><tr><th>Index</th><th>Table</th><th>Size</th></tr></thead>
+        <tbody>{indexes}</tbody>
+      </table>
+    </section>"#,
+            schema = escape_html(&file.schema),
+            path = escape_html(&file.path),
+            bytes = format_bytes(file.bytes as i64),
+            wal = format_bytes(file.wal_bytes as i64),
+            free = format_bytes(file.free_bytes),
+        ));
+    }
+    let backup = match &stats.last_backup {
+        Some(backup) => format!(
+            "Last backup {} UTC ({}).",
+            backup.taken_at,
+            format_bytes(backup.bytes as i64)
+        ),
+        None if state.backup.is_some() => "No backups yet.".into(),
+        None => "Backups are not configured.".into(),
+    };
+    let content = format!(
+        r#"<style>
+      table {{ width: 100%; margin-bottom: 1rem; font-size: 14px; }}
+      td:not(:first-child), th:not(:first-child) {{ text-align: right; }}
+    </style>
+    <p>{backup} <a href="/admin/db.json">JSON</a></p>
+    {}"#,
+        sections.join("\n    ")
+    );
+    Ok(Html(render_page(&state, "Database", &content)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod collect {
+        use super::*;
+
+        #[test]
+        fn counts_rows_and_finds_the_last_backup() {
+            let dir = std::env::temp_dir().join(format!("db-stats-{}", std::process::id()));
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(dir.join("backups")).unwrap();
+            let path = dir.join("digest.db");
+            Connection::open(&path)
+                .unwrap()
+                .execute_batch(
+                    "CREATE TABLE digests (date TEXT PRIMARY KEY, html TEXT);
+                     CREATE INDEX idx_digests_html ON digests(html);
+                     INSERT INTO digests VALUES ('2026-01-01', 'a'), ('2026-01-02', 'b');",
+                )
+                .unwrap();
+            for name in ["digest-20260101-060000.db", "digest-20260102-060000.db"] {
+                std::fs::write(dir.join("backups").join(name), "x").unwrap();
+            }
+
+            let stats = collect(path.to_str().unwrap(), Some(&dir.join("backups"))).unwrap();
+            let main = &stats.files[0];
+            assert_eq!(main.schema, "main");
+            assert!(main.bytes > 0);
+            assert_eq!(main.tables[0].name, "digests");
+            assert_eq!(main.tables[0].rows, 2);
+            assert!(main.tables[0].bytes.is_some());
+            assert_eq!(main.indexes[0].table, "digests");
+            let backup = stats.last_backup.unwrap();
+            assert_eq!(backup.taken_at, "2026-01-02 06:00:00");
+        }
+    }
+
+    mod format_bytes {
+        use super::*;
+
+        #[test]
+        fn picks_a_unit() {
+            assert_eq!(format_bytes(512), "512 B");
+            assert_eq!(format_bytes(1536), "1.5 KB");
+            assert_eq!(format_bytes(5 * 1024 * 1024 * 1024), "5.0 GB");
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! Digest HTML made ready for mail clients, as `run.py`'s `prepare_for_email`
+//! does before it sends.
+//!
+//! `digests` holds the web variant, whose stylesheet uses CSS variables and a
+//! dark-mode media query. Mail clients support neither, so the variables
+//! resolve to their light-mode values and the dark-mode rules go. Each rule
+//! is then also copied into the `style` of the elements it matches, for
+//! clients (Gmail among them) that drop `<style>`; the stylesheet stays for
+//! the rest, along with what can't be inlined (media queries, `:hover`).
+
+use crate::{css, links};
+use html5ever::{QualName, local_name, ns};
+use regex::Regex;
+use scraper::{Html, Node, Selector};
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+static STYLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<style>([^<]+)</style>").expect("valid regex"));
+
+/// A stored digest of `date` as it goes out by mail, its links routed through
+/// the click redirect at `tracking_url` when there is one
+pub fn digest(html: String, date: &str, tracking_url: Option<&str>) -> String {
+    let html = match tracking_url {
+        Some(base_url) => links::rewrite(&html, date, base_url),
+        None => html,
+    };
+    prepare(&html)
+}
+
+/// `html` as mail clients should get it
+pub fn prepare(html: &str) -> String {
+    let mut stylesheet = String::new();
+    let html = STYLE.replace_all(html, |c: &regex::Captures| {
+        let css = css::minify(&css::resolve_variables(&c[1]));
+        stylesheet.push_str(&css);
+        format!("<style>{css}</style>")
+    });
+    inline_styles(&html, &stylesheet)
+}
+
+/// The top-level rules of `css` as (selectors, declarations), skipping
+/// at-rules such as media queries
+fn rules(css: &str) -> Vec<(&str, &str)> {
+    let mut rules = Vec::new();
+    let mut rest = css;
+    while let Some(open) = rest.find('{') {
+        let selectors = rest[..open].trim();
+        if selectors.starts_with('@') {
+            // Skip to the brace closing the at-rule's block
+            let mut depth = 0;
+            let close = rest[open..].char_indices().find_map(|(i, c)| {
+                match c {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+                (depth == 0).then_some(open + i)
+            });
+            let Some(close) = close else {
+                break;
+            };
+            rest = &rest[close + 1..];
+            continue;
+        }
+        let Some(close) = rest[open..].find('}').map(|i| open + i) else {
+            break;
+        };
+        rules.push((selectors, &rest[open + 1..close]));
+        rest = &rest[close + 1..];
+    }
+    rules
+}
+
+/// A rule's (property, value) pairs
+fn declarations(block: &str) -> Vec<(String, String)> {
+    block
+        .split(';')
+        .filter_map(|declaration| declaration.split_once(':'))
+        .map(|(property, value)| (property.trim().to_lowercase(), value.trim().to_string()))
+        .filter(|(property, value)| !property.is_empty() && !value.is_empty())
+        .collect()
+}
+
+/// Set `property` in `style`, replacing an earlier value
+fn set(style: &mut Vec<(String, String)>, property: &str, value: &str) {
+    match style.iter_mut().find(|(p, _)| p == property) {
+        Some(existing) => existing.1 = value.to_string(),
+        None => style.push((property.to_string(), value.to_string())),
+    }
+}
+
+/// (ids, classes and attributes, elements) in a simple selector, enough to
+/// order the stylesheet's rules as browsers would
+fn specificity(selector: &str) -> (usize, usize, usize) {
+    let ids = selector.matches('#').count();
+    let classes = selector.matches('.').count() + selector.matches('[').count();
+    let elements = selector
+        .split([' ', '>', '+', '~'])
+        .filter(|compound| compound.starts_with(|c: char| c.is_ascii_alphabetic()))
+        .count();
+    (ids, classes, elements)
+}
+
+/// `html` with `stylesheet`'s rules copied into each matching element's
+/// `style`, by specificity and then order, under what it already sets itself
+fn inline_styles(html: &str, stylesheet: &str) -> String {
+    let mut rules: Vec<_> = rules(stylesheet)
+        .into_iter()
+        .flat_map(|(selectors, block)| {
+            let declarations = declarations(block);
+            selectors
+                .split(',')
+                .map(str::trim)
+                // Pseudo-classes such as :hover only apply in a stylesheet
+                .filter(|selector| !selector.is_empty() && !selector.contains(':'))
+                .filter_map(|selector| {
+                    let parsed = Selector::parse(selector).ok()?;
+                    Some((specificity(selector), parsed, declarations.clone()))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    if rules.is_empty() {
+        return html.to_string();
+    }
+    // Stable, so equally specific rules keep their order
+    rules.sort_by_key(|(specificity, _, _)| *specificity);
+
+    let mut document = Html::parse_document(html);
+    let mut styles: HashMap<_, Vec<(String, String)>> = HashMap::new();
+    for (_, selector, declarations) in &rules {
+        for element in document.select(selector) {
+            let style = styles.entry(element.id()).or_default();
+            for (property, value) in declarations {
+                set(style, property, value);
+            }
+        }
+    }
+    let name = QualName::new(None, ns!(), local_name!("style"));
+    for (id, mut style) in styles {
+        let Some(mut node) = document.tree.get_mut(id) else {
+            continue;
+        };
+        let Node::Element(element) = node.value() else {
+            continue;
+        };
+        if let Some((_, own)) = element.attrs.iter().find(|(n, _)| *n == name) {
+            for (property, value) in declarations(own) {
+                set(&mut style, &property, &value);
+            }
+        }
+        let style = style
+            .iter()
+            .map(|(property, value)| format!("{property}:{value}"))
+            .collect::<Vec<_>>()
+            .join(";");
+        element.attrs.retain(|(n, _)| *n != name);
+        element.attrs.push((name.clone(), style.into()));
+    }
+    document.html()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod prepare {
+        use super::*;
+
+        const PAGE: &str = "<!DOCTYPE html><html><head><style>
+            :root { --text: #111; --link: #15a; }
+            @media (prefers-color-scheme: dark) { :root { --text: #eee; } }
+            body { color: var(--text); }
+            a { color: var(--link); text-decoration: none; }
+            a:hover { text-decoration: underline; }
+            .source a { color: #555; }
+            @media (min-width: 768px) { body { font-size: 20px; } }
+            </style></head><body>
+            <p class=\"source\"><a href=\"https://a.example\">A</a></p>
+            <a href=\"https://b.example\" style=\"color: red\">B</a>
+            </body></html>";
+
+        #[test]
+        fn resolves_variables_to_light_mode() {
+            let html = prepare(PAGE);
+            assert!(!html.contains("var(--"));
+            assert!(!html.contains("prefers-color-scheme"));
+            assert!(html.contains("<style>body{color:#111;}"));
+            // What can't be inlined stays in the stylesheet
+            assert!(html.contains("a:hover{text-decoration:underline;}"));
+            assert!(html.contains("@media (min-width:768px)"));
+        }
+
+        #[test]
+        fn inlines_rules_by_specificity_under_own_styles() {
+            let html = prepare(PAGE);
+            assert!(html.contains(r#"<body style="color:#111">"#), "{html}");
+            assert!(html.contains(
+                r#"<a href="https://a.example" style="color:#555;text-decoration:none">"#
+            ));
+            assert!(html.contains(
+                r#"<a href="https://b.example" style="color:red;text-decoration:none">"#
+            ));
+            assert!(!html.contains("font-size:20px\""));
+        }
+
+        #[test]
+        fn leaves_unstyled_html_alone() {
+            assert_eq!(prepare("<p>Plain</p>"), "<p>Plain</p>");
+        }
+    }
+
+    mod digest {
+        use super::*;
+
+        #[test]
+        fn tracks_links_when_asked() {
+            let html = "<style>a{color:#15a}</style><a href=\"https://a.example/story\">A</a>";
+            let tracked = digest(
+                html.into(),
+                "2026-01-05",
+                Some("https://digest.example.com"),
+            );
+            assert!(tracked.contains(r#"href="https://digest.example.com/r/20260105"#));
+            assert!(tracked.contains(r#"style="color:#15a""#));
+            let untracked = digest(html.into(), "2026-01-05", None);
+            assert!(untracked.contains(r#"href="https://a.example/story""#));
+        }
+    }
+
+    mod rules {
+        use super::*;
+
+        #[test]
+        fn skips_at_rules() {
+            assert_eq!(
+                rules("@media (x){a{b:c}p{d:e}}h1,h2{f:g}@font-face{x:y}em{h:i}"),
+                [("h1,h2", "f:g"), ("em", "h:i")]
+            );
+        }
+    }
+}
@@ -0,0 +1,115 @@
+//! Compressed responses: brotli or gzip, whichever the client prefers, for
+//! text (HTML, CSV, metrics), JSON, and XML feeds of at least
+//! `RESPONSE_COMPRESSION_MIN_BYTES` (default 1024; 0 turns compression off).
+//! Images are already compressed and left alone. A digest page, hundreds of
+//! KB of HTML, goes over the wire at a third of that or less.
+
+use axum::{
+    body::Body,
+    http::{Extensions, HeaderMap, HeaderValue, Response, StatusCode, Version, header},
+};
+use tower_http::compression::{
+    CompressionLayer, CompressionLevel,
+    predicate::{And, Predicate, SizeAbove},
+};
+
+type Compressible = fn(StatusCode, Version, &HeaderMap, &Extensions) -> bool;
+
+/// Whether the response's content type is worth compressing
+fn compressible(_: StatusCode, _: Version, headers: &HeaderMap, _: &Extensions) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(is_compressible)
+}
+
+fn is_compressible(content_type: &str) -> bool {
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence.starts_with("text/")
+        || essence == "application/json"
+        || essence == "application/xml"
+        || essence.ends_with("+xml")
+}
+
+/// The compression layer, passing everything through when
+/// `RESPONSE_COMPRESSION_MIN_BYTES` is 0
+pub fn layer() -> CompressionLayer<And<SizeAbove, Compressible>> {
+    let min_bytes: u16 = std::env::var("RESPONSE_COMPRESSION_MIN_BYTES")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(1024);
+    CompressionLayer::new()
+        .no_deflate()
+        .no_zstd()
+        .br(min_bytes > 0)
+        .gzip(min_bytes > 0)
+        // Brotli's own default (11) takes far longer on a digest page for
+        // little gain; 4 is about gzip's size at gzip's speed
+        .quality(CompressionLevel::Precise(4))
+        .compress_when(SizeAbove::new(min_bytes).and(compressible as Compressible))
+}
+
+/// Weaken a compressed response's ETag: the compressed bytes differ from the
+/// page the tag was made for, though it's still the same page
+pub async fn weaken_etag(mut response: Response<Body>) -> Response<Body> {
+    if response.headers().contains_key(header::CONTENT_ENCODING)
+        && let Some(etag) = response.headers().get(header::ETAG)
+        && etag.as_bytes().starts_with(b"\"")
+        && let Ok(weak) = HeaderValue::from_bytes(&[b"W/", etag.as_bytes()].concat())
+    {
+        response.headers_mut().insert(header::ETAG, weak);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod is_compressible {
+        use super::*;
+
+        #[test]
+        fn takes_text_json_and_feeds() {
+            for content_type in [
+                "text/html; charset=utf-8",
+                "text/csv; charset=utf-8",
+                "application/json",
+                "application/rss+xml",
+                "application/atom+xml; charset=utf-8",
+                "application/xml",
+            ] {
+                assert!(is_compressible(content_type), "{content_type}");
+            }
+            for content_type in ["image/png", "image/jpeg", "application/octet-stream"] {
+                assert!(!is_compressible(content_type), "{content_type}");
+            }
+        }
+    }
+
+    mod weaken_etag {
+        use super::*;
+
+        #[tokio::test]
+        async fn only_for_compressed_responses() {
+            let response = |encoding: Option<&str>| {
+                let mut response = Response::builder().header(header::ETAG, "\"17-0.1.0\"");
+                if let Some(encoding) = encoding {
+                    response = response.header(header::CONTENT_ENCODING, encoding);
+                }
+                response.body(Body::empty()).unwrap()
+            };
+            let etag = |response: Response<Body>| response.headers()[header::ETAG].clone();
+            assert_eq!(
+                etag(weaken_etag(response(Some("br"))).await),
+                "W/\"17-0.1.0\""
+            );
+            assert_eq!(etag(weaken_etag(response(None)).await), "\"17-0.1.0\"");
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Open and click tracking from Resend webhooks
+
+use crate::{AppState, db, tokens};
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use ring::hmac;
+use rusqlite::Connection;
+use serde::Deserialize;
+use std::sync::Arc;
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS email_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    webhook_id TEXT UNIQUE,
+    event_type TEXT NOT NULL,
+    email_id TEXT,
+    broadcast_id TEXT,
+    recipient TEXT,
+    link TEXT,
+    occurred_at DATETIME,
+    received_at DATETIME DEFAULT (datetime('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_email_events_broadcast ON email_events(broadcast_id, event_type);
+";
+
+/// Webhook event types worth keeping; others are acknowledged and dropped
+const STORED_EVENTS: [&str; 3] = ["email.opened", "email.clicked", "email.bounced"];
+
+/// Webhooks older (or newer) than this are rejected to limit replays
+const TIMESTAMP_TOLERANCE_SECS: u64 = 5 * 60;
+
+#[derive(Deserialize)]
+struct WebhookEvent {
+    #[serde(rename = "type")]
+    event_type: String,
+    created_at: Option<String>,
+    data: WebhookData,
+}
+
+#[derive(Deserialize)]
+struct WebhookData {
+    email_id: Option<String>,
+    broadcast_id: Option<String>,
+    #[serde(default)]
+    to: Vec<String>,
+    click: Option<WebhookClick>,
+}
+
+#[derive(Deserialize)]
+struct WebhookClick {
+    link: Option<String>,
+}
+
+/// Verify a Svix-style signature: HMAC-SHA256 over `{id}.{timestamp}.{body}`,
+/// keyed with the base64 part of a `whsec_...` secret
+fn verify_signature(
+    secret: &str,
+    id: &str,
+    timestamp: &str,
+    body: &[u8],
+    signatures: &str,
+    now: u64,
+) -> Result<(), &'static str> {
+    let sent_at: u64 = timestamp.parse().map_err(|_| "Bad timestamp")?;
+    if sent_at.abs_diff(now) > TIMESTAMP_TOLERANCE_SECS {
+        return Err("Stale webhook");
+    }
+    let key_bytes = STANDARD
+        .decode(secret.trim_start_matches("whsec_"))
+        .map_err(|_| "Bad webhook secret")?;
+    let key = hmac::Key::new(hmac::HMAC_SHA256, &key_bytes);
+
+    let mut signed = Vec::with_capacity(id.len() + timestamp.len() + body.len() + 2);
+    signed.extend_from_slice(id.as_bytes());
+    signed.push(b'.');
+    signed.extend_from_slice(timestamp.as_bytes());
+    signed.push(b'.');
+    signed.extend_from_slice(body);
+
+    // The header may carry several space-separated "v1,<base64>" signatures
+    let valid = signatures
+        .split_whitespace()
+        .filter_map(|s| s.strip_prefix("v1,"))
+        .filter_map(|s| STANDARD.decode(s).ok())
+        .any(|tag| hmac::verify(&key, &signed, &tag).is_ok());
+    if valid { Ok(()) } else { Err("Bad signature") }
+}
+
+/// Resend webhook receiver - stores opens and clicks, plus bounces for list health
+pub async fn resend_webhook(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let secret = state.resend_webhook_secret.as_deref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Webhooks not configured".into(),
+    ))?;
+    // Email events live in the pipeline's SQLite database
+    if state.db.is_none() {
+        return Err((
+            StatusCode::NOT_IMPLEMENTED,
+            "Email events need SQLite storage".into(),
+        ));
+    }
+    if !state.read_write {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Server is read-only (SERVER_MODE=rw records email events)".into(),
+        ));
+    }
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .ok_or((StatusCode::BAD_REQUEST, format!("Missing {name} header")))
+    };
+    let webhook_id = header("svix-id")?;
+    verify_signature(
+        secret,
+        webhook_id,
+        header("svix-timestamp")?,
+        &body,
+        header("svix-signature")?,
+        tokens::now(),
+    )
+    .map_err(|e| (StatusCode::UNAUTHORIZED, e.to_string()))?;
+
+    let event: WebhookEvent = serde_json::from_slice(&body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Invalid payload: {e}")))?;
+    if !STORED_EVENTS.contains(&event.event_type.as_str()) {
+        // Acknowledge other event types so Resend doesn't retry them
+        return Ok(StatusCode::NO_CONTENT);
+    }
+
+    let webhook_id = webhook_id.to_string();
+    state
+        .blocking(move |state| {
+            let conn =
+                db::open_rw(&state.db_path).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+            conn.execute(
+                "INSERT OR IGNORE INTO email_events
+                     (webhook_id, event_type, email_id, broadcast_id, recipient, link, occurred_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, COALESCE(datetime(?7), datetime('now')))",
+                rusqlite::params![
+                    webhook_id,
+                    event.event_type,
+                    event.data.email_id,
+                    event.data.broadcast_id,
+                    event.data.to.first().map(|t| t.to_lowercase()),
+                    event.data.click.and_then(|c| c.link),
+                    event.created_at,
+                ],
+            )
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Cannot save event: {e}"),
+                )
+            })
+        })
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Open and click-through rates for one sent digest
+#[derive(Clone)]
+pub struct DigestEngagement {
+    pub digest_date: String,
+    pub recipients: i64,
+    pub unique_opens: i64,
+    pub unique_clicks: i64,
+    pub open_rate_pct: f64,
+    pub click_rate_pct: f64,
+}
+
+fn pct(part: i64, whole: i64) -> f64 {
+    if whole > 0 {
+        (part as f64 / whole as f64 * 1000.0).round() / 10.0
+    } else {
+        0.0
+    }
+}
+
+/// Per-digest engagement for broadcasts sent within the range.
+///
+/// Needs `digest_broadcasts` (written by the pipeline when it sends) to map
+/// broadcasts to digests; returns nothing until both tables exist.
+pub fn per_digest(
+    conn: &Connection,
+    range: &db::DateRange,
+) -> Result<Vec<DigestEngagement>, String> {
+    if !db::table_exists(conn, "digest_broadcasts")? || !db::table_exists(conn, "email_events")? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn
+        .prepare(
+            "WITH sent AS (
+                 SELECT digest_date, SUM(recipients) AS recipients
+                 FROM digest_broadcasts
+                 WHERE sent_at >= ?1 AND sent_at < date(?2, '+1 day')
+                 GROUP BY digest_date
+             ),
+             reached AS (
+                 SELECT b.digest_date, e.event_type, COUNT(DISTINCT e.recipient) AS n
+                 FROM email_events e
+                 JOIN digest_broadcasts b ON e.broadcast_id = b.broadcast_id
+                 GROUP BY b.digest_date, e.event_type
+             )
+             SELECT s.digest_date, s.recipients, COALESCE(o.n, 0), COALESCE(c.n, 0)
+             FROM sent s
+             LEFT JOIN reached o ON o.digest_date = s.digest_date AND o.event_type = 'email.opened'
+             LEFT JOIN reached c ON c.digest_date = s.digest_date AND c.event_type = 'email.clicked'
+             ORDER BY s.digest_date DESC",
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    let rows = stmt
+        .query_map([&range.from, &range.to], |row| {
+            let recipients: i64 = row.get(1)?;
+            let unique_opens: i64 = row.get(2)?;
+            let unique_clicks: i64 = row.get(3)?;
+            Ok(DigestEngagement {
+                digest_date: row.get(0)?,
+                recipients,
+                unique_opens,
+                unique_clicks,
+                open_rate_pct: pct(unique_opens, recipients),
+                click_rate_pct: pct(unique_clicks, recipients),
+            })
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod verify_signature {
+        use super::*;
+
+        // base64("test-webhook-secret")
+        const SECRET: &str = "whsec_dGVzdC13ZWJob29rLXNlY3JldA==";
+
+        fn sign(id: &str, ts: &str, body: &[u8]) -> String {
+            let key = hmac::Key::new(hmac::HMAC_SHA256, b"test-webhook-secret");
+            let msg = [id.as_bytes(), b".", ts.as_bytes(), b".", body].concat();
+            format!("v1,{}", STANDARD.encode(hmac::sign(&key, &msg).as_ref()))
+        }
+
+        #[test]
+        fn accepts_valid_signature() {
+            let sig = sign("msg_1", "1000", b"{}");
+            assert!(verify_signature(SECRET, "msg_1", "1000", b"{}", &sig, 1010).is_ok());
+        }
+
+        #[test]
+        fn accepts_any_of_several_signatures() {
+            let sig = format!("v1,bogus {}", sign("msg_1", "1000", b"{}"));
+            assert!(verify_signature(SECRET, "msg_1", "1000", b"{}", &sig, 1000).is_ok());
+        }
+
+        #[test]
+        fn rejects_modified_body() {
+            let sig = sign("msg_1", "1000", b"{}");
+            assert!(verify_signature(SECRET, "msg_1", "1000", b"{\"x\":1}", &sig, 1000).is_err());
+        }
+
+        #[test]
+        fn rejects_stale_timestamp() {
+            let sig = sign("msg_1", "1000", b"{}");
+            assert_eq!(
+                verify_signature(SECRET, "msg_1", "1000", b"{}", &sig, 1000 + 301),
+                Err("Stale webhook")
+            );
+        }
+    }
+
+    mod pct {
+        use super::*;
+
+        #[test]
+        fn rounds_to_one_decimal() {
+            assert_eq!(pct(1, 3), 33.3);
+            assert_eq!(pct(0, 0), 0.0);
+        }
+    }
+}
@@ -0,0 +1,410 @@
+//! Database health probe, with a periodic self-check recorded to `health_checks`
+//! and an on-demand deep check (`/health/deep`).
+//!
+//! Results that can't be written (e.g. the DB is locked or down) are kept in
+//! memory and flushed with the next successful write, so outages still show
+//! up in the history once the database is back.
+
+use crate::{AppState, db};
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS health_checks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    checked_at DATETIME NOT NULL,
+    ok INTEGER NOT NULL,
+    latency_ms INTEGER NOT NULL,
+    error TEXT
+);
+CREATE INDEX IF NOT EXISTS idx_health_checks_at ON health_checks(checked_at);
+";
+
+/// Unwritten results kept while the DB is unavailable (a day at the default interval)
+const MAX_PENDING: usize = 1440;
+
+/// Verify the database can be opened and queried
+pub fn probe(db_path: &str) -> Result<(), String> {
+    let conn = db::open(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("DB error: {e}"))?;
+    conn.query_row("SELECT 1", [], |_| Ok(()))
+        .map_err(|e| format!("DB query failed: {e}"))
+}
+
+/// One self-check result
+#[derive(Clone, Debug)]
+struct Check {
+    /// UTC, `YYYY-MM-DD HH:MM:SS` like SQLite's datetime('now')
+    checked_at: String,
+    ok: bool,
+    latency_ms: i64,
+    error: Option<String>,
+}
+
+fn save(db_path: &str, checks: &[Check]) -> Result<(), String> {
+    let mut conn = db::open_rw(db_path)?;
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Cannot save health checks: {e}"))?;
+    for check in checks {
+        tx.execute(
+            "INSERT INTO health_checks (checked_at, ok, latency_ms, error) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![check.checked_at, check.ok, check.latency_ms, check.error],
+        )
+        .map_err(|e| format!("Cannot save health checks: {e}"))?;
+    }
+    tx.commit()
+        .map_err(|e| format!("Cannot save health checks: {e}"))
+}
+
+/// Current UTC time as `YYYY-MM-DD HH:MM:SS`, computed without touching the
+/// (possibly unavailable) database
+fn utc_now() -> String {
+    Connection::open_in_memory()
+        .and_then(|conn| conn.query_row("SELECT datetime('now')", [], |row| row.get(0)))
+        .unwrap_or_default()
+}
+
+/// Probe the database every `interval` and record the result, forever
+pub async fn run(db_path: String, interval: Duration) {
+    let mut pending: Vec<Check> = Vec::new();
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let path = db_path.clone();
+        let (result, latency) = db::blocking(move || {
+            let start = Instant::now();
+            (probe(&path), start.elapsed())
+        })
+        .await
+        .unwrap_or_else(|e| (Err(e), Duration::ZERO));
+        pending.push(Check {
+            checked_at: utc_now(),
+            ok: result.is_ok(),
+            latency_ms: latency.as_millis() as i64,
+            error: result.err(),
+        });
+        let (path, checks) = (db_path.clone(), pending.clone());
+        match db::blocking(move || save(&path, &checks)).await.flatten() {
+            Ok(()) => pending.clear(),
+            Err(e) => {
+                tracing::warn!("Could not record health check: {}", e);
+                if pending.len() > MAX_PENDING {
+                    pending.drain(..pending.len() - MAX_PENDING);
+                }
+            }
+        }
+    }
+}
+
+/// Share of successful self-checks within the range, as (checks, uptime %)
+pub fn uptime(conn: &Connection, range: &db::DateRange) -> Result<Option<(i64, f64)>, String> {
+    if !db::table_exists(conn, "health_checks")? {
+        return Ok(None);
+    }
+    let (checks, ok): (i64, i64) = conn
+        .query_row(
+            "SELECT COUNT(*), COALESCE(SUM(ok), 0) FROM health_checks
+             WHERE checked_at >= ?1 AND checked_at < date(?2, '+1 day')",
+            [&range.from, &range.to],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    Ok((checks > 0).then(|| (checks, ok as f64 / checks as f64 * 100.0)))
+}
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    hours: Option<u32>,
+}
+
+/// Recent self-check results with uptime over the window (default 24 hours)
+pub async fn history(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<axum::Json<serde_json::Value>, (StatusCode, String)> {
+    let hours = query.hours.unwrap_or(24).clamp(1, 24 * 31);
+    let checks: Vec<serde_json::Value> = state
+        .blocking(move |state| {
+            let conn = state.db()?;
+            let exists = db::table_exists(&conn, "health_checks")
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+            let checks = if exists {
+                let mut stmt = conn
+                    .prepare(
+                        "SELECT checked_at, ok, latency_ms, error FROM health_checks
+                         WHERE checked_at >= datetime('now', ?1)
+                         ORDER BY checked_at DESC",
+                    )
+                    .map_err(|e| {
+                        (
+                            StatusCode::INTERNAL_SERVER_ERROR,
+                            format!("Query error: {e}"),
+                        )
+                    })?;
+                stmt.query_map([format!("-{hours} hours")], |row| {
+                    Ok(serde_json::json!({
+                        "checked_at": row.get::<_, String>(0)?,
+                        "ok": row.get::<_, bool>(1)?,
+                        "latency_ms": row.get::<_, i64>(2)?,
+                        "error": row.get::<_, Option<String>>(3)?,
+                    }))
+                })
+                .map_err(|e| {
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        format!("Query error: {e}"),
+                    )
+                })?
+                .filter_map(|r| r.ok())
+                .collect()
+            } else {
+                Vec::new()
+            };
+            Ok(checks)
+        })
+        .await?;
+    let failures = checks.iter().filter(|c| c["ok"] == false).count();
+    let uptime_pct = (!checks.is_empty())
+        .then(|| (checks.len() - failures) as f64 / checks.len() as f64 * 100.0);
+    Ok(axum::Json(serde_json::json!({
+        "hours": hours,
+        "uptime_pct": uptime_pct,
+        "failures": failures,
+        "checks": checks,
+    })))
+}
+
+/// Thresholds for `/health/deep` (`HEALTH_*` environment variables)
+#[derive(Clone, Debug)]
+pub struct DeepConfig {
+    /// Fail when the newest digest is older than this; 0 skips the check
+    pub max_digest_age_hours: u32,
+    /// Fail when the database volume has less free space than this
+    pub min_free_mb: u64,
+}
+
+impl DeepConfig {
+    pub fn from_env() -> Self {
+        fn number<T: std::str::FromStr>(name: &str, default: T) -> T {
+            std::env::var(name)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(default)
+        }
+        Self {
+            max_digest_age_hours: number("HEALTH_MAX_DIGEST_AGE_HOURS", 36),
+            min_free_mb: number("HEALTH_MIN_FREE_MB", 100),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct DeepHealth {
+    ok: bool,
+    /// What failed; empty when healthy
+    problems: Vec<String>,
+    /// `PRAGMA quick_check` result per database file (`main`, `telemetry`)
+    integrity: BTreeMap<String, String>,
+    latest_digest_at: Option<String>,
+    digest_age_hours: Option<f64>,
+    /// Free space on the database volume, when it can be read
+    free_mb: Option<u64>,
+}
+
+/// Space available to unprivileged writers on the file system holding `path`
+fn free_bytes(path: &Path) -> Option<u64> {
+    rustix::fs::statvfs(path)
+        .ok()
+        .map(|stats| stats.f_bavail * stats.f_frsize)
+}
+
+/// Check integrity, that the archive is still being updated, and disk space
+fn deep_check(conn: &Connection, db_path: &str, config: &DeepConfig) -> Result<DeepHealth, String> {
+    let mut problems = Vec::new();
+    let mut integrity = BTreeMap::new();
+    for schema in db::schemas(conn)? {
+        let mut stmt = conn
+            .prepare(&format!("PRAGMA {schema}.quick_check(10)"))
+            .map_err(|e| format!("Query error: {e}"))?;
+        let result = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(|e| format!("Integrity check failed: {e}"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Integrity check failed: {e}"))?
+            .join("; ");
+        if result != "ok" {
+            problems.push(format!("{schema} database failed quick_check: {result}"));
+        }
+        integrity.insert(schema, result);
+    }
+
+    // created_at is when the pipeline wrote the digest; older tables only have the date
+    let written_at = if db::column_exists(conn, "digests", "created_at")? {
+        "MAX(COALESCE(created_at, date))"
+    } else {
+        "MAX(date)"
+    };
+    let (latest_digest_at, digest_age_hours): (Option<String>, Option<f64>) = conn
+        .query_row(
+            &format!(
+                "SELECT {written_at}, (julianday('now') - julianday({written_at})) * 24 FROM digests"
+            ),
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    let max_age = config.max_digest_age_hours;
+    match digest_age_hours {
+        _ if max_age == 0 => {}
+        Some(hours) if hours > f64::from(max_age) => problems.push(format!(
+            "Newest digest is {hours:.0} hours old (limit {max_age})"
+        )),
+        Some(_) => {}
+        None => problems.push("No digests".into()),
+    }
+
+    let free_mb = free_bytes(Path::new(db_path)).map(|bytes| bytes / (1024 * 1024));
+    if let Some(mb) = free_mb
+        && mb < config.min_free_mb
+    {
+        problems.push(format!(
+            "Only {mb} MB free on the database volume (minimum {})",
+            config.min_free_mb
+        ));
+    }
+
+    Ok(DeepHealth {
+        ok: problems.is_empty(),
+        problems,
+        integrity,
+        latest_digest_at,
+        digest_age_hours,
+        free_mb,
+    })
+}
+
+/// Integrity, freshness, and disk-space checks, with 503 when any fails.
+/// Results are reused briefly, since `quick_check` reads the whole database.
+pub async fn deep(
+    State(state): State<Arc<AppState>>,
+) -> Result<(StatusCode, Json<DeepHealth>), (StatusCode, String)> {
+    let report = state
+        .blocking(|state| {
+            state.deep_health_cache.get_or_try_insert((), || {
+                let conn = state.db()?;
+                deep_check(&conn, &state.db_path, &state.deep_health)
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+            })
+        })
+        .await?;
+    let status = if report.ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    Ok((status, Json(DeepHealth::clone(&report))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod uptime {
+        use super::*;
+
+        #[test]
+        fn share_of_ok_checks_in_range() {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(SCHEMA).unwrap();
+            conn.execute_batch(
+                "INSERT INTO health_checks (checked_at, ok, latency_ms) VALUES
+                     ('2026-01-01 10:00:00', 1, 2),
+                     ('2026-01-01 10:01:00', 1, 2),
+                     ('2026-01-01 10:02:00', 1, 3),
+                     ('2026-01-01 10:03:00', 0, 5000),
+                     ('2026-02-01 10:00:00', 0, 5000)",
+            )
+            .unwrap();
+            let range = db::DateRange {
+                from: "2026-01-01".into(),
+                to: "2026-01-01".into(),
+                days: 1,
+            };
+            assert_eq!(uptime(&conn, &range).unwrap(), Some((4, 75.0)));
+        }
+
+        #[test]
+        fn none_without_checks() {
+            let conn = Connection::open_in_memory().unwrap();
+            let range = db::DateRange {
+                from: "2026-01-01".into(),
+                to: "2026-01-31".into(),
+                days: 31,
+            };
+            assert_eq!(uptime(&conn, &range).unwrap(), None);
+        }
+    }
+
+    mod deep_check {
+        use super::*;
+
+        fn conn(written: &str) -> Connection {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(
+                "CREATE TABLE digests (date TEXT PRIMARY KEY, html TEXT, created_at DATETIME)",
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO digests VALUES (date('now'), '<p>x</p>', datetime('now', ?1))",
+                [written],
+            )
+            .unwrap();
+            conn
+        }
+
+        fn config(min_free_mb: u64) -> DeepConfig {
+            DeepConfig {
+                max_digest_age_hours: 36,
+                min_free_mb,
+            }
+        }
+
+        #[test]
+        fn healthy() {
+            let report = deep_check(&conn("-2 hours"), ".", &config(0)).unwrap();
+            assert!(report.ok, "{:?}", report.problems);
+            assert_eq!(report.integrity["main"], "ok");
+            assert_eq!(report.digest_age_hours.map(f64::round), Some(2.0));
+            assert!(report.free_mb.is_some());
+        }
+
+        #[test]
+        fn stale_archive_and_full_disk() {
+            let report = deep_check(&conn("-3 days"), ".", &config(u64::MAX)).unwrap();
+            assert!(!report.ok);
+            assert_eq!(report.problems.len(), 2, "{:?}", report.problems);
+            assert!(report.problems[0].starts_with("Newest digest is 72 hours old"));
+            assert!(report.problems[1].contains("MB free"));
+        }
+
+        #[test]
+        fn empty_archive() {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch("CREATE TABLE digests (date TEXT PRIMARY KEY, html TEXT)")
+                .unwrap();
+            let report = deep_check(&conn, ":memory:", &config(100)).unwrap();
+            assert_eq!(report.problems, ["No digests"]);
+            assert_eq!(report.free_mb, None);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! Article thumbnails the pipeline stored in the `images` table, served by
+//! content hash so digests never hotlink publishers.
+//!
+//! The pipeline enforces the size limit and checks the bytes match the
+//! declared type when storing; serving re-checks the type against a raster
+//! allowlist so a bad row can't turn into, say, an SVG with scripts.
+
+use crate::{AppState, conditional};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+/// Content types served; anything else in the table is treated as missing
+const ALLOWED_TYPES: &[&str] = &[
+    "image/jpeg",
+    "image/png",
+    "image/gif",
+    "image/webp",
+    "image/avif",
+];
+
+/// A hash names its bytes forever, so browsers and CDNs may keep them as long
+const CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+
+/// Hex SHA-256, as the pipeline names images
+fn is_valid_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Serve an image by hash, answering revalidations with 304
+pub async fn image(
+    Path(hash): Path<String>,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let not_found = || (StatusCode::NOT_FOUND, "Unknown image".to_string());
+    if !is_valid_hash(&hash) {
+        return Err(not_found());
+    }
+    let etag = format!("\"{hash}\"");
+    if headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| conditional::etag_matches(v, &etag))
+    {
+        return Ok((
+            StatusCode::NOT_MODIFIED,
+            [
+                (header::ETAG, etag),
+                (header::CACHE_CONTROL, CACHE_CONTROL.to_string()),
+            ],
+        )
+            .into_response());
+    }
+
+    let (content_type, data) = state
+        .blocking(move |state| {
+            let _timer = state.metrics.time_db("image");
+            state
+                .storage
+                .image(&hash)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+        })
+        .await?
+        .filter(|(content_type, _)| ALLOWED_TYPES.contains(&content_type.as_str()))
+        .ok_or_else(not_found)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::ETAG, etag),
+            (header::CACHE_CONTROL, CACHE_CONTROL.to_string()),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod is_valid_hash {
+        use super::*;
+
+        #[test]
+        fn accepts_lowercase_sha256_hex() {
+            assert!(is_valid_hash(&"ab12".repeat(16)));
+        }
+
+        #[test]
+        fn rejects_other_shapes() {
+            assert!(!is_valid_hash(&"AB12".repeat(16)));
+            assert!(!is_valid_hash(&"ab12".repeat(15)));
+            assert!(!is_valid_hash(&format!("{}.png", "ab12".repeat(15))));
+            assert!(!is_valid_hash(""));
+        }
+    }
+}
@@ -0,0 +1,368 @@
+//! First-party click tracking via `/r/{id}` redirects.
+//!
+//! Link IDs are `YYYYMMDD` + the first 8 chars of base64url(sha256(href)), so the
+//! stored digest itself is the ID → URL mapping: a redirect only resolves to a
+//! URL that actually appears in that day's digest (no open redirect, no
+//! registration writes). `run.py` computes the same IDs for the email path.
+
+use crate::{AppState, db, is_valid_date};
+use axum::{
+    extract::{Path, State},
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use regex::Regex;
+use ring::digest;
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+use std::sync::{Arc, LazyLock};
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS link_clicks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    digest_date TEXT NOT NULL,
+    link_id TEXT NOT NULL,
+    url TEXT NOT NULL,
+    narrative TEXT,
+    clicked_at DATETIME DEFAULT (datetime('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_link_clicks_date ON link_clicks(clicked_at);
+";
+
+static HREF: LazyLock<Regex> = LazyLock::new(|| Regex::new(r#"href="(https?://[^"]+)""#).unwrap());
+static ARTICLE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<article>.*?</article>").unwrap());
+static HEADLINE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?s)<h3>(.*?)</h3>").unwrap());
+static SIGNAL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?s)<p class="signal">(.*?) — .*?</p>"#).unwrap());
+
+/// Short, stable ID for a link in a given digest
+pub fn link_id(date: &str, href: &str) -> String {
+    let hash = digest::digest(&digest::SHA256, href.as_bytes());
+    let hash = URL_SAFE_NO_PAD.encode(hash.as_ref());
+    format!("{}{}", date.replace('-', ""), &hash[..8])
+}
+
+/// Minimal unescape for attribute values written by the renderer
+fn unescape_attr(s: &str) -> String {
+    s.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Find the link with `id` in a digest, returning (url, narrative headline)
+pub fn resolve(html: &str, date: &str, id: &str) -> Option<(String, Option<String>)> {
+    let href = HREF
+        .captures_iter(html)
+        .map(|c| c.get(1).unwrap())
+        .find(|m| link_id(date, m.as_str()) == id)?;
+
+    // Attribute the click to the story the link sits in, if any
+    let within = |block: &regex::Match| block.start() <= href.start() && href.end() <= block.end();
+    let narrative = SIGNAL
+        .captures_iter(html)
+        .find(|c| within(&c.get(0).unwrap()))
+        .map(|c| c[1].to_string())
+        .or_else(|| {
+            ARTICLE
+                .find_iter(html)
+                .find(within)
+                .and_then(|a| HEADLINE.captures(a.as_str()).map(|c| c[1].to_string()))
+        })
+        .map(|n| unescape_attr(n.trim()));
+
+    Some((unescape_attr(href.as_str()), narrative))
+}
+
+/// Rewrite outbound links to go through `{base}/r/{id}`, leaving links to
+/// `base` itself untouched. An empty base yields site-relative links.
+pub fn rewrite(html: &str, date: &str, base: &str) -> String {
+    let base = base.trim_end_matches('/');
+    HREF.replace_all(html, |c: &regex::Captures| {
+        let href = &c[1];
+        if !base.is_empty() && href.starts_with(base) {
+            c[0].to_string()
+        } else {
+            format!(r#"href="{base}/r/{}""#, link_id(date, href))
+        }
+    })
+    .into_owned()
+}
+
+/// The digest date a link ID starts with, if it's a well-formed ID.
+/// Checked as ASCII first, so slicing by byte can't split a character.
+fn date_of(id: &str) -> Option<String> {
+    if id.len() != 16 || !id.is_ascii() || !id[..8].chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let date = format!("{}-{}-{}", &id[..4], &id[4..6], &id[6..8]);
+    is_valid_date(&date).then_some(date)
+}
+
+/// Redirect endpoint - logs the click and 302s to the article
+pub async fn redirect(
+    Path(id): Path<String>,
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let not_found = || (StatusCode::NOT_FOUND, "Unknown link".to_string());
+    let date = date_of(&id).ok_or_else(not_found)?;
+
+    let url = state
+        .blocking(move |state| {
+            let (_, html) = state
+                .storage
+                .digest(Some(&date))
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+                .ok_or_else(not_found)?;
+            let (url, narrative) = resolve(&html, &date, &id).ok_or_else(not_found)?;
+
+            // Log best-effort: a failed write must never break the reader's click
+            if state.sqlite_writable()
+                && let Err(e) = record_click(&state.db_path, &date, &id, &url, narrative.as_deref())
+            {
+                tracing::warn!("Could not record click: {}", e);
+            }
+            Ok(url)
+        })
+        .await?;
+
+    Ok((StatusCode::FOUND, [(header::LOCATION, url)]))
+}
+
+fn record_click(
+    db_path: &str,
+    date: &str,
+    id: &str,
+    url: &str,
+    narrative: Option<&str>,
+) -> Result<(), String> {
+    let conn = db::open_rw(db_path)?;
+    conn.execute(
+        "INSERT INTO link_clicks (digest_date, link_id, url, narrative) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![date, id, url, narrative],
+    )
+    .map_err(|e| format!("Cannot save click: {e}"))?;
+    Ok(())
+}
+
+/// Clicks on one outbound link within the stats window
+#[derive(Clone)]
+pub struct LinkClicks {
+    pub url: String,
+    /// Story the link appeared in, when it could be attributed
+    pub narrative: Option<String>,
+    pub clicks: i64,
+}
+
+/// Most-clicked outbound links within the range
+pub fn top_links(
+    conn: &Connection,
+    range: &db::DateRange,
+    limit: u32,
+) -> Result<Vec<LinkClicks>, String> {
+    if !db::table_exists(conn, "link_clicks")? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn
+        .prepare(
+            "SELECT url, MAX(narrative), COUNT(*)
+             FROM link_clicks
+             WHERE clicked_at >= ?1 AND clicked_at < date(?2, '+1 day')
+             GROUP BY url
+             ORDER BY COUNT(*) DESC, url
+             LIMIT ?3",
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    let rows = stmt
+        .query_map(rusqlite::params![range.from, range.to, limit], |row| {
+            Ok(LinkClicks {
+                url: row.get(0)?,
+                narrative: row.get(1)?,
+                clicks: row.get(2)?,
+            })
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(rows)
+}
+
+/// Host of a URL without any `www.` prefix, used to group clicks by publisher
+fn domain(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let host = rest.split(['/', '?', '#']).next().unwrap_or(rest);
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    let host = host.split(':').next().unwrap_or(host);
+    host.strip_prefix("www.").unwrap_or(host)
+}
+
+/// Publishers readers follow through to most, as (domain, clicks), within the range
+pub fn top_domains(
+    conn: &Connection,
+    range: &db::DateRange,
+    limit: usize,
+) -> Result<Vec<(String, i64)>, String> {
+    if !db::table_exists(conn, "link_clicks")? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn
+        .prepare(
+            "SELECT url, COUNT(*)
+             FROM link_clicks
+             WHERE clicked_at >= ?1 AND clicked_at < date(?2, '+1 day')
+             GROUP BY url",
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    let mut by_domain: BTreeMap<String, i64> = BTreeMap::new();
+    let rows = stmt
+        .query_map([&range.from, &range.to], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok());
+    for (url, clicks) in rows {
+        *by_domain.entry(domain(&url).to_lowercase()).or_default() += clicks;
+    }
+    let mut domains: Vec<(String, i64)> = by_domain.into_iter().collect();
+    domains.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    domains.truncate(limit);
+    Ok(domains)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST: &str = r#"<html><body>
+    <article>
+      <h3>Ceasefire holds &amp; talks resume</h3>
+      <p class="sources"><a href="https://example.com/a?x=1&amp;y=2">Wire</a> (center)</p>
+    </article>
+    <div class="cluster">
+      <h3>🌍 Europe</h3>
+      <p class="signal">Rail strike ends — <a href="https://example.org/b">Daily</a></p>
+    </div>
+    <p><a href="https://digest.example.com">Past digests</a></p>
+    </body></html>"#;
+
+    mod link_id {
+        use super::*;
+
+        #[test]
+        fn stable_and_date_prefixed() {
+            // Shared test vector with run.py's tracked_link_id
+            assert_eq!(
+                link_id("2026-01-24", "https://example.com/a"),
+                "20260124Lc4KTFBE"
+            );
+        }
+    }
+
+    mod date_of {
+        use super::*;
+
+        #[test]
+        fn rejects_malformed_ids() {
+            assert_eq!(date_of("20260124Lc4KTFBE").as_deref(), Some("2026-01-24"));
+            assert_eq!(date_of("20261324Lc4KTFBE"), None);
+            assert_eq!(date_of("2026012"), None);
+            // 16 bytes, with a two-byte character across byte 8
+            assert_eq!(date_of("2026010\u{e9}1234567"), None);
+        }
+    }
+
+    mod resolve {
+        use super::*;
+
+        #[test]
+        fn finds_article_link_and_headline() {
+            let id = link_id("2026-01-24", "https://example.com/a?x=1&amp;y=2");
+            let (url, narrative) = resolve(DIGEST, "2026-01-24", &id).unwrap();
+            assert_eq!(url, "https://example.com/a?x=1&y=2");
+            assert_eq!(narrative.as_deref(), Some("Ceasefire holds & talks resume"));
+        }
+
+        #[test]
+        fn finds_signal_headline() {
+            let id = link_id("2026-01-24", "https://example.org/b");
+            let (_, narrative) = resolve(DIGEST, "2026-01-24", &id).unwrap();
+            assert_eq!(narrative.as_deref(), Some("Rail strike ends"));
+        }
+
+        #[test]
+        fn unknown_id_is_none() {
+            assert!(resolve(DIGEST, "2026-01-24", "20260124AAAAAAAA").is_none());
+        }
+
+        #[test]
+        fn links_outside_stories_have_no_narrative() {
+            let id = link_id("2026-01-24", "https://digest.example.com");
+            let (_, narrative) = resolve(DIGEST, "2026-01-24", &id).unwrap();
+            assert_eq!(narrative, None);
+        }
+    }
+
+    mod rewrite {
+        use super::*;
+
+        #[test]
+        fn routes_outbound_links_only() {
+            let out = rewrite(DIGEST, "2026-01-24", "https://digest.example.com/");
+            let id = link_id("2026-01-24", "https://example.org/b");
+            assert!(out.contains(&format!(r#"href="https://digest.example.com/r/{id}""#)));
+            assert!(out.contains(r#"href="https://digest.example.com">Past digests"#));
+            assert!(!out.contains("example.org"));
+        }
+
+        #[test]
+        fn empty_base_is_relative() {
+            let out = rewrite(DIGEST, "2026-01-24", "");
+            let id = link_id("2026-01-24", "https://example.org/b");
+            assert!(out.contains(&format!(r#"href="/r/{id}""#)));
+        }
+    }
+
+    mod domain {
+        use super::*;
+
+        #[test]
+        fn strips_scheme_path_and_www() {
+            assert_eq!(domain("https://www.bbc.co.uk/news/world-1"), "bbc.co.uk");
+            assert_eq!(domain("http://example.org:8080?q=1"), "example.org");
+            assert_eq!(domain("https://user@apnews.com/article"), "apnews.com");
+        }
+    }
+
+    mod top_domains {
+        use super::*;
+
+        #[test]
+        fn groups_clicks_by_publisher() {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(SCHEMA).unwrap();
+            conn.execute_batch(
+                "INSERT INTO link_clicks (digest_date, link_id, url, clicked_at) VALUES
+                     ('2026-01-24', 'a', 'https://www.bbc.co.uk/1', '2026-01-24 08:00:00'),
+                     ('2026-01-24', 'b', 'https://bbc.co.uk/2', '2026-01-24 09:00:00'),
+                     ('2026-01-24', 'c', 'https://apnews.com/3', '2026-01-24 09:30:00'),
+                     ('2026-01-01', 'd', 'https://apnews.com/4', '2026-01-01 09:30:00')",
+            )
+            .unwrap();
+            let range = db::DateRange {
+                from: "2026-01-20".into(),
+                to: "2026-01-24".into(),
+                days: 5,
+            };
+            assert_eq!(
+                top_domains(&conn, &range, 10).unwrap(),
+                vec![("bbc.co.uk".to_string(), 2), ("apnews.com".to_string(), 1)]
+            );
+            assert_eq!(top_links(&conn, &range, 1).unwrap()[0].clicks, 1);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Where the server listens: TCP on `PORT` by default, an address of its own
+//! with `LISTEN=127.0.0.1:8080`, or a Unix domain socket with
+//! `LISTEN=unix:/run/digest.sock`, for nginx or a systemd setup that proxies
+//! to a socket rather than a localhost port.
+//!
+//! The socket gets `LISTEN_MODE` (octal, default `660`: the server's user and
+//! group), and is removed when the server stops. One left behind by a server
+//! that didn't stop cleanly is replaced at startup, but not one still in use,
+//! nor anything that isn't a socket.
+
+use axum::serve::Listener;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use tokio::net::{UnixListener, UnixStream};
+
+/// Where to listen (`LISTEN`, `PORT`, and `LISTEN_MODE`)
+#[derive(Debug, PartialEq)]
+pub enum Listen {
+    Tcp(SocketAddr),
+    Unix { path: PathBuf, mode: u32 },
+}
+
+impl Listen {
+    pub fn from_env() -> Result<Self, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let port = var("PORT").and_then(|p| p.parse().ok()).unwrap_or(8080);
+        Self::parse(
+            var("LISTEN").as_deref(),
+            port,
+            var("LISTEN_MODE").as_deref(),
+        )
+    }
+
+    fn parse(listen: Option<&str>, port: u16, mode: Option<&str>) -> Result<Self, String> {
+        let Some(listen) = listen else {
+            return Ok(Self::Tcp(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port))));
+        };
+        if let Some(path) = listen.strip_prefix("unix:") {
+            if !path.starts_with('/') {
+                return Err(format!(
+                    "LISTEN=unix: needs an absolute socket path, like unix:/run/digest.sock, not {path}"
+                ));
+            }
+            let mode = match mode {
+                Some(mode) => u32::from_str_radix(mode, 8)
+                    .ok()
+                    .filter(|m| *m <= 0o777)
+                    .ok_or_else(|| format!("LISTEN_MODE must be octal, like 660, not {mode}"))?,
+                None => 0o660,
+            };
+            return Ok(Self::Unix {
+                path: PathBuf::from(path),
+                mode,
+            });
+        }
+        listen.parse().map(Self::Tcp).map_err(|_| {
+            format!("LISTEN must be an address like 127.0.0.1:8080, or unix:/path, not {listen}")
+        })
+    }
+}
+
+/// A Unix domain socket, removed when dropped
+pub struct UnixSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl UnixSocket {
+    pub fn bind(path: &Path, mode: u32) -> Result<Self, String> {
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => {
+                if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                    return Err(format!(
+                        "{} is in use; is another server running?",
+                        path.display()
+                    ));
+                }
+                std::fs::remove_file(path)
+                    .map_err(|e| format!("Cannot remove the old {}: {e}", path.display()))?;
+            }
+            Ok(_) => {
+                return Err(format!("{} exists and isn't a socket", path.display()));
+            }
+            Err(_) => {}
+        }
+        let listener = UnixListener::bind(path)
+            .map_err(|e| format!("Cannot listen on {}: {e}", path.display()))?;
+        let socket = Self {
+            listener,
+            path: path.to_path_buf(),
+        };
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .map_err(|e| format!("Cannot set {}'s permissions: {e}", path.display()))?;
+        Ok(socket)
+    }
+}
+
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// What handlers see as a socket connection's peer, which has no address.
+/// The proxy in front passes the client's in `X-Forwarded-For`.
+const LOCAL_PEER: SocketAddr = SocketAddr::new(std::net::IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+
+impl Listener for UnixSocket {
+    type Io = UnixStream;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        let (stream, _) = Listener::accept(&mut self.listener).await;
+        (stream, LOCAL_PEER)
+    }
+
+    fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        Ok(LOCAL_PEER)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod parse {
+        use super::*;
+
+        #[test]
+        fn takes_a_socket_path_or_an_address() {
+            assert_eq!(
+                Listen::parse(None, 8080, None),
+                Ok(Listen::Tcp("0.0.0.0:8080".parse().unwrap()))
+            );
+            assert_eq!(
+                Listen::parse(Some("127.0.0.1:9000"), 8080, None),
+                Ok(Listen::Tcp("127.0.0.1:9000".parse().unwrap()))
+            );
+            assert_eq!(
+                Listen::parse(Some("unix:/run/digest.sock"), 8080, Some("666")),
+                Ok(Listen::Unix {
+                    path: "/run/digest.sock".into(),
+                    mode: 0o666
+                })
+            );
+            assert!(Listen::parse(Some("unix:digest.sock"), 8080, None).is_err());
+            assert!(Listen::parse(Some("unix:/run/digest.sock"), 8080, Some("rw")).is_err());
+            assert!(Listen::parse(Some("localhost"), 8080, None).is_err());
+        }
+    }
+
+    mod bind {
+        use super::*;
+
+        #[tokio::test]
+        async fn replaces_a_stale_socket_but_not_a_live_one() {
+            let dir = std::env::temp_dir().join(format!("listen-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("digest.sock");
+
+            let socket = UnixSocket::bind(&path, 0o660).unwrap();
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o660);
+            assert!(UnixSocket::bind(&path, 0o660).is_err());
+            drop(socket);
+            assert!(!path.exists());
+
+            // Left behind, as by a server that was killed
+            drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+            assert!(UnixSocket::bind(&path, 0o660).is_ok());
+
+            std::fs::write(&path, "not a socket").unwrap();
+            assert!(UnixSocket::bind(&path, 0o660).is_err());
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
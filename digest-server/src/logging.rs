@@ -0,0 +1,76 @@
+//! Log setup and per-request access logging
+
+use axum::{body::Body, http::Request};
+use tower_http::{
+    LatencyUnit,
+    classify::{ServerErrorsAsFailures, SharedClassifier},
+    trace::{DefaultOnResponse, TraceLayer},
+};
+use tracing::{Level, Span};
+use tracing_subscriber::{Layer, layer::SubscriberExt, util::SubscriberInitExt};
+
+type MakeSpan = fn(&Request<Body>) -> Span;
+type AccessLogLayer = TraceLayer<SharedClassifier<ServerErrorsAsFailures>, MakeSpan>;
+
+/// Initialize the global subscriber (respects RUST_LOG, defaults to info).
+///
+/// `LOG_FORMAT=json` emits one JSON object per line for log aggregators;
+/// anything else keeps the human-readable format. Built with the `otel`
+/// feature, spans are also exported over OTLP when configured.
+pub fn init() {
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+    let fmt = tracing_subscriber::fmt::layer();
+    let fmt = if std::env::var("LOG_FORMAT").is_ok_and(|f| f.eq_ignore_ascii_case("json")) {
+        fmt.json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed()
+    } else {
+        fmt.boxed()
+    };
+    let registry = tracing_subscriber::registry().with(filter).with(fmt);
+
+    #[cfg(feature = "otel")]
+    match crate::otel::layer() {
+        Ok(otel) => registry.with(otel).init(),
+        Err(e) => {
+            registry.init();
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    #[cfg(not(feature = "otel"))]
+    registry.init();
+}
+
+/// Span for one request. Only the path is recorded: query strings can carry
+/// signed tokens and email addresses.
+fn request_span(request: &Request<Body>) -> Span {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let span = tracing::info_span!(
+        "request",
+        method = %request.method(),
+        path = request.uri().path(),
+        request_id,
+    );
+    #[cfg(feature = "otel")]
+    crate::otel::set_parent(&span, request.headers());
+    span
+}
+
+/// Access log layer: one INFO line per response with status and latency
+pub fn trace_layer() -> AccessLogLayer {
+    TraceLayer::new_for_http()
+        .make_span_with(request_span as MakeSpan)
+        .on_response(
+            DefaultOnResponse::new()
+                .level(Level::INFO)
+                .latency_unit(LatencyUnit::Millis),
+        )
+}
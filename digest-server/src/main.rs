@@ -1,70 +1,323 @@
+mod admin;
+mod alerts;
+mod archive;
+mod backup;
+mod blobs;
+mod cache;
+mod cache_control;
+mod charts;
+mod compression;
+mod conditional;
+mod css;
+mod db;
+mod db_stats;
+mod email_html;
+mod encoding;
+mod engagement;
+mod health;
+mod images;
+mod links;
+mod listen;
+mod logging;
+mod metrics;
+mod migrations;
+#[cfg(feature = "otel")]
+mod otel;
+mod pageviews;
+#[cfg(feature = "postgres")]
+mod postgres;
+mod preferences;
+mod preview;
+mod rate_limit;
+mod reload;
+mod resend;
+mod retention;
+mod revisions;
+mod rollups;
+mod runs;
+mod s3;
+mod schedule;
+mod shutdown;
+mod slo;
+mod smtp;
+mod sources;
+mod sources_page;
+mod stats_api;
+mod storage;
+mod subscribers;
+mod timeline;
+mod tls;
+mod tokens;
+mod topics;
+mod unsubscribe;
+
 use axum::{
     Form, Router,
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::{Html, Redirect},
+    extract::{ConnectInfo, Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    middleware,
+    response::{AppendHeaders, Html, IntoResponse, Redirect, Response},
     routing::{get, post},
 };
 use reqwest::Client;
 use rusqlite::{Connection, OpenFlags};
-use serde::{Deserialize, Serialize};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tower_http::trace::TraceLayer;
+use std::time::Duration;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
 
 struct AppState {
     db_path: String,
+    /// Digests, stats, and subscribers
+    storage: Arc<dyn storage::Storage>,
+    /// Read-only connections for the SQLite-only extras (pageviews, clicks,
+    /// email events, self-checks); `None` with Postgres storage
+    db: Option<db::Pool>,
+    /// `SERVER_MODE=rw`: the SQLite database is migrated and written to
+    /// (pageviews, clicks, email events, local subscribers, self-checks)
+    read_write: bool,
     digest_name: String,
     css_url: Option<String>,
     homepage_url: Option<String>,
     source_url: Option<String>,
     resend_api_key: Option<String>,
-    resend_audience_id: Option<String>,
+    audiences: Vec<resend::AudienceConfig>,
+    resend_from: Option<String>,
+    admin_token: Option<String>,
+    /// Restricts /stats (and its JSON and CSV), /sources, and /health/history when set
+    stats_token: Option<String>,
+    /// What CDNs and browsers may cache, per kind of page
+    cache_control: cache_control::CacheControl,
+    /// Requests each client may make per minute, and what they have left
+    rate_limits: rate_limit::RateLimits,
+    /// Days without new items before a working feed is flagged as dormant
+    stale_source_days: u32,
+    /// Feed definitions from `SOURCES_FILE`, for /sources, re-read when it changes
+    sources: Option<reload::SourcesFile>,
+    slo: Option<slo::SloConfig>,
+    resend_webhook_secret: Option<String>,
+    token_secret: Option<Vec<u8>>,
+    public_url: Option<String>,
+    double_opt_in: bool,
+    click_tracking: bool,
+    view_salt: [u8; 32],
+    smtp: Option<smtp::SmtpConfig>,
+    backup: Option<backup::BackupConfig>,
+    /// `PIPELINE_COMMAND`, when to run it, and the runs it's had
+    pipeline: Option<Arc<runs::Pipeline>>,
+    metrics: metrics::Metrics,
+    stats_cache: cache::TtlCache<StatsKey, StatsData>,
+    deep_health: health::DeepConfig,
+    deep_health_cache: cache::TtlCache<(), health::DeepHealth>,
+    /// Rendered digest pages by date, with the row version they came from
+    digest_cache: cache::LruCache<String, (String, String)>,
     http_client: Client,
 }
 
+impl AppState {
+    /// A read-only connection from the pool
+    fn db(&self) -> Result<db::PooledConnection, (StatusCode, String)> {
+        self.db
+            .as_ref()
+            .ok_or_else(|| {
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "Not available with Postgres storage".to_string(),
+                )
+            })?
+            .get()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {e}")))
+    }
+
+    /// Run database work off the async runtime so a slow query can't stall
+    /// unrelated requests
+    async fn blocking<T, F>(self: &Arc<Self>, f: F) -> Result<T, (StatusCode, String)>
+    where
+        F: FnOnce(&AppState) -> Result<T, (StatusCode, String)> + Send + 'static,
+        T: Send + 'static,
+    {
+        let state = Arc::clone(self);
+        db::blocking(move || f(&state))
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+    }
+
+    /// Stats for the requested window, reusing results younger than STATS_CACHE_SECS
+    async fn stats_data(
+        self: &Arc<Self>,
+        query: &StatsQuery,
+    ) -> Result<Arc<StatsData>, (StatusCode, String)> {
+        let query = query.clone();
+        self.blocking(move |state| {
+            let key = (query.days, query.from.clone(), query.to.clone());
+            state.stats_cache.get_or_try_insert(key, || {
+                let _timer = state.metrics.time_db("stats");
+                state.storage.stats(&query, state.slo.as_ref())
+            })
+        })
+        .await
+    }
+
+    /// Stats for the period before `data` when the query asks to compare
+    async fn previous_stats(
+        self: &Arc<Self>,
+        query: &StatsQuery,
+        data: &StatsData,
+    ) -> Result<Option<Arc<StatsData>>, (StatusCode, String)> {
+        match query.compare.as_deref() {
+            None | Some("") => Ok(None),
+            Some("previous") => self
+                .stats_data(&StatsQuery {
+                    from: Some(data.previous_range.from.clone()),
+                    to: Some(data.previous_range.to.clone()),
+                    ..StatsQuery::default()
+                })
+                .await
+                .map(Some),
+            Some(_) => Err((StatusCode::BAD_REQUEST, "compare must be 'previous'".into())),
+        }
+    }
+
+    /// Subscriptions need somewhere to keep them: Resend, or locally with SMTP delivery
+    fn subscriptions_enabled(&self) -> bool {
+        // Without Resend the local table is the mailing list, so it must be writable
+        !self.audiences.is_empty()
+            && (self.resend_api_key.is_some() || (self.smtp.is_some() && self.storage_writable()))
+    }
+
+    /// Whether subscriber changes and feedback can be stored: always with
+    /// Postgres, and with SQLite only in read-write mode
+    fn storage_writable(&self) -> bool {
+        self.db.is_none() || self.read_write
+    }
+
+    /// Whether the SQLite-only extras (pageviews, clicks, email events) can be recorded
+    fn sqlite_writable(&self) -> bool {
+        self.db.is_some() && self.read_write
+    }
+
+    /// Look up an audience by name, or the default (first configured) one
+    fn audience(&self, name: Option<&str>) -> Option<&resend::AudienceConfig> {
+        match name.filter(|n| !n.is_empty()) {
+            Some(name) => self.audiences.iter().find(|a| a.name == name),
+            None => self.audiences.first(),
+        }
+    }
+
+    /// Build an absolute link carrying a signed token, if signing is configured
+    fn signed_link(&self, path: &str, claims: &tokens::Claims) -> Option<String> {
+        let secret = self.token_secret.as_deref()?;
+        let base = self.public_url.as_deref()?.trim_end_matches('/');
+        Some(format!(
+            "{base}{path}?token={}",
+            tokens::mint(secret, claims)
+        ))
+    }
+
+    /// Send a one-off email through Resend if configured, otherwise SMTP.
+    ///
+    /// Returns the provider name and, for Resend, its message ID.
+    async fn send_email(
+        &self,
+        to: &str,
+        subject: &str,
+        html: &str,
+    ) -> Result<(&'static str, Option<String>), (StatusCode, String)> {
+        if let (Some(api_key), Some(from)) = (&self.resend_api_key, &self.resend_from) {
+            let email = resend::Email {
+                from,
+                to: vec![to],
+                subject,
+                html,
+            };
+            let id = resend::send_email(&self.http_client, api_key, &email)
+                .await
+                .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+            return Ok(("resend", Some(id)));
+        }
+        let config = self.smtp.as_ref().ok_or((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Email not configured (RESEND_API_KEY and RESEND_FROM, or SMTP_HOST)".into(),
+        ))?;
+        let email = smtp::Outgoing {
+            to: to.to_string(),
+            subject: subject.to_string(),
+            html: html.to_string(),
+            unsubscribe_url: None,
+        };
+        smtp::send(config, &email)
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+        Ok(("smtp", None))
+    }
+
+    /// Verify a token from a link, mapping failures to a 400
+    fn verify_token(
+        &self,
+        token: &str,
+        action: tokens::Action,
+    ) -> Result<tokens::Claims, (StatusCode, String)> {
+        let secret = self.token_secret.as_deref().ok_or((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Signed links not configured".into(),
+        ))?;
+        tokens::verify(secret, token, action, tokens::now())
+            .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+    }
+}
+
 #[derive(Deserialize)]
 struct SubscribeForm {
     email: String,
+    audience: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ConfirmQuery {
+    token: String,
 }
 
 #[derive(Deserialize, Default)]
 struct IndexQuery {
     subscribed: Option<String>,
-}
-
-#[derive(Serialize)]
-struct ResendContact {
-    email: String,
+    pending: Option<String>,
 }
 
 /// Index page - lists recent digests
 async fn index(
     State(state): State<Arc<AppState>>,
     Query(query): Query<IndexQuery>,
-) -> Result<Html<String>, (StatusCode, String)> {
-    let conn = Connection::open_with_flags(&state.db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {e}")))?;
-
-    // Get list of available digests (most recent first)
-    let mut stmt = conn
-        .prepare("SELECT date FROM digests ORDER BY date DESC LIMIT 30")
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Query error: {e}"),
-            )
-        })?;
-
-    let dates: Vec<String> = stmt
-        .query_map([], |row| row.get(0))
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Query error: {e}"),
-            )
-        })?
-        .filter_map(|r| r.ok())
-        .collect();
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    // Get list of available digests (most recent first), and when the newest changed
+    let (dates, updated_at) = state
+        .blocking(|state| {
+            let _timer = state.metrics.time_db("list_digests");
+            let dates = state
+                .storage
+                .digest_dates(30)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+            let updated_at = match dates.first() {
+                Some(newest) => state
+                    .storage
+                    .digest_version(newest)
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+                    .and_then(|(_, updated_at)| updated_at),
+                None => None,
+            };
+            Ok::<_, (StatusCode, String)>((dates, updated_at))
+        })
+        .await?;
+    // A new digest moves the timestamp; one removed changes the list
+    let validators = updated_at
+        .as_deref()
+        .map(|updated_at| conditional::Validators::new(updated_at).including(&dates.join(" ")));
+    let validator_headers = validators.as_ref().map(|v| v.headers()).unwrap_or_default();
+    if validators.is_some_and(|v| v.is_fresh(&headers)) {
+        return Ok((StatusCode::NOT_MODIFIED, AppendHeaders(validator_headers)).into_response());
+    }
 
     let links: String = dates
         .iter()
@@ -78,18 +331,33 @@ async fn index(
     let name = &state.digest_name;
     let success_msg = if query.subscribed.is_some() {
         r#"<div class="success-msg">Thanks for subscribing! You'll receive the next digest.</div>"#
+    } else if query.pending.is_some() {
+        r#"<div class="success-msg">Almost done! Check your inbox for a confirmation link.</div>"#
     } else {
         ""
     };
-    let subscriptions_enabled =
-        state.resend_api_key.is_some() && state.resend_audience_id.is_some();
+    let subscriptions_enabled = state.subscriptions_enabled();
+    // Offer a list picker only when there's more than one audience to choose from
+    let audience_select = if state.audiences.len() > 1 {
+        let options: String = state
+            .audiences
+            .iter()
+            .map(|a| format!(r#"<option value="{0}">{0}</option>"#, a.name))
+            .collect();
+        format!(r#"<select name="audience">{options}</select>"#)
+    } else {
+        String::new()
+    };
     let subscribe_form = if subscriptions_enabled {
-        r#"<form method="post" action="/subscribe" class="subscribe-form">
+        format!(
+            r#"<form method="post" action="/subscribe" class="subscribe-form">
         <input type="email" name="email" placeholder="your@email.com" required>
+        {audience_select}
         <button type="submit">Subscribe</button>
       </form>"#
+        )
     } else {
-        ""
+        String::new()
     };
     let homepage_link = state.homepage_url.as_ref().map(|url| {
         let display = url
@@ -103,12 +371,19 @@ async fn index(
             state.source_url.as_ref().unwrap()
         )
     });
-    let stats_link = r#"<a href="/stats" class="meta-link">Stats</a>"#;
-    let meta_links = match (homepage_link, source_link) {
-        (Some(h), Some(s)) => format!(r#"<p class="meta-links">{h} · {s} · {stats_link}</p>"#),
-        (Some(h), None) => format!(r#"<p class="meta-links">{h} · {stats_link}</p>"#),
-        (None, Some(s)) => format!(r#"<p class="meta-links">{s} · {stats_link}</p>"#),
-        (None, None) => format!(r#"<p class="meta-links">{stats_link}</p>"#),
+    // Private stats aren't advertised to readers
+    let stats_link = state
+        .stats_token
+        .is_none()
+        .then(|| r#"<a href="/stats" class="meta-link">Stats</a>"#.to_string());
+    let meta: Vec<String> = [homepage_link, source_link, stats_link]
+        .into_iter()
+        .flatten()
+        .collect();
+    let meta_links = if meta.is_empty() {
+        String::new()
+    } else {
+        format!(r#"<p class="meta-links">{}</p>"#, meta.join(" · "))
     };
     let css_link = state
         .css_url
@@ -174,6 +449,14 @@ async fn index(
       color: var(--text-primary);
       font-size: 1rem;
     }}
+    .subscribe-form select {{
+      padding: 0.75rem;
+      background: var(--bg-card);
+      border: 1px solid var(--border-white-light);
+      border-radius: 0.5rem;
+      color: var(--text-primary);
+      font-size: 1rem;
+    }}
     .subscribe-form input::placeholder {{
       color: var(--text-tertiary);
     }}
@@ -260,70 +543,153 @@ async fn index(
 </html>"##
     );
 
-    Ok(Html(html))
+    Ok((AppendHeaders(validator_headers), Html(html)).into_response())
 }
 
-/// Subscribe handler - adds email to Resend audience
+/// Subscribe handler - adds email to the chosen (or default) Resend audience,
+/// or emails a confirmation link first when double opt-in is enabled
 async fn subscribe(
     State(state): State<Arc<AppState>>,
     Form(form): Form<SubscribeForm>,
 ) -> Result<Redirect, (StatusCode, String)> {
-    let (api_key, audience_id) = state
-        .resend_api_key
-        .as_ref()
-        .zip(state.resend_audience_id.as_ref())
-        .ok_or((
+    let result = request_subscription(&state, form).await;
+    state.metrics.record_subscription(match &result {
+        Ok(redirect) if redirect.location().contains("pending") => "pending",
+        Ok(_) => "subscribed",
+        Err(_) => "failed",
+    });
+    result
+}
+
+async fn request_subscription(
+    state: &AppState,
+    form: SubscribeForm,
+) -> Result<Redirect, (StatusCode, String)> {
+    if !state.subscriptions_enabled() {
+        return Err((
             StatusCode::SERVICE_UNAVAILABLE,
             "Subscriptions not configured".into(),
-        ))?;
+        ));
+    }
+    let audience = state
+        .audience(form.audience.as_deref())
+        .ok_or((StatusCode::BAD_REQUEST, "Unknown audience".into()))?;
+
+    if state.double_opt_in {
+        let claims = tokens::Claims::new(
+            tokens::Action::Confirm,
+            &form.email,
+            Some(&audience.name),
+            tokens::CONFIRM_TTL_SECS,
+        );
+        let Some(link) = state.signed_link("/subscribe/confirm", &claims) else {
+            return Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Double opt-in not configured".into(),
+            ));
+        };
+        let name = escape_html(&state.digest_name);
+        let html = format!(
+            r#"<p>Confirm your subscription to {name}:</p>
+<p><a href="{link}">Yes, subscribe me</a></p>
+<p>If you didn't ask for this, ignore this email and you won't hear from us again.</p>"#
+        );
+        let subject = format!("Confirm your subscription to {}", state.digest_name);
+        state.send_email(&claims.email, &subject, &html).await?;
+        return Ok(Redirect::to("/?pending=1"));
+    }
 
-    let url = format!("https://api.resend.com/audiences/{}/contacts", audience_id);
+    add_subscriber(state, audience, &form.email).await?;
 
-    let response = state
-        .http_client
-        .post(&url)
-        .header("Authorization", format!("Bearer {}", api_key))
-        .json(&ResendContact { email: form.email })
-        .send()
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Request failed: {e}"),
-            )
-        })?;
+    // Redirect back to index with success message
+    Ok(Redirect::to("/?subscribed=1"))
+}
 
-    if !response.status().is_success() {
-        let status = response.status();
-        let body = response.text().await.unwrap_or_default();
+/// Double opt-in confirmation link target
+async fn confirm_subscription(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ConfirmQuery>,
+) -> Result<Redirect, (StatusCode, String)> {
+    let claims = state.verify_token(&query.token, tokens::Action::Confirm)?;
+    if !state.subscriptions_enabled() {
         return Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Resend error {}: {}", status, body),
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Subscriptions not configured".into(),
         ));
     }
+    let audience = state
+        .audience(claims.audience.as_deref())
+        .ok_or((StatusCode::BAD_REQUEST, "Unknown audience".into()))?;
 
-    // Redirect back to index with success message
-    Ok(Redirect::to("/?subscribed=1"))
+    let result = add_subscriber(&state, audience, &claims.email).await;
+    state.metrics.record_subscription(if result.is_ok() {
+        "subscribed"
+    } else {
+        "failed"
+    });
+    result.map(|_| Redirect::to("/?subscribed=1"))
 }
 
-/// Health check endpoint - verifies DB is accessible
-async fn health(State(state): State<Arc<AppState>>) -> Result<&'static str, (StatusCode, String)> {
-    let conn = Connection::open_with_flags(&state.db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
-        .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, format!("DB error: {e}")))?;
+/// Add a contact to the audience in Resend (when used) and to the local table
+async fn add_subscriber(
+    state: &AppState,
+    audience: &resend::AudienceConfig,
+    email: &str,
+) -> Result<(), (StatusCode, String)> {
+    if let Some(api_key) = &state.resend_api_key {
+        resend::Audience::new(&state.http_client, api_key, &audience.id)
+            .add_contact(email, false)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    }
 
-    conn.query_row("SELECT 1", [], |_| Ok(())).map_err(|e| {
-        (
-            StatusCode::SERVICE_UNAVAILABLE,
-            format!("DB query failed: {e}"),
-        )
-    })?;
+    // Without Resend the local table is the SMTP mailing list, so it must be
+    // written; otherwise it's a best-effort mirror, kept only in read-write mode
+    if !state.storage_writable() {
+        return Ok(());
+    }
+    let (storage, email, audience_name) = (
+        state.storage.clone(),
+        email.to_string(),
+        audience.name.clone(),
+    );
+    let stored = db::blocking(move || storage.set_subscribed(&email, &audience_name, true))
+        .await
+        .flatten();
+    if let Err(e) = stored {
+        if state.resend_api_key.is_none() {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, e));
+        }
+        tracing::warn!("Could not record subscriber locally: {}", e);
+    }
+    Ok(())
+}
 
+/// Health check endpoint - verifies DB is accessible
+async fn health(State(state): State<Arc<AppState>>) -> Result<&'static str, (StatusCode, String)> {
+    state
+        .blocking(|state| {
+            state
+                .storage
+                .probe()
+                .map_err(|e| (StatusCode::SERVICE_UNAVAILABLE, e))
+        })
+        .await?;
     Ok("ok")
 }
 
-#[derive(Deserialize, Default)]
+#[derive(Clone, Deserialize, Default)]
 struct StatsQuery {
     days: Option<u32>,
+    /// Explicit range (YYYY-MM-DD, inclusive); overrides `days`
+    from: Option<String>,
+    to: Option<String>,
+    /// Which table `/stats.csv` exports: health, usage, runs, stages, or topics
+    table: Option<String>,
+    /// `previous` to show the preceding period of the same length alongside
+    compare: Option<String>,
+    /// `/stats.json` schema version (default: current)
+    version: Option<u32>,
 }
 
 #[derive(Clone)]
@@ -332,6 +698,28 @@ struct SourceHealth {
     total_fetches: i64,
     successes: i64,
     success_rate_pct: f64,
+    /// Median and 95th-percentile fetch time (absent before fetch_ms was recorded)
+    p50_ms: Option<i64>,
+    p95_ms: Option<i64>,
+    /// Daily success rate (%) across the range, oldest first
+    daily_success: Vec<f64>,
+    /// When the pipeline last saw new items in this feed, and how many days ago
+    last_new_item_at: Option<String>,
+    days_since_new_item: Option<f64>,
+    /// New articles per fetch in the range against the source's own history
+    volume: Option<Volume>,
+    /// Articles its keyword filters dropped in the range
+    filtered_articles: i64,
+}
+
+impl SourceHealth {
+    /// Fetching fine but publishing nothing new for `stale_days` or more
+    fn is_dormant(&self, stale_days: u32) -> bool {
+        self.successes > 0
+            && self
+                .days_since_new_item
+                .is_some_and(|d| d >= f64::from(stale_days))
+    }
 }
 
 #[derive(Clone)]
@@ -346,76 +734,380 @@ struct DigestRun {
     run_at: String,
     articles_fetched: i64,
     articles_emailed: i64,
+    /// Pipeline wall-clock time and Claude usage (absent for older or send-only runs)
+    duration_ms: Option<i64>,
+    input_tokens: Option<i64>,
+    output_tokens: Option<i64>,
+    cost_usd: Option<f64>,
 }
 
+/// Stats cache key: the window parameters of a `StatsQuery`
+type StatsKey = (Option<u32>, Option<String>, Option<String>);
+
 struct StatsData {
-    period_days: u32,
+    range: db::DateRange,
+    /// Equally long range just before `range`, for `?compare=previous`
+    previous_range: db::DateRange,
     source_health: Vec<SourceHealth>,
     source_usage: Vec<SourceUsage>,
+    topic_coverage: topics::Coverage,
     recent_runs: Vec<DigestRun>,
+    /// The latest runs stage by stage, failed ones included
+    run_timeline: Vec<timeline::RunStages>,
+    /// Articles fetched by every run in the range, oldest first
+    run_articles: Vec<f64>,
+    /// Runs by weekday (Monday first) and UTC hour
+    publish_times: [[i64; 24]; 7],
+    /// Estimated Claude cost of every run in the range
+    total_cost_usd: f64,
+    subscriber_growth: Vec<subscribers::GrowthDay>,
+    unsubscribe_reasons: Vec<(String, i64)>,
+    engagement: Vec<engagement::DigestEngagement>,
+    top_viewed: Vec<pageviews::DigestViews>,
+    /// Server self-checks in the range and the share that passed
+    uptime: Option<(i64, f64)>,
+    subscriber_counts: subscribers::ListCounts,
+    top_links: Vec<links::LinkClicks>,
+    /// Clicks per publisher domain
+    top_domains: Vec<(String, i64)>,
+    /// On-time delivery SLO, when a deadline is configured
+    delivery: Option<slo::Budget>,
+}
+
+/// Recorded fetch durations per source within the range, each sorted ascending
+fn fetch_latencies(
+    conn: &Connection,
+    range: &db::DateRange,
+) -> Result<BTreeMap<String, Vec<i64>>, String> {
+    let mut latencies: BTreeMap<String, Vec<i64>> = BTreeMap::new();
+    if !db::column_exists(conn, "source_health", "fetch_ms")? {
+        return Ok(latencies);
+    }
+    let mut stmt = conn
+        .prepare(
+            "SELECT source_id, fetch_ms FROM source_health
+             WHERE fetch_ms IS NOT NULL
+               AND recorded_at >= ?1 AND recorded_at < date(?2, '+1 day')
+             ORDER BY source_id, fetch_ms",
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    let rows = stmt
+        .query_map([&range.from, &range.to], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok());
+    for (source_id, ms) in rows {
+        latencies.entry(source_id).or_default().push(ms);
+    }
+    Ok(latencies)
 }
 
-/// Fetch stats data from database
-fn fetch_stats_data(db_path: &str, days: u32) -> Result<StatsData, (StatusCode, String)> {
-    let conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {e}")))?;
+/// Success rate (%) per source per day within the range, oldest day first
+fn daily_success(
+    conn: &Connection,
+    range: &db::DateRange,
+) -> Result<BTreeMap<String, Vec<f64>>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT source_id, AVG(success) * 100.0 FROM source_health
+             WHERE recorded_at >= ?1 AND recorded_at < date(?2, '+1 day')
+             GROUP BY source_id, date(recorded_at)
+             ORDER BY source_id, date(recorded_at)",
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    let rows = stmt
+        .query_map([&range.from, &range.to], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, f64>(1)?))
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok());
+    let mut daily: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for (source_id, rate) in rows {
+        daily.entry(source_id).or_default().push(rate);
+    }
+    Ok(daily)
+}
 
-    // Source health: success rate per source over last N days
-    let source_health: Vec<SourceHealth> = {
-        let mut stmt = conn
-            .prepare(
-                "SELECT source_id,
-                        COUNT(*) as total,
-                        SUM(CASE WHEN success = 1 THEN 1 ELSE 0 END) as successes
-                 FROM source_health
-                 WHERE recorded_at >= datetime('now', '-' || ?1 || ' days')
-                 GROUP BY source_id
-                 ORDER BY source_id",
-            )
-            .map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Query error: {e}"),
-                )
-            })?;
+/// When each feed last had new items (written by the pipeline), as
+/// source_id -> (timestamp, days ago)
+fn source_activity(conn: &Connection) -> Result<BTreeMap<String, (String, f64)>, String> {
+    if !db::table_exists(conn, "source_activity")? {
+        return Ok(BTreeMap::new());
+    }
+    let mut stmt = conn
+        .prepare(
+            "SELECT source_id, last_new_item_at, julianday('now') - julianday(last_new_item_at)
+             FROM source_activity",
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(0)?, (row.get(1)?, row.get(2)?))))
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(rows)
+}
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Digest runs in the range by weekday (Monday first) and UTC hour
+fn publish_times(conn: &Connection, range: &db::DateRange) -> Result<[[i64; 24]; 7], String> {
+    let mut grid = [[0; 24]; 7];
+    let mut stmt = conn
+        .prepare(
+            "SELECT CAST(strftime('%w', run_at) AS INTEGER), CAST(strftime('%H', run_at) AS INTEGER), COUNT(*)
+             FROM digest_runs
+             WHERE run_at >= ?1 AND run_at < date(?2, '+1 day')
+             GROUP BY 1, 2",
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    let rows = stmt
+        .query_map([&range.from, &range.to], |row| {
+            Ok((
+                row.get::<_, u8>(0)?,
+                row.get::<_, u8>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok());
+    for (weekday, hour, runs) in rows {
+        // strftime('%w') counts from Sunday
+        if let Some(cell) = grid
+            .get_mut((usize::from(weekday) + 6) % 7)
+            .and_then(|r| r.get_mut(usize::from(hour)))
+        {
+            *cell = runs;
+        }
+    }
+    Ok(grid)
+}
+
+/// Flag fetch volumes this many standard deviations from a source's history
+const ANOMALY_SIGMA: f64 = 3.0;
+
+/// Fetches of history needed before a source's volume is judged
+const MIN_VOLUME_HISTORY: usize = 14;
+
+/// Mean new articles per fetch in the range, and over the 90 days before it
+#[derive(Clone, Debug, PartialEq)]
+struct Volume {
+    mean: f64,
+    history_mean: f64,
+    history_sd: f64,
+}
+
+impl Volume {
+    /// Compare the range's fetches to the history; `None` without enough of either
+    fn from_counts(period: &[i64], history: &[i64]) -> Option<Self> {
+        if period.is_empty() || history.len() < MIN_VOLUME_HISTORY {
+            return None;
+        }
+        let mean = |v: &[i64]| v.iter().sum::<i64>() as f64 / v.len() as f64;
+        let history_mean = mean(history);
+        let variance = history
+            .iter()
+            .map(|&n| (n as f64 - history_mean).powi(2))
+            .sum::<f64>()
+            / history.len() as f64;
+        Some(Self {
+            mean: mean(period),
+            history_mean,
+            history_sd: variance.sqrt(),
+        })
+    }
+
+    /// Deviations from the historical mean; the deviation is floored at one
+    /// article so steady feeds aren't flagged for tiny changes
+    fn z_score(&self) -> f64 {
+        (self.mean - self.history_mean) / self.history_sd.max(1.0)
+    }
+
+    fn is_anomaly(&self) -> bool {
+        self.z_score().abs() >= ANOMALY_SIGMA
+    }
+}
+
+/// Fetch volume per source: new articles per successful fetch within the
+/// range compared with the 90 days before it
+fn fetch_volumes(
+    conn: &Connection,
+    range: &db::DateRange,
+) -> Result<BTreeMap<String, Volume>, String> {
+    if !db::column_exists(conn, "source_health", "new_articles")? {
+        return Ok(BTreeMap::new());
+    }
+    let mut stmt = conn
+        .prepare(
+            "SELECT source_id, recorded_at >= ?1, new_articles FROM source_health
+             WHERE success = 1 AND new_articles IS NOT NULL
+               AND recorded_at >= date(?1, '-90 days') AND recorded_at < date(?2, '+1 day')",
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    let rows = stmt
+        .query_map([&range.from, &range.to], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, bool>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok());
+    let mut counts: BTreeMap<String, (Vec<i64>, Vec<i64>)> = BTreeMap::new();
+    for (source_id, in_range, n) in rows {
+        let (period, history) = counts.entry(source_id).or_default();
+        if in_range { period } else { history }.push(n);
+    }
+    Ok(counts
+        .into_iter()
+        .filter_map(|(id, (period, history))| {
+            Volume::from_counts(&period, &history).map(|v| (id, v))
+        })
+        .collect())
+}
+
+/// Articles keyword filters dropped per source within the range
+fn filtered_articles(
+    conn: &Connection,
+    range: &db::DateRange,
+) -> Result<BTreeMap<String, i64>, String> {
+    if !db::column_exists(conn, "source_health", "filtered_articles")? {
+        return Ok(BTreeMap::new());
+    }
+    let mut stmt = conn
+        .prepare(
+            "SELECT source_id, SUM(filtered_articles) FROM source_health
+             WHERE filtered_articles > 0
+               AND recorded_at >= ?1 AND recorded_at < date(?2, '+1 day')
+             GROUP BY source_id",
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    let rows = stmt
+        .query_map([&range.from, &range.to], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(rows)
+}
 
-        stmt.query_map([days], |row| {
-            let source_id: String = row.get(0)?;
-            let total: i64 = row.get(1)?;
-            let successes: i64 = row.get(2)?;
+/// Nearest-rank percentile of an ascending slice
+fn percentile(sorted: &[i64], p: f64) -> Option<i64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+/// Fetch stats data from database for the requested window
+fn fetch_stats_data(
+    conn: &Connection,
+    query: &StatsQuery,
+    slo: Option<&slo::SloConfig>,
+) -> Result<StatsData, (StatusCode, String)> {
+    let range = db::DateRange::resolve(
+        conn,
+        query.days.unwrap_or(30),
+        query.from.as_deref(),
+        query.to.as_deref(),
+    )
+    .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let previous_range = range
+        .previous(conn)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let bounds = [&range.from, &range.to];
+
+    // Source health: success rate per source within the range (whole weeks
+    // come from the weekly rollup when available)
+    let mut source_health: Vec<SourceHealth> = rollups::source_health_totals(conn, &range)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .into_iter()
+        .map(|(source_id, total, successes)| {
             let rate = if total > 0 {
                 (successes as f64 / total as f64 * 100.0).round()
             } else {
                 0.0
             };
-            Ok(SourceHealth {
+            SourceHealth {
                 source_id,
                 total_fetches: total,
                 successes,
                 success_rate_pct: rate,
-            })
+                p50_ms: None,
+                p95_ms: None,
+                daily_success: Vec::new(),
+                last_new_item_at: None,
+                days_since_new_item: None,
+                volume: None,
+                filtered_articles: 0,
+            }
         })
-        .map_err(|e| {
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Query error: {e}"),
-            )
-        })?
-        .filter_map(|r| r.ok())
-        .collect()
-    };
+        .collect();
+    let latencies =
+        fetch_latencies(conn, &range).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let mut activity = source_activity(conn).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let mut volumes =
+        fetch_volumes(conn, &range).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let filtered =
+        filtered_articles(conn, &range).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let mut daily = if range.days >= rollups::WEEKLY_TREND_DAYS {
+        rollups::weekly_success(conn, &range)
+    } else {
+        daily_success(conn, &range)
+    }
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    for h in &mut source_health {
+        if let Some(ms) = latencies.get(&h.source_id) {
+            h.p50_ms = percentile(ms, 50.0);
+            h.p95_ms = percentile(ms, 95.0);
+        }
+        h.daily_success = daily.remove(&h.source_id).unwrap_or_default();
+        h.volume = volumes.remove(&h.source_id);
+        h.filtered_articles = filtered.get(&h.source_id).copied().unwrap_or_default();
+        if let Some((at, days)) = activity.remove(&h.source_id) {
+            h.last_new_item_at = Some(at);
+            h.days_since_new_item = Some(days);
+        }
+    }
 
     // Source usage: how often each source appears in digests, by tier
-    let source_usage: Vec<SourceUsage> = {
+    let source_usage: Vec<SourceUsage> = rollups::source_usage(conn, &range)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+        .into_iter()
+        .map(|(source_id, tier, count)| SourceUsage {
+            source_id,
+            tier,
+            count,
+        })
+        .collect();
+
+    // Topic mix per week, from the selection pass's topic tags
+    let topic_coverage =
+        topics::coverage(conn, &range).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    // Run duration and usage columns are added by newer pipelines
+    let has_usage = db::column_exists(conn, "digest_runs", "cost_usd")
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let usage_columns = if has_usage {
+        "duration_ms, input_tokens, output_tokens, cost_usd"
+    } else {
+        "NULL, NULL, NULL, NULL"
+    };
+
+    // Recent runs: last 10 digest runs within the range
+    let recent_runs: Vec<DigestRun> = {
         let mut stmt = conn
-            .prepare(
-                "SELECT source_id, tier, COUNT(*) as count
-                 FROM shown_narratives
-                 WHERE source_id IS NOT NULL
-                   AND shown_at >= datetime('now', '-' || ?1 || ' days')
-                 GROUP BY source_id, tier
-                 ORDER BY count DESC",
-            )
+            .prepare(&format!(
+                "SELECT run_at, articles_fetched, articles_emailed, {usage_columns}
+                 FROM digest_runs
+                 WHERE run_at >= ?1 AND run_at < date(?2, '+1 day')
+                 ORDER BY run_at DESC
+                 LIMIT 10"
+            ))
             .map_err(|e| {
                 (
                     StatusCode::INTERNAL_SERVER_ERROR,
@@ -423,11 +1115,15 @@ fn fetch_stats_data(db_path: &str, days: u32) -> Result<StatsData, (StatusCode,
                 )
             })?;
 
-        stmt.query_map([days], |row| {
-            Ok(SourceUsage {
-                source_id: row.get(0)?,
-                tier: row.get(1)?,
-                count: row.get(2)?,
+        stmt.query_map(bounds, |row| {
+            Ok(DigestRun {
+                run_at: row.get(0)?,
+                articles_fetched: row.get(1)?,
+                articles_emailed: row.get(2)?,
+                duration_ms: row.get(3)?,
+                input_tokens: row.get(4)?,
+                output_tokens: row.get(5)?,
+                cost_usd: row.get(6)?,
             })
         })
         .map_err(|e| {
@@ -440,14 +1136,18 @@ fn fetch_stats_data(db_path: &str, days: u32) -> Result<StatsData, (StatusCode,
         .collect()
     };
 
-    // Recent runs: last 10 digest runs
-    let recent_runs: Vec<DigestRun> = {
+    // The same runs stage by stage, with those that failed or were killed
+    let run_timeline =
+        timeline::recent(conn, &range).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    // Articles fetched per run across the whole range, for the trend line
+    let run_articles: Vec<f64> = {
         let mut stmt = conn
             .prepare(
-                "SELECT run_at, articles_fetched, articles_emailed
+                "SELECT articles_fetched
                  FROM digest_runs
-                 ORDER BY run_at DESC
-                 LIMIT 10",
+                 WHERE run_at >= ?1 AND run_at < date(?2, '+1 day')
+                 ORDER BY run_at",
             )
             .map_err(|e| {
                 (
@@ -456,82 +1156,273 @@ fn fetch_stats_data(db_path: &str, days: u32) -> Result<StatsData, (StatusCode,
                 )
             })?;
 
-        stmt.query_map([], |row| {
-            Ok(DigestRun {
-                run_at: row.get(0)?,
-                articles_fetched: row.get(1)?,
-                articles_emailed: row.get(2)?,
-            })
-        })
+        stmt.query_map(bounds, |row| row.get::<_, i64>(0))
+            .map_err(|e| {
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!("Query error: {e}"),
+                )
+            })?
+            .filter_map(|r| r.ok())
+            .map(|n| n as f64)
+            .collect()
+    };
+
+    // When runs happened, to spot schedule drift
+    let publish_times =
+        publish_times(conn, &range).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    let total_cost_usd: f64 = if has_usage {
+        conn.query_row(
+            "SELECT COALESCE(SUM(cost_usd), 0) FROM digest_runs
+             WHERE run_at >= ?1 AND run_at < date(?2, '+1 day')",
+            bounds,
+            |row| row.get(0),
+        )
         .map_err(|e| {
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
                 format!("Query error: {e}"),
             )
         })?
-        .filter_map(|r| r.ok())
-        .collect()
+    } else {
+        0.0
     };
 
-    Ok(StatsData {
-        period_days: days,
-        source_health,
-        source_usage,
-        recent_runs,
-    })
-}
+    // Subscriber growth: daily signups and unsubscribes from local subscription events
+    let subscriber_growth =
+        subscribers::growth(conn, &range).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
 
-/// Stats JSON endpoint
-async fn stats_json(
-    State(state): State<Arc<AppState>>,
-    Query(query): Query<StatsQuery>,
-) -> Result<axum::Json<serde_json::Value>, (StatusCode, String)> {
-    let days = query.days.unwrap_or(30);
-    let data = fetch_stats_data(&state.db_path, days)?;
+    // Unsubscribe reasons from the exit survey
+    let unsubscribe_reasons = subscribers::feedback_counts(conn, &range)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
 
-    let source_health: Vec<serde_json::Value> = data
-        .source_health
-        .iter()
-        .map(|h| {
-            serde_json::json!({
-                "source_id": h.source_id,
-                "total_fetches": h.total_fetches,
-                "successes": h.successes,
-                "success_rate_pct": h.success_rate_pct
-            })
-        })
-        .collect();
+    // Email engagement: open and click-through rates per sent digest
+    let engagement =
+        engagement::per_digest(conn, &range).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
 
-    let source_usage: Vec<serde_json::Value> = data
-        .source_usage
-        .iter()
-        .map(|u| {
-            serde_json::json!({
-                "source_id": u.source_id,
-                "tier": u.tier,
-                "count": u.count
-            })
-        })
-        .collect();
+    // Most-read digests on the web archive
+    let top_viewed = pageviews::top_viewed(conn, &range, 10)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
 
-    let recent_runs: Vec<serde_json::Value> = data
-        .recent_runs
-        .iter()
-        .map(|r| {
-            serde_json::json!({
-                "run_at": r.run_at,
-                "articles_fetched": r.articles_fetched,
-                "articles_emailed": r.articles_emailed
-            })
+    // Outbound links and publishers readers clicked through to
+    let top_links =
+        links::top_links(conn, &range, 10).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let top_domains =
+        links::top_domains(conn, &range, 10).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    // On-time delivery against the configured deadline
+    let delivery = slo
+        .map(|config| slo::delivery(conn, &range, config))
+        .transpose()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    // Current list size by status
+    let subscriber_counts =
+        subscribers::counts(conn).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    // Server uptime from the periodic self-check
+    let uptime =
+        health::uptime(conn, &range).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+    Ok(StatsData {
+        range,
+        previous_range,
+        source_health,
+        source_usage,
+        topic_coverage,
+        recent_runs,
+        run_timeline,
+        run_articles,
+        publish_times,
+        total_cost_usd,
+        subscriber_growth,
+        unsubscribe_reasons,
+        engagement,
+        top_viewed,
+        uptime,
+        subscriber_counts,
+        top_links,
+        top_domains,
+        delivery,
+    })
+}
+
+/// Signed change against the previous period, coloured by whether it's an
+/// improvement (e.g. "+3pp", "−$0.40")
+fn delta_html(change: f64, decimals: usize, unit: &str, higher_is_better: bool) -> String {
+    let magnitude = format!("{:.*}", decimals, change.abs());
+    if magnitude.trim_start_matches(['0', '.']).is_empty() {
+        return format!(r#"<span class="delta">±0{unit}</span>"#);
+    }
+    let class = if (change > 0.0) == higher_is_better {
+        "good"
+    } else {
+        "bad"
+    };
+    let sign = if change > 0.0 { "+" } else { "−" };
+    match unit {
+        "$" => format!(r#"<span class="delta {class}">{sign}${magnitude}</span>"#),
+        _ => format!(r#"<span class="delta {class}">{sign}{magnitude}{unit}</span>"#),
+    }
+}
+
+/// Join fields into one CSV line, quoting per RFC 4180 where needed
+fn csv_row<I, S>(fields: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let fields: Vec<String> = fields
+        .into_iter()
+        .map(|f| {
+            let f = f.as_ref();
+            if f.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", f.replace('"', "\"\""))
+            } else {
+                f.to_string()
+            }
         })
         .collect();
+    fields.join(",") + "\r\n"
+}
+
+/// Stats CSV export of one underlying table, for spreadsheets
+async fn stats_csv(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<StatsQuery>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let table = query.table.as_deref().unwrap_or("health");
+    if !["health", "usage", "runs", "stages", "topics"].contains(&table) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "table must be health, usage, runs, stages, or topics".into(),
+        ));
+    }
+    let data = state.stats_data(&query).await?;
+
+    let mut csv = String::new();
+    match table {
+        "health" => {
+            csv += &csv_row([
+                "source_id",
+                "total_fetches",
+                "successes",
+                "success_rate_pct",
+                "p50_ms",
+                "p95_ms",
+                "filtered_articles",
+            ]);
+            for h in &data.source_health {
+                csv += &csv_row([
+                    h.source_id.clone(),
+                    h.total_fetches.to_string(),
+                    h.successes.to_string(),
+                    h.success_rate_pct.to_string(),
+                    h.p50_ms.map_or(String::new(), |ms| ms.to_string()),
+                    h.p95_ms.map_or(String::new(), |ms| ms.to_string()),
+                    h.filtered_articles.to_string(),
+                ]);
+            }
+        }
+        "usage" => {
+            csv += &csv_row(["source_id", "tier", "count"]);
+            for u in &data.source_usage {
+                csv += &csv_row([u.source_id.clone(), u.tier.clone(), u.count.to_string()]);
+            }
+        }
+        "topics" => {
+            csv += &csv_row(["week", "topic", "count"]);
+            let coverage = &data.topic_coverage;
+            for (i, week) in coverage.weeks.iter().enumerate() {
+                for t in &coverage.topics {
+                    csv += &csv_row([week.clone(), t.topic.clone(), t.weekly[i].to_string()]);
+                }
+            }
+        }
+        "stages" => {
+            csv += &csv_row([
+                "run_started_at",
+                "outcome",
+                "stage",
+                "started_at",
+                "duration_ms",
+                "items",
+                "error",
+            ]);
+            for r in &data.run_timeline {
+                for st in &r.stages {
+                    csv += &csv_row([
+                        r.started_at.clone(),
+                        r.outcome().to_string(),
+                        st.stage.clone(),
+                        st.started_at.clone(),
+                        st.duration_ms.to_string(),
+                        st.items.map_or(String::new(), |n| n.to_string()),
+                        st.error.clone().unwrap_or_default(),
+                    ]);
+                }
+            }
+        }
+        _ => {
+            csv += &csv_row([
+                "run_at",
+                "articles_fetched",
+                "articles_emailed",
+                "duration_ms",
+                "input_tokens",
+                "output_tokens",
+                "cost_usd",
+            ]);
+            let opt = |v: Option<String>| v.unwrap_or_default();
+            for r in &data.recent_runs {
+                csv += &csv_row([
+                    r.run_at.clone(),
+                    r.articles_fetched.to_string(),
+                    r.articles_emailed.to_string(),
+                    opt(r.duration_ms.map(|v| v.to_string())),
+                    opt(r.input_tokens.map(|v| v.to_string())),
+                    opt(r.output_tokens.map(|v| v.to_string())),
+                    opt(r.cost_usd.map(|v| v.to_string())),
+                ]);
+            }
+        }
+    }
+
+    let disposition = format!(
+        "attachment; filename=\"stats-{table}-{}-{}.csv\"",
+        data.range.from, data.range.to
+    );
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        csv,
+    ))
+}
 
-    Ok(axum::Json(serde_json::json!({
-        "period_days": data.period_days,
-        "source_health": source_health,
-        "source_usage": source_usage,
-        "recent_runs": recent_runs
-    })))
+/// Stats JSON endpoint
+async fn stats_json(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<StatsQuery>,
+) -> Result<axum::response::Response, (StatusCode, String)> {
+    let data = state.stats_data(&query).await?;
+    let previous = state.previous_stats(&query, &data).await?;
+    let stats = stats_api::Stats::new(&data, previous.as_deref(), state.stale_source_days);
+    match query.version {
+        None | Some(stats_api::VERSION) => Ok(axum::Json(stats_api::Envelope {
+            version: stats_api::VERSION,
+            data: stats,
+        })
+        .into_response()),
+        // Deprecated: the unwrapped body served before versioning
+        Some(1) => Ok(axum::Json(stats).into_response()),
+        Some(_) => Err((
+            StatusCode::BAD_REQUEST,
+            format!("version must be 1 or {}", stats_api::VERSION),
+        )),
+    }
 }
 
 /// Stats HTML dashboard
@@ -540,17 +1431,88 @@ async fn stats_html(
     Query(query): Query<StatsQuery>,
 ) -> Result<Html<String>, (StatusCode, String)> {
     let days = query.days.unwrap_or(30);
-    let data = fetch_stats_data(&state.db_path, days)?;
+    let data = state.stats_data(&query).await?;
+    let previous = state.previous_stats(&query, &data).await?;
+    let previous = previous.as_deref();
+    let comparing = previous.is_some();
     let name = &state.digest_name;
+    let (range_from, range_to) = (&data.range.from, &data.range.to);
+    // Preset periods only highlight when no explicit range is in effect
+    let custom_range = query.from.is_some() || query.to.is_some();
+    let (mut period_text, range_query) = if custom_range {
+        (
+            format!("from {range_from} to {range_to}"),
+            format!("from={range_from}&amp;to={range_to}"),
+        )
+    } else {
+        (format!("over the last {days} days"), format!("days={days}"))
+    };
+    // Comparison sticks across period changes until toggled off
+    let (compare_query, compare_input, compare_toggle) = if comparing {
+        (
+            "&amp;compare=previous",
+            r#"<input type="hidden" name="compare" value="previous">"#,
+            format!(r#"<a href="/stats?{range_query}" class="active">Compare with previous</a>"#),
+        )
+    } else {
+        (
+            "",
+            "",
+            format!(
+                r#"<a href="/stats?{range_query}&amp;compare=previous">Compare with previous</a>"#
+            ),
+        )
+    };
+    if let Some(p) = previous {
+        period_text += &format!(", compared with {} to {}", p.range.from, p.range.to);
+    }
+    // Extra "vs Previous" column, present only while comparing
+    let vs_header = if comparing {
+        "<th>vs Previous</th>"
+    } else {
+        ""
+    };
+    let vs_cell = |delta: Option<String>| {
+        if comparing {
+            format!("<td>{}</td>", delta.unwrap_or_else(|| "new".into()))
+        } else {
+            String::new()
+        }
+    };
+    let colspan = |columns: usize| columns + usize::from(comparing);
+    let active = |preset: u32| {
+        if !custom_range && days == preset {
+            " class=\"active\""
+        } else {
+            ""
+        }
+    };
     let css_link = state
         .css_url
         .as_ref()
         .map(|url| format!(r#"<link rel="stylesheet" href="{url}">"#))
         .unwrap_or_default();
 
+    let sources_link = if state.sources.is_some() {
+        r#"<p class="subtitle"><a href="/sources">Configured sources</a></p>"#
+    } else {
+        ""
+    };
+
     // Build source health table rows
+    let previous_rates: std::collections::HashMap<&str, f64> = previous
+        .map(|p| {
+            p.source_health
+                .iter()
+                .map(|h| (h.source_id.as_str(), h.success_rate_pct))
+                .collect()
+        })
+        .unwrap_or_default();
     let health_rows: String = if data.source_health.is_empty() {
-        r#"<tr><td colspan="4" class="empty">No data yet</td></tr>"#.to_string()
+        format!(
+            r#"<tr><td colspan="{}" class="empty">No data yet</td></tr>"#,
+            colspan(7)
+        )
     } else {
         data.source_health
             .iter()
@@ -562,14 +1524,59 @@ async fn stats_html(
                 } else {
                     "bad"
                 };
+                let ms = |v: Option<i64>| v.map_or("–".to_string(), |ms| format!("{ms} ms"));
+                let dormant = if h.is_dormant(state.stale_source_days) {
+                    format!(
+                        r#" <span class="warn" title="Last new item {}">dormant {:.0}d</span>"#,
+                        h.last_new_item_at.as_deref().unwrap_or_default(),
+                        h.days_since_new_item.unwrap_or_default()
+                    )
+                } else {
+                    String::new()
+                };
+                let anomaly = match &h.volume {
+                    Some(v) if v.is_anomaly() => format!(
+                        r#" <span class="bad" title="{:.1} new articles per fetch vs {:.1} ± {:.1} usually">volume {}</span>"#,
+                        v.mean,
+                        v.history_mean,
+                        v.history_sd,
+                        if v.z_score() > 0.0 { "spike" } else { "drop" }
+                    ),
+                    _ => String::new(),
+                };
+                let filtered = if h.filtered_articles > 0 {
+                    format!(
+                        r#" <span class="muted" title="Articles its keyword filters dropped">{} filtered</span>"#,
+                        h.filtered_articles
+                    )
+                } else {
+                    String::new()
+                };
+                let vs = vs_cell(
+                    previous_rates
+                        .get(h.source_id.as_str())
+                        .map(|rate| delta_html(h.success_rate_pct - rate, 0, "pp", true)),
+                );
                 format!(
                     r#"<tr>
-                        <td>{}</td>
+                        <td>{}{dormant}{anomaly}{filtered}</td>
                         <td>{}</td>
                         <td>{}</td>
                         <td class="{}">{:.0}%</td>
+                        {vs}
+                        <td>{}</td>
+                        <td>{}</td>
+                        <td class="{}">{}</td>
                     </tr>"#,
-                    h.source_id, h.total_fetches, h.successes, status_class, h.success_rate_pct
+                    h.source_id,
+                    h.total_fetches,
+                    h.successes,
+                    status_class,
+                    h.success_rate_pct,
+                    ms(h.p50_ms),
+                    ms(h.p95_ms),
+                    status_class,
+                    charts::sparkline_between(&h.daily_success, 0.0, 100.0, 80, 20)
                 )
             })
             .collect()
@@ -588,6 +1595,10 @@ async fn stats_html(
             _ => entry.2 += u.count,
         }
     }
+    let mut previous_usage: std::collections::HashMap<&str, i64> = std::collections::HashMap::new();
+    for u in previous.iter().flat_map(|p| &p.source_usage) {
+        *previous_usage.entry(u.source_id.as_str()).or_default() += u.count;
+    }
     let mut usage_sorted: Vec<_> = usage_by_source.into_iter().collect();
     usage_sorted.sort_by(|a, b| {
         let total_a = a.1.0 + a.1.1 + a.1.2;
@@ -596,12 +1607,20 @@ async fn stats_html(
     });
 
     let usage_rows: String = if usage_sorted.is_empty() {
-        r#"<tr><td colspan="5" class="empty">No data yet</td></tr>"#.to_string()
+        format!(
+            r#"<tr><td colspan="{}" class="empty">No data yet</td></tr>"#,
+            colspan(5)
+        )
     } else {
         usage_sorted
             .iter()
             .map(|(source_id, (must, should, other))| {
                 let total = must + should + other;
+                let vs = vs_cell(
+                    previous_usage
+                        .get(source_id.as_str())
+                        .map(|prev| delta_html((total - prev) as f64, 0, "", true)),
+                );
                 format!(
                     r#"<tr>
                         <td>{}</td>
@@ -609,6 +1628,7 @@ async fn stats_html(
                         <td>{}</td>
                         <td>{}</td>
                         <td><strong>{}</strong></td>
+                        {vs}
                     </tr>"#,
                     source_id, must, should, other, total
                 )
@@ -616,20 +1636,333 @@ async fn stats_html(
             .collect()
     };
 
+    // Topic coverage: overall share plus the weekly share trend
+    let coverage = &data.topic_coverage;
+    let topic_total: i64 = coverage.topics.iter().map(|t| t.total()).sum();
+    let previous_shares: std::collections::HashMap<&str, f64> = previous
+        .map(|p| {
+            let c = &p.topic_coverage;
+            let total: i64 = c.topics.iter().map(|t| t.total()).sum();
+            c.topics
+                .iter()
+                .map(|t| (t.topic.as_str(), t.total() as f64 / total as f64 * 100.0))
+                .collect()
+        })
+        .unwrap_or_default();
+    let topic_rows: String = if coverage.topics.is_empty() {
+        format!(
+            r#"<tr><td colspan="{}" class="empty">No topic tags yet</td></tr>"#,
+            colspan(4)
+        )
+    } else {
+        coverage
+            .topics
+            .iter()
+            .map(|t| {
+                let share = coverage.weekly_share(t);
+                let overall = t.total() as f64 / topic_total as f64 * 100.0;
+                let vs = vs_cell(
+                    previous_shares
+                        .get(t.topic.as_str())
+                        .map(|prev| delta_html(overall - prev, 0, "pp", true)),
+                );
+                format!(
+                    r#"<tr>
+                        <td>{}</td>
+                        <td>{}</td>
+                        <td>{:.0}%</td>
+                        {vs}
+                        <td title="{}">{}</td>
+                    </tr>"#,
+                    escape_html(&t.topic),
+                    t.total(),
+                    overall,
+                    coverage
+                        .weeks
+                        .iter()
+                        .zip(&share)
+                        .map(|(week, pct)| format!("{week}: {pct:.0}%"))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    charts::sparkline_between(&share, 0.0, 100.0, 80, 20)
+                )
+            })
+            .collect()
+    };
+
     // Build recent runs table rows
     let runs_rows: String = if data.recent_runs.is_empty() {
-        r#"<tr><td colspan="3" class="empty">No runs yet</td></tr>"#.to_string()
+        r#"<tr><td colspan="6" class="empty">No runs yet</td></tr>"#.to_string()
     } else {
         data.recent_runs
             .iter()
             .map(|r| {
+                let duration = r.duration_ms.map_or("–".to_string(), |ms| {
+                    format!("{}m {:02}s", ms / 60_000, ms / 1000 % 60)
+                });
+                let tokens = match (r.input_tokens, r.output_tokens) {
+                    (Some(i), Some(o)) => format!("{i} / {o}"),
+                    _ => "–".to_string(),
+                };
+                let cost = r.cost_usd.map_or("–".to_string(), |c| format!("${c:.2}"));
+                format!(
+                    r#"<tr>
+                        <td>{}</td>
+                        <td>{}</td>
+                        <td>{}</td>
+                        <td>{}</td>
+                        <td>{}</td>
+                        <td>{}</td>
+                    </tr>"#,
+                    r.run_at, r.articles_fetched, r.articles_emailed, duration, tokens, cost
+                )
+            })
+            .collect()
+    };
+    let total_cost = format!("${:.2}", data.total_cost_usd);
+    let cost_delta = previous.map_or(String::new(), |p| {
+        delta_html(data.total_cost_usd - p.total_cost_usd, 2, "$", false)
+    });
+
+    let runs_sparkline = charts::sparkline(&data.run_articles, 240, 32);
+
+    // Build run timeline rows: each attempt's stages as a bar, failures in red
+    let minutes = |ms: i64| format!("{}m {:02}s", ms / 60_000, ms / 1000 % 60);
+    let timeline_rows: String = if data.run_timeline.is_empty() {
+        r#"<tr><td colspan="4" class="empty">No runs yet</td></tr>"#.to_string()
+    } else {
+        data.run_timeline
+            .iter()
+            .map(|r| {
+                let segments: Vec<(String, i64, bool)> = r
+                    .stages
+                    .iter()
+                    .map(|s| {
+                        let mut label = format!("{}: {}", s.stage, minutes(s.duration_ms));
+                        if let Some(n) = s.items {
+                            label += &format!(", {n} items");
+                        }
+                        if let Some(e) = &s.error {
+                            label += &format!(" ({e})");
+                        }
+                        (escape_html(&label), s.duration_ms, s.error.is_some())
+                    })
+                    .collect();
+                let outcome = match r.failed() {
+                    Some(s) => format!(
+                        r#"<span class="bad" title="{}">failed at {}</span>"#,
+                        escape_html(s.error.as_deref().unwrap_or_default()),
+                        escape_html(&s.stage)
+                    ),
+                    None => r.outcome().to_string(),
+                };
+                format!(
+                    r#"<tr>
+                        <td>{}</td>
+                        <td>{}</td>
+                        <td>{}</td>
+                        <td>{}</td>
+                    </tr>"#,
+                    r.started_at,
+                    outcome,
+                    charts::stages(&segments, 240, 12),
+                    minutes(r.duration_ms())
+                )
+            })
+            .collect()
+    };
+    let heatmap_rows: Vec<(&str, [i64; 24])> =
+        WEEKDAYS.iter().copied().zip(data.publish_times).collect();
+    let publish_heatmap = charts::heatmap(&heatmap_rows, 14);
+
+    let delivery_panel = data.delivery.as_ref().map_or(String::new(), |b| {
+        let class = if b.remaining_pct > 50.0 {
+            "good"
+        } else if b.remaining_pct > 0.0 {
+            "warn"
+        } else {
+            "bad"
+        };
+        let deadline = state.slo.as_ref().map_or("", |c| c.deadline.as_str());
+        format!(
+            r#"<section>
+      <h2>On-Time Delivery</h2>
+      <div class="summary">
+        <span><strong class="{class}">{attained:.1}%</strong>{delta} of {days} days by {deadline} UTC (target {target}%)</span>
+        <span>{late} late · {missed} missed</span>
+      </div>
+      <div class="summary">
+        <div class="budget-bar" title="Error budget remaining"><div class="{class}" style="width: {bar:.0}%"></div></div>
+        <span><strong class="{class}">{remaining:.0}%</strong> error budget left</span>
+        {burn}
+      </div>
+    </section>"#,
+            attained = b.attained_pct(),
+            days = b.days,
+            target = b.target_pct,
+            late = b.late,
+            missed = b.missed,
+            bar = b.remaining_pct.clamp(0.0, 100.0),
+            remaining = b.remaining_pct,
+            burn = charts::sparkline_between(&b.burn_down, 0.0, 100.0, 160, 24),
+            delta = previous
+                .and_then(|p| p.delivery.as_ref())
+                .map_or(String::new(), |prev| {
+                    delta_html(b.attained_pct() - prev.attained_pct(), 1, "pp", true)
+                }),
+        )
+    });
+
+    let uptime_summary = data.uptime.map_or(String::new(), |(checks, pct)| {
+        let class = if pct >= 99.9 {
+            "good"
+        } else if pct >= 99.0 {
+            "warn"
+        } else {
+            "bad"
+        };
+        let delta = previous
+            .and_then(|p| p.uptime)
+            .map_or(String::new(), |(_, prev)| {
+                delta_html(pct - prev, 2, "pp", true)
+            });
+        format!(
+            r#"<div class="summary">
+      <span><strong class="{class}">{pct:.2}%</strong>{delta} server uptime</span>
+      <span>{checks} self-checks · <a href="/health/history">history</a></span>
+    </div>"#
+        )
+    });
+
+    // Subscriber growth: headline numbers, sparkline of daily totals, and active days
+    let growth = &data.subscriber_growth;
+    let subscriber_total = growth.last().map(|g| g.total).unwrap_or(0);
+    let subscriber_signups: i64 = growth.iter().map(|g| g.signups).sum();
+    let subscriber_unsubscribes: i64 = growth.iter().map(|g| g.unsubscribes).sum();
+    let signups_delta = previous.map_or(String::new(), |p| {
+        let prev: i64 = p.subscriber_growth.iter().map(|g| g.signups).sum();
+        delta_html((subscriber_signups - prev) as f64, 0, "", true)
+    });
+    let growth_sparkline = charts::sparkline(
+        &growth.iter().map(|g| g.total as f64).collect::<Vec<_>>(),
+        240,
+        32,
+    );
+    let growth_rows: String = if growth.iter().all(|g| g.signups == 0 && g.unsubscribes == 0) {
+        r#"<tr><td colspan="4" class="empty">No subscription activity</td></tr>"#.to_string()
+    } else {
+        growth
+            .iter()
+            .rev()
+            .filter(|g| g.signups > 0 || g.unsubscribes > 0)
+            .map(|g| {
+                format!(
+                    r#"<tr>
+                        <td>{}</td>
+                        <td class="good">+{}</td>
+                        <td class="bad">−{}</td>
+                        <td>{}</td>
+                    </tr>"#,
+                    g.date, g.signups, g.unsubscribes, g.total
+                )
+            })
+            .collect()
+    };
+
+    // Unsubscribe survey responses
+    let reason_rows: String = if data.unsubscribe_reasons.is_empty() {
+        r#"<tr><td colspan="2" class="empty">No feedback yet</td></tr>"#.to_string()
+    } else {
+        data.unsubscribe_reasons
+            .iter()
+            .map(|(reason, count)| {
+                let label = subscribers::FEEDBACK_REASONS
+                    .iter()
+                    .find(|(value, _)| value == reason)
+                    .map(|(_, label)| *label)
+                    .unwrap_or(reason);
                 format!(
                     r#"<tr>
                         <td>{}</td>
                         <td>{}</td>
+                    </tr>"#,
+                    escape_html(label),
+                    count
+                )
+            })
+            .collect()
+    };
+
+    // Email engagement per digest
+    let engagement_rows: String = if data.engagement.is_empty() {
+        r#"<tr><td colspan="5" class="empty">No tracked sends yet</td></tr>"#.to_string()
+    } else {
+        data.engagement
+            .iter()
+            .map(|e| {
+                format!(
+                    r#"<tr>
+                        <td><a href="/{0}">{0}</a></td>
+                        <td>{1}</td>
+                        <td>{2}</td>
+                        <td>{3:.1}%</td>
+                        <td>{4:.1}%</td>
+                    </tr>"#,
+                    e.digest_date, e.recipients, e.unique_opens, e.open_rate_pct, e.click_rate_pct
+                )
+            })
+            .collect()
+    };
+
+    // Most-read digests on the web
+    let viewed_rows: String = if data.top_viewed.is_empty() {
+        r#"<tr><td colspan="3" class="empty">No pageviews yet</td></tr>"#.to_string()
+    } else {
+        data.top_viewed
+            .iter()
+            .map(|v| {
+                format!(
+                    r#"<tr>
+                        <td><a href="/{0}">{0}</a></td>
+                        <td>{1}</td>
+                        <td>{2}</td>
+                    </tr>"#,
+                    v.digest_date, v.views, v.readers
+                )
+            })
+            .collect()
+    };
+
+    let link_rows: String = if data.top_links.is_empty() {
+        r#"<tr><td colspan="2" class="empty">No clicks yet</td></tr>"#.to_string()
+    } else {
+        data.top_links
+            .iter()
+            .map(|l| {
+                let url = escape_html(&l.url);
+                let label = l.narrative.as_deref().map_or(url.clone(), escape_html);
+                format!(
+                    r#"<tr>
+                        <td><a href="{url}" rel="noopener">{label}</a></td>
+                        <td>{}</td>
+                    </tr>"#,
+                    l.clicks
+                )
+            })
+            .collect()
+    };
+    let domain_rows: String = if data.top_domains.is_empty() {
+        r#"<tr><td colspan="2" class="empty">No clicks yet</td></tr>"#.to_string()
+    } else {
+        data.top_domains
+            .iter()
+            .map(|(domain, clicks)| {
+                format!(
+                    r#"<tr>
                         <td>{}</td>
+                        <td>{clicks}</td>
                     </tr>"#,
-                    r.run_at, r.articles_fetched, r.articles_emailed
+                    escape_html(domain)
                 )
             })
             .collect()
@@ -662,6 +1995,26 @@ async fn stats_html(
     .period-select {{
       margin-bottom: 2rem;
     }}
+    .range-form {{
+      display: inline-flex;
+      gap: 0.5rem;
+      align-items: center;
+      margin-top: 0.5rem;
+      font-size: 0.875rem;
+      color: var(--text-secondary);
+    }}
+    .range-form input,
+    .range-form button {{
+      padding: 0.4rem 0.6rem;
+      background: var(--bg-card);
+      border: 1px solid var(--border-white-subtle);
+      border-radius: 0.5rem;
+      color: var(--text-primary);
+      font: inherit;
+    }}
+    .range-form button {{
+      cursor: pointer;
+    }}
     .period-select a {{
       display: inline-block;
       padding: 0.5rem 1rem;
@@ -720,6 +2073,51 @@ async fn stats_html(
     .good {{ color: var(--accent-green, #22c55e); }}
     .warn {{ color: var(--accent-yellow, #eab308); }}
     .bad {{ color: var(--ruby-red); }}
+    .summary {{
+      display: flex;
+      align-items: center;
+      gap: 1.5rem;
+      margin-bottom: 1rem;
+      color: var(--text-secondary);
+    }}
+    .summary strong {{
+      color: var(--text-primary);
+      font-size: 1.25rem;
+    }}
+    .sparkline {{
+      color: var(--ruby-red);
+    }}
+    .budget-bar {{
+      width: 160px;
+      height: 8px;
+      border-radius: 4px;
+      background: var(--border, #e5e5e5);
+      overflow: hidden;
+    }}
+    .budget-bar div {{
+      height: 100%;
+      background: currentColor;
+    }}
+    .delta {{
+      margin-left: 0.4rem;
+      font-size: 0.8em;
+      color: var(--text-tertiary);
+    }}
+    .delta.good {{ color: var(--accent-green, #22c55e); }}
+    .delta.bad {{ color: var(--ruby-red); }}
+    .heatmap {{
+      color: var(--ruby-red);
+      max-width: 100%;
+      height: auto;
+    }}
+    td .sparkline {{
+      color: inherit;
+      vertical-align: middle;
+    }}
+    .stages {{
+      color: var(--ruby-red);
+      vertical-align: middle;
+    }}
     .back-link {{
       display: inline-block;
       margin-bottom: 1.5rem;
@@ -744,16 +2142,29 @@ async fn stats_html(
   <div class="container">
     <a href="/" class="back-link">← Back to digests</a>
     <h1>Stats</h1>
-    <p class="subtitle">Source health and usage over the last {days} days</p>
+    <p class="subtitle">Source health and usage {period_text}</p>
 
     <div class="period-select">
-      <a href="/stats?days=7"{}>7 days</a>
-      <a href="/stats?days=30"{}>30 days</a>
-      <a href="/stats?days=90"{}>90 days</a>
+      <a href="/stats?days=7{compare_query}"{}>7 days</a>
+      <a href="/stats?days=30{compare_query}"{}>30 days</a>
+      <a href="/stats?days=90{compare_query}"{}>90 days</a>
+      {compare_toggle}
+      <form class="range-form" method="get" action="/stats">
+        <input type="date" name="from" value="{range_from}" required>
+        <span>to</span>
+        <input type="date" name="to" value="{range_to}" required>
+        {compare_input}
+        <button type="submit">Apply</button>
+      </form>
     </div>
 
+    {uptime_summary}
+
+    {delivery_panel}
+
     <section>
       <h2>Source Health</h2>
+      {sources_link}
       <table>
         <thead>
           <tr>
@@ -761,6 +2172,10 @@ async fn stats_html(
             <th>Fetches</th>
             <th>Successes</th>
             <th>Rate</th>
+            {vs_header}
+            <th>p50</th>
+            <th>p95</th>
+            <th>Trend</th>
           </tr>
         </thead>
         <tbody>
@@ -779,6 +2194,7 @@ async fn stats_html(
             <th>Should Know</th>
             <th>Other</th>
             <th>Total</th>
+            {vs_header}
           </tr>
         </thead>
         <tbody>
@@ -788,52 +2204,267 @@ async fn stats_html(
     </section>
 
     <section>
-      <h2>Recent Runs</h2>
+      <h2>Topic Coverage</h2>
       <table>
         <thead>
           <tr>
-            <th>Time (UTC)</th>
-            <th>Articles Fetched</th>
+            <th>Topic</th>
+            <th>Stories</th>
+            <th>Share</th>
+            {vs_header}
+            <th>Weekly Share</th>
+          </tr>
+        </thead>
+        <tbody>
+          {topic_rows}
+        </tbody>
+      </table>
+    </section>
+
+    <section>
+      <h2>Email Engagement</h2>
+      <table>
+        <thead>
+          <tr>
+            <th>Digest</th>
             <th>Recipients</th>
+            <th>Opened</th>
+            <th>Open Rate</th>
+            <th>Click Rate</th>
           </tr>
         </thead>
         <tbody>
-          {runs_rows}
+          {engagement_rows}
         </tbody>
       </table>
     </section>
-  </div>
-</body>
-</html>"##,
-        if days == 7 { " class=\"active\"" } else { "" },
-        if days == 30 { " class=\"active\"" } else { "" },
-        if days == 90 { " class=\"active\"" } else { "" },
-    );
 
-    Ok(Html(html))
+    <section>
+      <h2>Most Read Digests</h2>
+      <table>
+        <thead>
+          <tr>
+            <th>Digest</th>
+            <th>Views</th>
+            <th>Readers</th>
+          </tr>
+        </thead>
+        <tbody>
+          {viewed_rows}
+        </tbody>
+      </table>
+    </section>
+
+    <section>
+      <h2>Top Clicked Links</h2>
+      <table>
+        <thead>
+          <tr>
+            <th>Story</th>
+            <th>Clicks</th>
+          </tr>
+        </thead>
+        <tbody>
+          {link_rows}
+        </tbody>
+      </table>
+      <table>
+        <thead>
+          <tr>
+            <th>Source</th>
+            <th>Clicks</th>
+          </tr>
+        </thead>
+        <tbody>
+          {domain_rows}
+        </tbody>
+      </table>
+    </section>
+
+    <section>
+      <h2>Subscribers</h2>
+      <div class="summary">
+        <span><strong>{subscriber_total}</strong> subscribed</span>
+        <span class="good">+{subscriber_signups} new</span>{signups_delta}
+        <span class="bad">−{subscriber_unsubscribes} left</span>
+        {growth_sparkline}
+      </div>
+      <table>
+        <thead>
+          <tr>
+            <th>Date</th>
+            <th>Signups</th>
+            <th>Unsubscribes</th>
+            <th>Total</th>
+          </tr>
+        </thead>
+        <tbody>
+          {growth_rows}
+        </tbody>
+      </table>
+    </section>
+
+    <section>
+      <h2>Why Readers Unsubscribe</h2>
+      <table>
+        <thead>
+          <tr>
+            <th>Reason</th>
+            <th>Responses</th>
+          </tr>
+        </thead>
+        <tbody>
+          {reason_rows}
+        </tbody>
+      </table>
+    </section>
+
+    <section>
+      <h2>Recent Runs</h2>
+      <div class="summary">
+        <span><strong>{total_cost}</strong>{cost_delta} estimated Claude cost</span>
+        <span>Articles fetched per run</span>
+        {runs_sparkline}
+      </div>
+      <table>
+        <thead>
+          <tr>
+            <th>Time (UTC)</th>
+            <th>Articles Fetched</th>
+            <th>Recipients</th>
+            <th>Duration</th>
+            <th>Tokens (in / out)</th>
+            <th>Cost</th>
+          </tr>
+        </thead>
+        <tbody>
+          {runs_rows}
+        </tbody>
+      </table>
+    </section>
+
+    <section>
+      <h2>Run Timeline</h2>
+      <p class="subtitle">Each run's stages (fetch, select, render, send, record), including runs that failed</p>
+      <table>
+        <thead>
+          <tr>
+            <th>Started (UTC)</th>
+            <th>Outcome</th>
+            <th>Stages</th>
+            <th>Duration</th>
+          </tr>
+        </thead>
+        <tbody>
+          {timeline_rows}
+        </tbody>
+      </table>
+    </section>
+
+    <section>
+      <h2>Publishing Times</h2>
+      <p class="subtitle">Digest runs by weekday and hour (UTC)</p>
+      {publish_heatmap}
+    </section>
+
+    <p class="subtitle">Export CSV:
+      <a href="/stats.csv?table=health&amp;{range_query}">health</a> ·
+      <a href="/stats.csv?table=usage&amp;{range_query}">usage</a> ·
+      <a href="/stats.csv?table=runs&amp;{range_query}">runs</a> ·
+      <a href="/stats.csv?table=stages&amp;{range_query}">stages</a> ·
+      <a href="/stats.csv?table=topics&amp;{range_query}">topics</a>
+    </p>
+  </div>
+</body>
+</html>"##,
+        active(7),
+        active(30),
+        active(90),
+    );
+
+    Ok(Html(html))
 }
 
 /// Serve digest HTML by date (YYYY-MM-DD)
 async fn get_digest(
     Path(date): Path<String>,
     State(state): State<Arc<AppState>>,
-) -> Result<Html<String>, (StatusCode, String)> {
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
     // Validate date format: exactly YYYY-MM-DD
     if !is_valid_date(&date) {
         return Err((StatusCode::BAD_REQUEST, "Invalid date format".into()));
     }
 
-    // Open database read-only
-    let conn = Connection::open_with_flags(&state.db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {e}")))?;
-
-    // Query for digest HTML
-    let html: String = conn
-        .query_row("SELECT html FROM digests WHERE date = ?1", [&date], |row| {
-            row.get(0)
+    let (page, validators) = state
+        .blocking(move |state| {
+            let timer = state.metrics.time_db("get_digest");
+            let (version, updated_at) = state
+                .storage
+                .digest_version(&date)
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+                .ok_or_else(|| (StatusCode::NOT_FOUND, format!("No digest for {date}")))?;
+            let validators = updated_at.as_deref().map(conditional::Validators::new);
+            // A client holding the current page needs neither a render nor a cache slot
+            let page = if validators.as_ref().is_some_and(|v| v.is_fresh(&headers)) {
+                None
+            } else {
+                match state.digest_cache.get(&date) {
+                    Some(cached) if cached.0 == version => Some(cached),
+                    _ => {
+                        let (_, html) = state
+                            .storage
+                            .digest(Some(&date))
+                            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+                            .ok_or_else(|| {
+                                (StatusCode::NOT_FOUND, format!("No digest for {date}"))
+                            })?;
+                        let updated = state
+                            .storage
+                            .digest_updated_at(&date)
+                            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+                        let page = render_digest(state, &date, html, updated.as_deref());
+                        Some(state.digest_cache.insert(date.clone(), (version, page)))
+                    }
+                }
+            };
+            drop(timer);
+
+            // Count the view, revalidated or not (best-effort: a failed write
+            // must not break the page)
+            if state.sqlite_writable()
+                && let Err(e) =
+                    pageviews::record(&state.db_path, &state.view_salt, &date, &headers, peer)
+            {
+                tracing::warn!("Could not record pageview: {}", e);
+            }
+            Ok((page.map(|page| page.1.clone()), validators))
         })
-        .map_err(|_| (StatusCode::NOT_FOUND, format!("No digest for {date}")))?;
+        .await?;
 
+    let validators = validators.map(|v| v.headers()).unwrap_or_default();
+    Ok(match page {
+        Some(page) => (AppendHeaders(validators), Html(page)).into_response(),
+        None => (StatusCode::NOT_MODIFIED, AppendHeaders(validators)).into_response(),
+    })
+}
+
+/// Turn stored digest HTML into the web page: tracked links, navigation (with
+/// when it was last corrected), and no email-only elements
+fn render_digest(state: &AppState, date: &str, html: String, updated: Option<&str>) -> String {
+    let html = if state.click_tracking {
+        links::rewrite(&html, date, state.public_url.as_deref().unwrap_or_default())
+    } else {
+        html
+    };
+    let updated = updated.map(revisions::updated_note);
+    web_view(html, updated.as_deref())
+}
+
+/// Digest HTML as a web page: navigation, with `note` (HTML) beside it, and
+/// no email-only elements
+fn web_view(html: String, note: Option<&str>) -> String {
     // Inject navigation header CSS and HTML when viewing in browser
     let nav_css = r#"<style>
 .digest-nav {
@@ -850,14 +2481,22 @@ async fn get_digest(
     color: var(--text-muted, #777);
     text-decoration: none;
 }
+.digest-nav span {
+    color: var(--text-muted, #777);
+}
 .digest-nav a:hover {
     color: var(--accent, #c45a3b);
 }
 </style>"#;
 
-    let nav_html = r#"<nav class="digest-nav">
-    <a href="/">← All digests</a>
-</nav>"#;
+    let note = note
+        .map(|note| format!("\n    <span>{note}</span>"))
+        .unwrap_or_default();
+    let nav_html = format!(
+        r#"<nav class="digest-nav">
+    <a href="/">← All digests</a>{note}
+</nav>"#
+    );
 
     // Insert CSS before </head> and nav after <body>
     let html = html.replacen("</head>", &format!("{}</head>", nav_css), 1);
@@ -875,12 +2514,98 @@ async fn get_digest(
         .replace(&html, "")
         .to_string();
     // Remove feedback buttons (mailto links don't work well on web)
-    let html = regex::Regex::new(r#"(?s)<div class="feedback">.*?</div>\s*</div>"#)
+    regex::Regex::new(r#"(?s)<div class="feedback">.*?</div>\s*</div>"#)
         .unwrap()
         .replace(&html, "")
-        .to_string();
+        .into_owned()
+}
 
-    Ok(Html(html))
+/// Render a small standalone page (confirmations, forms) in the site's style
+fn render_page(state: &AppState, title: &str, content: &str) -> String {
+    let name = &state.digest_name;
+    let css_link = state
+        .css_url
+        .as_ref()
+        .map(|url| format!(r#"<link rel="stylesheet" href="{url}">"#))
+        .unwrap_or_default();
+    format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <title>{title} – {name}</title>
+  {css_link}
+  <style>
+    .container {{
+      max-width: 600px;
+      margin: 0 auto;
+      padding: 3rem 1.5rem;
+    }}
+    h1 {{
+      font-size: 1.75rem;
+      font-weight: 700;
+      margin-bottom: 1rem;
+      letter-spacing: -0.02em;
+    }}
+    p {{
+      color: var(--text-secondary);
+      margin-bottom: 1rem;
+    }}
+    form label {{
+      display: block;
+      margin: 0.5rem 0;
+      color: var(--text-secondary);
+    }}
+    form input[type="email"], form textarea {{
+      width: 100%;
+      padding: 0.75rem 1rem;
+      margin: 0.5rem 0 1rem;
+      background: var(--bg-card);
+      border: 1px solid var(--border-white-light);
+      border-radius: 0.5rem;
+      color: var(--text-primary);
+      font-size: 1rem;
+    }}
+    form button {{
+      padding: 0.75rem 1.5rem;
+      background: linear-gradient(135deg, var(--ruby-red) 0%, var(--ruby-red-light) 100%);
+      color: white;
+      border: none;
+      border-radius: 0.5rem;
+      font-weight: 600;
+      cursor: pointer;
+    }}
+    .back-link {{
+      display: inline-block;
+      margin-top: 1.5rem;
+      color: var(--text-tertiary);
+      text-decoration: none;
+      font-size: 0.875rem;
+    }}
+    .back-link:hover {{
+      color: var(--ruby-red);
+    }}
+  </style>
+</head>
+<body>
+  <div class="container">
+    <h1>{title}</h1>
+    {content}
+    <a href="/" class="back-link">← Back to digests</a>
+  </div>
+</body>
+</html>"##
+    )
+}
+
+/// Escape text for safe interpolation into HTML
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
 }
 
 /// Format date from YYYY-MM-DD to "Friday, January 17"
@@ -919,7 +2644,12 @@ fn format_date(date_str: &str) -> String {
         "Saturday",
     ];
 
-    // Zeller's congruence for day of week
+    let dow = day_of_week(year, month, day);
+    format!("{}, {} {}", days[dow], months[month as usize], day)
+}
+
+/// Day of the week, 0 for Sunday, by Zeller's congruence
+fn day_of_week(year: i32, month: u32, day: u32) -> usize {
     let (y, m) = if month < 3 {
         (year - 1, month + 12)
     } else {
@@ -929,9 +2659,7 @@ fn format_date(date_str: &str) -> String {
     let k = y % 100;
     let j = y / 100;
     let h = (q + (13 * (m as i32 + 1)) / 5 + k + k / 4 + j / 4 - 2 * j) % 7;
-    let dow = ((h + 6) % 7) as usize;
-
-    format!("{}, {} {}", days[dow], months[month as usize], day)
+    ((h + 6) % 7) as usize
 }
 
 /// Validate date is exactly YYYY-MM-DD format with valid numbers
@@ -949,74 +2677,804 @@ fn is_valid_date(s: &str) -> bool {
 
 #[tokio::main]
 async fn main() {
-    // Initialize tracing subscriber (respects RUST_LOG env var, defaults to info)
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
-        )
-        .init();
+    logging::init();
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
     let db_path = std::env::var("DATABASE_PATH").unwrap_or_else(|_| "/data/digest.db".into());
+    let db_pool_size = std::env::var("DB_POOL_SIZE")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .filter(|&n| n > 0)
+        .unwrap_or(8);
+    // Read-only unless asked otherwise, so the archive can be served from a read-only mount
+    let read_write = match std::env::var("SERVER_MODE").ok().filter(|v| !v.is_empty()) {
+        None => false,
+        Some(mode) if mode == "ro" => false,
+        Some(mode) if mode == "rw" => true,
+        Some(mode) => {
+            tracing::error!("SERVER_MODE must be ro or rw, got '{}'", mode);
+            std::process::exit(1);
+        }
+    };
+    // Dry runs change nothing, so never migrate or write for one
+    let read_write = read_write && !args.iter().any(|a| a == "--dry-run");
+
+    // Digest bodies moved out of the database, if configured
+    let blob_store = match blobs::from_env(&db_path) {
+        Ok(store) => store,
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
-    // Validate database path is within expected directories
-    if !db_path.starts_with("/data/")
-        && !db_path.starts_with("/app/data/")
-        && !db_path.starts_with("./data/")
-    {
-        tracing::error!("DATABASE_PATH must be within /data/, /app/data/, or ./data/");
-        std::process::exit(1);
-    }
-
-    let port: u16 = std::env::var("PORT")
+    // A postgres:// DATABASE_URL selects Postgres; otherwise the SQLite file
+    let database_url = std::env::var("DATABASE_URL")
         .ok()
-        .and_then(|p| p.parse().ok())
-        .unwrap_or(8080);
-    let addr = format!("0.0.0.0:{port}");
+        .filter(|url| storage::is_postgres_url(url));
+    let (storage, db) = match database_url {
+        Some(url) => match open_postgres(url, db_pool_size, blob_store.clone()).await {
+            Ok(storage) => (storage, None),
+            Err(e) => {
+                tracing::error!("Database error: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            // Validate database path is within expected directories
+            let roots = std::env::var("DATABASE_ROOTS")
+                .ok()
+                .filter(|r| !r.is_empty())
+                .unwrap_or_else(|| DEFAULT_DATABASE_ROOTS.to_string());
+            if let Err(e) = check_database_path(&db_path, &roots) {
+                tracing::error!("{}", e);
+                std::process::exit(1);
+            }
+
+            // Encrypted databases (`sqlcipher` feature); set before anything opens one
+            match database_key() {
+                Ok(Some(key)) => {
+                    if let Err(e) = db::use_key(key) {
+                        tracing::error!("Database error: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::error!("Database error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+
+            // Telemetry in its own file next to the database, so stat writes
+            // never contend with reader-facing queries
+            if let Some(name) = std::env::var("TELEMETRY_DB").ok().filter(|v| !v.is_empty()) {
+                if name.contains('/') || name.starts_with('.') {
+                    tracing::error!("TELEMETRY_DB must be a file name, like telemetry.db");
+                    std::process::exit(1);
+                }
+                let path = std::path::Path::new(&db_path).with_file_name(&name);
+                if let Err(e) = db::use_telemetry_db(&path.to_string_lossy()) {
+                    tracing::error!("Database error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+
+            // Fail on a wrong key now rather than on the first request. New
+            // databases are created (and encrypted) by migration or import.
+            for path in std::iter::once(db_path.as_str()).chain(db::telemetry_path()) {
+                if std::fs::metadata(path).is_ok_and(|m| m.len() > 0)
+                    && let Err(e) = db::check_key(path)
+                {
+                    tracing::error!("Database error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+
+            // Imports may seed a new database, so run before it's required to exist
+            if args.first().is_some_and(|c| c == "import-archive") {
+                std::process::exit(archive::import_command(&db_path, &args[1..]));
+            }
+
+            // Read-write mode creates a missing database, so migrate before
+            // checking there are digests to serve
+            if let Err(e) = migrate_database(&db_path, read_write) {
+                tracing::error!("Database error: {}", e);
+                std::process::exit(1);
+            }
+            if let Err(e) = verify_database(&db_path) {
+                tracing::error!("Database error: {}", e);
+                std::process::exit(1);
+            }
+            match db::read_only_pool(&db_path, db_pool_size) {
+                Ok(pool) => {
+                    let storage: Arc<dyn storage::Storage> = Arc::new(storage::Sqlite::new(
+                        &db_path,
+                        pool.clone(),
+                        blob_store.clone(),
+                    ));
+                    (storage, Some(pool))
+                }
+                Err(e) => {
+                    tracing::error!("Database error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    };
+    tracing::info!("Using {} storage", storage.name());
+    if let Some(store) = &blob_store {
+        tracing::info!("Digest bodies move to the {} blob store", store.name());
+    }
 
-    // Verify database exists and has digests table
-    if let Err(e) = verify_database(&db_path) {
-        tracing::error!("Database error: {}", e);
-        std::process::exit(1);
+    if let Some(command) = args.first() {
+        let local_db = db.is_some().then_some(db_path.as_str());
+        let code = run_command(command, &args[1..], &storage, local_db).await;
+        std::process::exit(code);
     }
 
+    let listen = match listen::Listen::from_env() {
+        Ok(listen) => listen,
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
     let digest_name = std::env::var("DIGEST_NAME").unwrap_or_else(|_| "News Digest".into());
     let css_url = std::env::var("CSS_URL").ok();
     let homepage_url = std::env::var("HOMEPAGE_URL").ok();
     let source_url = std::env::var("SOURCE_URL").ok();
     let resend_api_key = std::env::var("RESEND_API_KEY").ok();
-    let resend_audience_id = std::env::var("RESEND_AUDIENCE_ID").ok();
+    let mut audiences = match resend::parse_audiences(
+        std::env::var("RESEND_AUDIENCES").ok().as_deref(),
+        std::env::var("RESEND_AUDIENCE_ID").ok().as_deref(),
+    ) {
+        Ok(audiences) => audiences,
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let resend_from = std::env::var("RESEND_FROM").ok();
+    let admin_token = std::env::var("ADMIN_TOKEN").ok().filter(|t| !t.is_empty());
+    let stats_token = std::env::var("STATS_TOKEN").ok().filter(|t| !t.is_empty());
+    let cache_control = match cache_control::CacheControl::from_env(stats_token.is_some()) {
+        Ok(cache_control) => cache_control,
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let resend_webhook_secret = std::env::var("RESEND_WEBHOOK_SECRET")
+        .ok()
+        .filter(|s| !s.is_empty());
+    let token_secret = std::env::var("TOKEN_SECRET")
+        .ok()
+        .filter(|t| !t.is_empty())
+        .map(String::into_bytes);
+    let public_url = std::env::var("PUBLIC_URL").ok().filter(|u| !u.is_empty());
+    let double_opt_in = std::env::var("DOUBLE_OPT_IN").is_ok_and(|v| v == "1" || v == "true");
+    let smtp = match smtp::SmtpConfig::from_env() {
+        Ok(smtp) => smtp,
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    // SMTP-only deployments keep a single local list
+    if audiences.is_empty() && smtp.is_some() {
+        audiences.push(resend::AudienceConfig {
+            name: "default".into(),
+            id: String::new(),
+        });
+    }
+    let can_send = smtp.is_some() || (resend_api_key.is_some() && resend_from.is_some());
+    if double_opt_in && (token_secret.is_none() || public_url.is_none() || !can_send) {
+        tracing::error!(
+            "DOUBLE_OPT_IN requires TOKEN_SECRET, PUBLIC_URL, and RESEND_FROM or SMTP_HOST"
+        );
+        std::process::exit(1);
+    }
+    let click_tracking = std::env::var("CLICK_TRACKING").is_ok_and(|v| v == "1" || v == "true");
+    let alert_config = match alerts::AlertConfig::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let backup = match backup::BackupConfig::from_env() {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let pipeline = match runs::Pipeline::from_env() {
+        Ok(pipeline) => pipeline.map(Arc::new),
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let slo = match slo::SloConfig::from_env() {
+        Ok(slo) => slo,
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let tls = match tls::Tls::from_env() {
+        Ok(tls) => tls,
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    if tls.is_some() && matches!(listen, listen::Listen::Unix { .. }) {
+        tracing::error!("TLS_CERT_FILE is for TCP; leave TLS to the proxy in front of a socket");
+        std::process::exit(1);
+    }
+    let sources = match std::env::var("SOURCES_FILE").ok().filter(|v| !v.is_empty()) {
+        Some(path) => match reload::SourcesFile::load(&path) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                tracing::error!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let stale_source_days = std::env::var("STALE_SOURCE_DAYS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(7);
+    let stats_cache_secs = std::env::var("STATS_CACHE_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(60);
+    let digest_cache_size = std::env::var("DIGEST_CACHE_SIZE")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(64);
     let http_client = Client::new();
 
     let state = Arc::new(AppState {
         db_path,
+        storage,
+        db,
+        read_write,
         digest_name,
         css_url,
         homepage_url,
         source_url,
         resend_api_key,
-        resend_audience_id,
+        audiences,
+        resend_from,
+        admin_token,
+        stats_token,
+        cache_control,
+        rate_limits: rate_limit::RateLimits::from_env(),
+        stale_source_days,
+        sources,
+        slo,
+        resend_webhook_secret,
+        token_secret,
+        public_url,
+        double_opt_in,
+        click_tracking,
+        view_salt: pageviews::new_salt(),
+        smtp,
+        backup,
+        pipeline,
+        metrics: metrics::Metrics::default(),
+        stats_cache: cache::TtlCache::new(Duration::from_secs(stats_cache_secs)),
+        deep_health: health::DeepConfig::from_env(),
+        deep_health_cache: cache::TtlCache::new(Duration::from_secs(30)),
+        digest_cache: cache::LruCache::new(digest_cache_size),
         http_client,
     });
 
+    // Self-checks, rollups, compression, retention, source alerts, backups, and
+    // scheduled runs work on the pipeline's SQLite tables; the first four write to them
+    if state.db.is_some() {
+        if state.read_write {
+            let health_check_secs = std::env::var("HEALTH_CHECK_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(60);
+            if health_check_secs > 0 {
+                tokio::spawn(health::run(
+                    state.db_path.clone(),
+                    Duration::from_secs(health_check_secs),
+                ));
+            }
+            let rollup_interval_mins = std::env::var("ROLLUP_INTERVAL_MINS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(60);
+            if rollup_interval_mins > 0 {
+                tokio::spawn(rollups::run(
+                    state.db_path.clone(),
+                    Duration::from_secs(60 * rollup_interval_mins),
+                ));
+            }
+            let compress_interval_mins = std::env::var("COMPRESS_INTERVAL_MINS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(60);
+            if compress_interval_mins > 0 {
+                tokio::spawn(compression::run(
+                    state.db_path.clone(),
+                    Duration::from_secs(60 * compress_interval_mins),
+                    blob_store.clone(),
+                ));
+            }
+            let retention_days = std::env::var("RETENTION_DAYS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(365);
+            let retention_interval_hours = std::env::var("RETENTION_INTERVAL_HOURS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(24);
+            if retention_days > 0 && retention_interval_hours > 0 {
+                tokio::spawn(retention::run(
+                    state.db_path.clone(),
+                    retention_days,
+                    Duration::from_secs(3600 * retention_interval_hours),
+                ));
+            }
+        }
+        if let Some(config) = alert_config {
+            tokio::spawn(alerts::run(state.clone(), config));
+        }
+        if let Some(config) = &state.backup
+            && let Some(interval) = config.interval
+        {
+            tokio::spawn(backup::run(state.clone(), config.clone(), interval));
+        }
+        if let Some(pipeline) = &state.pipeline
+            && let Some(schedule) = &pipeline.schedule
+        {
+            tracing::info!("Running the pipeline on schedule '{}'", schedule.expression);
+            tokio::spawn(schedule::run(
+                state.db_path.clone(),
+                pipeline.clone(),
+                schedule.clone(),
+            ));
+        }
+    } else {
+        if alert_config.is_some() {
+            tracing::warn!("Source alerts need SQLite storage; disabled");
+        }
+        if state.backup.is_some() {
+            tracing::warn!("Backups need SQLite storage; disabled");
+        }
+        if state
+            .pipeline
+            .as_ref()
+            .is_some_and(|p| p.schedule.is_some())
+        {
+            tracing::warn!("Scheduled pipeline runs need SQLite storage; disabled");
+        }
+    }
+    let sources_watch_secs = std::env::var("SOURCES_WATCH_SECS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(30);
+    if state.sources.is_some() && sources_watch_secs > 0 {
+        tokio::spawn(reload::watch(
+            state.clone(),
+            Duration::from_secs(sources_watch_secs),
+        ));
+    }
+
+    let stats_routes = Router::new()
+        .route("/stats", get(stats_html))
+        .route("/stats.json", get(stats_json))
+        .route("/stats.csv", get(stats_csv))
+        .route("/sources", get(sources_page::page))
+        .route("/sources.json", get(sources_page::json))
+        .route("/health/history", get(health::history))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            admin::require_stats_token,
+        ));
+
     let app = Router::new()
         .route("/", get(index))
         .route("/subscribe", post(subscribe))
+        .route("/subscribe/confirm", get(confirm_subscription))
+        .route(
+            "/preferences",
+            get(preferences::preferences_page).post(preferences::update_preferences),
+        )
+        .route(
+            "/unsubscribe",
+            get(unsubscribe::unsubscribe_page).post(unsubscribe::unsubscribe),
+        )
+        .route("/unsubscribe/feedback", post(unsubscribe::feedback))
+        .route("/admin/test-email", post(admin::test_email))
+        .route("/admin/backup", post(backup::backup))
+        .route("/admin/cache/purge", post(admin::purge_cache))
+        .route("/admin/reload", post(reload::reload))
+        .route("/admin/run", post(runs::trigger))
+        .route("/admin/runs/checkpoints", get(runs::checkpoints))
+        .route("/admin/runs/{id}", get(runs::run))
+        .route("/admin/preview", get(preview::preview))
+        .route("/admin/digests/{date}/history", get(revisions::history))
+        .route("/admin/db", get(db_stats::page))
+        .route("/admin/db.json", get(db_stats::json))
+        .route("/webhooks/resend", post(engagement::resend_webhook))
+        .route("/r/{id}", get(links::redirect))
+        .route("/img/{hash}", get(images::image))
         .route("/health", get(health))
-        .route("/stats", get(stats_html))
-        .route("/stats.json", get(stats_json))
+        .route("/health/deep", get(health::deep))
+        .route("/metrics", get(metrics::metrics))
+        .merge(stats_routes)
         .route("/{date}", get(get_digest))
-        .layer(TraceLayer::new_for_http())
-        .with_state(state);
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            rate_limit::limit,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            metrics::track,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            cache_control::set,
+        ))
+        .layer(encoding::layer())
+        .layer(middleware::map_response(encoding::weaken_etag))
+        // Each layer wraps those above it, so requests pass these bottom to
+        // top: assign an ID, log with it, echo it in the response
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(logging::trace_layer())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .with_state(state.clone());
+
+    let addr = match listen {
+        listen::Listen::Tcp(addr) => addr,
+        listen::Listen::Unix { path, mode } => {
+            let socket = match listen::UnixSocket::bind(&path, mode) {
+                Ok(socket) => socket,
+                Err(e) => {
+                    tracing::error!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+            tracing::info!("digest-server listening on unix:{}", path.display());
+            shutdown::serve(socket, app, state, shutdown::timeout()).await;
+            return;
+        }
+    };
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    let Some(tls) = tls else {
+        tracing::info!("digest-server listening on {}", addr);
+        shutdown::serve(listener, app, state, shutdown::timeout()).await;
+        return;
+    };
+    let listener = match tls.listen(listener) {
+        Ok(listener) => listener,
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    tracing::info!("digest-server listening on {} (HTTPS)", addr);
+    if let Some(redirect_port) = tls.redirect_port {
+        let plain = tokio::net::TcpListener::bind(format!("0.0.0.0:{redirect_port}"))
+            .await
+            .unwrap();
+        tracing::info!("Redirecting HTTP on port {} to HTTPS", redirect_port);
+        tokio::spawn(async move { tls.redirect(plain, addr.port()).await });
+    }
+    shutdown::serve(listener, app, state, shutdown::timeout()).await;
+}
 
-    tracing::info!("digest-server listening on {}", addr);
+/// Run a one-off admin command instead of the server, returning the exit code
+///
+/// `local_db` is the SQLite file for pipeline bookkeeping, when that's the storage
+async fn run_command(
+    command: &str,
+    args: &[String],
+    storage: &Arc<dyn storage::Storage>,
+    local_db: Option<&str>,
+) -> i32 {
+    match command {
+        "reconcile-audience" => {
+            let dry_run = args.iter().any(|a| a == "--dry-run");
+            let only = args
+                .iter()
+                .position(|a| a == "--audience")
+                .and_then(|i| args.get(i + 1));
+            let api_key = std::env::var("RESEND_API_KEY").ok();
+            let audiences = resend::parse_audiences(
+                std::env::var("RESEND_AUDIENCES").ok().as_deref(),
+                std::env::var("RESEND_AUDIENCE_ID").ok().as_deref(),
+            );
+            let (Some(api_key), Ok(audiences)) = (api_key, audiences) else {
+                eprintln!(
+                    "RESEND_API_KEY and a valid RESEND_AUDIENCE_ID/RESEND_AUDIENCES must be set"
+                );
+                return 1;
+            };
+            let selected: Vec<_> = audiences
+                .iter()
+                .filter(|a| only.is_none_or(|name| &a.name == name))
+                .collect();
+            if selected.is_empty() {
+                eprintln!("No matching audience configured");
+                return 1;
+            }
+            let client = Client::new();
+            let mut code = 0;
+            for config in selected {
+                println!("== Audience '{}' ==", config.name);
+                let audience = resend::Audience::new(&client, &api_key, &config.id);
+                match subscribers::reconcile(storage, &config.name, &audience, dry_run).await {
+                    Ok(plan) => subscribers::print_report(&plan, dry_run),
+                    Err(e) => {
+                        eprintln!("Reconciliation failed: {e}");
+                        code = 1;
+                    }
+                }
+            }
+            code
+        }
+        "send-digest" => {
+            // send-digest [--audience NAME] [--date YYYY-MM-DD] [--dry-run]
+            let flag = |name: &str| {
+                args.iter()
+                    .position(|a| a == name)
+                    .and_then(|i| args.get(i + 1))
+                    .map(String::as_str)
+            };
+            let config = match smtp::SmtpConfig::from_env() {
+                Ok(Some(config)) => config,
+                Ok(None) => {
+                    eprintln!("SMTP_HOST must be set");
+                    return 1;
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    return 1;
+                }
+            };
+            // Every message carries a signed unsubscribe link
+            let (Ok(secret), Ok(public_url)) =
+                (std::env::var("TOKEN_SECRET"), std::env::var("PUBLIC_URL"))
+            else {
+                eprintln!("TOKEN_SECRET and PUBLIC_URL must be set");
+                return 1;
+            };
+            if flag("--date").is_some_and(|d| !is_valid_date(d)) {
+                eprintln!("Invalid --date (expected YYYY-MM-DD)");
+                return 2;
+            }
+            let digest_name = std::env::var("DIGEST_NAME").unwrap_or_else(|_| "News Digest".into());
+            let delivery = smtp::Delivery {
+                storage,
+                db_path: local_db,
+                audience: flag("--audience").unwrap_or("default"),
+                date: flag("--date"),
+                digest_name: &digest_name,
+                token_secret: secret.as_bytes(),
+                public_url: &public_url,
+                click_tracking: std::env::var("CLICK_TRACKING")
+                    .is_ok_and(|v| v == "1" || v == "true"),
+                dry_run: args.iter().any(|a| a == "--dry-run"),
+            };
+            match smtp::deliver_digest(&config, &delivery).await {
+                Ok(sent) => {
+                    if !delivery.dry_run {
+                        println!("Sent {sent} message(s)");
+                    }
+                    0
+                }
+                Err(e) => {
+                    eprintln!("Delivery failed: {e}");
+                    1
+                }
+            }
+        }
+        "mint-link" => {
+            // mint-link <unsubscribe|preferences> EMAIL [--audience NAME]
+            let action = match args.first().map(String::as_str) {
+                Some("unsubscribe") => tokens::Action::Unsubscribe,
+                Some("preferences") => tokens::Action::Preferences,
+                _ => {
+                    eprintln!(
+                        "Usage: digest-server mint-link <unsubscribe|preferences> EMAIL [--audience NAME]"
+                    );
+                    return 2;
+                }
+            };
+            let Some(email) = args.get(1) else {
+                eprintln!("Missing EMAIL");
+                return 2;
+            };
+            let audience = args
+                .iter()
+                .position(|a| a == "--audience")
+                .and_then(|i| args.get(i + 1));
+            let (Ok(secret), Ok(base)) =
+                (std::env::var("TOKEN_SECRET"), std::env::var("PUBLIC_URL"))
+            else {
+                eprintln!("TOKEN_SECRET and PUBLIC_URL must be set");
+                return 1;
+            };
+            let claims = tokens::Claims::new(
+                action,
+                email,
+                audience.map(String::as_str),
+                tokens::LINK_TTL_SECS,
+            );
+            let path = match action {
+                tokens::Action::Preferences => "/preferences",
+                _ => "/unsubscribe",
+            };
+            println!(
+                "{}{path}?token={}",
+                base.trim_end_matches('/'),
+                tokens::mint(secret.as_bytes(), &claims)
+            );
+            0
+        }
+        // export-archive [--stats] [--from DB] [--output FILE]
+        "export-archive" => archive::export_command(args, local_db),
+        // SQLite imports are handled before the database is opened
+        "import-archive" => {
+            eprintln!("import-archive needs SQLite storage");
+            1
+        }
+        _ => {
+            eprintln!("Unknown command: {command}");
+            eprintln!(
+                "Usage: digest-server [reconcile-audience [--audience NAME] [--dry-run] | mint-link <unsubscribe|preferences> EMAIL [--audience NAME] | send-digest [--audience NAME] [--date YYYY-MM-DD] [--dry-run] | export-archive [--stats] [--from DB] [--output FILE] | import-archive [--replace] [--input FILE]]"
+            );
+            2
+        }
+    }
+}
+
+/// Connect to Postgres off the async runtime, since the client blocks
+#[cfg(feature = "postgres")]
+async fn open_postgres(
+    url: String,
+    size: u32,
+    blobs: Option<Arc<dyn blobs::BlobStore>>,
+) -> Result<Arc<dyn storage::Storage>, String> {
+    let storage = db::blocking(move || postgres::Postgres::connect(&url, size, blobs)).await??;
+    Ok(Arc::new(storage))
+}
+
+#[cfg(not(feature = "postgres"))]
+async fn open_postgres(
+    _url: String,
+    _size: u32,
+    _blobs: Option<Arc<dyn blobs::BlobStore>>,
+) -> Result<Arc<dyn storage::Storage>, String> {
+    Err("DATABASE_URL is a Postgres URL, but this build lacks the `postgres` feature".into())
+}
+
+/// Directories DATABASE_PATH may live in, unless DATABASE_ROOTS says otherwise
+const DEFAULT_DATABASE_ROOTS: &str = "/data,/app/data,./data";
+
+/// Check `path` resolves, after following symlinks and `..`, to a file inside
+/// one of the comma-separated `roots`. The file needn't exist yet (read-write
+/// mode creates it), but its directory must.
+fn check_database_path(path: &str, roots: &str) -> Result<(), String> {
+    let path = std::path::Path::new(path);
+    let name = path
+        .file_name()
+        .ok_or_else(|| format!("DATABASE_PATH {} is not a file", path.display()))?;
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(std::path::Path::new("."));
+    let dir = dir.canonicalize().map_err(|e| {
+        format!(
+            "Cannot resolve DATABASE_PATH directory {}: {e}",
+            dir.display()
+        )
+    })?;
+    let resolved = dir.join(name);
+    let resolved = resolved.canonicalize().unwrap_or(resolved);
+    // Roots that don't exist on this machine can't contain anything
+    let inside = roots
+        .split(',')
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+        .filter_map(|r| std::path::Path::new(r).canonicalize().ok())
+        .any(|root| resolved.starts_with(root));
+    if !inside {
+        return Err(format!(
+            "DATABASE_PATH {} must be within {roots} (set DATABASE_ROOTS to allow others)",
+            resolved.display()
+        ));
+    }
+    Ok(())
+}
+
+/// SQLCipher key from `DATABASE_KEY`, or the first line of `DATABASE_KEY_FILE`
+/// (for Docker secrets)
+fn database_key() -> Result<Option<String>, String> {
+    if let Some(key) = std::env::var("DATABASE_KEY").ok().filter(|k| !k.is_empty()) {
+        return Ok(Some(key));
+    }
+    let Some(file) = std::env::var("DATABASE_KEY_FILE")
+        .ok()
+        .filter(|f| !f.is_empty())
+    else {
+        return Ok(None);
+    };
+    let contents = std::fs::read_to_string(&file)
+        .map_err(|e| format!("Cannot read DATABASE_KEY_FILE {file}: {e}"))?;
+    match contents.lines().next().map(str::trim_end) {
+        Some(key) if !key.is_empty() => Ok(Some(key.to_string())),
+        _ => Err(format!("DATABASE_KEY_FILE {file} is empty")),
+    }
+}
 
-    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+/// Bring the schema up to date in read-write mode, creating the database and
+/// the pipeline's tables if they don't exist yet. In read-only mode the
+/// database is served as is, without the features whose tables are missing.
+fn migrate_database(path: &str, read_write: bool) -> Result<(), String> {
+    if !read_write {
+        let conn = db::open(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| format!("Cannot open database: {e}"))?;
+        let version = migrations::version(&conn)?;
+        if version < migrations::latest() {
+            tracing::info!(
+                "Serving read-only; schema is at version {} of {} (SERVER_MODE=rw migrates it)",
+                version,
+                migrations::latest()
+            );
+        }
+        return Ok(());
+    }
+    let mut conn = db::open(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE,
+    )
+    .map_err(|e| format!("Cannot open database read-write: {e}"))?;
+    if conn
+        .is_readonly(rusqlite::MAIN_DB)
+        .map_err(|e| format!("DB error: {e}"))?
+    {
+        return Err("SERVER_MODE=rw, but the database is read-only (check the mount)".into());
+    }
+    let version = migrations::version(&conn)?;
+    db::enable_wal(&conn)?;
+    if migrations::bootstrap(&mut conn)? {
+        tracing::info!("Created the pipeline's tables in a new database");
+    }
+    if version > migrations::latest() {
+        tracing::warn!(
+            "Database schema version {} is newer than this server ({})",
+            version,
+            migrations::latest()
+        );
+    }
+    for migration in migrations::run(&mut conn)? {
+        tracing::info!(
+            "Applied migration {} ({})",
+            migration.version,
+            migration.name
+        );
+    }
+    for table in db::move_telemetry_tables(&mut conn)? {
+        tracing::info!("Moved {} to the telemetry database", table);
+    }
+    Ok(())
 }
 
 fn verify_database(path: &str) -> Result<(), String> {
-    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+    let conn = db::open(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
         .map_err(|e| format!("Cannot open database: {e}"))?;
 
     // Check that digests table exists
@@ -1034,6 +3492,256 @@ fn verify_database(path: &str) -> Result<(), String> {
 mod tests {
     use super::*;
 
+    /// State over a fresh read-write SQLite database in `dir`, with one
+    /// audience mailed through an SMTP server that isn't listening
+    pub(crate) fn test_state(dir: &std::path::Path) -> AppState {
+        std::fs::create_dir_all(dir).unwrap();
+        let db_path = dir.join("digest.db").display().to_string();
+        migrate_database(&db_path, true).unwrap();
+        let pool = db::read_only_pool(&db_path, 2).unwrap();
+        AppState {
+            storage: Arc::new(storage::Sqlite::new(&db_path, pool.clone(), None)),
+            db_path,
+            db: Some(pool),
+            read_write: true,
+            digest_name: "News Digest".into(),
+            css_url: None,
+            homepage_url: None,
+            source_url: None,
+            resend_api_key: None,
+            audiences: vec![resend::AudienceConfig {
+                name: "default".into(),
+                id: "local".into(),
+            }],
+            resend_from: None,
+            admin_token: None,
+            stats_token: None,
+            cache_control: cache_control::CacheControl::from_env(false).unwrap(),
+            rate_limits: rate_limit::RateLimits::from_env(),
+            stale_source_days: 7,
+            sources: None,
+            slo: None,
+            resend_webhook_secret: None,
+            token_secret: Some(b"test secret".to_vec()),
+            public_url: Some("https://digest.example.com".into()),
+            double_opt_in: false,
+            click_tracking: false,
+            view_salt: [0; 32],
+            smtp: Some(smtp::SmtpConfig {
+                host: "127.0.0.1".into(),
+                port: Some(1),
+                tls: "none".into(),
+                username: None,
+                password: None,
+                from: "digest@example.com".into(),
+                max_recipients: 100,
+                batch_size: 10,
+            }),
+            backup: None,
+            pipeline: None,
+            metrics: metrics::Metrics::default(),
+            stats_cache: cache::TtlCache::new(Duration::ZERO),
+            deep_health: health::DeepConfig::from_env(),
+            deep_health_cache: cache::TtlCache::new(Duration::ZERO),
+            digest_cache: cache::LruCache::new(0),
+            http_client: Client::new(),
+        }
+    }
+
+    mod check_database_path {
+        use super::*;
+
+        fn root(name: &str) -> std::path::PathBuf {
+            let dir = std::env::temp_dir().join(format!("roots-{name}-{}", std::process::id()));
+            std::fs::create_dir_all(dir.join("data")).unwrap();
+            std::fs::create_dir_all(dir.join("elsewhere")).unwrap();
+            dir
+        }
+
+        #[test]
+        fn accepts_paths_under_a_root() {
+            let dir = root("ok");
+            let roots = format!("/nonexistent, {}", dir.join("data").display());
+            let db = dir.join("data/digest.db");
+            assert!(check_database_path(db.to_str().unwrap(), &roots).is_ok());
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+
+        #[test]
+        fn rejects_escapes() {
+            let dir = root("escape");
+            let roots = dir.join("data").display().to_string();
+            let dotdot = dir.join("data/../elsewhere/digest.db");
+            assert!(check_database_path(dotdot.to_str().unwrap(), &roots).is_err());
+
+            std::fs::write(dir.join("elsewhere/real.db"), "").unwrap();
+            std::os::unix::fs::symlink(dir.join("elsewhere/real.db"), dir.join("data/link.db"))
+                .unwrap();
+            let link = dir.join("data/link.db");
+            assert!(check_database_path(link.to_str().unwrap(), &roots).is_err());
+
+            let missing = dir.join("data/missing/digest.db");
+            assert!(check_database_path(missing.to_str().unwrap(), &roots).is_err());
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    mod is_dormant {
+        use super::*;
+
+        fn source(successes: i64, days_since_new_item: Option<f64>) -> SourceHealth {
+            SourceHealth {
+                source_id: "bbc".into(),
+                total_fetches: 10,
+                successes,
+                success_rate_pct: 0.0,
+                p50_ms: None,
+                p95_ms: None,
+                daily_success: Vec::new(),
+                last_new_item_at: None,
+                days_since_new_item,
+                volume: None,
+                filtered_articles: 0,
+            }
+        }
+
+        #[test]
+        fn live_feed_without_new_items() {
+            assert!(source(10, Some(9.5)).is_dormant(7));
+            assert!(!source(10, Some(2.0)).is_dormant(7));
+            assert!(!source(10, None).is_dormant(7));
+            // Failing feeds are a health problem, not dormancy
+            assert!(!source(0, Some(30.0)).is_dormant(7));
+        }
+    }
+
+    mod publish_times {
+        use super::*;
+
+        #[test]
+        fn monday_first_by_utc_hour() {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(
+                "CREATE TABLE digest_runs (run_at DATETIME);
+                 INSERT INTO digest_runs VALUES
+                     ('2026-01-05 07:10:00'), ('2026-01-12 07:40:00'), ('2026-01-11 23:59:00');",
+            )
+            .unwrap();
+            let range = db::DateRange {
+                from: "2026-01-01".into(),
+                to: "2026-01-31".into(),
+                days: 31,
+            };
+            let grid = publish_times(&conn, &range).unwrap();
+            assert_eq!(grid[0][7], 2);
+            assert_eq!(grid[6][23], 1);
+            assert_eq!(grid.iter().flatten().sum::<i64>(), 3);
+        }
+    }
+
+    mod volume {
+        use super::*;
+
+        #[test]
+        fn flags_floods_and_silence() {
+            let history: Vec<i64> = (0..20).map(|i| 8 + i % 5).collect();
+            let normal = Volume::from_counts(&[9, 10, 11], &history).unwrap();
+            assert!(!normal.is_anomaly(), "{normal:?}");
+            let silent = Volume::from_counts(&[0, 0, 1], &history).unwrap();
+            assert!(silent.is_anomaly() && silent.z_score() < 0.0);
+            let flood = Volume::from_counts(&[40, 35], &history).unwrap();
+            assert!(flood.is_anomaly() && flood.z_score() > 0.0);
+        }
+
+        #[test]
+        fn needs_history_and_floors_deviation() {
+            assert_eq!(Volume::from_counts(&[5], &[5; 10]), None);
+            // A steady feed moving by two articles isn't an anomaly
+            assert!(!Volume::from_counts(&[7], &[5; 20]).unwrap().is_anomaly());
+        }
+    }
+
+    mod filtered_articles {
+        use super::*;
+
+        #[test]
+        fn sums_the_range_per_source() {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(
+                "CREATE TABLE source_health (source_id TEXT, success INTEGER,
+                     filtered_articles INTEGER, recorded_at DATETIME);
+                 INSERT INTO source_health VALUES
+                     ('bbc', 1, 3, '2026-03-02 09:00:00'), ('bbc', 1, 2, '2026-03-07 21:00:00'),
+                     ('bbc', 1, 9, '2026-02-27 09:00:00'), ('npr', 1, 0, '2026-03-03 09:00:00'),
+                     ('npr', 0, NULL, '2026-03-04 09:00:00');",
+            )
+            .unwrap();
+            let range = db::DateRange {
+                from: "2026-03-01".into(),
+                to: "2026-03-07".into(),
+                days: 7,
+            };
+            assert_eq!(
+                filtered_articles(&conn, &range).unwrap(),
+                BTreeMap::from([("bbc".to_string(), 5)])
+            );
+        }
+    }
+
+    mod percentile {
+        use super::*;
+
+        #[test]
+        fn nearest_rank() {
+            let ms: Vec<i64> = (1..=20).map(|i| i * 10).collect();
+            assert_eq!(percentile(&ms, 50.0), Some(100));
+            assert_eq!(percentile(&ms, 95.0), Some(190));
+            assert_eq!(percentile(&[42], 95.0), Some(42));
+            assert_eq!(percentile(&[], 50.0), None);
+        }
+    }
+
+    mod delta_html {
+        use super::*;
+
+        #[test]
+        fn colours_by_direction() {
+            assert_eq!(
+                delta_html(-12.4, 0, "pp", true),
+                r#"<span class="delta bad">−12pp</span>"#
+            );
+            assert_eq!(
+                delta_html(-0.4, 2, "$", false),
+                r#"<span class="delta good">−$0.40</span>"#
+            );
+        }
+
+        #[test]
+        fn rounds_to_no_change() {
+            assert_eq!(
+                delta_html(0.3, 0, "pp", true),
+                r#"<span class="delta">±0pp</span>"#
+            );
+        }
+    }
+
+    mod csv_row {
+        use super::*;
+
+        #[test]
+        fn plain_fields() {
+            assert_eq!(csv_row(["a", "1", "2.5"]), "a,1,2.5\r\n");
+        }
+
+        #[test]
+        fn quotes_special_fields() {
+            assert_eq!(
+                csv_row(["bbc, world", "say \"hi\"", "x"]),
+                "\"bbc, world\",\"say \"\"hi\"\"\",x\r\n"
+            );
+        }
+    }
+
     mod is_valid_date {
         use super::*;
 
@@ -1085,6 +3793,23 @@ mod tests {
         }
     }
 
+    mod escape_html {
+        use super::*;
+
+        #[test]
+        fn escapes_markup() {
+            assert_eq!(
+                escape_html(r#"<a href="x">'&'</a>"#),
+                "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;"
+            );
+        }
+
+        #[test]
+        fn plain_text_unchanged() {
+            assert_eq!(escape_html("reader@example.com"), "reader@example.com");
+        }
+    }
+
     mod format_date {
         use super::*;
 
@@ -0,0 +1,261 @@
+//! Prometheus metrics in the text exposition format, kept in memory
+
+use crate::{AppState, db};
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Latency bucket upper bounds in seconds
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+    /// Non-cumulative count per bucket, plus one for +Inf
+    counts: [u64; BUCKETS.len() + 1],
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        let i = BUCKETS
+            .iter()
+            .position(|&b| seconds <= b)
+            .unwrap_or(BUCKETS.len());
+        self.counts[i] += 1;
+        self.sum += seconds;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (i, count) in self.counts.iter().enumerate() {
+            cumulative += count;
+            let le = BUCKETS.get(i).map_or("+Inf".to_string(), |b| b.to_string());
+            let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{le}\"}} {cumulative}");
+        }
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{{{labels}}} {cumulative}");
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    /// (method, route, status) -> count
+    requests: BTreeMap<(String, String, u16), u64>,
+    request_seconds: BTreeMap<String, Histogram>,
+    db_seconds: BTreeMap<&'static str, Histogram>,
+    /// result -> count
+    subscriptions: BTreeMap<&'static str, u64>,
+}
+
+/// Process-wide counters and histograms
+#[derive(Default)]
+pub struct Metrics {
+    registry: Mutex<Registry>,
+    #[cfg(feature = "otel")]
+    otel: crate::otel::Instruments,
+}
+
+impl Metrics {
+    fn record_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let mut r = self.registry.lock().unwrap();
+        *r.requests
+            .entry((method.to_string(), route.to_string(), status))
+            .or_default() += 1;
+        r.request_seconds
+            .entry(route.to_string())
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+        #[cfg(feature = "otel")]
+        self.otel.record_request(method, route, status, elapsed);
+    }
+
+    /// Count a subscribe attempt: "subscribed", "pending" (awaiting opt-in), or "failed"
+    pub fn record_subscription(&self, result: &'static str) {
+        #[cfg(feature = "otel")]
+        self.otel.record_subscription(result);
+        *self
+            .registry
+            .lock()
+            .unwrap()
+            .subscriptions
+            .entry(result)
+            .or_default() += 1;
+    }
+
+    /// Time a database query until the returned guard is dropped
+    pub fn time_db(&self, query: &'static str) -> DbTimer<'_> {
+        DbTimer {
+            metrics: self,
+            query,
+            start: Instant::now(),
+        }
+    }
+
+    fn render(&self, digests: Option<i64>) -> String {
+        let r = self.registry.lock().unwrap();
+        let mut out = String::new();
+
+        out.push_str("# HELP http_requests_total HTTP requests by route and status\n");
+        out.push_str("# TYPE http_requests_total counter\n");
+        for ((method, route, status), count) in &r.requests {
+            let _ = writeln!(
+                out,
+                "http_requests_total{{method=\"{method}\",route=\"{route}\",status=\"{status}\"}} {count}"
+            );
+        }
+
+        out.push_str("# HELP http_request_duration_seconds HTTP request latency by route\n");
+        out.push_str("# TYPE http_request_duration_seconds histogram\n");
+        for (route, histogram) in &r.request_seconds {
+            histogram.render(
+                &mut out,
+                "http_request_duration_seconds",
+                &format!("route=\"{route}\""),
+            );
+        }
+
+        out.push_str("# HELP db_query_duration_seconds SQLite query latency\n");
+        out.push_str("# TYPE db_query_duration_seconds histogram\n");
+        for (query, histogram) in &r.db_seconds {
+            histogram.render(
+                &mut out,
+                "db_query_duration_seconds",
+                &format!("query=\"{query}\""),
+            );
+        }
+
+        out.push_str("# HELP subscriptions_total Subscribe attempts by result\n");
+        out.push_str("# TYPE subscriptions_total counter\n");
+        for (result, count) in &r.subscriptions {
+            let _ = writeln!(out, "subscriptions_total{{result=\"{result}\"}} {count}");
+        }
+
+        if let Some(digests) = digests {
+            out.push_str("# HELP digests Digests stored in the database\n");
+            out.push_str("# TYPE digests gauge\n");
+            let _ = writeln!(out, "digests {digests}");
+        }
+        out
+    }
+}
+
+/// Records a query's duration when dropped
+pub struct DbTimer<'a> {
+    metrics: &'a Metrics,
+    query: &'static str,
+    start: Instant,
+}
+
+impl Drop for DbTimer<'_> {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        self.metrics
+            .registry
+            .lock()
+            .unwrap()
+            .db_seconds
+            .entry(self.query)
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+        #[cfg(feature = "otel")]
+        self.metrics.otel.record_db(self.query, elapsed);
+    }
+}
+
+/// Middleware counting requests and latency per matched route (not raw path,
+/// so `/{date}` stays one series)
+pub async fn track(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched".to_string(), |p| p.as_str().to_string());
+    let method = request.method().to_string();
+    let start = Instant::now();
+    let response = next.run(request).await;
+    state
+        .metrics
+        .record_request(&method, &route, response.status().as_u16(), start.elapsed());
+    response
+}
+
+/// Prometheus scrape endpoint
+pub async fn metrics(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let digests = state
+        .blocking(|state| {
+            let _timer = state.metrics.time_db("count_digests");
+            state
+                .storage
+                .digest_count()
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+        })
+        .await
+        .inspect_err(|(_, e)| tracing::warn!("Could not count digests: {}", e))
+        .ok();
+    (
+        StatusCode::OK,
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(digests),
+    )
+}
+
+pub fn count_digests(conn: &Connection) -> Result<i64, String> {
+    if !db::table_exists(conn, "digests")? {
+        return Ok(0);
+    }
+    conn.query_row("SELECT COUNT(*) FROM digests", [], |row| row.get(0))
+        .map_err(|e| format!("Query error: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod histogram {
+        use super::*;
+
+        #[test]
+        fn buckets_are_cumulative() {
+            let mut h = Histogram::default();
+            h.observe(0.001);
+            h.observe(0.2);
+            h.observe(60.0);
+            let mut out = String::new();
+            h.render(&mut out, "t", "route=\"/\"");
+            assert!(out.contains("t_bucket{route=\"/\",le=\"0.005\"} 1\n"));
+            assert!(out.contains("t_bucket{route=\"/\",le=\"0.25\"} 2\n"));
+            assert!(out.contains("t_bucket{route=\"/\",le=\"10\"} 2\n"));
+            assert!(out.contains("t_bucket{route=\"/\",le=\"+Inf\"} 3\n"));
+            assert!(out.contains("t_count{route=\"/\"} 3\n"));
+        }
+    }
+
+    mod render {
+        use super::*;
+
+        #[test]
+        fn includes_all_families() {
+            let m = Metrics::default();
+            m.record_request("GET", "/{date}", 200, Duration::from_millis(3));
+            m.record_subscription("subscribed");
+            drop(m.time_db("get_digest"));
+            let out = m.render(Some(4));
+            assert!(out.contains(
+                "http_requests_total{method=\"GET\",route=\"/{date}\",status=\"200\"} 1\n"
+            ));
+            assert!(out.contains("http_request_duration_seconds_count{route=\"/{date}\"} 1\n"));
+            assert!(out.contains("db_query_duration_seconds_count{query=\"get_digest\"} 1\n"));
+            assert!(out.contains("subscriptions_total{result=\"subscribed\"} 1\n"));
+            assert!(out.contains("digests 4\n"));
+        }
+    }
+}
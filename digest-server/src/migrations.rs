@@ -0,0 +1,494 @@
+//! Versioned schema migrations, applied in order at startup.
+//!
+//! Each migration runs once, in its own transaction, and is recorded in
+//! `schema_migrations`. Append new ones to `MIGRATIONS`; never edit or
+//! reorder one that has shipped.
+
+use crate::{
+    blobs, compression, db, engagement, health, links, pageviews, revisions, rollups, subscribers,
+};
+use rusqlite::Connection;
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    up: fn(&Connection) -> Result<(), String>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "subscribers",
+        up: |conn| {
+            batch(conn, subscribers::SCHEMA)?;
+            subscribers::migrate(conn)
+        },
+    },
+    Migration {
+        version: 2,
+        name: "health_checks",
+        up: |conn| batch(conn, health::SCHEMA),
+    },
+    Migration {
+        version: 3,
+        name: "digest_views",
+        up: |conn| batch(conn, pageviews::SCHEMA),
+    },
+    Migration {
+        version: 4,
+        name: "link_clicks",
+        up: |conn| batch(conn, links::SCHEMA),
+    },
+    Migration {
+        version: 5,
+        name: "email_events",
+        up: |conn| batch(conn, engagement::SCHEMA),
+    },
+    Migration {
+        version: 6,
+        name: "weekly_rollups",
+        up: |conn| batch(conn, rollups::SCHEMA),
+    },
+    Migration {
+        version: 7,
+        name: "pipeline_columns",
+        up: pipeline_columns,
+    },
+    Migration {
+        version: 8,
+        name: "digest_html_zstd",
+        up: |conn| {
+            if !db::table_exists(conn, "digests")?
+                || db::column_exists(conn, "digests", "html_zstd")?
+            {
+                return Ok(());
+            }
+            batch(conn, "ALTER TABLE digests ADD COLUMN html_zstd BLOB")
+        },
+    },
+    Migration {
+        version: 9,
+        name: "digest_revisions",
+        up: revisions::migrate,
+    },
+    Migration {
+        version: 10,
+        name: "digest_updated_at",
+        up: digest_updated_at,
+    },
+    Migration {
+        version: 11,
+        name: "digest_blobs",
+        up: blobs::migrate,
+    },
+    Migration {
+        version: 12,
+        name: "source_health_filtered_articles",
+        up: source_health_filtered_articles,
+    },
+    Migration {
+        version: 13,
+        name: "digest_html_sha256",
+        up: digest_html_sha256,
+    },
+];
+
+fn batch(conn: &Connection, sql: &str) -> Result<(), String> {
+    conn.execute_batch(sql).map_err(|e| e.to_string())
+}
+
+/// Columns stats reads that older pipelines didn't create. Tables the
+/// pipeline hasn't created yet are left alone.
+fn pipeline_columns(conn: &Connection) -> Result<(), String> {
+    let columns = [
+        ("digest_runs", "articles_emailed", "INTEGER DEFAULT 0"),
+        ("digest_runs", "duration_ms", "INTEGER"),
+        ("digest_runs", "input_tokens", "INTEGER"),
+        ("digest_runs", "output_tokens", "INTEGER"),
+        ("digest_runs", "cost_usd", "REAL"),
+        ("shown_narratives", "source_id", "TEXT"),
+        ("shown_narratives", "topic", "TEXT"),
+        ("source_health", "fetch_ms", "INTEGER"),
+        ("source_health", "new_articles", "INTEGER"),
+    ];
+    for (table, column, column_type) in columns {
+        if db::table_exists(conn, table)? && !db::column_exists(conn, table, column)? {
+            batch(
+                conn,
+                &format!("ALTER TABLE {table} ADD COLUMN {column} {column_type}"),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// How many articles each fetch's keyword filters dropped, for stats
+fn source_health_filtered_articles(conn: &Connection) -> Result<(), String> {
+    if !db::table_exists(conn, "source_health")?
+        || db::column_exists(conn, "source_health", "filtered_articles")?
+    {
+        return Ok(());
+    }
+    batch(
+        conn,
+        "ALTER TABLE source_health ADD COLUMN filtered_articles INTEGER",
+    )
+}
+
+/// The hash of each compressed digest's body, backfilled for those still in
+/// the database (offloaded ones get theirs if they are ever compressed again)
+fn digest_html_sha256(conn: &Connection) -> Result<(), String> {
+    if !db::table_exists(conn, "digests")? {
+        return Ok(());
+    }
+    if !db::column_exists(conn, "digests", "html_sha256")? {
+        batch(conn, "ALTER TABLE digests ADD COLUMN html_sha256 TEXT")?;
+    }
+    let compressed: Vec<(String, Vec<u8>)> = {
+        let mut stmt = conn
+            .prepare(
+                "SELECT date, html_zstd FROM digests WHERE html = '' AND html_zstd IS NOT NULL",
+            )
+            .map_err(|e| e.to_string())?;
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?
+            .filter_map(|r| r.ok())
+            .collect()
+    };
+    for (date, bytes) in compressed {
+        // A row that doesn't decompress just keeps no hash
+        let Ok(html) = compression::html(String::new(), Some(bytes)) else {
+            continue;
+        };
+        conn.execute(
+            "UPDATE digests SET html_sha256 = ?1 WHERE date = ?2",
+            [compression::sha256(&html), date],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Stamp `digests.updated_at` (to the millisecond, for ETags) on every insert
+/// and real edit; compression's rewrites leave it alone
+const UPDATED_AT_TRIGGERS: &str = "
+CREATE TRIGGER IF NOT EXISTS digests_updated_at_on_insert AFTER INSERT ON digests
+WHEN NEW.updated_at IS NULL
+BEGIN
+    UPDATE digests SET updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now') WHERE rowid = NEW.rowid;
+END;
+CREATE TRIGGER IF NOT EXISTS digests_updated_at_on_update AFTER UPDATE OF html ON digests
+WHEN NEW.html != '' AND NEW.html IS NOT OLD.html
+BEGIN
+    UPDATE digests SET updated_at = strftime('%Y-%m-%d %H:%M:%f', 'now') WHERE rowid = NEW.rowid;
+END;
+";
+
+/// When each digest last changed, backfilled from when it was stored
+fn digest_updated_at(conn: &Connection) -> Result<(), String> {
+    if !db::table_exists(conn, "digests")? {
+        return Ok(());
+    }
+    if !db::column_exists(conn, "digests", "updated_at")? {
+        batch(conn, "ALTER TABLE digests ADD COLUMN updated_at DATETIME")?;
+    }
+    batch(
+        conn,
+        "UPDATE digests SET updated_at = COALESCE(created_at, datetime('now')) WHERE updated_at IS NULL",
+    )?;
+    batch(conn, UPDATED_AT_TRIGGERS)
+}
+
+/// The pipeline's core tables, as `run.py` creates them
+const PIPELINE_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS digest_runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    run_at DATETIME DEFAULT (datetime('now', 'utc')),
+    articles_fetched INTEGER,
+    articles_emailed INTEGER,
+    duration_ms INTEGER,
+    input_tokens INTEGER,
+    output_tokens INTEGER,
+    cost_usd REAL
+);
+CREATE TABLE IF NOT EXISTS shown_narratives (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    headline TEXT NOT NULL,
+    tier TEXT,
+    source_id TEXT,
+    topic TEXT,
+    shown_at DATETIME DEFAULT (datetime('now', 'utc'))
+);
+CREATE TABLE IF NOT EXISTS source_health (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    source_id TEXT NOT NULL,
+    success INTEGER NOT NULL,
+    error_message TEXT,
+    fetch_ms INTEGER,
+    new_articles INTEGER,
+    filtered_articles INTEGER,
+    recorded_at DATETIME DEFAULT (datetime('now', 'utc'))
+);
+CREATE TABLE IF NOT EXISTS digests (
+    date TEXT PRIMARY KEY,
+    html TEXT NOT NULL,
+    created_at DATETIME DEFAULT (datetime('now', 'utc')),
+    updated_at DATETIME DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now'))
+);
+CREATE INDEX IF NOT EXISTS idx_shown_narratives_date ON shown_narratives(shown_at);
+CREATE INDEX IF NOT EXISTS idx_shown_narratives_source ON shown_narratives(source_id);
+CREATE INDEX IF NOT EXISTS idx_digest_runs_date ON digest_runs(run_at);
+CREATE INDEX IF NOT EXISTS idx_source_health_source ON source_health(source_id, recorded_at);
+CREATE INDEX IF NOT EXISTS idx_digests_date ON digests(date);
+";
+
+/// Create the pipeline's core tables in a database it has never written to,
+/// so the server can start before the first pipeline run. Returns whether it
+/// did; databases that already have `digests` are left alone.
+pub fn bootstrap(conn: &mut Connection) -> Result<bool, String> {
+    if db::table_exists(conn, "digests")? {
+        return Ok(false);
+    }
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Cannot create tables: {e}"))?;
+    batch(&tx, PIPELINE_SCHEMA).map_err(|e| format!("Cannot create tables: {e}"))?;
+    tx.commit()
+        .map_err(|e| format!("Cannot create tables: {e}"))?;
+    Ok(true)
+}
+
+/// Highest applied migration, 0 for a database that has never been migrated
+pub fn version(conn: &Connection) -> Result<i64, String> {
+    if !db::table_exists(conn, "schema_migrations")? {
+        return Ok(0);
+    }
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
+        [],
+        |row| row.get(0),
+    )
+    .map_err(|e| format!("Query error: {e}"))
+}
+
+/// Newest migration this build knows about
+pub fn latest() -> i64 {
+    MIGRATIONS.last().map_or(0, |m| m.version)
+}
+
+/// Apply every pending migration, returning the ones applied
+pub fn run(conn: &mut Connection) -> Result<Vec<&'static Migration>, String> {
+    apply(conn, MIGRATIONS)
+}
+
+fn apply<'a>(
+    conn: &mut Connection,
+    migrations: &'a [Migration],
+) -> Result<Vec<&'a Migration>, String> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+             version INTEGER PRIMARY KEY,
+             name TEXT NOT NULL,
+             applied_at DATETIME DEFAULT (datetime('now'))
+         )",
+    )
+    .map_err(|e| format!("Cannot create schema_migrations table: {e}"))?;
+    let current = version(conn)?;
+    let mut applied = Vec::new();
+    for migration in migrations.iter().filter(|m| m.version > current) {
+        let failed = |e| {
+            format!(
+                "Migration {} ({}) failed: {e}",
+                migration.version, migration.name
+            )
+        };
+        let tx = conn.transaction().map_err(|e| failed(e.to_string()))?;
+        (migration.up)(&tx).map_err(failed)?;
+        tx.execute(
+            "INSERT INTO schema_migrations (version, name) VALUES (?1, ?2)",
+            rusqlite::params![migration.version, migration.name],
+        )
+        .map_err(|e| failed(e.to_string()))?;
+        tx.commit().map_err(|e| failed(e.to_string()))?;
+        applied.push(migration);
+    }
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod bootstrap {
+        use super::*;
+
+        #[test]
+        fn creates_pipeline_tables_in_empty_database() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            assert!(bootstrap(&mut conn).unwrap());
+            assert!(!bootstrap(&mut conn).unwrap());
+            run(&mut conn).unwrap();
+            for table in [
+                "digests",
+                "digest_runs",
+                "source_health",
+                "shown_narratives",
+            ] {
+                assert!(db::table_exists(&conn, table).unwrap(), "{table}");
+            }
+            assert!(db::column_exists(&conn, "digests", "html_zstd").unwrap());
+        }
+
+        #[test]
+        fn leaves_pipeline_databases_alone() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch("CREATE TABLE digests (date TEXT PRIMARY KEY, html TEXT)")
+                .unwrap();
+            assert!(!bootstrap(&mut conn).unwrap());
+            assert!(!db::table_exists(&conn, "digest_runs").unwrap());
+        }
+    }
+
+    mod run {
+        use super::*;
+
+        #[test]
+        fn applies_each_migration_once() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            assert_eq!(version(&conn).unwrap(), 0);
+            assert_eq!(run(&mut conn).unwrap().len(), MIGRATIONS.len());
+            assert_eq!(version(&conn).unwrap(), latest());
+            assert!(run(&mut conn).unwrap().is_empty());
+            for table in [
+                "subscribers",
+                "health_checks",
+                "digest_views",
+                "link_clicks",
+            ] {
+                assert!(db::table_exists(&conn, table).unwrap(), "{table}");
+            }
+        }
+
+        #[test]
+        fn adds_columns_to_old_pipeline_tables() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(
+                "CREATE TABLE digests (date TEXT PRIMARY KEY, html TEXT);
+                 CREATE TABLE digest_runs (id INTEGER PRIMARY KEY, run_at DATETIME, articles_fetched INTEGER);
+                 CREATE TABLE source_health (source_id TEXT, success INTEGER, recorded_at DATETIME);",
+            )
+            .unwrap();
+            run(&mut conn).unwrap();
+            assert!(db::column_exists(&conn, "digest_runs", "cost_usd").unwrap());
+            assert!(db::column_exists(&conn, "source_health", "new_articles").unwrap());
+            assert!(db::column_exists(&conn, "source_health", "filtered_articles").unwrap());
+            assert!(db::column_exists(&conn, "digests", "html_zstd").unwrap());
+            assert!(!db::table_exists(&conn, "shown_narratives").unwrap());
+        }
+
+        #[test]
+        fn adds_filtered_articles_to_databases_past_pipeline_columns() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(
+                "CREATE TABLE source_health (source_id TEXT, success INTEGER, recorded_at DATETIME);",
+            )
+            .unwrap();
+            apply(&mut conn, &MIGRATIONS[..11]).unwrap();
+            assert!(!db::column_exists(&conn, "source_health", "filtered_articles").unwrap());
+            run(&mut conn).unwrap();
+            assert!(db::column_exists(&conn, "source_health", "filtered_articles").unwrap());
+        }
+
+        #[test]
+        fn failed_migration_is_rolled_back() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            let migrations = [
+                Migration {
+                    version: 1,
+                    name: "ok",
+                    up: |conn| batch(conn, "CREATE TABLE a (x)"),
+                },
+                Migration {
+                    version: 2,
+                    name: "broken",
+                    up: |conn| batch(conn, "CREATE TABLE b (x); NOT SQL"),
+                },
+            ];
+            let Err(err) = apply(&mut conn, &migrations) else {
+                panic!("broken migration applied");
+            };
+            assert!(err.starts_with("Migration 2 (broken) failed"), "{err}");
+            assert_eq!(version(&conn).unwrap(), 1);
+            assert!(!db::table_exists(&conn, "b").unwrap());
+        }
+    }
+
+    mod digest_updated_at {
+        use super::*;
+
+        fn updated_at(conn: &Connection, date: &str) -> String {
+            conn.query_row(
+                "SELECT updated_at FROM digests WHERE date = ?1",
+                [date],
+                |row| row.get(0),
+            )
+            .unwrap()
+        }
+
+        #[test]
+        fn stamps_inserts_and_edits() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(
+                "CREATE TABLE digests (date TEXT PRIMARY KEY, html TEXT, created_at DATETIME);
+                 INSERT INTO digests VALUES ('2026-01-01', 'old', '2026-01-01 06:00:00');",
+            )
+            .unwrap();
+            run(&mut conn).unwrap();
+            assert_eq!(updated_at(&conn, "2026-01-01"), "2026-01-01 06:00:00");
+
+            conn.execute_batch(
+                "INSERT INTO digests (date, html) VALUES ('2026-01-02', 'new');
+                 UPDATE digests SET html = '', html_zstd = x'00' WHERE date = '2026-01-01';",
+            )
+            .unwrap();
+            assert_eq!(updated_at(&conn, "2026-01-01"), "2026-01-01 06:00:00");
+            assert!(updated_at(&conn, "2026-01-02").contains('.'));
+
+            conn.execute(
+                "UPDATE digests SET html = 'fixed' WHERE date = '2026-01-01'",
+                [],
+            )
+            .unwrap();
+            assert_ne!(updated_at(&conn, "2026-01-01"), "2026-01-01 06:00:00");
+        }
+    }
+
+    mod digest_html_sha256 {
+        use super::*;
+
+        #[test]
+        fn backfills_compressed_digests() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(
+                "CREATE TABLE digests (date TEXT PRIMARY KEY, html TEXT NOT NULL, html_zstd BLOB);
+                 INSERT INTO digests (date, html) VALUES ('2026-01-01', '<p>plain</p>');",
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO digests (date, html, html_zstd) VALUES ('2026-01-02', '', ?1)",
+                [zstd::encode_all(&b"<p>packed</p>"[..], 3).unwrap()],
+            )
+            .unwrap();
+            run(&mut conn).unwrap();
+            assert!(compression::unchanged(&conn, "2026-01-02", "<p>packed</p>").unwrap());
+            let plain: Option<String> = conn
+                .query_row(
+                    "SELECT html_sha256 FROM digests WHERE date = '2026-01-01'",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(plain, None);
+        }
+    }
+}
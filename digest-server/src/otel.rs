@@ -0,0 +1,136 @@
+//! Optional OTLP export of traces and metrics (`otel` feature).
+//!
+//! Off unless `OTEL_EXPORTER_OTLP_ENDPOINT` is set. Endpoint, headers,
+//! timeout and service name come from the standard `OTEL_*` variables, read by
+//! the exporter itself. Export is HTTP/protobuf; the Prometheus endpoint and
+//! log output are unaffected.
+
+use axum::http::HeaderMap;
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Counter, Histogram};
+use opentelemetry::propagation::Extractor;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::metrics::SdkMeterProvider;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use std::time::Duration;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::Layer;
+use tracing_subscriber::registry::LookupSpan;
+
+const SERVICE_NAME: &str = "digest-server";
+
+/// Build the exporters and return a tracing layer feeding them, or `None`
+/// when no endpoint is configured. Also installs the global meter provider
+/// and W3C trace-context propagator.
+pub fn layer<S>() -> Result<Option<impl Layer<S>>, String>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    if std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").map_or(true, |v| v.is_empty()) {
+        return Ok(None);
+    }
+    let resource = if std::env::var("OTEL_SERVICE_NAME").is_ok() {
+        Resource::builder().build()
+    } else {
+        Resource::builder().with_service_name(SERVICE_NAME).build()
+    };
+
+    let spans = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .build()
+        .map_err(|e| format!("Cannot create OTLP span exporter: {e}"))?;
+    let tracer_provider = SdkTracerProvider::builder()
+        .with_batch_exporter(spans)
+        .with_resource(resource.clone())
+        .build();
+
+    let metrics = opentelemetry_otlp::MetricExporter::builder()
+        .with_http()
+        .build()
+        .map_err(|e| format!("Cannot create OTLP metric exporter: {e}"))?;
+    let meter_provider = SdkMeterProvider::builder()
+        .with_periodic_exporter(metrics)
+        .with_resource(resource)
+        .build();
+
+    opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+    opentelemetry::global::set_meter_provider(meter_provider);
+    let tracer = tracer_provider.tracer(SERVICE_NAME);
+    opentelemetry::global::set_tracer_provider(tracer_provider);
+    Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+/// Continue the caller's trace when the request carries a `traceparent` header
+pub fn set_parent(span: &tracing::Span, headers: &HeaderMap) {
+    let cx =
+        opentelemetry::global::get_text_map_propagator(|p| p.extract(&HeaderExtractor(headers)));
+    let _ = span.set_parent(cx);
+}
+
+/// OTLP counterparts of the Prometheus series in `metrics`. Recording is a
+/// no-op until `layer` has installed a meter provider.
+pub struct Instruments {
+    request_duration: Histogram<f64>,
+    db_duration: Histogram<f64>,
+    subscriptions: Counter<u64>,
+}
+
+impl Default for Instruments {
+    fn default() -> Self {
+        let meter = opentelemetry::global::meter(SERVICE_NAME);
+        Self {
+            request_duration: meter
+                .f64_histogram("http.server.request.duration")
+                .with_unit("s")
+                .build(),
+            db_duration: meter
+                .f64_histogram("db.client.operation.duration")
+                .with_unit("s")
+                .build(),
+            subscriptions: meter.u64_counter("digest.subscriptions").build(),
+        }
+    }
+}
+
+impl Instruments {
+    pub fn record_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        self.request_duration.record(
+            elapsed.as_secs_f64(),
+            &[
+                KeyValue::new("http.request.method", method.to_string()),
+                KeyValue::new("http.route", route.to_string()),
+                KeyValue::new("http.response.status_code", i64::from(status)),
+            ],
+        );
+    }
+
+    pub fn record_db(&self, query: &'static str, elapsed: Duration) {
+        self.db_duration.record(
+            elapsed.as_secs_f64(),
+            &[
+                KeyValue::new("db.system.name", "sqlite"),
+                KeyValue::new("db.operation.name", query),
+            ],
+        );
+    }
+
+    pub fn record_subscription(&self, result: &'static str) {
+        self.subscriptions
+            .add(1, &[KeyValue::new("result", result)]);
+    }
+}
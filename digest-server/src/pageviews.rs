@@ -0,0 +1,259 @@
+//! Cookie-free pageview counts for digest pages.
+//!
+//! Readers are deduplicated per day by a truncated hash of IP + user agent,
+//! salted with a random key that lives only in memory: hashes can't be
+//! reversed or linked across restarts, and no raw IP is ever stored.
+
+use crate::db;
+use axum::http::HeaderMap;
+use ring::{digest, rand::SecureRandom};
+use rusqlite::Connection;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::LazyLock;
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS digest_views (
+    digest_date TEXT NOT NULL,
+    day TEXT NOT NULL,
+    visitor TEXT NOT NULL,
+    views INTEGER NOT NULL DEFAULT 1,
+    PRIMARY KEY (digest_date, day, visitor)
+);
+";
+
+/// User agents that aren't readers
+const BOT_MARKERS: [&str; 6] = ["bot", "spider", "crawl", "preview", "monitor", "curl"];
+
+/// Random per-process salt for visitor hashes
+pub fn new_salt() -> [u8; 32] {
+    let mut salt = [0u8; 32];
+    ring::rand::SystemRandom::new()
+        .fill(&mut salt)
+        .expect("system RNG unavailable");
+    salt
+}
+
+/// Running on Fly.io, whose proxy sets `Fly-Client-IP`
+static ON_FLY: LazyLock<bool> = LazyLock::new(|| std::env::var_os("FLY_APP_NAME").is_some());
+
+/// Client IP: the peer, or, when that's a proxy, whom it forwards for
+pub fn client_ip(headers: &HeaderMap, peer: SocketAddr) -> IpAddr {
+    forwarded_client(headers, peer, *ON_FLY)
+}
+
+/// Proxies in front connect from loopback or private addresses (Fly's proxy,
+/// a local nginx); anyone else could have written the forwarding headers.
+fn is_proxy(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_private(),
+        IpAddr::V6(ip) => ip.is_loopback() || ip.is_unique_local(),
+    }
+}
+
+fn forwarded_client(headers: &HeaderMap, peer: SocketAddr, on_fly: bool) -> IpAddr {
+    if !is_proxy(peer.ip()) {
+        return peer.ip();
+    }
+    if on_fly
+        && let Some(ip) = headers
+            .get("fly-client-ip")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok())
+    {
+        return ip;
+    }
+    // Each proxy appends whom it heard from, so the client is the rightmost
+    // hop that isn't a proxy; whatever is left of it, the client could have sent
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .rev()
+        .flat_map(|v| v.to_str().unwrap_or_default().rsplit(','))
+        .map_while(|hop| hop.trim().parse::<IpAddr>().ok())
+        .find(|ip| !is_proxy(*ip))
+        .unwrap_or(peer.ip())
+}
+
+/// Truncated salted hash identifying a visitor for one day only
+fn visitor_hash(salt: &[u8], day: &str, ip: &str, user_agent: &str) -> String {
+    let mut ctx = digest::Context::new(&digest::SHA256);
+    for part in [salt, day.as_bytes(), ip.as_bytes(), user_agent.as_bytes()] {
+        ctx.update(part);
+        ctx.update(b"\0");
+    }
+    ctx.finish().as_ref()[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn is_bot(user_agent: &str) -> bool {
+    let ua = user_agent.to_lowercase();
+    ua.is_empty() || BOT_MARKERS.iter().any(|m| ua.contains(m))
+}
+
+/// Count a view of a digest page
+pub fn record(
+    db_path: &str,
+    salt: &[u8],
+    digest_date: &str,
+    headers: &HeaderMap,
+    peer: SocketAddr,
+) -> Result<(), String> {
+    let user_agent = headers
+        .get("user-agent")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if is_bot(user_agent) {
+        return Ok(());
+    }
+    let conn = db::open_rw(db_path)?;
+    let day: String = conn
+        .query_row("SELECT date('now')", [], |row| row.get(0))
+        .map_err(|e| format!("Query error: {e}"))?;
+    let visitor = visitor_hash(
+        salt,
+        &day,
+        &client_ip(headers, peer).to_string(),
+        user_agent,
+    );
+    conn.execute(
+        "INSERT INTO digest_views (digest_date, day, visitor) VALUES (?1, ?2, ?3)
+         ON CONFLICT (digest_date, day, visitor) DO UPDATE SET views = views + 1",
+        rusqlite::params![digest_date, day, visitor],
+    )
+    .map_err(|e| format!("Cannot save view: {e}"))?;
+    Ok(())
+}
+
+/// Views of one digest within the stats window
+#[derive(Clone)]
+pub struct DigestViews {
+    pub digest_date: String,
+    pub views: i64,
+    /// Unique readers per day, summed over the window
+    pub readers: i64,
+}
+
+/// Most-viewed digests within the range
+pub fn top_viewed(
+    conn: &Connection,
+    range: &db::DateRange,
+    limit: u32,
+) -> Result<Vec<DigestViews>, String> {
+    if !db::table_exists(conn, "digest_views")? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn
+        .prepare(
+            "SELECT digest_date, SUM(views), COUNT(*)
+             FROM digest_views
+             WHERE day BETWEEN ?1 AND ?2
+             GROUP BY digest_date
+             ORDER BY SUM(views) DESC, digest_date DESC
+             LIMIT ?3",
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    let rows = stmt
+        .query_map(rusqlite::params![range.from, range.to, limit], |row| {
+            Ok(DigestViews {
+                digest_date: row.get(0)?,
+                views: row.get(1)?,
+                readers: row.get(2)?,
+            })
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod visitor_hash {
+        use super::*;
+
+        #[test]
+        fn stable_within_a_day() {
+            let a = visitor_hash(b"salt", "2026-01-24", "1.2.3.4", "Firefox");
+            assert_eq!(a, visitor_hash(b"salt", "2026-01-24", "1.2.3.4", "Firefox"));
+            assert_eq!(a.len(), 16);
+        }
+
+        #[test]
+        fn changes_with_day_and_salt() {
+            let a = visitor_hash(b"salt", "2026-01-24", "1.2.3.4", "Firefox");
+            assert_ne!(a, visitor_hash(b"salt", "2026-01-25", "1.2.3.4", "Firefox"));
+            assert_ne!(
+                a,
+                visitor_hash(b"other", "2026-01-24", "1.2.3.4", "Firefox")
+            );
+        }
+    }
+
+    mod forwarded_client {
+        use super::*;
+
+        fn ip(ip: &str) -> IpAddr {
+            ip.parse().unwrap()
+        }
+
+        fn forwarded(value: &str) -> HeaderMap {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-forwarded-for", value.parse().unwrap());
+            headers
+        }
+
+        #[test]
+        fn takes_the_rightmost_hop_past_the_proxies() {
+            let proxy: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+            assert_eq!(
+                forwarded_client(&HeaderMap::new(), proxy, false),
+                ip("10.0.0.1")
+            );
+            assert_eq!(
+                forwarded_client(&forwarded("5.6.7.8, 10.0.0.2"), proxy, false),
+                ip("5.6.7.8")
+            );
+            // What the client sent comes first, and doesn't count
+            assert_eq!(
+                forwarded_client(&forwarded("1.1.1.1, 5.6.7.8"), proxy, false),
+                ip("5.6.7.8")
+            );
+            assert_eq!(
+                forwarded_client(&forwarded("5.6.7.8, garbage"), proxy, false),
+                ip("10.0.0.1")
+            );
+        }
+
+        #[test]
+        fn ignores_forwarding_headers_from_anyone_else() {
+            let direct: SocketAddr = "203.0.113.9:5000".parse().unwrap();
+            let mut headers = forwarded("5.6.7.8");
+            headers.insert("fly-client-ip", "9.9.9.9".parse().unwrap());
+            assert_eq!(forwarded_client(&headers, direct, true), ip("203.0.113.9"));
+        }
+
+        #[test]
+        fn trusts_fly_client_ip_only_on_fly() {
+            let proxy: SocketAddr = "[fdaa::2]:5000".parse().unwrap();
+            let mut headers = forwarded("5.6.7.8");
+            headers.insert("fly-client-ip", "9.9.9.9".parse().unwrap());
+            assert_eq!(forwarded_client(&headers, proxy, true), ip("9.9.9.9"));
+            assert_eq!(forwarded_client(&headers, proxy, false), ip("5.6.7.8"));
+        }
+    }
+
+    mod is_bot {
+        use super::*;
+
+        #[test]
+        fn filters_crawlers() {
+            assert!(is_bot("Googlebot/2.1"));
+            assert!(is_bot(""));
+            assert!(!is_bot("Mozilla/5.0 (Macintosh) Safari/605.1.15"));
+        }
+    }
+}
@@ -0,0 +1,743 @@
+//! Postgres storage backend (`postgres` feature).
+//!
+//! Selected when `DATABASE_URL` is a `postgres://` URL. Digests and the
+//! pipeline's `digest_runs`, `source_health`, and `shown_narratives` tables are
+//! read with the same columns as in SQLite; the server creates its own
+//! subscriber tables on startup. Date arithmetic for stats windows reuses
+//! `db::DateRange` on an in-memory SQLite connection so both backends agree.
+
+use crate::blobs::{self, BlobStore};
+use crate::preview::Preview;
+use crate::revisions::Revision;
+use crate::runs::Checkpoint;
+use crate::storage::Storage;
+use crate::{
+    DigestRun, SourceHealth, SourceUsage, StatsData, StatsQuery, db, slo, subscribers, timeline,
+    topics,
+};
+use axum::http::StatusCode;
+use postgres::types::ToSql;
+use r2d2_postgres::PostgresConnectionManager;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_postgres_rustls::MakeRustlsConnect;
+
+type Pool = r2d2::Pool<PostgresConnectionManager<MakeRustlsConnect>>;
+type Client = r2d2::PooledConnection<PostgresConnectionManager<MakeRustlsConnect>>;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS subscribers (
+    email TEXT NOT NULL,
+    audience TEXT NOT NULL DEFAULT 'default',
+    status TEXT NOT NULL DEFAULT 'subscribed',
+    created_at TIMESTAMP DEFAULT (now() AT TIME ZONE 'utc'),
+    updated_at TIMESTAMP DEFAULT (now() AT TIME ZONE 'utc'),
+    PRIMARY KEY (email, audience)
+);
+
+CREATE TABLE IF NOT EXISTS subscription_events (
+    id BIGSERIAL PRIMARY KEY,
+    email TEXT NOT NULL,
+    audience TEXT NOT NULL DEFAULT 'default',
+    event TEXT NOT NULL,
+    occurred_at TIMESTAMP DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+CREATE TABLE IF NOT EXISTS unsubscribe_feedback (
+    id BIGSERIAL PRIMARY KEY,
+    reason TEXT NOT NULL,
+    comment TEXT,
+    submitted_at TIMESTAMP DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+CREATE INDEX IF NOT EXISTS idx_subscription_events_date ON subscription_events(occurred_at);
+CREATE INDEX IF NOT EXISTS idx_unsubscribe_feedback_date ON unsubscribe_feedback(submitted_at);
+";
+
+/// Rows within the range for a timestamp column, with the bounds bound as $1 and $2
+fn in_range(column: &str) -> String {
+    format!("{column}::timestamp >= $1::text::date AND {column}::timestamp < $2::text::date + 1")
+}
+
+pub struct Postgres {
+    pool: Pool,
+    /// Where bodies of digests copied with `html_blob` keys live
+    blobs: Option<Arc<dyn BlobStore>>,
+}
+
+impl Postgres {
+    /// Connect with `size` pooled connections (TLS when the server or
+    /// `sslmode` asks for it), check for the digests table, and create the
+    /// subscriber tables. Blocks, so call it off the async runtime.
+    pub fn connect(
+        url: &str,
+        size: u32,
+        blobs: Option<Arc<dyn BlobStore>>,
+    ) -> Result<Self, String> {
+        let config: postgres::Config = url
+            .parse()
+            .map_err(|e| format!("Invalid DATABASE_URL: {e}"))?;
+        let roots = rustls::RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        let tls = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| format!("TLS error: {e}"))?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        let manager = PostgresConnectionManager::new(config, MakeRustlsConnect::new(tls));
+        let pool = r2d2::Pool::builder()
+            .max_size(size)
+            .connection_timeout(Duration::from_secs(5))
+            .build(manager)
+            .map_err(|e| format!("Cannot connect to Postgres: {e}"))?;
+
+        let storage = Self { pool, blobs };
+        let mut client = storage.client()?;
+        if !table_exists(&mut client, "digests")? {
+            return Err("Table 'digests' not found".into());
+        }
+        client
+            .batch_execute(SCHEMA)
+            .map_err(|e| format!("Cannot create subscribers table: {e}"))?;
+        Ok(storage)
+    }
+
+    fn client(&self) -> Result<Client, String> {
+        self.pool.get().map_err(|e| format!("DB error: {e}"))
+    }
+}
+
+/// Record a subscribe or unsubscribe in `tx`, logging a subscription event on
+/// change, as `subscribers::upsert` does
+fn upsert_subscriber(
+    tx: &mut postgres::Transaction,
+    email: &str,
+    audience: &str,
+    subscribed: bool,
+) -> Result<(), String> {
+    let email = subscribers::normalize_email(email);
+    let status = if subscribed {
+        "subscribed"
+    } else {
+        "unsubscribed"
+    };
+    let current: Option<String> = tx
+        .query_opt(
+            "SELECT status FROM subscribers WHERE email = $1 AND audience = $2 FOR UPDATE",
+            &[&email, &audience],
+        )
+        .map_err(|e| format!("Query error: {e}"))?
+        .map(|row| row.get(0));
+    if current.as_deref() == Some(status) {
+        return Ok(());
+    }
+    tx.execute(
+        "INSERT INTO subscribers (email, audience, status) VALUES ($1, $2, $3)
+         ON CONFLICT (email, audience)
+         DO UPDATE SET status = $3, updated_at = now() AT TIME ZONE 'utc'",
+        &[&email, &audience, &status],
+    )
+    .map_err(|e| format!("Cannot save subscriber: {e}"))?;
+    // Only someone who was subscribed can unsubscribe (see subscribers::upsert)
+    if subscribed || current.as_deref() == Some("subscribed") {
+        tx.execute(
+            "INSERT INTO subscription_events (email, audience, event) VALUES ($1, $2, $3)",
+            &[&email, &audience, &status],
+        )
+        .map_err(|e| format!("Cannot record subscription event: {e}"))?;
+    }
+    Ok(())
+}
+
+fn table_exists(client: &mut Client, name: &str) -> Result<bool, String> {
+    client
+        .query_one("SELECT to_regclass($1) IS NOT NULL", &[&name])
+        .map(|row| row.get(0))
+        .map_err(|e| format!("Query error: {e}"))
+}
+
+fn column_exists(client: &mut Client, table: &str, column: &str) -> Result<bool, String> {
+    client
+        .query_opt(
+            "SELECT 1 FROM information_schema.columns
+             WHERE table_schema = current_schema() AND table_name = $1 AND column_name = $2",
+            &[&table, &column],
+        )
+        .map(|row| row.is_some())
+        .map_err(|e| format!("Query error: {e}"))
+}
+
+/// Run a query, mapping each row; query errors are surfaced, bad rows skipped
+fn rows<T>(
+    client: &mut Client,
+    sql: &str,
+    params: &[&(dyn ToSql + Sync)],
+    map: impl Fn(&postgres::Row) -> Result<T, postgres::Error>,
+) -> Result<Vec<T>, String> {
+    Ok(client
+        .query(sql, params)
+        .map_err(|e| format!("Query error: {e}"))?
+        .iter()
+        .filter_map(|row| map(row).ok())
+        .collect())
+}
+
+impl Storage for Postgres {
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+
+    fn probe(&self) -> Result<(), String> {
+        self.client()?
+            .simple_query("SELECT 1")
+            .map(|_| ())
+            .map_err(|e| format!("DB query failed: {e}"))
+    }
+
+    fn digest_dates(&self, limit: u32) -> Result<Vec<String>, String> {
+        rows(
+            &mut self.client()?,
+            "SELECT date::text FROM digests ORDER BY date DESC LIMIT $1",
+            &[&i64::from(limit)],
+            |row| row.try_get(0),
+        )
+    }
+
+    fn digest(&self, date: Option<&str>) -> Result<Option<(String, String)>, String> {
+        let mut client = self.client()?;
+        // Copied from a SQLite database whose digests were compressed or offloaded
+        let compressed = if column_exists(&mut client, "digests", "html_zstd")? {
+            "html_zstd"
+        } else {
+            "NULL::bytea"
+        };
+        let blob = if column_exists(&mut client, "digests", "html_blob")? {
+            "html_blob"
+        } else {
+            "NULL::text"
+        };
+        let row = client
+            .query_opt(
+                &format!(
+                    "SELECT date::text, html, {compressed}, {blob} FROM digests
+                     WHERE $1::text IS NULL OR date::text = $1
+                     ORDER BY date DESC LIMIT 1"
+                ),
+                &[&date],
+            )
+            .map_err(|e| format!("Query error: {e}"))?;
+        row.map(|row| {
+            let html = blobs::html(self.blobs.as_deref(), row.get(1), row.get(2), row.get(3))?;
+            Ok((row.get(0), html))
+        })
+        .transpose()
+    }
+
+    fn digest_version(&self, date: &str) -> Result<Option<(String, Option<String>)>, String> {
+        let mut client = self.client()?;
+        // Copied from a SQLite database that records it
+        let updated_at = if column_exists(&mut client, "digests", "updated_at")? {
+            "updated_at::text"
+        } else {
+            "NULL"
+        };
+        // xmin changes with every write to the row
+        client
+            .query_opt(
+                &format!("SELECT xmin::text, {updated_at} FROM digests WHERE date::text = $1"),
+                &[&date],
+            )
+            .map(|row| row.map(|row| (row.get(0), row.get(1))))
+            .map_err(|e| format!("Query error: {e}"))
+    }
+
+    fn digest_revisions(&self, date: &str) -> Result<Vec<Revision>, String> {
+        let mut client = self.client()?;
+        if !table_exists(&mut client, "digest_revisions")? {
+            return Ok(Vec::new());
+        }
+        let blob = if column_exists(&mut client, "digest_revisions", "html_blob")? {
+            "html_blob"
+        } else {
+            "NULL::text"
+        };
+        client
+            .query(
+                &format!(
+                    "SELECT created_at::text, replaced_at::text, html, html_zstd, {blob}
+                     FROM digest_revisions WHERE digest_date::text = $1 ORDER BY id"
+                ),
+                &[&date],
+            )
+            .map_err(|e| format!("Query error: {e}"))?
+            .into_iter()
+            .map(|row| {
+                Ok(Revision {
+                    created_at: row.get(0),
+                    replaced_at: row.get(1),
+                    html: blobs::html(self.blobs.as_deref(), row.get(2), row.get(3), row.get(4))?,
+                })
+            })
+            .collect()
+    }
+
+    fn digest_updated_at(&self, date: &str) -> Result<Option<String>, String> {
+        let mut client = self.client()?;
+        if !table_exists(&mut client, "digest_revisions")? {
+            return Ok(None);
+        }
+        client
+            .query_one(
+                "SELECT MAX(replaced_at)::text FROM digest_revisions WHERE digest_date::text = $1",
+                &[&date],
+            )
+            .map(|row| row.get(0))
+            .map_err(|e| format!("Query error: {e}"))
+    }
+
+    fn image(&self, hash: &str) -> Result<Option<(String, Vec<u8>)>, String> {
+        let mut client = self.client()?;
+        if !table_exists(&mut client, "images")? {
+            return Ok(None);
+        }
+        client
+            .query_opt(
+                "SELECT content_type, data FROM images WHERE hash = $1",
+                &[&hash],
+            )
+            .map(|row| row.map(|row| (row.get(0), row.get(1))))
+            .map_err(|e| format!("Query error: {e}"))
+    }
+
+    fn digest_preview(&self) -> Result<Option<Preview>, String> {
+        let mut client = self.client()?;
+        if !table_exists(&mut client, "digest_preview")? {
+            return Ok(None);
+        }
+        client
+            .query_opt(
+                "SELECT date::text, html, created_at::text FROM digest_preview WHERE slot = 1",
+                &[],
+            )
+            .map(|row| {
+                row.map(|row| Preview {
+                    date: row.get(0),
+                    html: row.get(1),
+                    created_at: row.get(2),
+                })
+            })
+            .map_err(|e| format!("Query error: {e}"))
+    }
+
+    fn run_checkpoints(&self) -> Result<Vec<Checkpoint>, String> {
+        let mut client = self.client()?;
+        if !table_exists(&mut client, "run_checkpoints")? {
+            return Ok(Vec::new());
+        }
+        client
+            .query(
+                "SELECT stage, dry_run <> 0, state, completed_at::text FROM run_checkpoints
+                 ORDER BY completed_at",
+                &[],
+            )
+            .map(|rows| {
+                rows.iter()
+                    .map(|row| Checkpoint {
+                        stage: row.get(0),
+                        dry_run: row.get(1),
+                        state: serde_json::from_str(row.get(2)).unwrap_or_default(),
+                        completed_at: row.get(3),
+                    })
+                    .collect()
+            })
+            .map_err(|e| format!("Query error: {e}"))
+    }
+
+    fn digest_count(&self) -> Result<i64, String> {
+        self.client()?
+            .query_one("SELECT COUNT(*) FROM digests", &[])
+            .map(|row| row.get(0))
+            .map_err(|e| format!("Query error: {e}"))
+    }
+
+    fn subscribers(&self, audience: &str) -> Result<BTreeMap<String, bool>, String> {
+        rows(
+            &mut self.client()?,
+            "SELECT email, status FROM subscribers WHERE audience = $1",
+            &[&audience],
+            |row| {
+                let email: String = row.try_get(0)?;
+                let status: String = row.try_get(1)?;
+                Ok((subscribers::normalize_email(&email), status == "subscribed"))
+            },
+        )
+        .map(|rows| rows.into_iter().collect())
+    }
+
+    fn set_subscribed(&self, email: &str, audience: &str, subscribed: bool) -> Result<(), String> {
+        let mut client = self.client()?;
+        let mut tx = client.transaction().map_err(|e| format!("DB error: {e}"))?;
+        upsert_subscriber(&mut tx, email, audience, subscribed)?;
+        tx.commit().map_err(|e| format!("DB error: {e}"))
+    }
+
+    fn set_subscriptions(&self, audience: &str, changes: &[(String, bool)]) -> Result<(), String> {
+        let mut client = self.client()?;
+        let mut tx = client.transaction().map_err(|e| format!("DB error: {e}"))?;
+        for (email, subscribed) in changes {
+            upsert_subscriber(&mut tx, email, audience, *subscribed)?;
+        }
+        tx.commit().map_err(|e| format!("DB error: {e}"))
+    }
+
+    fn record_feedback(&self, reason: &str, comment: Option<&str>) -> Result<(), String> {
+        self.client()?
+            .execute(
+                "INSERT INTO unsubscribe_feedback (reason, comment) VALUES ($1, $2)",
+                &[&reason, &comment],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("Cannot save feedback: {e}"))
+    }
+
+    /// The panels backed by the pipeline's core tables and the subscriber
+    /// tables; SQLite-only telemetry panels come back empty
+    fn stats(
+        &self,
+        query: &StatsQuery,
+        _slo: Option<&slo::SloConfig>,
+    ) -> Result<StatsData, (StatusCode, String)> {
+        let mem = rusqlite::Connection::open_in_memory()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("DB error: {e}")))?;
+        let range = db::DateRange::resolve(
+            &mem,
+            query.days.unwrap_or(30),
+            query.from.as_deref(),
+            query.to.as_deref(),
+        )
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+        let previous_range = range
+            .previous(&mem)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        let mut client = self
+            .client()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        stats(&mut client, range, previous_range)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+    }
+}
+
+fn stats(
+    client: &mut Client,
+    range: db::DateRange,
+    previous_range: db::DateRange,
+) -> Result<StatsData, String> {
+    let bounds: [&(dyn ToSql + Sync); 2] = [&range.from, &range.to];
+
+    let mut source_health = Vec::new();
+    if table_exists(client, "source_health")? {
+        let recorded = in_range("recorded_at");
+        let fetch_ms = if column_exists(client, "source_health", "fetch_ms")? {
+            "percentile_disc(0.5) WITHIN GROUP (ORDER BY fetch_ms)::bigint,
+             percentile_disc(0.95) WITHIN GROUP (ORDER BY fetch_ms)::bigint"
+        } else {
+            "NULL::bigint, NULL::bigint"
+        };
+        let filtered = if column_exists(client, "source_health", "filtered_articles")? {
+            "COALESCE(SUM(filtered_articles), 0)::bigint"
+        } else {
+            "0::bigint"
+        };
+        source_health = rows(
+            client,
+            &format!(
+                "SELECT source_id, COUNT(*), SUM(success::int)::bigint, {fetch_ms}, {filtered}
+                 FROM source_health WHERE {recorded}
+                 GROUP BY source_id ORDER BY source_id"
+            ),
+            &bounds,
+            |row| {
+                let (total, successes): (i64, i64) = (row.try_get(1)?, row.try_get(2)?);
+                Ok(SourceHealth {
+                    source_id: row.try_get(0)?,
+                    total_fetches: total,
+                    successes,
+                    success_rate_pct: if total > 0 {
+                        (successes as f64 / total as f64 * 100.0).round()
+                    } else {
+                        0.0
+                    },
+                    p50_ms: row.try_get(3)?,
+                    p95_ms: row.try_get(4)?,
+                    daily_success: Vec::new(),
+                    last_new_item_at: None,
+                    days_since_new_item: None,
+                    volume: None,
+                    filtered_articles: row.try_get(5)?,
+                })
+            },
+        )?;
+        let mut daily: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+        for (source_id, rate) in rows(
+            client,
+            &format!(
+                "SELECT source_id, AVG(success::int)::float8 * 100.0 FROM source_health
+                 WHERE {recorded}
+                 GROUP BY source_id, recorded_at::timestamp::date
+                 ORDER BY source_id, recorded_at::timestamp::date"
+            ),
+            &bounds,
+            |row| Ok((row.try_get::<_, String>(0)?, row.try_get::<_, f64>(1)?)),
+        )? {
+            daily.entry(source_id).or_default().push(rate);
+        }
+        for h in &mut source_health {
+            h.daily_success = daily.remove(&h.source_id).unwrap_or_default();
+        }
+    }
+
+    let mut source_usage = Vec::new();
+    let mut topic_coverage = topics::Coverage::default();
+    if table_exists(client, "shown_narratives")? {
+        let shown = in_range("shown_at");
+        source_usage = rows(
+            client,
+            &format!(
+                "SELECT source_id, COALESCE(tier, ''), COUNT(*) FROM shown_narratives
+                 WHERE source_id IS NOT NULL AND {shown}
+                 GROUP BY 1, 2 ORDER BY 3 DESC"
+            ),
+            &bounds,
+            |row| {
+                Ok(SourceUsage {
+                    source_id: row.try_get(0)?,
+                    tier: row.try_get(1)?,
+                    count: row.try_get(2)?,
+                })
+            },
+        )?;
+        if column_exists(client, "shown_narratives", "topic")? {
+            topic_coverage = topics::Coverage::from_rows(
+                &range,
+                rows(
+                    client,
+                    &format!(
+                        "SELECT to_char(date_trunc('week', shown_at::timestamp), 'YYYY-MM-DD'),
+                            COALESCE(NULLIF(topic, ''), 'untagged'), COUNT(*)
+                     FROM shown_narratives WHERE {shown}
+                     GROUP BY 1, 2 ORDER BY 1"
+                    ),
+                    &bounds,
+                    |row| Ok((row.try_get(0)?, row.try_get(1)?, row.try_get(2)?)),
+                )?,
+            );
+        }
+    }
+
+    let mut recent_runs = Vec::new();
+    let mut run_timeline = Vec::new();
+    let mut run_articles = Vec::new();
+    let mut publish_times = [[0; 24]; 7];
+    let mut total_cost_usd = 0.0;
+    if table_exists(client, "digest_runs")? {
+        let run_at = in_range("run_at");
+        let has_usage = column_exists(client, "digest_runs", "cost_usd")?;
+        let usage_columns = if has_usage {
+            "duration_ms::bigint, input_tokens::bigint, output_tokens::bigint, cost_usd::float8"
+        } else {
+            "NULL::bigint, NULL::bigint, NULL::bigint, NULL::float8"
+        };
+        recent_runs = rows(
+            client,
+            &format!(
+                "SELECT run_at::text, articles_fetched::bigint, articles_emailed::bigint, {usage_columns}
+                 FROM digest_runs WHERE {run_at}
+                 ORDER BY run_at DESC LIMIT 10"
+            ),
+            &bounds,
+            |row| {
+                Ok(DigestRun {
+                    run_at: row.try_get(0)?,
+                    articles_fetched: row.try_get(1)?,
+                    articles_emailed: row.try_get(2)?,
+                    duration_ms: row.try_get(3)?,
+                    input_tokens: row.try_get(4)?,
+                    output_tokens: row.try_get(5)?,
+                    cost_usd: row.try_get(6)?,
+                })
+            },
+        )?;
+        run_articles = rows(
+            client,
+            &format!(
+                "SELECT articles_fetched::float8 FROM digest_runs WHERE {run_at} ORDER BY run_at"
+            ),
+            &bounds,
+            |row| row.try_get(0),
+        )?;
+        for (weekday, hour, runs) in rows(
+            client,
+            &format!(
+                "SELECT extract(isodow FROM run_at::timestamp)::int, extract(hour FROM run_at::timestamp)::int, COUNT(*)
+                 FROM digest_runs WHERE {run_at}
+                 GROUP BY 1, 2"
+            ),
+            &bounds,
+            |row| {
+                Ok((
+                    row.try_get::<_, i32>(0)?,
+                    row.try_get::<_, i32>(1)?,
+                    row.try_get::<_, i64>(2)?,
+                ))
+            },
+        )? {
+            // isodow counts from Monday = 1
+            if let Some(cell) = usize::try_from(weekday - 1)
+                .ok()
+                .and_then(|d| publish_times.get_mut(d))
+                .zip(usize::try_from(hour).ok())
+                .and_then(|(r, h)| r.get_mut(h))
+            {
+                *cell = runs;
+            }
+        }
+        if has_usage {
+            total_cost_usd = client
+                .query_one(
+                    &format!(
+                        "SELECT COALESCE(SUM(cost_usd), 0)::float8 FROM digest_runs WHERE {run_at}"
+                    ),
+                    &bounds,
+                )
+                .map(|row| row.get(0))
+                .map_err(|e| format!("Query error: {e}"))?;
+        }
+    }
+
+    if table_exists(client, "run_stages")? {
+        run_timeline = timeline::RunStages::from_rows(rows(
+            client,
+            &format!(
+                "SELECT run_started_at::text, run_id::bigint, dry_run <> 0, stage,
+                        started_at::text, duration_ms::bigint, items::bigint, error
+                 FROM run_stages
+                 WHERE run_started_at IN (
+                     SELECT DISTINCT run_started_at FROM run_stages WHERE {}
+                     ORDER BY run_started_at DESC LIMIT 10
+                 )
+                 ORDER BY run_started_at DESC, id",
+                in_range("run_started_at")
+            ),
+            &bounds,
+            |row| {
+                Ok((
+                    row.try_get(0)?,
+                    row.try_get(1)?,
+                    row.try_get(2)?,
+                    timeline::StageTiming {
+                        stage: row.try_get(3)?,
+                        started_at: row.try_get(4)?,
+                        duration_ms: row.try_get(5)?,
+                        items: row.try_get(6)?,
+                        error: row.try_get(7)?,
+                    },
+                ))
+            },
+        )?);
+    }
+
+    Ok(StatsData {
+        subscriber_growth: growth(client, &range)?,
+        unsubscribe_reasons: rows(
+            client,
+            &format!(
+                "SELECT reason, COUNT(*) FROM unsubscribe_feedback WHERE {}
+                 GROUP BY reason ORDER BY 2 DESC",
+                in_range("submitted_at")
+            ),
+            &bounds,
+            |row| Ok((row.try_get(0)?, row.try_get(1)?)),
+        )?,
+        subscriber_counts: client
+            .query_one(
+                "SELECT COUNT(*),
+                        COUNT(*) FILTER (WHERE subscribed),
+                        COUNT(*) FILTER (WHERE NOT subscribed)
+                 FROM (SELECT bool_or(status = 'subscribed') AS subscribed
+                       FROM subscribers GROUP BY email) AS addresses",
+                &[],
+            )
+            .map(|row| subscribers::ListCounts {
+                total: row.get(0),
+                confirmed: row.get(1),
+                unsubscribed: row.get(2),
+                bounced: 0,
+            })
+            .map_err(|e| format!("Query error: {e}"))?,
+        range,
+        previous_range,
+        source_health,
+        source_usage,
+        topic_coverage,
+        recent_runs,
+        run_timeline,
+        run_articles,
+        publish_times,
+        total_cost_usd,
+        engagement: Vec::new(),
+        top_viewed: Vec::new(),
+        uptime: None,
+        top_links: Vec::new(),
+        top_domains: Vec::new(),
+        delivery: None,
+    })
+}
+
+/// Daily subscriber totals, signups, and unsubscribes for each day in the range
+fn growth(
+    client: &mut Client,
+    range: &db::DateRange,
+) -> Result<Vec<subscribers::GrowthDay>, String> {
+    let baseline: i64 = client
+        .query_one(
+            "SELECT COALESCE(SUM(CASE event WHEN 'subscribed' THEN 1 ELSE -1 END), 0)::bigint
+             FROM subscription_events WHERE occurred_at < $1::text::date",
+            &[&range.from],
+        )
+        .map(|row| row.get(0))
+        .map_err(|e| format!("Query error: {e}"))?;
+    let mut total = baseline;
+    let days = rows(
+        client,
+        "SELECT d::date::text,
+                COUNT(*) FILTER (WHERE e.event = 'subscribed'),
+                COUNT(*) FILTER (WHERE e.event = 'unsubscribed')
+         FROM generate_series($1::text::date, $2::text::date, interval '1 day') AS d
+         LEFT JOIN subscription_events e ON e.occurred_at::date = d::date
+         GROUP BY d ORDER BY d",
+        &[&range.from, &range.to],
+        |row| {
+            Ok((
+                row.try_get::<_, String>(0)?,
+                row.try_get::<_, i64>(1)?,
+                row.try_get::<_, i64>(2)?,
+            ))
+        },
+    )?;
+    Ok(days
+        .into_iter()
+        .map(|(date, signups, unsubscribes)| {
+            total += signups - unsubscribes;
+            subscribers::GrowthDay {
+                date,
+                total,
+                signups,
+                unsubscribes,
+            }
+        })
+        .collect())
+}
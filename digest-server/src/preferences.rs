@@ -0,0 +1,186 @@
+//! Per-reader list preferences, reached through a signed link
+
+use crate::{AppState, db, escape_html, render_page, resend, tokens};
+use axum::{
+    Form,
+    extract::{Query, State},
+    http::StatusCode,
+    response::Html,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+pub struct PreferencesQuery {
+    token: String,
+}
+
+/// Form fields arrive as repeated `audience=name` pairs, so parse them by hand
+type PreferencesForm = Vec<(String, String)>;
+
+/// The Resend key, or `None` when subscribers are kept locally for SMTP delivery
+fn api_key(state: &AppState) -> Result<Option<&str>, (StatusCode, String)> {
+    if !state.subscriptions_enabled() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Subscriptions not configured".into(),
+        ));
+    }
+    Ok(state.resend_api_key.as_deref())
+}
+
+/// Update the local list directly when there's no Resend audience to drive
+fn update_locally(state: &AppState, email: &str, wanted: &[&str]) -> Result<(), String> {
+    state.audiences.iter().try_for_each(|audience| {
+        let subscribe = wanted.contains(&audience.name.as_str());
+        let known = state
+            .storage
+            .subscribers(&audience.name)?
+            .contains_key(email);
+        // Unchecking a list the reader never joined shouldn't create a row
+        if subscribe || known {
+            state
+                .storage
+                .set_subscribed(email, &audience.name, subscribe)?;
+        }
+        Ok(())
+    })
+}
+
+/// Show every audience with a checkbox reflecting the reader's current status
+pub async fn preferences_page(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<PreferencesQuery>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    let claims = state.verify_token(&query.token, tokens::Action::Preferences)?;
+    let api_key = api_key(&state)?;
+    // Local statuses, one per audience, when there's no Resend audience to ask
+    let local: Option<Vec<bool>> = match api_key {
+        Some(_) => None,
+        None => {
+            let email = claims.email.clone();
+            let statuses = state
+                .blocking(move |state| {
+                    state
+                        .audiences
+                        .iter()
+                        .map(|audience| {
+                            state
+                                .storage
+                                .subscribers(&audience.name)
+                                .map(|list| list.get(&email).copied().unwrap_or(false))
+                                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+                        })
+                        .collect()
+                })
+                .await?;
+            Some(statuses)
+        }
+    };
+
+    let mut options = Vec::new();
+    for (i, audience) in state.audiences.iter().enumerate() {
+        let subscribed = match (api_key, &local) {
+            (Some(api_key), _) => resend::Audience::new(&state.http_client, api_key, &audience.id)
+                .get_contact(&claims.email)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+                .is_some_and(|c| !c.unsubscribed),
+            (None, Some(statuses)) => statuses[i],
+            (None, None) => false,
+        };
+        let checked = if subscribed { " checked" } else { "" };
+        options.push(format!(
+            r#"<label><input type="checkbox" name="audience" value="{0}"{checked}> {0}</label>"#,
+            escape_html(&audience.name)
+        ));
+    }
+
+    let content = format!(
+        r#"<p>Choose which lists <strong>{}</strong> receives.</p>
+    <form method="post" action="/preferences">
+      <input type="hidden" name="token" value="{}">
+      {}
+      <button type="submit">Save preferences</button>
+    </form>"#,
+        escape_html(&claims.email),
+        escape_html(&query.token),
+        options.join("\n      ")
+    );
+    Ok(Html(render_page(&state, "Email preferences", &content)))
+}
+
+/// Apply the submitted checkboxes: subscribe to checked audiences, unsubscribe the rest
+pub async fn update_preferences(
+    State(state): State<Arc<AppState>>,
+    Form(form): Form<PreferencesForm>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    let token = form
+        .iter()
+        .find(|(k, _)| k == "token")
+        .map(|(_, v)| v.as_str())
+        .ok_or((StatusCode::BAD_REQUEST, "Missing token".into()))?;
+    let claims = state.verify_token(token, tokens::Action::Preferences)?;
+    let api_key = api_key(&state)?;
+    let wanted: Vec<&str> = form
+        .iter()
+        .filter(|(k, _)| k == "audience")
+        .map(|(_, v)| v.as_str())
+        .collect();
+
+    let Some(api_key) = api_key else {
+        let email = claims.email.clone();
+        let wanted: Vec<String> = wanted.iter().map(|a| a.to_string()).collect();
+        state
+            .blocking(move |state| {
+                let wanted: Vec<&str> = wanted.iter().map(String::as_str).collect();
+                update_locally(state, &email, &wanted)
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+            })
+            .await?;
+        return Ok(Html(render_page(
+            &state,
+            "Preferences saved",
+            "<p>Your email preferences have been updated.</p>",
+        )));
+    };
+
+    for audience in &state.audiences {
+        let subscribe = wanted.contains(&audience.name.as_str());
+        let remote = resend::Audience::new(&state.http_client, api_key, &audience.id);
+        let existing = remote
+            .get_contact(&claims.email)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        let result = match existing {
+            None if subscribe => remote.add_contact(&claims.email, false).await,
+            Some(c) if c.unsubscribed == subscribe => {
+                remote.set_unsubscribed(&claims.email, !subscribe).await
+            }
+            // Already in the requested state
+            _ => continue,
+        };
+        result.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+
+        if !state.storage_writable() {
+            continue;
+        }
+        let (storage, email, name) = (
+            state.storage.clone(),
+            claims.email.clone(),
+            audience.name.clone(),
+        );
+        let stored = db::blocking(move || storage.set_subscribed(&email, &name, subscribe))
+            .await
+            .flatten();
+        if let Err(e) = stored {
+            tracing::warn!("Could not record preferences locally: {}", e);
+        }
+    }
+
+    Ok(Html(render_page(
+        &state,
+        "Preferences saved",
+        "<p>Your email preferences have been updated.</p>",
+    )))
+}
@@ -0,0 +1,53 @@
+//! The preview slot: where a dry run (`run.py --dry-run`, or
+//! `POST /admin/run?dry_run=true`) leaves its digest instead of publishing
+//! it, so prompt and source changes can be checked before readers see them.
+//! Each dry run replaces the last one's preview.
+
+use crate::{AppState, admin, escape_html, format_date, web_view};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+};
+use std::sync::Arc;
+
+/// The latest dry run's digest
+#[derive(Debug)]
+pub struct Preview {
+    pub date: String,
+    pub html: String,
+    /// UTC, as "YYYY-MM-DD HH:MM:SS"
+    pub created_at: Option<String>,
+}
+
+/// Admin page showing the preview as its digest page would look
+pub async fn preview(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    if let Some(response) = admin::deny_admin_page(&state, &headers) {
+        return Ok(response);
+    }
+    let preview = state
+        .blocking(|state| {
+            state
+                .storage
+                .digest_preview()
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+        })
+        .await?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            "No preview yet; start a dry run with POST /admin/run?dry_run=true".to_string(),
+        ))?;
+    let made = preview
+        .created_at
+        .as_deref()
+        .map(|at| format!(", made {at} UTC"))
+        .unwrap_or_default();
+    let note = format!(
+        "Preview of {}{made} · not published",
+        format_date(&preview.date)
+    );
+    Ok(Html(web_view(preview.html, Some(&escape_html(&note)))).into_response())
+}
@@ -0,0 +1,279 @@
+//! Per-client request budgets, so one scraper can't keep the database busy
+//! for everyone else. Each client IP gets a budget per minute for each kind of
+//! request, spent as it goes and refilled steadily (a token bucket), so a
+//! burst up to the whole budget passes:
+//!
+//! - `RATE_LIMIT_SUBSCRIBE_PER_MINUTE`: `POST /subscribe` and `POST
+//!   /unsubscribe`, which mail a link to whatever address is typed in
+//!   (default 5). The unsubscribe page and its exit survey are browsing.
+//! - `RATE_LIMIT_STATS_PER_MINUTE`: the pages `STATS_TOKEN` covers (default 30)
+//! - `RATE_LIMIT_PER_MINUTE`: everything else a reader browses: digests, the
+//!   index, images, links (default 300)
+//!
+//! 0 lifts a budget. Health checks, metrics, webhooks, and the admin
+//! endpoints (behind `ADMIN_TOKEN`) aren't limited. Over budget, a client gets
+//! `429 Too Many Requests` with `Retry-After`.
+//!
+//! The client is the connection's peer, or, when that's a proxy (a loopback or
+//! private address, as Fly's proxy and a local nginx are), the client it
+//! forwards for (see `pageviews::client_ip`): the rightmost `X-Forwarded-For`
+//! hop that isn't a proxy, as what's left of it the client could have sent,
+//! or `Fly-Client-IP` on Fly. IPv6 clients count by their /64, since a single
+//! host can usually pick any address in it.
+
+use crate::{AppState, pageviews};
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{HeaderMap, Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// What a request spends its client's budget on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Budget {
+    Subscribe,
+    Stats,
+    Browsing,
+}
+
+impl Budget {
+    /// The budget for a `method` request to `route`, `None` for those never
+    /// limited
+    fn for_route(method: &Method, route: &str) -> Option<Self> {
+        match route {
+            "/subscribe" | "/unsubscribe" if method == Method::POST => Some(Self::Subscribe),
+            "/stats" | "/stats.json" | "/stats.csv" | "/sources" | "/sources.json"
+            | "/health/history" => Some(Self::Stats),
+            "/health" | "/health/deep" | "/metrics" | "/webhooks/resend" => None,
+            route if route.starts_with("/admin/") => None,
+            _ => Some(Self::Browsing),
+        }
+    }
+}
+
+/// A client's remaining requests, refilled at the budget's rate
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub struct RateLimits {
+    subscribe: u32,
+    stats: u32,
+    browsing: u32,
+    buckets: Mutex<Buckets>,
+}
+
+struct Buckets {
+    by_client: HashMap<(Budget, IpAddr), Bucket>,
+    pruned: Instant,
+}
+
+impl RateLimits {
+    pub fn from_env() -> Self {
+        let per_minute = |name: &str, default: u32| {
+            std::env::var(name)
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(default)
+        };
+        Self::new(
+            per_minute("RATE_LIMIT_SUBSCRIBE_PER_MINUTE", 5),
+            per_minute("RATE_LIMIT_STATS_PER_MINUTE", 30),
+            per_minute("RATE_LIMIT_PER_MINUTE", 300),
+        )
+    }
+
+    fn new(subscribe: u32, stats: u32, browsing: u32) -> Self {
+        Self {
+            subscribe,
+            stats,
+            browsing,
+            buckets: Mutex::new(Buckets {
+                by_client: HashMap::new(),
+                pruned: Instant::now(),
+            }),
+        }
+    }
+
+    fn per_minute(&self, budget: Budget) -> u32 {
+        match budget {
+            Budget::Subscribe => self.subscribe,
+            Budget::Stats => self.stats,
+            Budget::Browsing => self.browsing,
+        }
+    }
+
+    /// Spend one of `client`'s requests from `budget`, or say how long until
+    /// it has one again
+    fn take(&self, budget: Budget, client: IpAddr, now: Instant) -> Result<(), Duration> {
+        let per_minute = self.per_minute(budget);
+        if per_minute == 0 {
+            return Ok(());
+        }
+        let capacity = f64::from(per_minute);
+        let per_sec = capacity / 60.0;
+        let mut buckets = self.buckets.lock().unwrap();
+
+        // Forget clients whose buckets have refilled (any bucket does within a
+        // minute), at most once a minute
+        let minute = Duration::from_secs(60);
+        if now.saturating_duration_since(buckets.pruned) >= minute {
+            buckets.pruned = now;
+            buckets
+                .by_client
+                .retain(|_, bucket| now.saturating_duration_since(bucket.updated) < minute);
+        }
+
+        let bucket = buckets.by_client.entry((budget, client)).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_sec).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec))
+        }
+    }
+}
+
+/// The client a request counts against: its IP, or an IPv6 address's /64
+fn client(headers: &HeaderMap, peer: SocketAddr) -> IpAddr {
+    match pageviews::client_ip(headers, peer) {
+        IpAddr::V6(ip) => IpAddr::V6(Ipv6Addr::from_bits(ip.to_bits() & !u128::from(u64::MAX))),
+        ip => ip,
+    }
+}
+
+/// Middleware refusing requests past their client's budget with a 429
+pub async fn limit(
+    State(state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let budget = request
+        .extensions()
+        .get::<MatchedPath>()
+        .and_then(|route| Budget::for_route(request.method(), route.as_str()));
+    if let Some(budget) = budget {
+        let client = client(request.headers(), peer);
+        if let Err(wait) = state.rate_limits.take(budget, client, Instant::now()) {
+            tracing::debug!("Rate limited {} ({:?})", client, budget);
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(
+                    header::RETRY_AFTER,
+                    wait.as_secs_f64().ceil().max(1.0).to_string(),
+                )],
+                "Too many requests; try again shortly",
+            )
+                .into_response();
+        }
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod take {
+        use super::*;
+
+        #[test]
+        fn allows_a_burst_then_refills() {
+            let limits = RateLimits::new(2, 30, 60);
+            let client: IpAddr = "1.2.3.4".parse().unwrap();
+            let start = Instant::now();
+            assert!(limits.take(Budget::Subscribe, client, start).is_ok());
+            assert!(limits.take(Budget::Subscribe, client, start).is_ok());
+            let wait = limits.take(Budget::Subscribe, client, start).unwrap_err();
+            assert_eq!(wait.as_secs(), 30);
+
+            // Other budgets, and other clients, are their own
+            assert!(limits.take(Budget::Browsing, client, start).is_ok());
+            let other: IpAddr = "5.6.7.8".parse().unwrap();
+            assert!(limits.take(Budget::Subscribe, other, start).is_ok());
+
+            let later = start + Duration::from_secs(30);
+            assert!(limits.take(Budget::Subscribe, client, later).is_ok());
+            assert!(limits.take(Budget::Subscribe, client, later).is_err());
+        }
+
+        #[test]
+        fn zero_is_unlimited() {
+            let limits = RateLimits::new(0, 0, 0);
+            let client: IpAddr = "1.2.3.4".parse().unwrap();
+            let now = Instant::now();
+            for _ in 0..1000 {
+                assert!(limits.take(Budget::Browsing, client, now).is_ok());
+            }
+        }
+    }
+
+    mod client {
+        use super::*;
+
+        #[test]
+        fn groups_ipv6_by_prefix() {
+            let peer = |ip: &str| SocketAddr::new(ip.parse().unwrap(), 5000);
+            let headers = HeaderMap::new();
+            assert_eq!(
+                client(&headers, peer("203.0.113.9")),
+                "203.0.113.9".parse::<IpAddr>().unwrap()
+            );
+            assert_eq!(
+                client(&headers, peer("2001:db8:1:2:a:b:c:d")),
+                client(&headers, peer("2001:db8:1:2::1"))
+            );
+            assert_ne!(
+                client(&headers, peer("2001:db8:1:2::1")),
+                client(&headers, peer("2001:db8:1:3::1"))
+            );
+        }
+
+        #[test]
+        fn cannot_be_spoofed_through_x_forwarded_for() {
+            let proxy: SocketAddr = "10.0.0.2:5000".parse().unwrap();
+            let mut headers = HeaderMap::new();
+            headers.insert("x-forwarded-for", "1.1.1.1, 5.6.7.8".parse().unwrap());
+            let first = client(&headers, proxy);
+            headers.insert("x-forwarded-for", "2.2.2.2, 5.6.7.8".parse().unwrap());
+            assert_eq!(client(&headers, proxy), first);
+            assert_eq!(first, "5.6.7.8".parse::<IpAddr>().unwrap());
+        }
+    }
+
+    mod for_route {
+        use super::*;
+
+        #[test]
+        fn leaves_operations_unlimited() {
+            let route = |route| Budget::for_route(&Method::GET, route);
+            assert_eq!(
+                Budget::for_route(&Method::POST, "/subscribe"),
+                Some(Budget::Subscribe)
+            );
+            assert_eq!(
+                Budget::for_route(&Method::POST, "/unsubscribe"),
+                Some(Budget::Subscribe)
+            );
+            assert_eq!(route("/unsubscribe"), Some(Budget::Browsing));
+            assert_eq!(route("/stats.csv"), Some(Budget::Stats));
+            assert_eq!(route("/{date}"), Some(Budget::Browsing));
+            assert_eq!(route("/img/{hash}"), Some(Budget::Browsing));
+            assert_eq!(route("/health"), None);
+            assert_eq!(route("/admin/run"), None);
+        }
+    }
+}
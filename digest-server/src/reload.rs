@@ -0,0 +1,195 @@
+//! `SOURCES_FILE`, re-read while the server runs: when it changes on disk
+//! (checked every `SOURCES_WATCH_SECS`) or on `POST /admin/reload`. A file
+//! that no longer parses is logged and the sources read before are kept, so
+//! a typo can't empty `/sources`. Caches are left alone. The pipeline reads
+//! `sources.toml`, `SCORING_FILE`, and `PROMPTS_DIR` afresh each run, so
+//! changes to them reach the next run without a restart either way.
+
+use crate::{AppState, admin::require_admin, sources};
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+};
+use serde::Serialize;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+/// The sources of `SOURCES_FILE`, as last read
+pub struct SourcesFile {
+    path: String,
+    loaded: RwLock<Loaded>,
+}
+
+struct Loaded {
+    sources: Arc<Vec<sources::Source>>,
+    /// The file's modification time when it was read
+    modified: Option<SystemTime>,
+}
+
+/// What a reload changed, by source ID
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct Changes {
+    pub sources: usize,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl Changes {
+    fn between(old: &[sources::Source], new: &[sources::Source]) -> Self {
+        let find = |list: &[sources::Source], id: &str| list.iter().position(|s| s.id == id);
+        let ids = |list: &[sources::Source], keep: &dyn Fn(&sources::Source) -> bool| {
+            list.iter()
+                .filter(|s| keep(s))
+                .map(|s| s.id.clone())
+                .collect()
+        };
+        Self {
+            sources: new.len(),
+            added: ids(new, &|s| find(old, &s.id).is_none()),
+            removed: ids(old, &|s| find(new, &s.id).is_none()),
+            changed: ids(new, &|s| find(old, &s.id).is_some_and(|i| old[i] != *s)),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl SourcesFile {
+    /// Read `path`, failing if it doesn't parse
+    pub fn load(path: &str) -> Result<Self, String> {
+        let modified = modified(path);
+        Ok(Self {
+            path: path.to_string(),
+            loaded: RwLock::new(Loaded {
+                sources: Arc::new(sources::load(path)?),
+                modified,
+            }),
+        })
+    }
+
+    pub fn sources(&self) -> Arc<Vec<sources::Source>> {
+        Arc::clone(&self.loaded.read().unwrap().sources)
+    }
+
+    /// Whether the file was modified since it was read
+    pub fn changed_on_disk(&self) -> bool {
+        modified(&self.path) != self.loaded.read().unwrap().modified
+    }
+
+    /// Read the file again, keeping what was read before if it doesn't parse
+    pub fn reload(&self) -> Result<Changes, String> {
+        let modified = modified(&self.path);
+        let result = sources::load(&self.path);
+        let mut loaded = self.loaded.write().unwrap();
+        // Only retried once the file changes again
+        loaded.modified = modified;
+        let sources = result?;
+        let changes = Changes::between(&loaded.sources, &sources);
+        loaded.sources = Arc::new(sources);
+        Ok(changes)
+    }
+}
+
+fn log(path: &str, result: &Result<Changes, String>) {
+    match result {
+        Ok(changes) if changes.is_empty() => {
+            tracing::info!("Reloaded {}: {} sources, unchanged", path, changes.sources)
+        }
+        Ok(changes) => tracing::info!(
+            "Reloaded {}: {} sources, added {:?}, removed {:?}, changed {:?}",
+            path,
+            changes.sources,
+            changes.added,
+            changes.removed,
+            changes.changed
+        ),
+        Err(e) => tracing::warn!("Kept the sources read before: {}", e),
+    }
+}
+
+/// Reload `SOURCES_FILE` whenever it changes, checking every `interval`,
+/// forever
+pub async fn watch(state: Arc<AppState>, interval: Duration) {
+    let Some(file) = state.sources.as_ref() else {
+        return;
+    };
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        if file.changed_on_disk() {
+            log(&file.path, &file.reload());
+        }
+    }
+}
+
+/// Re-read `SOURCES_FILE` now, answering with what changed
+pub async fn reload(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Changes>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    let file = state.sources.as_ref().ok_or((
+        StatusCode::NOT_FOUND,
+        "No sources file configured (set SOURCES_FILE)".to_string(),
+    ))?;
+    let result = file.reload();
+    log(&file.path, &result);
+    result
+        .map(Json)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+[[source]]
+id = "wire"
+name = "Wire"
+url = "https://wire.example/feed"
+bias = "center"
+perspective = "Wire service"
+"#;
+
+    mod reload {
+        use super::*;
+
+        #[test]
+        fn reports_changes_and_keeps_the_last_good_file() {
+            let dir = std::env::temp_dir().join(format!("reload-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("sources.toml");
+            let path = path.to_str().unwrap();
+            std::fs::write(path, SOURCE).unwrap();
+            let file = SourcesFile::load(path).unwrap();
+            assert!(!file.changed_on_disk());
+
+            let added = SOURCE.replace("wire", "daily").replace("Wire", "Daily");
+            std::fs::write(path, format!("{}{added}", SOURCE.replace("center", "left"))).unwrap();
+            assert_eq!(
+                file.reload().unwrap(),
+                Changes {
+                    sources: 2,
+                    added: vec!["daily".into()],
+                    removed: vec![],
+                    changed: vec!["wire".into()],
+                }
+            );
+
+            std::fs::write(path, "[[source]]\nid = ").unwrap();
+            assert!(file.reload().is_err());
+            assert_eq!(file.sources().len(), 2);
+            assert!(!file.changed_on_disk());
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
@@ -0,0 +1,318 @@
+//! Minimal Resend API client for audience contacts and transactional email
+
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+const API_BASE: &str = "https://api.resend.com";
+
+#[derive(Serialize)]
+struct NewContact<'a> {
+    email: &'a str,
+    unsubscribed: bool,
+}
+
+#[derive(Serialize)]
+struct ContactUpdate {
+    unsubscribed: bool,
+}
+
+/// A single transactional email
+#[derive(Serialize)]
+pub struct Email<'a> {
+    pub from: &'a str,
+    pub to: Vec<&'a str>,
+    pub subject: &'a str,
+    pub html: &'a str,
+}
+
+#[derive(Deserialize)]
+struct SentEmail {
+    id: String,
+}
+
+/// Contacts listed per request, Resend's maximum
+const CONTACTS_PER_PAGE: &str = "100";
+
+/// A page of an audience's contacts
+#[derive(Deserialize)]
+struct ContactList {
+    data: Vec<Contact>,
+    #[serde(default)]
+    has_more: bool,
+}
+
+impl ContactList {
+    /// The contact to list the next page after, if there is one
+    fn next_after(&self) -> Option<&str> {
+        self.has_more
+            .then(|| self.data.last()?.id.as_deref())
+            .flatten()
+    }
+}
+
+/// A contact as returned by the Resend audience API
+#[derive(Deserialize, Clone, Debug)]
+pub struct Contact {
+    #[serde(default)]
+    pub id: Option<String>,
+    pub email: String,
+    #[serde(default)]
+    pub unsubscribed: bool,
+}
+
+/// A named audience from configuration
+#[derive(Clone, Debug, PartialEq)]
+pub struct AudienceConfig {
+    pub name: String,
+    pub id: String,
+}
+
+/// Parse `RESEND_AUDIENCES` ("daily:id1,weekly:id2") and the legacy single
+/// `RESEND_AUDIENCE_ID`, which becomes the audience named "default".
+///
+/// The first audience in the result is used when a request doesn't name one.
+pub fn parse_audiences(
+    list: Option<&str>,
+    legacy_id: Option<&str>,
+) -> Result<Vec<AudienceConfig>, String> {
+    let mut audiences = Vec::new();
+    if let Some(id) = legacy_id.map(str::trim).filter(|id| !id.is_empty()) {
+        audiences.push(AudienceConfig {
+            name: "default".into(),
+            id: id.into(),
+        });
+    }
+    for entry in list.unwrap_or_default().split(',').map(str::trim) {
+        if entry.is_empty() {
+            continue;
+        }
+        let (name, id) = entry
+            .split_once(':')
+            .map(|(n, i)| (n.trim(), i.trim()))
+            .filter(|(n, i)| !n.is_empty() && !i.is_empty())
+            .ok_or_else(|| format!("Invalid audience '{entry}' (expected name:audience_id)"))?;
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!("Invalid audience name '{name}'"));
+        }
+        if let Some(existing) = audiences.iter_mut().find(|a| a.name == name) {
+            existing.id = id.into();
+        } else {
+            audiences.push(AudienceConfig {
+                name: name.into(),
+                id: id.into(),
+            });
+        }
+    }
+    Ok(audiences)
+}
+
+/// Handle to a single Resend audience
+pub struct Audience<'a> {
+    client: &'a Client,
+    api_key: &'a str,
+    audience_id: &'a str,
+}
+
+impl<'a> Audience<'a> {
+    pub fn new(client: &'a Client, api_key: &'a str, audience_id: &'a str) -> Self {
+        Self {
+            client,
+            api_key,
+            audience_id,
+        }
+    }
+
+    fn contacts_url(&self) -> String {
+        format!("{API_BASE}/audiences/{}/contacts", self.audience_id)
+    }
+
+    /// List every contact in the audience, a page at a time
+    pub async fn list_contacts(&self) -> Result<Vec<Contact>, String> {
+        let mut contacts = Vec::new();
+        let mut after: Option<String> = None;
+        loop {
+            let mut request = self
+                .client
+                .get(self.contacts_url())
+                .bearer_auth(self.api_key)
+                .query(&[("limit", CONTACTS_PER_PAGE)]);
+            if let Some(id) = &after {
+                request = request.query(&[("after", id)]);
+            }
+            let response = request
+                .send()
+                .await
+                .map_err(|e| format!("Request failed: {e}"))?;
+            let response = check_status(response).await?;
+            let page: ContactList = response
+                .json()
+                .await
+                .map_err(|e| format!("Invalid contact list: {e}"))?;
+            after = page.next_after().map(str::to_string);
+            contacts.extend(page.data);
+            if after.is_none() {
+                return Ok(contacts);
+            }
+        }
+    }
+
+    /// Look up a single contact by email
+    pub async fn get_contact(&self, email: &str) -> Result<Option<Contact>, String> {
+        let response = self
+            .client
+            .get(format!("{}/{email}", self.contacts_url()))
+            .bearer_auth(self.api_key)
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {e}"))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = check_status(response).await?;
+        let contact = response
+            .json()
+            .await
+            .map_err(|e| format!("Invalid contact: {e}"))?;
+        Ok(Some(contact))
+    }
+
+    /// Add a contact (Resend treats an existing email as an upsert)
+    pub async fn add_contact(&self, email: &str, unsubscribed: bool) -> Result<(), String> {
+        let response = self
+            .client
+            .post(self.contacts_url())
+            .bearer_auth(self.api_key)
+            .json(&NewContact {
+                email,
+                unsubscribed,
+            })
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {e}"))?;
+        check_status(response).await.map(|_| ())
+    }
+
+    /// Flip the unsubscribed flag on an existing contact (addressed by email).
+    ///
+    /// Unsubscribing a contact the audience doesn't have is a no-op, not an error.
+    pub async fn set_unsubscribed(&self, email: &str, unsubscribed: bool) -> Result<(), String> {
+        let response = self
+            .client
+            .patch(format!("{}/{email}", self.contacts_url()))
+            .bearer_auth(self.api_key)
+            .json(&ContactUpdate { unsubscribed })
+            .send()
+            .await
+            .map_err(|e| format!("Request failed: {e}"))?;
+        if unsubscribed && response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        check_status(response).await.map(|_| ())
+    }
+}
+
+/// Send a transactional email, returning Resend's message ID
+pub async fn send_email(
+    client: &Client,
+    api_key: &str,
+    email: &Email<'_>,
+) -> Result<String, String> {
+    let response = client
+        .post(format!("{API_BASE}/emails"))
+        .bearer_auth(api_key)
+        .json(email)
+        .send()
+        .await
+        .map_err(|e| format!("Request failed: {e}"))?;
+    let response = check_status(response).await?;
+    let sent: SentEmail = response
+        .json()
+        .await
+        .map_err(|e| format!("Invalid send response: {e}"))?;
+    Ok(sent.id)
+}
+
+async fn check_status(response: reqwest::Response) -> Result<reqwest::Response, String> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    Err(format!("Resend error {status}: {body}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod next_after {
+        use super::*;
+
+        fn page(json: &str) -> ContactList {
+            serde_json::from_str(json).unwrap()
+        }
+
+        #[test]
+        fn follows_the_last_contact_while_there_are_more() {
+            let more = page(
+                r#"{"object": "list", "has_more": true, "data": [
+                    {"id": "c1", "email": "a@x.com", "unsubscribed": false},
+                    {"id": "c2", "email": "b@x.com", "unsubscribed": true}]}"#,
+            );
+            assert_eq!(more.next_after(), Some("c2"));
+            let last = page(r#"{"has_more": false, "data": [{"id": "c3", "email": "c@x.com"}]}"#);
+            assert_eq!(last.next_after(), None);
+            // Older responses had no cursor at all
+            assert_eq!(page(r#"{"data": []}"#).next_after(), None);
+        }
+    }
+
+    mod parse_audiences {
+        use super::*;
+
+        fn names(audiences: &[AudienceConfig]) -> Vec<&str> {
+            audiences.iter().map(|a| a.name.as_str()).collect()
+        }
+
+        #[test]
+        fn legacy_id_is_default() {
+            let a = parse_audiences(None, Some("aud-1")).unwrap();
+            assert_eq!(
+                a,
+                vec![AudienceConfig {
+                    name: "default".into(),
+                    id: "aud-1".into()
+                }]
+            );
+        }
+
+        #[test]
+        fn named_list_keeps_order() {
+            let a = parse_audiences(Some("daily:a, weekly:b,beta:c"), None).unwrap();
+            assert_eq!(names(&a), vec!["daily", "weekly", "beta"]);
+            assert_eq!(a[1].id, "b");
+        }
+
+        #[test]
+        fn legacy_id_comes_first() {
+            let a = parse_audiences(Some("weekly:b"), Some("a")).unwrap();
+            assert_eq!(names(&a), vec!["default", "weekly"]);
+        }
+
+        #[test]
+        fn rejects_malformed_entries() {
+            assert!(parse_audiences(Some("daily"), None).is_err());
+            assert!(parse_audiences(Some("daily:"), None).is_err());
+            assert!(parse_audiences(Some("da ily:x"), None).is_err());
+        }
+
+        #[test]
+        fn nothing_configured() {
+            assert!(parse_audiences(None, None).unwrap().is_empty());
+            assert!(parse_audiences(Some(""), Some("")).unwrap().is_empty());
+        }
+    }
+}
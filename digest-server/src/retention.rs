@@ -0,0 +1,205 @@
+//! Retention for the telemetry tables that grow without bound.
+//!
+//! A background job deletes `source_health`, `shown_narratives`, and
+//! `health_checks` rows older than the retention window, then vacuums once
+//! enough of the file is free pages. Raw rows not yet summarized into the
+//! weekly rollups are kept, so long stats ranges lose nothing but the partial
+//! weeks at their edges.
+
+use crate::db;
+use rusqlite::Connection;
+use std::time::Duration;
+
+/// Vacuum once free pages make up at least this share of the file
+const VACUUM_FREE_RATIO: f64 = 0.1;
+
+/// Raw table, its timestamp column, and the rollup summarizing it
+const TABLES: &[(&str, &str, Option<&str>)] = &[
+    ("source_health", "recorded_at", Some("source_health_weekly")),
+    (
+        "shown_narratives",
+        "shown_at",
+        Some("shown_narratives_weekly"),
+    ),
+    ("health_checks", "checked_at", None),
+];
+
+/// Delete rows older than `days`, returning how many went per table
+fn prune(conn: &mut Connection, days: u32) -> Result<Vec<(&'static str, usize)>, String> {
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Cannot prune telemetry: {e}"))?;
+    let mut pruned = Vec::new();
+    for &(table, column, rollup) in TABLES {
+        if !db::table_exists(&tx, table)? {
+            continue;
+        }
+        // Rollups redo their latest week, so only rows before it are safe to
+        // drop. With no rollups (disabled, or never run) the window alone applies.
+        let kept_from = match rollup {
+            Some(rollup) if db::table_exists(&tx, rollup)? => {
+                format!("(SELECT MAX(week) FROM {rollup})")
+            }
+            _ => "NULL".to_string(),
+        };
+        let deleted = tx
+            .execute(
+                &format!(
+                    "DELETE FROM {table}
+                     WHERE {column} < datetime('now', ?1)
+                       AND {column} < COALESCE({kept_from}, datetime('now', ?1))"
+                ),
+                [format!("-{days} days")],
+            )
+            .map_err(|e| format!("Cannot prune {table}: {e}"))?;
+        pruned.push((table, deleted));
+    }
+    tx.commit()
+        .map_err(|e| format!("Cannot prune telemetry: {e}"))?;
+    Ok(pruned)
+}
+
+/// Rebuild each database file (the telemetry one too, when separate) that is
+/// enough free pages; returns those it vacuumed
+fn vacuum_if_fragmented(conn: &Connection) -> Result<Vec<String>, String> {
+    let mut vacuumed = Vec::new();
+    for schema in db::schemas(conn)? {
+        let pragma = |name: &str| {
+            conn.query_row(&format!("PRAGMA {schema}.{name}"), [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map_err(|e| format!("Query error: {e}"))
+        };
+        let (free, total) = (pragma("freelist_count")?, pragma("page_count")?);
+        if total == 0 || (free as f64) < VACUUM_FREE_RATIO * total as f64 {
+            continue;
+        }
+        conn.execute_batch(&format!("VACUUM {schema}"))
+            .map_err(|e| format!("Cannot vacuum {schema}: {e}"))?;
+        vacuumed.push(schema);
+    }
+    Ok(vacuumed)
+}
+
+/// Prune rows older than `days` every `interval`, forever. The first pass
+/// waits one interval so the rollups get to run first.
+pub async fn run(db_path: String, days: u32, interval: Duration) {
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    loop {
+        ticker.tick().await;
+        let path = db_path.clone();
+        let result = db::blocking(move || {
+            let mut conn = db::open_rw(&path)?;
+            let pruned = prune(&mut conn, days)?;
+            Ok::<_, String>((pruned, vacuum_if_fragmented(&conn)?))
+        })
+        .await
+        .flatten();
+        match result {
+            Ok((pruned, vacuumed)) => {
+                for (table, deleted) in pruned.into_iter().filter(|(_, n)| *n > 0) {
+                    tracing::info!("Pruned {} old rows from {}", deleted, table);
+                }
+                for schema in vacuumed {
+                    tracing::info!("Vacuumed {} database", schema);
+                }
+            }
+            Err(e) => tracing::warn!("Could not prune telemetry: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE source_health (source_id TEXT, success INTEGER, recorded_at DATETIME);
+             CREATE TABLE shown_narratives (source_id TEXT, tier TEXT, shown_at DATETIME);
+             INSERT INTO source_health VALUES
+                 ('bbc', 1, datetime('now', '-400 days')),
+                 ('bbc', 1, datetime('now', '-10 days'));
+             INSERT INTO shown_narratives VALUES
+                 ('bbc', 'must_know', datetime('now', '-400 days')),
+                 ('bbc', 'must_know', datetime('now', '-10 days'));",
+        )
+        .unwrap();
+        conn.execute_batch(crate::rollups::SCHEMA).unwrap();
+        conn.execute_batch(crate::health::SCHEMA).unwrap();
+        conn
+    }
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+            row.get(0)
+        })
+        .unwrap()
+    }
+
+    mod prune {
+        use super::*;
+
+        #[test]
+        fn deletes_rows_older_than_the_window() {
+            let mut conn = conn();
+            conn.execute_batch(
+                "INSERT INTO health_checks (checked_at, ok, latency_ms)
+                 VALUES (datetime('now', '-400 days'), 1, 1), (datetime('now'), 1, 1)",
+            )
+            .unwrap();
+            let pruned = prune(&mut conn, 365).unwrap();
+            assert_eq!(
+                pruned,
+                [
+                    ("source_health", 1),
+                    ("shown_narratives", 1),
+                    ("health_checks", 1)
+                ]
+            );
+            assert_eq!(count(&conn, "source_health"), 1);
+            assert_eq!(count(&conn, "shown_narratives"), 1);
+            assert_eq!(count(&conn, "health_checks"), 1);
+        }
+
+        #[test]
+        fn keeps_rows_not_yet_rolled_up() {
+            let mut conn = conn();
+            conn.execute_batch(
+                "INSERT INTO source_health_weekly
+                 VALUES (date('now', '-500 days'), 'bbc', 1, 1)",
+            )
+            .unwrap();
+            let pruned = prune(&mut conn, 365).unwrap();
+            assert_eq!(pruned[0], ("source_health", 0));
+            assert_eq!(pruned[1], ("shown_narratives", 1));
+        }
+
+        #[test]
+        fn skips_missing_tables() {
+            let mut conn = Connection::open_in_memory().unwrap();
+            assert!(prune(&mut conn, 365).unwrap().is_empty());
+        }
+    }
+
+    mod vacuum_if_fragmented {
+        use super::*;
+
+        #[test]
+        fn vacuums_only_the_fragmented_database() {
+            let conn = Connection::open_in_memory().unwrap();
+            db::attach_telemetry(&conn, ":memory:").unwrap();
+            conn.execute_batch(
+                "CREATE TABLE digests (date TEXT);
+                 CREATE TABLE telemetry.source_health (error_message TEXT);
+                 WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 500)
+                 INSERT INTO source_health SELECT hex(randomblob(200)) FROM n;
+                 DELETE FROM source_health;",
+            )
+            .unwrap();
+            assert_eq!(vacuum_if_fragmented(&conn).unwrap(), [db::TELEMETRY]);
+            assert!(vacuum_if_fragmented(&conn).unwrap().is_empty());
+        }
+    }
+}
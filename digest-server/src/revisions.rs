@@ -0,0 +1,345 @@
+//! Edit history for digests.
+//!
+//! Triggers on `digests` copy a digest into `digest_revisions` whenever the
+//! pipeline re-ingests it or someone edits its HTML, so corrections never
+//! erase what readers saw. Compression's rewrites don't count as edits, and
+//! writers don't re-save a body a compressed digest already holds (see
+//! `compression::unchanged`), which the triggers can't see through.
+//! Digest pages note when they were last updated, and an admin page shows
+//! what each revision changed.
+
+use crate::{AppState, admin, escape_html, format_date, is_valid_date, render_page};
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+};
+use rusqlite::Connection;
+use std::sync::Arc;
+
+/// `BEFORE INSERT` sees the row an `INSERT OR REPLACE` is about to replace;
+/// replaced rows never reach delete triggers unless `recursive_triggers` is on
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS digest_revisions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    digest_date TEXT NOT NULL,
+    html TEXT NOT NULL,
+    html_zstd BLOB,
+    created_at DATETIME,
+    replaced_at DATETIME DEFAULT (datetime('now', 'utc'))
+);
+CREATE INDEX IF NOT EXISTS idx_digest_revisions_date ON digest_revisions(digest_date, id);
+CREATE TRIGGER IF NOT EXISTS digests_revision_on_insert BEFORE INSERT ON digests
+BEGIN
+    INSERT INTO digest_revisions (digest_date, html, html_zstd, created_at)
+    SELECT date, html, html_zstd, created_at FROM digests
+    WHERE date = NEW.date AND html IS NOT NEW.html;
+END;
+CREATE TRIGGER IF NOT EXISTS digests_revision_on_update BEFORE UPDATE OF html ON digests
+WHEN NEW.html != '' AND NEW.html IS NOT OLD.html
+BEGIN
+    INSERT INTO digest_revisions (digest_date, html, html_zstd, created_at)
+    VALUES (OLD.date, OLD.html, OLD.html_zstd, OLD.created_at);
+END;
+";
+
+/// The triggers again, also copying the blob key of bodies moved out of the
+/// database (`blobs::migrate` adds the columns first)
+pub const BLOB_TRIGGERS: &str = "
+DROP TRIGGER IF EXISTS digests_revision_on_insert;
+DROP TRIGGER IF EXISTS digests_revision_on_update;
+CREATE TRIGGER digests_revision_on_insert BEFORE INSERT ON digests
+BEGIN
+    INSERT INTO digest_revisions (digest_date, html, html_zstd, html_blob, created_at)
+    SELECT date, html, html_zstd, html_blob, created_at FROM digests
+    WHERE date = NEW.date AND html IS NOT NEW.html;
+END;
+CREATE TRIGGER digests_revision_on_update BEFORE UPDATE OF html ON digests
+WHEN NEW.html != '' AND NEW.html IS NOT OLD.html
+BEGIN
+    INSERT INTO digest_revisions (digest_date, html, html_zstd, html_blob, created_at)
+    VALUES (OLD.date, OLD.html, OLD.html_zstd, OLD.html_blob, OLD.created_at);
+END;
+";
+
+/// Create the table and triggers, once the pipeline has created `digests`
+pub fn migrate(conn: &Connection) -> Result<(), String> {
+    if !crate::db::table_exists(conn, "digests")? {
+        return Ok(());
+    }
+    // The triggers copy created_at, which the oldest pipelines didn't write
+    if !crate::db::column_exists(conn, "digests", "created_at")? {
+        conn.execute_batch("ALTER TABLE digests ADD COLUMN created_at DATETIME")
+            .map_err(|e| e.to_string())?;
+    }
+    conn.execute_batch(SCHEMA).map_err(|e| e.to_string())
+}
+
+/// A superseded version of a digest
+pub struct Revision {
+    /// When this version was first stored, if known
+    pub created_at: Option<String>,
+    /// When a newer version replaced it
+    pub replaced_at: String,
+    pub html: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum Change<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Above this many line pairs, changed regions are shown as replaced wholesale
+/// rather than diffed, to bound the table's memory
+const MAX_DIFF_CELLS: usize = 4_000_000;
+
+/// Lines of unchanged context kept around each change
+const CONTEXT: usize = 3;
+
+/// Line diff of `old` to `new` by longest common subsequence
+fn diff<'a>(old: &'a str, new: &'a str) -> Vec<Change<'a>> {
+    let (a, b): (Vec<&str>, Vec<&str>) = (old.lines().collect(), new.lines().collect());
+    // Only the middle, past any shared start and end, needs the table
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (ma, mb) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut changes: Vec<Change> = a[..prefix].iter().map(|l| Change::Same(l)).collect();
+    if (ma.len() + 1) * (mb.len() + 1) > MAX_DIFF_CELLS {
+        changes.extend(ma.iter().map(|l| Change::Removed(l)));
+        changes.extend(mb.iter().map(|l| Change::Added(l)));
+    } else {
+        // lcs[i][j]: longest common subsequence of ma[i..] and mb[j..]
+        let mut lcs = vec![vec![0u32; mb.len() + 1]; ma.len() + 1];
+        for i in (0..ma.len()).rev() {
+            for j in (0..mb.len()).rev() {
+                lcs[i][j] = if ma[i] == mb[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < ma.len() || j < mb.len() {
+            if i < ma.len() && j < mb.len() && ma[i] == mb[j] {
+                changes.push(Change::Same(ma[i]));
+                (i, j) = (i + 1, j + 1);
+            } else if i < ma.len() && (j == mb.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+                changes.push(Change::Removed(ma[i]));
+                i += 1;
+            } else {
+                changes.push(Change::Added(mb[j]));
+                j += 1;
+            }
+        }
+    }
+    changes.extend(a[a.len() - suffix..].iter().map(|l| Change::Same(l)));
+    changes
+}
+
+/// The diff as HTML lines, keeping `CONTEXT` unchanged lines around changes
+fn render_diff(changes: &[Change]) -> String {
+    let near_change = |i: usize| {
+        let from = i.saturating_sub(CONTEXT);
+        let to = (i + CONTEXT + 1).min(changes.len());
+        changes[from..to]
+            .iter()
+            .any(|c| !matches!(c, Change::Same(_)))
+    };
+    let mut out = String::new();
+    let mut skipped = false;
+    for (i, change) in changes.iter().enumerate() {
+        let (class, sign, line) = match change {
+            Change::Same(line) if near_change(i) => ("same", ' ', line),
+            Change::Same(_) => {
+                if !skipped {
+                    out.push_str("<span class=\"skip\">⋯</span>\n");
+                    skipped = true;
+                }
+                continue;
+            }
+            Change::Removed(line) => ("del", '-', line),
+            Change::Added(line) => ("add", '+', line),
+        };
+        skipped = false;
+        out.push_str(&format!(
+            "<span class=\"{class}\">{sign} {}</span>\n",
+            escape_html(line)
+        ));
+    }
+    out
+}
+
+/// "Updated Friday, January 17, 14:02 UTC" from a SQLite UTC timestamp
+pub fn updated_note(replaced_at: &str) -> String {
+    let time = replaced_at.get(11..16).unwrap_or_default();
+    let date = format_date(replaced_at.get(..10).unwrap_or(replaced_at));
+    if time.is_empty() {
+        format!("Updated {date}")
+    } else {
+        format!("Updated {date}, {time} UTC")
+    }
+}
+
+/// Admin page listing a digest's revisions, each diffed against the version
+/// that replaced it, newest first
+pub async fn history(
+    Path(date): Path<String>,
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    if let Some(response) = admin::deny_admin_page(&state, &headers) {
+        return Ok(response);
+    }
+    if !is_valid_date(&date) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid date format".into()));
+    }
+    let (current, revisions) = state
+        .blocking({
+            let date = date.clone();
+            move |state| {
+                let (_, current) = state
+                    .storage
+                    .digest(Some(&date))
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?
+                    .ok_or_else(|| (StatusCode::NOT_FOUND, format!("No digest for {date}")))?;
+                let revisions = state
+                    .storage
+                    .digest_revisions(&date)
+                    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+                Ok((current, revisions))
+            }
+        })
+        .await?;
+
+    let mut sections = Vec::new();
+    for (i, revision) in revisions.iter().enumerate() {
+        let next = revisions.get(i + 1).map_or(current.as_str(), |r| &r.html);
+        let stored = revision
+            .created_at
+            .as_deref()
+            .map(|at| format!(", first stored {at}"))
+            .unwrap_or_default();
+        sections.push(format!(
+            r#"<h2>Replaced {}{stored}</h2>
+    <pre class="diff">{}</pre>"#,
+            revision.replaced_at,
+            render_diff(&diff(&revision.html, next))
+        ));
+    }
+    sections.reverse();
+    let content = if sections.is_empty() {
+        "<p>This digest hasn't changed since it was published.</p>".to_string()
+    } else {
+        sections.join("\n    ")
+    };
+    let content = format!(
+        r#"<style>
+      .diff {{ font-size: 12px; overflow-x: auto; }}
+      .diff .add {{ background: #e6ffec; }}
+      .diff .del {{ background: #ffebe9; }}
+      .diff .skip {{ color: #999; }}
+    </style>
+    <p><a href="/{date}">Current version</a> · {} revisions</p>
+    {content}"#,
+        revisions.len()
+    );
+    let title = format!("History of {}", format_date(&date));
+    Ok(Html(render_page(&state, &title, &content)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digests() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE digests (date TEXT PRIMARY KEY, html TEXT NOT NULL, html_zstd BLOB);
+             INSERT INTO digests (date, html) VALUES ('2026-01-01', 'first');",
+        )
+        .unwrap();
+        migrate(&conn).unwrap();
+        conn
+    }
+
+    fn revisions(conn: &Connection) -> Vec<String> {
+        let mut stmt = conn
+            .prepare("SELECT html FROM digest_revisions ORDER BY id")
+            .unwrap();
+        stmt.query_map([], |row| row.get(0))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+    }
+
+    mod migrate {
+        use super::*;
+
+        #[test]
+        fn keeps_replaced_and_edited_versions() {
+            let conn = digests();
+            conn.execute_batch(
+                "INSERT OR REPLACE INTO digests (date, html) VALUES ('2026-01-01', 'second');
+                 UPDATE digests SET html = 'third' WHERE date = '2026-01-01';
+                 INSERT INTO digests (date, html) VALUES ('2026-01-02', 'other');",
+            )
+            .unwrap();
+            assert_eq!(revisions(&conn), ["first", "second"]);
+        }
+
+        #[test]
+        fn ignores_compression_and_identical_rewrites() {
+            let conn = digests();
+            conn.execute_batch(
+                "INSERT OR REPLACE INTO digests (date, html) VALUES ('2026-01-01', 'first');
+                 UPDATE digests SET html_zstd = x'00', html = '' WHERE date = '2026-01-01';",
+            )
+            .unwrap();
+            assert!(revisions(&conn).is_empty());
+        }
+
+        #[test]
+        fn waits_for_the_pipeline() {
+            let conn = Connection::open_in_memory().unwrap();
+            migrate(&conn).unwrap();
+            assert!(!crate::db::table_exists(&conn, "digest_revisions").unwrap());
+        }
+    }
+
+    mod diff {
+        use super::*;
+        use Change::*;
+
+        #[test]
+        fn marks_changed_lines() {
+            let changes = diff("a\nb\nc\nd", "a\nc\nx\nd");
+            assert_eq!(
+                changes,
+                [Same("a"), Removed("b"), Same("c"), Added("x"), Same("d")]
+            );
+        }
+
+        #[test]
+        fn renders_context_around_changes() {
+            let old = (1..=20)
+                .map(|n| n.to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+            let new = old.replace("10", "<ten>");
+            let html = render_diff(&diff(&old, &new));
+            assert!(html.contains("<span class=\"del\">- 10</span>"));
+            assert!(html.contains("<span class=\"add\">+ &lt;ten&gt;</span>"));
+            assert!(html.contains("  7</span>") && !html.contains("  6</span>"));
+            assert_eq!(html.matches("⋯").count(), 2);
+        }
+    }
+}
@@ -0,0 +1,370 @@
+//! Weekly rollups of `source_health` and `shown_narratives` for long stats ranges.
+//!
+//! A background job sums completed weeks (Monday to Sunday, UTC) into
+//! `source_health_weekly` and `shown_narratives_weekly`. Readers take whole
+//! weeks that have been rolled up from those tables and everything else
+//! (partial weeks at either end, the current week) from the raw rows, so the
+//! totals match a raw-only query.
+
+use crate::db;
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS source_health_weekly (
+    week DATE NOT NULL,
+    source_id TEXT NOT NULL,
+    fetches INTEGER NOT NULL,
+    successes INTEGER NOT NULL,
+    PRIMARY KEY (week, source_id)
+);
+CREATE TABLE IF NOT EXISTS shown_narratives_weekly (
+    week DATE NOT NULL,
+    source_id TEXT NOT NULL,
+    tier TEXT NOT NULL,
+    shown INTEGER NOT NULL,
+    PRIMARY KEY (week, source_id, tier)
+);
+";
+
+/// SQLite expression for the Monday starting the week of a timestamp
+pub fn week_of(column: &str) -> String {
+    format!("date({column}, '-6 days', 'weekday 1')")
+}
+
+/// Ranges at least this long report the source trend per week instead of per day
+pub const WEEKLY_TREND_DAYS: i64 = 60;
+
+/// Roll up every completed week not yet summarized. The latest rolled-up
+/// week is redone in case rows arrived late.
+fn roll_up(conn: &mut Connection) -> Result<(), String> {
+    let tx = conn
+        .transaction()
+        .map_err(|e| format!("Cannot roll up stats: {e}"))?;
+    let this_week: String = tx
+        .query_row(&format!("SELECT {}", week_of("'now'")), [], |row| {
+            row.get(0)
+        })
+        .map_err(|e| format!("Query error: {e}"))?;
+    let jobs = [
+        (
+            "source_health",
+            "source_health_weekly",
+            format!(
+                "INSERT INTO source_health_weekly (week, source_id, fetches, successes)
+                 SELECT {week}, source_id, COUNT(*), SUM(success = 1) FROM source_health
+                 WHERE recorded_at >= ?1 AND recorded_at < ?2
+                 GROUP BY 1, source_id",
+                week = week_of("recorded_at")
+            ),
+        ),
+        (
+            "shown_narratives",
+            "shown_narratives_weekly",
+            format!(
+                "INSERT INTO shown_narratives_weekly (week, source_id, tier, shown)
+                 SELECT {week}, source_id, COALESCE(tier, ''), COUNT(*) FROM shown_narratives
+                 WHERE source_id IS NOT NULL AND shown_at >= ?1 AND shown_at < ?2
+                 GROUP BY 1, source_id, 3",
+                week = week_of("shown_at")
+            ),
+        ),
+    ];
+    for (source, rollup, insert) in jobs {
+        if !db::table_exists(&tx, source)? {
+            continue;
+        }
+        let start: String = tx
+            .query_row(
+                &format!("SELECT COALESCE(MAX(week), '') FROM {rollup}"),
+                [],
+                |row| row.get(0),
+            )
+            .map_err(|e| format!("Query error: {e}"))?;
+        tx.execute(&format!("DELETE FROM {rollup} WHERE week >= ?1"), [&start])
+            .map_err(|e| format!("Cannot roll up {source}: {e}"))?;
+        tx.execute(&insert, [&start, &this_week])
+            .map_err(|e| format!("Cannot roll up {source}: {e}"))?;
+    }
+    tx.commit()
+        .map_err(|e| format!("Cannot roll up stats: {e}"))
+}
+
+/// Refresh the rollups every `interval`, forever
+pub async fn run(db_path: String, interval: Duration) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        let path = db_path.clone();
+        let result =
+            db::blocking(move || db::open_rw(&path).and_then(|mut conn| roll_up(&mut conn)))
+                .await
+                .flatten();
+        if let Err(e) = result {
+            tracing::warn!("Could not update stats rollups: {}", e);
+        }
+    }
+}
+
+/// The whole weeks of the range, as [first Monday, end Monday), that `rollup`
+/// already covers; `None` if there are none
+fn rolled_weeks(
+    conn: &Connection,
+    rollup: &str,
+    range: &db::DateRange,
+) -> Result<Option<(String, String)>, String> {
+    if !db::table_exists(conn, rollup)? {
+        return Ok(None);
+    }
+    let (start, end): (String, Option<String>) = conn
+        .query_row(
+            &format!(
+                "SELECT date(?1, 'weekday 1'),
+                        MIN(date(?2, '-5 days', 'weekday 1'),
+                            (SELECT date(MAX(week), '+7 days') FROM {rollup}))"
+            ),
+            [&range.from, &range.to],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    Ok(end.filter(|end| *end > start).map(|end| (start, end)))
+}
+
+/// Fetches and successes per source within the range
+pub fn source_health_totals(
+    conn: &Connection,
+    range: &db::DateRange,
+) -> Result<Vec<(String, i64, i64)>, String> {
+    let weeks = rolled_weeks(conn, "source_health_weekly", range)?;
+    let (rolled, skip) = match weeks {
+        Some(_) => (
+            "UNION ALL
+             SELECT source_id, fetches, successes FROM source_health_weekly
+             WHERE week >= ?3 AND week < ?4",
+            "AND NOT (recorded_at >= ?3 AND recorded_at < ?4)",
+        ),
+        None => ("", ""),
+    };
+    let sql = format!(
+        "SELECT source_id, SUM(fetches), SUM(successes) FROM (
+             SELECT source_id, COUNT(*) AS fetches, SUM(success = 1) AS successes
+             FROM source_health
+             WHERE recorded_at >= ?1 AND recorded_at < date(?2, '+1 day') {skip}
+             GROUP BY source_id
+             {rolled}
+         )
+         GROUP BY source_id
+         ORDER BY source_id"
+    );
+    query(conn, &sql, range, weeks, |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    })
+}
+
+/// Success rate (%) per source per week within the range, oldest week first
+pub fn weekly_success(
+    conn: &Connection,
+    range: &db::DateRange,
+) -> Result<BTreeMap<String, Vec<f64>>, String> {
+    let weeks = rolled_weeks(conn, "source_health_weekly", range)?;
+    let (rolled, skip) = match weeks {
+        Some(_) => (
+            "UNION ALL
+             SELECT source_id, week, fetches, successes FROM source_health_weekly
+             WHERE week >= ?3 AND week < ?4",
+            "AND NOT (recorded_at >= ?3 AND recorded_at < ?4)",
+        ),
+        None => ("", ""),
+    };
+    let sql = format!(
+        "SELECT source_id, SUM(successes) * 100.0 / SUM(fetches) FROM (
+             SELECT source_id, {week} AS week, COUNT(*) AS fetches, SUM(success = 1) AS successes
+             FROM source_health
+             WHERE recorded_at >= ?1 AND recorded_at < date(?2, '+1 day') {skip}
+             GROUP BY source_id, 2
+             {rolled}
+         )
+         GROUP BY source_id, week
+         ORDER BY source_id, week",
+        week = week_of("recorded_at")
+    );
+    let rows: Vec<(String, f64)> = query(conn, &sql, range, weeks, |row| {
+        Ok((row.get(0)?, row.get(1)?))
+    })?;
+    let mut weekly: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for (source_id, rate) in rows {
+        weekly.entry(source_id).or_default().push(rate);
+    }
+    Ok(weekly)
+}
+
+/// Narratives shown per source and tier within the range, most shown first
+pub fn source_usage(
+    conn: &Connection,
+    range: &db::DateRange,
+) -> Result<Vec<(String, String, i64)>, String> {
+    let weeks = rolled_weeks(conn, "shown_narratives_weekly", range)?;
+    let (rolled, skip) = match weeks {
+        Some(_) => (
+            "UNION ALL
+             SELECT source_id, tier, shown FROM shown_narratives_weekly
+             WHERE week >= ?3 AND week < ?4",
+            "AND NOT (shown_at >= ?3 AND shown_at < ?4)",
+        ),
+        None => ("", ""),
+    };
+    let sql = format!(
+        "SELECT source_id, tier, SUM(shown) AS count FROM (
+             SELECT source_id, COALESCE(tier, '') AS tier, COUNT(*) AS shown
+             FROM shown_narratives
+             WHERE source_id IS NOT NULL
+               AND shown_at >= ?1 AND shown_at < date(?2, '+1 day') {skip}
+             GROUP BY source_id, 2
+             {rolled}
+         )
+         GROUP BY source_id, tier
+         ORDER BY count DESC"
+    );
+    query(conn, &sql, range, weeks, |row| {
+        Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+    })
+}
+
+/// Run a rollup-aware query bound to the range (?1, ?2) and rolled weeks (?3, ?4)
+fn query<T>(
+    conn: &Connection,
+    sql: &str,
+    range: &db::DateRange,
+    weeks: Option<(String, String)>,
+    map: impl FnMut(&rusqlite::Row<'_>) -> rusqlite::Result<T>,
+) -> Result<Vec<T>, String> {
+    let mut stmt = conn.prepare(sql).map_err(|e| format!("Query error: {e}"))?;
+    let rows = match weeks {
+        Some((start, end)) => stmt.query_map([&range.from, &range.to, &start, &end], map),
+        None => stmt.query_map([&range.from, &range.to], map),
+    }
+    .map_err(|e| format!("Query error: {e}"))?
+    .filter_map(|r| r.ok())
+    .collect();
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Raw rows spanning three weeks (Mon 2026-01-05 to Sun 2026-01-25) plus
+    /// the current week, which is never rolled up
+    fn conn() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE source_health (source_id TEXT, success INTEGER, recorded_at DATETIME);
+             CREATE TABLE shown_narratives (source_id TEXT, tier TEXT, shown_at DATETIME);
+             INSERT INTO source_health VALUES
+                 ('bbc', 1, '2026-01-04 09:00:00'),
+                 ('bbc', 1, '2026-01-05 09:00:00'),
+                 ('bbc', 0, '2026-01-11 23:00:00'),
+                 ('bbc', 1, '2026-01-12 09:00:00'),
+                 ('bbc', 1, '2026-01-20 09:00:00'),
+                 ('npr', 0, '2026-01-21 09:00:00'),
+                 ('bbc', 1, datetime('now'));
+             INSERT INTO shown_narratives VALUES
+                 ('bbc', 'must_know', '2026-01-06 09:00:00'),
+                 ('bbc', 'must_know', '2026-01-13 09:00:00'),
+                 ('bbc', 'also_notable', '2026-01-24 09:00:00'),
+                 (NULL, 'must_know', '2026-01-13 09:00:00');",
+        )
+        .unwrap();
+        conn.execute_batch(SCHEMA).unwrap();
+        conn
+    }
+
+    fn range(from: &str, to: &str) -> db::DateRange {
+        db::DateRange {
+            from: from.into(),
+            to: to.into(),
+            days: 0,
+        }
+    }
+
+    mod roll_up {
+        use super::*;
+
+        #[test]
+        fn sums_completed_weeks_only() {
+            let mut conn = conn();
+            roll_up(&mut conn).unwrap();
+            roll_up(&mut conn).unwrap();
+            let weeks: Vec<(String, String, i64, i64)> = conn
+                .prepare("SELECT week, source_id, fetches, successes FROM source_health_weekly ORDER BY 1, 2")
+                .unwrap()
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))
+                .unwrap()
+                .map(|r| r.unwrap())
+                .collect();
+            assert_eq!(
+                weeks,
+                [
+                    ("2025-12-29".into(), "bbc".into(), 1, 1),
+                    ("2026-01-05".into(), "bbc".into(), 2, 1),
+                    ("2026-01-12".into(), "bbc".into(), 1, 1),
+                    ("2026-01-19".into(), "bbc".into(), 1, 1),
+                    ("2026-01-19".into(), "npr".into(), 1, 0),
+                ]
+            );
+        }
+    }
+
+    mod source_health_totals {
+        use super::*;
+
+        #[test]
+        fn rollups_match_raw_rows() {
+            let mut conn = conn();
+            // Partial weeks at both ends
+            let r = range("2026-01-07", "2026-01-20");
+            let raw = source_health_totals(&conn, &r).unwrap();
+            assert_eq!(raw, [("bbc".into(), 3, 2)]);
+            roll_up(&mut conn).unwrap();
+            assert_eq!(
+                rolled_weeks(&conn, "source_health_weekly", &r).unwrap(),
+                Some(("2026-01-12".into(), "2026-01-19".into()))
+            );
+            assert_eq!(source_health_totals(&conn, &r).unwrap(), raw);
+        }
+    }
+
+    mod weekly_success {
+        use super::*;
+
+        #[test]
+        fn one_point_per_week() {
+            let mut conn = conn();
+            roll_up(&mut conn).unwrap();
+            let weekly = weekly_success(&conn, &range("2026-01-05", "2026-01-25")).unwrap();
+            assert_eq!(weekly["bbc"], [50.0, 100.0, 100.0]);
+            assert_eq!(weekly["npr"], [0.0]);
+        }
+    }
+
+    mod source_usage {
+        use super::*;
+
+        #[test]
+        fn rollups_match_raw_rows() {
+            let mut conn = conn();
+            let r = range("2026-01-01", "2026-01-31");
+            let raw = source_usage(&conn, &r).unwrap();
+            assert_eq!(
+                raw,
+                [
+                    ("bbc".into(), "must_know".into(), 2),
+                    ("bbc".into(), "also_notable".into(), 1)
+                ]
+            );
+            roll_up(&mut conn).unwrap();
+            assert_eq!(source_usage(&conn, &r).unwrap(), raw);
+        }
+    }
+}
@@ -0,0 +1,582 @@
+//! Pipeline runs started by the server: on `PIPELINE_SCHEDULE` (see
+//! `schedule`) or on demand with `POST /admin/run`.
+//!
+//! Only one run goes at a time. Each gets an ID that `/admin/runs/{id}`
+//! reports on while it runs and after: its status, the stages it has been
+//! through, and the tail of its output. The pipeline marks a stage by logging
+//! a line ending in `Stage: <name>`. Runs are remembered in memory, so a
+//! restart forgets them.
+//!
+//! The pipeline itself checkpoints each stage it completes in
+//! `run_checkpoints`, so a run that crashed or was killed resumes after its
+//! last completed stage; `/admin/runs/checkpoints` shows where the next run
+//! would pick up, and `POST /admin/run?restart=true` starts over instead.
+//!
+//! When the server shuts down, a run in progress is asked to stop after its
+//! current stage: the command gets a `STOP_FILE` path, which the server
+//! creates, and `run.py` checks for it before starting each stage. One still
+//! going when the server's time is up is killed, and resumes from its last
+//! checkpoint.
+
+use crate::{AppState, admin, schedule};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::Notify;
+
+/// Runs remembered for `/admin/runs/{id}`, oldest dropped first
+const KEEP_RUNS: usize = 20;
+
+/// Lines of output kept per run
+const OUTPUT_LINES: usize = 50;
+
+static STAGE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\bStage: ([A-Za-z][\w-]*)\s*$").expect("valid regex"));
+
+/// Terminal colors, which the pipeline's logs may have
+static ANSI_COLOR: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\x1b\[[0-9;]*m").expect("valid regex"));
+
+/// A stage an interrupted pipeline run completed, as `run.py` recorded it
+#[derive(Debug, Serialize)]
+pub struct Checkpoint {
+    pub stage: String,
+    pub dry_run: bool,
+    /// What later stages need from it, such as the articles fetched or the
+    /// recipients emailed
+    pub state: serde_json::Value,
+    /// UTC, as "YYYY-MM-DD HH:MM:SS"
+    pub completed_at: Option<String>,
+}
+
+/// How and when to run the pipeline (`PIPELINE_*` environment variables)
+#[derive(Debug)]
+pub struct Pipeline {
+    /// Run with `sh -c`, in the server's environment
+    pub command: String,
+    pub schedule: Option<schedule::Schedule>,
+    runs: Mutex<Runs>,
+    /// The command running now
+    running: Mutex<Option<Running>>,
+    /// Set when the server shuts down, after which no run starts
+    stopping: AtomicBool,
+    /// Woken when a run's command exits
+    exited: Notify,
+}
+
+#[derive(Debug)]
+struct Running {
+    /// The command's process group, which its children share
+    group: u32,
+    /// Created to ask it to stop after its current stage
+    stop_file: PathBuf,
+}
+
+/// Why a run didn't start
+#[derive(Debug, PartialEq)]
+pub enum NotStarted {
+    /// This run is still going
+    Running(u64),
+    ShuttingDown,
+}
+
+#[derive(Debug, Default)]
+struct Runs {
+    next_id: u64,
+    recent: VecDeque<Run>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Run {
+    id: u64,
+    /// What started it: "manual", or the schedule
+    trigger: String,
+    /// Left its digest in the preview slot, published nothing, and sent no email
+    dry_run: bool,
+    status: Status,
+    /// UTC, as "YYYY-MM-DD HH:MM:SS"
+    started_at: String,
+    finished_at: Option<String>,
+    stages: Vec<Stage>,
+    error: Option<String>,
+    /// The last lines the command wrote, stdout and stderr interleaved
+    output: VecDeque<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Running,
+    Succeeded,
+    Failed,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct Stage {
+    name: String,
+    started_at: String,
+    /// When the next stage started, or the run ended
+    finished_at: Option<String>,
+}
+
+fn utc_now() -> String {
+    chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+impl Run {
+    fn finish(&mut self, error: Option<String>) {
+        let now = utc_now();
+        if let Some(stage) = self.stages.last_mut() {
+            stage.finished_at.get_or_insert_with(|| now.clone());
+        }
+        self.status = if error.is_some() {
+            Status::Failed
+        } else {
+            Status::Succeeded
+        };
+        self.finished_at = Some(now);
+        self.error = error;
+    }
+
+    /// Record a line of output, and the stage it starts if it marks one
+    fn record(&mut self, line: &str) {
+        if let Some(name) = STAGE.captures(line).map(|c| c[1].to_string()) {
+            let now = utc_now();
+            if let Some(stage) = self.stages.last_mut() {
+                stage.finished_at.get_or_insert_with(|| now.clone());
+            }
+            self.stages.push(Stage {
+                name,
+                started_at: now,
+                finished_at: None,
+            });
+        }
+        if self.output.len() == OUTPUT_LINES {
+            self.output.pop_front();
+        }
+        self.output.push_back(line.to_string());
+    }
+}
+
+impl Pipeline {
+    /// Read configuration from the environment; `None` when `PIPELINE_COMMAND` is unset
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let schedule = schedule::Schedule::from_env()?;
+        let Some(command) = std::env::var("PIPELINE_COMMAND")
+            .ok()
+            .filter(|v| !v.is_empty())
+        else {
+            if schedule.is_some() {
+                return Err("PIPELINE_SCHEDULE requires PIPELINE_COMMAND".into());
+            }
+            return Ok(None);
+        };
+        Ok(Some(Self::new(command, schedule)))
+    }
+
+    fn new(command: String, schedule: Option<schedule::Schedule>) -> Self {
+        Self {
+            command,
+            schedule,
+            runs: Mutex::default(),
+            running: Mutex::default(),
+            stopping: AtomicBool::new(false),
+            exited: Notify::new(),
+        }
+    }
+
+    /// Register a run as started, unless one is still going or the server is
+    /// shutting down
+    fn begin(&self, trigger: &str, dry_run: bool) -> Result<u64, NotStarted> {
+        let mut runs = self.runs.lock().unwrap();
+        if self.stopping.load(Ordering::SeqCst) {
+            return Err(NotStarted::ShuttingDown);
+        }
+        if let Some(running) = runs.recent.iter().find(|r| r.status == Status::Running) {
+            return Err(NotStarted::Running(running.id));
+        }
+        runs.next_id += 1;
+        let id = runs.next_id;
+        if runs.recent.len() == KEEP_RUNS {
+            runs.recent.pop_front();
+        }
+        runs.recent.push_back(Run {
+            id,
+            trigger: trigger.to_string(),
+            dry_run,
+            status: Status::Running,
+            started_at: utc_now(),
+            finished_at: None,
+            stages: Vec::new(),
+            error: None,
+            output: VecDeque::new(),
+        });
+        Ok(id)
+    }
+
+    fn update(&self, id: u64, f: impl FnOnce(&mut Run)) {
+        let mut runs = self.runs.lock().unwrap();
+        if let Some(run) = runs.recent.iter_mut().find(|r| r.id == id) {
+            f(run);
+        }
+    }
+
+    fn get(&self, id: u64) -> Option<Run> {
+        let runs = self.runs.lock().unwrap();
+        runs.recent.iter().find(|r| r.id == id).cloned()
+    }
+
+    /// Run the command to completion for run `id`, logging and recording its
+    /// output. A dry run's command gets `DRY_RUN=1`, and one that starts over
+    /// rather than resuming an interrupted run `RESTART=1`. It runs in its own
+    /// process group, so a Ctrl-C meant for the server doesn't cut a stage
+    /// short: the server stops it between stages instead.
+    async fn execute(&self, id: u64, dry_run: bool, restart: bool) -> Result<(), String> {
+        let start = Instant::now();
+        let stop_file =
+            std::env::temp_dir().join(format!("digest-pipeline-{}-{id}.stop", std::process::id()));
+        let mut command = tokio::process::Command::new("sh");
+        command
+            .arg("-c")
+            .arg(&self.command)
+            .env("STOP_FILE", &stop_file)
+            .process_group(0);
+        if dry_run {
+            command.env("DRY_RUN", "1");
+        }
+        if restart {
+            command.env("RESTART", "1");
+        }
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("failed to start: {e}"))?;
+        if let Some(group) = child.id() {
+            *self.running.lock().unwrap() = Some(Running {
+                group,
+                stop_file: stop_file.clone(),
+            });
+        }
+        let stdout = child.stdout.take().map(|out| self.follow(id, out));
+        let stderr = child.stderr.take().map(|err| self.follow(id, err));
+        let (status, _, _) = tokio::join!(
+            child.wait(),
+            async {
+                if let Some(out) = stdout {
+                    out.await
+                }
+            },
+            async {
+                if let Some(err) = stderr {
+                    err.await
+                }
+            },
+        );
+        *self.running.lock().unwrap() = None;
+        let _ = std::fs::remove_file(&stop_file);
+        self.exited.notify_waiters();
+        let status = status.map_err(|e| format!("failed: {e}"))?;
+        let secs = start.elapsed().as_secs();
+        if !status.success() && self.stopping.load(Ordering::SeqCst) {
+            return Err(format!(
+                "stopped after {secs}s as the server shut down ({status}); the next run resumes after its last completed stage"
+            ));
+        }
+        if !status.success() {
+            return Err(format!("failed after {secs}s ({status})"));
+        }
+        tracing::info!(run = id, "Pipeline run finished in {}s", secs);
+        Ok(())
+    }
+
+    /// Log and record each line `output` writes, until it closes
+    async fn follow(&self, id: u64, output: impl AsyncRead + Unpin) {
+        let mut lines = BufReader::new(output).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let line = ANSI_COLOR.replace_all(&line, "");
+            tracing::info!(run = id, "{}", line);
+            self.update(id, |run| run.record(&line));
+        }
+    }
+
+    /// Stop starting runs, and ask the one in progress, if any, to stop after
+    /// its current stage. It has `grace` to do so before it's killed.
+    pub async fn stop(&self, grace: Duration) {
+        self.stopping.store(true, Ordering::SeqCst);
+        let exited = self.exited.notified();
+        let group = {
+            let running = self.running.lock().unwrap();
+            let Some(running) = running.as_ref() else {
+                return;
+            };
+            if let Err(e) = std::fs::write(&running.stop_file, "") {
+                tracing::warn!("Cannot ask the pipeline run to stop: {}", e);
+            }
+            running.group
+        };
+        tracing::info!(
+            "Waiting up to {}s for the pipeline run to finish its current stage",
+            grace.as_secs()
+        );
+        if tokio::time::timeout(grace, exited).await.is_err() {
+            tracing::warn!(
+                "Killing the pipeline run, still going after {}s; the next run resumes after its last completed stage",
+                grace.as_secs()
+            );
+            if let Some(pid) = rustix::process::Pid::from_raw(group as i32) {
+                let _ = rustix::process::kill_process_group(pid, rustix::process::Signal::KILL);
+            }
+        }
+    }
+}
+
+/// Start a pipeline run in the background, returning its ID
+pub fn start(
+    pipeline: &Arc<Pipeline>,
+    trigger: String,
+    dry_run: bool,
+    restart: bool,
+) -> Result<u64, NotStarted> {
+    let id = match pipeline.begin(&trigger, dry_run) {
+        Ok(id) => id,
+        Err(NotStarted::Running(running)) => {
+            tracing::warn!(
+                "Pipeline run ({}) skipped, as run {} is still going",
+                trigger,
+                running
+            );
+            return Err(NotStarted::Running(running));
+        }
+        Err(NotStarted::ShuttingDown) => {
+            tracing::warn!(
+                "Pipeline run ({}) skipped, as the server is shutting down",
+                trigger
+            );
+            return Err(NotStarted::ShuttingDown);
+        }
+    };
+    tracing::info!(run = id, "Pipeline run started ({})", trigger);
+    let pipeline = pipeline.clone();
+    tokio::spawn(async move {
+        let result = pipeline.execute(id, dry_run, restart).await;
+        if let Err(e) = &result {
+            tracing::warn!(run = id, "Pipeline run ({}) {}", trigger, e);
+        }
+        pipeline.update(id, |run| run.finish(result.err()));
+    });
+    Ok(id)
+}
+
+#[derive(Deserialize)]
+pub struct Options {
+    #[serde(default)]
+    dry_run: bool,
+    #[serde(default)]
+    restart: bool,
+}
+
+#[derive(Serialize)]
+pub struct Started {
+    id: u64,
+    /// Where to follow it
+    url: String,
+    /// Where a dry run's digest will be
+    #[serde(skip_serializing_if = "Option::is_none")]
+    preview: Option<String>,
+}
+
+/// Start a pipeline run now, or a dry run with `?dry_run=true`; with
+/// `?restart=true`, an interrupted run's checkpoints are discarded
+pub async fn trigger(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Query(options): Query<Options>,
+) -> Result<(StatusCode, Json<Started>), (StatusCode, String)> {
+    admin::require_admin(&state, &headers)?;
+    let pipeline = state.pipeline.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Pipeline runs not configured (PIPELINE_COMMAND)".to_string(),
+    ))?;
+    match start(pipeline, "manual".into(), options.dry_run, options.restart) {
+        Ok(id) => Ok((
+            StatusCode::ACCEPTED,
+            Json(Started {
+                id,
+                url: format!("/admin/runs/{id}"),
+                preview: options.dry_run.then(|| "/admin/preview".into()),
+            }),
+        )),
+        Err(NotStarted::Running(running)) => Err((
+            StatusCode::CONFLICT,
+            format!("Run {running} is still going; see /admin/runs/{running}"),
+        )),
+        Err(NotStarted::ShuttingDown) => Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The server is shutting down".to_string(),
+        )),
+    }
+}
+
+/// A run's progress, or its outcome
+pub async fn run(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<u64>,
+) -> Result<Json<Run>, (StatusCode, String)> {
+    admin::require_admin(&state, &headers)?;
+    state
+        .pipeline
+        .as_ref()
+        .and_then(|pipeline| pipeline.get(id))
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, format!("No run {id}")))
+}
+
+/// The stages an interrupted run completed, oldest first, which the next
+/// run resumes after; empty when the last run finished
+pub async fn checkpoints(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<Checkpoint>>, (StatusCode, String)> {
+    admin::require_admin(&state, &headers)?;
+    state
+        .blocking(|state| {
+            state
+                .storage
+                .run_checkpoints()
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+        })
+        .await
+        .map(Json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipeline(command: &str) -> Pipeline {
+        Pipeline::new(command.into(), None)
+    }
+
+    mod begin {
+        use super::*;
+
+        #[test]
+        fn allows_one_run_at_a_time() {
+            let pipeline = pipeline("true");
+            assert_eq!(pipeline.begin("manual", false), Ok(1));
+            assert_eq!(pipeline.begin("manual", false), Err(NotStarted::Running(1)));
+            pipeline.update(1, |run| run.finish(None));
+            assert_eq!(pipeline.begin("manual", false), Ok(2));
+            assert_eq!(pipeline.get(1).unwrap().status, Status::Succeeded);
+        }
+
+        #[test]
+        fn forgets_the_oldest_runs() {
+            let pipeline = pipeline("true");
+            for id in 1..=KEEP_RUNS as u64 + 1 {
+                assert_eq!(pipeline.begin("manual", false), Ok(id));
+                pipeline.update(id, |run| run.finish(None));
+            }
+            assert!(pipeline.get(1).is_none());
+            assert!(pipeline.get(2).is_some());
+        }
+    }
+
+    mod record {
+        use super::*;
+
+        #[test]
+        fn tracks_stages_and_the_output_tail() {
+            let pipeline = pipeline("true");
+            let id = pipeline.begin("manual", false).unwrap();
+            pipeline.update(id, |run| {
+                run.record("[2026-03-01 07:00:00 UTC] [INFO] Stage: fetch");
+                for i in 0..OUTPUT_LINES {
+                    run.record(&format!("line {i}"));
+                }
+                run.record("INFO digest_pipeline: Stage: curate");
+                run.record("Next Stage: unmarked, as it doesn't end the line");
+                run.finish(Some("failed after 3s (exit status: 1)".into()));
+            });
+            let run = pipeline.get(id).unwrap();
+            let stages: Vec<&str> = run.stages.iter().map(|s| s.name.as_str()).collect();
+            assert_eq!(stages, ["fetch", "curate"]);
+            assert!(run.stages.iter().all(|s| s.finished_at.is_some()));
+            assert_eq!(run.status, Status::Failed);
+            assert_eq!(run.output.len(), OUTPUT_LINES);
+            assert_eq!(run.output.front().unwrap(), "line 2");
+        }
+    }
+
+    mod execute {
+        use super::*;
+
+        #[tokio::test]
+        async fn records_the_commands_output() {
+            let pipeline = pipeline(
+                "printf '\\033[32mINFO\\033[0m Stage: fetch\\n'; echo \"oops $DRY_RUN $RESTART\" >&2; exit 3",
+            );
+            let id = pipeline.begin("manual", true).unwrap();
+            let error = pipeline.execute(id, true, true).await.unwrap_err();
+            assert!(error.contains("exit status: 3"), "{error}");
+            let run = pipeline.get(id).unwrap();
+            assert_eq!(run.stages[0].name, "fetch");
+            assert!(run.output.contains(&"INFO Stage: fetch".to_string()));
+            assert!(run.output.contains(&"oops 1 1".to_string()));
+        }
+    }
+
+    mod stop {
+        use super::*;
+
+        async fn stop_while_running(command: &str, grace: Duration) -> String {
+            let pipeline = Arc::new(pipeline(command));
+            let id = pipeline.begin("manual", false).unwrap();
+            let running = tokio::spawn({
+                let pipeline = pipeline.clone();
+                async move { pipeline.execute(id, false, false).await }
+            });
+            while pipeline.running.lock().unwrap().is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            pipeline.stop(grace).await;
+            assert_eq!(
+                pipeline.begin("manual", false),
+                Err(NotStarted::ShuttingDown)
+            );
+            running.await.unwrap().unwrap_err()
+        }
+
+        #[tokio::test]
+        async fn asks_the_run_to_stop_between_stages() {
+            let error = stop_while_running(
+                "while [ ! -e \"$STOP_FILE\" ]; do sleep 0.01; done; exit 75",
+                Duration::from_secs(10),
+            )
+            .await;
+            assert!(
+                error.contains("as the server shut down (exit status: 75)"),
+                "{error}"
+            );
+        }
+
+        #[tokio::test]
+        async fn kills_a_run_that_keeps_going() {
+            let error = stop_while_running("sleep 30", Duration::from_millis(50)).await;
+            assert!(error.contains("signal: 9"), "{error}");
+        }
+    }
+}
@@ -0,0 +1,388 @@
+//! Built-in scheduler for pipeline runs.
+//!
+//! With `PIPELINE_SCHEDULE` (a five-field cron expression in local time, as
+//! set by `TZ`) and `PIPELINE_COMMAND`, the server runs the pipeline itself
+//! instead of relying on an external cron or timer. A run that's due while
+//! the previous one is still going is skipped (see `runs`). At startup, a run missed within `PIPELINE_CATCH_UP_HOURS` (while the
+//! server was down, say) is made up for when `digest_runs` has nothing since.
+
+use crate::{db, runs};
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+use rusqlite::OpenFlags;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Longest single sleep while waiting for a run, so a suspended host or a
+/// clock change delays it by a minute at most
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// Years searched for the next match, enough for a schedule on February 29
+const SEARCH_YEARS: usize = 5;
+
+/// When to run the pipeline (`PIPELINE_SCHEDULE`, `PIPELINE_CATCH_UP_HOURS`)
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    pub expression: String,
+    cron: Cron,
+    /// How far back a missed run is still made up for at startup
+    catch_up: Duration,
+}
+
+impl Schedule {
+    /// Read configuration from the environment; `None` when `PIPELINE_SCHEDULE` is unset
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let Some(expression) = var("PIPELINE_SCHEDULE") else {
+            return Ok(None);
+        };
+        let cron = Cron::parse(&expression).map_err(|e| format!("PIPELINE_SCHEDULE: {e}"))?;
+        let hours: u64 = match var("PIPELINE_CATCH_UP_HOURS") {
+            Some(v) => v
+                .parse()
+                .map_err(|_| format!("PIPELINE_CATCH_UP_HOURS must be a number, got '{v}'"))?,
+            None => 12,
+        };
+        Ok(Some(Self {
+            expression,
+            cron,
+            catch_up: Duration::from_secs(3600 * hours),
+        }))
+    }
+}
+
+/// A parsed cron expression: minute, hour, day of month, month, day of week
+#[derive(Clone, Debug, PartialEq)]
+struct Cron {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Day of month or day of week left as `*`; when neither is, a day
+    /// matching either one matches, as in cron
+    any_day: bool,
+    any_weekday: bool,
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+impl Cron {
+    fn parse(expression: &str) -> Result<Self, String> {
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(format!(
+                "expected 5 fields (minute hour day month weekday), got '{expression}'"
+            ));
+        };
+        // Sunday is 0 or 7
+        let weekdays = field(weekday, 0, 7, &WEEKDAYS)?;
+        Ok(Self {
+            minutes: field(minute, 0, 59, &[])?,
+            hours: field(hour, 0, 23, &[])?,
+            days: field(day, 1, 31, &[])?,
+            months: field(month, 1, 12, &MONTHS)?,
+            weekdays: (weekdays | weekdays >> 7) & 0x7f,
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+        })
+    }
+
+    fn matches_day(&self, date: NaiveDate) -> bool {
+        let day = self.days & 1 << date.day() != 0;
+        let weekday = self.weekdays & 1 << date.weekday().num_days_from_sunday() != 0;
+        let day = match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        };
+        day && self.months & 1 << date.month() != 0
+    }
+
+    /// The first matching minute after `after`, in wall-clock time
+    fn next_naive(&self, after: NaiveDateTime) -> Option<NaiveDateTime> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + chrono::Duration::minutes(1);
+        let (first_hour, first_minute) = (start.hour(), start.minute());
+        for (offset, date) in start
+            .date()
+            .iter_days()
+            .take(366 * SEARCH_YEARS)
+            .enumerate()
+        {
+            if !self.matches_day(date) {
+                continue;
+            }
+            let from_hour = if offset == 0 { first_hour } else { 0 };
+            for hour in (from_hour..24).filter(|h| self.hours & 1 << h != 0) {
+                let from_minute = if offset == 0 && hour == first_hour {
+                    first_minute
+                } else {
+                    0
+                };
+                if let Some(minute) = (from_minute..60).find(|m| self.minutes & 1 << m != 0) {
+                    return date.and_hms_opt(hour, minute, 0);
+                }
+            }
+        }
+        None
+    }
+
+    /// The first matching time after `after` in its time zone. Times that
+    /// don't exist there (skipped by a daylight saving change) are passed
+    /// over; times that happen twice match the first time.
+    fn next<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let zone = after.timezone();
+        let mut naive = after.naive_local();
+        loop {
+            naive = self.next_naive(naive)?;
+            if let Some(time) = zone.from_local_datetime(&naive).earliest()
+                && time > *after
+            {
+                return Some(time);
+            }
+        }
+    }
+}
+
+/// Parse one cron field into a bitmask of the values it matches
+fn field(spec: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, String> {
+    let value = |s: &str| -> Result<u32, String> {
+        let lower = s.to_ascii_lowercase();
+        let value = match names.iter().position(|n| *n == lower) {
+            // Names count from the field's minimum (January is 1, Sunday 0)
+            Some(index) => index as u32 + min,
+            None => s.parse().map_err(|_| format!("invalid value '{s}'"))?,
+        };
+        if value < min || value > max {
+            return Err(format!("{value} is outside {min}-{max}"));
+        }
+        Ok(value)
+    };
+    let mut mask = 0;
+    for item in spec.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(format!("invalid step in '{item}'")),
+            },
+            None => (item, 1),
+        };
+        let (from, to) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((from, to)) => (value(from)?, value(to)?),
+            // "5/15" runs from 5 to the end
+            None if step > 1 => (value(range)?, max),
+            None => {
+                let v = value(range)?;
+                (v, v)
+            }
+        };
+        if from > to {
+            return Err(format!("backwards range '{range}'"));
+        }
+        for v in (from..=to).step_by(step as usize) {
+            mask |= 1 << v;
+        }
+    }
+    Ok(mask)
+}
+
+/// When the pipeline last recorded a run, in UTC
+fn last_run(db_path: &str) -> Result<Option<NaiveDateTime>, String> {
+    let conn = db::open(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| format!("DB error: {e}"))?;
+    if !db::table_exists(&conn, "digest_runs")? {
+        return Ok(None);
+    }
+    let last: Option<String> = conn
+        .query_row("SELECT MAX(run_at) FROM digest_runs", [], |row| row.get(0))
+        .map_err(|e| format!("Query error: {e}"))?;
+    Ok(last.and_then(|t| NaiveDateTime::parse_from_str(&t, "%Y-%m-%d %H:%M:%S").ok()))
+}
+
+/// The latest scheduled time in the catch-up window before `now` that no
+/// run has been recorded since
+fn missed<Tz: TimeZone>(
+    schedule: &Schedule,
+    now: &DateTime<Tz>,
+    last_run: Option<NaiveDateTime>,
+) -> Option<DateTime<Tz>> {
+    let window = chrono::Duration::from_std(schedule.catch_up).ok()?;
+    let mut due = None;
+    let mut after = now.clone() - window;
+    while let Some(time) = schedule.cron.next(&after).filter(|t| t <= now) {
+        after = time.clone();
+        due = Some(time);
+    }
+    due.filter(|due| last_run.is_none_or(|last| last.and_utc() < due.with_timezone(&Utc)))
+}
+
+/// Run the pipeline on `schedule`, forever, after catching up on a run
+/// missed while the server was down
+pub async fn run(db_path: String, pipeline: Arc<runs::Pipeline>, schedule: Schedule) {
+    match db::blocking(move || last_run(&db_path)).await.flatten() {
+        Ok(last) => {
+            if let Some(due) = missed(&schedule, &Local::now(), last) {
+                let _ = runs::start(
+                    &pipeline,
+                    format!("catching up on {}", due.format("%F %R %Z")),
+                    false,
+                    false,
+                );
+            }
+        }
+        Err(e) => tracing::warn!("Cannot check for a missed pipeline run: {}", e),
+    }
+    loop {
+        let Some(next) = schedule.cron.next(&Local::now()) else {
+            tracing::warn!("PIPELINE_SCHEDULE never matches; no runs scheduled");
+            return;
+        };
+        tracing::info!("Next pipeline run at {}", next.format("%F %R %Z"));
+        while let Ok(wait) = (next - Local::now()).to_std() {
+            tokio::time::sleep(wait.min(MAX_SLEEP)).await;
+        }
+        let _ = runs::start(
+            &pipeline,
+            format!("scheduled for {}", next.format("%F %R %Z")),
+            false,
+            false,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    fn at(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    mod parse {
+        use super::*;
+
+        #[test]
+        fn reads_lists_ranges_steps_and_names() {
+            let cron = Cron::parse("*/15 6-8,20 * jan-mar mon-fri").unwrap();
+            assert_eq!(cron.minutes, 1 | 1 << 15 | 1 << 30 | 1 << 45);
+            assert_eq!(cron.hours, 1 << 6 | 1 << 7 | 1 << 8 | 1 << 20);
+            assert_eq!(cron.months, 0b1110);
+            assert_eq!(cron.weekdays, 0b0111110);
+            assert!(cron.any_day && !cron.any_weekday);
+            assert_eq!(Cron::parse("0 0 * * 7").unwrap().weekdays, 1);
+            assert_eq!(
+                Cron::parse("@daily").unwrap(),
+                Cron::parse("0 0 * * *").unwrap()
+            );
+        }
+
+        #[test]
+        fn rejects_bad_expressions() {
+            for bad in [
+                "0 7 * *",
+                "60 7 * * *",
+                "0 7 0 * *",
+                "0 9-7 * * *",
+                "*/0 * * * *",
+            ] {
+                assert!(Cron::parse(bad).is_err(), "{bad}");
+            }
+        }
+    }
+
+    mod next {
+        use super::*;
+
+        #[test]
+        fn finds_the_next_matching_minute() {
+            let daily = Cron::parse("30 7 * * *").unwrap();
+            assert_eq!(
+                daily.next_naive(at("2026-03-01 07:29")),
+                Some(at("2026-03-01 07:30"))
+            );
+            assert_eq!(
+                daily.next_naive(at("2026-03-01 07:30")),
+                Some(at("2026-03-02 07:30"))
+            );
+            let weekdays = Cron::parse("0 6 * * mon-fri").unwrap();
+            // 2026-03-06 is a Friday
+            assert_eq!(
+                weekdays.next_naive(at("2026-03-06 06:00")),
+                Some(at("2026-03-09 06:00"))
+            );
+            let leap = Cron::parse("0 0 29 2 *").unwrap();
+            assert_eq!(
+                leap.next_naive(at("2026-03-01 00:00")),
+                Some(at("2028-02-29 00:00"))
+            );
+        }
+
+        #[test]
+        fn matches_either_day_when_both_are_set() {
+            // The 1st, or any Monday
+            let cron = Cron::parse("0 0 1 * 1").unwrap();
+            assert_eq!(
+                cron.next_naive(at("2026-03-01 00:00")),
+                Some(at("2026-03-02 00:00"))
+            );
+            assert_eq!(
+                cron.next_naive(at("2026-03-30 00:00")),
+                Some(at("2026-04-01 00:00"))
+            );
+        }
+
+        #[test]
+        fn works_in_local_time() {
+            let cron = Cron::parse("0 7 * * *").unwrap();
+            let zone = FixedOffset::west_opt(5 * 3600).unwrap();
+            let after = zone.from_local_datetime(&at("2026-03-01 08:00")).unwrap();
+            let next = cron.next(&after).unwrap();
+            assert_eq!(next.naive_local(), at("2026-03-02 07:00"));
+            assert_eq!(next.naive_utc(), at("2026-03-02 12:00"));
+        }
+    }
+
+    mod missed {
+        use super::*;
+
+        fn schedule(expression: &str, hours: u64) -> Schedule {
+            Schedule {
+                expression: expression.into(),
+                cron: Cron::parse(expression).unwrap(),
+                catch_up: Duration::from_secs(3600 * hours),
+            }
+        }
+
+        #[test]
+        fn catches_up_on_the_latest_unrun_time_in_the_window() {
+            let now = Utc.from_utc_datetime(&at("2026-03-01 10:00"));
+            let daily = schedule("0 7 * * *", 12);
+            let due = Some(Utc.from_utc_datetime(&at("2026-03-01 07:00")));
+            assert_eq!(missed(&daily, &now, None), due);
+            assert_eq!(missed(&daily, &now, Some(at("2026-02-28 07:05"))), due);
+            assert_eq!(missed(&daily, &now, Some(at("2026-03-01 07:05"))), None);
+            let hourly = schedule("0 * * * *", 12);
+            assert_eq!(
+                missed(&hourly, &now, Some(at("2026-03-01 08:30"))),
+                Some(now)
+            );
+        }
+
+        #[test]
+        fn leaves_runs_outside_the_window() {
+            let now = Utc.from_utc_datetime(&at("2026-03-01 22:00"));
+            assert_eq!(missed(&schedule("0 7 * * *", 12), &now, None), None);
+            assert_eq!(missed(&schedule("0 7 * * *", 0), &now, None), None);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+//! Stopping on SIGTERM or SIGINT without cutting anyone off: the listener
+//! closes, requests in progress finish, a pipeline run stops after its
+//! current stage (see `runs`), and the database is checkpointed and closed.
+//! Each gets `SHUTDOWN_TIMEOUT_SECS` (default 20) before it's cut short, so
+//! the platform's own timeout (Fly's `kill_timeout`, Docker's `stop_timeout`)
+//! should be longer.
+
+use crate::{AppState, db};
+use axum::{
+    Router,
+    serve::{Listener, ListenerExt},
+};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::watch;
+
+/// How long requests in progress, and a pipeline run's current stage, get to
+/// finish once a shutdown starts
+pub fn timeout() -> Duration {
+    Duration::from_secs(
+        std::env::var("SHUTDOWN_TIMEOUT_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(20),
+    )
+}
+
+/// Wait for SIGTERM (what Docker and most platforms stop with) or SIGINT
+/// (Ctrl-C, and Fly's default), returning its name
+async fn stop_signal() -> &'static str {
+    let (Ok(mut terminate), Ok(mut interrupt)) = (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+    ) else {
+        tracing::warn!("Cannot listen for shutdown signals; stopping will cut requests short");
+        return std::future::pending().await;
+    };
+    tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = interrupt.recv() => "SIGINT",
+    }
+}
+
+/// Resolve once `stopping` is set
+async fn stopped(mut stopping: watch::Receiver<bool>) {
+    let _ = stopping.wait_for(|stopping| *stopping).await;
+}
+
+/// Serve `app` until SIGTERM or SIGINT, then shut down within `grace`
+pub async fn serve<L>(listener: L, app: Router, state: Arc<AppState>, grace: Duration)
+where
+    L: Listener<Addr = SocketAddr>,
+{
+    let (stop, stopping) = watch::channel(false);
+    tokio::spawn(async move {
+        let name = stop_signal().await;
+        tracing::info!(
+            "{} received; finishing requests in progress (up to {}s)",
+            name,
+            grace.as_secs()
+        );
+        let _ = stop.send(true);
+    });
+
+    // Through `tap_io`, any listener's peer address reaches handlers as `ConnectInfo`
+    let server = axum::serve(
+        listener.tap_io(|_| {}),
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(stopped(stopping.clone()));
+    let drain = async {
+        tokio::select! {
+            result = server.into_future() => {
+                if let Err(e) = result {
+                    tracing::error!("Server error: {}", e);
+                }
+            }
+            _ = async {
+                stopped(stopping.clone()).await;
+                tokio::time::sleep(grace).await;
+            } => tracing::warn!("Requests still going after {}s; closing them", grace.as_secs()),
+        }
+    };
+    let stop_pipeline = async {
+        stopped(stopping.clone()).await;
+        if let Some(pipeline) = &state.pipeline {
+            pipeline.stop(grace).await;
+        }
+    };
+    tokio::join!(drain, stop_pipeline);
+    close(&state).await;
+    tracing::info!("digest-server stopped");
+}
+
+/// Fold the write-ahead log back into the SQLite file, so it stands alone
+/// for a snapshot of the volume. Connections close as the server exits.
+async fn close(state: &AppState) {
+    if state.db.is_none() || !state.read_write {
+        return;
+    }
+    let path = state.db_path.clone();
+    let checkpoint = db::blocking(move || {
+        db::open_rw(&path)?
+            .execute_batch("PRAGMA wal_checkpoint(TRUNCATE)")
+            .map_err(|e| format!("Cannot checkpoint the database: {e}"))
+    })
+    .await
+    .flatten();
+    if let Err(e) = checkpoint {
+        tracing::warn!("{}", e);
+    }
+}
@@ -0,0 +1,225 @@
+//! "Digest delivered on time" SLO and its error budget.
+//!
+//! Each day from the first recorded run onwards counts once: on time if a run
+//! finished before the deadline, late if one finished after, missed if none did.
+
+use crate::db;
+use rusqlite::Connection;
+
+/// Delivery objective (`DELIVERY_DEADLINE`, `DELIVERY_SLO_PCT`)
+#[derive(Clone, Debug)]
+pub struct SloConfig {
+    /// UTC time of day (HH:MM) the digest should be out by
+    pub deadline: String,
+    /// Share of days that must be on time, e.g. 95.0
+    pub target_pct: f64,
+}
+
+impl SloConfig {
+    /// Read configuration from the environment; `None` when no deadline is set
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let Some(deadline) = std::env::var("DELIVERY_DEADLINE")
+            .ok()
+            .filter(|v| !v.is_empty())
+        else {
+            return Ok(None);
+        };
+        let valid = deadline.len() == 5
+            && deadline.as_bytes()[2] == b':'
+            && deadline[..2].parse::<u8>().is_ok_and(|h| h < 24)
+            && deadline[3..].parse::<u8>().is_ok_and(|m| m < 60);
+        if !valid {
+            return Err(format!(
+                "DELIVERY_DEADLINE must be HH:MM (UTC), got '{deadline}'"
+            ));
+        }
+        let target_pct = match std::env::var("DELIVERY_SLO_PCT") {
+            Ok(v) if !v.is_empty() => v
+                .parse::<f64>()
+                .ok()
+                .filter(|p| (0.0..100.0).contains(p))
+                .ok_or(format!("DELIVERY_SLO_PCT must be below 100, got '{v}'"))?,
+            _ => 95.0,
+        };
+        Ok(Some(Self {
+            deadline,
+            target_pct,
+        }))
+    }
+}
+
+/// SLO attainment and error budget over a window
+#[derive(Clone, Debug, PartialEq)]
+pub struct Budget {
+    pub target_pct: f64,
+    pub days: i64,
+    pub on_time: i64,
+    pub late: i64,
+    pub missed: i64,
+    /// Share of the window's allowed bad days still unspent (negative when blown)
+    pub remaining_pct: f64,
+    /// Remaining budget (%) after each day, oldest first, for the burn-down line
+    pub burn_down: Vec<f64>,
+}
+
+impl Budget {
+    pub fn attained_pct(&self) -> f64 {
+        if self.days > 0 {
+            self.on_time as f64 / self.days as f64 * 100.0
+        } else {
+            100.0
+        }
+    }
+}
+
+/// Score each day given the time (HH:MM:SS) its first run finished, if any.
+///
+/// `today`'s entry is skipped while it has no run and `now` is before the deadline.
+fn score(days: &[(String, Option<String>)], config: &SloConfig, today: &str, now: &str) -> Budget {
+    let deadline = format!("{}:00", config.deadline);
+    let scored: Vec<Option<bool>> = days
+        .iter()
+        .filter(|(day, finished)| !(day == today && finished.is_none() && now < deadline.as_str()))
+        .map(|(_, finished)| finished.as_ref().map(|t| *t <= deadline))
+        .collect();
+    let total = scored.len() as i64;
+    let allowed = total as f64 * (100.0 - config.target_pct) / 100.0;
+    let mut bad = 0;
+    let burn_down = scored
+        .iter()
+        .map(|s| {
+            if *s != Some(true) {
+                bad += 1;
+            }
+            remaining(allowed, bad)
+        })
+        .collect();
+    Budget {
+        target_pct: config.target_pct,
+        days: total,
+        on_time: scored.iter().filter(|s| **s == Some(true)).count() as i64,
+        late: scored.iter().filter(|s| **s == Some(false)).count() as i64,
+        missed: scored.iter().filter(|s| s.is_none()).count() as i64,
+        remaining_pct: remaining(allowed, bad),
+        burn_down,
+    }
+}
+
+fn remaining(allowed: f64, bad: i64) -> f64 {
+    if allowed > 0.0 {
+        (allowed - bad as f64) / allowed * 100.0
+    } else if bad == 0 {
+        100.0
+    } else {
+        -100.0 * bad as f64
+    }
+}
+
+/// Delivery SLO over the range, counting from the first recorded run
+pub fn delivery(
+    conn: &Connection,
+    range: &db::DateRange,
+    config: &SloConfig,
+) -> Result<Budget, String> {
+    let (today, now): (String, String) = conn
+        .query_row("SELECT date('now'), time('now')", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .map_err(|e| format!("Query error: {e}"))?;
+    if !db::table_exists(conn, "digest_runs")? {
+        return Ok(score(&[], config, &today, &now));
+    }
+    let mut stmt = conn
+        .prepare(
+            "WITH RECURSIVE bounds AS (
+                 SELECT MAX(?1, (SELECT date(MIN(run_at)) FROM digest_runs)) AS f,
+                        MIN(?2, date('now')) AS t
+             ),
+             days(d) AS (
+                 SELECT f FROM bounds WHERE f IS NOT NULL AND f <= t
+                 UNION ALL
+                 SELECT date(d, '+1 day') FROM days, bounds WHERE d < t
+             ),
+             firsts AS (
+                 SELECT date(run_at) AS d, MIN(time(run_at)) AS finished
+                 FROM digest_runs
+                 WHERE run_at >= ?1 AND run_at < date(?2, '+1 day')
+                 GROUP BY date(run_at)
+             )
+             SELECT days.d, firsts.finished
+             FROM days LEFT JOIN firsts ON firsts.d = days.d
+             ORDER BY days.d",
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    let days: Vec<(String, Option<String>)> = stmt
+        .query_map([&range.from, &range.to], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(score(&days, config, &today, &now))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> SloConfig {
+        SloConfig {
+            deadline: "08:00".into(),
+            target_pct: 90.0,
+        }
+    }
+
+    fn day(d: &str, finished: Option<&str>) -> (String, Option<String>) {
+        (d.into(), finished.map(String::from))
+    }
+
+    mod score {
+        use super::*;
+
+        #[test]
+        fn classifies_days_and_burns_budget() {
+            let mut days: Vec<_> = (10..=27)
+                .map(|d| day(&format!("2026-01-{d}"), Some("07:05:00")))
+                .collect();
+            days.push(day("2026-01-28", Some("09:30:00")));
+            days.push(day("2026-01-29", None));
+            let b = score(&days, &config(), "2026-02-01", "12:00:00");
+            assert_eq!((b.days, b.on_time, b.late, b.missed), (20, 18, 1, 1));
+            // 10% of 20 days = 2 bad days allowed, both spent
+            assert_eq!(b.remaining_pct, 0.0);
+            assert_eq!(b.burn_down[17..], [100.0, 50.0, 0.0]);
+            assert_eq!(b.attained_pct(), 90.0);
+        }
+
+        #[test]
+        fn today_is_pending_until_the_deadline() {
+            let days = [day("2026-01-10", Some("07:00:00")), day("2026-01-11", None)];
+            assert_eq!(score(&days, &config(), "2026-01-11", "06:00:00").days, 1);
+            assert_eq!(score(&days, &config(), "2026-01-11", "08:30:00").missed, 1);
+        }
+    }
+
+    mod delivery {
+        use super::*;
+
+        #[test]
+        fn starts_at_first_run() {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(
+                "CREATE TABLE digest_runs (run_at DATETIME);
+                 INSERT INTO digest_runs VALUES ('2026-01-10 07:30:00'), ('2026-01-12 08:30:00');",
+            )
+            .unwrap();
+            let range = db::DateRange {
+                from: "2026-01-01".into(),
+                to: "2026-01-12".into(),
+                days: 12,
+            };
+            let b = delivery(&conn, &range, &config()).unwrap();
+            assert_eq!((b.days, b.on_time, b.late, b.missed), (3, 1, 1, 1));
+        }
+    }
+}
@@ -0,0 +1,341 @@
+//! Direct SMTP delivery for small lists that don't need an email provider
+
+use crate::storage::Storage;
+use crate::{db, email_html, format_date, tokens};
+use lettre::{
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    message::{
+        Mailbox,
+        header::{ContentType, HeaderName, HeaderValue},
+    },
+    transport::smtp::authentication::Credentials,
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Placeholder Resend substitutes per recipient; we fill it in ourselves over SMTP
+pub const UNSUBSCRIBE_PLACEHOLDER: &str = "{{{RESEND_UNSUBSCRIBE_URL}}}";
+
+/// Pause between batches so small relays don't throttle or flag us
+const BATCH_PAUSE: Duration = Duration::from_secs(1);
+
+/// SMTP relay settings (`SMTP_*` environment variables)
+#[derive(Clone, Debug)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: Option<u16>,
+    /// "starttls" (default), "tls" for implicit TLS, or "none" for a local relay
+    pub tls: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    /// Refuse to send to lists bigger than this - use a provider instead
+    pub max_recipients: usize,
+    pub batch_size: usize,
+}
+
+impl SmtpConfig {
+    /// Read configuration from the environment; `None` when `SMTP_HOST` is unset
+    pub fn from_env() -> Result<Option<Self>, String> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+        let Some(host) = var("SMTP_HOST") else {
+            return Ok(None);
+        };
+        let number = |name: &str, default: usize| {
+            var(name).map_or(Ok(default), |v| {
+                v.parse()
+                    .map_err(|_| format!("{name} must be a number, got '{v}'"))
+            })
+        };
+        let port = var("SMTP_PORT")
+            .map(|p| p.parse().map_err(|_| format!("Invalid SMTP_PORT '{p}'")))
+            .transpose()?;
+        let tls = var("SMTP_TLS").unwrap_or_else(|| "starttls".into());
+        if !["starttls", "tls", "none"].contains(&tls.as_str()) {
+            return Err(format!(
+                "SMTP_TLS must be starttls, tls, or none, got '{tls}'"
+            ));
+        }
+        Ok(Some(Self {
+            host,
+            port,
+            tls,
+            username: var("SMTP_USERNAME"),
+            password: var("SMTP_PASSWORD"),
+            from: var("SMTP_FROM").ok_or("SMTP_FROM is required with SMTP_HOST")?,
+            max_recipients: number("SMTP_MAX_RECIPIENTS", 100)?,
+            batch_size: number("SMTP_BATCH_SIZE", 10)?.max(1),
+        }))
+    }
+
+    fn transport(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>, String> {
+        let mut builder = match self.tls.as_str() {
+            "tls" => AsyncSmtpTransport::<Tokio1Executor>::relay(&self.host),
+            "none" => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                &self.host,
+            )),
+            _ => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.host),
+        }
+        .map_err(|e| format!("Invalid SMTP relay: {e}"))?;
+        if let Some(port) = self.port {
+            builder = builder.port(port);
+        }
+        if let (Some(user), Some(pass)) = (&self.username, &self.password) {
+            builder = builder.credentials(Credentials::new(user.clone(), pass.clone()));
+        }
+        Ok(builder.build())
+    }
+}
+
+/// One outgoing message
+pub struct Outgoing {
+    pub to: String,
+    pub subject: String,
+    pub html: String,
+    /// Sent as `List-Unsubscribe` when present
+    pub unsubscribe_url: Option<String>,
+}
+
+fn build(config: &SmtpConfig, email: &Outgoing) -> Result<Message, String> {
+    let from: Mailbox = config
+        .from
+        .parse()
+        .map_err(|e| format!("Invalid SMTP_FROM: {e}"))?;
+    let to: Mailbox = email
+        .to
+        .parse()
+        .map_err(|e| format!("Invalid recipient '{}': {e}", email.to))?;
+    let mut builder = Message::builder()
+        .from(from)
+        .to(to)
+        .subject(&email.subject)
+        .header(ContentType::TEXT_HTML);
+    if let Some(url) = &email.unsubscribe_url {
+        builder = builder.raw_header(HeaderValue::new(
+            HeaderName::new_from_ascii_str("List-Unsubscribe"),
+            format!("<{url}>"),
+        ));
+    }
+    builder
+        .body(email.html.clone())
+        .map_err(|e| format!("Cannot build message: {e}"))
+}
+
+/// Send a single message
+pub async fn send(config: &SmtpConfig, email: &Outgoing) -> Result<(), String> {
+    let message = build(config, email)?;
+    config
+        .transport()?
+        .send(message)
+        .await
+        .map_err(|e| format!("SMTP error: {e}"))?;
+    Ok(())
+}
+
+/// Send one message per recipient in batches over a single pooled connection.
+///
+/// Returns how many were accepted by the relay; individual failures are logged
+/// and skipped so one bad address doesn't stop the rest of the list.
+pub async fn send_batched(config: &SmtpConfig, emails: &[Outgoing]) -> Result<usize, String> {
+    check_list_size(emails.len(), config.max_recipients)?;
+    let transport = config.transport()?;
+    let mut sent = 0;
+    for (i, batch) in emails.chunks(config.batch_size).enumerate() {
+        if i > 0 {
+            tokio::time::sleep(BATCH_PAUSE).await;
+        }
+        for email in batch {
+            match build(config, email) {
+                Ok(message) => match transport.send(message).await {
+                    Ok(_) => sent += 1,
+                    Err(e) => tracing::warn!("SMTP send to {} failed: {}", email.to, e),
+                },
+                Err(e) => tracing::warn!("{}", e),
+            }
+        }
+    }
+    Ok(sent)
+}
+
+fn check_list_size(recipients: usize, max: usize) -> Result<(), String> {
+    if recipients > max {
+        return Err(format!(
+            "{recipients} recipients exceeds SMTP_MAX_RECIPIENTS ({max}); use Resend for larger lists"
+        ));
+    }
+    Ok(())
+}
+
+/// Fill the per-recipient unsubscribe link into a stored digest
+pub fn personalize(html: &str, unsubscribe_url: &str) -> String {
+    html.replace(UNSUBSCRIBE_PLACEHOLDER, unsubscribe_url)
+}
+
+/// A `send-digest` run
+pub struct Delivery<'a> {
+    pub storage: &'a Arc<dyn Storage>,
+    /// SQLite file to log the broadcast in, when that's the storage
+    pub db_path: Option<&'a str>,
+    pub audience: &'a str,
+    /// Digest to send; the latest when `None`
+    pub date: Option<&'a str>,
+    pub digest_name: &'a str,
+    pub token_secret: &'a [u8],
+    pub public_url: &'a str,
+    pub click_tracking: bool,
+    pub dry_run: bool,
+}
+
+/// Send a stored digest to every local subscriber of an audience, each with
+/// their own signed unsubscribe link. Returns the number of messages sent.
+pub async fn deliver_digest(config: &SmtpConfig, delivery: &Delivery<'_>) -> Result<usize, String> {
+    let (storage, date, audience) = (
+        Arc::clone(delivery.storage),
+        delivery.date.map(str::to_string),
+        delivery.audience.to_string(),
+    );
+    let ((date, html), recipients) = db::blocking(move || {
+        let digest = storage
+            .digest(date.as_deref())?
+            .ok_or_else(|| "No matching digest".to_string())?;
+        let recipients: Vec<String> = storage
+            .subscribers(&audience)?
+            .into_iter()
+            .filter_map(|(email, subscribed)| subscribed.then_some(email))
+            .collect();
+        Ok::<_, String>((digest, recipients))
+    })
+    .await??;
+    check_list_size(recipients.len(), config.max_recipients)?;
+
+    let public_url = delivery.public_url.trim_end_matches('/');
+    let html = email_html::digest(html, &date, delivery.click_tracking.then_some(public_url));
+    let subject = format!("{} – {}", delivery.digest_name, format_date(&date));
+    let emails: Vec<Outgoing> = recipients
+        .into_iter()
+        .map(|to| {
+            let claims = tokens::Claims::new(
+                tokens::Action::Unsubscribe,
+                &to,
+                Some(delivery.audience),
+                tokens::LINK_TTL_SECS,
+            );
+            let unsubscribe_url = format!(
+                "{public_url}/unsubscribe?token={}",
+                tokens::mint(delivery.token_secret, &claims)
+            );
+            Outgoing {
+                to,
+                subject: subject.clone(),
+                html: personalize(&html, &unsubscribe_url),
+                unsubscribe_url: Some(unsubscribe_url),
+            }
+        })
+        .collect();
+
+    if delivery.dry_run {
+        println!(
+            "Would send {date} to {} subscriber(s) of '{}'",
+            emails.len(),
+            delivery.audience
+        );
+        return Ok(0);
+    }
+    let sent = send_batched(config, &emails).await?;
+    if let Some(db_path) = delivery.db_path
+        && let Err(e) = record_broadcast(db_path, &date, delivery.audience, sent)
+    {
+        tracing::warn!("Could not record broadcast: {}", e);
+    }
+    Ok(sent)
+}
+
+/// Log the send in the pipeline's `digest_broadcasts` table, if it exists, so
+/// engagement stats see SMTP sends too
+fn record_broadcast(db_path: &str, date: &str, audience: &str, sent: usize) -> Result<(), String> {
+    let conn = db::open_rw(db_path)?;
+    if !db::table_exists(&conn, "digest_broadcasts")? {
+        return Ok(());
+    }
+    conn.execute(
+        "INSERT INTO digest_broadcasts (broadcast_id, digest_date, audience_id, recipients)
+         VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![
+            format!("smtp-{date}-{audience}-{}", tokens::now()),
+            date,
+            format!("smtp:{audience}"),
+            sent as i64
+        ],
+    )
+    .map_err(|e| format!("Cannot save broadcast: {e}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> SmtpConfig {
+        SmtpConfig {
+            host: "localhost".into(),
+            port: None,
+            tls: "none".into(),
+            username: None,
+            password: None,
+            from: "Digest <digest@example.com>".into(),
+            max_recipients: 2,
+            batch_size: 1,
+        }
+    }
+
+    mod check_list_size {
+        use super::*;
+
+        #[test]
+        fn allows_up_to_max() {
+            assert!(check_list_size(2, 2).is_ok());
+            assert!(check_list_size(3, 2).is_err());
+        }
+    }
+
+    mod build {
+        use super::*;
+
+        #[test]
+        fn adds_list_unsubscribe_header() {
+            let email = Outgoing {
+                to: "reader@example.com".into(),
+                subject: "Digest".into(),
+                html: "<p>Hi</p>".into(),
+                unsubscribe_url: Some("https://d.example.com/unsubscribe?token=t".into()),
+            };
+            let raw = String::from_utf8(build(&config(), &email).unwrap().formatted()).unwrap();
+            assert!(raw.contains("List-Unsubscribe: <https://d.example.com/unsubscribe?token=t>"));
+            assert!(raw.contains("To: reader@example.com"));
+        }
+
+        #[test]
+        fn rejects_bad_recipient() {
+            let email = Outgoing {
+                to: "not an address".into(),
+                subject: "Digest".into(),
+                html: String::new(),
+                unsubscribe_url: None,
+            };
+            assert!(build(&config(), &email).is_err());
+        }
+    }
+
+    mod personalize {
+        use super::*;
+
+        #[test]
+        fn replaces_placeholder() {
+            let html = r#"<a href="{{{RESEND_UNSUBSCRIBE_URL}}}">Unsubscribe</a>"#;
+            assert_eq!(
+                personalize(html, "https://x/u"),
+                r#"<a href="https://x/u">Unsubscribe</a>"#
+            );
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! Feed definitions from `sources.toml`, shared by the server's source pages
+//! and `digest-pipeline`, and validated as `run.py` validates them.
+//!
+//! Each feed is a `[[source]]` table. Only `id`, `name`, `url`, `bias`, and
+//! `perspective` are required:
+//!
+//! ```toml
+//! [[source]]
+//! id = "bbc_world"
+//! name = "BBC World"
+//! url = "https://feeds.bbci.co.uk/news/world/rss.xml"  # or a site, for its feed to be found
+//! bias = "center"
+//! perspective = "british"
+//! owner = "BBC (public)"  # shown with its stories, with its bias and region
+//! region = "UK"
+//! weight = 1.5           # how much its stories count in curation (default 1)
+//! topics = ["geopolitics"]
+//! delay_secs = 5         # between requests to its host (default FETCH_HOST_DELAY)
+//! enabled = false        # keep it listed without fetching it (default true)
+//! ```
+//!
+//! A `url` of `imaps://host/Mailbox?from=address` reads newsletters from a
+//! mailbox instead (see `digest-pipeline`'s `newsletters`).
+//!
+//! A `[filters]` table applies to every feed, and a `[source.filters]` table
+//! after a source to that one:
+//!
+//! ```toml
+//! [filters]
+//! exclude = ["celebrity", "Premier League"]  # words or phrases, any case
+//! exclude_regex = ['\bNFL\b']               # regular expressions
+//! include = []                               # when set, one must match
+//! include_regex = []
+//! ```
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Source {
+    /// Lowercase letters, digits, and underscores; names the fetched file
+    pub id: String,
+    pub name: String,
+    pub url: String,
+    pub bias: String,
+    pub perspective: String,
+    /// Who owns it, as readers should know it
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// Where it reports from
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Relative importance of the feed's stories; 1 is the norm
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    /// Topics the feed mostly covers, as the digest tags them
+    #[serde(default)]
+    pub topics: Vec<String>,
+    /// Seconds between the end of a request to the feed's host and this
+    /// feed's request, overriding the pipeline's `FETCH_HOST_DELAY`
+    #[serde(default)]
+    pub delay_secs: Option<f64>,
+    /// Disabled feeds stay listed but aren't fetched
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Which of its articles to keep, besides the `[filters]` every feed has
+    #[serde(default, skip_serializing_if = "Filters::is_empty")]
+    pub filters: Filters,
+}
+
+/// Keyword and regular expression filters on articles' titles, summaries,
+/// and keywords, applied by `digest-pipeline fetch`
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Filters {
+    /// Words or phrases, matched whole and in any case, one of which an
+    /// article must mention when any are given
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<String>,
+    /// Words or phrases articles mentioning any of are dropped
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Regular expressions, like `include`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include_regex: Vec<String>,
+    /// Regular expressions, like `exclude`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_regex: Vec<String>,
+}
+
+impl Filters {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty()
+            && self.exclude.is_empty()
+            && self.include_regex.is_empty()
+            && self.exclude_regex.is_empty()
+    }
+
+    /// What's wrong with them, if anything: a blank keyword or a regular
+    /// expression that doesn't compile
+    fn check(&self) -> Result<(), String> {
+        for (field, keywords) in [("include", &self.include), ("exclude", &self.exclude)] {
+            if keywords.iter().any(|k| k.trim().is_empty()) {
+                return Err(format!("empty keyword in filters.{field}"));
+            }
+        }
+        for (field, patterns) in [
+            ("include_regex", &self.include_regex),
+            ("exclude_regex", &self.exclude_regex),
+        ] {
+            for pattern in patterns {
+                regex::Regex::new(pattern)
+                    .map_err(|e| format!("invalid filters.{field} {pattern:?}: {e}"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SourcesFile {
+    #[serde(default)]
+    source: Vec<Source>,
+    #[serde(default)]
+    filters: Filters,
+}
+
+/// Read and validate the sources file
+pub fn load(path: &str) -> Result<Vec<Source>, String> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| format!("Cannot read sources {path}: {e}"))?;
+    parse(&contents).map_err(|e| format!("{path}: {e}"))
+}
+
+/// Parse sources, naming the first invalid one and what's wrong with it
+pub fn parse(contents: &str) -> Result<Vec<Source>, String> {
+    let file: SourcesFile = toml::from_str(contents).map_err(|e| e.to_string())?;
+    file.filters.check()?;
+    let (mut ids, mut urls) = (HashSet::new(), HashSet::new());
+    for (i, source) in file.source.iter().enumerate() {
+        let error = |message: String| format!("source {} ({}): {message}", i + 1, source.id);
+        // The id is used in file paths
+        if !is_slug(&source.id) {
+            return Err(error(
+                "invalid id: must be lowercase alphanumeric/underscore only".into(),
+            ));
+        }
+        if !ids.insert(source.id.as_str()) {
+            return Err(error("duplicate id".into()));
+        }
+        // imaps:// names a mailbox of newsletters
+        if !["http://", "https://", "imaps://"]
+            .iter()
+            .any(|scheme| source.url.starts_with(scheme))
+        {
+            return Err(error(format!(
+                "invalid URL {:?}: must be http(s) or imaps",
+                source.url
+            )));
+        }
+        if !urls.insert(source.url.as_str()) {
+            return Err(error(format!("duplicate URL {}", source.url)));
+        }
+        if !(source.weight.is_finite() && source.weight > 0.0) {
+            return Err(error(format!(
+                "invalid weight {}: must be above 0",
+                source.weight
+            )));
+        }
+        if let Some(delay) = source.delay_secs.filter(|d| !(d.is_finite() && *d >= 0.0)) {
+            return Err(error(format!(
+                "invalid delay_secs {delay}: must be 0 or more"
+            )));
+        }
+        for (field, value) in [("owner", &source.owner), ("region", &source.region)] {
+            if value.as_ref().is_some_and(|v| v.trim().is_empty()) {
+                return Err(error(format!("empty {field}")));
+            }
+        }
+        if let Some(topic) = source.topics.iter().find(|t| !is_slug(t)) {
+            return Err(error(format!(
+                "invalid topic {topic:?}: must be lowercase alphanumeric/underscore only"
+            )));
+        }
+        source.filters.check().map_err(error)?;
+    }
+    Ok(file.source)
+}
+
+fn is_slug(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'_'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod parse {
+        use super::*;
+
+        fn source(id: &str, url: &str) -> String {
+            format!(
+                "[[source]]\nid = \"{id}\"\nname = \"Feed\"\nurl = \"{url}\"\nbias = \"center\"\nperspective = \"western\"\n"
+            )
+        }
+
+        #[test]
+        fn reads_valid_sources_with_defaults() {
+            let sources = parse(&source("bbc_world", "https://example.com/rss")).unwrap();
+            assert_eq!(sources[0].id, "bbc_world");
+            assert_eq!(sources[0].perspective, "western");
+            assert_eq!(sources[0].weight, 1.0);
+            assert!(sources[0].topics.is_empty());
+            assert_eq!(sources[0].delay_secs, None);
+            assert_eq!(sources[0].owner, None);
+            assert!(sources[0].enabled);
+        }
+
+        #[test]
+        fn reads_optional_fields() {
+            let toml = source("bbc_world", "https://example.com/rss")
+                + "weight = 1.5\ntopics = [\"geopolitics\"]\ndelay_secs = 2\nenabled = false\n"
+                + "owner = \"BBC (public)\"\nregion = \"UK\"\n";
+            let sources = parse(&toml).unwrap();
+            assert_eq!(sources[0].owner.as_deref(), Some("BBC (public)"));
+            assert_eq!(sources[0].region.as_deref(), Some("UK"));
+            assert_eq!(sources[0].weight, 1.5);
+            assert_eq!(sources[0].topics, ["geopolitics"]);
+            assert_eq!(sources[0].delay_secs, Some(2.0));
+            assert!(!sources[0].enabled);
+        }
+
+        #[test]
+        fn rejects_ids_unfit_for_file_names() {
+            for id in ["../etc", "BBC", ""] {
+                assert!(parse(&source(id, "https://example.com/rss")).is_err());
+            }
+        }
+
+        #[test]
+        fn rejects_other_schemes_and_missing_keys() {
+            assert!(parse(&source("bbc", "file:///etc/passwd")).is_err());
+            assert!(parse(&source("letters", "imaps://imap.example.com/Newsletters")).is_ok());
+            assert!(parse("[[source]]\nid = \"bbc\"\nurl = \"https://example.com\"\n").is_err());
+        }
+
+        #[test]
+        fn names_the_source_at_fault() {
+            let toml =
+                source("bbc", "https://example.com/a") + &source("bbc", "https://example.com/b");
+            assert_eq!(parse(&toml).unwrap_err(), "source 2 (bbc): duplicate id");
+            let toml = source("bbc", "https://example.com/a") + "weight = 0\n";
+            assert_eq!(
+                parse(&toml).unwrap_err(),
+                "source 1 (bbc): invalid weight 0: must be above 0"
+            );
+        }
+
+        #[test]
+        fn reads_filters_after_the_feeds_they_apply_to() {
+            let toml = "[filters]\nexclude = [\"celebrity\"]\n\n".to_string()
+                + &source("bbc", "https://example.com/a")
+                + "[source.filters]\ninclude_regex = ['(?i)climate']\n"
+                + &source("npr", "https://example.com/b");
+            let sources = parse(&toml).unwrap();
+            assert_eq!(sources[0].filters.include_regex, ["(?i)climate"]);
+            assert!(sources[1].filters.is_empty());
+        }
+
+        #[test]
+        fn rejects_broken_filters() {
+            let toml = source("bbc", "https://example.com/a")
+                + "[source.filters]\nexclude_regex = ['(']\n";
+            let error = parse(&toml).unwrap_err();
+            assert!(
+                error.starts_with("source 1 (bbc): invalid filters.exclude_regex"),
+                "{error}"
+            );
+            assert!(parse("[filters]\ninclude = [\" \"]\n").is_err());
+        }
+
+        #[test]
+        fn points_at_misspelled_fields() {
+            let toml = source("bbc", "https://example.com/a") + "dealy_secs = 2\n";
+            let error = parse(&toml).unwrap_err();
+            assert!(error.contains("line 7"), "{error}");
+            assert!(error.contains("unknown field `dealy_secs`"), "{error}");
+        }
+    }
+}
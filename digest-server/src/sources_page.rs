@@ -0,0 +1,219 @@
+//! The feeds configured in `SOURCES_FILE` with how each last fetched, at
+//! `/sources` and `/sources.json`. Fetch status needs SQLite storage; with
+//! Postgres the configuration alone is shown.
+
+use crate::{AppState, db, escape_html, render_page, source_activity, sources};
+use axum::{
+    Json,
+    extract::State,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+};
+use rusqlite::Connection;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Debug, Serialize)]
+pub struct SourceStatus {
+    #[serde(flatten)]
+    source: sources::Source,
+    last_fetch: Option<LastFetch>,
+    /// When the feed last had items it didn't have before
+    last_new_item_at: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+struct LastFetch {
+    recorded_at: String,
+    success: bool,
+    error_message: Option<String>,
+}
+
+/// The newest `source_health` row per source
+fn last_fetches(conn: &Connection) -> Result<HashMap<String, LastFetch>, String> {
+    if !db::table_exists(conn, "source_health")? {
+        return Ok(HashMap::new());
+    }
+    let mut stmt = conn
+        .prepare(
+            "SELECT source_id, recorded_at, success, error_message FROM source_health
+             WHERE id IN (SELECT MAX(id) FROM source_health GROUP BY source_id)",
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get(0)?,
+                LastFetch {
+                    recorded_at: row.get(1)?,
+                    success: row.get(2)?,
+                    error_message: row.get(3)?,
+                },
+            ))
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(rows)
+}
+
+/// The configured sources in file order, with fetch status when available
+async fn statuses(state: &Arc<AppState>) -> Result<Vec<SourceStatus>, (StatusCode, String)> {
+    let Some(configured) = state.sources.as_ref().map(|file| (*file.sources()).clone()) else {
+        return Ok(Vec::new());
+    };
+    let (mut fetches, mut activity) = if state.db.is_some() {
+        state
+            .blocking(|state| {
+                let conn = state.db()?;
+                let error = |e| (StatusCode::INTERNAL_SERVER_ERROR, e);
+                Ok((
+                    last_fetches(&conn).map_err(error)?,
+                    source_activity(&conn).map_err(error)?,
+                ))
+            })
+            .await?
+    } else {
+        Default::default()
+    };
+    Ok(configured
+        .into_iter()
+        .map(|source| SourceStatus {
+            last_fetch: fetches.remove(&source.id),
+            last_new_item_at: activity.remove(&source.id).map(|(at, _)| at),
+            source,
+        })
+        .collect())
+}
+
+/// Configured sources as JSON
+pub async fn json(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<SourceStatus>>, (StatusCode, String)> {
+    statuses(&state).await.map(Json)
+}
+
+/// Configured sources page
+pub async fn page(State(state): State<Arc<AppState>>) -> Result<Response, (StatusCode, String)> {
+    let statuses = statuses(&state).await?;
+    let rows: String = statuses
+        .iter()
+        .map(|s| {
+            let source = &s.source;
+            let status = match &s.last_fetch {
+                None => "–".to_string(),
+                // A successful fetch's message notes article pages it skipped
+                Some(f) if f.success => match &f.error_message {
+                    Some(note) => format!(
+                        r#"<span class="warn" title="{}">OK</span> {}"#,
+                        escape_html(note),
+                        f.recorded_at
+                    ),
+                    None => format!(r#"<span class="good">OK</span> {}"#, f.recorded_at),
+                },
+                Some(f) => format!(
+                    r#"<span class="bad" title="{}">failed</span> {}"#,
+                    escape_html(f.error_message.as_deref().unwrap_or_default()),
+                    f.recorded_at
+                ),
+            };
+            let disabled = if source.enabled {
+                ""
+            } else {
+                r#" <span class="warn">disabled</span>"#
+            };
+            format!(
+                r#"<tr>
+          <td><a href="{url}">{name}</a>{disabled}<br><small>{id}</small></td>
+          <td>{bias}, {perspective}{about}</td>
+          <td>{topics}</td>
+          <td>{weight}</td>
+          <td>{delay}</td>
+          <td>{status}</td>
+          <td>{new_item}</td>
+        </tr>"#,
+                url = escape_html(&source.url),
+                name = escape_html(&source.name),
+                id = escape_html(&source.id),
+                bias = escape_html(&source.bias),
+                perspective = escape_html(&source.perspective),
+                about = match [&source.owner, &source.region].map(Option::as_deref) {
+                    [None, None] => String::new(),
+                    about => format!(
+                        "<br><small>{}</small>",
+                        escape_html(&about.into_iter().flatten().collect::<Vec<_>>().join(" · "))
+                    ),
+                },
+                topics = escape_html(&source.topics.join(", ")),
+                weight = source.weight,
+                delay = source
+                    .delay_secs
+                    .map_or("–".into(), |delay| format!("{delay}s")),
+                new_item = s.last_new_item_at.as_deref().unwrap_or("–"),
+            )
+        })
+        .collect();
+    let summary = match state.sources.as_ref().map(|file| file.sources()) {
+        Some(sources) => format!(
+            "{} sources, {} enabled.",
+            sources.len(),
+            sources.iter().filter(|s| s.enabled).count()
+        ),
+        None => "No sources file configured (set SOURCES_FILE).".into(),
+    };
+    let content = format!(
+        r#"<style>
+      table {{ width: 100%; font-size: 14px; }}
+      td {{ vertical-align: top; }}
+    </style>
+    <p>{summary} <a href="/sources.json">JSON</a> · <a href="/stats">Stats</a></p>
+    <table>
+      <thead>
+        <tr><th>Source</th><th>Bias</th><th>Topics</th><th>Weight</th><th>Delay</th><th>Last fetch (UTC)</th><th>Last new item</th></tr>
+      </thead>
+      <tbody>{rows}</tbody>
+    </table>"#
+    );
+    Ok(Html(render_page(&state, "Sources", &content)).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod last_fetches {
+        use super::*;
+
+        #[test]
+        fn keeps_the_newest_fetch_per_source() {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(
+                "CREATE TABLE source_health (
+                     id INTEGER PRIMARY KEY AUTOINCREMENT, source_id TEXT, success INTEGER,
+                     error_message TEXT, recorded_at DATETIME);
+                 INSERT INTO source_health (source_id, success, error_message, recorded_at) VALUES
+                     ('bbc', 0, 'HTTP 503', '2026-01-01 06:00:00'),
+                     ('bbc', 1, NULL, '2026-01-02 06:00:00'),
+                     ('npr', 0, 'Timed out', '2026-01-02 06:00:00');",
+            )
+            .unwrap();
+            let fetches = last_fetches(&conn).unwrap();
+            assert_eq!(
+                fetches["bbc"],
+                LastFetch {
+                    recorded_at: "2026-01-02 06:00:00".into(),
+                    success: true,
+                    error_message: None,
+                }
+            );
+            assert_eq!(fetches["npr"].error_message.as_deref(), Some("Timed out"));
+        }
+
+        #[test]
+        fn is_empty_before_the_first_fetch() {
+            let conn = Connection::open_in_memory().unwrap();
+            assert!(last_fetches(&conn).unwrap().is_empty());
+        }
+    }
+}
@@ -0,0 +1,528 @@
+//! `/stats.json` schema.
+//!
+//! Responses are wrapped as `{"version": 2, "data": {...}}`. Field names are a
+//! contract with dashboards: new fields may appear, but renaming or removing
+//! one bumps `VERSION`. `?version=1` serves the unwrapped v1 body until it is
+//! retired.
+
+use crate::{StatsData, WEEKDAYS};
+use serde::Serialize;
+
+/// Current schema version
+pub const VERSION: u32 = 2;
+
+#[derive(Serialize)]
+pub struct Envelope<'a> {
+    pub version: u32,
+    pub data: Stats<'a>,
+}
+
+/// Everything on the stats page for one date range
+#[derive(Serialize)]
+pub struct Stats<'a> {
+    /// Days covered, counting both ends
+    pub period_days: i64,
+    /// First and last day of the range (YYYY-MM-DD, UTC)
+    pub from: &'a str,
+    pub to: &'a str,
+    pub source_health: Vec<SourceHealth<'a>>,
+    /// Narratives shown per source and tier
+    pub source_usage: Vec<SourceUsage<'a>>,
+    pub topic_coverage: TopicCoverage<'a>,
+    /// Latest pipeline runs, newest first
+    pub recent_runs: Vec<Run<'a>>,
+    /// Latest pipeline runs stage by stage, newest first, including runs that
+    /// failed or were killed partway
+    pub run_timeline: Vec<RunStages<'a>>,
+    /// Estimated Claude cost of every run in the range
+    pub total_cost_usd: f64,
+    /// Runs per weekday (Monday first) and UTC hour
+    pub publish_times: Vec<PublishTimes<'a>>,
+    /// On-time delivery SLO; null unless `DELIVERY_DEADLINE` is set
+    pub delivery: Option<Delivery>,
+    /// Server self-checks; null before any were recorded
+    pub uptime: Option<Uptime>,
+    /// Current list size (not limited to the range)
+    pub subscribers: Subscribers,
+    pub subscriber_growth: SubscriberGrowth<'a>,
+    pub unsubscribe_reasons: Vec<UnsubscribeReason<'a>>,
+    /// Opens and clicks per sent digest
+    pub engagement: Vec<Engagement<'a>>,
+    pub top_viewed: Vec<DigestViews<'a>>,
+    pub top_links: Vec<LinkClicks<'a>>,
+    /// Clicks per publisher domain
+    pub top_sources: Vec<DomainClicks<'a>>,
+    /// The preceding period of the same length; only with `?compare=previous`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub previous: Option<Previous<'a>>,
+}
+
+#[derive(Serialize)]
+pub struct SourceHealth<'a> {
+    pub source_id: &'a str,
+    pub total_fetches: i64,
+    pub successes: i64,
+    pub success_rate_pct: f64,
+    /// Fetch time percentiles; null before fetch times were recorded
+    pub p50_ms: Option<i64>,
+    pub p95_ms: Option<i64>,
+    pub last_new_item_at: Option<&'a str>,
+    /// Fetching fine but nothing new for `STALE_SOURCE_DAYS`
+    pub dormant: bool,
+    pub new_articles_per_fetch: Option<f64>,
+    /// Standard deviations from the source's usual volume
+    pub volume_z: Option<f64>,
+    pub volume_anomaly: bool,
+    /// Articles its keyword filters dropped in the range
+    pub filtered_articles: i64,
+    /// Change in success rate against the previous period; only when comparing,
+    /// and absent for sources that weren't fetched then
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success_rate_delta_pp: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct SourceUsage<'a> {
+    pub source_id: &'a str,
+    /// must_know, should_know, signal, quick_signal, or below_fold
+    pub tier: &'a str,
+    pub count: i64,
+}
+
+#[derive(Serialize)]
+pub struct TopicCoverage<'a> {
+    /// Mondays of the weeks counted, oldest first
+    pub weeks: &'a [String],
+    pub topics: Vec<TopicWeeks<'a>>,
+}
+
+#[derive(Serialize)]
+pub struct TopicWeeks<'a> {
+    pub topic: &'a str,
+    /// Narratives per week, aligned to `weeks`
+    pub weekly: &'a [i64],
+}
+
+#[derive(Serialize)]
+pub struct Run<'a> {
+    pub run_at: &'a str,
+    pub articles_fetched: i64,
+    pub articles_emailed: i64,
+    /// Null for older or send-only runs
+    pub duration_ms: Option<i64>,
+    pub input_tokens: Option<i64>,
+    pub output_tokens: Option<i64>,
+    pub cost_usd: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct RunStages<'a> {
+    pub started_at: &'a str,
+    /// "recorded", "dry run", "failed", or "unfinished" (killed, or still going)
+    pub outcome: &'a str,
+    pub duration_ms: i64,
+    pub stages: Vec<Stage<'a>>,
+}
+
+#[derive(Serialize)]
+pub struct Stage<'a> {
+    pub stage: &'a str,
+    pub started_at: &'a str,
+    pub duration_ms: i64,
+    /// Articles fetched, stories selected or rendered, recipients emailed, or
+    /// headlines recorded
+    pub items: Option<i64>,
+    pub error: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+pub struct PublishTimes<'a> {
+    pub weekday: &'a str,
+    pub runs_by_hour_utc: [i64; 24],
+}
+
+#[derive(Serialize)]
+pub struct Delivery {
+    pub target_pct: f64,
+    pub attained_pct: f64,
+    pub days: i64,
+    pub on_time: i64,
+    pub late: i64,
+    pub missed: i64,
+    /// Negative once the budget is blown
+    pub error_budget_remaining_pct: f64,
+}
+
+#[derive(Serialize)]
+pub struct Uptime {
+    pub checks: i64,
+    pub pct: f64,
+}
+
+#[derive(Serialize)]
+pub struct Subscribers {
+    pub total: i64,
+    pub confirmed: i64,
+    pub unsubscribed: i64,
+    pub bounced: i64,
+}
+
+#[derive(Serialize)]
+pub struct SubscriberGrowth<'a> {
+    /// Subscribed at the end of the range
+    pub total: i64,
+    pub signups: i64,
+    pub unsubscribes: i64,
+    pub daily: Vec<GrowthDay<'a>>,
+}
+
+#[derive(Serialize)]
+pub struct GrowthDay<'a> {
+    pub date: &'a str,
+    pub total: i64,
+    pub signups: i64,
+    pub unsubscribes: i64,
+}
+
+#[derive(Serialize)]
+pub struct UnsubscribeReason<'a> {
+    pub reason: &'a str,
+    pub count: i64,
+}
+
+#[derive(Serialize)]
+pub struct Engagement<'a> {
+    pub digest_date: &'a str,
+    pub recipients: i64,
+    pub unique_opens: i64,
+    pub unique_clicks: i64,
+    pub open_rate_pct: f64,
+    pub click_rate_pct: f64,
+}
+
+#[derive(Serialize)]
+pub struct DigestViews<'a> {
+    pub digest_date: &'a str,
+    pub views: i64,
+    pub readers: i64,
+}
+
+#[derive(Serialize)]
+pub struct LinkClicks<'a> {
+    pub url: &'a str,
+    /// Story the link appeared in, when it could be attributed
+    pub narrative: Option<&'a str>,
+    pub clicks: i64,
+}
+
+#[derive(Serialize)]
+pub struct DomainClicks<'a> {
+    pub domain: &'a str,
+    pub clicks: i64,
+}
+
+/// Headline numbers of the comparison period
+#[derive(Serialize)]
+pub struct Previous<'a> {
+    pub from: &'a str,
+    pub to: &'a str,
+    pub source_health: Vec<PreviousSourceHealth<'a>>,
+    pub source_usage: Vec<SourceUsage<'a>>,
+    pub total_cost_usd: f64,
+    pub delivery_attained_pct: Option<f64>,
+    pub uptime_pct: Option<f64>,
+    pub subscriber_signups: i64,
+}
+
+#[derive(Serialize)]
+pub struct PreviousSourceHealth<'a> {
+    pub source_id: &'a str,
+    pub total_fetches: i64,
+    pub success_rate_pct: f64,
+}
+
+fn source_usage(data: &StatsData) -> Vec<SourceUsage<'_>> {
+    data.source_usage
+        .iter()
+        .map(|u| SourceUsage {
+            source_id: &u.source_id,
+            tier: &u.tier,
+            count: u.count,
+        })
+        .collect()
+}
+
+impl<'a> Stats<'a> {
+    pub fn new(data: &'a StatsData, previous: Option<&'a StatsData>, stale_days: u32) -> Self {
+        let previous_rate = |source_id: &str| {
+            previous?
+                .source_health
+                .iter()
+                .find(|h| h.source_id == source_id)
+                .map(|h| h.success_rate_pct)
+        };
+        let growth = &data.subscriber_growth;
+        Self {
+            period_days: data.range.days,
+            from: &data.range.from,
+            to: &data.range.to,
+            source_health: data
+                .source_health
+                .iter()
+                .map(|h| SourceHealth {
+                    source_id: &h.source_id,
+                    total_fetches: h.total_fetches,
+                    successes: h.successes,
+                    success_rate_pct: h.success_rate_pct,
+                    p50_ms: h.p50_ms,
+                    p95_ms: h.p95_ms,
+                    last_new_item_at: h.last_new_item_at.as_deref(),
+                    dormant: h.is_dormant(stale_days),
+                    new_articles_per_fetch: h.volume.as_ref().map(|v| v.mean),
+                    volume_z: h.volume.as_ref().map(|v| v.z_score()),
+                    volume_anomaly: h.volume.as_ref().is_some_and(|v| v.is_anomaly()),
+                    filtered_articles: h.filtered_articles,
+                    success_rate_delta_pp: previous_rate(&h.source_id)
+                        .map(|rate| h.success_rate_pct - rate),
+                })
+                .collect(),
+            source_usage: source_usage(data),
+            topic_coverage: TopicCoverage {
+                weeks: &data.topic_coverage.weeks,
+                topics: data
+                    .topic_coverage
+                    .topics
+                    .iter()
+                    .map(|t| TopicWeeks {
+                        topic: &t.topic,
+                        weekly: &t.weekly,
+                    })
+                    .collect(),
+            },
+            recent_runs: data
+                .recent_runs
+                .iter()
+                .map(|r| Run {
+                    run_at: &r.run_at,
+                    articles_fetched: r.articles_fetched,
+                    articles_emailed: r.articles_emailed,
+                    duration_ms: r.duration_ms,
+                    input_tokens: r.input_tokens,
+                    output_tokens: r.output_tokens,
+                    cost_usd: r.cost_usd,
+                })
+                .collect(),
+            run_timeline: data
+                .run_timeline
+                .iter()
+                .map(|r| RunStages {
+                    started_at: &r.started_at,
+                    outcome: r.outcome(),
+                    duration_ms: r.duration_ms(),
+                    stages: r
+                        .stages
+                        .iter()
+                        .map(|s| Stage {
+                            stage: &s.stage,
+                            started_at: &s.started_at,
+                            duration_ms: s.duration_ms,
+                            items: s.items,
+                            error: s.error.as_deref(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+            total_cost_usd: data.total_cost_usd,
+            publish_times: WEEKDAYS
+                .iter()
+                .zip(data.publish_times)
+                .map(|(weekday, runs_by_hour_utc)| PublishTimes {
+                    weekday,
+                    runs_by_hour_utc,
+                })
+                .collect(),
+            delivery: data.delivery.as_ref().map(|b| Delivery {
+                target_pct: b.target_pct,
+                attained_pct: b.attained_pct(),
+                days: b.days,
+                on_time: b.on_time,
+                late: b.late,
+                missed: b.missed,
+                error_budget_remaining_pct: b.remaining_pct,
+            }),
+            uptime: data.uptime.map(|(checks, pct)| Uptime { checks, pct }),
+            subscribers: Subscribers {
+                total: data.subscriber_counts.total,
+                confirmed: data.subscriber_counts.confirmed,
+                unsubscribed: data.subscriber_counts.unsubscribed,
+                bounced: data.subscriber_counts.bounced,
+            },
+            subscriber_growth: SubscriberGrowth {
+                total: growth.last().map(|g| g.total).unwrap_or(0),
+                signups: growth.iter().map(|g| g.signups).sum(),
+                unsubscribes: growth.iter().map(|g| g.unsubscribes).sum(),
+                daily: growth
+                    .iter()
+                    .map(|g| GrowthDay {
+                        date: &g.date,
+                        total: g.total,
+                        signups: g.signups,
+                        unsubscribes: g.unsubscribes,
+                    })
+                    .collect(),
+            },
+            unsubscribe_reasons: data
+                .unsubscribe_reasons
+                .iter()
+                .map(|(reason, count)| UnsubscribeReason {
+                    reason,
+                    count: *count,
+                })
+                .collect(),
+            engagement: data
+                .engagement
+                .iter()
+                .map(|e| Engagement {
+                    digest_date: &e.digest_date,
+                    recipients: e.recipients,
+                    unique_opens: e.unique_opens,
+                    unique_clicks: e.unique_clicks,
+                    open_rate_pct: e.open_rate_pct,
+                    click_rate_pct: e.click_rate_pct,
+                })
+                .collect(),
+            top_viewed: data
+                .top_viewed
+                .iter()
+                .map(|v| DigestViews {
+                    digest_date: &v.digest_date,
+                    views: v.views,
+                    readers: v.readers,
+                })
+                .collect(),
+            top_links: data
+                .top_links
+                .iter()
+                .map(|l| LinkClicks {
+                    url: &l.url,
+                    narrative: l.narrative.as_deref(),
+                    clicks: l.clicks,
+                })
+                .collect(),
+            top_sources: data
+                .top_domains
+                .iter()
+                .map(|(domain, clicks)| DomainClicks {
+                    domain,
+                    clicks: *clicks,
+                })
+                .collect(),
+            previous: previous.map(|p| Previous {
+                from: &p.range.from,
+                to: &p.range.to,
+                source_health: p
+                    .source_health
+                    .iter()
+                    .map(|h| PreviousSourceHealth {
+                        source_id: &h.source_id,
+                        total_fetches: h.total_fetches,
+                        success_rate_pct: h.success_rate_pct,
+                    })
+                    .collect(),
+                source_usage: source_usage(p),
+                total_cost_usd: p.total_cost_usd,
+                delivery_attained_pct: p.delivery.as_ref().map(|b| b.attained_pct()),
+                uptime_pct: p.uptime.map(|(_, pct)| pct),
+                subscriber_signups: p.subscriber_growth.iter().map(|g| g.signups).sum(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::DateRange;
+
+    fn empty() -> StatsData {
+        let range = DateRange {
+            from: "2026-01-01".into(),
+            to: "2026-01-07".into(),
+            days: 7,
+        };
+        StatsData {
+            previous_range: range.clone(),
+            range,
+            source_health: vec![],
+            source_usage: vec![],
+            topic_coverage: Default::default(),
+            recent_runs: vec![],
+            run_timeline: vec![],
+            run_articles: vec![],
+            publish_times: [[0; 24]; 7],
+            total_cost_usd: 0.0,
+            subscriber_growth: vec![],
+            unsubscribe_reasons: vec![],
+            engagement: vec![],
+            top_viewed: vec![],
+            uptime: None,
+            subscriber_counts: Default::default(),
+            top_links: vec![],
+            top_domains: vec![],
+            delivery: None,
+        }
+    }
+
+    mod envelope {
+        use super::*;
+
+        #[test]
+        fn field_names_are_stable() {
+            let data = empty();
+            let json = serde_json::to_value(Envelope {
+                version: VERSION,
+                data: Stats::new(&data, None, 7),
+            })
+            .unwrap();
+            assert_eq!(json["version"], 2);
+            let mut fields: Vec<&str> = json["data"]
+                .as_object()
+                .unwrap()
+                .keys()
+                .map(String::as_str)
+                .collect();
+            fields.sort_unstable();
+            assert_eq!(
+                fields,
+                [
+                    "delivery",
+                    "engagement",
+                    "from",
+                    "period_days",
+                    "publish_times",
+                    "recent_runs",
+                    "run_timeline",
+                    "source_health",
+                    "source_usage",
+                    "subscriber_growth",
+                    "subscribers",
+                    "to",
+                    "top_links",
+                    "top_sources",
+                    "top_viewed",
+                    "topic_coverage",
+                    "total_cost_usd",
+                    "unsubscribe_reasons",
+                    "uptime",
+                ]
+            );
+        }
+
+        #[test]
+        fn previous_only_when_comparing() {
+            let (data, previous) = (empty(), empty());
+            let json = serde_json::to_value(Stats::new(&data, Some(&previous), 7)).unwrap();
+            assert_eq!(json["previous"]["from"], "2026-01-01");
+        }
+    }
+}
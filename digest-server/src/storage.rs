@@ -0,0 +1,499 @@
+//! Storage backends for digests, stats, and subscribers.
+//!
+//! SQLite (the pipeline's own database) is the default. Built with the
+//! `postgres` feature, a `postgres://` `DATABASE_URL` selects Postgres instead.
+//! Telemetry that only the SQLite schema has (pageviews, clicks, email events,
+//! self-checks, rollups) stays SQLite-only and is switched off under Postgres.
+
+use crate::blobs::{self, BlobStore};
+use crate::preview::Preview;
+use crate::revisions::Revision;
+use crate::runs::Checkpoint;
+use crate::{StatsData, StatsQuery, db, fetch_stats_data, metrics, slo, subscribers};
+use axum::http::StatusCode;
+use rusqlite::OptionalExtension;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Reads and writes shared by every backend. Calls block, so handlers run
+/// them through `AppState::blocking`.
+pub trait Storage: Send + Sync {
+    /// Backend name for logs
+    fn name(&self) -> &'static str;
+
+    /// Verify the database can be reached and queried
+    fn probe(&self) -> Result<(), String>;
+
+    /// Most recent digest dates, newest first
+    fn digest_dates(&self, limit: u32) -> Result<Vec<String>, String>;
+
+    /// A digest's (date, html) by date, or the latest when `date` is `None`
+    fn digest(&self, date: Option<&str>) -> Result<Option<(String, String)>, String>;
+
+    /// Marker that changes whenever the digest for `date` is rewritten, so
+    /// rendered pages can be cached until then, and its `updated_at` if the
+    /// database records one; `None` if there is no digest
+    fn digest_version(&self, date: &str) -> Result<Option<(String, Option<String>)>, String>;
+
+    /// Superseded versions of the digest for `date`, oldest first
+    fn digest_revisions(&self, date: &str) -> Result<Vec<Revision>, String>;
+
+    /// When the digest for `date` was last replaced or edited, if ever
+    fn digest_updated_at(&self, date: &str) -> Result<Option<String>, String>;
+
+    /// An image's (content type, bytes) by hash, if the pipeline stored one
+    fn image(&self, hash: &str) -> Result<Option<(String, Vec<u8>)>, String>;
+
+    /// The digest in the preview slot, if a dry run has left one
+    fn digest_preview(&self) -> Result<Option<Preview>, String>;
+
+    /// The stages an interrupted pipeline run completed, oldest first
+    fn run_checkpoints(&self) -> Result<Vec<Checkpoint>, String>;
+
+    /// Number of stored digests
+    fn digest_count(&self) -> Result<i64, String>;
+
+    /// An audience's local subscribers as email -> subscribed
+    fn subscribers(&self, audience: &str) -> Result<BTreeMap<String, bool>, String>;
+
+    /// Record a subscribe or unsubscribe, logging a subscription event on change
+    fn set_subscribed(&self, email: &str, audience: &str, subscribed: bool) -> Result<(), String>;
+
+    /// Record many subscribes and unsubscribes, as (email, subscribed), in one
+    /// transaction: if one fails, none is kept
+    fn set_subscriptions(&self, audience: &str, changes: &[(String, bool)]) -> Result<(), String>;
+
+    /// Store an anonymous unsubscribe survey response
+    fn record_feedback(&self, reason: &str, comment: Option<&str>) -> Result<(), String>;
+
+    /// Stats for the requested window
+    fn stats(
+        &self,
+        query: &StatsQuery,
+        slo: Option<&slo::SloConfig>,
+    ) -> Result<StatsData, (StatusCode, String)>;
+}
+
+/// Whether `DATABASE_URL` points at Postgres rather than a SQLite file
+pub fn is_postgres_url(url: &str) -> bool {
+    url.starts_with("postgres://") || url.starts_with("postgresql://")
+}
+
+/// A digest's date, `html`, `html_zstd`, and `html_blob`
+type DigestRow = (String, String, Option<Vec<u8>>, Option<String>);
+
+/// The pipeline's SQLite database: pooled read-only connections for reads,
+/// a fresh read-write connection for each write, and the blob store digest
+/// bodies may have been moved to
+pub struct Sqlite {
+    path: String,
+    pool: db::Pool,
+    blobs: Option<Arc<dyn BlobStore>>,
+}
+
+impl Sqlite {
+    pub fn new(path: &str, pool: db::Pool, blobs: Option<Arc<dyn BlobStore>>) -> Self {
+        Self {
+            path: path.to_string(),
+            pool,
+            blobs,
+        }
+    }
+
+    fn conn(&self) -> Result<db::PooledConnection, String> {
+        self.pool.get().map_err(|e| format!("DB error: {e}"))
+    }
+}
+
+impl Storage for Sqlite {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn probe(&self) -> Result<(), String> {
+        crate::health::probe(&self.path)
+    }
+
+    fn digest_dates(&self, limit: u32) -> Result<Vec<String>, String> {
+        let conn = self.conn()?;
+        let mut stmt = conn
+            .prepare("SELECT date FROM digests ORDER BY date DESC LIMIT ?1")
+            .map_err(|e| format!("Query error: {e}"))?;
+        let dates = stmt
+            .query_map([limit], |row| row.get(0))
+            .map_err(|e| format!("Query error: {e}"))?
+            .filter_map(|r| r.ok())
+            .collect();
+        Ok(dates)
+    }
+
+    fn digest(&self, date: Option<&str>) -> Result<Option<(String, String)>, String> {
+        let conn = self.conn()?;
+        // Read-only databases may predate the compressed and blob columns
+        let column = |name| -> Result<&str, String> {
+            Ok(if db::column_exists(&conn, "digests", name)? {
+                name
+            } else {
+                "NULL"
+            })
+        };
+        let (compressed, blob) = (column("html_zstd")?, column("html_blob")?);
+        let row: Option<DigestRow> = conn
+            .query_row(
+                &format!(
+                    "SELECT date, html, {compressed}, {blob} FROM digests
+                     WHERE ?1 IS NULL OR date = ?1 ORDER BY date DESC LIMIT 1"
+                ),
+                [date],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()
+            .map_err(|e| format!("Query error: {e}"))?;
+        row.map(|(date, html, zstd, blob)| {
+            Ok((date, blobs::html(self.blobs.as_deref(), html, zstd, blob)?))
+        })
+        .transpose()
+    }
+
+    fn digest_version(&self, date: &str) -> Result<Option<(String, Option<String>)>, String> {
+        let conn = self.conn()?;
+        // The pipeline's INSERT OR REPLACE gives the row a new rowid; edits in
+        // place add a revision
+        let revision = if db::table_exists(&conn, "digest_revisions")? {
+            "(SELECT MAX(id) FROM digest_revisions WHERE digest_date = ?1)"
+        } else {
+            "NULL"
+        };
+        let updated_at = if db::column_exists(&conn, "digests", "updated_at")? {
+            "updated_at"
+        } else {
+            "NULL"
+        };
+        conn.query_row(
+            &format!(
+                "SELECT rowid || '/' || COALESCE({revision}, 0), {updated_at} FROM digests
+                 WHERE date = ?1"
+            ),
+            [date],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| format!("Query error: {e}"))
+    }
+
+    fn digest_revisions(&self, date: &str) -> Result<Vec<Revision>, String> {
+        let conn = self.conn()?;
+        if !db::table_exists(&conn, "digest_revisions")? {
+            return Ok(Vec::new());
+        }
+        let blob = if db::column_exists(&conn, "digest_revisions", "html_blob")? {
+            "html_blob"
+        } else {
+            "NULL"
+        };
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT created_at, replaced_at, html, html_zstd, {blob} FROM digest_revisions
+                 WHERE digest_date = ?1 ORDER BY id"
+            ))
+            .map_err(|e| format!("Query error: {e}"))?;
+        let rows = stmt
+            .query_map([date], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })
+            .map_err(|e| format!("Query error: {e}"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Query error: {e}"))?;
+        rows.into_iter()
+            .map(|(created_at, replaced_at, html, zstd, blob)| {
+                Ok(Revision {
+                    created_at,
+                    replaced_at,
+                    html: blobs::html(self.blobs.as_deref(), html, zstd, blob)?,
+                })
+            })
+            .collect()
+    }
+
+    fn digest_updated_at(&self, date: &str) -> Result<Option<String>, String> {
+        let conn = self.conn()?;
+        if !db::table_exists(&conn, "digest_revisions")? {
+            return Ok(None);
+        }
+        conn.query_row(
+            "SELECT MAX(replaced_at) FROM digest_revisions WHERE digest_date = ?1",
+            [date],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Query error: {e}"))
+    }
+
+    fn image(&self, hash: &str) -> Result<Option<(String, Vec<u8>)>, String> {
+        let conn = self.conn()?;
+        if !db::table_exists(&conn, "images")? {
+            return Ok(None);
+        }
+        conn.query_row(
+            "SELECT content_type, data FROM images WHERE hash = ?1",
+            [hash],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| format!("Query error: {e}"))
+    }
+
+    fn digest_preview(&self) -> Result<Option<Preview>, String> {
+        let conn = self.conn()?;
+        if !db::table_exists(&conn, "digest_preview")? {
+            return Ok(None);
+        }
+        conn.query_row(
+            "SELECT date, html, created_at FROM digest_preview WHERE slot = 1",
+            [],
+            |row| {
+                Ok(Preview {
+                    date: row.get(0)?,
+                    html: row.get(1)?,
+                    created_at: row.get(2)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(|e| format!("Query error: {e}"))
+    }
+
+    fn run_checkpoints(&self) -> Result<Vec<Checkpoint>, String> {
+        let conn = self.conn()?;
+        if !db::table_exists(&conn, "run_checkpoints")? {
+            return Ok(Vec::new());
+        }
+        let mut stmt = conn
+            .prepare(
+                "SELECT stage, dry_run, state, completed_at FROM run_checkpoints
+                 ORDER BY completed_at, rowid",
+            )
+            .map_err(|e| format!("Query error: {e}"))?;
+        stmt.query_map([], |row| {
+            Ok(Checkpoint {
+                stage: row.get(0)?,
+                dry_run: row.get(1)?,
+                state: serde_json::from_str(&row.get::<_, String>(2)?).unwrap_or_default(),
+                completed_at: row.get(3)?,
+            })
+        })
+        .and_then(|rows| rows.collect())
+        .map_err(|e| format!("Query error: {e}"))
+    }
+
+    fn digest_count(&self) -> Result<i64, String> {
+        metrics::count_digests(&*self.conn()?)
+    }
+
+    fn subscribers(&self, audience: &str) -> Result<BTreeMap<String, bool>, String> {
+        let conn = self.conn()?;
+        if !db::table_exists(&conn, "subscribers")? {
+            return Ok(BTreeMap::new());
+        }
+        subscribers::load(&conn, audience)
+    }
+
+    fn set_subscribed(&self, email: &str, audience: &str, subscribed: bool) -> Result<(), String> {
+        let conn = db::open_rw(&self.path)?;
+        subscribers::upsert(&conn, email, audience, subscribed)
+    }
+
+    fn set_subscriptions(&self, audience: &str, changes: &[(String, bool)]) -> Result<(), String> {
+        let mut conn = db::open_rw(&self.path)?;
+        let tx = conn.transaction().map_err(|e| format!("DB error: {e}"))?;
+        changes.iter().try_for_each(|(email, subscribed)| {
+            subscribers::upsert(&tx, email, audience, *subscribed)
+        })?;
+        tx.commit().map_err(|e| format!("DB error: {e}"))
+    }
+
+    fn record_feedback(&self, reason: &str, comment: Option<&str>) -> Result<(), String> {
+        let conn = db::open_rw(&self.path)?;
+        subscribers::record_feedback(&conn, reason, comment)
+    }
+
+    fn stats(
+        &self,
+        query: &StatsQuery,
+        slo: Option<&slo::SloConfig>,
+    ) -> Result<StatsData, (StatusCode, String)> {
+        let conn = self
+            .conn()
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        fetch_stats_data(&conn, query, slo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod sqlite {
+        use super::*;
+        use crate::migrations;
+
+        fn storage(dir: &std::path::Path) -> Sqlite {
+            let path = dir.join("digest.db");
+            let mut conn = rusqlite::Connection::open(&path).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE digests (date TEXT PRIMARY KEY, html TEXT);
+                 INSERT INTO digests VALUES ('2026-01-01', '<p>one</p>'), ('2026-01-02', '<p>two</p>');",
+            )
+            .unwrap();
+            migrations::run(&mut conn).unwrap();
+            let path = path.to_str().unwrap();
+            Sqlite::new(path, db::read_only_pool(path, 2).unwrap(), None)
+        }
+
+        #[test]
+        fn reads_digests() {
+            let dir = std::env::temp_dir().join(format!("storage-digests-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let storage = storage(&dir);
+            assert_eq!(storage.digest_dates(1).unwrap(), ["2026-01-02"]);
+            assert_eq!(storage.digest_count().unwrap(), 2);
+            assert_eq!(storage.digest(None).unwrap().unwrap().0, "2026-01-02");
+            assert_eq!(
+                storage.digest(Some("2026-01-01")).unwrap(),
+                Some(("2026-01-01".into(), "<p>one</p>".into()))
+            );
+            assert_eq!(storage.digest(Some("2025-01-01")).unwrap(), None);
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+
+        #[test]
+        fn reads_images() {
+            let dir = std::env::temp_dir().join(format!("storage-images-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let storage = storage(&dir);
+            let hash = "ab12".repeat(16);
+            assert_eq!(storage.image(&hash).unwrap(), None);
+            let conn = rusqlite::Connection::open(dir.join("digest.db")).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE images (hash TEXT PRIMARY KEY, content_type TEXT, data BLOB)",
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO images VALUES (?1, 'image/png', x'89504e47')",
+                [&hash],
+            )
+            .unwrap();
+            assert_eq!(
+                storage.image(&hash).unwrap(),
+                Some(("image/png".into(), b"\x89PNG".to_vec()))
+            );
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+
+        #[test]
+        fn reads_the_preview() {
+            let dir = std::env::temp_dir().join(format!("storage-preview-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let storage = storage(&dir);
+            assert!(storage.digest_preview().unwrap().is_none());
+            rusqlite::Connection::open(dir.join("digest.db"))
+                .unwrap()
+                .execute_batch(
+                    "CREATE TABLE digest_preview (slot INTEGER PRIMARY KEY, date TEXT, html TEXT,
+                         created_at DATETIME);
+                     INSERT INTO digest_preview VALUES (1, '2026-01-05', '<p>draft</p>', NULL);",
+                )
+                .unwrap();
+            let preview = storage.digest_preview().unwrap().unwrap();
+            assert_eq!(
+                (preview.date.as_str(), preview.html.as_str()),
+                ("2026-01-05", "<p>draft</p>")
+            );
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+
+        #[test]
+        fn reads_run_checkpoints_in_order() {
+            let dir =
+                std::env::temp_dir().join(format!("storage-checkpoints-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let storage = storage(&dir);
+            assert!(storage.run_checkpoints().unwrap().is_empty());
+            rusqlite::Connection::open(dir.join("digest.db"))
+                .unwrap()
+                .execute_batch(
+                    r#"CREATE TABLE run_checkpoints (stage TEXT PRIMARY KEY, dry_run INTEGER,
+                           state TEXT, completed_at DATETIME);
+                       INSERT INTO run_checkpoints VALUES
+                           ('select', 0, '{"usage": {}}', '2026-01-05 07:04:00'),
+                           ('fetch', 0, '{"articles_fetched": 42}', '2026-01-05 07:01:00');"#,
+                )
+                .unwrap();
+            let checkpoints = storage.run_checkpoints().unwrap();
+            let stages: Vec<&str> = checkpoints.iter().map(|c| c.stage.as_str()).collect();
+            assert_eq!(stages, ["fetch", "select"]);
+            assert_eq!(checkpoints[0].state["articles_fetched"], 42);
+            assert!(!checkpoints[0].dry_run);
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+
+        #[test]
+        fn writes_subscribers() {
+            let dir = std::env::temp_dir().join(format!("storage-subs-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let storage = storage(&dir);
+            assert!(storage.subscribers("default").unwrap().is_empty());
+            storage
+                .set_subscribed("Reader@Example.com", "default", true)
+                .unwrap();
+            storage
+                .set_subscribed("other@example.com", "default", false)
+                .unwrap();
+            let subs = storage.subscribers("default").unwrap();
+            assert_eq!(subs.get("reader@example.com"), Some(&true));
+            assert_eq!(subs.get("other@example.com"), Some(&false));
+            storage.record_feedback("other", Some("bye")).unwrap();
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+
+        #[test]
+        fn writes_subscriptions_all_or_none() {
+            let dir =
+                std::env::temp_dir().join(format!("storage-subscriptions-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let storage = storage(&dir);
+            storage
+                .set_subscribed("reader@example.com", "default", true)
+                .unwrap();
+            rusqlite::Connection::open(dir.join("digest.db"))
+                .unwrap()
+                .execute_batch(
+                    "CREATE TRIGGER refuse_bad BEFORE INSERT ON subscribers
+                     WHEN NEW.email = 'bad@example.com'
+                     BEGIN SELECT RAISE(ABORT, 'refused'); END;",
+                )
+                .unwrap();
+            let changes = |emails: &[&str]| -> Vec<(String, bool)> {
+                emails.iter().map(|e| (e.to_string(), false)).collect()
+            };
+
+            let failed = changes(&["reader@example.com", "bad@example.com"]);
+            assert!(storage.set_subscriptions("default", &failed).is_err());
+            let subs = storage.subscribers("default").unwrap();
+            assert_eq!(subs.len(), 1);
+            assert_eq!(subs.get("reader@example.com"), Some(&true));
+
+            let changed = changes(&["reader@example.com", "new@example.com"]);
+            storage.set_subscriptions("default", &changed).unwrap();
+            let subs = storage.subscribers("default").unwrap();
+            assert_eq!(subs.get("reader@example.com"), Some(&false));
+            assert_eq!(subs.get("new@example.com"), Some(&false));
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
@@ -0,0 +1,543 @@
+//! Local subscriber list, kept in sync with the Resend audience
+
+use crate::db;
+use crate::resend::{Audience, Contact};
+use crate::storage::Storage;
+use rusqlite::{Connection, OptionalExtension};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+pub const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS subscribers (
+    email TEXT NOT NULL,
+    audience TEXT NOT NULL DEFAULT 'default',
+    status TEXT NOT NULL DEFAULT 'subscribed',
+    created_at DATETIME DEFAULT (datetime('now')),
+    updated_at DATETIME DEFAULT (datetime('now')),
+    PRIMARY KEY (email, audience)
+);
+
+CREATE TABLE IF NOT EXISTS subscription_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    email TEXT NOT NULL,
+    audience TEXT NOT NULL DEFAULT 'default',
+    event TEXT NOT NULL,
+    occurred_at DATETIME DEFAULT (datetime('now'))
+);
+
+CREATE TABLE IF NOT EXISTS unsubscribe_feedback (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    reason TEXT NOT NULL,
+    comment TEXT,
+    submitted_at DATETIME DEFAULT (datetime('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_subscription_events_date ON subscription_events(occurred_at);
+CREATE INDEX IF NOT EXISTS idx_unsubscribe_feedback_date ON unsubscribe_feedback(submitted_at);
+";
+
+/// Normalize an email for comparison and storage
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Upgrade tables created before subscribers were tracked per audience
+pub fn migrate(conn: &Connection) -> Result<(), String> {
+    if !db::column_exists(conn, "subscribers", "audience")? {
+        tracing::info!("Migrating subscribers table: adding audience column");
+        // The primary key changes, so the table has to be rebuilt
+        conn.execute_batch(
+            "CREATE TABLE subscribers_new (
+                 email TEXT NOT NULL,
+                 audience TEXT NOT NULL DEFAULT 'default',
+                 status TEXT NOT NULL DEFAULT 'subscribed',
+                 created_at DATETIME DEFAULT (datetime('now')),
+                 updated_at DATETIME DEFAULT (datetime('now')),
+                 PRIMARY KEY (email, audience)
+             );
+             INSERT INTO subscribers_new (email, status, created_at, updated_at)
+                 SELECT email, status, created_at, updated_at FROM subscribers;
+             DROP TABLE subscribers;
+             ALTER TABLE subscribers_new RENAME TO subscribers;",
+        )
+        .map_err(|e| format!("Migration failed: {e}"))?;
+    }
+    if !db::column_exists(conn, "subscription_events", "audience")? {
+        tracing::info!("Migrating subscription_events table: adding audience column");
+        conn.execute(
+            "ALTER TABLE subscription_events ADD COLUMN audience TEXT NOT NULL DEFAULT 'default'",
+            [],
+        )
+        .map_err(|e| format!("Migration failed: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Insert or update a subscriber's status in an audience, logging a subscription
+/// event on change. Only someone who was subscribed can unsubscribe: a new
+/// address stored as unsubscribed (an import, say) was never counted as joining.
+pub fn upsert(
+    conn: &Connection,
+    email: &str,
+    audience: &str,
+    subscribed: bool,
+) -> Result<(), String> {
+    let email = normalize_email(email);
+    let status = if subscribed {
+        "subscribed"
+    } else {
+        "unsubscribed"
+    };
+    let current: Option<String> = conn
+        .query_row(
+            "SELECT status FROM subscribers WHERE email = ?1 AND audience = ?2",
+            [&email, audience],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| format!("Query error: {e}"))?;
+    if current.as_deref() == Some(status) {
+        return Ok(());
+    }
+
+    conn.execute(
+        "INSERT INTO subscribers (email, audience, status) VALUES (?1, ?2, ?3)
+         ON CONFLICT(email, audience) DO UPDATE SET status = ?3, updated_at = datetime('now')",
+        [&email, audience, status],
+    )
+    .map_err(|e| format!("Cannot save subscriber: {e}"))?;
+    if subscribed || current.as_deref() == Some("subscribed") {
+        conn.execute(
+            "INSERT INTO subscription_events (email, audience, event) VALUES (?1, ?2, ?3)",
+            [&email, audience, status],
+        )
+        .map_err(|e| format!("Cannot record subscription event: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Load an audience's local subscribers as email -> subscribed
+pub fn load(conn: &Connection, audience: &str) -> Result<BTreeMap<String, bool>, String> {
+    let mut stmt = conn
+        .prepare("SELECT email, status FROM subscribers WHERE audience = ?1")
+        .map_err(|e| format!("Query error: {e}"))?;
+    let rows = stmt
+        .query_map([audience], |row| {
+            let email: String = row.get(0)?;
+            let status: String = row.get(1)?;
+            Ok((normalize_email(&email), status == "subscribed"))
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(rows)
+}
+
+/// Subscriber activity for a single day
+#[derive(Clone)]
+pub struct GrowthDay {
+    pub date: String,
+    pub total: i64,
+    pub signups: i64,
+    pub unsubscribes: i64,
+}
+
+/// Daily subscriber totals, signups, and unsubscribes for each day in the range.
+///
+/// Returns an empty series if the subscription events table doesn't exist yet.
+pub fn growth(conn: &Connection, range: &db::DateRange) -> Result<Vec<GrowthDay>, String> {
+    if !db::table_exists(conn, "subscription_events")? {
+        return Ok(Vec::new());
+    }
+
+    // Net subscribers before the window opens
+    let baseline: i64 = conn
+        .query_row(
+            "SELECT COALESCE(SUM(CASE event WHEN 'subscribed' THEN 1 ELSE -1 END), 0)
+             FROM subscription_events
+             WHERE date(occurred_at) < ?1",
+            [&range.from],
+            |row| row.get(0),
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+
+    let mut stmt = conn
+        .prepare(
+            "WITH RECURSIVE days(d) AS (
+                 SELECT ?1
+                 UNION ALL
+                 SELECT date(d, '+1 day') FROM days WHERE d < ?2
+             )
+             SELECT d,
+                    COALESCE(SUM(CASE WHEN e.event = 'subscribed' THEN 1 ELSE 0 END), 0),
+                    COALESCE(SUM(CASE WHEN e.event = 'unsubscribed' THEN 1 ELSE 0 END), 0)
+             FROM days
+             LEFT JOIN subscription_events e ON date(e.occurred_at) = d
+             GROUP BY d
+             ORDER BY d",
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+
+    let mut total = baseline;
+    let series = stmt
+        .query_map([&range.from, &range.to], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok())
+        .map(|(date, signups, unsubscribes)| {
+            total += signups - unsubscribes;
+            GrowthDay {
+                date,
+                total,
+                signups,
+                unsubscribes,
+            }
+        })
+        .collect();
+    Ok(series)
+}
+
+/// Current size of the local list across all audiences, by address
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ListCounts {
+    /// Every address that has ever subscribed to an audience
+    pub total: i64,
+    /// Addresses subscribed to at least one audience
+    pub confirmed: i64,
+    /// Addresses that have unsubscribed from every audience
+    pub unsubscribed: i64,
+    /// Distinct addresses that have hard-bounced (from Resend webhooks)
+    pub bounced: i64,
+}
+
+/// Subscriber addresses by status, plus bounces when webhook events are stored
+pub fn counts(conn: &Connection) -> Result<ListCounts, String> {
+    let mut counts = ListCounts::default();
+    if db::table_exists(conn, "subscribers")? {
+        (counts.total, counts.confirmed, counts.unsubscribed) = conn
+            .query_row(
+                "SELECT COUNT(*), COALESCE(SUM(subscribed), 0), COALESCE(SUM(NOT subscribed), 0)
+                 FROM (SELECT MAX(status = 'subscribed') AS subscribed
+                       FROM subscribers GROUP BY email)",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .map_err(|e| format!("Query error: {e}"))?;
+    }
+    if db::table_exists(conn, "email_events")? {
+        counts.bounced = conn
+            .query_row(
+                "SELECT COUNT(DISTINCT recipient) FROM email_events
+                 WHERE event_type = 'email.bounced' AND recipient IS NOT NULL",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(|e| format!("Query error: {e}"))?;
+    }
+    Ok(counts)
+}
+
+/// Reasons offered in the post-unsubscribe survey, as (value, label)
+pub const FEEDBACK_REASONS: &[(&str, &str)] = &[
+    ("too_frequent", "Too frequent"),
+    ("not_relevant", "Not relevant to me"),
+    ("other", "Other"),
+];
+
+/// Store an anonymous unsubscribe survey response
+pub fn record_feedback(
+    conn: &Connection,
+    reason: &str,
+    comment: Option<&str>,
+) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO unsubscribe_feedback (reason, comment) VALUES (?1, ?2)",
+        rusqlite::params![reason, comment],
+    )
+    .map_err(|e| format!("Cannot save feedback: {e}"))?;
+    Ok(())
+}
+
+/// Count of unsubscribe survey responses per reason within the range
+pub fn feedback_counts(
+    conn: &Connection,
+    range: &db::DateRange,
+) -> Result<Vec<(String, i64)>, String> {
+    if !db::table_exists(conn, "unsubscribe_feedback")? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn
+        .prepare(
+            "SELECT reason, COUNT(*) as count
+             FROM unsubscribe_feedback
+             WHERE submitted_at >= ?1 AND submitted_at < date(?2, '+1 day')
+             GROUP BY reason
+             ORDER BY count DESC",
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    let counts = stmt
+        .query_map([&range.from, &range.to], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(counts)
+}
+
+/// Changes needed to bring the local table and the Resend audience into agreement.
+///
+/// Unsubscribes always win: if either side has opted out, both end up opted out.
+#[derive(Default, Debug, PartialEq)]
+pub struct ReconcilePlan {
+    /// Subscribed locally but missing from Resend
+    pub add_to_resend: Vec<String>,
+    /// In Resend but missing locally, with their subscribed flag
+    pub add_to_local: Vec<(String, bool)>,
+    /// Unsubscribed locally but still active in Resend
+    pub unsubscribe_in_resend: Vec<String>,
+    /// Unsubscribed in Resend but still active locally
+    pub unsubscribe_locally: Vec<String>,
+}
+
+impl ReconcilePlan {
+    pub fn is_empty(&self) -> bool {
+        self.add_to_resend.is_empty()
+            && self.add_to_local.is_empty()
+            && self.unsubscribe_in_resend.is_empty()
+            && self.unsubscribe_locally.is_empty()
+    }
+}
+
+/// Diff the local subscriber map against the Resend contact list
+pub fn plan(local: &BTreeMap<String, bool>, remote: &[Contact]) -> ReconcilePlan {
+    let remote: BTreeMap<String, bool> = remote
+        .iter()
+        .map(|c| (normalize_email(&c.email), !c.unsubscribed))
+        .collect();
+
+    let mut plan = ReconcilePlan::default();
+    for (email, &subscribed) in local {
+        match remote.get(email) {
+            None if subscribed => plan.add_to_resend.push(email.clone()),
+            None => {}
+            Some(true) if !subscribed => plan.unsubscribe_in_resend.push(email.clone()),
+            Some(false) if subscribed => plan.unsubscribe_locally.push(email.clone()),
+            Some(_) => {}
+        }
+    }
+    for (email, &subscribed) in &remote {
+        if !local.contains_key(email) {
+            plan.add_to_local.push((email.clone(), subscribed));
+        }
+    }
+    plan
+}
+
+/// Reconcile the local subscribers of one named audience with its Resend audience.
+///
+/// With `dry_run` set, only the plan is computed; nothing is written.
+pub async fn reconcile(
+    storage: &Arc<dyn Storage>,
+    name: &str,
+    audience: &Audience<'_>,
+    dry_run: bool,
+) -> Result<ReconcilePlan, String> {
+    let local = {
+        let (storage, name) = (Arc::clone(storage), name.to_string());
+        db::blocking(move || storage.subscribers(&name)).await??
+    };
+    let remote = audience.list_contacts().await?;
+    let plan = plan(&local, &remote);
+
+    if dry_run {
+        return Ok(plan);
+    }
+
+    for email in &plan.add_to_resend {
+        audience.add_contact(email, false).await?;
+    }
+    for email in &plan.unsubscribe_in_resend {
+        audience.set_unsubscribed(email, true).await?;
+    }
+    let local_changes: Vec<(String, bool)> = plan
+        .add_to_local
+        .iter()
+        .cloned()
+        .chain(plan.unsubscribe_locally.iter().map(|e| (e.clone(), false)))
+        .collect();
+    let (storage, name) = (Arc::clone(storage), name.to_string());
+    db::blocking(move || storage.set_subscriptions(&name, &local_changes)).await??;
+
+    Ok(plan)
+}
+
+/// Print a human-readable summary of a reconciliation
+pub fn print_report(plan: &ReconcilePlan, dry_run: bool) {
+    let verb = if dry_run { "Would" } else { "Did" };
+    if plan.is_empty() {
+        println!("Local subscribers and Resend audience are in sync");
+        return;
+    }
+    for email in &plan.add_to_resend {
+        println!("{verb} add to Resend: {email}");
+    }
+    for email in &plan.unsubscribe_in_resend {
+        println!("{verb} unsubscribe in Resend: {email}");
+    }
+    for (email, subscribed) in &plan.add_to_local {
+        let status = if *subscribed {
+            "subscribed"
+        } else {
+            "unsubscribed"
+        };
+        println!("{verb} add locally ({status}): {email}");
+    }
+    for email in &plan.unsubscribe_locally {
+        println!("{verb} unsubscribe locally: {email}");
+    }
+    println!(
+        "Resend: +{} added, {} unsubscribed · Local: +{} added, {} unsubscribed",
+        plan.add_to_resend.len(),
+        plan.unsubscribe_in_resend.len(),
+        plan.add_to_local.len(),
+        plan.unsubscribe_locally.len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contact(email: &str, unsubscribed: bool) -> Contact {
+        Contact {
+            id: None,
+            email: email.into(),
+            unsubscribed,
+        }
+    }
+
+    mod migrate {
+        use super::*;
+
+        #[test]
+        fn adds_audience_to_legacy_tables() {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(
+                "CREATE TABLE subscribers (email TEXT PRIMARY KEY, status TEXT NOT NULL,
+                     created_at DATETIME, updated_at DATETIME);
+                 CREATE TABLE subscription_events (id INTEGER PRIMARY KEY, email TEXT NOT NULL,
+                     event TEXT NOT NULL, occurred_at DATETIME);
+                 INSERT INTO subscribers (email, status) VALUES ('a@x.com', 'subscribed');",
+            )
+            .unwrap();
+            migrate(&conn).unwrap();
+
+            let local = load(&conn, "default").unwrap();
+            assert_eq!(local.get("a@x.com"), Some(&true));
+            // Same email can now join a second audience
+            upsert(&conn, "a@x.com", "weekly", true).unwrap();
+            assert_eq!(load(&conn, "weekly").unwrap().len(), 1);
+        }
+    }
+
+    mod growth {
+        use super::*;
+
+        #[test]
+        fn ignores_addresses_that_never_subscribed() {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(SCHEMA).unwrap();
+            upsert(&conn, "imported@x.com", "default", false).unwrap();
+            upsert(&conn, "a@x.com", "default", true).unwrap();
+            upsert(&conn, "a@x.com", "default", false).unwrap();
+
+            let today: String = conn
+                .query_row("SELECT date('now')", [], |row| row.get(0))
+                .unwrap();
+            let range = db::DateRange {
+                from: today.clone(),
+                to: today,
+                days: 1,
+            };
+            let day = &growth(&conn, &range).unwrap()[0];
+            assert_eq!((day.signups, day.unsubscribes, day.total), (1, 1, 0));
+        }
+    }
+
+    mod counts {
+        use super::*;
+
+        #[test]
+        fn by_status_with_bounces() {
+            let conn = Connection::open_in_memory().unwrap();
+            assert_eq!(counts(&conn).unwrap(), ListCounts::default());
+
+            conn.execute_batch(SCHEMA).unwrap();
+            upsert(&conn, "a@x.com", "default", true).unwrap();
+            upsert(&conn, "b@x.com", "default", true).unwrap();
+            upsert(&conn, "b@x.com", "default", false).unwrap();
+            // Counted once, as still subscribed
+            upsert(&conn, "a@x.com", "news", true).unwrap();
+            upsert(&conn, "a@x.com", "default", false).unwrap();
+            conn.execute_batch(
+                "CREATE TABLE email_events (event_type TEXT, recipient TEXT);
+                 INSERT INTO email_events VALUES ('email.bounced', 'c@x.com'),
+                     ('email.bounced', 'c@x.com'), ('email.opened', 'a@x.com');",
+            )
+            .unwrap();
+            assert_eq!(
+                counts(&conn).unwrap(),
+                ListCounts {
+                    total: 2,
+                    confirmed: 1,
+                    unsubscribed: 1,
+                    bounced: 1,
+                }
+            );
+        }
+    }
+
+    mod plan {
+        use super::*;
+
+        #[test]
+        fn in_sync_is_empty() {
+            let local = BTreeMap::from([("a@x.com".to_string(), true)]);
+            let remote = [contact("A@x.com", false)];
+            assert!(plan(&local, &remote).is_empty());
+        }
+
+        #[test]
+        fn adds_missing_on_both_sides() {
+            let local = BTreeMap::from([("a@x.com".to_string(), true)]);
+            let remote = [contact("b@x.com", false)];
+            let p = plan(&local, &remote);
+            assert_eq!(p.add_to_resend, vec!["a@x.com"]);
+            assert_eq!(p.add_to_local, vec![("b@x.com".to_string(), true)]);
+        }
+
+        #[test]
+        fn unsubscribes_win() {
+            let local = BTreeMap::from([
+                ("a@x.com".to_string(), false),
+                ("b@x.com".to_string(), true),
+            ]);
+            let remote = [contact("a@x.com", false), contact("b@x.com", true)];
+            let p = plan(&local, &remote);
+            assert_eq!(p.unsubscribe_in_resend, vec!["a@x.com"]);
+            assert_eq!(p.unsubscribe_locally, vec!["b@x.com"]);
+        }
+
+        #[test]
+        fn local_unsubscribed_not_pushed_to_resend() {
+            let local = BTreeMap::from([("a@x.com".to_string(), false)]);
+            assert!(plan(&local, &[]).is_empty());
+        }
+    }
+}
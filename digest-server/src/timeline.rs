@@ -0,0 +1,183 @@
+//! Pipeline runs stage by stage, from the `run_stages` table `run.py` writes:
+//! how long each stage took, how many items it handled (articles fetched,
+//! stories selected or rendered, recipients emailed, headlines recorded), and
+//! the error it stopped on. Runs that failed or were killed are there too,
+//! unlike in `digest_runs`, which only records finished runs.
+
+use crate::db;
+use rusqlite::Connection;
+
+/// Runs shown in the timeline, newest first
+const RECENT_RUNS: u32 = 10;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StageTiming {
+    pub stage: String,
+    /// UTC, as "YYYY-MM-DD HH:MM:SS"
+    pub started_at: String,
+    pub duration_ms: i64,
+    pub items: Option<i64>,
+    pub error: Option<String>,
+}
+
+/// One attempt at a run, with its stages in order
+#[derive(Clone, Debug, PartialEq)]
+pub struct RunStages {
+    /// UTC, as "YYYY-MM-DD HH:MM:SS"
+    pub started_at: String,
+    /// Its `digest_runs` row, once recorded
+    pub run_id: Option<i64>,
+    pub dry_run: bool,
+    pub stages: Vec<StageTiming>,
+}
+
+/// A `run_stages` row: the run it belongs to (its start, `digest_runs` ID,
+/// and whether it was a dry run) and the stage
+pub type Row = (String, Option<i64>, bool, StageTiming);
+
+impl RunStages {
+    pub fn duration_ms(&self) -> i64 {
+        self.stages.iter().map(|s| s.duration_ms).sum()
+    }
+
+    /// The stage it stopped on, if one failed
+    pub fn failed(&self) -> Option<&StageTiming> {
+        self.stages.iter().find(|s| s.error.is_some())
+    }
+
+    /// "recorded", "dry run", "failed", or, for a run killed partway or still
+    /// going, "unfinished"
+    pub fn outcome(&self) -> &'static str {
+        if self.failed().is_some() {
+            "failed"
+        } else if self.run_id.is_some() {
+            "recorded"
+        } else if self.dry_run {
+            "dry run"
+        } else {
+            "unfinished"
+        }
+    }
+
+    /// Group rows, ordered by run and then stage, into runs
+    pub fn from_rows(rows: Vec<Row>) -> Vec<Self> {
+        let mut runs: Vec<Self> = Vec::new();
+        for (started_at, run_id, dry_run, stage) in rows {
+            match runs.last_mut() {
+                Some(run) if run.started_at == started_at => {
+                    run.run_id = run.run_id.or(run_id);
+                    run.stages.push(stage);
+                }
+                _ => runs.push(Self {
+                    started_at,
+                    run_id,
+                    dry_run,
+                    stages: vec![stage],
+                }),
+            }
+        }
+        runs
+    }
+}
+
+/// The latest runs started within the range, newest first; empty before the
+/// pipeline records stages
+pub fn recent(conn: &Connection, range: &db::DateRange) -> Result<Vec<RunStages>, String> {
+    if !db::table_exists(conn, "run_stages")? {
+        return Ok(Vec::new());
+    }
+    let mut stmt = conn
+        .prepare(
+            "SELECT run_started_at, run_id, dry_run, stage, started_at, duration_ms, items, error
+             FROM run_stages
+             WHERE run_started_at IN (
+                 SELECT DISTINCT run_started_at FROM run_stages
+                 WHERE run_started_at >= ?1 AND run_started_at < date(?2, '+1 day')
+                 ORDER BY run_started_at DESC
+                 LIMIT ?3
+             )
+             ORDER BY run_started_at DESC, id",
+        )
+        .map_err(|e| format!("Query error: {e}"))?;
+    let rows = stmt
+        .query_map(
+            rusqlite::params![range.from, range.to, RECENT_RUNS],
+            |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    StageTiming {
+                        stage: row.get(3)?,
+                        started_at: row.get(4)?,
+                        duration_ms: row.get(5)?,
+                        items: row.get(6)?,
+                        error: row.get(7)?,
+                    },
+                ))
+            },
+        )
+        .map_err(|e| format!("Query error: {e}"))?
+        .collect::<Result<Vec<Row>, _>>()
+        .map_err(|e| format!("Query error: {e}"))?;
+    Ok(RunStages::from_rows(rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod recent {
+        use super::*;
+
+        #[test]
+        fn groups_stages_by_run_newest_first() {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(
+                "CREATE TABLE run_stages (id INTEGER PRIMARY KEY AUTOINCREMENT,
+                     run_started_at DATETIME, run_id INTEGER, dry_run INTEGER, stage TEXT,
+                     started_at DATETIME, duration_ms INTEGER, items INTEGER, error TEXT);
+                 INSERT INTO run_stages (run_started_at, run_id, dry_run, stage, started_at,
+                     duration_ms, items, error) VALUES
+                     ('2026-01-05 07:00:00', 4, 0, 'fetch', '2026-01-05 07:00:00', 30000, 250, NULL),
+                     ('2026-01-05 07:00:00', 4, 0, 'select', '2026-01-05 07:00:30', 180000, 24, NULL),
+                     ('2026-01-06 07:00:00', NULL, 0, 'fetch', '2026-01-06 07:00:00', 28000, 240, NULL),
+                     ('2026-01-06 07:00:00', NULL, 0, 'select', '2026-01-06 07:00:28', 5000, NULL,
+                      'RuntimeError: model overloaded'),
+                     ('2026-01-01 07:00:00', NULL, 1, 'fetch', '2026-01-01 07:00:00', 1000, 9, NULL);",
+            )
+            .unwrap();
+            let range = db::DateRange {
+                from: "2026-01-05".into(),
+                to: "2026-01-06".into(),
+                days: 2,
+            };
+            let runs = recent(&conn, &range).unwrap();
+            let outcomes: Vec<(&str, &str)> = runs
+                .iter()
+                .map(|r| (r.started_at.as_str(), r.outcome()))
+                .collect();
+            assert_eq!(
+                outcomes,
+                [
+                    ("2026-01-06 07:00:00", "failed"),
+                    ("2026-01-05 07:00:00", "recorded")
+                ]
+            );
+            assert_eq!(runs[0].failed().unwrap().stage, "select");
+            assert_eq!(runs[1].duration_ms(), 210_000);
+            assert_eq!(runs[1].stages[0].items, Some(250));
+        }
+
+        #[test]
+        fn empty_before_the_pipeline_records_stages() {
+            let conn = Connection::open_in_memory().unwrap();
+            let range = db::DateRange {
+                from: "2026-01-05".into(),
+                to: "2026-01-06".into(),
+                days: 2,
+            };
+            assert!(recent(&conn, &range).unwrap().is_empty());
+        }
+    }
+}
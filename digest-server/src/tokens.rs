@@ -0,0 +1,227 @@
+//! HMAC-signed, expiring tokens for stateless email links.
+//!
+//! A token is `base64url(payload).base64url(hmac_sha256(payload))`, where the
+//! payload is `action\nemail\naudience\nexpires_at`. Confirm, unsubscribe, and
+//! preferences links carry everything they need, so no DB lookup is required.
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use ring::hmac;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How long a double opt-in confirmation link stays valid
+pub const CONFIRM_TTL_SECS: u64 = 2 * 24 * 60 * 60;
+/// How long unsubscribe/preferences links in emails stay valid
+pub const LINK_TTL_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// What a token authorizes its bearer to do
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Confirm,
+    Unsubscribe,
+    Preferences,
+}
+
+impl Action {
+    fn as_str(self) -> &'static str {
+        match self {
+            Action::Confirm => "confirm",
+            Action::Unsubscribe => "unsubscribe",
+            Action::Preferences => "preferences",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "confirm" => Some(Action::Confirm),
+            "unsubscribe" => Some(Action::Unsubscribe),
+            "preferences" => Some(Action::Preferences),
+            _ => None,
+        }
+    }
+}
+
+/// The signed contents of a token
+#[derive(Clone, Debug, PartialEq)]
+pub struct Claims {
+    pub action: Action,
+    pub email: String,
+    /// Audience name, if the link targets a single list
+    pub audience: Option<String>,
+    /// Unix timestamp after which the token is rejected
+    pub expires_at: u64,
+}
+
+impl Claims {
+    pub fn new(action: Action, email: &str, audience: Option<&str>, ttl_secs: u64) -> Self {
+        Self {
+            action,
+            email: email.trim().to_lowercase(),
+            audience: audience.map(str::to_string),
+            expires_at: now() + ttl_secs,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TokenError {
+    Malformed,
+    BadSignature,
+    WrongAction,
+    Expired,
+}
+
+impl std::fmt::Display for TokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TokenError::Malformed => "Malformed link",
+            TokenError::BadSignature => "Invalid link",
+            TokenError::WrongAction => "Link not valid for this page",
+            TokenError::Expired => "Link has expired",
+        })
+    }
+}
+
+/// Current Unix time in seconds
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Sign claims into a URL-safe token
+pub fn mint(secret: &[u8], claims: &Claims) -> String {
+    let payload = format!(
+        "{}\n{}\n{}\n{}",
+        claims.action.as_str(),
+        claims.email,
+        claims.audience.as_deref().unwrap_or_default(),
+        claims.expires_at
+    );
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    let tag = hmac::sign(&key, payload.as_bytes());
+    format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(payload),
+        URL_SAFE_NO_PAD.encode(tag.as_ref())
+    )
+}
+
+/// Verify a token's signature, action, and expiry as of `now`
+pub fn verify(secret: &[u8], token: &str, action: Action, now: u64) -> Result<Claims, TokenError> {
+    let (payload_b64, tag_b64) = token.trim().split_once('.').ok_or(TokenError::Malformed)?;
+    let payload = URL_SAFE_NO_PAD
+        .decode(payload_b64)
+        .map_err(|_| TokenError::Malformed)?;
+    let tag = URL_SAFE_NO_PAD
+        .decode(tag_b64)
+        .map_err(|_| TokenError::Malformed)?;
+
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+    hmac::verify(&key, &payload, &tag).map_err(|_| TokenError::BadSignature)?;
+
+    let payload = String::from_utf8(payload).map_err(|_| TokenError::Malformed)?;
+    let fields: Vec<&str> = payload.split('\n').collect();
+    let [kind, email, audience, expires_at] = fields[..] else {
+        return Err(TokenError::Malformed);
+    };
+    let claims = Claims {
+        action: Action::parse(kind).ok_or(TokenError::Malformed)?,
+        email: email.to_string(),
+        audience: Some(audience).filter(|a| !a.is_empty()).map(str::to_string),
+        expires_at: expires_at.parse().map_err(|_| TokenError::Malformed)?,
+    };
+
+    if claims.action != action {
+        return Err(TokenError::WrongAction);
+    }
+    if claims.expires_at < now {
+        return Err(TokenError::Expired);
+    }
+    Ok(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"test-secret";
+
+    fn claims(action: Action, expires_at: u64) -> Claims {
+        Claims {
+            action,
+            email: "reader@example.com".into(),
+            audience: Some("weekly".into()),
+            expires_at,
+        }
+    }
+
+    mod verify {
+        use super::*;
+
+        #[test]
+        fn round_trip() {
+            let c = claims(Action::Unsubscribe, 2_000);
+            let token = mint(SECRET, &c);
+            assert_eq!(verify(SECRET, &token, Action::Unsubscribe, 1_000), Ok(c));
+        }
+
+        #[test]
+        fn audience_is_optional() {
+            let mut c = claims(Action::Confirm, 2_000);
+            c.audience = None;
+            let token = mint(SECRET, &c);
+            assert_eq!(verify(SECRET, &token, Action::Confirm, 1_000), Ok(c));
+        }
+
+        #[test]
+        fn rejects_expired() {
+            let token = mint(SECRET, &claims(Action::Confirm, 1_000));
+            assert_eq!(
+                verify(SECRET, &token, Action::Confirm, 1_001),
+                Err(TokenError::Expired)
+            );
+        }
+
+        #[test]
+        fn rejects_other_action() {
+            let token = mint(SECRET, &claims(Action::Preferences, 2_000));
+            assert_eq!(
+                verify(SECRET, &token, Action::Unsubscribe, 1_000),
+                Err(TokenError::WrongAction)
+            );
+        }
+
+        #[test]
+        fn rejects_wrong_secret() {
+            let token = mint(b"other", &claims(Action::Confirm, 2_000));
+            assert_eq!(
+                verify(SECRET, &token, Action::Confirm, 1_000),
+                Err(TokenError::BadSignature)
+            );
+        }
+
+        #[test]
+        fn rejects_tampered_payload() {
+            let token = mint(SECRET, &claims(Action::Confirm, 2_000));
+            let (_, tag) = token.split_once('.').unwrap();
+            let forged = URL_SAFE_NO_PAD.encode("confirm\nattacker@example.com\n\n2000");
+            assert_eq!(
+                verify(SECRET, &format!("{forged}.{tag}"), Action::Confirm, 1_000),
+                Err(TokenError::BadSignature)
+            );
+        }
+
+        #[test]
+        fn rejects_garbage() {
+            assert_eq!(
+                verify(SECRET, "not-a-token", Action::Confirm, 0),
+                Err(TokenError::Malformed)
+            );
+            assert_eq!(
+                verify(SECRET, "!!.??", Action::Confirm, 0),
+                Err(TokenError::Malformed)
+            );
+        }
+    }
+}
@@ -0,0 +1,175 @@
+//! Coverage per topic per week, from the `topic` the selection pass tags each
+//! narrative with. Narratives recorded before tagging count as "untagged".
+
+use crate::{db, rollups};
+use chrono::{NaiveDate, Weekday};
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+
+/// Narratives shown per topic, one count per week of the range
+#[derive(Debug, PartialEq)]
+pub struct TopicCoverage {
+    pub topic: String,
+    pub weekly: Vec<i64>,
+}
+
+impl TopicCoverage {
+    pub fn total(&self) -> i64 {
+        self.weekly.iter().sum()
+    }
+}
+
+/// Topic counts aligned to `weeks` (Mondays, oldest first), busiest topic first
+#[derive(Debug, Default, PartialEq)]
+pub struct Coverage {
+    pub weeks: Vec<String>,
+    pub topics: Vec<TopicCoverage>,
+}
+
+impl Coverage {
+    /// Narratives shown each week across all topics
+    pub fn weekly_totals(&self) -> Vec<i64> {
+        (0..self.weeks.len())
+            .map(|i| self.topics.iter().map(|t| t.weekly[i]).sum())
+            .collect()
+    }
+
+    /// A topic's share (%) of each week's narratives
+    pub fn weekly_share(&self, topic: &TopicCoverage) -> Vec<f64> {
+        topic
+            .weekly
+            .iter()
+            .zip(self.weekly_totals())
+            .map(|(&n, total)| {
+                if total > 0 {
+                    n as f64 / total as f64 * 100.0
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    /// Group (week, topic, count) rows into per-topic series over every week
+    /// of `range`, so weeks without narratives count as zeros
+    pub fn from_rows(range: &db::DateRange, rows: Vec<(String, String, i64)>) -> Self {
+        let weeks = weeks(range);
+        let mut by_topic: BTreeMap<String, Vec<i64>> = BTreeMap::new();
+        for (week, topic, count) in rows {
+            let Some(i) = weeks.iter().position(|w| *w == week) else {
+                continue;
+            };
+            by_topic
+                .entry(topic)
+                .or_insert_with(|| vec![0; weeks.len()])[i] += count;
+        }
+        let mut topics: Vec<TopicCoverage> = by_topic
+            .into_iter()
+            .map(|(topic, weekly)| TopicCoverage { topic, weekly })
+            .collect();
+        topics.sort_by_key(|t| std::cmp::Reverse(t.total()));
+        Coverage { weeks, topics }
+    }
+}
+
+/// The Mondays starting each week that overlaps `range`, oldest first
+fn weeks(range: &db::DateRange) -> Vec<String> {
+    let parse = |date: &str| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok();
+    let (Some(from), Some(to)) = (parse(&range.from), parse(&range.to)) else {
+        return Vec::new();
+    };
+    from.week(Weekday::Mon)
+        .first_day()
+        .iter_weeks()
+        .take_while(|monday| *monday <= to)
+        .map(|monday| monday.format("%Y-%m-%d").to_string())
+        .collect()
+}
+
+/// Weekly topic coverage within the range; empty before the pipeline stores topics
+pub fn coverage(conn: &Connection, range: &db::DateRange) -> Result<Coverage, String> {
+    if !db::column_exists(conn, "shown_narratives", "topic")? {
+        return Ok(Coverage::default());
+    }
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {week} AS week, COALESCE(NULLIF(topic, ''), 'untagged'), COUNT(*)
+             FROM shown_narratives
+             WHERE shown_at >= ?1 AND shown_at < date(?2, '+1 day')
+             GROUP BY 1, 2
+             ORDER BY 1",
+            week = rollups::week_of("shown_at")
+        ))
+        .map_err(|e| format!("Query error: {e}"))?;
+    let rows: Vec<(String, String, i64)> = stmt
+        .query_map([&range.from, &range.to], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })
+        .map_err(|e| format!("Query error: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(Coverage::from_rows(range, rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    mod coverage {
+        use super::*;
+
+        #[test]
+        fn counts_per_topic_per_week() {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(
+                "CREATE TABLE shown_narratives (topic TEXT, shown_at DATETIME);
+                 INSERT INTO shown_narratives VALUES
+                     ('geopolitics', '2026-01-05 09:00:00'),
+                     ('geopolitics', '2026-01-06 09:00:00'),
+                     ('privacy', '2026-01-07 09:00:00'),
+                     (NULL, '2026-01-07 09:00:00'),
+                     ('geopolitics', '2026-01-12 09:00:00'),
+                     ('geopolitics', '2026-01-13 09:00:00'),
+                     ('geopolitics', '2026-01-14 09:00:00');",
+            )
+            .unwrap();
+            let range = db::DateRange {
+                from: "2026-01-01".into(),
+                to: "2026-01-31".into(),
+                days: 31,
+            };
+            let c = coverage(&conn, &range).unwrap();
+            assert_eq!(
+                c.weeks,
+                [
+                    "2025-12-29",
+                    "2026-01-05",
+                    "2026-01-12",
+                    "2026-01-19",
+                    "2026-01-26"
+                ]
+            );
+            assert_eq!(c.topics[0].topic, "geopolitics");
+            // Weeks without narratives are zeros, not missing
+            assert_eq!(c.topics[0].weekly, [0, 2, 3, 0, 0]);
+            assert_eq!(c.weekly_totals(), [0, 4, 3, 0, 0]);
+            let privacy = c.topics.iter().find(|t| t.topic == "privacy").unwrap();
+            assert_eq!(c.weekly_share(privacy), [0.0, 25.0, 0.0, 0.0, 0.0]);
+            assert!(c.topics.iter().any(|t| t.topic == "untagged"));
+        }
+
+        #[test]
+        fn empty_without_topic_column() {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch("CREATE TABLE shown_narratives (shown_at DATETIME)")
+                .unwrap();
+            let range = db::DateRange {
+                from: "2026-01-01".into(),
+                to: "2026-01-31".into(),
+                days: 31,
+            };
+            assert_eq!(coverage(&conn, &range).unwrap(), Coverage::default());
+        }
+    }
+}
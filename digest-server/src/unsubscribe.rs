@@ -0,0 +1,289 @@
+//! Unsubscribe flow with an optional one-question exit survey
+
+use crate::{AppState, db, escape_html, render_page, resend, subscribers, tokens};
+use axum::{
+    Form,
+    extract::{Query, State},
+    http::StatusCode,
+    response::Html,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Longest free-text comment kept from the survey
+const MAX_COMMENT_LEN: usize = 500;
+
+#[derive(Deserialize, Default)]
+pub struct UnsubscribeQuery {
+    email: Option<String>,
+    audience: Option<String>,
+    token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct UnsubscribeForm {
+    /// Signed link token; when present it supplies the email and audience
+    token: Option<String>,
+    email: Option<String>,
+    /// Leave one audience; without it the reader leaves every list
+    audience: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct FeedbackForm {
+    reason: String,
+    comment: Option<String>,
+}
+
+/// Unsubscribe confirmation page (GET never mutates, so link prefetchers are harmless)
+pub async fn unsubscribe_page(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<UnsubscribeQuery>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    // A signed link needs no typing: confirm the address it was issued for
+    if let Some(token) = query.token.as_deref() {
+        let claims = state.verify_token(token, tokens::Action::Unsubscribe)?;
+        let content = format!(
+            r#"<p>Stop sending the digest to <strong>{}</strong>?</p>
+    <form method="post" action="/unsubscribe">
+      <input type="hidden" name="token" value="{}">
+      <button type="submit">Unsubscribe</button>
+    </form>"#,
+            escape_html(&claims.email),
+            escape_html(token)
+        );
+        return Ok(Html(render_page(&state, "Unsubscribe", &content)));
+    }
+
+    let email = escape_html(query.email.as_deref().unwrap_or_default());
+    let audience_field = query
+        .audience
+        .as_deref()
+        .map(|a| {
+            format!(
+                r#"<input type="hidden" name="audience" value="{}">"#,
+                escape_html(a)
+            )
+        })
+        .unwrap_or_default();
+    let content = format!(
+        r#"<p>Enter your email, and we'll send it a link to stop receiving the digest.</p>
+    <form method="post" action="/unsubscribe">
+      {audience_field}
+      <input type="email" name="email" value="{email}" placeholder="your@email.com" required>
+      <button type="submit">Unsubscribe</button>
+    </form>"#
+    );
+    Ok(Html(render_page(&state, "Unsubscribe", &content)))
+}
+
+/// Unsubscribe handler - with a signed token, marks the contact unsubscribed
+/// in Resend and locally, then offers the exit survey. A bare email address
+/// proves nothing, so it's sent a signed link instead.
+pub async fn unsubscribe(
+    State(state): State<Arc<AppState>>,
+    Form(form): Form<UnsubscribeForm>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    if !state.subscriptions_enabled() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Subscriptions not configured".into(),
+        ));
+    }
+    let Some(token) = form.token.as_deref() else {
+        let email = form
+            .email
+            .ok_or((StatusCode::BAD_REQUEST, "Missing email".to_string()))?;
+        return send_link(&state, &email, form.audience.as_deref()).await;
+    };
+    let claims = state.verify_token(token, tokens::Action::Unsubscribe)?;
+    let (email, audience) = (claims.email, claims.audience);
+    let audiences: Vec<_> = match audience.as_deref().filter(|a| !a.is_empty()) {
+        Some(name) => vec![
+            state
+                .audience(Some(name))
+                .ok_or((StatusCode::BAD_REQUEST, "Unknown audience".to_string()))?,
+        ],
+        None => state.audiences.iter().collect(),
+    };
+
+    if let Some(api_key) = &state.resend_api_key {
+        for audience in &audiences {
+            resend::Audience::new(&state.http_client, api_key, &audience.id)
+                .set_unsubscribed(&email, true)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+        }
+    }
+
+    // Without Resend the local table is the SMTP mailing list, so it must be
+    // written; otherwise it's a best-effort mirror, kept only in read-write mode
+    let gone = "<p>You won't receive any more digests. Sorry to see you go.</p>";
+    if !state.storage_writable() {
+        // Nowhere to store an exit survey either
+        return Ok(Html(render_page(&state, "Unsubscribed", gone)));
+    }
+    let names: Vec<String> = audiences.iter().map(|a| a.name.clone()).collect();
+    let (storage, local_email) = (state.storage.clone(), email.clone());
+    let stored = db::blocking(move || {
+        names
+            .iter()
+            .try_for_each(|name| storage.set_subscribed(&local_email, name, false))
+    })
+    .await
+    .flatten();
+    if let Err(e) = stored {
+        if state.resend_api_key.is_none() {
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, e));
+        }
+        tracing::warn!("Could not record unsubscribe locally: {}", e);
+    }
+
+    let options: String = subscribers::FEEDBACK_REASONS
+        .iter()
+        .map(|(value, label)| {
+            format!(r#"<label><input type="radio" name="reason" value="{value}" required> {label}</label>"#)
+        })
+        .collect::<Vec<_>>()
+        .join("\n      ");
+    let content = format!(
+        r#"{gone}
+    <p>Optional: why are you leaving?</p>
+    <form method="post" action="/unsubscribe/feedback">
+      {options}
+      <textarea name="comment" rows="3" maxlength="{MAX_COMMENT_LEN}" placeholder="Anything else? (optional)"></textarea>
+      <button type="submit">Send feedback</button>
+    </form>"#
+    );
+    Ok(Html(render_page(&state, "Unsubscribed", &content)))
+}
+
+/// Email `email` a signed unsubscribe link, leaving the subscription as it is
+async fn send_link(
+    state: &AppState,
+    email: &str,
+    audience: Option<&str>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    let audience = audience.filter(|a| !a.is_empty());
+    if audience.is_some() && state.audience(audience).is_none() {
+        return Err((StatusCode::BAD_REQUEST, "Unknown audience".into()));
+    }
+    let claims = tokens::Claims::new(
+        tokens::Action::Unsubscribe,
+        email,
+        audience,
+        tokens::CONFIRM_TTL_SECS,
+    );
+    let link = state.signed_link("/unsubscribe", &claims).ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "Signed links not configured".to_string(),
+    ))?;
+    let name = escape_html(&state.digest_name);
+    let html = format!(
+        r#"<p>Stop receiving {name}:</p>
+<p><a href="{link}">Unsubscribe me</a></p>
+<p>If you didn't ask for this, ignore this email and nothing will change.</p>"#
+    );
+    let subject = format!("Unsubscribe from {}", state.digest_name);
+    state.send_email(&claims.email, &subject, &html).await?;
+    let content = format!(
+        "<p>We've sent <strong>{}</strong> a link to confirm. It works for two days.</p>",
+        escape_html(&claims.email)
+    );
+    Ok(Html(render_page(state, "Check your inbox", &content)))
+}
+
+/// Store an anonymous exit survey response
+pub async fn feedback(
+    State(state): State<Arc<AppState>>,
+    Form(form): Form<FeedbackForm>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    if !subscribers::FEEDBACK_REASONS
+        .iter()
+        .any(|(value, _)| *value == form.reason)
+    {
+        return Err((StatusCode::BAD_REQUEST, "Unknown reason".into()));
+    }
+    if !state.storage_writable() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Server is read-only".into(),
+        ));
+    }
+    let comment: Option<String> = form
+        .comment
+        .as_deref()
+        .map(str::trim)
+        .filter(|c| !c.is_empty())
+        .map(|c| c.chars().take(MAX_COMMENT_LEN).collect());
+
+    state
+        .blocking(move |state| {
+            state
+                .storage
+                .record_feedback(&form.reason, comment.as_deref())
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))
+        })
+        .await?;
+
+    Ok(Html(render_page(
+        &state,
+        "Thanks",
+        "<p>Thanks for the feedback. It helps shape what the digest covers.</p>",
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_state;
+
+    mod unsubscribe {
+        use super::*;
+
+        fn form(token: Option<String>, email: Option<&str>) -> Form<UnsubscribeForm> {
+            Form(UnsubscribeForm {
+                token,
+                email: email.map(str::to_string),
+                audience: None,
+            })
+        }
+
+        #[tokio::test]
+        async fn needs_a_signed_link() {
+            let dir = std::env::temp_dir().join(format!("unsubscribe-{}", std::process::id()));
+            let state = Arc::new(test_state(&dir));
+            let subscribed = |state: &AppState| state.storage.subscribers("default").unwrap();
+            state
+                .storage
+                .set_subscribed("reader@example.com", "default", true)
+                .unwrap();
+
+            // No token: the address would be mailed a link (here the mail
+            // server is down), and stays subscribed either way
+            let result =
+                unsubscribe(State(state.clone()), form(None, Some("reader@example.com"))).await;
+            assert!(result.is_err());
+            assert!(subscribed(&state)["reader@example.com"]);
+
+            let forged = form(Some("not-a-token".into()), None);
+            assert!(unsubscribe(State(state.clone()), forged).await.is_err());
+            assert!(subscribed(&state)["reader@example.com"]);
+
+            let claims = tokens::Claims::new(
+                tokens::Action::Unsubscribe,
+                "reader@example.com",
+                None,
+                tokens::LINK_TTL_SECS,
+            );
+            let token = tokens::mint(b"test secret", &claims);
+            assert!(
+                unsubscribe(State(state.clone()), form(Some(token), None))
+                    .await
+                    .is_ok()
+            );
+            assert!(!subscribed(&state)["reader@example.com"]);
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
@@ -36,6 +36,11 @@ def send_notification(method: str, params: Any = None):
 
 
 # Schema definitions
+# Coverage topics tracked in stats (mirrors the interests in news-digest-select.md)
+TOPICS = ["geopolitics", "tech_ai", "privacy", "economy", "other"]
+
+TOPIC_SCHEMA = {"type": "string", "enum": TOPICS, "description": "Main topic of the story"}
+
 SOURCE_SCHEMA = {
     "type": "object",
     "properties": {
@@ -52,6 +57,7 @@ ARTICLE_SCHEMA = {
         "headline": {"type": "string", "description": "Headline in sentence case"},
         "summary": {"type": "string", "description": "2-3 sentence summary"},
         "why_it_matters": {"type": "string", "description": "1-2 sentence insight on significance"},
+        "topic": TOPIC_SCHEMA,
         "sources": {"type": "array", "items": SOURCE_SCHEMA, "minItems": 1},
         "reporting_varies": {
             "type": "array",
@@ -71,6 +77,7 @@ SIGNAL_SCHEMA = {
     "properties": {
         "headline": {"type": "string", "description": "Brief headline with key fact"},
         "source": SOURCE_SCHEMA,
+        "topic": TOPIC_SCHEMA,
     },
     "required": ["headline", "source"],
 }
@@ -6,7 +6,10 @@ Pipeline: Fetch RSS → Claude curation → Email delivery
 """
 
 import argparse
+import base64
 import csv
+import hashlib
+import hmac
 import html
 import json
 import math
@@ -17,10 +20,13 @@ import sqlite3
 import subprocess
 import sys
 import time
+import tomllib
 import urllib.error
+import urllib.parse
 import urllib.request
 from collections import Counter
 from concurrent.futures import ThreadPoolExecutor, as_completed
+from contextlib import contextmanager
 from datetime import UTC, datetime
 from email.utils import parsedate_to_datetime
 from pathlib import Path
@@ -37,24 +43,35 @@ MAX_RETRIES = int(os.environ.get("RSS_MAX_RETRIES", "3"))  # Retry flaky RSS fee
 RETRY_DELAY = int(os.environ.get("RSS_RETRY_DELAY", "2"))  # Base delay in seconds (exponential backoff)
 HEALTH_ALERT_THRESHOLD = int(os.environ.get("HEALTH_ALERT_THRESHOLD", "3"))  # Consecutive failures before alert
 
+# Resuming an interrupted run
+CHECKPOINT_MAX_AGE_HOURS = float(os.environ.get("CHECKPOINT_MAX_AGE_HOURS", "12"))  # Older checkpoints start over
+
 # Article processing
 MAX_TOKENS_PER_FILE = 10000  # Conservative limit for Claude Code file reading
 MAX_TITLE_LENGTH = 500  # Cap title length for safety
 MAX_SUMMARY_LENGTH = 200  # Cap summary length
+MAX_CONTENT_LENGTH = 1000  # Cap article text digest-pipeline extracted in place of a teaser
 DEDUP_WINDOW_DAYS = 7  # Days of headline history for deduplication
 
 # Deduplication (TF-IDF pre-filter)
 DEDUP_SIMILARITY_THRESHOLD = float(os.environ.get("DEDUP_SIMILARITY_THRESHOLD", "0.35"))
 
+# Hosted images (article thumbnails digest-server serves at /img/{hash})
+IMAGE_MAX_BYTES = int(os.environ.get("IMAGE_MAX_BYTES", "500000"))
+
 # Paths
 APP_DIR = Path(__file__).parent
 DATA_DIR = APP_DIR / "data"
 DB_PATH = DATA_DIR / "digest.db"
+# Optional separate file for high-churn telemetry (source_health), next to DB_PATH
+TELEMETRY_DB = os.environ.get("TELEMETRY_DB")
+TELEMETRY_DB_PATH = DATA_DIR / TELEMETRY_DB if TELEMETRY_DB else None
 LOG_FILE = DATA_DIR / "digest.log"
 FETCHED_DIR = DATA_DIR / "fetched"
 OUTPUT_DIR = DATA_DIR / "output"
 CLAUDE_INPUT_DIR = DATA_DIR / "claude_input"  # Intermediate files for Claude
-SOURCES_FILE = APP_DIR / "sources.json"
+SELECT_COMMAND_FILE = APP_DIR / ".claude" / "commands" / "news-digest-select.md"
+SOURCES_FILE = APP_DIR / "sources.toml"
 STYLES_FILE = APP_DIR / "digest.css"
 
 # Logging
@@ -62,25 +79,64 @@ MAX_LOG_LINES = 1000  # Keep last N log lines
 
 
 def load_sources() -> list[dict]:
-    """Load and validate RSS sources from JSON file."""
-    with open(SOURCES_FILE) as f:
-        sources = json.load(f)
+    """Load and validate RSS sources from the TOML file, leaving out disabled ones.
+
+    Validated as digest-server's sources.rs validates them; optional fields
+    get their defaults.
+    """
+    with open(SOURCES_FILE, "rb") as f:
+        sources = tomllib.load(f).get("source", [])
 
     # Validate schema
     required_keys = {"id", "name", "url", "bias", "perspective"}
-    for i, source in enumerate(sources):
+    optional_keys = {
+        "weight": 1.0,
+        "topics": [],
+        "delay_secs": None,
+        "enabled": True,
+        "owner": None,
+        "region": None,
+        "filters": {},
+    }
+    ids, urls = set(), set()
+    for i, source in enumerate(sources, start=1):
+        where = f"sources.toml source {i} ({source.get('id', '?')})"
         missing = required_keys - set(source.keys())
         if missing:
-            raise ValueError(f"sources.json[{i}] missing keys: {missing}")
-        if not source["url"].startswith(("http://", "https://")):
-            raise ValueError(f"sources.json[{i}] invalid URL: {source['url']}")
+            raise ValueError(f"{where} missing keys: {missing}")
+        unknown = set(source.keys()) - required_keys - set(optional_keys)
+        if unknown:
+            raise ValueError(f"{where} unknown keys: {unknown}")
         # Prevent path traversal - source_id is used in file paths
         if not re.match(r"^[a-z0-9_]+$", source["id"]):
-            raise ValueError(
-                f"sources.json[{i}] invalid id '{source['id']}': must be lowercase alphanumeric/underscore only"
-            )
-
-    return sources
+            raise ValueError(f"{where}: invalid id: must be lowercase alphanumeric/underscore only")
+        if source["id"] in ids:
+            raise ValueError(f"{where}: duplicate id")
+        # imaps:// names a mailbox of newsletters, read by digest-pipeline
+        if not source["url"].startswith(("http://", "https://", "imaps://")):
+            raise ValueError(f"{where}: invalid URL {source['url']!r}: must be http(s) or imaps")
+        if source["url"] in urls:
+            raise ValueError(f"{where}: duplicate URL {source['url']}")
+        ids.add(source["id"])
+        urls.add(source["url"])
+        for key, default in optional_keys.items():
+            source.setdefault(key, default)
+        if not source["weight"] > 0:
+            raise ValueError(f"{where}: invalid weight {source['weight']}: must be above 0")
+        if source["delay_secs"] is not None and not source["delay_secs"] >= 0:
+            raise ValueError(f"{where}: invalid delay_secs {source['delay_secs']}: must be 0 or more")
+        for key in ("owner", "region"):
+            if source[key] is not None and not str(source[key]).strip():
+                raise ValueError(f"{where}: empty {key}")
+        for topic in source["topics"]:
+            if not re.match(r"^[a-z0-9_]+$", topic):
+                raise ValueError(f"{where}: invalid topic {topic!r}: must be lowercase alphanumeric/underscore only")
+        # Applied by digest-pipeline fetch, which checks the patterns
+        unknown = set(source["filters"]) - {"include", "exclude", "include_regex", "exclude_regex"}
+        if unknown:
+            raise ValueError(f"{where}: unknown filters keys: {unknown}")
+
+    return [s for s in sources if s["enabled"]]
 
 
 _source_name_to_id_cache: dict[str, str] | None = None
@@ -137,9 +193,11 @@ def validate_env(dry_run: bool = False):
     # ANTHROPIC_API_KEY is optional - Claude CLI can use `claude login` for Pro subscription
     required = []
     if not dry_run:
-        required.extend(["RESEND_API_KEY", "RESEND_FROM", "RESEND_AUDIENCE_ID"])
+        required.extend(["RESEND_API_KEY", "RESEND_FROM"])
 
     missing = [var for var in required if not os.environ.get(var)]
+    if not dry_run and not os.environ.get("RESEND_AUDIENCE_ID") and not os.environ.get("RESEND_AUDIENCES"):
+        missing.append("RESEND_AUDIENCE_ID (or RESEND_AUDIENCES)")
     if missing:
         log(f"Missing environment variables: {', '.join(missing)}", "ERROR")
         sys.exit(1)
@@ -154,7 +212,14 @@ CREATE TABLE IF NOT EXISTS digest_runs (
     id INTEGER PRIMARY KEY AUTOINCREMENT,
     run_at DATETIME DEFAULT (datetime('now', 'utc')),
     articles_fetched INTEGER,
-    articles_emailed INTEGER
+    articles_emailed INTEGER,
+    duration_ms INTEGER,
+    input_tokens INTEGER,
+    output_tokens INTEGER,
+    cost_usd REAL,
+    prompt_version TEXT,
+    budget_usd REAL,
+    budget_tokens INTEGER
 );
 
 CREATE TABLE IF NOT EXISTS shown_narratives (
@@ -162,21 +227,60 @@ CREATE TABLE IF NOT EXISTS shown_narratives (
     headline TEXT NOT NULL,
     tier TEXT,
     source_id TEXT,
+    topic TEXT,
     shown_at DATETIME DEFAULT (datetime('now', 'utc'))
 );
 
-CREATE TABLE IF NOT EXISTS source_health (
-    id INTEGER PRIMARY KEY AUTOINCREMENT,
-    source_id TEXT NOT NULL,
-    success INTEGER NOT NULL,
-    error_message TEXT,
-    recorded_at DATETIME DEFAULT (datetime('now', 'utc'))
+CREATE TABLE IF NOT EXISTS source_activity (
+    source_id TEXT PRIMARY KEY,
+    fingerprint TEXT NOT NULL,
+    last_new_item_at DATETIME NOT NULL
 );
 
 CREATE TABLE IF NOT EXISTS digests (
     date TEXT PRIMARY KEY,
     html TEXT NOT NULL,
-    created_at DATETIME DEFAULT (datetime('now', 'utc'))
+    created_at DATETIME DEFAULT (datetime('now', 'utc')),
+    updated_at DATETIME DEFAULT (strftime('%Y-%m-%d %H:%M:%f', 'now'))
+);
+
+CREATE TABLE IF NOT EXISTS narratives (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    digest_date TEXT NOT NULL,
+    tier TEXT NOT NULL,
+    cluster TEXT,
+    position INTEGER NOT NULL,
+    headline TEXT NOT NULL,
+    summary TEXT,
+    why_it_matters TEXT,
+    topic TEXT,
+    sources TEXT NOT NULL DEFAULT '[]',
+    reporting_varies TEXT NOT NULL DEFAULT '[]',
+    story TEXT,
+    image TEXT
+);
+
+CREATE TABLE IF NOT EXISTS story_articles (
+    digest_date TEXT NOT NULL,
+    story TEXT NOT NULL,
+    source_id TEXT NOT NULL,
+    title TEXT NOT NULL,
+    url TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS regional_summaries (
+    digest_date TEXT NOT NULL,
+    region TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    PRIMARY KEY (digest_date, region)
+);
+
+CREATE TABLE IF NOT EXISTS digest_broadcasts (
+    broadcast_id TEXT PRIMARY KEY,
+    digest_date TEXT NOT NULL,
+    audience_id TEXT,
+    recipients INTEGER,
+    sent_at DATETIME DEFAULT (datetime('now', 'utc'))
 );
 
 CREATE TABLE IF NOT EXISTS dedup_log (
@@ -190,22 +294,121 @@ CREATE TABLE IF NOT EXISTS dedup_log (
     action TEXT NOT NULL
 );
 
+CREATE TABLE IF NOT EXISTS digest_preview (
+    slot INTEGER PRIMARY KEY CHECK (slot = 1),
+    date TEXT NOT NULL,
+    html TEXT NOT NULL,
+    created_at DATETIME DEFAULT (datetime('now', 'utc'))
+);
+
+CREATE TABLE IF NOT EXISTS run_checkpoints (
+    stage TEXT PRIMARY KEY,
+    dry_run INTEGER NOT NULL,
+    state TEXT NOT NULL,
+    completed_at DATETIME DEFAULT (datetime('now', 'utc'))
+);
+
+CREATE TABLE IF NOT EXISTS run_stages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    run_started_at DATETIME NOT NULL,
+    run_id INTEGER,
+    dry_run INTEGER NOT NULL,
+    stage TEXT NOT NULL,
+    started_at DATETIME NOT NULL,
+    duration_ms INTEGER NOT NULL,
+    items INTEGER,
+    error TEXT
+);
+
+CREATE TABLE IF NOT EXISTS images (
+    hash TEXT PRIMARY KEY,
+    content_type TEXT NOT NULL,
+    data BLOB NOT NULL,
+    created_at DATETIME DEFAULT (datetime('now', 'utc'))
+);
+
 CREATE INDEX IF NOT EXISTS idx_shown_narratives_date ON shown_narratives(shown_at);
 CREATE INDEX IF NOT EXISTS idx_shown_narratives_source ON shown_narratives(source_id);
 CREATE INDEX IF NOT EXISTS idx_digest_runs_date ON digest_runs(run_at);
-CREATE INDEX IF NOT EXISTS idx_source_health_source ON source_health(source_id, recorded_at);
 CREATE INDEX IF NOT EXISTS idx_digests_date ON digests(date);
+CREATE INDEX IF NOT EXISTS idx_narratives_digest ON narratives(digest_date, tier, position);
+CREATE INDEX IF NOT EXISTS idx_narratives_topic ON narratives(topic);
+CREATE INDEX IF NOT EXISTS idx_story_articles_digest ON story_articles(digest_date, story);
 CREATE INDEX IF NOT EXISTS idx_dedup_log_date ON dedup_log(logged_at);
+CREATE INDEX IF NOT EXISTS idx_digest_broadcasts_date ON digest_broadcasts(digest_date);
+CREATE INDEX IF NOT EXISTS idx_run_stages_run ON run_stages(run_started_at);
 """
 
+# Telemetry tables, created in the telemetry database when TELEMETRY_DB is set
+TELEMETRY_SCHEMA = """
+CREATE TABLE IF NOT EXISTS {schema}.source_health (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    source_id TEXT NOT NULL,
+    success INTEGER NOT NULL,
+    error_message TEXT,
+    fetch_ms INTEGER,
+    new_articles INTEGER,
+    filtered_articles INTEGER,
+    recorded_at DATETIME DEFAULT (datetime('now', 'utc'))
+);
+
+CREATE INDEX IF NOT EXISTS {schema}.idx_source_health_source ON source_health(source_id, recorded_at);
+"""
+
+
+def connect_db() -> sqlite3.Connection:
+    """Open the database (keyed when DATABASE_KEY is set), attaching the telemetry database when TELEMETRY_DB is set.
+
+    Unqualified table names resolve in the main database first, then the attached one,
+    so queries don't need to know where a table lives. digest-server moves any telemetry
+    tables left in the main database on startup.
+    """
+    conn = sqlite3.connect(DB_PATH)
+    key = database_key()
+    if key:
+        # PRAGMA takes no parameters; quote the key as a string literal
+        conn.execute("PRAGMA key = '{}'".format(key.replace("'", "''")))
+        if conn.execute("PRAGMA cipher_version").fetchone() is None:
+            conn.close()
+            raise sqlite3.DatabaseError("DATABASE_KEY is set, but Python's sqlite3 isn't built with SQLCipher")
+    if TELEMETRY_DB_PATH:
+        if key:
+            conn.execute("ATTACH DATABASE ? AS telemetry KEY ?", (str(TELEMETRY_DB_PATH), key))
+        else:
+            conn.execute("ATTACH DATABASE ? AS telemetry", (str(TELEMETRY_DB_PATH),))
+    return conn
+
+
+def database_key() -> str | None:
+    """SQLCipher key from DATABASE_KEY, or the first line of DATABASE_KEY_FILE (as digest-server reads it)."""
+    if key := os.environ.get("DATABASE_KEY"):
+        return key
+    if path := os.environ.get("DATABASE_KEY_FILE"):
+        lines = Path(path).read_text().splitlines()
+        return lines[0].rstrip() if lines else None
+    return None
+
+
+# Columns added to digest_runs after the initial schema (name -> SQL type)
+RUN_USAGE_COLUMNS = {
+    "duration_ms": "INTEGER",
+    "input_tokens": "INTEGER",
+    "output_tokens": "INTEGER",
+    "cost_usd": "REAL",
+    "prompt_version": "TEXT",
+    "budget_usd": "REAL",
+    "budget_tokens": "INTEGER",
+}
+
 
 def init_db():
     """Initialize or migrate database."""
     DATA_DIR.mkdir(exist_ok=True)
 
-    with sqlite3.connect(DB_PATH) as conn:
+    with connect_db() as conn:
         # Create tables if they don't exist
         conn.executescript(DB_SCHEMA)
+        conn.executescript(TELEMETRY_SCHEMA.format(schema="telemetry" if TELEMETRY_DB_PATH else "main"))
 
         # Migrate: add articles_emailed if missing (old schema had different columns)
         cursor = conn.execute("PRAGMA table_info(digest_runs)")
@@ -221,6 +424,18 @@ def init_db():
                 conn.rollback()
                 raise
 
+        # Migrate: add run duration and LLM usage columns if missing
+        for column, column_type in RUN_USAGE_COLUMNS.items():
+            if column not in columns:
+                try:
+                    log(f"Migrating database: adding {column} column to digest_runs...")
+                    conn.execute(f"ALTER TABLE digest_runs ADD COLUMN {column} {column_type}")
+                    conn.commit()
+                except sqlite3.Error as e:
+                    log(f"Migration failed: {e}", "ERROR")
+                    conn.rollback()
+                    raise
+
         # Migrate: add source_id to shown_narratives if missing
         cursor = conn.execute("PRAGMA table_info(shown_narratives)")
         columns = {row[1] for row in cursor.fetchall()}
@@ -235,6 +450,74 @@ def init_db():
                 conn.rollback()
                 raise
 
+        if "topic" not in columns:
+            try:
+                log("Migrating database: adding topic column to shown_narratives...")
+                conn.execute("ALTER TABLE shown_narratives ADD COLUMN topic TEXT")
+                conn.commit()
+            except sqlite3.Error as e:
+                log(f"Migration failed: {e}", "ERROR")
+                conn.rollback()
+                raise
+
+        # Migrate: add fetch_ms to source_health if missing
+        cursor = conn.execute("PRAGMA table_info(source_health)")
+        columns = {row[1] for row in cursor.fetchall()}
+
+        if "fetch_ms" not in columns:
+            try:
+                log("Migrating database: adding fetch_ms column to source_health...")
+                conn.execute("ALTER TABLE source_health ADD COLUMN fetch_ms INTEGER")
+                conn.commit()
+            except sqlite3.Error as e:
+                log(f"Migration failed: {e}", "ERROR")
+                conn.rollback()
+                raise
+
+        if "new_articles" not in columns:
+            try:
+                log("Migrating database: adding new_articles column to source_health...")
+                conn.execute("ALTER TABLE source_health ADD COLUMN new_articles INTEGER")
+                conn.commit()
+            except sqlite3.Error as e:
+                log(f"Migration failed: {e}", "ERROR")
+                conn.rollback()
+                raise
+
+        if "filtered_articles" not in columns:
+            try:
+                log("Migrating database: adding filtered_articles column to source_health...")
+                conn.execute("ALTER TABLE source_health ADD COLUMN filtered_articles INTEGER")
+                conn.commit()
+            except sqlite3.Error as e:
+                log(f"Migration failed: {e}", "ERROR")
+                conn.rollback()
+                raise
+
+        # Migrate: add story to narratives (the pipeline's story the narrative was written from)
+        cursor = conn.execute("PRAGMA table_info(narratives)")
+        columns = [row[1] for row in cursor.fetchall()]
+        if "story" not in columns:
+            try:
+                log("Migrating database: adding story column to narratives...")
+                conn.execute("ALTER TABLE narratives ADD COLUMN story TEXT")
+                conn.commit()
+            except sqlite3.Error as e:
+                log(f"Migration failed: {e}", "ERROR")
+                conn.rollback()
+                raise
+
+        # Migrate: add image to narratives (hash of the picture digest-pipeline stored in images)
+        if "image" not in columns:
+            try:
+                log("Migrating database: adding image column to narratives...")
+                conn.execute("ALTER TABLE narratives ADD COLUMN image TEXT")
+                conn.commit()
+            except sqlite3.Error as e:
+                log(f"Migration failed: {e}", "ERROR")
+                conn.rollback()
+                raise
+
         # Migrate: remove old unused columns by ignoring them (SQLite can't drop columns easily)
         # Old columns (timezone, narratives_presented) will just be ignored
 
@@ -244,7 +527,7 @@ def get_last_run_time() -> datetime | None:
     if not DB_PATH.exists():
         return None
     try:
-        with sqlite3.connect(DB_PATH) as conn:
+        with connect_db() as conn:
             cursor = conn.execute("SELECT MAX(run_at) FROM digest_runs")
             result = cursor.fetchone()[0]
             if result:
@@ -254,13 +537,33 @@ def get_last_run_time() -> datetime | None:
     return None
 
 
-def record_run(articles_fetched: int, articles_emailed: int = 0) -> int | None:
-    """Record a successful digest run. Returns run ID or None on error."""
+def record_run(
+    articles_fetched: int, articles_emailed: int = 0, duration_ms: int | None = None, usage: dict | None = None
+) -> int | None:
+    """Record a successful digest run. Returns run ID or None on error.
+
+    usage is the summed Claude usage (input_tokens, output_tokens, cost_usd) when Claude ran,
+    with the prompt_version that selected the stories and the budget_usd and budget_tokens it was held to.
+    """
+    usage = usage or {}
     try:
-        with sqlite3.connect(DB_PATH) as conn:
+        with connect_db() as conn:
             cursor = conn.execute(
-                "INSERT INTO digest_runs (articles_fetched, articles_emailed) VALUES (?, ?)",
-                (articles_fetched, articles_emailed),
+                """INSERT INTO digest_runs
+                   (articles_fetched, articles_emailed, duration_ms, input_tokens, output_tokens, cost_usd,
+                    prompt_version, budget_usd, budget_tokens)
+                   VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)""",
+                (
+                    articles_fetched,
+                    articles_emailed,
+                    duration_ms,
+                    usage.get("input_tokens"),
+                    usage.get("output_tokens"),
+                    usage.get("cost_usd"),
+                    usage.get("prompt_version"),
+                    usage.get("budget_usd"),
+                    usage.get("budget_tokens"),
+                ),
             )
             run_id = cursor.lastrowid
         log(f"Recorded run: {articles_fetched} fetched, {articles_emailed} emailed")
@@ -270,32 +573,291 @@ def record_run(articles_fetched: int, articles_emailed: int = 0) -> int | None:
         return None
 
 
-def save_digest(digest_path: Path):
-    """Save digest HTML to database for web serving."""
-    # Extract date from filename (digest-YYYY-MM-DD*.html -> YYYY-MM-DD)
+class StopRequested(Exception):
+    """digest-server is shutting down and asked this run to stop before its next stage."""
+
+
+def stop_requested() -> bool:
+    """Whether the STOP_FILE digest-server passes to the runs it starts has been created."""
+    stop_file = os.environ.get("STOP_FILE")
+    return bool(stop_file) and Path(stop_file).exists()
+
+
+@contextmanager
+def timed_stage(run_started: str, name: str, dry_run: bool):
+    """Log "Stage: <name>", run the stage, and record in run_stages how long it took, how many items
+    it handled (set as the yielded dict's "items"), and its error if it raised one. Raises StopRequested
+    instead of starting the stage if digest-server is shutting down."""
+    if stop_requested():
+        raise StopRequested(f"Stopping before the {name} stage, as digest-server is shutting down")
+    log(f"Stage: {name}")
+    progress = {"items": None}
+    started_at = datetime.now(UTC).strftime("%Y-%m-%d %H:%M:%S")
+    start = time.monotonic()
+    error = None
+    try:
+        yield progress
+    except BaseException as e:
+        error = f"{type(e).__name__}: {e}"
+        raise
+    finally:
+        duration_ms = round((time.monotonic() - start) * 1000)
+        try:
+            with connect_db() as conn:
+                conn.execute(
+                    """INSERT INTO run_stages
+                       (run_started_at, dry_run, stage, started_at, duration_ms, items, error)
+                       VALUES (?, ?, ?, ?, ?, ?, ?)""",
+                    (run_started, int(dry_run), name, started_at, duration_ms, progress["items"], error),
+                )
+        except sqlite3.Error as e:
+            log(f"DB error recording the {name} stage: {e}", "ERROR")
+
+
+def link_stages(run_started: str, run_id: int | None):
+    """Tie a run's stages to the digest_runs row it was recorded as."""
+    if run_id is None:
+        return
+    try:
+        with connect_db() as conn:
+            conn.execute("UPDATE run_stages SET run_id = ? WHERE run_started_at = ?", (run_id, run_started))
+    except sqlite3.Error as e:
+        log(f"DB error linking run stages: {e}", "ERROR")
+
+
+def save_checkpoint(stage: str, dry_run: bool, state: dict):
+    """Record that a stage of this run completed, with what later stages need from it."""
+    with connect_db() as conn:
+        conn.execute(
+            "INSERT OR REPLACE INTO run_checkpoints (stage, dry_run, state, completed_at) VALUES (?, ?, ?, datetime('now'))",
+            (stage, int(dry_run), json.dumps(state)),
+        )
+
+
+def load_checkpoints(dry_run: bool) -> dict[str, dict]:
+    """The stages an interrupted run completed, with their state, if it was the same kind of run (dry or not)
+    and its last stage completed within CHECKPOINT_MAX_AGE_HOURS; otherwise none."""
+    with connect_db() as conn:
+        rows = conn.execute(
+            """SELECT stage, dry_run, state, completed_at,
+                      (julianday('now') - julianday(completed_at)) * 24
+               FROM run_checkpoints ORDER BY completed_at, rowid"""
+        ).fetchall()
+    if not rows or any(bool(row[1]) != dry_run for row in rows) or rows[-1][4] > CHECKPOINT_MAX_AGE_HOURS:
+        return {}
+    return {stage: {**json.loads(state), "completed_at": completed_at} for stage, _, state, completed_at, _ in rows}
+
+
+def clear_checkpoints():
+    """Forget the last run's checkpoints, once it has finished or is being started over."""
+    with connect_db() as conn:
+        conn.execute("DELETE FROM run_checkpoints")
+
+
+def digest_date_from_path(digest_path: Path) -> str:
+    """Extract date from filename (digest-YYYY-MM-DD*.html -> YYYY-MM-DD), defaulting to today."""
     match = re.search(r"(\d{4}-\d{2}-\d{2})", digest_path.stem)
     if match:
-        date_str = match.group(1)
-    else:
-        date_str = datetime.now(UTC).strftime("%Y-%m-%d")
-        log(f"Could not extract date from '{digest_path.stem}', using {date_str}", "WARN")
+        return match.group(1)
+    date_str = datetime.now(UTC).strftime("%Y-%m-%d")
+    log(f"Could not extract date from '{digest_path.stem}', using {date_str}", "WARN")
+    return date_str
+
+
+def compressed_copy_matches(conn: sqlite3.Connection, date_str: str, html: str) -> bool:
+    """Whether digest-server already holds this HTML compressed, by the hash it records when compressing.
+
+    Re-saving it would only record a revision of the same digest.
+    """
+    columns = {row[1] for row in conn.execute("PRAGMA table_info(digests)")}
+    if "html_sha256" not in columns:
+        return False
+    row = conn.execute("SELECT html_sha256 FROM digests WHERE date = ? AND html = ''", (date_str,)).fetchone()
+    return row is not None and row[0] == hashlib.sha256(html.encode()).hexdigest()
+
+
+def save_digest(digest_path: Path):
+    """Save digest HTML, plus its narratives when rendered this run, to database for web serving."""
+    date_str = digest_date_from_path(digest_path)
 
     html_content = digest_path.read_text()
+    structured = read_narratives()
 
     try:
-        with sqlite3.connect(DB_PATH) as conn:
-            conn.execute("INSERT OR REPLACE INTO digests (date, html) VALUES (?, ?)", (date_str, html_content))
+        with connect_db() as conn:
+            if not compressed_copy_matches(conn, date_str, html_content):
+                conn.execute("INSERT OR REPLACE INTO digests (date, html) VALUES (?, ?)", (date_str, html_content))
+            if structured:
+                save_narratives(conn, date_str, structured["narratives"], structured["regional_summary"])
         log(f"Saved digest to database: {date_str}")
     except sqlite3.Error as e:
         log(f"DB error saving digest: {e}", "ERROR")
 
 
+def save_preview(digest_path: Path):
+    """Put a dry run's digest in the preview slot (digest-server's /admin/preview), replacing the last one."""
+    date_str = digest_date_from_path(digest_path)
+    try:
+        with connect_db() as conn:
+            conn.execute(
+                "INSERT OR REPLACE INTO digest_preview (slot, date, html, created_at) VALUES (1, ?, ?, datetime('now'))",
+                (date_str, digest_path.read_text()),
+            )
+        log(f"Saved preview of {date_str} (not published)")
+    except sqlite3.Error as e:
+        log(f"DB error saving preview: {e}", "ERROR")
+
+
+def save_narratives(conn: sqlite3.Connection, digest_date: str, narratives: list[dict], regional_summary: dict):
+    """Replace a digest's structured narratives, the articles of their stories, and regional summaries."""
+    conn.execute("DELETE FROM narratives WHERE digest_date = ?", (digest_date,))
+    conn.execute("DELETE FROM story_articles WHERE digest_date = ?", (digest_date,))
+    conn.execute("DELETE FROM regional_summaries WHERE digest_date = ?", (digest_date,))
+    conn.executemany(
+        "INSERT INTO narratives (digest_date, tier, cluster, position, headline, summary, why_it_matters, topic, "
+        "sources, reporting_varies, story, image) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        [
+            (
+                digest_date,
+                n["tier"],
+                n["cluster"],
+                n["position"],
+                n["headline"],
+                n["summary"],
+                n["why_it_matters"],
+                n["topic"],
+                json.dumps(n["sources"]),
+                json.dumps(n["reporting_varies"]),
+                n.get("story"),
+                n.get("image"),
+            )
+            for n in narratives
+        ],
+    )
+    stories = {n["story"]: n.get("story_articles", []) for n in narratives if n.get("story")}
+    conn.executemany(
+        "INSERT INTO story_articles (digest_date, story, source_id, title, url) VALUES (?, ?, ?, ?, ?)",
+        [
+            (digest_date, story, a["source_id"], a["title"], a["url"])
+            for story, articles in stories.items()
+            for a in articles
+        ],
+    )
+    conn.executemany(
+        "INSERT INTO regional_summaries (digest_date, region, summary) VALUES (?, ?, ?)",
+        [(digest_date, region, text) for region, text in regional_summary.items() if text],
+    )
+
+
+def load_narratives(conn: sqlite3.Connection, digest_date: str) -> tuple[list[dict], dict]:
+    """Load a digest's narratives (in display order, with their stories' articles) and regional summaries,
+    ready for render_digest()."""
+    cursor = conn.cursor()
+    cursor.row_factory = sqlite3.Row
+    rows = cursor.execute(
+        "SELECT tier, cluster, position, headline, summary, why_it_matters, topic, sources, reporting_varies, story, "
+        "image FROM narratives WHERE digest_date = ? ORDER BY id",
+        (digest_date,),
+    ).fetchall()
+    story_articles: dict[str, list[dict]] = {}
+    for article in cursor.execute(
+        "SELECT story, source_id, title, url FROM story_articles WHERE digest_date = ? ORDER BY rowid", (digest_date,)
+    ).fetchall():
+        story_articles.setdefault(article["story"], []).append(
+            {"source_id": article["source_id"], "title": article["title"], "url": article["url"]}
+        )
+    narratives = [
+        {
+            **dict(row),
+            "sources": json.loads(row["sources"]),
+            "reporting_varies": json.loads(row["reporting_varies"]),
+            "story_articles": story_articles.get(row["story"], []),
+        }
+        for row in rows
+    ]
+    regional_summary = dict(
+        conn.execute(
+            "SELECT region, summary FROM regional_summaries WHERE digest_date = ?", (digest_date,)
+        ).fetchall()
+    )
+    return narratives, regional_summary
+
+
+def record_broadcast(broadcast_id: str, digest_date: str, audience_id: str, recipients: int):
+    """Map a Resend broadcast to its digest so webhook opens/clicks can be attributed."""
+    try:
+        with connect_db() as conn:
+            conn.execute(
+                "INSERT OR REPLACE INTO digest_broadcasts (broadcast_id, digest_date, audience_id, recipients) "
+                "VALUES (?, ?, ?, ?)",
+                (broadcast_id, digest_date, audience_id, recipients),
+            )
+    except sqlite3.Error as e:
+        log(f"DB error recording broadcast: {e}", "ERROR")
+
+
+def image_type(data: bytes) -> str | None:
+    """Content type of a JPEG, PNG, GIF, WebP, or AVIF image, sniffed from its leading bytes."""
+    if data.startswith(b"\xff\xd8\xff"):
+        return "image/jpeg"
+    if data.startswith(b"\x89PNG\r\n\x1a\n"):
+        return "image/png"
+    if data[:6] in (b"GIF87a", b"GIF89a"):
+        return "image/gif"
+    if data[:4] == b"RIFF" and data[8:12] == b"WEBP":
+        return "image/webp"
+    if data[4:12] in (b"ftypavif", b"ftypavis"):
+        return "image/avif"
+    return None
+
+
+def store_image(conn: sqlite3.Connection, data: bytes, content_type: str) -> str | None:
+    """Store an image under its SHA-256 hash and return the hash.
+
+    Stores nothing and returns None when the image is over IMAGE_MAX_BYTES, or its
+    bytes aren't a supported format matching the declared content type.
+    """
+    declared = content_type.split(";")[0].strip().lower()
+    if len(data) > IMAGE_MAX_BYTES or image_type(data) != declared:
+        return None
+    image_hash = hashlib.sha256(data).hexdigest()
+    conn.execute(
+        "INSERT OR IGNORE INTO images (hash, content_type, data) VALUES (?, ?, ?)", (image_hash, declared, data)
+    )
+    return image_hash
+
+
+def host_image(url: str, base_url: str, timeout: int = 10) -> str | None:
+    """Download an image into the database, returning its digest-server URL (None if unusable)."""
+    if not is_safe_url(url):
+        return None
+    try:
+        req = urllib.request.Request(url, headers={"User-Agent": "Mozilla/5.0"})
+        with urllib.request.urlopen(req, timeout=timeout) as response:  # nosec B310
+            content_type = response.headers.get("Content-Type", "")
+            # One byte over the limit is enough to reject it
+            data = response.read(IMAGE_MAX_BYTES + 1)
+    except (urllib.error.URLError, TimeoutError, OSError) as e:
+        log(f"Could not fetch image {url}: {e}", "WARN")
+        return None
+    try:
+        with connect_db() as conn:
+            image_hash = store_image(conn, data, content_type)
+    except sqlite3.Error as e:
+        log(f"DB error storing image: {e}", "ERROR")
+        return None
+    if image_hash is None:
+        log(f"Skipped image {url}: not a supported image under {IMAGE_MAX_BYTES} bytes", "WARN")
+        return None
+    return f"{base_url.rstrip('/')}/img/{image_hash}"
+
+
 def get_previous_headlines(days: int = 7) -> list[dict]:
     """Get headlines shown in the last N days for deduplication."""
     if not DB_PATH.exists():
         return []
     try:
-        with sqlite3.connect(DB_PATH) as conn:
+        with connect_db() as conn:
             cursor = conn.execute(
                 """
                 SELECT headline, tier, date(shown_at) as date
@@ -324,23 +886,30 @@ def record_shown_headlines(headlines: list[dict]):
         log(f"First item: {repr(headlines[0][:100]) if isinstance(headlines[0], str) else repr(headlines[0])}", "ERROR")
         return
     try:
-        with sqlite3.connect(DB_PATH) as conn:
+        with connect_db() as conn:
             conn.executemany(
-                "INSERT INTO shown_narratives (headline, tier, source_id) VALUES (?, ?, ?)",
-                [(h.get("headline", ""), h.get("tier", ""), h.get("source_id")) for h in headlines],
+                "INSERT INTO shown_narratives (headline, tier, source_id, topic) VALUES (?, ?, ?, ?)",
+                [(h.get("headline", ""), h.get("tier", ""), h.get("source_id"), h.get("topic")) for h in headlines],
             )
         log(f"Saved {len(headlines)} headlines to dedup history")
     except sqlite3.Error as e:
         log(f"DB error recording headlines: {e}", "ERROR")
 
 
-def record_source_health(results: list[tuple[str, bool, str | None]]):
-    """Record source fetch results. Each tuple is (source_id, success, error_message)."""
+def record_source_health(results: list[tuple[str, bool, str | None, int, int | None]]):
+    """Record source fetch results.
+
+    Each tuple is (source_id, success, error_message, fetch_ms, new_articles); new_articles is None for failed fetches.
+    """
     if not results:
         return
     try:
-        with sqlite3.connect(DB_PATH) as conn:
-            conn.executemany("INSERT INTO source_health (source_id, success, error_message) VALUES (?, ?, ?)", results)
+        with connect_db() as conn:
+            conn.executemany(
+                "INSERT INTO source_health (source_id, success, error_message, fetch_ms, new_articles)"
+                " VALUES (?, ?, ?, ?, ?)",
+                results,
+            )
     except sqlite3.Error as e:
         log(f"DB error recording source health for {len(results)} sources: {e}", "ERROR")
 
@@ -350,7 +919,7 @@ def get_consecutive_failures(source_id: str, limit: int = 10) -> int:
     if not DB_PATH.exists():
         return 0
     try:
-        with sqlite3.connect(DB_PATH) as conn:
+        with connect_db() as conn:
             cursor = conn.execute(
                 """
                 SELECT success FROM source_health
@@ -376,7 +945,7 @@ def get_failing_sources(min_consecutive: int = 3) -> list[tuple[str, int]]:
     if not DB_PATH.exists():
         return []
     try:
-        with sqlite3.connect(DB_PATH) as conn:
+        with connect_db() as conn:
             cursor = conn.execute("""
                 SELECT DISTINCT source_id FROM source_health
                 WHERE recorded_at > datetime('now', '-7 days')
@@ -400,7 +969,7 @@ def log_dedup_action(
 ):
     """Log a dedup decision to the database."""
     try:
-        with sqlite3.connect(DB_PATH) as conn:
+        with connect_db() as conn:
             conn.execute(
                 """INSERT INTO dedup_log
                    (article_title, article_source_id, matched_headline, similarity, threshold, action)
@@ -646,6 +1215,8 @@ def fetch_source(source: dict, timeout: int = 15) -> tuple[str, list[dict], str
     """Fetch single RSS source with retry logic. Returns (source_id, articles, error_or_none)."""
     source_id = source["id"]
     last_error = None
+    if source["url"].startswith("imaps://"):
+        return source_id, [], "Newsletter mailboxes are read by digest-pipeline fetch"
 
     for attempt in range(MAX_RETRIES):
         try:
@@ -701,6 +1272,46 @@ def fetch_source(source: dict, timeout: int = 15) -> tuple[str, list[dict], str
     return source_id, [], f"Failed after {MAX_RETRIES} retries: {error_msg}"
 
 
+def item_fingerprint(articles: list[dict]) -> str | None:
+    """Identify a feed's current contents, so a change means new items appeared.
+
+    Uses the newest publish date when entries are dated, else a hash of the entry URLs.
+    """
+    if not articles:
+        return None
+    dates = [d for a in articles if (d := parse_date(a.get("published")))]
+    if dates:
+        return max(dates).isoformat()
+    urls = "\n".join(sorted(a["url"] for a in articles))
+    return hashlib.sha256(urls.encode()).hexdigest()[:16]
+
+
+def record_source_activity(fingerprints: dict[str, str]):
+    """Bump last_new_item_at for sources whose feed fingerprint changed since the last fetch."""
+    if not fingerprints:
+        return
+    try:
+        with connect_db() as conn:
+            conn.executemany(
+                """INSERT INTO source_activity (source_id, fingerprint, last_new_item_at)
+                   VALUES (?, ?, datetime('now'))
+                   ON CONFLICT(source_id) DO UPDATE SET
+                       last_new_item_at = CASE WHEN fingerprint != excluded.fingerprint
+                           THEN excluded.last_new_item_at ELSE last_new_item_at END,
+                       fingerprint = excluded.fingerprint""",
+                fingerprints.items(),
+            )
+    except sqlite3.Error as e:
+        log(f"DB error recording source activity for {len(fingerprints)} sources: {e}", "ERROR")
+
+
+def fetch_source_timed(source: dict) -> tuple[str, list[dict], str | None, int]:
+    """fetch_source plus wall-clock duration in milliseconds (including retries)."""
+    start = time.monotonic()
+    source_id, articles, error = fetch_source(source)
+    return source_id, articles, error, round((time.monotonic() - start) * 1000)
+
+
 def fetch_feeds(sources: list[dict]) -> tuple[int, int]:
     """Fetch all RSS feeds in parallel. Returns (total_articles, failed_count)."""
     log(f"Fetching {len(sources)} RSS feeds...")
@@ -712,16 +1323,17 @@ def fetch_feeds(sources: list[dict]) -> tuple[int, int]:
         f.unlink()
 
     results = {}
-    health_records = []  # (source_id, success, error_message)
+    health_records = []  # (source_id, success, error_message, fetch_ms)
     with ThreadPoolExecutor(max_workers=10) as executor:
-        futures = {executor.submit(fetch_source, s): s for s in sources}
+        futures = {executor.submit(fetch_source_timed, s): s for s in sources}
         for future in as_completed(futures):
-            source_id, articles, error = future.result()
+            source_id, articles, error, fetch_ms = future.result()
             results[source_id] = articles
-            health_records.append((source_id, error is None, error))
+            health_records.append((source_id, error is None, error, fetch_ms))
 
-    # Record health to DB
-    record_source_health(health_records)
+    # Record when each live feed last had something new
+    fingerprints = {sid: fp for sid, articles in results.items() if (fp := item_fingerprint(articles))}
+    record_source_activity(fingerprints)
 
     # Filter by date and save, tracking per-source counts
     total_kept = 0
@@ -741,6 +1353,15 @@ def fetch_feeds(sources: list[dict]) -> tuple[int, int]:
         total_fetched += fetched_count
         per_source_counts.append((source_id, fetched_count, kept_count))
 
+    # Record health to DB, with how many new articles each successful fetch brought
+    kept_by_source = {sid: kept for sid, _, kept in per_source_counts}
+    record_source_health(
+        [
+            (sid, success, err, fetch_ms, kept_by_source.get(sid) if success else None)
+            for sid, success, err, fetch_ms in health_records
+        ]
+    )
+
     # Per-source breakdown (show sources with articles, sorted by kept desc)
     sources_with_articles = [(sid, f, k) for sid, f, k in per_source_counts if f > 0]
     if sources_with_articles:
@@ -751,7 +1372,7 @@ def fetch_feeds(sources: list[dict]) -> tuple[int, int]:
             print(f"  [{sid}] {kept}/{fetched}", flush=True)
 
     # Summary
-    failed_this_run = [(sid, err) for sid, success, err in health_records if not success]
+    failed_this_run = [(sid, err) for sid, success, err, _ in health_records if not success]
     succeeded = len(sources) - len(failed_this_run)
     log(f"Fetched {total_kept}/{total_fetched} articles from {succeeded}/{len(sources)} sources")
 
@@ -769,6 +1390,18 @@ def fetch_feeds(sources: list[dict]) -> tuple[int, int]:
     return total_kept, len(failed_this_run)
 
 
+def count_fetched(sources: list[dict]) -> int:
+    """Count the articles digest-pipeline left in FETCHED_DIR (for --skip-fetch)."""
+    total = 0
+    for source in sources:
+        source_file = FETCHED_DIR / f"{source['id']}.json"
+        if source_file.exists():
+            with open(source_file) as f:
+                total += len(json.load(f))
+    log(f"Using {total} articles already fetched by digest-pipeline")
+    return total
+
+
 # =============================================================================
 # Digest Generation
 # =============================================================================
@@ -872,6 +1505,29 @@ def prepare_for_email(html_content: str) -> str:
     return html_content
 
 
+def tracked_link_id(digest_date: str, href: str) -> str:
+    """Short, stable ID for a link in a digest (must match digest-server's links::link_id)."""
+    digest = hashlib.sha256(href.encode()).digest()
+    return digest_date.replace("-", "") + base64.urlsafe_b64encode(digest).decode()[:8]
+
+
+def track_links(html_content: str, digest_date: str, base_url: str) -> str:
+    """Route outbound links through the digest server's /r/{id} click redirect.
+
+    The server resolves IDs against the stored digest, so nothing is registered here.
+    Links back to the digest site itself are left alone.
+    """
+    base_url = base_url.rstrip("/")
+
+    def rewrite(match):
+        href = match.group(1)
+        if href.startswith(base_url):
+            return match.group(0)
+        return f'href="{base_url}/r/{tracked_link_id(digest_date, href)}"'
+
+    return re.sub(r'href="(https?://[^"]+)"', rewrite, html_content)
+
+
 def inline_styles(html: str) -> str:
     """Inline CSS styles for email compatibility using premailer."""
     try:
@@ -906,6 +1562,30 @@ REGION_CONFIG = {
 # Region display order (Americas first - where subscribers are)
 REGION_ORDER = ["americas", "europe", "asia_pacific", "middle_east_africa", "tech"]
 
+# Biases with a color of their own in digest.css
+BIASES = {"left", "center-left", "center", "center-right", "right"}
+
+# Languages digest-pipeline tells articles apart by (its language.rs), by ISO 639-1 code
+LANGUAGE_NAMES = {
+    "ar": "Arabic",
+    "de": "German",
+    "el": "Greek",
+    "es": "Spanish",
+    "fa": "Persian",
+    "fr": "French",
+    "he": "Hebrew",
+    "hi": "Hindi",
+    "it": "Italian",
+    "ja": "Japanese",
+    "ko": "Korean",
+    "nl": "Dutch",
+    "pt": "Portuguese",
+    "ru": "Russian",
+    "th": "Thai",
+    "uk": "Ukrainian",
+    "zh": "Chinese",
+}
+
 
 def markdown_to_html(text: str) -> str:
     """Convert markdown links [text](url) to HTML <a> tags."""
@@ -920,26 +1600,49 @@ def markdown_to_html(text: str) -> str:
     return re.sub(r"\[([^\]]+)\]\(([^)]+)\)", replace_link, text)
 
 
+def source_chips(source: dict) -> str:
+    """Chips for a source's bias, owner and region, and its article's language when digest-pipeline found
+    it isn't English, each led by a space."""
+    chips = []
+    bias = source.get("bias") or ""
+    if bias:
+        css_class = f"chip bias-{bias}" if bias in BIASES else "chip"
+        chips.append((css_class, bias))
+    language = LANGUAGE_NAMES.get(source.get("language") or "")
+    for text in (source.get("owner"), source.get("region"), language and f"in {language}"):
+        if text:
+            chips.append(("chip", text))
+    return "".join(f' <span class="{css_class}">{html.escape(text)}</span>' for css_class, text in chips)
+
+
 def render_article(article: dict, include_reporting_varies: bool = True) -> str:
     """Render a single article (must_know or should_know) to HTML."""
     headline = html.escape(article.get("headline", ""))
     summary = html.escape(article.get("summary", ""))
     why = html.escape(article.get("why_it_matters", ""))
 
-    # Sources line
+    # Sources line, each source followed by chips for what's known of it
     sources_html = []
     for src in article.get("sources", []):
         name = html.escape(src.get("name", ""))
         url = src.get("url", "")
-        bias = html.escape(src.get("bias", ""))
         if name and url and is_safe_url(url):
-            sources_html.append(f'<a href="{html.escape(url)}">{name}</a> ({bias})')
+            sources_html.append(f'<a href="{html.escape(url)}">{name}</a>{source_chips(src)}')
     sources_line = " · ".join(sources_html)
 
     # Build article HTML
     parts = [
         "    <article>",
         f"      <h3>{headline}</h3>",
+    ]
+
+    # Its picture, served by digest-server, which mail needs the digest's domain to reach
+    image = article.get("image")
+    digest_domain = os.environ.get("DIGEST_DOMAIN", "")
+    if image and digest_domain and re.fullmatch(r"[0-9a-f]{64}", image):
+        parts.append(f'      <img class="thumbnail" src="https://{html.escape(digest_domain)}/img/{image}" alt="">')
+
+    parts += [
         f"      <p>{summary}</p>",
         f'      <p class="why"><strong>Why it matters:</strong> {why}</p>',
     ]
@@ -966,9 +1669,9 @@ def render_article(article: dict, include_reporting_varies: bool = True) -> str:
 
 
 def render_signal(item: dict) -> str:
-    """Render a quick signal or below_fold item to HTML."""
+    """Render a signal narrative to HTML."""
     headline = html.escape(item.get("headline", ""))
-    src = item.get("source", {})
+    src = (item.get("sources") or [{}])[0]
     name = html.escape(src.get("name", ""))
     url = src.get("url", "")
     if url and is_safe_url(url):
@@ -976,15 +1679,97 @@ def render_signal(item: dict) -> str:
     return f'      <p class="signal">{headline} — {name}</p>'
 
 
-def render_digest(selections: dict) -> str:
-    """Render selections.json to complete HTML string."""
+def load_stories() -> dict[str, list[dict]]:
+    """Group the articles digest-pipeline left in FETCHED_DIR by the story it put them in."""
+    stories: dict[str, list[dict]] = {}
+    for path in sorted(FETCHED_DIR.glob("*.json")):
+        try:
+            articles = json.loads(path.read_text())
+        except (OSError, json.JSONDecodeError):
+            continue
+        for a in articles:
+            if a.get("story"):
+                stories.setdefault(a["story"], []).append(
+                    {"source_id": path.stem, "title": a.get("title", ""), "url": a.get("url", "")}
+                )
+    return stories
+
+
+def story_of(sources: list[dict], stories: dict[str, list[dict]]) -> str | None:
+    """The story whose articles a narrative cites most, if it cites any."""
+    urls = {s.get("url") for s in sources if isinstance(s, dict) and s.get("url")}
+    cited = {story: len(urls & {a["url"] for a in articles}) for story, articles in stories.items()}
+    best = max(cited, key=lambda story: cited[story], default=None)
+    return best if best and cited[best] else None
+
+
+def narratives_from_selections(
+    selections: dict, stories: dict[str, list[dict]] | None = None, configured: list[dict] | None = None
+) -> list[dict]:
+    """Flatten selections.json into narrative records in display order.
+
+    Every record has the same fields whatever its tier; a signal's single source becomes a one-item list.
+    A narrative citing articles of one of `stories` (see load_stories) is linked to it, with its articles.
+    Sources named as one of the `configured` sources get its owner and region, unless they have them.
+    """
+    stories = stories or {}
+    by_name = {s["name"].lower(): s for s in configured or []}
+    narratives = []
+    for tier in ["must_know", "should_know"]:
+        for position, article in enumerate(selections.get(tier, [])):
+            narratives.append(
+                {
+                    "tier": tier,
+                    "cluster": None,
+                    "position": position,
+                    "headline": article.get("headline", ""),
+                    "summary": article.get("summary", ""),
+                    "why_it_matters": article.get("why_it_matters", ""),
+                    "topic": article.get("topic"),
+                    "sources": article.get("sources", []),
+                    "reporting_varies": article.get("reporting_varies", []),
+                    "image": article.get("image"),
+                }
+            )
+
+    signals = selections.get("signals", {})
+    for cluster in REGION_ORDER:
+        for position, item in enumerate(signals.get(cluster, [])):
+            source = item.get("source")
+            narratives.append(
+                {
+                    "tier": "signal",
+                    "cluster": cluster,
+                    "position": position,
+                    "headline": item.get("headline", ""),
+                    "summary": "",
+                    "why_it_matters": "",
+                    "topic": item.get("topic"),
+                    "sources": [source] if source else [],
+                    "reporting_varies": [],
+                    "image": None,
+                }
+            )
+
+    for narrative in narratives:
+        for source in narrative["sources"]:
+            match = by_name.get(str(source.get("name", "")).lower()) if isinstance(source, dict) else None
+            for key in ("owner", "region"):
+                if match and match.get(key) and not source.get(key):
+                    source[key] = match[key]
+        narrative["story"] = story_of(narrative["sources"], stories)
+        narrative["story_articles"] = stories.get(narrative["story"], [])
+    return narratives
+
+
+def render_digest(narratives: list[dict], regional_summary: dict) -> str:
+    """Render structured narratives (see narratives_from_selections) to complete HTML string."""
     # Load template
     if not TEMPLATE_FILE.exists():
         raise RuntimeError(f"Template file not found: {TEMPLATE_FILE}")
     template = TEMPLATE_FILE.read_text()
 
     # Render regional summary
-    regional_summary = selections.get("regional_summary", {})
     summary_parts = []
     for region_key in REGION_ORDER:
         text = regional_summary.get(region_key, "")
@@ -996,19 +1781,22 @@ def render_digest(selections: dict) -> str:
 
     # Render must_know
     must_know_html = "\n".join(
-        render_article(article, include_reporting_varies=True) for article in selections.get("must_know", [])
+        render_article(article, include_reporting_varies=True)
+        for article in narratives
+        if article["tier"] == "must_know"
     )
 
     # Render should_know
     should_know_html = "\n".join(
-        render_article(article, include_reporting_varies=False) for article in selections.get("should_know", [])
+        render_article(article, include_reporting_varies=False)
+        for article in narratives
+        if article["tier"] == "should_know"
     )
 
     # Render signals (clustered by region)
-    signals = selections.get("signals", {})
     cluster_parts = []
     for region_key in REGION_ORDER:
-        items = signals.get(region_key, [])
+        items = [n for n in narratives if n["tier"] == "signal" and n["cluster"] == region_key]
         if items:
             region_name, emoji = REGION_CONFIG[region_key]
             region_id = region_key.replace("_", "-")
@@ -1054,6 +1842,7 @@ def extract_headlines(selections: dict) -> list[dict]:
                     "headline": item.get("headline", ""),
                     "tier": tier,
                     "source_id": get_first_source_id(item),
+                    "topic": item.get("topic"),
                 }
             )
 
@@ -1067,12 +1856,19 @@ def extract_headlines(selections: dict) -> list[dict]:
                     "tier": "signal",
                     "cluster": cluster,
                     "source_id": get_first_source_id(item),
+                    "topic": item.get("topic"),
                 }
             )
 
     return headlines
 
 
+def count_stories(selections: dict) -> int:
+    """Stories selected across the tiers and regional signals."""
+    tiers = sum(len(selections.get(tier, [])) for tier in ["must_know", "should_know"])
+    return tiers + sum(len(items) for items in selections.get("signals", {}).values())
+
+
 def extract_preheader(selections: dict, max_length: int = 150) -> str:
     """Extract preheader text from first regional summary for email preview."""
     regional_summary = selections.get("regional_summary", {})
@@ -1217,7 +2013,11 @@ def prepare_claude_input(sources: list[dict]) -> list[Path]:
                     continue
                 # Strip HTML, escape for safety, and cap lengths
                 title = html.escape(strip_html(a.get("title") or ""))[:MAX_TITLE_LENGTH]
-                summary = html.escape(strip_html(a.get("summary") or ""))[:MAX_SUMMARY_LENGTH]
+                # Prefer the article text digest-pipeline extracted over a feed's teaser
+                if a.get("content"):
+                    summary = html.escape(strip_html(a["content"]))[:MAX_CONTENT_LENGTH]
+                else:
+                    summary = html.escape(strip_html(a.get("summary") or ""))[:MAX_SUMMARY_LENGTH]
 
                 # TF-IDF dedup pre-filter
                 if dedup_matcher and title:
@@ -1236,14 +2036,22 @@ def prepare_claude_input(sources: list[dict]) -> list[Path]:
                         filtered_similarities.append(similarity)
                         continue
 
-                all_articles.append([source["id"], title, url, a.get("published", ""), summary])
+                all_articles.append([source["id"], title, url, a.get("published", ""), summary, a.get("story") or ""])
+
+    # Keep each story's articles together, where the first of them appeared
+    first_seen: dict[str, int] = {}
+    for i, row in enumerate(all_articles):
+        first_seen.setdefault(row[5] or f"#{i}", i)
+    all_articles = [
+        row for _, row in sorted(enumerate(all_articles), key=lambda x: first_seen[x[1][5] or f"#{x[0]}"])
+    ]
 
     # Split articles into multiple files if needed
     article_files = []
     current_file_num = 1
     current_rows: list[list[str]] = []
     current_tokens = 0
-    header = ["source_id", "title", "url", "published", "summary"]
+    header = ["source_id", "title", "url", "published", "summary", "story"]
 
     for row in all_articles:
         row_text = ",".join(str(x) for x in row)
@@ -1299,17 +2107,48 @@ def read_shown_headlines() -> list[dict]:
         return []
 
 
-def cleanup_shown_headlines():
-    """Remove shown_headlines.json after successful run."""
-    headlines_file = DATA_DIR / "shown_headlines.json"
-    if headlines_file.exists():
-        headlines_file.unlink()
+def read_narratives() -> dict | None:
+    """Read narratives.json written when the digest was rendered, if any."""
+    narratives_file = DATA_DIR / "narratives.json"
+    if not narratives_file.exists():
+        return None
+
+    try:
+        with open(narratives_file) as f:
+            return json.load(f)
+    except (OSError, json.JSONDecodeError) as e:
+        log(f"Error reading narratives.json: {e}", "ERROR")
+        return None
+
+
+def cleanup_run_files():
+    """Remove shown_headlines.json and narratives.json after successful run."""
+    for name in ["shown_headlines.json", "narratives.json"]:
+        run_file = DATA_DIR / name
+        if run_file.exists():
+            run_file.unlink()
+
+
+def parse_claude_usage(result: dict) -> dict:
+    """Extract token counts and estimated cost from a Claude CLI result event.
+
+    Cached prompt tokens (read or written) are counted as input tokens.
+    """
+    usage = result.get("usage") or {}
+    return {
+        "input_tokens": sum(
+            usage.get(key) or 0 for key in ("input_tokens", "cache_creation_input_tokens", "cache_read_input_tokens")
+        ),
+        "output_tokens": usage.get("output_tokens") or 0,
+        "cost_usd": result.get("total_cost_usd") or 0.0,
+    }
 
 
-def run_claude_command(command: str, description: str, mcp_config: str | None = None):
-    """Run a Claude command with streaming output."""
+def run_claude_command(command: str, description: str, mcp_config: str | None = None) -> dict:
+    """Run a Claude command with streaming output. Returns its usage (see parse_claude_usage)."""
     log(f"{description}...")
-    cmd = ["claude", "--print", "--permission-mode", "acceptEdits", command]
+    cmd = ["claude", "--print", "--permission-mode", "acceptEdits", "--output-format", "stream-json", "--verbose"]
+    cmd.append(command)
     if mcp_config:
         cmd.extend(["--mcp-config", mcp_config, "--allowedTools", "mcp__news-digest__write_selections"])
     log(f"Running: {' '.join(cmd)}")
@@ -1320,11 +2159,22 @@ def run_claude_command(command: str, description: str, mcp_config: str | None =
         text=True,
         bufsize=1,  # Line buffered
     )
+    usage = {"input_tokens": 0, "output_tokens": 0, "cost_usd": 0.0}
     try:
-        # Stream output in real-time
+        # Stream assistant text in real-time; the final "result" event carries usage
         assert process.stdout is not None, "stdout=PIPE guarantees this"  # nosec B101
         for line in process.stdout:
-            print(line, end="", flush=True)
+            try:
+                event = json.loads(line)
+            except json.JSONDecodeError:
+                print(line, end="", flush=True)
+                continue
+            if event.get("type") == "assistant":
+                for block in event.get("message", {}).get("content", []):
+                    if block.get("type") == "text":
+                        print(block["text"], flush=True)
+            elif event.get("type") == "result":
+                usage = parse_claude_usage(event)
         process.wait()
     finally:
         # Ensure process is cleaned up even on interrupt
@@ -1333,11 +2183,34 @@ def run_claude_command(command: str, description: str, mcp_config: str | None =
             process.wait(timeout=5)
     if process.returncode != 0:
         raise RuntimeError(f"Claude failed with code {process.returncode}")
+    log(f"Claude usage: {usage['input_tokens']} in / {usage['output_tokens']} out tokens, ~${usage['cost_usd']:.4f}")
+    return usage
+
+
+def select_prompt_version() -> str | None:
+    """Version of the /news-digest-select prompt: a hash of the command file, as recorded with each run."""
+    try:
+        return "command-" + hashlib.sha256(SELECT_COMMAND_FILE.read_bytes()).hexdigest()[:12]
+    except OSError as e:
+        log(f"Cannot read {SELECT_COMMAND_FILE.name}: {e}", "WARN")
+        return None
+
 
+def generate_selections() -> dict:
+    """Pass 1: Run Claude to select and curate stories. Returns Claude usage and the prompt version."""
+    usage = run_claude_command("/news-digest-select", "Pass 1: Selecting stories", mcp_config=".mcp.json")
+    return {**usage, "prompt_version": select_prompt_version()}
 
-def generate_selections():
-    """Pass 1: Run Claude to select and curate stories."""
-    run_claude_command("/news-digest-select", "Pass 1: Selecting stories", mcp_config=".mcp.json")
+
+def read_pipeline_usage() -> dict:
+    """Read the usage and prompt version digest-pipeline curate recorded beside selections.json (for --skip-select)."""
+    usage_file = CLAUDE_INPUT_DIR / "usage.json"
+    try:
+        with open(usage_file) as f:
+            return json.load(f)
+    except (OSError, json.JSONDecodeError) as e:
+        log(f"Cannot read {usage_file.name}, run not costed: {e}", "WARN")
+        return {}
 
 
 def validate_source(src: dict, context: str) -> list[str]:
@@ -1545,8 +2418,10 @@ def write_digest_from_selections(selections: dict) -> Path:
     signals_count = sum(len(signals.get(c, [])) for c in REGION_ORDER)
     log(f"Rendering: {must_know} must_know, {should_know} should_know, {signals_count} signals")
 
-    # Render HTML
-    html_content = render_digest(selections)
+    # Render HTML from the structured form that save_digest() stores
+    narratives = narratives_from_selections(selections, load_stories(), load_sources())
+    regional_summary = selections.get("regional_summary", {})
+    html_content = render_digest(narratives, regional_summary)
 
     # Generate filename with timestamp
     OUTPUT_DIR.mkdir(parents=True, exist_ok=True)
@@ -1562,6 +2437,9 @@ def write_digest_from_selections(selections: dict) -> Path:
     with open(headlines_file, "w") as f:
         json.dump(headlines, f, indent=2)
 
+    with open(DATA_DIR / "narratives.json", "w") as f:
+        json.dump({"narratives": narratives, "regional_summary": regional_summary}, f, indent=2)
+
     log(f"Wrote {digest_path.name} ({len(headlines)} stories)")
     return digest_path
 
@@ -1612,7 +2490,7 @@ def send_health_alert(failing_sources: list[tuple[str, int]], failed_this_run: i
 <p><strong>{failed_this_run}/{total_sources}</strong> sources failed this run.</p>
 <p>The following sources have failed 3+ times in a row:</p>
 <pre>{source_list}</pre>
-<p>Consider checking these feeds or removing them from sources.json.</p>
+<p>Consider checking these feeds or removing them from sources.toml.</p>
 <p style="color: #777; font-size: 0.85em;">This is an automated alert from your News Digest system.</p>
 """
 
@@ -1631,6 +2509,85 @@ def send_health_alert(failing_sources: list[tuple[str, int]], failed_this_run: i
         log(f"Failed to send health alert: {e}", "ERROR")
 
 
+def run_report(run_started: str, dry_run: bool, duration_ms: int, digest_date: str | None = None) -> dict:
+    """Summarize a run from its run_stages rows: whether it succeeded, the stage it failed at and why,
+    the articles fetched, stories selected, and recipients emailed, and the digest's URL."""
+    with connect_db() as conn:
+        rows = conn.execute(
+            "SELECT stage, duration_ms, items, error FROM run_stages WHERE run_started_at = ? ORDER BY id",
+            (run_started,),
+        ).fetchall()
+    stages = [{"stage": r[0], "duration_ms": r[1], "items": r[2], "error": r[3]} for r in rows]
+    items = {s["stage"]: s["items"] for s in stages}
+    failed = next((s for s in stages if s["error"]), None)
+    digest_domain = os.environ.get("DIGEST_DOMAIN", "")
+    digest_url = f"https://{digest_domain}/{digest_date}" if digest_domain and digest_date and not dry_run else None
+    digest_name = os.environ.get("DIGEST_NAME", "News Digest")
+    minutes = f"{duration_ms // 60_000}m {duration_ms // 1000 % 60:02d}s"
+    if failed:
+        text = f"{digest_name} run failed at {failed['stage']} after {minutes}: {failed['error']}"
+    else:
+        text = (
+            f"{digest_name} {'dry run' if dry_run else 'run'} finished in {minutes}: "
+            f"{items.get('fetch') or 0} articles, {items.get('select') or 0} stories, "
+            f"{items.get('send') or 0} recipients"
+        )
+        if digest_url:
+            text += f" {digest_url}"
+    return {
+        "event": "run.failed" if failed else "run.succeeded",
+        "status": "failed" if failed else "succeeded",
+        "dry_run": dry_run,
+        "started_at": run_started,
+        "duration_ms": duration_ms,
+        "articles_fetched": items.get("fetch"),
+        "stories": items.get("select"),
+        "recipients": items.get("send"),
+        "failed_stage": failed["stage"] if failed else None,
+        "error": failed["error"] if failed else None,
+        "digest_url": digest_url,
+        "stages": stages,
+        # Shown as the message by Slack, Discord (via /slack), and Mattermost incoming webhooks
+        "text": text,
+    }
+
+
+def sign_webhook(body: bytes, secret: str) -> str:
+    """X-Digest-Signature value: the body's HMAC-SHA256 under RUN_WEBHOOK_SECRET, hex-encoded."""
+    return "sha256=" + hmac.new(secret.encode(), body, hashlib.sha256).hexdigest()
+
+
+def send_run_webhooks(
+    run_started: str, dry_run: bool, duration_ms: int, digest_date: str | None = None, timeout: int = 10
+):
+    """POST the run's report to each of RUN_WEBHOOK_URLS (comma-separated), signed when RUN_WEBHOOK_SECRET
+    is set. Failures are logged, never raised, so a webhook that's down can't fail the run."""
+    urls = [u.strip() for u in os.environ.get("RUN_WEBHOOK_URLS", "").split(",") if u.strip()]
+    if not urls:
+        return
+    try:
+        report = run_report(run_started, dry_run, duration_ms, digest_date)
+    except sqlite3.Error as e:
+        log(f"DB error reading the run for its webhooks: {e}", "ERROR")
+        return
+    body = json.dumps(report).encode()
+    headers = {"Content-Type": "application/json", "User-Agent": "news-digest"}
+    secret = os.environ.get("RUN_WEBHOOK_SECRET")
+    if secret:
+        headers["X-Digest-Signature"] = sign_webhook(body, secret)
+    for url in urls:
+        if not url.startswith(("https://", "http://")):
+            log(f"Skipping run webhook {url}: not an http(s) URL", "WARN")
+            continue
+        try:
+            req = urllib.request.Request(url, data=body, headers=headers, method="POST")
+            with urllib.request.urlopen(req, timeout=timeout):  # nosec B310
+                pass
+            log(f"Sent {report['event']} webhook to {urllib.parse.urlsplit(url).netloc}")
+        except (urllib.error.URLError, TimeoutError, OSError) as e:
+            log(f"Run webhook to {urllib.parse.urlsplit(url).netloc} failed: {e}", "WARN")
+
+
 def get_audience_contact_count(audience_id: str) -> int:
     """Get number of contacts in an audience."""
     try:
@@ -1644,14 +2601,44 @@ def get_audience_contact_count(audience_id: str) -> int:
         return 0
 
 
-def send_broadcast(digest_path: Path) -> int:
-    """Send digest via Resend Broadcasts API. Returns number of recipients."""
+def parse_audiences() -> dict[str, str]:
+    """Parse configured audiences: RESEND_AUDIENCE_ID is "default", RESEND_AUDIENCES adds "name:id" pairs."""
+    audiences = {}
+    if legacy_id := os.environ.get("RESEND_AUDIENCE_ID", "").strip():
+        audiences["default"] = legacy_id
+    for entry in os.environ.get("RESEND_AUDIENCES", "").split(","):
+        name, _, audience_id = entry.strip().partition(":")
+        if name.strip() and audience_id.strip():
+            audiences[name.strip()] = audience_id.strip()
+    return audiences
+
+
+def resolve_audience_id(name: str | None = None) -> str:
+    """Resolve an audience name (default: DIGEST_AUDIENCE, else the first configured) to its Resend ID."""
+    audiences = parse_audiences()
+    name = name or os.environ.get("DIGEST_AUDIENCE")
+    if name:
+        if name not in audiences:
+            log(f"Unknown audience '{name}' (configured: {', '.join(audiences) or 'none'})", "ERROR")
+            sys.exit(1)
+        return audiences[name]
+    if not audiences:
+        log("No Resend audience configured", "ERROR")
+        sys.exit(1)
+    return next(iter(audiences.values()))
+
+
+def send_broadcast(digest_path: Path, audience: str | None = None) -> int:
+    """Send digest via Resend Broadcasts API to the named audience. Returns number of recipients."""
     resend.api_key = os.environ["RESEND_API_KEY"]
     from_email = os.environ["RESEND_FROM"]
     digest_name = os.environ.get("DIGEST_NAME", "News Digest")
-    audience_id = os.environ["RESEND_AUDIENCE_ID"]
+    audience_id = resolve_audience_id(audience)
 
     content = digest_path.read_text()
+    digest_domain = os.environ.get("DIGEST_DOMAIN", "")
+    if os.environ.get("CLICK_TRACKING") in ("1", "true") and digest_domain:
+        content = track_links(content, digest_date_from_path(digest_path), f"https://{digest_domain}")
     # Prepare for email: resolve CSS variables and inline styles
     content = prepare_for_email(content)
     date_str = datetime.now(UTC).strftime("%B %d, %Y")
@@ -1677,6 +2664,7 @@ def send_broadcast(digest_path: Path) -> int:
         # Send the broadcast
         resend_with_retry(resend.Broadcasts.send, {"broadcast_id": broadcast_id})
         log(f"Sent broadcast to {contact_count} contacts in audience {audience_id}")
+        record_broadcast(broadcast_id, digest_date_from_path(digest_path), audience_id, contact_count)
 
         return contact_count
     except resend.exceptions.ResendError as e:
@@ -1848,13 +2836,21 @@ Examples:
   python run.py --select-only      # Run Pass 1 only (create selections.json)
   python run.py --write-only       # Run Pass 2 only (use existing selections.json)
   python run.py --send-only        # Send latest digest (retry after failure)
+  python run.py --audience weekly  # Send to a named audience from RESEND_AUDIENCES
   python run.py --preview          # Open latest digest in browser
   python run.py --test-email you@example.com  # Test Resend config
   python run.py --validate         # Test all RSS feeds and report status
   python run.py --validate --json  # Test RSS feeds with JSON output
+  python run.py --skip-fetch       # Curate what digest-pipeline fetched
+  python run.py --skip-fetch --skip-select  # Render what digest-pipeline curated
+  python run.py --restart          # Start over rather than resume an interrupted run
         """,
     )
-    parser.add_argument("--dry-run", action="store_true", help="Fetch and generate only (no email, no DB record)")
+    parser.add_argument(
+        "--dry-run",
+        action="store_true",
+        help="Fetch and generate only (no email, no DB record); the digest goes to the preview slot",
+    )
     parser.add_argument("--no-email", action="store_true", help="Skip sending email (still records to DB)")
     parser.add_argument("--no-record", action="store_true", help="Skip recording to DB (still sends email)")
     parser.add_argument(
@@ -1872,12 +2868,32 @@ Examples:
     parser.add_argument("--test-email", metavar="EMAIL", help="Send test email to specified address and exit")
     parser.add_argument("--validate", action="store_true", help="Test all RSS feeds and report health status")
     parser.add_argument("--json", action="store_true", help="Output in JSON format (use with --validate)")
+    parser.add_argument(
+        "--audience", metavar="NAME", help="Send to a named audience from RESEND_AUDIENCES (default: first configured)"
+    )
     parser.add_argument("--health-check", action="store_true", help="Verify Claude auth is working (for monitoring)")
+    parser.add_argument(
+        "--skip-fetch", action="store_true", help="Use the articles digest-pipeline fetched into data/fetched"
+    )
+    parser.add_argument(
+        "--skip-select",
+        action="store_true",
+        help="Use the selections.json digest-pipeline curate wrote instead of running Pass 1",
+    )
+    parser.add_argument(
+        "--restart",
+        action="store_true",
+        help="Start over, rather than resume from the stages an interrupted run completed",
+    )
     args = parser.parse_args()
 
-    # --dry-run is shorthand for --no-email --no-record
-    skip_email = args.dry_run or args.no_email
-    skip_record = args.dry_run or args.no_record
+    # --dry-run is shorthand for --no-email --no-record, plus a preview; DRY_RUN=1 is
+    # how digest-server asks for one (POST /admin/run?dry_run=true)
+    dry_run = args.dry_run or os.environ.get("DRY_RUN") in ("1", "true")
+    skip_email = dry_run or args.no_email
+    skip_record = dry_run or args.no_record
+    # RESTART=1 is how digest-server asks for one (POST /admin/run?restart=true)
+    restart = args.restart or os.environ.get("RESTART") in ("1", "true")
 
     # Test email mode - verify Resend config works
     if args.test_email:
@@ -1927,13 +2943,13 @@ Examples:
             return 1
         log(f"Sending existing digest: {digest.name}")
         save_digest(digest)  # Save before broadcast so link works
-        recipients = send_broadcast(digest)
+        recipients = send_broadcast(digest, args.audience)
         shown_headlines = read_shown_headlines()
         if shown_headlines:
             record_shown_headlines(shown_headlines)
         record_run(0, articles_emailed=recipients)
         # Source usage not tracked for send-only (no selections available)
-        cleanup_shown_headlines()
+        cleanup_run_files()
         return 0
 
     # Write-only mode - render HTML from existing selections
@@ -1943,20 +2959,22 @@ Examples:
         selections = validate_selections()  # Ensure selections.json exists and is valid
         digest = write_digest_from_selections(selections)
         replace_placeholders(digest, extract_preheader(selections))
+        if dry_run:
+            save_preview(digest)
         # Save before broadcast so link works
         if not skip_record:
             save_digest(digest)
         # Send broadcast
         recipients = 0
         if not skip_email:
-            recipients = send_broadcast(digest)
+            recipients = send_broadcast(digest, args.audience)
         # Record run metadata
         if not skip_record:
             shown_headlines = read_shown_headlines()
             if shown_headlines:
                 record_shown_headlines(shown_headlines)
             record_run(0, articles_emailed=recipients)
-        cleanup_shown_headlines()
+        cleanup_run_files()
         return 0
 
     validate_env(dry_run=skip_email)  # Don't require SMTP vars if skipping email
@@ -1965,52 +2983,122 @@ Examples:
         log("No internet connection, skipping")
         return 0
 
+    started = time.monotonic()
     sources = load_sources()
     init_db()
-    articles_fetched, failed_count = fetch_feeds(sources)
-
-    # Send health alert if sources are persistently failing
-    persistently_failing = get_failing_sources(min_consecutive=HEALTH_ALERT_THRESHOLD)
-    if persistently_failing:
-        send_health_alert(persistently_failing, failed_count, len(sources))
-
-    # Prepare input for Claude (articles + previous headlines)
-    prepare_claude_input(sources)
+    # A run that crashed or was killed resumes after the last stage it completed,
+    # so it doesn't fetch, spend tokens on selection, or email readers again
+    checkpoints = {} if restart else load_checkpoints(dry_run)
+    if checkpoints:
+        last = list(checkpoints)[-1]
+        log(f"Resuming the interrupted run after its {last} stage ({checkpoints[last]['completed_at']} UTC)")
+    else:
+        clear_checkpoints()
+    # "Stage: <name>" lines mark progress for runs digest-server starts (/admin/runs/{id}),
+    # and each stage's time, items, and error go in run_stages for the stats page's timeline
+    run_started = datetime.now(UTC).strftime("%Y-%m-%d %H:%M:%S")
+    try:
+        with timed_stage(run_started, "fetch", dry_run) as stage:
+            if "fetch" in checkpoints:
+                articles_fetched = checkpoints["fetch"]["articles_fetched"]
+                log(f"Already fetched {articles_fetched} articles")
+            else:
+                if args.skip_fetch:
+                    articles_fetched, failed_count = count_fetched(sources), 0
+                else:
+                    articles_fetched, failed_count = fetch_feeds(sources)
+
+                # Send health alert if sources are persistently failing
+                persistently_failing = get_failing_sources(min_consecutive=HEALTH_ALERT_THRESHOLD)
+                if persistently_failing:
+                    send_health_alert(persistently_failing, failed_count, len(sources))
+                save_checkpoint("fetch", dry_run, {"articles_fetched": articles_fetched})
+            stage["items"] = articles_fetched
+
+        with timed_stage(run_started, "select", dry_run) as stage:
+            if "select" in checkpoints:
+                usage = checkpoints["select"]["usage"]
+                log("Already selected stories: using selections.json")
+            else:
+                if args.skip_select:
+                    usage = read_pipeline_usage()
+                else:
+                    # Prepare input for Claude (articles + previous headlines)
+                    prepare_claude_input(sources)
+
+                    # Pass 1: Select stories (Claude)
+                    usage = generate_selections()
+            selections = validate_selections()
+            stage["items"] = count_stories(selections)
+
+            if not args.select_only and "select" not in checkpoints:
+                save_checkpoint("select", dry_run, {"usage": usage})
+
+        # Select-only mode - stop after Pass 1, once its stage is recorded for the webhooks
+        if args.select_only:
+            clear_checkpoints()
+            log("Select-only mode: stopping after Pass 1")
+            send_run_webhooks(run_started, dry_run, round((time.monotonic() - started) * 1000))
+            return 0
 
-    # Pass 1: Select stories (Claude)
-    generate_selections()
-    selections = validate_selections()
+        # Pass 2: Render HTML digest (Python - no Claude)
+        with timed_stage(run_started, "render", dry_run) as stage:
+            if "render" in checkpoints:
+                digest = Path(checkpoints["render"]["digest"])
+                log(f"Already rendered {digest.name}")
+            else:
+                digest = write_digest_from_selections(selections)
+                replace_placeholders(digest, extract_preheader(selections))
 
-    # Select-only mode - stop after Pass 1
-    if args.select_only:
-        log("Select-only mode: stopping after Pass 1")
-        return 0
+                if dry_run:
+                    save_preview(digest)
 
-    # Pass 2: Render HTML digest (Python - no Claude)
-    digest = write_digest_from_selections(selections)
-    replace_placeholders(digest, extract_preheader(selections))
+                # Save digest to DB BEFORE broadcast so "view in browser" link works immediately
+                if not skip_record:
+                    save_digest(digest)
+                save_checkpoint("render", dry_run, {"digest": str(digest)})
+            stage["items"] = count_stories(selections)
 
-    # Save digest to DB BEFORE broadcast so "view in browser" link works immediately
-    if not skip_record:
-        save_digest(digest)
+        # Send broadcast
+        with timed_stage(run_started, "send", dry_run) as stage:
+            if "send" in checkpoints:
+                recipients = checkpoints["send"]["recipients"]
+                log(f"Already sent {digest.name} to {recipients} recipients")
+            else:
+                recipients = 0
+                if not skip_email:
+                    recipients = send_broadcast(digest, args.audience)
+                else:
+                    log(f"Skipping broadcast: {digest.name}")
+                save_checkpoint("send", dry_run, {"recipients": recipients})
+            stage["items"] = recipients
 
-    # Send broadcast
-    recipients = 0
-    if not skip_email:
-        recipients = send_broadcast(digest)
-    else:
-        log(f"Skipping broadcast: {digest.name}")
+        # Record run metadata after broadcast succeeds
+        if not skip_record:
+            with timed_stage(run_started, "record", dry_run) as stage:
+                shown_headlines = read_shown_headlines()
+                if not shown_headlines:
+                    log("No headlines recorded - Claude may not have generated shown_headlines.json", "WARN")
+                record_shown_headlines(shown_headlines)
+                duration_ms = round((time.monotonic() - started) * 1000)
+                run_id = record_run(articles_fetched, articles_emailed=recipients, duration_ms=duration_ms, usage=usage)
+                stage["items"] = len(shown_headlines)
+            link_stages(run_started, run_id)
+    except StopRequested as e:
+        # Not a failure: the stages completed so far are checkpointed, and the next run resumes after them
+        log(str(e), "WARN")
+        return 75
+    except BaseException:
+        # The failed stage and its error are in run_stages. BaseException, as sys.exit() (a missing
+        # audience, say) and Ctrl-C fail a run too.
+        send_run_webhooks(run_started, dry_run, round((time.monotonic() - started) * 1000))
+        raise
 
-    # Record run metadata after broadcast succeeds
-    if not skip_record:
-        shown_headlines = read_shown_headlines()
-        if not shown_headlines:
-            log("No headlines recorded - Claude may not have generated shown_headlines.json", "WARN")
-        record_shown_headlines(shown_headlines)
-        record_run(articles_fetched, articles_emailed=recipients)
+    send_run_webhooks(run_started, dry_run, round((time.monotonic() - started) * 1000), digest_date_from_path(digest))
 
-    # Clean up shown_headlines.json only after successful completion
-    cleanup_shown_headlines()
+    # Clean up run files only after successful completion
+    cleanup_run_files()
+    clear_checkpoints()
 
     return 0
 
@@ -165,6 +165,16 @@ class TestValidateSelections:
         assert len(errors) > 0
         assert any("americas" in e for e in errors)
 
+    def test_rejects_unknown_topic(self):
+        selections = valid_selections()
+        selections["must_know"] = [{**valid_article(), "topic": "geopolitics"}]
+        selections["signals"]["tech"] = [{**valid_signal(), "topic": "sports"}]
+
+        errors = validate_selections(selections)
+
+        assert len(errors) == 1
+        assert "signals.tech.0.topic" in errors[0]
+
     def test_handle_tool_call_rejects_invalid_input(self, tmp_path):
         """Integration: invalid input returns error, doesn't write file."""
         with patch("mcp_server.DATA_DIR", tmp_path):
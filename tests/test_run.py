@@ -1,25 +1,65 @@
 """Tests for run.py pure functions."""
 
+import copy
+import hashlib
+import sqlite3
 import sys
 from pathlib import Path
 
+import pytest
+
 # Add parent to path so we can import run
 sys.path.insert(0, str(Path(__file__).parent.parent))
 
+import run
 from run import (
+    DB_SCHEMA,
+    StopRequested,
     TfidfMatcher,
+    clear_checkpoints,
+    compressed_copy_matches,
     estimate_tokens,
+    extract_headlines,
+    fetch_feeds,
     fix_selections_schema,
     generate_feedback_html,
+    image_type,
+    init_db,
     is_safe_url,
+    item_fingerprint,
+    link_stages,
+    load_checkpoints,
+    load_narratives,
     minify_css,
+    narratives_from_selections,
+    parse_audiences,
+    parse_claude_usage,
     parse_date,
+    record_source_health,
+    render_digest,
     resolve_css_variables,
+    run_report,
+    save_checkpoint,
+    save_narratives,
+    sign_webhook,
+    store_image,
     strip_html,
+    timed_stage,
     tokenize,
+    track_links,
+    tracked_link_id,
 )
 
 
+@pytest.fixture(autouse=True)
+def data_dir(tmp_path, monkeypatch):
+    """Keep log(), the run files, and the database out of the real data/ directory."""
+    monkeypatch.setattr(run, "DATA_DIR", tmp_path)
+    monkeypatch.setattr(run, "LOG_FILE", tmp_path / "digest.log")
+    monkeypatch.setattr(run, "DB_PATH", tmp_path / "digest.db")
+    monkeypatch.setattr(run, "TELEMETRY_DB_PATH", None)
+
+
 class TestEstimateTokens:
     def test_empty_string(self):
         assert estimate_tokens("") == 0
@@ -335,3 +375,446 @@ class TestGenerateFeedbackHtml:
         result = generate_feedback_html("<script>@evil.com")
         assert "<script>" not in result
         assert "&lt;script&gt;" in result
+
+
+class TestParseAudiences:
+    def test_legacy_id_is_default(self, monkeypatch):
+        monkeypatch.setenv("RESEND_AUDIENCE_ID", "aud-1")
+        monkeypatch.delenv("RESEND_AUDIENCES", raising=False)
+        assert parse_audiences() == {"default": "aud-1"}
+
+    def test_named_audiences(self, monkeypatch):
+        monkeypatch.delenv("RESEND_AUDIENCE_ID", raising=False)
+        monkeypatch.setenv("RESEND_AUDIENCES", "daily:a, weekly:b")
+        assert parse_audiences() == {"daily": "a", "weekly": "b"}
+
+    def test_skips_malformed_entries(self, monkeypatch):
+        monkeypatch.delenv("RESEND_AUDIENCE_ID", raising=False)
+        monkeypatch.setenv("RESEND_AUDIENCES", "daily,weekly:b,:c")
+        assert parse_audiences() == {"weekly": "b"}
+
+
+class TestTrackLinks:
+    def test_id_matches_server(self):
+        # Shared test vector with digest-server's links::link_id
+        assert tracked_link_id("2026-01-24", "https://example.com/a") == "20260124Lc4KTFBE"
+
+    def test_rewrites_outbound_links_only(self):
+        html_in = '<a href="https://example.com/a">A</a> <a href="https://digest.example.com/2026-01-24">B</a>'
+        out = track_links(html_in, "2026-01-24", "https://digest.example.com")
+        assert 'href="https://digest.example.com/r/20260124Lc4KTFBE"' in out
+        assert 'href="https://digest.example.com/2026-01-24"' in out
+
+
+class TestParseClaudeUsage:
+    def test_sums_cached_input_tokens(self):
+        event = {
+            "type": "result",
+            "total_cost_usd": 0.42,
+            "usage": {
+                "input_tokens": 100,
+                "cache_creation_input_tokens": 2000,
+                "cache_read_input_tokens": 30000,
+                "output_tokens": 1500,
+            },
+        }
+        assert parse_claude_usage(event) == {"input_tokens": 32100, "output_tokens": 1500, "cost_usd": 0.42}
+
+    def test_missing_usage(self):
+        assert parse_claude_usage({"type": "result"}) == {"input_tokens": 0, "output_tokens": 0, "cost_usd": 0.0}
+
+
+class TestLoadSources:
+    FEED = '[[source]]\nid = "{id}"\nname = "Feed"\nurl = "{url}"\nbias = "center"\nperspective = "western"\n'
+
+    def load(self, tmp_path, monkeypatch, toml):
+        path = tmp_path / "sources.toml"
+        path.write_text(toml)
+        monkeypatch.setattr(run, "SOURCES_FILE", path)
+        return run.load_sources()
+
+    def test_repo_sources_are_valid(self):
+        assert len(run.load_sources()) > 0
+
+    def test_defaults_and_disabled_sources(self, tmp_path, monkeypatch):
+        toml = self.FEED.format(id="a", url="https://a.com") + self.FEED.format(id="b", url="https://b.com")
+        sources = self.load(tmp_path, monkeypatch, toml + "enabled = false\n")
+        assert [s["id"] for s in sources] == ["a"]
+        assert sources[0]["weight"] == 1.0
+        assert sources[0]["topics"] == []
+
+    def test_names_the_source_at_fault(self, tmp_path, monkeypatch):
+        toml = self.FEED.format(id="a", url="https://a.com") + self.FEED.format(id="a", url="https://b.com")
+        with pytest.raises(ValueError, match=r"source 2 \(a\): duplicate id"):
+            self.load(tmp_path, monkeypatch, toml)
+        with pytest.raises(ValueError, match="unknown keys"):
+            self.load(tmp_path, monkeypatch, self.FEED.format(id="a", url="https://a.com") + "dealy_secs = 2\n")
+
+    def test_leaves_newsletter_mailboxes_to_the_pipeline(self, tmp_path, monkeypatch):
+        toml = self.FEED.format(id="letters", url="imaps://imap.example.com/Newsletters")
+        source = self.load(tmp_path, monkeypatch, toml)[0]
+        assert run.fetch_source(source) == ("letters", [], "Newsletter mailboxes are read by digest-pipeline fetch")
+        with pytest.raises(ValueError, match="must be http"):
+            self.load(tmp_path, monkeypatch, self.FEED.format(id="a", url="ftp://a.com"))
+
+    def test_accepts_filters_for_the_pipeline(self, tmp_path, monkeypatch):
+        toml = '[filters]\nexclude = ["celebrity"]\n\n' + self.FEED.format(id="a", url="https://a.com")
+        sources = self.load(tmp_path, monkeypatch, toml + '[source.filters]\nexclude = ["sport"]\n')
+        assert sources[0]["filters"] == {"exclude": ["sport"]}
+        with pytest.raises(ValueError, match="unknown filters keys"):
+            self.load(tmp_path, monkeypatch, toml + '[source.filters]\nexlude = ["sport"]\n')
+
+
+class TestItemFingerprint:
+    def test_empty_feed(self):
+        assert item_fingerprint([]) is None
+
+    def test_newest_publish_date(self):
+        articles = [
+            {"url": "https://a.com/1", "published": "2026-01-24T08:00:00+00:00"},
+            {"url": "https://a.com/2", "published": "2026-01-25T09:30:00+00:00"},
+        ]
+        assert item_fingerprint(articles) == "2026-01-25T09:30:00+00:00"
+
+    def test_undated_feed_hashes_urls(self):
+        first = item_fingerprint([{"url": "https://a.com/1"}, {"url": "https://a.com/2"}])
+        assert first == item_fingerprint([{"url": "https://a.com/2"}, {"url": "https://a.com/1"}])
+        assert first != item_fingerprint([{"url": "https://a.com/3"}, {"url": "https://a.com/2"}])
+
+
+class TestExtractHeadlines:
+    def test_keeps_topic_tags(self):
+        selections = {
+            "must_know": [{"headline": "Ceasefire holds", "sources": [], "topic": "geopolitics"}],
+            "should_know": [{"headline": "Untagged story", "sources": []}],
+            "signals": {"tech": [{"headline": "New chip", "source": {}, "topic": "tech_ai"}]},
+        }
+
+        headlines = extract_headlines(selections)
+
+        assert [(h["tier"], h["topic"]) for h in headlines] == [
+            ("must_know", "geopolitics"),
+            ("should_know", None),
+            ("signal", "tech_ai"),
+        ]
+
+
+SELECTIONS = {
+    "regional_summary": {"americas": "Quiet day.", "europe": "", "tech": "Chips [up](https://a.com/chips)."},
+    "must_know": [
+        {
+            "headline": "Ceasefire holds",
+            "summary": "Talks continue.",
+            "why_it_matters": "Fewer casualties.",
+            "topic": "geopolitics",
+            "sources": [{"name": "BBC", "url": "https://bbc.com/1", "bias": "center"}],
+            "reporting_varies": [{"source": "Fox", "bias": "right", "angle": "Skeptical"}],
+        }
+    ],
+    "should_know": [{"headline": "Rates steady", "summary": "No change.", "why_it_matters": "", "sources": []}],
+    "signals": {
+        "tech": [
+            {"headline": "New chip", "source": {"name": "Verge", "url": "https://verge.com/1"}, "topic": "tech_ai"}
+        ],
+        "americas": [{"headline": "Storm nears", "source": {"name": "AP", "url": "https://ap.org/1"}}],
+    },
+}
+
+
+STORIES = {
+    "s1": [
+        {"source_id": "bbc", "title": "Ceasefire holds", "url": "https://bbc.com/1"},
+        {"source_id": "fox", "title": "Truce holds, for now", "url": "https://fox.com/1"},
+    ],
+    "s2": [{"source_id": "ap", "title": "Rates", "url": "https://ap.org/2"}],
+}
+
+
+class TestNarrativesFromSelections:
+    def test_flattens_in_display_order(self):
+        narratives = narratives_from_selections(SELECTIONS)
+
+        assert [(n["tier"], n["cluster"], n["position"], n["headline"]) for n in narratives] == [
+            ("must_know", None, 0, "Ceasefire holds"),
+            ("should_know", None, 0, "Rates steady"),
+            ("signal", "americas", 0, "Storm nears"),
+            ("signal", "tech", 0, "New chip"),
+        ]
+
+    def test_signal_source_becomes_list(self):
+        signal = narratives_from_selections(SELECTIONS)[-1]
+
+        assert signal["sources"] == [{"name": "Verge", "url": "https://verge.com/1"}]
+        assert signal["topic"] == "tech_ai"
+
+    def test_links_narratives_to_the_stories_they_cite(self):
+        narratives = narratives_from_selections(SELECTIONS, STORIES)
+
+        assert narratives[0]["story"] == "s1"
+        assert narratives[0]["story_articles"] == STORIES["s1"]
+        assert [n["story"] for n in narratives[1:]] == [None, None, None]
+
+
+class TestRenderDigest:
+    def test_renders_each_tier(self):
+        html = render_digest(narratives_from_selections(SELECTIONS), SELECTIONS["regional_summary"])
+
+        assert "<h3>Ceasefire holds</h3>" in html
+        assert "How reporting varies" in html
+        assert "<h3>Rates steady</h3>" in html
+        assert 'New chip — <a href="https://verge.com/1">Verge</a>' in html
+        assert html.index('id="americas"') < html.index('id="tech"')
+        assert '<a href="https://a.com/chips">up</a>' in html
+
+    def test_names_the_language_of_sources_not_in_english(self):
+        selections = copy.deepcopy(SELECTIONS)
+        selections["must_know"][0]["sources"].append(
+            {"name": "Le Monde", "url": "https://lemonde.fr/1", "bias": "center-left", "language": "fr"}
+        )
+        html = render_digest(narratives_from_selections(selections), selections["regional_summary"])
+
+        assert '<a href="https://bbc.com/1">BBC</a> <span class="chip bias-center">center</span> · ' in html
+        assert (
+            '<a href="https://lemonde.fr/1">Le Monde</a> <span class="chip bias-center-left">center-left</span>'
+            ' <span class="chip">in French</span>'
+        ) in html
+
+    def test_shows_configured_owner_and_region(self):
+        configured = [{"name": "bbc", "owner": "BBC (public)", "region": "UK"}, {"name": "AP", "owner": "AP"}]
+        narratives = narratives_from_selections(copy.deepcopy(SELECTIONS), configured=configured)
+
+        assert narratives[0]["sources"][0] == {
+            "name": "BBC",
+            "url": "https://bbc.com/1",
+            "bias": "center",
+            "owner": "BBC (public)",
+            "region": "UK",
+        }
+        html = render_digest(narratives, SELECTIONS["regional_summary"])
+        assert (
+            '<span class="chip bias-center">center</span> <span class="chip">BBC (public)</span>'
+            ' <span class="chip">UK</span>'
+        ) in html
+
+    def test_shows_stored_pictures_from_the_digest_domain(self, monkeypatch):
+        selections = copy.deepcopy(SELECTIONS)
+        selections["must_know"][0]["image"] = "ab" * 32
+        narratives = narratives_from_selections(selections)
+
+        monkeypatch.delenv("DIGEST_DOMAIN", raising=False)
+        assert render_digest(narratives, selections["regional_summary"]).count('class="thumbnail"') == 0
+        monkeypatch.setenv("DIGEST_DOMAIN", "news.example")
+        html = render_digest(narratives, selections["regional_summary"])
+        assert f'<img class="thumbnail" src="https://news.example/img/{"ab" * 32}" alt="">' in html
+
+    def test_round_trips_through_database(self):
+        conn = sqlite3.connect(":memory:")
+        conn.executescript(DB_SCHEMA)
+        narratives = narratives_from_selections(SELECTIONS, STORIES)
+        save_narratives(conn, "2026-01-05", narratives, SELECTIONS["regional_summary"])
+
+        loaded, regional_summary = load_narratives(conn, "2026-01-05")
+
+        assert loaded == narratives
+        assert regional_summary == {"americas": "Quiet day.", "tech": "Chips [up](https://a.com/chips)."}
+        assert render_digest(loaded, regional_summary) == render_digest(narratives, SELECTIONS["regional_summary"])
+
+
+class TestFetchFeeds:
+    def test_records_health_and_counts_failures(self, tmp_path, monkeypatch):
+        monkeypatch.setattr(run, "FETCHED_DIR", tmp_path / "fetched")
+        init_db()
+        articles = [{"title": "Rail strike ends", "url": "https://a.com/1", "published": None}]
+        results = {
+            "bbc": ("bbc", articles, None, 120),
+            "wire": ("wire", [], "timeout", 5000),
+        }
+        monkeypatch.setattr(run, "fetch_source_timed", lambda source: results[source["id"]])
+
+        assert fetch_feeds([{"id": "bbc"}, {"id": "wire"}]) == (1, 1)
+
+        with run.connect_db() as conn:
+            rows = conn.execute("SELECT source_id, success, fetch_ms FROM source_health ORDER BY source_id").fetchall()
+        assert rows == [("bbc", 1, 120), ("wire", 0, 5000)]
+        assert (tmp_path / "fetched" / "bbc.json").exists()
+
+
+class TestTelemetryDb:
+    def test_source_health_goes_to_telemetry_db(self, tmp_path, monkeypatch):
+        monkeypatch.setattr(run, "TELEMETRY_DB_PATH", tmp_path / "telemetry.db")
+        init_db()
+        record_source_health([("bbc", True, None, 120, 3)])
+
+        main = sqlite3.connect(tmp_path / "digest.db")
+        assert main.execute("SELECT name FROM sqlite_master WHERE name = 'source_health'").fetchone() is None
+        assert main.execute("SELECT COUNT(*) FROM digests").fetchone() == (0,)
+        telemetry = sqlite3.connect(tmp_path / "telemetry.db")
+        assert telemetry.execute("SELECT source_id, fetch_ms FROM source_health").fetchall() == [("bbc", 120)]
+
+    def test_database_key_needs_sqlcipher(self, monkeypatch):
+        monkeypatch.setenv("DATABASE_KEY", "s3cret")
+
+        # The standard library's sqlite3 isn't linked against SQLCipher
+        try:
+            run.connect_db()
+        except sqlite3.DatabaseError as e:
+            assert "SQLCipher" in str(e)
+        else:
+            raise AssertionError("opened an encrypted database without SQLCipher")
+
+    def test_single_file_without_telemetry_db(self, tmp_path):
+        init_db()
+        record_source_health([("bbc", False, "timeout", 5000, None)])
+
+        main = sqlite3.connect(tmp_path / "digest.db")
+        assert main.execute("SELECT source_id, success FROM source_health").fetchall() == [("bbc", 0)]
+        assert not (tmp_path / "telemetry.db").exists()
+
+
+class TestCheckpoints:
+    def test_resumes_the_same_kind_of_run(self):
+        init_db()
+        save_checkpoint("fetch", False, {"articles_fetched": 42})
+        save_checkpoint("select", False, {"usage": {"input_tokens": 1000}})
+
+        checkpoints = load_checkpoints(dry_run=False)
+        assert list(checkpoints) == ["fetch", "select"]
+        assert checkpoints["fetch"]["articles_fetched"] == 42
+        assert checkpoints["select"]["usage"] == {"input_tokens": 1000}
+        assert load_checkpoints(dry_run=True) == {}
+
+    def test_starts_over_after_old_or_cleared_checkpoints(self):
+        init_db()
+        save_checkpoint("fetch", True, {"articles_fetched": 42})
+        with run.connect_db() as conn:
+            conn.execute("UPDATE run_checkpoints SET completed_at = datetime('now', '-2 days')")
+        assert load_checkpoints(dry_run=True) == {}
+
+        save_checkpoint("fetch", True, {"articles_fetched": 7})
+        clear_checkpoints()
+        assert load_checkpoints(dry_run=True) == {}
+
+
+class TestTimedStage:
+    def test_records_each_stage_and_its_error(self):
+        init_db()
+
+        with timed_stage("2026-01-05 07:00:00", "fetch", False) as stage:
+            stage["items"] = 120
+        with pytest.raises(RuntimeError):
+            with timed_stage("2026-01-05 07:00:00", "select", False):
+                raise RuntimeError("model overloaded")
+        link_stages("2026-01-05 07:00:00", 9)
+
+        with run.connect_db() as conn:
+            rows = conn.execute("SELECT run_id, stage, items, error FROM run_stages ORDER BY id").fetchall()
+        assert rows == [
+            (9, "fetch", 120, None),
+            (9, "select", None, "RuntimeError: model overloaded"),
+        ]
+
+    def test_stops_before_a_stage_once_asked(self, tmp_path, monkeypatch):
+        monkeypatch.setenv("STOP_FILE", str(tmp_path / "stop"))
+        init_db()
+
+        with timed_stage("2026-01-05 07:00:00", "fetch", False):
+            (tmp_path / "stop").touch()
+        with pytest.raises(StopRequested):
+            with timed_stage("2026-01-05 07:00:00", "select", False):
+                pass
+
+        with run.connect_db() as conn:
+            assert conn.execute("SELECT stage FROM run_stages").fetchall() == [("fetch",)]
+
+
+
+class TestRunReport:
+    def test_reports_a_failed_run_at_its_stage(self, monkeypatch):
+        monkeypatch.setenv("DIGEST_NAME", "News Digest")
+        init_db()
+        with timed_stage("2026-01-05 07:00:00", "fetch", False) as stage:
+            stage["items"] = 120
+        with pytest.raises(RuntimeError):
+            with timed_stage("2026-01-05 07:00:00", "select", False):
+                raise RuntimeError("model overloaded")
+
+        report = run_report("2026-01-05 07:00:00", False, 95_000)
+        assert report["event"] == "run.failed"
+        assert report["articles_fetched"] == 120
+        assert report["failed_stage"] == "select"
+        assert report["error"] == "RuntimeError: model overloaded"
+        assert report["digest_url"] is None
+        assert report["text"] == "News Digest run failed at select after 1m 35s: RuntimeError: model overloaded"
+
+    def test_links_a_finished_runs_digest(self, monkeypatch):
+        monkeypatch.setenv("DIGEST_DOMAIN", "news.example.com")
+        init_db()
+        for name, items in [("fetch", 120), ("select", 24), ("render", 24), ("send", 300)]:
+            with timed_stage("2026-01-05 07:00:00", name, False) as stage:
+                stage["items"] = items
+
+        report = run_report("2026-01-05 07:00:00", False, 200_000, "2026-01-05")
+        assert report["status"] == "succeeded"
+        assert (report["stories"], report["recipients"]) == (24, 300)
+        assert report["digest_url"] == "https://news.example.com/2026-01-05"
+        assert [s["stage"] for s in report["stages"]] == ["fetch", "select", "render", "send"]
+
+
+class TestSignWebhook:
+    def test_signs_the_body_with_hmac_sha256(self):
+        # echo -n '{"a":1}' | openssl dgst -sha256 -hmac secret
+        assert sign_webhook(b'{"a":1}', "secret") == (
+            "sha256=aa9e2e3575f5d7098b6caccd790888c36d5fdb63342a73bada2d6a51747a8494"
+        )
+
+
+PNG = b"\x89PNG\r\n\x1a\n" + b"\x00" * 24
+
+
+class TestImageType:
+    def test_sniffs_supported_formats(self):
+        assert image_type(PNG) == "image/png"
+        assert image_type(b"\xff\xd8\xff\xe0rest") == "image/jpeg"
+        assert image_type(b"RIFF\x00\x00\x00\x00WEBPVP8 ") == "image/webp"
+        assert image_type(b"\x00\x00\x00\x1cftypavif") == "image/avif"
+
+    def test_rejects_others(self):
+        assert image_type(b'<svg xmlns="http://www.w3.org/2000/svg"/>') is None
+        assert image_type(b"") is None
+
+
+class TestStoreImage:
+    def test_stores_by_hash_once(self):
+        conn = sqlite3.connect(":memory:")
+        conn.executescript(DB_SCHEMA)
+
+        first = store_image(conn, PNG, "image/png; charset=binary")
+        assert first is not None and len(first) == 64
+        assert store_image(conn, PNG, "image/png") == first
+        assert conn.execute("SELECT content_type, data FROM images").fetchall() == [("image/png", PNG)]
+
+    def test_rejects_mismatched_or_oversized(self, monkeypatch):
+        conn = sqlite3.connect(":memory:")
+        conn.executescript(DB_SCHEMA)
+
+        assert store_image(conn, PNG, "image/jpeg") is None
+        assert store_image(conn, b"<svg/>", "image/svg+xml") is None
+        monkeypatch.setattr(run, "IMAGE_MAX_BYTES", 16)
+        assert store_image(conn, PNG, "image/png") is None
+        assert conn.execute("SELECT COUNT(*) FROM images").fetchone() == (0,)
+
+
+class TestCompressedCopyMatches:
+    def test_matches_only_compressed_copies_of_the_same_html(self):
+        conn = sqlite3.connect(":memory:")
+        conn.executescript(DB_SCHEMA)
+        assert not compressed_copy_matches(conn, "2026-01-05", "<html>")
+
+        conn.execute("ALTER TABLE digests ADD COLUMN html_sha256 TEXT")
+        digest = hashlib.sha256(b"<html>").hexdigest()
+        conn.execute("INSERT INTO digests (date, html, html_sha256) VALUES ('2026-01-05', '', ?)", (digest,))
+        conn.execute("INSERT INTO digests (date, html, html_sha256) VALUES ('2026-01-06', '<html>', ?)", (digest,))
+        assert compressed_copy_matches(conn, "2026-01-05", "<html>")
+        assert not compressed_copy_matches(conn, "2026-01-05", "<html>fixed")
+        # Plain rows are for the revision triggers to compare
+        assert not compressed_copy_matches(conn, "2026-01-06", "<html>")